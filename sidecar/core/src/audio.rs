@@ -0,0 +1,676 @@
+//! Audio conversion utilities for VoiceMark.
+//!
+//! Converts WebM/Opus audio (from browser MediaRecorder) to WAV format
+//! that whisper.cpp expects (16kHz, mono, 16-bit PCM). Video containers
+//! (MP4, MKV, MOV) work too: video streams are dropped and one audio track
+//! is decoded.
+
+use anyhow::{Result, Context, bail};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+use tempfile::NamedTempFile;
+use tracing::{debug, info, instrument, warn};
+
+/// Where the ffmpeg binary was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FfmpegSource {
+    /// Bundled next to the sidecar executable.
+    Bundled,
+    /// Given by `VOICEMARK_FFMPEG`.
+    Env,
+    /// Found on the system `PATH`.
+    Path,
+}
+
+/// The ffmpeg binary in use, as reported by `/health`.
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FfmpegInfo {
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    pub path: PathBuf,
+    pub source: FfmpegSource,
+    /// Version from `ffmpeg -version`, if it could be determined.
+    pub version: Option<String>,
+}
+
+/// What to do with the channels of stereo (or multi-channel) audio.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Channels {
+    /// Mix all channels into one.
+    #[default]
+    Downmix,
+    /// Keep each channel separate, e.g. the two sides of a call recording.
+    Split,
+}
+
+impl Channels {
+    pub fn is_downmix(&self) -> bool {
+        *self == Channels::Downmix
+    }
+}
+
+/// Explicit ffmpeg path from `VOICEMARK_FFMPEG`.
+static FFMPEG_OVERRIDE: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Resolved ffmpeg binary (only successful lookups are cached).
+static FFMPEG: Mutex<Option<FfmpegInfo>> = Mutex::new(None);
+
+/// Set the `VOICEMARK_FFMPEG` override. Call once at startup.
+pub fn configure_ffmpeg(path: Option<PathBuf>) {
+    if FFMPEG_OVERRIDE.set(path).is_err() {
+        warn!("ffmpeg override already configured");
+    }
+}
+
+/// Path to the ffmpeg binary.
+///
+/// Tries the bundled binary, then `VOICEMARK_FFMPEG`, then the system `PATH`.
+pub fn ffmpeg_path() -> Result<PathBuf> {
+    ffmpeg().map(|info| info.path)
+}
+
+/// Locate ffmpeg and report where it came from and its version.
+pub fn ffmpeg() -> Result<FfmpegInfo> {
+    let mut cached = FFMPEG.lock().unwrap();
+    if let Some(info) = cached.as_ref() {
+        return Ok(info.clone());
+    }
+
+    let bundled = bundled_ffmpeg_in(&resources_dir()?);
+    let env_override = FFMPEG_OVERRIDE.get().cloned().flatten();
+    let Some((path, source)) = resolve_ffmpeg(
+        &bundled,
+        env_override.as_deref(),
+        std::env::var_os("PATH").as_deref(),
+    ) else {
+        bail!(
+            "ffmpeg not found: no bundled binary at {}, VOICEMARK_FFMPEG {}, and none on PATH. \
+             Install ffmpeg, or run: voicemark-sidecar fetch-ffmpeg --url URL --sha256 HEX",
+            bundled.display(),
+            match &env_override {
+                Some(p) => format!("points to missing {}", p.display()),
+                None => "unset".to_string(),
+            }
+        );
+    };
+
+    let info = FfmpegInfo {
+        version: ffmpeg_version(&path),
+        path,
+        source,
+    };
+    info!(path = ?info.path, source = ?info.source, version = ?info.version, "Using ffmpeg");
+    *cached = Some(info.clone());
+    Ok(info)
+}
+
+/// Platform directory of the bundled ffmpeg under `resources/ffmpeg/`.
+#[cfg(target_os = "linux")]
+pub const FFMPEG_PLATFORM: &str = "linux-x86_64";
+
+#[cfg(target_os = "macos")]
+pub const FFMPEG_PLATFORM: &str = "darwin-x86_64";
+
+#[cfg(target_os = "windows")]
+pub const FFMPEG_PLATFORM: &str = "win-x86_64";
+
+/// File name of the ffmpeg executable.
+pub const FFMPEG_EXE: &str = if cfg!(windows) { "ffmpeg.exe" } else { "ffmpeg" };
+
+/// The `resources/` directory next to the sidecar executable.
+pub fn resources_dir() -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("Failed to resolve current_exe()")?;
+    let base = exe
+        .parent()
+        .context("Failed to resolve executable directory")?;
+    Ok(base.join("resources"))
+}
+
+/// Location of the bundled ffmpeg inside a resources directory.
+pub fn bundled_ffmpeg_in(resources: &Path) -> PathBuf {
+    resources.join("ffmpeg").join(FFMPEG_PLATFORM).join(FFMPEG_EXE)
+}
+
+/// Pick the first existing ffmpeg: bundled, then the override, then `PATH`.
+fn resolve_ffmpeg(
+    bundled: &Path,
+    env_override: Option<&Path>,
+    path_var: Option<&OsStr>,
+) -> Option<(PathBuf, FfmpegSource)> {
+    if bundled.is_file() {
+        return Some((bundled.to_path_buf(), FfmpegSource::Bundled));
+    }
+    if let Some(p) = env_override {
+        if p.is_file() {
+            return Some((p.to_path_buf(), FfmpegSource::Env));
+        }
+        warn!(path = ?p, "VOICEMARK_FFMPEG does not exist, falling back to PATH");
+    }
+    std::env::split_paths(path_var?)
+        .map(|dir| dir.join(FFMPEG_EXE))
+        .find(|p| p.is_file())
+        .map(|p| (p, FfmpegSource::Path))
+}
+
+/// Run `ffmpeg -version` and extract the version string.
+pub fn ffmpeg_version(path: &Path) -> Option<String> {
+    let output = Command::new(path).arg("-version").output().ok()?;
+    parse_ffmpeg_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse the version from `ffmpeg -version` output
+/// (`ffmpeg version 6.1.1-3ubuntu5 Copyright ...`).
+fn parse_ffmpeg_version(output: &str) -> Option<String> {
+    output
+        .lines()
+        .next()?
+        .strip_prefix("ffmpeg version ")?
+        .split_whitespace()
+        .next()
+        .map(str::to_string)
+}
+
+pub fn write_temp_wav(bytes: &[u8]) -> Result<NamedTempFile> {
+    let f = tempfile::Builder::new()
+        .suffix(".wav")
+        .tempfile()
+        .context("Failed to create temp wav file")?;
+    std::fs::write(f.path(), bytes).context("Failed to write wav bytes")?;
+    Ok(f)
+}
+
+/// The requested audio track could not be decoded. Returned (inside the
+/// `anyhow::Error`) by [`convert_track_to_wav`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrackError {
+    /// The file has streams, but none of them is audio (e.g. a screen
+    /// recording without sound).
+    NoAudio,
+    /// There is no audio track with this (0-based) index.
+    NotFound { track: u32, tracks: usize },
+}
+
+impl std::fmt::Display for TrackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrackError::NoAudio => write!(f, "The file has no audio track"),
+            TrackError::NotFound { track, tracks } => write!(
+                f,
+                "Audio track {} not found; the file has {} audio track{} (numbered from 0)",
+                track,
+                tracks,
+                if *tracks == 1 { "" } else { "s" }
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TrackError {}
+
+/// Converts audio bytes (WebM/Opus) to a temporary WAV file.
+///
+/// `format` is an optional container hint (e.g. `"webm"`, `"mp3"`), used as
+/// the input file extension to help ffmpeg's probing.
+///
+/// Returns a NamedTempFile containing 16kHz mono 16-bit PCM WAV data.
+/// The file is automatically deleted when dropped.
+pub fn convert_to_wav(input_bytes: &[u8], format: Option<&str>) -> Result<NamedTempFile> {
+    convert_track_to_wav(input_bytes, format, None)
+}
+
+/// Like [`convert_to_wav`], decoding audio track `track` (0-based, among
+/// the audio streams only) instead of the one ffmpeg picks by default.
+///
+/// Fails with a [`TrackError`] if the file has no audio or no such track.
+pub fn convert_track_to_wav(
+    input_bytes: &[u8],
+    format: Option<&str>,
+    track: Option<u32>,
+) -> Result<NamedTempFile> {
+    convert_clip_to_wav(input_bytes, format, track, None, None, Channels::Downmix)
+}
+
+/// Like [`convert_track_to_wav`], decoding only the audio from `start_ms`
+/// to `end_ms` (the start and end of the file if unset). ffmpeg seeks to
+/// the start, so the rest of a long file isn't decoded. With
+/// [`Channels::Split`] the WAV keeps the file's channels (read them with
+/// [`read_wav_channels`]).
+#[instrument(skip(input_bytes), fields(input_size = input_bytes.len()))]
+pub fn convert_clip_to_wav(
+    input_bytes: &[u8],
+    format: Option<&str>,
+    track: Option<u32>,
+    start_ms: Option<i64>,
+    end_ms: Option<i64>,
+    channels: Channels,
+) -> Result<NamedTempFile> {
+    // Create temporary files for input and output
+    let input_file = write_temp_input(input_bytes, format)?;
+    let output_file = NamedTempFile::new().context("Failed to create temp output file")?;
+
+    debug!(
+        input_path = ?input_file.path(),
+        output_path = ?output_file.path(),
+        "Converting audio to WAV"
+    );
+
+    // Run ffmpeg to convert to 16kHz mono 16-bit PCM WAV
+    let map = track.map(|track| vec!["-map".to_string(), format!("0:a:{}", track)]);
+    let start = start_ms.map(|ms| vec!["-ss".to_string(), seconds(ms)]);
+    let duration = end_ms.map(|end| vec!["-t".to_string(), seconds(end - start_ms.unwrap_or(0))]);
+    let mono = channels.is_downmix().then(|| vec!["-ac", "1"]);
+    let output = Command::new(ffmpeg_path()?)
+        .arg("-y")                     // Overwrite output file
+        .args(start.unwrap_or_default()) // Seek before decoding
+        .args([
+            "-i",
+            input_file.path().to_str().unwrap(), // Input file
+        ])
+        .args(map.unwrap_or_default())
+        .args(duration.unwrap_or_default())
+        .args([
+            "-vn",                     // Drop video, subtitle and data streams
+            "-sn",
+            "-dn",
+            "-ar",
+            "16000",                   // 16kHz sample rate (whisper requirement)
+        ])
+        .args(mono.unwrap_or_default()) // Mono, unless channels are split
+        .args([
+            "-c:a",
+            "pcm_s16le",               // 16-bit PCM
+            "-f",
+            "wav",                     // WAV format
+            output_file.path().to_str().unwrap(),
+        ])
+        .output()
+        .context("Failed to execute ffmpeg")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if let Some(error) = track_error(&stderr, track) {
+            return Err(error.into());
+        }
+        bail!("ffmpeg conversion failed: {}", stderr);
+    }
+
+    debug!("Audio conversion successful");
+    Ok(output_file)
+}
+
+/// `ms` as an ffmpeg duration in seconds.
+fn seconds(ms: i64) -> String {
+    format!("{}.{:03}", ms / 1000, ms % 1000)
+}
+
+/// Number of audio streams listed in `ffmpeg -i` output.
+pub fn count_audio_streams(ffmpeg_output: &str) -> usize {
+    ffmpeg_output
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("Stream #") && line.contains(": Audio: "))
+        .count()
+}
+
+/// Explain a failed conversion of a file that was read, but has no audio
+/// (or not the requested track).
+fn track_error(ffmpeg_output: &str, track: Option<u32>) -> Option<TrackError> {
+    if !ffmpeg_output.contains("Input #0") {
+        return None;
+    }
+    let tracks = count_audio_streams(ffmpeg_output);
+    match track {
+        _ if tracks == 0 => Some(TrackError::NoAudio),
+        Some(track) if track as usize >= tracks => Some(TrackError::NotFound { track, tracks }),
+        _ => None,
+    }
+}
+
+/// Write uploaded bytes to a temp file for ffmpeg, using `format` (if it
+/// looks like a file extension) as the suffix.
+pub fn write_temp_input(bytes: &[u8], format: Option<&str>) -> Result<NamedTempFile> {
+    let suffix = format
+        .filter(|f| !f.is_empty() && f.chars().all(|c| c.is_ascii_alphanumeric()))
+        .map(|f| format!(".{}", f.to_ascii_lowercase()))
+        .unwrap_or_default();
+    let file = tempfile::Builder::new()
+        .suffix(&suffix)
+        .tempfile()
+        .context("Failed to create temp input file")?;
+    std::fs::write(file.path(), bytes).context("Failed to write input audio")?;
+    Ok(file)
+}
+
+/// Guess a file extension for uploaded audio from its magic bytes.
+pub fn sniff_extension(bytes: &[u8]) -> &'static str {
+    match bytes {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => "wav",
+        [0x1A, 0x45, 0xDF, 0xA3, ..] => "webm",
+        [b'O', b'g', b'g', b'S', ..] => "ogg",
+        [b'f', b'L', b'a', b'C', ..] => "flac",
+        [b'I', b'D', b'3', ..] | [0xFF, 0xE0..=0xFF, ..] => "mp3",
+        [_, _, _, _, b'f', b't', b'y', b'p', b'q', b't', ..] => "mov",
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => "m4a",
+        _ => "bin",
+    }
+}
+
+/// Content type for an extension returned by [`sniff_extension`].
+pub fn content_type_for_extension(ext: &str) -> &'static str {
+    match ext {
+        "wav" => "audio/wav",
+        "webm" => "audio/webm",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "mov" => "video/quicktime",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Format of a WAV file, from its `fmt ` and `data` chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavFormat {
+    /// `WAVE_FORMAT_*` tag (1 = PCM, 3 = IEEE float, 0xFFFE = extensible).
+    pub format_tag: u16,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+    /// Length of the audio data in bytes.
+    pub data_len: usize,
+}
+
+impl WavFormat {
+    /// Whether the samples can be fed to whisper without conversion
+    /// (16kHz mono 16-bit PCM).
+    pub fn is_whisper_ready(&self) -> bool {
+        self.format_tag == 1
+            && self.channels == 1
+            && self.sample_rate == 16000
+            && self.bits_per_sample == 16
+    }
+
+    /// ffmpeg-style codec name.
+    pub fn codec(&self) -> String {
+        match (self.format_tag, self.bits_per_sample) {
+            (1 | 0xFFFE, 8) => "pcm_u8".to_string(),
+            (1 | 0xFFFE, bits) => format!("pcm_s{}le", bits),
+            (3, bits) => format!("pcm_f{}le", bits),
+            (tag, _) => format!("wav_0x{:04x}", tag),
+        }
+    }
+
+    /// Duration of the audio data in milliseconds.
+    pub fn duration_ms(&self) -> Option<u64> {
+        let bytes_per_second = self.sample_rate as u64
+            * self.channels as u64
+            * (self.bits_per_sample as u64).div_ceil(8);
+        (bytes_per_second > 0).then(|| self.data_len as u64 * 1000 / bytes_per_second)
+    }
+}
+
+/// Parse the header of a RIFF/WAVE file.
+///
+/// Returns `None` if `bytes` is not a WAV file or has no `fmt ` and `data`
+/// chunks. A truncated data chunk is measured by the bytes present.
+pub fn parse_wav_header(bytes: &[u8]) -> Option<WavFormat> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return None;
+    }
+
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+    let mut fmt = None;
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id = &bytes[pos..pos + 4];
+        let size = u32_at(pos + 4) as usize;
+        let body = pos + 8;
+        if id == b"fmt " && size >= 16 && body + 16 <= bytes.len() {
+            fmt = Some((u16_at(body), u16_at(body + 2), u32_at(body + 4), u16_at(body + 14)));
+        } else if id == b"data" {
+            let (format_tag, channels, sample_rate, bits_per_sample) = fmt?;
+            return Some(WavFormat {
+                format_tag,
+                channels,
+                sample_rate,
+                bits_per_sample,
+                data_len: size.min(bytes.len() - body),
+            });
+        }
+        // Chunks are padded to an even size
+        pos = body.saturating_add(size).saturating_add(size % 2);
+    }
+    None
+}
+
+/// Reads WAV file and returns audio samples as f32 in range [-1.0, 1.0].
+///
+/// Whisper expects audio as f32 samples normalized to [-1.0, 1.0]. The
+/// channels of stereo files are mixed down to mono.
+pub fn read_wav_samples(wav_path: &Path) -> Result<Vec<f32>> {
+    Ok(downmix(read_wav_channels(wav_path)?))
+}
+
+/// Reads a 16-bit PCM WAV file and returns the samples of each channel as
+/// f32 in range [-1.0, 1.0].
+#[instrument(skip_all)]
+pub fn read_wav_channels(wav_path: &Path) -> Result<Vec<Vec<f32>>> {
+    let bytes = std::fs::read(wav_path).context("Failed to read WAV file")?;
+
+    // Skip WAV header (44 bytes for standard WAV)
+    // The data chunk starts after the header
+    if bytes.len() < 44 {
+        bail!("WAV file too small");
+    }
+
+    // Find the data chunk
+    let data_start = find_data_chunk(&bytes)?;
+    let pcm_data = &bytes[data_start..];
+
+    // Convert interleaved 16-bit PCM samples to f32
+    let channel_count = parse_wav_header(&bytes).map_or(1, |f| f.channels.max(1) as usize);
+    let mut channels = vec![Vec::with_capacity(pcm_data.len() / 2 / channel_count); channel_count];
+    for frame in pcm_data.chunks_exact(2 * channel_count) {
+        for (channel, chunk) in channels.iter_mut().zip(frame.chunks_exact(2)) {
+            let sample = i16::from_le_bytes([chunk[0], chunk[1]]);
+            channel.push(sample as f32 / 32768.0);
+        }
+    }
+
+    debug!(channels = channel_count, sample_count = channels[0].len(), "Read WAV samples");
+    Ok(channels)
+}
+
+/// Mix `channels` down to mono by averaging them.
+pub fn downmix(mut channels: Vec<Vec<f32>>) -> Vec<f32> {
+    if channels.len() <= 1 {
+        return channels.pop().unwrap_or_default();
+    }
+    let len = channels.iter().map(Vec::len).min().unwrap_or(0);
+    let scale = 1.0 / channels.len() as f32;
+    (0..len).map(|i| channels.iter().map(|channel| channel[i]).sum::<f32>() * scale).collect()
+}
+
+/// Find the start of the data chunk in a WAV file.
+fn find_data_chunk(bytes: &[u8]) -> Result<usize> {
+    // Look for "data" marker
+    for i in 0..bytes.len().saturating_sub(8) {
+        if &bytes[i..i + 4] == b"data" {
+            // Skip "data" marker (4 bytes) and chunk size (4 bytes)
+            return Ok(i + 8);
+        }
+    }
+    bail!("Could not find data chunk in WAV file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffmpeg_path_returns_valid_string() {
+        let path = ffmpeg_path().expect("ffmpeg should be found");
+        assert!(!path.as_os_str().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_ffmpeg_fallback_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundled = dir.path().join("bundled/ffmpeg");
+        let custom = dir.path().join("custom-ffmpeg");
+        let bin_dir = dir.path().join("bin");
+        std::fs::create_dir_all(&bin_dir).unwrap();
+        std::fs::write(bin_dir.join(FFMPEG_EXE), b"").unwrap();
+        let path_var = std::env::join_paths([dir.path().join("empty"), bin_dir.clone()]).unwrap();
+
+        // Nothing bundled, no override: found on PATH
+        let (path, source) = resolve_ffmpeg(&bundled, None, Some(&path_var)).unwrap();
+        assert_eq!(source, FfmpegSource::Path);
+        assert_eq!(path, bin_dir.join(FFMPEG_EXE));
+
+        // A missing override falls through to PATH
+        let (_, source) = resolve_ffmpeg(&bundled, Some(&custom), Some(&path_var)).unwrap();
+        assert_eq!(source, FfmpegSource::Path);
+
+        // An existing override wins over PATH
+        std::fs::write(&custom, b"").unwrap();
+        let (path, source) = resolve_ffmpeg(&bundled, Some(&custom), Some(&path_var)).unwrap();
+        assert_eq!(source, FfmpegSource::Env);
+        assert_eq!(path, custom);
+
+        // The bundled binary wins over everything
+        std::fs::create_dir_all(bundled.parent().unwrap()).unwrap();
+        std::fs::write(&bundled, b"").unwrap();
+        let (_, source) = resolve_ffmpeg(&bundled, Some(&custom), Some(&path_var)).unwrap();
+        assert_eq!(source, FfmpegSource::Bundled);
+    }
+
+    #[test]
+    fn test_resolve_ffmpeg_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundled = dir.path().join("ffmpeg");
+        assert!(resolve_ffmpeg(&bundled, None, Some(dir.path().as_os_str())).is_none());
+        assert!(resolve_ffmpeg(&bundled, None, None).is_none());
+    }
+
+    #[test]
+    fn test_parse_ffmpeg_version() {
+        let output = "ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023 the FFmpeg developers\nbuilt with gcc 13";
+        assert_eq!(parse_ffmpeg_version(output).as_deref(), Some("6.1.1-3ubuntu5"));
+        assert_eq!(parse_ffmpeg_version("not ffmpeg"), None);
+    }
+
+    /// Build a PCM WAV with `data_len` zero bytes.
+    fn wav(channels: u16, sample_rate: u32, bits: u16, data_len: u32) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&channels.to_le_bytes());
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        bytes.extend_from_slice(&block_align.to_le_bytes());
+        bytes.extend_from_slice(&bits.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        bytes.resize(bytes.len() + data_len as usize, 0);
+        bytes
+    }
+
+    #[test]
+    fn test_read_wav_channels() {
+        let mut bytes = wav(2, 16000, 16, 8);
+        let data = bytes.len() - 8;
+        for (i, sample) in [16384i16, -16384, 8192, 0].iter().enumerate() {
+            bytes[data + 2 * i..data + 2 * i + 2].copy_from_slice(&sample.to_le_bytes());
+        }
+        let file = write_temp_wav(&bytes).unwrap();
+
+        let channels = read_wav_channels(file.path()).unwrap();
+        assert_eq!(channels, vec![vec![0.5, 0.25], vec![-0.5, 0.0]]);
+        assert_eq!(read_wav_samples(file.path()).unwrap(), vec![0.0, 0.125]);
+    }
+
+    #[test]
+    fn test_sniff_extension() {
+        assert_eq!(sniff_extension(&wav(1, 16000, 16, 0)), "wav");
+        assert_eq!(sniff_extension(&[0x1A, 0x45, 0xDF, 0xA3, 0x9F]), "webm");
+        assert_eq!(sniff_extension(b"OggS\x00\x02"), "ogg");
+        assert_eq!(sniff_extension(b"\x00\x00\x00\x20ftypM4A "), "m4a");
+        assert_eq!(sniff_extension(b"\x00\x00\x00\x14ftypqt  "), "mov");
+        assert_eq!(sniff_extension(b"??"), "bin");
+        assert_eq!(content_type_for_extension("webm"), "audio/webm");
+    }
+
+    #[test]
+    fn test_seconds() {
+        assert_eq!(seconds(0), "0.000");
+        assert_eq!(seconds(90_050), "90.050");
+    }
+
+    #[test]
+    fn test_track_error() {
+        let output = "\
+Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'screen.mp4':
+  Duration: 00:00:10.00, start: 0.000000, bitrate: 1205 kb/s
+  Stream #0:0[0x1](und): Video: h264 (High) (avc1 / 0x31637661), yuv420p, 1280x720
+  Stream #0:1[0x2](eng): Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp
+  Stream #0:2[0x3](eng): Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, mono, fltp
+Stream map '0:a:2' matches no streams.";
+        assert_eq!(count_audio_streams(output), 2);
+        assert_eq!(track_error(output, Some(1)), None);
+        assert_eq!(
+            track_error(output, Some(2)),
+            Some(TrackError::NotFound { track: 2, tracks: 2 })
+        );
+
+        let silent = "\
+Input #0, matroska,webm, from 'screen.mkv':
+  Stream #0:0: Video: vp9, yuv420p(tv), 1920x1080
+Output file #0 does not contain any stream";
+        assert_eq!(track_error(silent, None), Some(TrackError::NoAudio));
+        assert_eq!(track_error("Invalid data found when processing input", None), None);
+    }
+
+    #[test]
+    fn test_parse_wav_header() {
+        let format = parse_wav_header(&wav(1, 16000, 16, 32000)).unwrap();
+        assert!(format.is_whisper_ready());
+        assert_eq!(format.codec(), "pcm_s16le");
+        assert_eq!(format.duration_ms(), Some(1000));
+
+        let format = parse_wav_header(&wav(2, 44100, 16, 44100 * 4)).unwrap();
+        assert!(!format.is_whisper_ready());
+        assert_eq!(format.channels, 2);
+        assert_eq!(format.duration_ms(), Some(1000));
+    }
+
+    #[test]
+    fn test_parse_wav_header_rejects_non_wav() {
+        assert!(parse_wav_header(b"not a wav file").is_none());
+        // RIFF/WAVE magic but no chunks
+        assert!(parse_wav_header(b"RIFF\x04\x00\x00\x00WAVE").is_none());
+    }
+
+    #[test]
+    fn test_find_data_chunk() {
+        // Minimal WAV-like data with "data" marker
+        let fake_wav = b"RIFFxxxxWAVEfmt ................data\x08\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+        let result = find_data_chunk(fake_wav);
+        assert!(result.is_ok());
+    }
+}
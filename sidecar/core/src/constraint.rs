@@ -0,0 +1,276 @@
+//! Constrained dictation for VoiceMark.
+//!
+//! Some fields only take a fixed set of characters: digits for a phone or
+//! order number, letters and digits spelled out one at a time for a licence
+//! plate or a booking reference. whisper writes what it hears as prose
+//! ("Four two oh seven.", "Bee, seven, X-ray."), so a [`Constraint`] biases
+//! decoding toward bare characters with a prompt ([`bias`]), and [`apply`]
+//! turns spoken digits ("oh", "double five", "forty two"), letter names
+//! ("bee") and the NATO alphabet ("bravo") into them, dropping everything
+//! else.
+
+use serde::{Deserialize, Serialize};
+
+use crate::transcribe::{TranscribeOptions, TranscribeResult};
+
+/// Prompt examples for [`Constraint::Digits`].
+const DIGITS_PROMPT: &str = "4 0 7 1 9 3 2 8 6 5";
+/// Prompt examples for [`Constraint::Spelling`].
+const SPELLING_PROMPT: &str = "K 7 X B 2 M Q 9 A 4";
+
+const UNITS: [&str; 10] =
+    ["zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine"];
+const TEENS: [&str; 10] = [
+    "ten", "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen",
+    "eighteen", "nineteen",
+];
+/// "twenty" to "ninety".
+const TENS: [&str; 8] =
+    ["twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+
+/// Spoken names of letters.
+const LETTER_NAMES: &[(&str, char)] = &[
+    ("bee", 'B'),
+    ("be", 'B'),
+    ("see", 'C'),
+    ("cee", 'C'),
+    ("dee", 'D'),
+    ("eff", 'F'),
+    ("ef", 'F'),
+    ("gee", 'G'),
+    ("aitch", 'H'),
+    ("eye", 'I'),
+    ("jay", 'J'),
+    ("kay", 'K'),
+    ("el", 'L'),
+    ("ell", 'L'),
+    ("em", 'M'),
+    ("en", 'N'),
+    ("oh", 'O'),
+    ("pee", 'P'),
+    ("cue", 'Q'),
+    ("queue", 'Q'),
+    ("ar", 'R'),
+    ("are", 'R'),
+    ("ess", 'S'),
+    ("tee", 'T'),
+    ("tea", 'T'),
+    ("you", 'U'),
+    ("vee", 'V'),
+    ("ex", 'X'),
+    ("why", 'Y'),
+    ("zed", 'Z'),
+    ("zee", 'Z'),
+];
+
+/// The NATO phonetic alphabet, A to Z.
+const NATO: [&str; 26] = [
+    "alfa", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel", "india", "juliett",
+    "kilo", "lima", "mike", "november", "oscar", "papa", "quebec", "romeo", "sierra", "tango",
+    "uniform", "victor", "whiskey", "xray", "yankee", "zulu",
+];
+
+/// Characters the text of a result is limited to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Constraint {
+    /// Any text.
+    #[default]
+    Free,
+    /// Digits only, e.g. phone, order or card numbers.
+    Digits,
+    /// Uppercase letters and digits, spelled out one at a time, e.g.
+    /// licence plates or booking references.
+    Spelling,
+}
+
+impl Constraint {
+    pub fn is_free(&self) -> bool {
+        *self == Self::Free
+    }
+}
+
+/// `options` with examples of the characters its constraint allows after
+/// the initial prompt, so whisper writes "4 2 0 7" rather than "four two oh
+/// seven".
+pub fn bias(mut options: TranscribeOptions) -> TranscribeOptions {
+    let examples = match options.constraint {
+        Constraint::Free => return options,
+        Constraint::Digits => DIGITS_PROMPT,
+        Constraint::Spelling => SPELLING_PROMPT,
+    };
+    options.initial_prompt = Some(match options.initial_prompt.take() {
+        Some(prompt) => format!("{} {}", prompt.trim_end(), examples),
+        None => examples.to_string(),
+    });
+    options
+}
+
+/// Reduce `result` to the characters `constraint` allows: each segment,
+/// then the text, which is rebuilt from the segments if there are any.
+/// Word timings and tokens keep whisper's text.
+pub fn apply(constraint: Constraint, result: &mut TranscribeResult) {
+    if constraint.is_free() {
+        return;
+    }
+    if result.timed_segments.is_empty() {
+        result.text = constrain(constraint, &result.text);
+        return;
+    }
+    for segment in &mut result.timed_segments {
+        segment.text = constrain(constraint, &segment.text);
+    }
+    result.text = result.timed_segments.iter().map(|s| s.text.as_str()).collect();
+}
+
+/// `text` as the characters `constraint` allows.
+fn constrain(constraint: Constraint, text: &str) -> String {
+    let spelling = constraint == Constraint::Spelling;
+    let mut constrained = String::new();
+    // Times the next character is repeated ("double five")
+    let mut repeat = 1;
+    // After "forty": a "0" unless the next word is a unit ("forty two")
+    let mut owed_zero = false;
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        let lower = word.to_lowercase();
+        let digit = unit(&lower, spelling);
+        if owed_zero {
+            owed_zero = false;
+            if let Some(digit @ '1'..='9') = digit {
+                constrained.push(digit);
+                continue;
+            }
+            constrained.push('0');
+        }
+        if let Some(tens) = TENS.iter().position(|t| *t == lower) {
+            constrained.push(char::from(b'2' + tens as u8));
+            owed_zero = true;
+            repeat = 1;
+            continue;
+        }
+        let characters = match lower.as_str() {
+            "double" => {
+                repeat = 2;
+                continue;
+            }
+            "triple" => {
+                repeat = 3;
+                continue;
+            }
+            // "double u", the letter W
+            "u" | "you" if spelling && repeat == 2 => {
+                repeat = 1;
+                "W".to_string()
+            }
+            _ => characters(word, &lower, digit, spelling),
+        };
+        let times = if characters.chars().count() == 1 { repeat } else { 1 };
+        constrained.push_str(&characters.repeat(times));
+        repeat = 1;
+    }
+    if owed_zero {
+        constrained.push('0');
+    }
+    constrained
+}
+
+/// The digit `word` names, if any. "oh" is a letter when spelling.
+fn unit(word: &str, spelling: bool) -> Option<char> {
+    match word {
+        "nought" | "nil" => Some('0'),
+        "oh" | "o" if !spelling => Some('0'),
+        _ => UNITS.iter().position(|u| *u == word).map(|i| char::from(b'0' + i as u8)),
+    }
+}
+
+/// The allowed characters `word` stands for, if any.
+fn characters(word: &str, lower: &str, digit: Option<char>, spelling: bool) -> String {
+    if let Some(digit) = digit {
+        return digit.to_string();
+    }
+    if let Some(teen) = TEENS.iter().position(|t| *t == lower) {
+        return format!("1{}", teen);
+    }
+    if !spelling {
+        return word.chars().filter(char::is_ascii_digit).collect();
+    }
+    if let Some(letter) = letter(lower) {
+        return letter.to_string();
+    }
+    // Already spelled: a single letter, "KX12" or "AB"
+    let spelled = word.chars().all(|c| c.is_ascii_alphanumeric())
+        && (word.len() == 1
+            || word.chars().any(|c| c.is_ascii_digit())
+            || !word.chars().any(|c| c.is_ascii_lowercase()));
+    if spelled { word.to_ascii_uppercase() } else { String::new() }
+}
+
+/// The letter `word` names, by its name or NATO code word.
+fn letter(word: &str) -> Option<char> {
+    let word = match word {
+        "alpha" => "alfa",
+        "juliet" => "juliett",
+        word => word,
+    };
+    LETTER_NAMES
+        .iter()
+        .find(|(name, _)| *name == word)
+        .map(|(_, letter)| *letter)
+        .or_else(|| NATO.iter().position(|n| *n == word).map(|i| char::from(b'A' + i as u8)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcribe::Segment;
+
+    #[test]
+    fn test_digits() {
+        let digits = |text| constrain(Constraint::Digits, text);
+        assert_eq!(digits("Four two oh seven."), "4207");
+        assert_eq!(digits("It's 555-0142, double five."), "555014255");
+        assert_eq!(digits("Forty two, nineteen, ninety."), "421990");
+        assert_eq!(digits("triple 8 twenty"), "88820");
+        assert_eq!(digits("Thank you."), "");
+    }
+
+    #[test]
+    fn test_spelling() {
+        let spelled = |text| constrain(Constraint::Spelling, text);
+        assert_eq!(spelled("Bee, seven, X-ray, alpha."), "B7XA");
+        assert_eq!(spelled("The plate is KX 12 ABC."), "KX12ABC");
+        assert_eq!(spelled("Oh, zero, double you, double E."), "O0WEE");
+        assert_eq!(spelled("Zulu nine nine"), "Z99");
+    }
+
+    #[test]
+    fn test_apply_rebuilds_text_from_segments() {
+        let segment = |text: &str| Segment { start_ms: 0, end_ms: 1000, text: text.to_string() };
+        let mut result = TranscribeResult {
+            text: "One two. Three.".to_string(),
+            segments: 2,
+            avg_token_prob: 0.9,
+            timed_segments: vec![segment("One two."), segment("Three.")],
+            words: Vec::new(),
+            tokens: Vec::new(),
+            language: None,
+            language_probability: None,
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        };
+        apply(Constraint::Digits, &mut result);
+        assert_eq!(result.text, "123");
+        assert_eq!(result.timed_segments[0].text, "12");
+
+        let options = TranscribeOptions {
+            constraint: Constraint::Spelling,
+            initial_prompt: Some("Acme".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(bias(options).initial_prompt.unwrap(), format!("Acme {}", SPELLING_PROMPT));
+        assert_eq!(bias(TranscribeOptions::default()).initial_prompt, None);
+    }
+}
@@ -0,0 +1,169 @@
+//! Audio fingerprints for VoiceMark.
+//!
+//! The same recording exported twice rarely has identical bytes: the
+//! container, its tags or the encoder differ. A fingerprint captures what
+//! the audio sounds like instead: one bit per 100 ms frame, set when the
+//! frame is louder than the one before. Fingerprints of the same recording
+//! agree on nearly every bit, while unrelated recordings agree on about
+//! half, so [`Fingerprint::matches`] can find re-submissions. Short clips
+//! (greetings, jingles, silence) are too easily alike, so only recordings of
+//! at least 30 seconds are compared (see [`Fingerprint::is_distinctive`]).
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Samples per frame (100 ms at 16 kHz).
+const FRAME_SAMPLES: usize = 1600;
+/// Frame energy floor (-60 dBFS), so near-silent frames compare equal
+/// instead of flipping with encoder noise.
+const MIN_ENERGY: f32 = 1e-6;
+/// Share of bits that must agree for fingerprints to match.
+const MIN_SIMILARITY: f32 = 0.9;
+/// Fewest bits worth comparing (30 seconds); shorter clips are too easily
+/// alike.
+const MIN_BITS: usize = 300;
+/// Largest difference in length, in frames, between fingerprints of the
+/// same recording (encoder padding, trimmed tails).
+const MAX_LENGTH_DIFFERENCE: usize = 5;
+
+/// A compact description of how a recording's loudness evolves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// Bit `i` is set when frame `i + 1` is louder than frame `i`.
+    bits: Vec<u64>,
+    len: usize,
+}
+
+impl Fingerprint {
+    /// Fingerprint 16 kHz audio, mixing multiple channels down to one.
+    pub fn of(channels: &[Vec<f32>]) -> Self {
+        let samples = channels.iter().map(Vec::len).max().unwrap_or(0);
+        let energies: Vec<f32> = (0..samples / FRAME_SAMPLES)
+            .map(|frame| {
+                let range = frame * FRAME_SAMPLES..(frame + 1) * FRAME_SAMPLES;
+                let sum: f32 = range
+                    .map(|i| {
+                        let mixed: f32 = channels.iter().filter_map(|c| c.get(i)).sum();
+                        let mixed = mixed / channels.len() as f32;
+                        mixed * mixed
+                    })
+                    .sum();
+                (sum / FRAME_SAMPLES as f32).max(MIN_ENERGY)
+            })
+            .collect();
+
+        let len = energies.len().saturating_sub(1);
+        let mut bits = vec![0u64; len.div_ceil(64)];
+        for (i, pair) in energies.windows(2).enumerate() {
+            if pair[1] > pair[0] {
+                bits[i / 64] |= 1 << (i % 64);
+            }
+        }
+        Self { bits, len }
+    }
+
+    /// Whether the recording is long enough to be told apart from others;
+    /// shorter ones never match.
+    pub fn is_distinctive(&self) -> bool {
+        self.len >= MIN_BITS
+    }
+
+    /// Whether both fingerprints are of the same recording.
+    pub fn matches(&self, other: &Self) -> bool {
+        let len = self.len.min(other.len);
+        if len < MIN_BITS || self.len.abs_diff(other.len) > MAX_LENGTH_DIFFERENCE {
+            return false;
+        }
+        let differing: u32 = self
+            .bits
+            .iter()
+            .zip(&other.bits)
+            .take(len.div_ceil(64))
+            .enumerate()
+            .map(|(i, (a, b))| {
+                let keep = (len - i * 64).min(64);
+                let mask = if keep == 64 { u64::MAX } else { (1 << keep) - 1 };
+                ((a ^ b) & mask).count_ones()
+            })
+            .sum();
+        1.0 - differing as f32 / len as f32 >= MIN_SIMILARITY
+    }
+}
+
+/// `<bits>:<hex words>`, as stored next to transcripts.
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.len)?;
+        for word in &self.bits {
+            write!(f, "{:016x}", word)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || "Invalid fingerprint".to_string();
+        let (len, hex) = s.trim().split_once(':').ok_or_else(invalid)?;
+        let len: usize = len.parse().map_err(|_| invalid())?;
+        if hex.len() != len.div_ceil(64) * 16 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let bits = (0..hex.len())
+            .step_by(16)
+            .map(|i| u64::from_str_radix(&hex[i..i + 16], 16).map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { bits, len })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Speech-like audio: a tone whose loudness follows `seed`.
+    fn recording(seconds: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        let mut level = 0.0;
+        (0..seconds * 16000)
+            .map(|i| {
+                if i % 800 == 0 {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    level = (state >> 16) as f32 / 65536.0 * 0.5;
+                }
+                (i as f32 * 0.07).sin() * level
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_same_recording_matches_after_reencoding() {
+        let original = recording(60, 1);
+        // Quantized to 8 bits, slightly quieter, with a bit of padding
+        let mut reencoded: Vec<f32> =
+            original.iter().map(|s| (s * 0.9 * 128.0).round() / 128.0).collect();
+        reencoded.extend([0.0; 3200]);
+
+        let fingerprint = Fingerprint::of(std::slice::from_ref(&original));
+        assert!(fingerprint.matches(&Fingerprint::of(&[reencoded])));
+        assert!(!fingerprint.matches(&Fingerprint::of(&[recording(60, 2)])));
+        assert!(!fingerprint.matches(&Fingerprint::of(&[original[..16000 * 40].to_vec()])));
+
+        // Too short to tell apart
+        let short = Fingerprint::of(&[recording(20, 1)]);
+        assert!(!short.is_distinctive());
+        assert!(!short.matches(&short));
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let fingerprint = Fingerprint::of(&[recording(10, 3)]);
+        let text = fingerprint.to_string();
+        assert!(text.starts_with("99:"), "{}", text);
+        assert_eq!(text.parse::<Fingerprint>().unwrap(), fingerprint);
+        assert!("99:abc".parse::<Fingerprint>().is_err());
+        assert!("nonsense".parse::<Fingerprint>().is_err());
+    }
+}
@@ -0,0 +1,489 @@
+//! Output text formatting for VoiceMark.
+//!
+//! whisper's text is usually cased and punctuated, but not always the way
+//! an application wants it: a search index wants bare lowercase words, a
+//! document readable sentences and paragraphs, a dictation field spoken
+//! "comma"s turned into commas. A [`TextFormat`] profile names a pipeline of
+//! [`Step`]s, which [`apply`] runs over each segment of a result, and then
+//! over the text rebuilt from them.
+//!
+//! [`paragraphs`] groups the segments into sentences and paragraphs, for
+//! clients rendering a readable document.
+
+use serde::{Deserialize, Serialize};
+
+use crate::transcribe::{Segment, TranscribeResult};
+
+/// Pause between segments from which [`Step::Paragraphs`] starts a new
+/// paragraph.
+pub const PARAGRAPH_PAUSE_MS: i64 = 2000;
+/// Pause that ends a sentence even without punctuation.
+const SENTENCE_PAUSE_MS: i64 = 1200;
+/// Sentences after which a paragraph is broken even without a pause.
+const MAX_PARAGRAPH_SENTENCES: usize = 6;
+
+/// Spoken commands replaced by [`Step::SpokenPunctuation`], longest first.
+const SPOKEN: &[(&[&str], &str)] = &[
+    (&["new", "paragraph"], "\n\n"),
+    (&["new", "line"], "\n"),
+    (&["question", "mark"], "?"),
+    (&["exclamation", "mark"], "!"),
+    (&["exclamation", "point"], "!"),
+    (&["full", "stop"], "."),
+    (&["period"], "."),
+    (&["comma"], ","),
+    (&["colon"], ":"),
+    (&["semicolon"], ";"),
+];
+
+/// How the returned text is formatted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum TextFormat {
+    /// whisper's output, unchanged.
+    #[default]
+    Raw,
+    /// Tidy spacing around punctuation, capitalize sentences, and start a
+    /// paragraph after long pauses.
+    Sentences,
+    /// Lowercase words without punctuation, e.g. for search indexes.
+    Lowercase,
+    /// Like `sentences`, with spoken punctuation ("comma", "question mark")
+    /// and "new line" / "new paragraph" turned into what they stand for.
+    Dictation,
+}
+
+impl TextFormat {
+    /// The profile's pipeline, in order.
+    pub fn steps(self) -> &'static [Step] {
+        match self {
+            Self::Raw => &[],
+            Self::Sentences => &[Step::NormalizePunctuation, Step::SentenceCase, Step::Paragraphs],
+            Self::Lowercase => &[Step::StripPunctuation, Step::Lowercase],
+            Self::Dictation => {
+                &[Step::SpokenPunctuation, Step::NormalizePunctuation, Step::SentenceCase]
+            }
+        }
+    }
+
+    pub fn is_raw(&self) -> bool {
+        *self == Self::Raw
+    }
+}
+
+/// One pass of a formatting pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Replace spoken punctuation and line break commands.
+    SpokenPunctuation,
+    /// Collapse runs of spaces, drop spaces before punctuation and add one
+    /// after it.
+    NormalizePunctuation,
+    /// Remove punctuation, keeping apostrophes, hyphens and separators
+    /// inside words and numbers ("don't", "e-mail", "3.5").
+    StripPunctuation,
+    /// Lowercase everything.
+    Lowercase,
+    /// Capitalize the first letter of every sentence.
+    SentenceCase,
+    /// Join segments separated by [`PARAGRAPH_PAUSE_MS`] or more with a
+    /// blank line instead of a space.
+    Paragraphs,
+}
+
+/// Format `result` with `steps`: each segment, then the text, which is
+/// rebuilt from the segments if there are any. Word timings and tokens keep
+/// whisper's text.
+pub fn apply(steps: &[Step], result: &mut TranscribeResult) {
+    if steps.is_empty() {
+        return;
+    }
+    if result.timed_segments.is_empty() {
+        result.text = format_text(steps, &result.text, &mut true);
+        return;
+    }
+
+    let paragraphs = steps.contains(&Step::Paragraphs);
+    let mut text = String::new();
+    let mut previous_end = None;
+    let mut sentence_start = true;
+    for segment in &mut result.timed_segments {
+        if let Some(end) = previous_end {
+            let paragraph = paragraphs && segment.start_ms - end >= PARAGRAPH_PAUSE_MS;
+            text.push_str(if paragraph { "\n\n" } else { " " });
+            sentence_start |= paragraph;
+        }
+        text.push_str(segment.text.trim());
+        previous_end = Some(segment.end_ms);
+        segment.text = format_text(steps, &segment.text, &mut sentence_start);
+    }
+    result.text = format_text(steps, &text, &mut true);
+}
+
+/// Run `steps` over `text`. `sentence_start` says whether `text` starts a
+/// sentence, and is updated to whether the text that follows it does.
+fn format_text(steps: &[Step], text: &str, sentence_start: &mut bool) -> String {
+    let starts_sentence = *sentence_start;
+    let mut text = text.trim().to_string();
+    for step in steps {
+        text = match step {
+            Step::SpokenPunctuation => spoken_punctuation(&text),
+            Step::NormalizePunctuation => normalize_punctuation(&text),
+            Step::StripPunctuation => strip_punctuation(&text),
+            Step::Lowercase => text.to_lowercase(),
+            Step::SentenceCase => sentence_case(&text, starts_sentence),
+            Step::Paragraphs => text,
+        };
+    }
+    if let Some(last) = text.trim_end_matches(' ').chars().last() {
+        *sentence_start = matches!(last, '.' | '!' | '?' | '\n');
+    }
+    text
+}
+
+/// Replace spoken commands, matched as whole words in any case. The
+/// punctuation whisper sometimes puts after them ("comma,") is dropped.
+fn spoken_punctuation(text: &str) -> String {
+    let words: Vec<&str> = text.split(' ').filter(|word| !word.is_empty()).collect();
+    let bare = |word: &str| {
+        word.trim_end_matches([',', '.', '!', '?', ';', ':']).to_lowercase()
+    };
+    let mut out = String::new();
+    let mut i = 0;
+    while i < words.len() {
+        let command = SPOKEN.iter().find(|(phrase, _)| {
+            phrase.len() <= words.len() - i
+                && phrase.iter().zip(&words[i..]).all(|(expected, word)| bare(word) == *expected)
+        });
+        match command {
+            Some((phrase, symbol)) => {
+                out.push_str(symbol);
+                if !symbol.starts_with('\n') {
+                    out.push(' ');
+                }
+                i += phrase.len();
+            }
+            None => {
+                out.push_str(words[i]);
+                out.push(' ');
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Normalize spacing line by line, keeping line breaks (at most one blank
+/// line in a row).
+fn normalize_punctuation(text: &str) -> String {
+    let lines: Vec<String> = text.split('\n').map(normalize_line).collect();
+    let mut out = String::new();
+    let mut blank = 0;
+    for line in lines {
+        if line.is_empty() {
+            blank += 1;
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank > 1 { "\n\n" } else { "\n" });
+        }
+        out.push_str(&line);
+        blank = 1;
+    }
+    out
+}
+
+fn normalize_line(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_whitespace() {
+            // One space, and none before punctuation
+            let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if !out.ends_with(' ') && !out.is_empty() && !next.is_some_and(|&n| closes(n)) {
+                out.push(' ');
+            }
+            continue;
+        }
+        if c == ',' && out.ends_with(',') {
+            continue;
+        }
+        out.push(c);
+        let next = chars.get(i + 1);
+        let separates = matches!(c, ',' | ';' | ':' | '.' | '!' | '?');
+        if separates && next.is_some_and(|n| n.is_alphabetic()) {
+            out.push(' ');
+        }
+    }
+    out.trim_end().to_string()
+}
+
+/// Punctuation that attaches to the preceding word.
+fn closes(c: char) -> bool {
+    matches!(c, ',' | '.' | ';' | ':' | '!' | '?' | ')')
+}
+
+fn strip_punctuation(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let kept: String = chars
+        .iter()
+        .enumerate()
+        .filter(|&(i, &c)| {
+            if c.is_alphanumeric() || c.is_whitespace() {
+                return true;
+            }
+            let inside = i > 0
+                && chars[i - 1].is_alphanumeric()
+                && chars.get(i + 1).is_some_and(|n| n.is_alphanumeric());
+            inside && matches!(c, '\'' | '\u{2019}' | '-' | '.' | ',')
+        })
+        .map(|(_, &c)| c)
+        .collect();
+    kept.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Uppercase the first character of each sentence if it is a letter.
+fn sentence_case(text: &str, starts_sentence: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut start = starts_sentence;
+    let mut after_end = false;
+    for c in text.chars() {
+        if start && !c.is_whitespace() {
+            out.extend(c.to_uppercase());
+            start = false;
+            after_end = false;
+            continue;
+        }
+        out.push(c);
+        match c {
+            '.' | '!' | '?' => after_end = true,
+            '\n' => start = true,
+            c if c.is_whitespace() => start = after_end,
+            _ => after_end = false,
+        }
+    }
+    out
+}
+
+/// A sentence of the transcript.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Sentence {
+    /// Sentence start, in milliseconds. Estimated from the characters
+    /// before it when it starts inside a segment.
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+}
+
+/// Consecutive sentences without a long pause between them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Paragraph {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    /// The sentences, joined with spaces.
+    pub text: String,
+    pub sentences: Vec<Sentence>,
+}
+
+/// Group `segments` into sentences, ended by `.`, `!`, `?`, a line break or
+/// a pause, and those into paragraphs, broken at pauses of
+/// [`PARAGRAPH_PAUSE_MS`] or after a few sentences.
+pub fn paragraphs(segments: &[Segment]) -> Vec<Paragraph> {
+    let mut sentences: Vec<Sentence> = Vec::new();
+    let mut current: Option<Sentence> = None;
+    for segment in segments {
+        for piece in sentence_pieces(segment) {
+            let sentence = match current.take() {
+                Some(mut sentence) if piece.start_ms - sentence.end_ms < SENTENCE_PAUSE_MS => {
+                    sentence.text.push(' ');
+                    sentence.text.push_str(&piece.text);
+                    sentence.end_ms = piece.end_ms;
+                    sentence
+                }
+                previous => {
+                    sentences.extend(previous);
+                    piece
+                }
+            };
+            if ends_sentence(&sentence.text) {
+                sentences.push(sentence);
+            } else {
+                current = Some(sentence);
+            }
+        }
+    }
+    sentences.extend(current);
+
+    let mut paragraphs: Vec<Paragraph> = Vec::new();
+    for sentence in sentences {
+        match paragraphs.last_mut() {
+            Some(paragraph)
+                if sentence.start_ms - paragraph.end_ms < PARAGRAPH_PAUSE_MS
+                    && paragraph.sentences.len() < MAX_PARAGRAPH_SENTENCES =>
+            {
+                paragraph.text.push(' ');
+                paragraph.text.push_str(&sentence.text);
+                paragraph.end_ms = sentence.end_ms;
+                paragraph.sentences.push(sentence);
+            }
+            _ => paragraphs.push(Paragraph {
+                start_ms: sentence.start_ms,
+                end_ms: sentence.end_ms,
+                text: sentence.text.clone(),
+                sentences: vec![sentence],
+            }),
+        }
+    }
+    paragraphs
+}
+
+/// Split a segment after each sentence end, timing the pieces by their
+/// share of its characters.
+fn sentence_pieces(segment: &Segment) -> Vec<Sentence> {
+    let text = segment.text.trim();
+    let total = text.chars().count().max(1) as i64;
+    let duration = segment.end_ms - segment.start_ms;
+    let at = |chars: usize| segment.start_ms + duration * chars as i64 / total;
+
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut previous = ' ';
+    for (chars, (i, c)) in text.char_indices().enumerate() {
+        if (c.is_whitespace() && matches!(previous, '.' | '!' | '?')) || c == '\n' {
+            let piece = text[start..i].trim();
+            if !piece.is_empty() {
+                let first = text[..start].chars().count();
+                pieces.push(Sentence {
+                    start_ms: at(first),
+                    end_ms: at(chars),
+                    text: piece.to_string(),
+                });
+            }
+            start = i + c.len_utf8();
+        }
+        previous = c;
+    }
+    let piece = text[start..].trim();
+    if !piece.is_empty() {
+        let first = text[..start].chars().count();
+        pieces.push(Sentence {
+            start_ms: at(first),
+            end_ms: segment.end_ms,
+            text: piece.to_string(),
+        });
+    }
+    pieces
+}
+
+/// Whether `text` ends with sentence-ending punctuation, possibly followed
+/// by closing quotes or brackets.
+fn ends_sentence(text: &str) -> bool {
+    let text = text.trim_end_matches(['"', '\'', '\u{201d}', ')', ']']);
+    text.ends_with(['.', '!', '?'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(segments: &[(i64, &str)]) -> TranscribeResult {
+        let timed_segments: Vec<Segment> = segments
+            .iter()
+            .map(|&(start_ms, text)| Segment {
+                start_ms,
+                end_ms: start_ms + 1000,
+                text: text.to_string(),
+            })
+            .collect();
+        TranscribeResult {
+            text: segments.iter().map(|(_, text)| *text).collect(),
+            segments: timed_segments.len(),
+            avg_token_prob: 0.9,
+            timed_segments,
+            words: Vec::new(),
+            tokens: Vec::new(),
+            language: None,
+            language_probability: None,
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn formatted(format: TextFormat, segments: &[(i64, &str)]) -> TranscribeResult {
+        let mut result = result(segments);
+        apply(format.steps(), &mut result);
+        result
+    }
+
+    #[test]
+    fn test_sentences_profile() {
+        let result = formatted(
+            TextFormat::Sentences,
+            &[
+                (0, " okay ,so the budget is 3.5 million.we"),
+                (1000, " agree"),
+                (5000, " next item?"),
+            ],
+        );
+        assert_eq!(result.text, "Okay, so the budget is 3.5 million. We agree\n\nNext item?");
+        assert_eq!(result.timed_segments[1].text, "agree");
+        assert_eq!(result.timed_segments[2].text, "Next item?");
+    }
+
+    #[test]
+    fn test_lowercase_profile() {
+        let result = formatted(TextFormat::Lowercase, &[(0, " Don't e-mail Dr. Smith, OK?")]);
+        assert_eq!(result.text, "don't e-mail dr smith ok");
+    }
+
+    #[test]
+    fn test_dictation_profile() {
+        let result = formatted(
+            TextFormat::Dictation,
+            &[
+                (0, " dear Sam comma new line thanks for the notes period"),
+                (1000, " new paragraph see you Friday."),
+            ],
+        );
+        assert_eq!(result.text, "Dear Sam,\nThanks for the notes.\n\nSee you Friday.");
+        assert_eq!(result.timed_segments[0].text, "Dear Sam,\nThanks for the notes.");
+    }
+
+    #[test]
+    fn test_paragraphs() {
+        let segment = |start_ms, end_ms, text: &str| Segment {
+            start_ms,
+            end_ms,
+            text: text.to_string(),
+        };
+        let paragraphs = paragraphs(&[
+            segment(0, 2000, " Welcome back. Today we"),
+            segment(2000, 3000, " look at budgets."),
+            segment(3500, 4500, " Questions later"),
+            segment(7000, 8000, " Right, item two!"),
+        ]);
+        assert_eq!(paragraphs.len(), 2);
+        let texts: Vec<&str> =
+            paragraphs[0].sentences.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, ["Welcome back.", "Today we look at budgets.", "Questions later"]);
+        assert_eq!(paragraphs[0].text, "Welcome back. Today we look at budgets. Questions later");
+        // "Today" starts 14 of 22 characters into the first segment
+        assert_eq!(paragraphs[0].sentences[1].start_ms, 2000 * 14 / 22);
+        assert_eq!(paragraphs[0].sentences[1].end_ms, 3000);
+        assert_eq!((paragraphs[0].start_ms, paragraphs[0].end_ms), (0, 4500));
+        assert_eq!(paragraphs[1].text, "Right, item two!");
+        assert_eq!(paragraphs[1].start_ms, 7000);
+    }
+
+    #[test]
+    fn test_raw_leaves_text_alone() {
+        let result = formatted(TextFormat::Raw, &[(0, " okay ,so")]);
+        assert_eq!(result.text, " okay ,so");
+        assert!(TextFormat::default().is_raw());
+    }
+}
@@ -0,0 +1,139 @@
+//! Hallucination suppression for streaming transcription.
+//!
+//! On silence or background noise whisper tends to emit stock phrases
+//! ("Thank you.", "Subtitles by ...") learned from its training data.
+//! These helpers decide when a chunk should not be transcribed at all,
+//! and when its output should be discarded instead of sent to the client.
+
+use crate::vad;
+
+/// Chunks quieter than this RMS are silence and are not transcribed.
+const SILENCE_RMS: f32 = 0.003;
+/// Below this RMS a chunk is "quiet": stock phrases are assumed hallucinated.
+const QUIET_RMS: f32 = 0.01;
+/// Below this mean token probability, stock phrases are assumed hallucinated.
+const LOW_CONFIDENCE: f32 = 0.5;
+
+/// Phrases whisper commonly produces from silence or noise (normalized).
+/// Entries ending in a space match as prefixes ("subtitles by someone").
+const KNOWN_HALLUCINATIONS: &[&str] = &[
+    "thank you",
+    "thanks",
+    "thank you very much",
+    "thank you so much",
+    "thanks for watching",
+    "thank you for watching",
+    "thank you for listening",
+    "please subscribe",
+    "like and subscribe",
+    "bye",
+    "you",
+    "so",
+    "subtitles by ",
+    "subtitles made by ",
+    "transcription by ",
+    "translated by ",
+    "captions by ",
+];
+
+/// Whether a chunk is too quiet to contain speech.
+pub fn is_silent(samples: &[f32]) -> bool {
+    vad::rms(samples) < SILENCE_RMS
+}
+
+/// Whether `text` is likely a hallucination rather than real speech.
+///
+/// Pure annotations like `[BLANK_AUDIO]` or `(silence)` are always
+/// suppressed. Stock phrases are suppressed when the audio was quiet
+/// (`rms`) or the decoder wasn't confident (`avg_token_prob`).
+pub fn is_hallucination(text: &str, rms: f32, avg_token_prob: f32) -> bool {
+    let trimmed = text.trim();
+    if is_annotation(trimmed) {
+        return true;
+    }
+
+    let normalized = normalize(trimmed);
+    if normalized.is_empty() {
+        return false;
+    }
+    let known = KNOWN_HALLUCINATIONS.iter().any(|phrase| match phrase.strip_suffix(' ') {
+        Some(prefix) => normalized.starts_with(prefix),
+        None => normalized == *phrase,
+    });
+
+    known && (rms < QUIET_RMS || avg_token_prob < LOW_CONFIDENCE)
+}
+
+/// Whether `text` consists only of bracketed annotations.
+fn is_annotation(text: &str) -> bool {
+    let mut rest = text;
+    if rest.is_empty() {
+        return false;
+    }
+    while !rest.is_empty() {
+        let close = match rest.chars().next() {
+            Some('[') => ']',
+            Some('(') => ')',
+            Some('*') => '*',
+            _ => return false,
+        };
+        match rest[1..].find(close) {
+            Some(end) => rest = rest[end + 2..].trim_start(),
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Lowercase, strip punctuation, and collapse whitespace.
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric() || *c == '\'')
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_silence_detection() {
+        assert!(is_silent(&vec![0.0; 16000]));
+        assert!(is_silent(&vec![0.001; 16000]));
+        assert!(!is_silent(&vec![0.1; 16000]));
+    }
+
+    #[test]
+    fn test_annotations_are_always_suppressed() {
+        assert!(is_hallucination("[BLANK_AUDIO]", 0.2, 0.9));
+        assert!(is_hallucination(" (silence) ", 0.2, 0.9));
+        assert!(is_hallucination("[Music] [Applause]", 0.2, 0.9));
+        assert!(!is_hallucination("[Music] plays", 0.2, 0.9));
+    }
+
+    #[test]
+    fn test_stock_phrases_suppressed_on_quiet_audio() {
+        assert!(is_hallucination("Thank you.", 0.005, 0.9));
+        assert!(is_hallucination("Subtitles by the Amara.org community", 0.005, 0.9));
+    }
+
+    #[test]
+    fn test_stock_phrases_suppressed_on_low_confidence() {
+        assert!(is_hallucination("Thanks for watching!", 0.2, 0.3));
+    }
+
+    #[test]
+    fn test_real_speech_is_kept() {
+        // A confident "thank you" over real speech energy is genuine
+        assert!(!is_hallucination("Thank you.", 0.2, 0.9));
+        assert!(!is_hallucination("Thank you for the update on the budget.", 0.005, 0.3));
+        assert!(!is_hallucination("", 0.0, 0.0));
+    }
+}
@@ -0,0 +1,46 @@
+//! VoiceMark transcription core.
+//!
+//! Speech-to-text with whisper.cpp, without the HTTP server: applications
+//! can embed transcription directly (e.g. in a Tauri backend) instead of
+//! talking to the sidecar over localhost.
+//!
+//! - [`audio`] - ffmpeg conversion and WAV decoding to 16kHz mono samples
+//! - [`transcribe`] - Model loading and batch transcription
+//! - [`session`] - Chunked live transcription with partial/final results
+//! - [`vad`], [`hallucination`] - Silence stripping and hallucination filtering
+//! - [`music`] - Music and noise detection for batch transcription
+//! - [`quality`] - Clipping, level and DC offset warnings
+//! - [`formatting`] - Casing, punctuation and paragraph profiles for the text
+//! - [`constraint`] - Digits-only and spelled-out dictation fields
+//! - [`fingerprint`] - Recognizing re-submitted recordings
+//! - [`speaker`] - Speaker change detection for live captions
+//! - [`waveform`] - Amplitude peaks for drawing waveforms
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use voicemark_core::{audio, transcribe};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! transcribe::init_model(Some("models/ggml-small.en.bin"))?;
+//!
+//! let wav = audio::convert_to_wav(&std::fs::read("meeting.m4a")?, None)?;
+//! let samples = audio::read_wav_samples(wav.path())?;
+//! let result = transcribe::transcribe(&samples, transcribe::TranscribeOptions::default())?;
+//! println!("{}", result.text);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod audio;
+pub mod constraint;
+pub mod fingerprint;
+pub mod formatting;
+pub mod hallucination;
+pub mod music;
+pub mod quality;
+pub mod session;
+pub mod speaker;
+pub mod transcribe;
+pub mod vad;
+pub mod waveform;
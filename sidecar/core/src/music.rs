@@ -0,0 +1,241 @@
+//! Music and noise detection for VoiceMark.
+//!
+//! Whisper has no notion of "no speech here": fed a podcast's intro music it
+//! happily transcribes pages of lyrics that were never sung. Batch requests
+//! can ask for regions of music or steady noise to be found first, so they
+//! are left out of transcription (and optionally marked with `[music]` or
+//! `[noise]` segments).
+//!
+//! The classification uses two classic features over 2-second windows.
+//! Speech pauses between syllables, so a good share of its frames are much
+//! quieter than the window average (the low-energy ratio), and it alternates
+//! voiced sounds with fricatives, so some frames have a far higher
+//! zero-crossing rate than the rest. Music and noise do neither. Noise is
+//! told apart from music by its high zero-crossing rate overall. Speech over
+//! a loud music bed still has both, so it is kept.
+
+use serde::{Deserialize, Serialize};
+
+use crate::vad;
+
+/// Sample rate of the audio fed to whisper.
+const SAMPLE_RATE: usize = 16000;
+/// Analysis frame length (20ms).
+const FRAME_SAMPLES: usize = SAMPLE_RATE / 50;
+/// Frames per classification step (1s).
+const HOP_FRAMES: usize = 50;
+/// Frames each step is classified from (2s, centered on the step).
+const WINDOW_FRAMES: usize = 2 * HOP_FRAMES;
+/// Windows quieter than this RMS are silence, which VAD already handles.
+const SILENCE_RMS: f32 = 0.002;
+/// Share of frames below half the window's energy from which it is speech.
+const MIN_SPEECH_LOW_ENERGY: f32 = 0.1;
+/// Share of frames above 1.5 times the window's zero-crossing rate from
+/// which it is speech.
+const MIN_SPEECH_HIGH_ZCR: f32 = 0.1;
+/// Mean zero-crossing rate above which non-speech is noise, not music.
+const NOISE_ZCR: f32 = 0.3;
+/// Shorter non-speech regions are left alone (a held note, a cough).
+const MIN_REGION_MS: i64 = 3000;
+
+/// What to do with music and noise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum NonSpeech {
+    /// Leave the regions out of transcription.
+    Exclude,
+    /// Leave them out, and add a `[music]` or `[noise]` segment for each.
+    Mark,
+}
+
+/// What a non-speech region sounds like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum NonSpeechKind {
+    Music,
+    Noise,
+}
+
+impl NonSpeechKind {
+    /// Text of the segment marking a region of this kind.
+    pub fn marker(self) -> &'static str {
+        match self {
+            NonSpeechKind::Music => "[music]",
+            NonSpeechKind::Noise => "[noise]",
+        }
+    }
+}
+
+/// A region of music or noise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NonSpeechRegion {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub kind: NonSpeechKind,
+}
+
+impl NonSpeechRegion {
+    /// The region's samples.
+    pub fn samples(&self) -> std::ops::Range<usize> {
+        let to_sample = |ms: i64| ms.max(0) as usize * SAMPLE_RATE / 1000;
+        to_sample(self.start_ms)..to_sample(self.end_ms)
+    }
+}
+
+/// Find the regions of music and noise in `samples`, in order.
+pub fn detect(samples: &[f32]) -> Vec<NonSpeechRegion> {
+    let frames: Vec<(f32, f32)> = samples
+        .chunks(FRAME_SAMPLES)
+        .map(|frame| (vad::rms(frame), zero_crossing_rate(frame)))
+        .collect();
+    let steps = frames.len().div_ceil(HOP_FRAMES);
+    let labels = (0..steps).map(|step| {
+        let center = step * HOP_FRAMES + HOP_FRAMES / 2;
+        let start = center.saturating_sub(WINDOW_FRAMES / 2);
+        let end = (start + WINDOW_FRAMES).min(frames.len());
+        classify(&frames[start..end])
+    });
+
+    // Merge runs of steps with the same label
+    let total_ms = (samples.len() * 1000 / SAMPLE_RATE) as i64;
+    let step_ms = (HOP_FRAMES * FRAME_SAMPLES * 1000 / SAMPLE_RATE) as i64;
+    let mut regions: Vec<NonSpeechRegion> = Vec::new();
+    for (step, label) in labels.enumerate() {
+        let Some(kind) = label else {
+            continue;
+        };
+        let start_ms = step as i64 * step_ms;
+        let end_ms = (start_ms + step_ms).min(total_ms);
+        match regions.last_mut() {
+            Some(last) if last.kind == kind && last.end_ms == start_ms => last.end_ms = end_ms,
+            _ => regions.push(NonSpeechRegion { start_ms, end_ms, kind }),
+        }
+    }
+    regions.retain(|region| region.end_ms - region.start_ms >= MIN_REGION_MS);
+    regions
+}
+
+/// `samples` with the `regions` silenced.
+pub fn silence(samples: &[f32], regions: &[NonSpeechRegion]) -> Vec<f32> {
+    let mut silenced = samples.to_vec();
+    for region in regions {
+        let range = region.samples();
+        let end = range.end.min(silenced.len());
+        silenced[range.start.min(end)..end].fill(0.0);
+    }
+    silenced
+}
+
+/// Classify a window of `(rms, zero-crossing rate)` frames: `None` for
+/// speech or silence.
+fn classify(frames: &[(f32, f32)]) -> Option<NonSpeechKind> {
+    if frames.len() < HOP_FRAMES {
+        return None;
+    }
+    let n = frames.len() as f32;
+    let mean_rms = frames.iter().map(|f| f.0).sum::<f32>() / n;
+    if mean_rms < SILENCE_RMS {
+        return None;
+    }
+    let mean_zcr = frames.iter().map(|f| f.1).sum::<f32>() / n;
+    let low_energy = frames.iter().filter(|f| f.0 < 0.5 * mean_rms).count() as f32 / n;
+    let high_zcr = frames.iter().filter(|f| f.1 > 1.5 * mean_zcr).count() as f32 / n;
+    if low_energy >= MIN_SPEECH_LOW_ENERGY || high_zcr >= MIN_SPEECH_HIGH_ZCR {
+        return None;
+    }
+    Some(if mean_zcr > NOISE_ZCR { NonSpeechKind::Noise } else { NonSpeechKind::Music })
+}
+
+/// Share of adjacent samples in `frame` with opposite signs.
+fn zero_crossing_rate(frame: &[f32]) -> f32 {
+    if frame.len() < 2 {
+        return 0.0;
+    }
+    let crossings = frame.windows(2).filter(|w| (w[0] >= 0.0) != (w[1] >= 0.0)).count();
+    crossings as f32 / (frame.len() - 1) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(s: f32) -> usize {
+        (s * SAMPLE_RATE as f32) as usize
+    }
+
+    /// A steady chord.
+    fn music(s: f32) -> Vec<f32> {
+        (0..seconds(s))
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                [220.0f32, 277.0, 330.0]
+                    .iter()
+                    .map(|f| (t * f * std::f32::consts::TAU).sin() * 0.1)
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Uniform white noise.
+    fn noise(s: f32) -> Vec<f32> {
+        let mut state = 0x2545_f491_u32;
+        (0..seconds(s))
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as f32 / u32::MAX as f32 - 0.5) * 0.4
+            })
+            .collect()
+    }
+
+    /// Syllable-like bursts: a vowel, a fricative, a pause.
+    fn speech(s: f32) -> Vec<f32> {
+        let syllable = [music(0.15), noise(0.1), vec![0.0; seconds(0.1)]].concat();
+        syllable.iter().copied().cycle().take(seconds(s)).collect()
+    }
+
+    #[test]
+    fn test_speech_is_not_flagged() {
+        assert!(detect(&speech(10.0)).is_empty());
+        assert!(detect(&vec![0.0; seconds(10.0)]).is_empty());
+    }
+
+    #[test]
+    fn test_music_and_noise_regions() {
+        let music_only = detect(&music(8.0));
+        assert_eq!(
+            music_only,
+            vec![NonSpeechRegion { start_ms: 0, end_ms: 8000, kind: NonSpeechKind::Music }]
+        );
+        let noise_only = detect(&noise(5.0));
+        assert_eq!(noise_only.len(), 1);
+        assert_eq!(noise_only[0].kind, NonSpeechKind::Noise);
+    }
+
+    #[test]
+    fn test_music_intro_before_speech() {
+        let audio = [music(10.0), speech(10.0)].concat();
+        let regions = detect(&audio);
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].kind, NonSpeechKind::Music);
+        assert_eq!(regions[0].start_ms, 0);
+        // Within a step of the real boundary
+        assert!((9000..=10000).contains(&regions[0].end_ms), "{:?}", regions[0]);
+        assert_eq!(regions[0].samples(), 0..regions[0].end_ms as usize * 16);
+
+        let silenced = silence(&audio, &regions);
+        assert!(silenced[regions[0].samples()].iter().all(|&s| s == 0.0));
+        assert_eq!(silenced[seconds(10.0)..], audio[seconds(10.0)..]);
+        assert!(detect(&silenced).is_empty());
+    }
+
+    #[test]
+    fn test_short_regions_are_ignored() {
+        let audio = [speech(5.0), music(1.5), speech(5.0)].concat();
+        assert!(detect(&audio).is_empty());
+    }
+}
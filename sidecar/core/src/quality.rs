@@ -0,0 +1,117 @@
+//! Recording quality checks for VoiceMark.
+//!
+//! A clipping microphone or a recording made at a whisper of a level gives
+//! poor transcripts, and users tend to blame the model. Batch results carry
+//! warnings about such problems so the client can point at the real cause.
+
+use serde::{Deserialize, Serialize};
+
+/// Samples at or above this magnitude are clipped (16-bit full scale is
+/// 32767/32768).
+const CLIPPED: f32 = 0.999;
+/// Share of clipped samples from which clipping is reported (0.1%).
+const MAX_CLIPPED_SHARE: f32 = 0.001;
+/// Peak level below which the recording is too quiet (about -30 dBFS).
+const MIN_PEAK: f32 = 0.03;
+/// Mean sample value beyond which the DC offset is reported.
+const MAX_DC_OFFSET: f32 = 0.05;
+
+/// What is wrong with the audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum WarningCode {
+    /// The input level was too high and the waveform was cut off.
+    Clipping,
+    /// The recording is very quiet.
+    LowLevel,
+    /// The waveform is shifted away from zero, usually a faulty microphone
+    /// or sound card.
+    DcOffset,
+}
+
+/// A problem with the recording that may hurt transcription.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AudioWarning {
+    pub code: WarningCode,
+    /// Explanation to show to the user.
+    pub message: String,
+}
+
+/// Check `samples` for clipping, a very low level and DC offset.
+pub fn analyze(samples: &[f32]) -> Vec<AudioWarning> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+    let n = samples.len() as f32;
+    let clipped = samples.iter().filter(|s| s.abs() >= CLIPPED).count() as f32 / n;
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    let mean = samples.iter().sum::<f32>() / n;
+
+    let mut warnings = Vec::new();
+    if clipped >= MAX_CLIPPED_SHARE {
+        warnings.push(AudioWarning {
+            code: WarningCode::Clipping,
+            message: format!(
+                "The input level was too high: {:.1}% of the audio is clipped. \
+                 Lower the microphone gain.",
+                clipped * 100.0
+            ),
+        });
+    }
+    // Digital silence is left to the empty transcript to explain
+    if peak > 0.0 && peak < MIN_PEAK {
+        warnings.push(AudioWarning {
+            code: WarningCode::LowLevel,
+            message: format!(
+                "The recording is very quiet (peak {:.0} dBFS). Move closer to the \
+                 microphone or raise its gain.",
+                20.0 * peak.log10()
+            ),
+        });
+    }
+    if mean.abs() > MAX_DC_OFFSET {
+        warnings.push(AudioWarning {
+            code: WarningCode::DcOffset,
+            message: format!(
+                "The audio has a DC offset of {:.0}% of full scale, which usually \
+                 points to a faulty microphone or sound card.",
+                mean.abs() * 100.0
+            ),
+        });
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(amplitude: f32, offset: f32) -> Vec<f32> {
+        (0..16000)
+            .map(|i| ((i as f32 * 0.05).sin() * amplitude + offset).clamp(-1.0, 1.0))
+            .collect()
+    }
+
+    fn codes(samples: &[f32]) -> Vec<WarningCode> {
+        analyze(samples).into_iter().map(|w| w.code).collect()
+    }
+
+    #[test]
+    fn test_clean_audio_has_no_warnings() {
+        assert!(analyze(&tone(0.3, 0.0)).is_empty());
+        assert!(analyze(&[0.0; 16000]).is_empty());
+        assert!(analyze(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_problems_are_reported() {
+        assert_eq!(codes(&tone(1.5, 0.0)), vec![WarningCode::Clipping]);
+        assert_eq!(codes(&tone(0.01, 0.0)), vec![WarningCode::LowLevel]);
+        assert_eq!(codes(&tone(0.3, 0.2)), vec![WarningCode::DcOffset]);
+
+        let quiet = analyze(&tone(0.01, 0.0));
+        assert!(quiet[0].message.contains("(peak -40 dBFS)"), "{}", quiet[0].message);
+    }
+}
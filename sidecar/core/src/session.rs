@@ -0,0 +1,636 @@
+//! Live (streaming) transcription sessions.
+//!
+//! Audio arrives in small frames and is accumulated into chunks. While a
+//! chunk fills up it is transcribed every ~500ms as a *partial* result that
+//! may still change; once it reaches 6 seconds it is transcribed one last
+//! time as a *final* result and a new chunk starts. How often partials run,
+//! and how much audio they need, is set per session (see [`SessionConfig`]).
+//! During pauses no partials run: if the audio since the last one is
+//! silent, the partial wouldn't change, so the chunk just keeps filling up.
+//!
+//! Finals can carry word timings relative to the start of the stream (see
+//! [`StreamingSession::commit_final_result`]), so clients can highlight
+//! words while playing back the recorded audio. The session keeps every
+//! committed final, so the whole transcript can be sent when the stream
+//! ends (see [`StreamingSession::transcript`]).
+//!
+//! [`StreamingSession`] only decides what to transcribe and when; it does no
+//! I/O and runs no model, so callers can drive it from any transport:
+//!
+//! ```no_run
+//! use voicemark_core::session::{self, StreamingSession, Work};
+//!
+//! # fn frames() -> Vec<Vec<f32>> { Vec::new() }
+//! # fn main() -> anyhow::Result<()> {
+//! let mut session = StreamingSession::new();
+//! for frame in frames() {
+//!     match session.push(&frame) {
+//!         Some(Work::Partial(audio)) => {
+//!             let text = session::transcribe_chunk(&audio);
+//!             session.finish_transcription();
+//!             if let Some(text) = text? {
+//!                 println!("partial: {}", text);
+//!             }
+//!         }
+//!         Some(Work::Final(audio)) => {
+//!             let text = session::transcribe_chunk(&audio);
+//!             session.finish_transcription();
+//!             if let Some(text) = text? {
+//!                 println!("final: {}", session.commit_final(text));
+//!             }
+//!         }
+//!         None => {}
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::Result;
+use serde::Serialize;
+use std::time::Instant;
+use tracing::debug;
+
+use crate::hallucination;
+use crate::transcribe::{self, TranscribeOptions, TranscribeResult, Word};
+use crate::vad;
+
+/// Sample rate of streamed audio.
+pub const SAMPLE_RATE: u32 = 16000;
+/// Chunk size before auto-commit (6 seconds of audio)
+const CHUNK_SECONDS: f32 = 6.0;
+pub const CHUNK_SAMPLES: usize = (SAMPLE_RATE as f32 * CHUNK_SECONDS) as usize;
+/// Default minimum interval between partials (throttle to avoid overload)
+pub const DEFAULT_PARTIAL_INTERVAL_MS: u64 = 500;
+/// Bounds of the interval between partials
+pub const PARTIAL_INTERVAL_RANGE_MS: (u64, u64) = (100, 10_000);
+/// Default minimum audio in a chunk before it is transcribed as a partial
+pub const DEFAULT_MIN_PARTIAL_MS: u64 = 500;
+/// Bounds of the minimum partial audio; a full chunk is always final
+pub const MIN_PARTIAL_RANGE_MS: (u64, u64) = (100, CHUNK_SECONDS as u64 * 1000);
+/// Minimum number of words that must repeat across a chunk boundary
+/// before they are treated as duplicated overlap
+const MIN_OVERLAP_WORDS: usize = 2;
+/// Longest overlap (in words) checked across a chunk boundary
+const MAX_OVERLAP_WORDS: usize = 12;
+
+/// Audio the caller should transcribe next, as decided by
+/// [`StreamingSession::push`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Work {
+    /// The chunk so far; its text is a partial result that may change.
+    Partial(Vec<f32>),
+    /// A complete chunk; its text is final. The session has already moved
+    /// on to the next chunk.
+    Final(Vec<f32>),
+}
+
+/// Partial result timing of a session. Low-latency captioning wants
+/// frequent partials; battery-constrained clients want fewer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionConfig {
+    /// Minimum time between partials, in milliseconds.
+    pub partial_interval_ms: u64,
+    /// Minimum audio in the chunk before it is transcribed as a partial, in
+    /// milliseconds.
+    pub min_partial_ms: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            partial_interval_ms: DEFAULT_PARTIAL_INTERVAL_MS,
+            min_partial_ms: DEFAULT_MIN_PARTIAL_MS,
+        }
+    }
+}
+
+impl SessionConfig {
+    /// Settings with each value clamped to its range
+    /// ([`PARTIAL_INTERVAL_RANGE_MS`], [`MIN_PARTIAL_RANGE_MS`]).
+    pub fn new(partial_interval_ms: u64, min_partial_ms: u64) -> Self {
+        let (interval_min, interval_max) = PARTIAL_INTERVAL_RANGE_MS;
+        let (audio_min, audio_max) = MIN_PARTIAL_RANGE_MS;
+        Self {
+            partial_interval_ms: partial_interval_ms.clamp(interval_min, interval_max),
+            min_partial_ms: min_partial_ms.clamp(audio_min, audio_max),
+        }
+    }
+}
+
+/// A committed final, positioned in the stream.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SessionSegment {
+    /// Start of the chunk it was transcribed from, in milliseconds from the
+    /// start of the stream.
+    pub start_ms: i64,
+    /// End of that chunk.
+    pub end_ms: i64,
+    /// Text as committed, without the overlap with the previous final.
+    pub text: String,
+}
+
+/// State for a streaming transcription session
+#[derive(Debug)]
+pub struct StreamingSession {
+    /// Partial result timing
+    config: SessionConfig,
+    /// Current audio chunk being accumulated (f32, 16kHz mono)
+    current_chunk: Vec<f32>,
+    /// Last time we ran transcription (for throttling)
+    last_transcribe_time: Option<Instant>,
+    /// Length of the chunk at the last partial (or skipped silent partial)
+    partial_end: usize,
+    /// Whether a transcription is currently in progress
+    transcription_pending: bool,
+    /// Text of the last committed final (for overlap deduplication)
+    last_final: String,
+    /// Samples taken as chunks since the stream started
+    taken_samples: usize,
+    /// Position of the last taken chunk in the stream, in samples
+    last_chunk_start: usize,
+    /// Finals committed since the stream started
+    transcript: Vec<SessionSegment>,
+}
+
+impl Default for StreamingSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamingSession {
+    pub fn new() -> Self {
+        Self::with_config(SessionConfig::default())
+    }
+
+    /// A session with the given partial result timing.
+    pub fn with_config(config: SessionConfig) -> Self {
+        Self {
+            config,
+            current_chunk: Vec::with_capacity(CHUNK_SAMPLES),
+            last_transcribe_time: None,
+            partial_end: 0,
+            transcription_pending: false,
+            last_final: String::new(),
+            taken_samples: 0,
+            last_chunk_start: 0,
+            transcript: Vec::new(),
+        }
+    }
+
+    /// Partial result timing of this session.
+    pub fn config(&self) -> SessionConfig {
+        self.config
+    }
+
+    /// Discard all buffered audio and state, keeping the configuration.
+    pub fn reset(&mut self) {
+        self.current_chunk.clear();
+        self.last_transcribe_time = None;
+        self.partial_end = 0;
+        self.transcription_pending = false;
+        self.last_final.clear();
+        self.taken_samples = 0;
+        self.last_chunk_start = 0;
+        self.transcript.clear();
+    }
+
+    /// Add 16kHz mono samples and return the audio to transcribe, if any.
+    ///
+    /// Full chunks are always returned as [`Work::Final`]. Otherwise the
+    /// chunk so far is returned as [`Work::Partial`] unless a transcription
+    /// is still running, one ran less than the partial interval ago, there
+    /// is less audio than the minimum (500ms each by default), or the audio
+    /// since the last partial is silent. After transcribing the returned audio, call
+    /// [`finish_transcription`](Self::finish_transcription).
+    pub fn push(&mut self, samples: &[f32]) -> Option<Work> {
+        let chunk_ready = self.add_samples(samples);
+        debug!("Added {} samples, chunk_ready={}", samples.len(), chunk_ready);
+
+        if chunk_ready {
+            self.transcription_pending = true;
+            Some(Work::Final(self.take_chunk()))
+        } else if self.should_transcribe() && self.has_meaningful_audio() {
+            let new_audio = &self.current_chunk[self.partial_end..];
+            self.partial_end = self.current_chunk.len();
+            if hallucination::is_silent(new_audio) {
+                // Nothing new to hear; check again after the interval
+                debug!("Skipping partial over {} silent samples", new_audio.len());
+                self.last_transcribe_time = Some(Instant::now());
+                return None;
+            }
+            self.transcription_pending = true;
+            Some(Work::Partial(self.current_chunk.clone()))
+        } else {
+            None
+        }
+    }
+
+    /// Mark the transcription of the last [`Work`] as done.
+    pub fn finish_transcription(&mut self) {
+        self.transcription_pending = false;
+        self.last_transcribe_time = Some(Instant::now());
+    }
+
+    /// Audio buffered since the last final, in samples.
+    pub fn buffered_samples(&self) -> usize {
+        self.current_chunk.len()
+    }
+
+    /// Take the audio buffered since the last final, e.g. to transcribe it
+    /// when the stream ends.
+    pub fn take_chunk(&mut self) -> Vec<f32> {
+        let chunk = std::mem::take(&mut self.current_chunk);
+        self.partial_end = 0;
+        self.last_chunk_start = self.taken_samples;
+        self.taken_samples += chunk.len();
+        chunk
+    }
+
+    /// Drop the audio buffered so far and skip the `samples` that followed
+    /// it, which won't be transcribed (e.g. dropped to catch up with a live
+    /// stream), so later finals stay positioned in the stream.
+    pub fn skip(&mut self, samples: usize) {
+        self.taken_samples += self.current_chunk.len() + samples;
+        self.current_chunk.clear();
+        self.partial_end = 0;
+    }
+
+    /// Position of the last taken chunk (the one a final is transcribed
+    /// from) in the stream, in milliseconds.
+    pub fn last_chunk_offset_ms(&self) -> i64 {
+        (self.last_chunk_start as u64 * 1000 / SAMPLE_RATE as u64) as i64
+    }
+
+    /// Length of the audio taken as chunks since the stream started, in
+    /// milliseconds.
+    pub fn duration_ms(&self) -> i64 {
+        (self.taken_samples as u64 * 1000 / SAMPLE_RATE as u64) as i64
+    }
+
+    /// Record a committed final of the last taken chunk, returning its text
+    /// with any words that repeat the end of the previous final removed
+    pub fn commit_final(&mut self, text: String) -> String {
+        let text = dedup_overlap(&self.last_final, &text);
+        if !text.is_empty() {
+            self.last_final = text.clone();
+            self.transcript.push(SessionSegment {
+                start_ms: self.last_chunk_offset_ms(),
+                end_ms: self.duration_ms(),
+                text: text.clone(),
+            });
+        }
+        text
+    }
+
+    /// Finals committed since the stream started (or was reset), in order.
+    pub fn transcript(&self) -> &[SessionSegment] {
+        &self.transcript
+    }
+
+    /// The committed finals joined into one text.
+    pub fn transcript_text(&self) -> String {
+        let texts: Vec<&str> = self.transcript.iter().map(|segment| segment.text.trim()).collect();
+        texts.join(" ")
+    }
+
+    /// Commit the result of transcribing the last taken chunk, like
+    /// [`commit_final`](Self::commit_final). Also returns its word timings
+    /// (if any) relative to the start of the stream, minus the words removed
+    /// as overlap.
+    pub fn commit_final_result(&mut self, result: TranscribeResult) -> (String, Vec<Word>) {
+        let word_count = result.text.split_whitespace().count();
+        let text = self.commit_final(result.text);
+        let removed = word_count - text.split_whitespace().count();
+
+        let offset_ms = self.last_chunk_offset_ms();
+        let words = result
+            .words
+            .into_iter()
+            .skip(removed)
+            .map(|word| Word {
+                start_ms: word.start_ms + offset_ms,
+                end_ms: word.end_ms + offset_ms,
+                ..word
+            })
+            .collect();
+        (text, words)
+    }
+
+    /// Add audio samples to the current chunk
+    /// Returns true if chunk is ready for auto-commit
+    fn add_samples(&mut self, samples: &[f32]) -> bool {
+        self.current_chunk.extend_from_slice(samples);
+        self.current_chunk.len() >= CHUNK_SAMPLES
+    }
+
+    /// Check if enough time has passed to transcribe again
+    fn should_transcribe(&self) -> bool {
+        if self.transcription_pending {
+            return false;
+        }
+        match self.last_transcribe_time {
+            None => true,
+            Some(last) => last.elapsed().as_millis() >= self.config.partial_interval_ms as u128,
+        }
+    }
+
+    /// Check if chunk has enough audio for a meaningful partial
+    fn has_meaningful_audio(&self) -> bool {
+        self.current_chunk.len() as u64 * 1000 >= self.config.min_partial_ms * SAMPLE_RATE as u64
+    }
+}
+
+/// Convert 16-bit little-endian PCM to f32 samples. A trailing odd byte is
+/// ignored.
+pub fn pcm16_to_f32(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(2)
+        .map(|chunk| {
+            let sample = i16::from_le_bytes([chunk[0], chunk[1]]);
+            sample as f32 / 32768.0
+        })
+        .collect()
+}
+
+/// Transcribe a chunk of streamed audio (English). Blocks while whisper runs.
+///
+/// Returns `Ok(None)` when there is nothing to emit: the chunk is silence,
+/// or whisper produced a likely hallucination ("Thank you.", "[BLANK_AUDIO]").
+pub fn transcribe_chunk(audio_data: &[f32]) -> Result<Option<String>> {
+    let result = transcribe_chunk_with(audio_data, None, transcribe::transcribe)?;
+    Ok(result.map(|result| result.text))
+}
+
+/// Like [`transcribe_chunk`], in `language` (English by default, `"auto"`
+/// to detect it) and transcribing with `transcribe` instead of the local
+/// model, e.g. to forward the audio to another backend. Returns the whole
+/// result, including the detected language and word timings (relative to
+/// the chunk).
+pub fn transcribe_chunk_with<T>(
+    audio_data: &[f32],
+    language: Option<&str>,
+    transcribe: T,
+) -> Result<Option<TranscribeResult>>
+where
+    T: FnOnce(&[f32], TranscribeOptions) -> Result<TranscribeResult>,
+{
+    if hallucination::is_silent(audio_data) {
+        debug!("Skipping silent chunk ({} samples)", audio_data.len());
+        return Ok(None);
+    }
+
+    let rms = vad::rms(audio_data);
+    let options = TranscribeOptions {
+        language: Some(language.unwrap_or("en").to_string()),
+        translate: false,
+        word_timestamps: true,
+        ..Default::default()
+    };
+    let result = transcribe(audio_data, options)?;
+
+    if hallucination::is_hallucination(&result.text, rms, result.avg_token_prob) {
+        debug!(text = %result.text, rms, "Suppressed likely hallucination");
+        return Ok(None);
+    }
+    Ok(Some(result))
+}
+
+/// Normalize a word for overlap comparison (case and punctuation-insensitive)
+fn normalize_word(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Remove the leading words of `next` that repeat the trailing words of
+/// `previous`.
+///
+/// Speech that straddles a chunk boundary is often transcribed in both
+/// chunks; this strips the longest such suffix/prefix overlap.
+pub fn dedup_overlap(previous: &str, next: &str) -> String {
+    let prev_words: Vec<String> = previous.split_whitespace().map(normalize_word).collect();
+    let next_tokens: Vec<&str> = next.split_whitespace().collect();
+    let next_words: Vec<String> = next_tokens.iter().map(|w| normalize_word(w)).collect();
+
+    let max_overlap = prev_words.len().min(next_words.len()).min(MAX_OVERLAP_WORDS);
+    let overlap = (MIN_OVERLAP_WORDS..=max_overlap)
+        .rev()
+        .find(|&k| prev_words[prev_words.len() - k..] == next_words[..k]);
+
+    match overlap {
+        Some(k) => {
+            debug!("Removed {} overlapping words from final", k);
+            next_tokens[k..].join(" ")
+        }
+        None => next.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pcm16_to_f32() {
+        // 0x0000 (0) -> 0.0, 0x7FFF (32767) -> ~1.0
+        let samples = pcm16_to_f32(&[0x00, 0x00, 0xFF, 0x7F]);
+        assert_eq!(samples.len(), 2);
+        assert!((samples[0] - 0.0).abs() < 0.001);
+        assert!((samples[1] - 0.99997).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_push_commits_full_chunks() {
+        let mut session = StreamingSession::new();
+
+        // Half a chunk: a partial is due
+        let work = session.push(&vec![0.5f32; CHUNK_SAMPLES / 2]);
+        assert!(matches!(work, Some(Work::Partial(ref audio)) if audio.len() == CHUNK_SAMPLES / 2));
+
+        // Still transcribing: no new partial
+        assert_eq!(session.push(&[0.5f32; 100]), None);
+
+        // A full chunk is committed even while a partial is running
+        let work = session.push(&vec![0.5f32; CHUNK_SAMPLES]);
+        assert!(matches!(work, Some(Work::Final(ref audio)) if audio.len() > CHUNK_SAMPLES));
+        assert!(session.take_chunk().is_empty());
+    }
+
+    #[test]
+    fn test_push_throttles_partials() {
+        let mut session = StreamingSession::new();
+
+        // Under half a second of audio is not worth transcribing
+        assert_eq!(session.push(&[0.5f32; 1000]), None);
+
+        assert!(session.push(&vec![0.5f32; SAMPLE_RATE as usize]).is_some());
+        session.finish_transcription();
+        // Just transcribed: wait before the next partial
+        assert_eq!(session.push(&[0.5f32; 1000]), None);
+    }
+
+    #[test]
+    fn test_session_config_sets_partial_timing() {
+        let config = SessionConfig::new(0, 2000);
+        assert_eq!(config.partial_interval_ms, PARTIAL_INTERVAL_RANGE_MS.0);
+        assert_eq!(SessionConfig::new(60_000, 60_000).min_partial_ms, 6000);
+
+        let mut session = StreamingSession::with_config(config);
+        // Under two seconds of audio is too little for this session
+        assert_eq!(session.push(&vec![0.5f32; SAMPLE_RATE as usize]), None);
+        assert!(session.push(&vec![0.5f32; SAMPLE_RATE as usize]).is_some());
+        session.finish_transcription();
+
+        // 100ms apart is long enough
+        std::thread::sleep(std::time::Duration::from_millis(110));
+        assert!(session.push(&[0.5f32; 100]).is_some());
+
+        session.reset();
+        assert_eq!(session.config(), config);
+    }
+
+    #[test]
+    fn test_silence_skips_partials() {
+        let mut session = StreamingSession::with_config(SessionConfig::new(100, 500));
+        let interval = std::time::Duration::from_millis(110);
+        let second = SAMPLE_RATE as usize;
+        assert!(session.push(&vec![0.5f32; second]).is_some());
+        session.finish_transcription();
+
+        // A pause: the partial wouldn't change
+        std::thread::sleep(interval);
+        assert_eq!(session.push(&vec![0.0f32; second / 2]), None);
+
+        // Speech again: the partial covers the whole chunk
+        std::thread::sleep(interval);
+        let work = session.push(&vec![0.5f32; second / 2]);
+        assert!(matches!(work, Some(Work::Partial(ref audio)) if audio.len() == 2 * second));
+        session.finish_transcription();
+
+        // A silent chunk still becomes final
+        let work = session.push(&vec![0.0f32; CHUNK_SAMPLES]);
+        assert!(matches!(work, Some(Work::Final(_))));
+    }
+
+    #[test]
+    fn test_skip_keeps_finals_in_place() {
+        let mut session = StreamingSession::new();
+        session.push(&[0.5f32; 8000]);
+        // The buffered half second is dropped with the skipped audio
+        session.skip(SAMPLE_RATE as usize * 2);
+        assert_eq!(session.buffered_samples(), 0);
+        session.push(&[0.5f32; 8000]);
+        session.take_chunk();
+        session.commit_final("Still here.".to_string());
+        assert_eq!(session.transcript()[0].start_ms, 2500);
+        assert_eq!(session.duration_ms(), 3000);
+    }
+
+    #[test]
+    fn test_dedup_overlap_removes_repeated_prefix() {
+        let previous = "We should ship the release on";
+        let next = "the release on Friday, after review.";
+        assert_eq!(dedup_overlap(previous, next), "Friday, after review.");
+    }
+
+    #[test]
+    fn test_dedup_overlap_ignores_case_and_punctuation() {
+        let previous = "Let's meet at the office.";
+        let next = "At the office we can talk.";
+        assert_eq!(dedup_overlap(previous, next), "we can talk.");
+    }
+
+    #[test]
+    fn test_dedup_overlap_keeps_single_word_repeats() {
+        // A single repeated word is more likely genuine than overlap
+        let previous = "I said no";
+        let next = "no problem";
+        assert_eq!(dedup_overlap(previous, next), "no problem");
+    }
+
+    #[test]
+    fn test_commit_final_tracks_previous_text() {
+        let mut session = StreamingSession::new();
+        assert_eq!(session.commit_final("hello there my friend".into()), "hello there my friend");
+        assert_eq!(session.commit_final("my friend how are you".into()), "how are you");
+
+        session.reset();
+        assert_eq!(session.commit_final("how are you".into()), "how are you");
+    }
+
+    #[test]
+    fn test_transcript_accumulates_finals() {
+        let mut session = StreamingSession::new();
+        session.push(&vec![0.5f32; CHUNK_SAMPLES]);
+        session.commit_final("see you on".into());
+        session.push(&vec![0.5f32; CHUNK_SAMPLES / 2]);
+        session.take_chunk();
+        session.commit_final("you on Friday".into());
+        session.commit_final(String::new());
+
+        assert_eq!(session.duration_ms(), 9000);
+        assert_eq!(session.transcript_text(), "see you on Friday");
+        let segment = |start_ms, end_ms, text: &str| SessionSegment {
+            start_ms,
+            end_ms,
+            text: text.to_string(),
+        };
+        assert_eq!(
+            session.transcript(),
+            [segment(0, 6000, "see you on"), segment(6000, 9000, "Friday")]
+        );
+
+        session.reset();
+        assert!(session.transcript().is_empty());
+    }
+
+    #[test]
+    fn test_commit_final_result_times_words_from_stream_start() {
+        let word = |start_ms, text: &str| Word {
+            start_ms,
+            end_ms: start_ms + 300,
+            text: text.to_string(),
+            probability: 0.9,
+        };
+        let result = |text: &str, words: Vec<Word>| TranscribeResult {
+            text: text.to_string(),
+            segments: 1,
+            avg_token_prob: 0.9,
+            timed_segments: Vec::new(),
+            words,
+            tokens: Vec::new(),
+            language: None,
+            language_probability: None,
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        let mut session = StreamingSession::new();
+        session.push(&vec![0.5f32; CHUNK_SAMPLES]);
+        let (text, words) = session.commit_final_result(result(
+            "see you on",
+            vec![word(0, "see"), word(300, "you"), word(600, "on")],
+        ));
+        assert_eq!(text, "see you on");
+        assert_eq!(words[2].start_ms, 600);
+
+        // The second chunk starts 6 seconds in; "you on" repeats the first
+        session.push(&vec![0.5f32; CHUNK_SAMPLES]);
+        assert_eq!(session.last_chunk_offset_ms(), 6000);
+        let (text, words) = session.commit_final_result(result(
+            "you on Friday",
+            vec![word(0, "you"), word(300, "on"), word(600, "Friday")],
+        ));
+        assert_eq!(text, "Friday");
+        assert_eq!(words, vec![word(6600, "Friday")]);
+
+        session.reset();
+        assert_eq!(session.last_chunk_offset_ms(), 0);
+    }
+}
@@ -0,0 +1,226 @@
+//! Lightweight online speaker change detection for VoiceMark.
+//!
+//! Live captions of an interview read better with a line per speaker, but
+//! full diarization needs the whole recording and a neural model. Instead,
+//! each committed chunk gets a *voiceprint*: its average spectral envelope
+//! (loudness-normalized energy in bands from 150 Hz to 4 kHz) over frames
+//! with speech. [`SpeakerTracker`] compares it with the voices heard so far
+//! and numbers speakers in order of appearance.
+//!
+//! It is a heuristic: voices with similar timbre can be merged, and a
+//! change of microphone or room can look like a new speaker. A chunk with
+//! two speakers is attributed to whoever dominates it.
+
+use std::f32::consts::PI;
+
+/// Sample rate of the audio.
+const SAMPLE_RATE: f32 = 16000.0;
+/// Analysis frame length (32ms).
+const FRAME_SAMPLES: usize = 512;
+/// Frequency bands of a voiceprint.
+const BANDS: usize = 16;
+/// Frequencies measured per band.
+const BINS_PER_BAND: usize = 3;
+/// Lowest and highest measured frequencies, in Hz.
+const MIN_HZ: f32 = 150.0;
+const MAX_HZ: f32 = 4000.0;
+/// Range below the loudest band that is measured, in dB.
+const DYNAMIC_RANGE_DB: f32 = 50.0;
+/// Frames quieter than this RMS don't contain speech.
+const SPEECH_RMS: f32 = 0.01;
+/// Speech needed for a voiceprint (about a second).
+const MIN_SPEECH_FRAMES: usize = 30;
+/// Most speakers told apart; further voices go to the closest one.
+const MAX_SPEAKERS: usize = 8;
+
+/// Default distance (RMS difference in dB across bands) beyond which a
+/// voice is a different speaker.
+pub const DEFAULT_CHANGE_DB: f32 = 4.0;
+
+/// Average spectral envelope of the speech in a chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Voiceprint {
+    /// Band energies in dB, relative to their mean.
+    bands: [f32; BANDS],
+}
+
+impl Voiceprint {
+    /// RMS difference from `other` across bands, in dB.
+    pub fn distance(&self, other: &Voiceprint) -> f32 {
+        let sum: f32 = self.bands.iter().zip(&other.bands).map(|(a, b)| (a - b).powi(2)).sum();
+        (sum / BANDS as f32).sqrt()
+    }
+}
+
+/// Compute the voiceprint of 16kHz mono `samples`, or `None` if they hold
+/// less than about a second of speech.
+pub fn voiceprint(samples: &[f32]) -> Option<Voiceprint> {
+    let frequencies: Vec<f32> = (0..BANDS * BINS_PER_BAND)
+        .map(|i| MIN_HZ * (MAX_HZ / MIN_HZ).powf(i as f32 / (BANDS * BINS_PER_BAND - 1) as f32))
+        .collect();
+
+    let mut sum = [0.0f32; BANDS];
+    let mut frames = 0;
+    for frame in samples.chunks_exact(FRAME_SAMPLES) {
+        if crate::vad::rms(frame) < SPEECH_RMS {
+            continue;
+        }
+        let mut bands = [0.0f32; BANDS];
+        for (band, freqs) in bands.iter_mut().zip(frequencies.chunks(BINS_PER_BAND)) {
+            let power: f32 = freqs.iter().map(|&hz| goertzel(frame, hz)).sum();
+            *band = 10.0 * (power / BINS_PER_BAND as f32 + 1e-12).log10();
+        }
+        // Bands far below the loudest are noise, whatever their level
+        let loudest = bands.iter().copied().fold(f32::MIN, f32::max);
+        for band in &mut bands {
+            *band = band.max(loudest - DYNAMIC_RANGE_DB);
+        }
+        // Loudness doesn't tell voices apart; the envelope's shape does
+        let mean = bands.iter().sum::<f32>() / BANDS as f32;
+        for (total, band) in sum.iter_mut().zip(bands) {
+            *total += band - mean;
+        }
+        frames += 1;
+    }
+    if frames < MIN_SPEECH_FRAMES {
+        return None;
+    }
+    Some(Voiceprint { bands: sum.map(|total| total / frames as f32) })
+}
+
+/// Power of `frame` at `hz` (Goertzel algorithm, Hann-windowed).
+fn goertzel(frame: &[f32], hz: f32) -> f32 {
+    let coeff = 2.0 * (2.0 * PI * hz / SAMPLE_RATE).cos();
+    let n = frame.len() as f32;
+    let (mut s1, mut s2) = (0.0f32, 0.0f32);
+    for (i, &sample) in frame.iter().enumerate() {
+        let window = 0.5 - 0.5 * (2.0 * PI * i as f32 / n).cos();
+        let s = sample * window + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s;
+    }
+    (s1 * s1 + s2 * s2 - coeff * s1 * s2) / n
+}
+
+/// Who is speaking in a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeakerTurn {
+    /// Speaker number, from 0 in order of appearance.
+    pub speaker: usize,
+    /// Whether someone else spoke in the previous chunk with speech.
+    pub changed: bool,
+}
+
+/// A voice heard so far.
+#[derive(Debug, Clone, PartialEq)]
+struct Speaker {
+    /// Running average of its voiceprints
+    print: Voiceprint,
+    chunks: usize,
+}
+
+/// Tells speakers apart across the chunks of a stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeakerTracker {
+    change_db: f32,
+    speakers: Vec<Speaker>,
+    current: Option<usize>,
+}
+
+impl Default for SpeakerTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHANGE_DB)
+    }
+}
+
+impl SpeakerTracker {
+    /// A tracker treating voices further than `change_db` apart as different
+    /// speakers.
+    pub fn new(change_db: f32) -> Self {
+        Self { change_db, speakers: Vec::new(), current: None }
+    }
+
+    /// Attribute the next chunk's voiceprint to a speaker: the closest one
+    /// heard so far within the threshold, or a new one.
+    pub fn observe(&mut self, print: &Voiceprint) -> SpeakerTurn {
+        let closest = self
+            .speakers
+            .iter()
+            .enumerate()
+            .map(|(i, speaker)| (i, speaker.print.distance(print)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        let speaker = match closest {
+            Some((i, distance)) if distance <= self.change_db => i,
+            Some((i, _)) if self.speakers.len() >= MAX_SPEAKERS => i,
+            _ => {
+                self.speakers.push(Speaker { print: print.clone(), chunks: 0 });
+                self.speakers.len() - 1
+            }
+        };
+
+        let known = &mut self.speakers[speaker];
+        known.chunks += 1;
+        let weight = 1.0 / known.chunks as f32;
+        for (band, new) in known.print.bands.iter_mut().zip(print.bands) {
+            *band += (new - *band) * weight;
+        }
+
+        let changed = self.current.is_some_and(|current| current != speaker);
+        self.current = Some(speaker);
+        SpeakerTurn { speaker, changed }
+    }
+
+    /// The speaker of the last chunk with speech, if any.
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two seconds of a voice-like tone: harmonics of `pitch_hz`, falling
+    /// off by `tilt` per harmonic.
+    fn voice(pitch_hz: f32, tilt: f32) -> Vec<f32> {
+        (0..32000)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE;
+                (1..20)
+                    .map(|k| tilt.powi(k) * (2.0 * PI * pitch_hz * k as f32 * t).sin())
+                    .sum::<f32>()
+                    * 0.1
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_voiceprint_needs_speech() {
+        assert!(voiceprint(&[0.0; 32000]).is_none());
+        assert!(voiceprint(&voice(120.0, 0.7)[..8000]).is_none());
+        assert!(voiceprint(&voice(120.0, 0.7)).is_some());
+    }
+
+    #[test]
+    fn test_voiceprint_ignores_loudness() {
+        let quiet: Vec<f32> = voice(120.0, 0.7).iter().map(|s| s * 0.3).collect();
+        let a = voiceprint(&voice(120.0, 0.7)).unwrap();
+        let b = voiceprint(&quiet).unwrap();
+        assert!(a.distance(&b) < 0.5);
+    }
+
+    #[test]
+    fn test_tracker_numbers_speakers() {
+        let low = voiceprint(&voice(110.0, 0.6)).unwrap();
+        let high = voiceprint(&voice(230.0, 0.9)).unwrap();
+        assert!(low.distance(&high) > DEFAULT_CHANGE_DB);
+
+        let mut tracker = SpeakerTracker::default();
+        assert_eq!(tracker.observe(&low), SpeakerTurn { speaker: 0, changed: false });
+        assert_eq!(tracker.observe(&low), SpeakerTurn { speaker: 0, changed: false });
+        assert_eq!(tracker.observe(&high), SpeakerTurn { speaker: 1, changed: true });
+        // The first voice again
+        assert_eq!(tracker.observe(&low), SpeakerTurn { speaker: 0, changed: true });
+        assert_eq!(tracker.current(), Some(0));
+    }
+}
@@ -0,0 +1,1653 @@
+//! Whisper transcription wrapper for VoiceMark.
+//!
+//! Offline speech-to-text with one of two backends, selected by Cargo
+//! feature:
+//!
+//! - `whisper-cpp` (default): whisper.cpp through whisper-rs. Loads ggml
+//!   model files (`ggml-small.en.bin`).
+//! - `candle`: a pure-Rust implementation on candle, with no C++ toolchain
+//!   needed. Loads Hugging Face model directories (`config.json`,
+//!   `tokenizer.json`, `model.safetensors`).
+//!
+//! When both are compiled in, the model path decides: directories load with
+//! candle, files with whisper.cpp.
+//!
+//! On macOS, whisper.cpp can be accelerated with the `metal` (GPU) and
+//! `coreml` (Neural Engine encoder) features; see [`acceleration`].
+//!
+//! How much CPU whisper.cpp may take (threads, cores, priority) is set with
+//! [`set_cpu_limits`], and its decoder heuristics are tuned with
+//! [`DecodingParams`].
+//!
+//! Besides the configured model, other models can be loaded by path with
+//! [`load_model`] and used through [`transcribe_with_model`], e.g. to
+//! compare model sizes on the same audio.
+//!
+//! The configured model can be replaced at runtime with [`swap_model`]:
+//! transcriptions keep using the old model until the new one is loaded and
+//! warmed up, and the old one is freed once the last of them finishes.
+
+#[cfg(not(any(feature = "whisper-cpp", feature = "candle")))]
+compile_error!("enable the `whisper-cpp` or `candle` feature to select a transcription backend");
+
+#[cfg(feature = "candle")]
+mod candle;
+#[cfg(feature = "whisper-cpp")]
+mod whisper_cpp;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, instrument};
+
+use crate::audio::Channels;
+use crate::constraint::{self, Constraint};
+use crate::formatting::{self, TextFormat};
+use crate::music::{self, NonSpeech, NonSpeechRegion};
+use crate::quality::{self, AudioWarning};
+use crate::vad::{self, TimeMap};
+
+/// Default model path relative to sidecar binary.
+#[cfg(feature = "whisper-cpp")]
+const DEFAULT_MODEL_PATH: &str = "./models/ggml-small.en.bin";
+#[cfg(not(feature = "whisper-cpp"))]
+const DEFAULT_MODEL_PATH: &str = "./models/whisper-small.en";
+
+/// Where to download the default model from.
+#[cfg(feature = "whisper-cpp")]
+const DOWNLOAD_HINT: &str = "curl -L -o ./models/ggml-small.en.bin \
+     https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small.en.bin";
+#[cfg(not(feature = "whisper-cpp"))]
+const DOWNLOAD_HINT: &str = "for f in config.json tokenizer.json model.safetensors; do \
+     curl -L --create-dirs -o ./models/whisper-small.en/$f \
+     https://huggingface.co/openai/whisper-small.en/resolve/main/$f; done";
+
+/// A loaded whisper model.
+enum Model {
+    #[cfg(feature = "whisper-cpp")]
+    WhisperCpp(whisper_cpp::Model),
+    #[cfg(feature = "candle")]
+    Candle(Box<candle::Model>),
+}
+
+/// Text and segments decoded by a backend, on the timeline of the audio it
+/// was given.
+struct Decoded {
+    /// Concatenated segment text, untrimmed.
+    text: String,
+    segments: Vec<Segment>,
+    /// Word timings, with [`TranscribeOptions::word_timestamps`].
+    words: Vec<Word>,
+    /// Decoded tokens, with [`TranscribeOptions::tokens`].
+    tokens: Vec<SegmentTokens>,
+    /// Mean probability of the decoded text tokens.
+    avg_token_prob: f32,
+    /// Language of the audio, see [`TranscribeResult::language`].
+    language: Option<String>,
+    /// Probability of `language`, if it was detected.
+    language_probability: Option<f32>,
+}
+
+/// The whisper model: where to load it from and, if resident, the context.
+struct ModelSlot {
+    /// Model path, set by `init_model` or `swap_model`. Used to reload after
+    /// idle unloading.
+    path: Option<String>,
+    /// Loaded model. In-flight transcriptions hold their own `Arc`, so
+    /// unloading only frees memory once they finish.
+    ctx: Option<Arc<Model>>,
+    /// When the context was last handed out.
+    last_used: Option<Instant>,
+}
+
+/// Global model slot shared by all transcriptions.
+static MODEL: Mutex<ModelSlot> = Mutex::new(ModelSlot {
+    path: None,
+    ctx: None,
+    last_used: None,
+});
+
+/// Serializes (re)loading so concurrent requests don't load the model twice.
+static LOAD_LOCK: Mutex<()> = Mutex::new(());
+
+/// A model loaded by [`load_model`] besides the configured one.
+struct ExtraModel {
+    ctx: Arc<Model>,
+    last_used: Instant,
+}
+
+/// Models loaded by [`load_model`], by path.
+static EXTRA_MODELS: Mutex<Option<HashMap<String, ExtraModel>>> = Mutex::new(None);
+
+/// Whether hardware acceleration may be used, see `set_acceleration`.
+static ACCELERATION: AtomicBool = AtomicBool::new(true);
+
+/// Server-wide decoder settings, see `set_decoding_defaults`.
+static DECODING_DEFAULTS: OnceLock<DecodingParams> = OnceLock::new();
+
+/// CPU limits for whisper.cpp, see `set_cpu_limits`.
+static CPU_LIMITS: OnceLock<CpuLimits> = OnceLock::new();
+
+/// How much CPU whisper.cpp transcription may use, so it doesn't starve the
+/// rest of the machine.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuLimits {
+    /// Decoding threads, unless a request asks for another count. `None`
+    /// uses whisper.cpp's default (one per core, at most 4).
+    pub threads: Option<u32>,
+    /// Cores to run transcription on (Linux only); empty means any core.
+    pub cpus: Vec<usize>,
+    /// Niceness of transcription threads (Linux only), e.g. 10 to yield to
+    /// interactive work.
+    pub nice: Option<i32>,
+}
+
+/// Hardware acceleration used by the model, as reported by `/health`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Acceleration {
+    /// whisper.cpp runs on the GPU through Metal (`metal` feature).
+    pub metal: bool,
+    /// The encoder runs through CoreML (`coreml` feature, with a
+    /// `<model>-encoder.mlmodelc` next to the ggml model).
+    pub coreml: bool,
+}
+
+/// Residency state of the model, as reported by `/health`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ModelState {
+    /// No model has been configured.
+    NotLoaded,
+    /// The model is resident in memory.
+    Loaded,
+    /// The model was unloaded while idle and reloads on the next request.
+    Unloaded,
+}
+
+/// Initialize the Whisper model.
+///
+/// Call this once at startup, or later if no model could be loaded then.
+/// Uses the model at the given path, or falls back to the default model
+/// location. Fails if a model is already initialized; see [`swap_model`] to
+/// replace it.
+#[instrument]
+pub fn init_model(model_path: Option<&str>) -> Result<()> {
+    let path = model_path.unwrap_or(DEFAULT_MODEL_PATH);
+
+    if !Path::new(path).exists() {
+        bail!(
+            "Whisper model not found at '{}'. Download the default model with:\n{}",
+            path,
+            DOWNLOAD_HINT
+        );
+    }
+
+    let _load_guard = LOAD_LOCK.lock().unwrap();
+    let mut slot = MODEL.lock().unwrap();
+    if slot.path.is_some() {
+        bail!("Whisper context already initialized");
+    }
+
+    slot.ctx = Some(Arc::new(load_context(path)?));
+    slot.path = Some(path.to_string());
+    slot.last_used = Some(Instant::now());
+    Ok(())
+}
+
+/// Allow or forbid GPU acceleration (Metal). Call before `init_model`;
+/// allowed by default.
+///
+/// CoreML cannot be turned off at runtime: whisper.cpp uses the CoreML
+/// encoder whenever it finds one next to the model.
+pub fn set_acceleration(enabled: bool) {
+    ACCELERATION.store(enabled, Ordering::Relaxed);
+}
+
+/// Limit the CPU used by whisper.cpp. Call once at startup, before any
+/// transcription; without it whisper.cpp uses its defaults.
+///
+/// With core pinning or niceness, whisper.cpp runs on a pool of threads that
+/// core starts with both applied, and the workers whisper.cpp starts from
+/// them inherit them; the caller's threads are left alone.
+pub fn set_cpu_limits(limits: CpuLimits) {
+    if CPU_LIMITS.set(limits).is_err() {
+        tracing::warn!("CPU limits already configured");
+    }
+}
+
+/// The configured CPU limits.
+pub fn cpu_limits() -> &'static CpuLimits {
+    static NONE: CpuLimits = CpuLimits {
+        threads: None,
+        cpus: Vec::new(),
+        nice: None,
+    };
+    CPU_LIMITS.get().unwrap_or(&NONE)
+}
+
+/// Set the decoder settings used where a request leaves them unset. Call
+/// once at startup, before any transcription.
+pub fn set_decoding_defaults(defaults: DecodingParams) {
+    if DECODING_DEFAULTS.set(defaults).is_err() {
+        tracing::warn!("Decoding defaults already configured");
+    }
+}
+
+/// The configured decoder settings.
+pub fn decoding_defaults() -> DecodingParams {
+    DECODING_DEFAULTS.get().cloned().unwrap_or_default()
+}
+
+/// Report the hardware acceleration used for the configured model.
+///
+/// Acceleration only applies to whisper.cpp; without a model, or with the
+/// candle backend, nothing is accelerated.
+pub fn acceleration() -> Acceleration {
+    #[cfg(feature = "whisper-cpp")]
+    if let Some(path) = model_path().filter(|path| !Path::new(path).is_dir()) {
+        return whisper_cpp::acceleration(Path::new(&path), ACCELERATION.load(Ordering::Relaxed));
+    }
+    Acceleration::default()
+}
+
+/// Load a whisper model from disk with the backend that handles `path`.
+fn load_context(path: &str) -> Result<Model> {
+    info!(model_path = path, "Loading Whisper model...");
+
+    #[cfg(feature = "candle")]
+    if Path::new(path).is_dir() {
+        let model = candle::Model::load(Path::new(path)).context("Failed to load Whisper model")?;
+        info!(backend = "candle", "Whisper model loaded successfully");
+        return Ok(Model::Candle(Box::new(model)));
+    }
+
+    #[cfg(feature = "whisper-cpp")]
+    {
+        let use_gpu = ACCELERATION.load(Ordering::Relaxed);
+        let model = whisper_cpp::Model::load(path, use_gpu).context("Failed to load Whisper model")?;
+        let acceleration = whisper_cpp::acceleration(Path::new(path), use_gpu);
+        info!(
+            backend = "whisper.cpp",
+            metal = acceleration.metal,
+            coreml = acceleration.coreml,
+            "Whisper model loaded successfully"
+        );
+        Ok(Model::WhisperCpp(model))
+    }
+
+    #[cfg(not(feature = "whisper-cpp"))]
+    bail!(
+        "'{}' is not a model directory; the candle backend loads Hugging Face models \
+         (config.json, tokenizer.json, model.safetensors)",
+        path
+    )
+}
+
+/// Get the whisper model, reloading it if it was unloaded while idle.
+fn context() -> Result<Arc<Model>> {
+    if let Some(ctx) = touch_loaded_context() {
+        return Ok(ctx);
+    }
+
+    let _load_guard = LOAD_LOCK.lock().unwrap();
+    // Another request may have reloaded the model while we waited
+    if let Some(ctx) = touch_loaded_context() {
+        return Ok(ctx);
+    }
+
+    let path = MODEL
+        .lock()
+        .unwrap()
+        .path
+        .clone()
+        .context("Whisper model not initialized. Call init_model() first.")?;
+    let ctx = Arc::new(load_context(&path)?);
+
+    let mut slot = MODEL.lock().unwrap();
+    slot.ctx = Some(Arc::clone(&ctx));
+    slot.last_used = Some(Instant::now());
+    Ok(ctx)
+}
+
+/// Return the resident model (if any), marking it as used.
+fn touch_loaded_context() -> Option<Arc<Model>> {
+    let mut slot = MODEL.lock().unwrap();
+    let ctx = slot.ctx.clone()?;
+    slot.last_used = Some(Instant::now());
+    Some(ctx)
+}
+
+/// Replace the configured model with the one at `path`, or set it if there
+/// is none. Returns `false` if `path` is the configured model already.
+///
+/// The new model is loaded and warmed up while transcriptions continue on
+/// the old one, then swapped in; requests that started before the swap
+/// finish on the old model, which is freed after them. Blocks for the
+/// whole load.
+#[instrument]
+pub fn swap_model(path: &str) -> Result<bool> {
+    if model_path().as_deref() == Some(path) {
+        return Ok(false);
+    }
+    if !Path::new(path).exists() {
+        bail!("Whisper model not found at '{}'", path);
+    }
+
+    let _load_guard = LOAD_LOCK.lock().unwrap();
+    // A model loaded for comparisons can be promoted as is
+    let loaded = EXTRA_MODELS.lock().unwrap().as_mut().and_then(|models| models.remove(path));
+    let ctx = match loaded {
+        Some(model) => model.ctx,
+        None => Arc::new(load_context(path)?),
+    };
+    warm(&ctx)?;
+
+    let old = {
+        let mut slot = MODEL.lock().unwrap();
+        slot.path = Some(path.to_string());
+        slot.last_used = Some(Instant::now());
+        slot.ctx.replace(ctx)
+    };
+    info!(
+        model_path = path,
+        in_flight = old.as_ref().map_or(0, |ctx| Arc::strong_count(ctx) - 1),
+        "Swapped Whisper model"
+    );
+    Ok(true)
+}
+
+/// Load the model at `path` next to the configured one, unless it is loaded
+/// already. Returns whether it had to be loaded.
+///
+/// Models loaded this way stay resident until they go unused for the idle
+/// timeout of [`unload_if_idle`].
+pub fn load_model(path: &str) -> Result<bool> {
+    if model_path().as_deref() == Some(path) || extra_context(path).is_some() {
+        return Ok(false);
+    }
+    if !Path::new(path).exists() {
+        bail!("Whisper model not found at '{}'", path);
+    }
+
+    let _load_guard = LOAD_LOCK.lock().unwrap();
+    if extra_context(path).is_some() {
+        return Ok(false);
+    }
+    let ctx = Arc::new(load_context(path)?);
+    EXTRA_MODELS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(path.to_string(), ExtraModel { ctx, last_used: Instant::now() });
+    Ok(true)
+}
+
+/// Return the model loaded from `path` by [`load_model`], marking it as
+/// used.
+fn extra_context(path: &str) -> Option<Arc<Model>> {
+    let mut models = EXTRA_MODELS.lock().unwrap();
+    let model = models.as_mut()?.get_mut(path)?;
+    model.last_used = Instant::now();
+    Some(Arc::clone(&model.ctx))
+}
+
+/// Unload the model if it hasn't been used for `idle_timeout`, along with
+/// any idle models loaded by [`load_model`].
+///
+/// Returns `true` if the configured model was unloaded. It is reloaded
+/// lazily by the next transcription.
+pub fn unload_if_idle(idle_timeout: Duration) -> bool {
+    if let Some(models) = EXTRA_MODELS.lock().unwrap().as_mut() {
+        models.retain(|path, model| {
+            let idle = model.last_used.elapsed() >= idle_timeout;
+            if idle {
+                info!(model_path = %path, "Unloaded idle Whisper model");
+            }
+            !idle
+        });
+    }
+
+    let mut slot = MODEL.lock().unwrap();
+    let idle = slot
+        .last_used
+        .is_some_and(|last| last.elapsed() >= idle_timeout);
+
+    if slot.ctx.is_some() && idle {
+        slot.ctx = None;
+        info!(
+            idle_secs = idle_timeout.as_secs(),
+            "Unloaded idle Whisper model"
+        );
+        return true;
+    }
+    false
+}
+
+/// Check if a model is available (resident, or unloaded and reloadable).
+pub fn is_model_loaded() -> bool {
+    MODEL.lock().unwrap().path.is_some()
+}
+
+/// Path of the configured model, if any.
+pub fn model_path() -> Option<String> {
+    MODEL.lock().unwrap().path.clone()
+}
+
+/// Report whether the model is resident in memory.
+pub fn model_state() -> ModelState {
+    let slot = MODEL.lock().unwrap();
+    match (&slot.path, &slot.ctx) {
+        (None, _) => ModelState::NotLoaded,
+        (Some(_), Some(_)) => ModelState::Loaded,
+        (Some(_), None) => ModelState::Unloaded,
+    }
+}
+
+/// Run a short dummy transcription to absorb cold-start costs.
+///
+/// The first `full()` call on a fresh context pays for memory paging and
+/// backend initialization; doing it here keeps that off the first real
+/// request. Returns how long the warmup took.
+#[instrument]
+pub fn warmup() -> Result<Duration> {
+    warm(&context()?)
+}
+
+/// Warm up `ctx`, see [`warmup`].
+fn warm(ctx: &Arc<Model>) -> Result<Duration> {
+    let started = Instant::now();
+    // One second of near-silence is enough to exercise the encoder and decoder
+    let samples = vec![0.0f32; 16000];
+    transcribe_in(ctx, &samples, TranscribeOptions::default(), |_| {}, |_| {})
+        .context("Warmup transcription failed")?;
+
+    let elapsed = started.elapsed();
+    info!(duration_ms = elapsed.as_millis() as u64, "Whisper model warmed up");
+    Ok(elapsed)
+}
+
+/// Transcription options.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TranscribeOptions {
+    /// Language code (e.g., "en"), or `"auto"` to detect it. Defaults to
+    /// English.
+    pub language: Option<String>,
+    /// Whether to translate to English.
+    pub translate: bool,
+    /// Strip long silent regions before transcription (batch uploads).
+    /// Segment timestamps are mapped back to the original audio.
+    pub vad: bool,
+    /// Vocabulary profile the request was made for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Text passed to whisper as preceding context, biasing it toward the
+    /// spellings it contains.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_prompt: Option<String>,
+    /// Decoding threads for this request, overriding
+    /// [`CpuLimits::threads`]. Only affects speed, so it is not serialized
+    /// (and not part of cache keys).
+    #[serde(skip)]
+    pub threads: Option<u32>,
+    /// Decoder settings; unset ones fall back to
+    /// [`set_decoding_defaults`], then to whisper.cpp's defaults.
+    #[serde(default, skip_serializing_if = "DecodingParams::is_empty")]
+    pub decoding: DecodingParams,
+    /// How whisper.cpp splits the text into segments.
+    #[serde(default, skip_serializing_if = "Segmentation::is_empty")]
+    pub segmentation: Segmentation,
+    /// Added to every timestamp, so segments of a clip cut from a longer
+    /// recording are placed on the recording's timeline.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub time_offset_ms: i64,
+    /// Audio track (0-based) of a multi-track file the samples were decoded
+    /// from, see [`audio::convert_track_to_wav`](crate::audio::convert_track_to_wav).
+    /// Transcription itself doesn't use it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track: Option<u32>,
+    /// Start of the part of the file the samples were decoded from (see
+    /// [`audio::convert_clip_to_wav`](crate::audio::convert_clip_to_wav)),
+    /// in milliseconds. Timestamps are moved by it, so they stay on the
+    /// file's timeline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_ms: Option<i64>,
+    /// End of the part of the file the samples were decoded from; the end
+    /// of the file if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_ms: Option<i64>,
+    /// Whether the channels of the file were mixed down or are transcribed
+    /// separately (see [`merge_channels`]). Like [`track`](Self::track),
+    /// it only affects decoding.
+    #[serde(default, skip_serializing_if = "Channels::is_downmix")]
+    pub channels: Channels,
+    /// Also time individual words ([`TranscribeResult::words`]). whisper.cpp
+    /// and remote backends only.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub word_timestamps: bool,
+    /// Also return the decoded tokens of each segment
+    /// ([`TranscribeResult::tokens`]), for tools that fuse confidences or
+    /// align text themselves. Local backends only.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub tokens: bool,
+    /// Cut leading and trailing silence before transcribing (see
+    /// [`trim_silence`]). Timestamps stay on the original timeline.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub trim_silence: bool,
+    /// Find music and steady noise first and leave it out of transcription
+    /// (see [`exclude_non_speech`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub non_speech: Option<NonSpeech>,
+    /// Casing, punctuation and paragraph formatting of the text (see
+    /// [`formatting`]).
+    #[serde(default, skip_serializing_if = "TextFormat::is_raw")]
+    pub text_format: TextFormat,
+    /// Limit the text to digits, or to spelled-out letters and digits (see
+    /// [`constraint`]). Applied before [`text_format`](Self::text_format).
+    #[serde(default, skip_serializing_if = "Constraint::is_free")]
+    pub constraint: Constraint,
+    /// Also group the result into sentences and paragraphs (see
+    /// [`formatting::paragraphs`]). Only shapes the response, so it is not
+    /// serialized.
+    #[serde(skip)]
+    pub paragraphs: bool,
+    /// How much of whisper's 30-second window the encoder runs over (see
+    /// [`AudioContext`]).
+    #[serde(default, skip_serializing_if = "AudioContext::is_full")]
+    pub audio_ctx: AudioContext,
+}
+
+impl TranscribeOptions {
+    /// Where the samples start on the timeline timestamps are reported on:
+    /// [`time_offset_ms`](Self::time_offset_ms) plus
+    /// [`start_ms`](Self::start_ms).
+    pub fn offset_ms(&self) -> i64 {
+        self.time_offset_ms + self.start_ms.unwrap_or(0)
+    }
+}
+
+fn is_zero(value: &i64) -> bool {
+    *value == 0
+}
+
+/// Encoder frames in whisper's 30-second window, 20 ms each.
+const FULL_AUDIO_CTX: u32 = 1500;
+
+/// Fewest encoder frames [`AudioContext::Fit`] uses (about 5 seconds);
+/// smaller windows make whisper noticeably less accurate.
+const MIN_AUDIO_CTX: u32 = 256;
+
+/// Encoder frames of padding [`AudioContext::Fit`] leaves after the audio,
+/// so the last words aren't cut off.
+const AUDIO_CTX_PADDING: u32 = 64;
+
+/// How much audio whisper's encoder runs over.
+///
+/// Whisper always encodes a 30-second window, padding shorter audio with
+/// silence, and the encoder's cost grows with the window. For the few
+/// seconds of a streaming chunk, most of that work is spent on silence.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum AudioContext {
+    /// The full 30-second window, as the models were trained.
+    #[default]
+    Full,
+    /// A window just long enough for audio shorter than 30 seconds, which
+    /// encodes a few seconds of audio several times faster at a small cost
+    /// in accuracy. Longer audio uses the full window.
+    Fit,
+}
+
+impl AudioContext {
+    pub fn is_full(&self) -> bool {
+        *self == AudioContext::Full
+    }
+
+    /// Encoder frames (`audio_ctx`) for `samples` of 16kHz audio, or `None`
+    /// for the full window.
+    pub fn frames(self, samples: usize) -> Option<u32> {
+        if self == AudioContext::Full {
+            return None;
+        }
+        // 50 frames per second
+        let frames = (samples as u64 * 50).div_ceil(16000) as u32 + AUDIO_CTX_PADDING;
+        let frames = frames.max(MIN_AUDIO_CTX).next_multiple_of(64);
+        (frames < FULL_AUDIO_CTX).then_some(frames)
+    }
+}
+
+impl std::str::FromStr for AudioContext {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "full" => Ok(Self::Full),
+            "fit" => Ok(Self::Fit),
+            other => bail!("Unknown audio context {:?} (expected full or fit)", other),
+        }
+    }
+}
+
+/// How whisper.cpp splits decoded text into segments, e.g. to size caption
+/// cues. By default segments end where whisper places timestamps, usually
+/// at sentence or clause boundaries.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema, utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct Segmentation {
+    /// Maximum segment length in characters; unset or 0 means no limit.
+    /// Enables token timestamps, which whisper.cpp needs to split segments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_len: Option<u32>,
+    /// With `max_len`, split at word boundaries instead of mid-word.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub split_on_word: bool,
+    /// Return one segment per 30-second window.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub single_segment: bool,
+}
+
+impl Segmentation {
+    /// Whether every setting is left at its default.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// whisper.cpp decoder heuristics. `None` keeps whisper.cpp's default.
+///
+/// When a decoded segment trips the entropy or log-probability threshold,
+/// it is decoded again at rising temperatures (see
+/// [`temperatures`](Self::temperatures)); the candle backend does the same
+/// per 30-second window. Below the no-speech threshold a segment is
+/// treated as silence.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema, utoipa::IntoParams))]
+#[cfg_attr(feature = "openapi", into_params(parameter_in = Query))]
+pub struct DecodingParams {
+    /// Retry segments whose token entropy is below this (repetitive
+    /// output); whisper.cpp default 2.4.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entropy_threshold: Option<f32>,
+    /// Retry segments whose average token log probability is below this;
+    /// whisper.cpp default -1.0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprob_threshold: Option<f32>,
+    /// Probability of the no-speech token above which a segment is
+    /// silence; whisper.cpp default 0.6.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_speech_threshold: Option<f32>,
+    /// Don't start a segment with a blank; default true.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suppress_blank: Option<bool>,
+    /// Suppress non-speech tokens such as `[MUSIC]` and `(laughs)`;
+    /// default false.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suppress_non_speech_tokens: Option<bool>,
+    /// Latest first timestamp of a window, in seconds; default 1.0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_initial_ts: Option<f32>,
+    /// Length penalty when ranking beam search candidates; default -1
+    /// (none).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length_penalty: Option<f32>,
+    /// Temperature step of the fallback: a failed segment is decoded again
+    /// at 0.2, 0.4, ... up to 1.0, keeping the first good result or else
+    /// the most probable one; 0 disables the fallback. Default 0.2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature_increment: Option<f32>,
+    /// Comma-separated tokens or phrases the decoder must not produce, e.g.
+    /// `[,(` to stop bracketed sound effects; empty suppresses nothing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suppress: Option<String>,
+}
+
+impl DecodingParams {
+    /// Whether every setting is left at its default.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Fill the unset settings from `defaults`.
+    pub fn or(self, defaults: &DecodingParams) -> DecodingParams {
+        DecodingParams {
+            entropy_threshold: self.entropy_threshold.or(defaults.entropy_threshold),
+            logprob_threshold: self.logprob_threshold.or(defaults.logprob_threshold),
+            no_speech_threshold: self.no_speech_threshold.or(defaults.no_speech_threshold),
+            suppress_blank: self.suppress_blank.or(defaults.suppress_blank),
+            suppress_non_speech_tokens: self
+                .suppress_non_speech_tokens
+                .or(defaults.suppress_non_speech_tokens),
+            max_initial_ts: self.max_initial_ts.or(defaults.max_initial_ts),
+            length_penalty: self.length_penalty.or(defaults.length_penalty),
+            temperature_increment: self.temperature_increment.or(defaults.temperature_increment),
+            suppress: self.suppress.or_else(|| defaults.suppress.clone()),
+        }
+    }
+
+    /// The phrases of [`suppress`](Self::suppress).
+    pub fn suppressed(&self) -> Vec<&str> {
+        self.suppress
+            .iter()
+            .flat_map(|list| list.split(','))
+            .map(str::trim)
+            .filter(|phrase| !phrase.is_empty())
+            .collect()
+    }
+
+    /// Check that the numeric settings are in range.
+    pub fn validate(&self) -> Result<()> {
+        let finite = [
+            ("entropy_threshold", self.entropy_threshold),
+            ("logprob_threshold", self.logprob_threshold),
+            ("length_penalty", self.length_penalty),
+        ];
+        for (name, value) in finite {
+            if value.is_some_and(|v| !v.is_finite()) {
+                bail!("{} must be a finite number", name);
+            }
+        }
+        if self.no_speech_threshold.is_some_and(|v| !(0.0..=1.0).contains(&v)) {
+            bail!("no_speech_threshold must be between 0 and 1");
+        }
+        if self.max_initial_ts.is_some_and(|v| !(v >= 0.0 && v.is_finite())) {
+            bail!("max_initial_ts must be a non-negative number of seconds");
+        }
+        if self.temperature_increment.is_some_and(|v| !(0.0..=1.0).contains(&v)) {
+            bail!("temperature_increment must be between 0 and 1");
+        }
+        Ok(())
+    }
+
+    /// Temperatures to decode a segment at until one succeeds: 0 (greedy),
+    /// then every [`temperature_increment`](Self::temperature_increment) up
+    /// to 1.0.
+    pub fn temperatures(&self) -> Vec<f32> {
+        let step = self.temperature_increment.unwrap_or(DEFAULT_TEMPERATURE_INCREMENT);
+        let mut temperatures = vec![0.0];
+        if step > 0.0 {
+            // Counted in steps so 0.2 * 5 doesn't miss 1.0 by rounding
+            let steps = (1.0 / step + 1e-3).floor() as usize;
+            temperatures.extend((1..=steps).map(|i| i as f32 * step));
+        }
+        temperatures
+    }
+
+    /// Whether decoded text tokens, with the probability of each, look like
+    /// a failure: repetitive (token entropy of the last 32 below
+    /// [`entropy_threshold`](Self::entropy_threshold), for output longer
+    /// than that) or improbable (mean log probability below
+    /// [`logprob_threshold`](Self::logprob_threshold)), as whisper.cpp
+    /// judges them.
+    pub fn needs_fallback(&self, tokens: &[u32], probs: &[f32]) -> bool {
+        let entropy_threshold = self.entropy_threshold.unwrap_or(DEFAULT_ENTROPY_THRESHOLD);
+        let logprob_threshold = self.logprob_threshold.unwrap_or(DEFAULT_LOGPROB_THRESHOLD);
+        let repetitive = tokens.len() > ENTROPY_TOKENS
+            && token_entropy(&tokens[tokens.len() - ENTROPY_TOKENS..]) < entropy_threshold;
+        repetitive || avg_logprob(probs) < logprob_threshold
+    }
+}
+
+/// whisper.cpp's default [`DecodingParams::entropy_threshold`].
+const DEFAULT_ENTROPY_THRESHOLD: f32 = 2.4;
+/// whisper.cpp's default [`DecodingParams::logprob_threshold`].
+const DEFAULT_LOGPROB_THRESHOLD: f32 = -1.0;
+/// whisper.cpp's default [`DecodingParams::temperature_increment`].
+const DEFAULT_TEMPERATURE_INCREMENT: f32 = 0.2;
+/// Trailing tokens whose entropy tells repetitive output.
+const ENTROPY_TOKENS: usize = 32;
+
+/// Entropy of the token frequencies, in nats: low when a few tokens repeat.
+fn token_entropy(tokens: &[u32]) -> f32 {
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for &token in tokens {
+        *counts.entry(token).or_default() += 1;
+    }
+    let n = tokens.len() as f32;
+    counts.values().map(|&count| count as f32 / n).map(|p| -p * p.ln()).sum()
+}
+
+/// Mean log probability of decoded tokens; 0 for none.
+fn avg_logprob(probs: &[f32]) -> f32 {
+    if probs.is_empty() {
+        return 0.0;
+    }
+    probs.iter().map(|p| p.max(f32::MIN_POSITIVE).ln()).sum::<f32>() / probs.len() as f32
+}
+
+/// Token sequences the decoder must not produce, from
+/// [`DecodingParams::suppress`].
+///
+/// Each phrase is tokenized both as it would appear inside running text
+/// (after a space) and at the start of a segment. A single-token phrase is
+/// never sampled; for a longer one, only its last token is masked, and only
+/// right after the tokens before it, so `[laughs]` doesn't also ban `[`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Suppression {
+    sequences: Vec<Vec<u32>>,
+}
+
+impl Suppression {
+    /// Tokenize `phrases` with the model's `tokenize`.
+    pub fn new(phrases: &[&str], tokenize: impl Fn(&str) -> Vec<u32>) -> Self {
+        let mut sequences: Vec<Vec<u32>> = Vec::new();
+        for phrase in phrases {
+            for text in [format!(" {}", phrase), phrase.to_string()] {
+                let tokens = tokenize(&text);
+                if !tokens.is_empty() && !sequences.contains(&tokens) {
+                    sequences.push(tokens);
+                }
+            }
+        }
+        Self { sequences }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sequences.is_empty()
+    }
+
+    /// Mask the logits of tokens that would complete a suppressed sequence
+    /// after the tokens decoded so far.
+    pub fn apply(&self, history: &[u32], logits: &mut [f32]) {
+        for sequence in &self.sequences {
+            let (&last, prefix) = sequence.split_last().expect("sequences are never empty");
+            if history.ends_with(prefix) {
+                if let Some(logit) = logits.get_mut(last as usize) {
+                    *logit = f32::NEG_INFINITY;
+                }
+            }
+        }
+    }
+}
+
+/// A decoded segment with its position in the audio.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Segment {
+    /// Segment start, in milliseconds from the beginning of the audio.
+    pub start_ms: i64,
+    /// Segment end, in milliseconds from the beginning of the audio.
+    pub end_ms: i64,
+    /// Segment text (trimmed).
+    pub text: String,
+}
+
+/// A decoded word with its position in the audio.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Word {
+    /// Word start, in milliseconds from the beginning of the audio.
+    pub start_ms: i64,
+    /// Word end, in milliseconds from the beginning of the audio.
+    pub end_ms: i64,
+    /// The word, with any attached punctuation (`"Hello,"`).
+    pub text: String,
+    /// Mean probability of the word's tokens (0.0-1.0).
+    pub probability: f32,
+}
+
+/// A decoded text token, with [`TranscribeOptions::tokens`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Token {
+    /// Token ID in the model's vocabulary.
+    pub id: u32,
+    /// The token's text, including any leading space. A character split
+    /// across tokens shows as U+FFFD in each part.
+    pub text: String,
+    /// Probability of the token when it was decoded (0.0-1.0).
+    pub probability: f32,
+    /// Token start, in milliseconds from the beginning of the audio.
+    /// whisper.cpp only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_ms: Option<i64>,
+    /// Token end, in milliseconds from the beginning of the audio.
+    /// whisper.cpp only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_ms: Option<i64>,
+}
+
+/// The text tokens a segment was decoded from. Timestamp and other special
+/// tokens are left out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SegmentTokens {
+    /// Segment start, in milliseconds from the beginning of the audio.
+    pub start_ms: i64,
+    /// Segment end, in milliseconds from the beginning of the audio.
+    pub end_ms: i64,
+    pub tokens: Vec<Token>,
+}
+
+/// Group decoded text tokens into words. Each token is its raw bytes (a
+/// multi-byte character may be split across tokens), start and end in
+/// milliseconds, and probability. A token starting with a space begins a
+/// new word; other tokens, punctuation included, extend the current one.
+pub fn words_from_tokens<'a>(
+    tokens: impl IntoIterator<Item = (&'a [u8], i64, i64, f32)>,
+) -> Vec<Word> {
+    struct Pending {
+        bytes: Vec<u8>,
+        start_ms: i64,
+        end_ms: i64,
+        prob_sum: f32,
+        tokens: usize,
+    }
+
+    fn finish(pending: Pending, words: &mut Vec<Word>) {
+        let text = String::from_utf8_lossy(&pending.bytes).trim().to_string();
+        if !text.is_empty() {
+            words.push(Word {
+                start_ms: pending.start_ms,
+                end_ms: pending.end_ms,
+                text,
+                probability: pending.prob_sum / pending.tokens as f32,
+            });
+        }
+    }
+
+    let mut words = Vec::new();
+    let mut current: Option<Pending> = None;
+    for (bytes, start_ms, end_ms, probability) in tokens {
+        match current.as_mut() {
+            Some(pending) if !bytes.starts_with(b" ") => {
+                pending.bytes.extend_from_slice(bytes);
+                pending.end_ms = pending.end_ms.max(end_ms);
+                pending.prob_sum += probability;
+                pending.tokens += 1;
+            }
+            _ => {
+                if let Some(pending) = current.take() {
+                    finish(pending, &mut words);
+                }
+                current = Some(Pending {
+                    bytes: bytes.to_vec(),
+                    start_ms,
+                    end_ms,
+                    prob_sum: probability,
+                    tokens: 1,
+                });
+            }
+        }
+    }
+    if let Some(pending) = current {
+        finish(pending, &mut words);
+    }
+    words
+}
+
+/// Transcription result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscribeResult {
+    /// The transcribed text.
+    pub text: String,
+    /// Number of audio segments processed.
+    pub segments: usize,
+    /// Mean probability of the decoded text tokens (0.0-1.0), a rough
+    /// confidence measure.
+    #[serde(default)]
+    pub avg_token_prob: f32,
+    /// Decoded segments with timestamps on the original timeline.
+    #[serde(default)]
+    pub timed_segments: Vec<Segment>,
+    /// Word timings on the original timeline, with
+    /// [`TranscribeOptions::word_timestamps`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<Word>,
+    /// Decoded tokens per segment, on the original timeline, with
+    /// [`TranscribeOptions::tokens`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<SegmentTokens>,
+    /// Language of the audio: detected with `"auto"`, otherwise the
+    /// requested one. Only set for multilingual models or `"auto"` (English
+    /// models always transcribe English).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Probability of the detected language (0.0-1.0); `None` unless the
+    /// language was detected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language_probability: Option<f32>,
+    /// Silence cut from the ends of the audio, with
+    /// [`TranscribeOptions::trim_silence`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trimmed: Option<TrimmedSilence>,
+    /// Regions of music and noise left out of transcription, with
+    /// [`TranscribeOptions::non_speech`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub non_speech: Vec<NonSpeechRegion>,
+    /// Transcript of each channel, with [`Channels::Split`]. The other
+    /// fields then cover all channels (see [`merge_channels`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<ChannelTranscript>,
+    /// Problems with the recording, like clipping (see [`quality`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<AudioWarning>,
+}
+
+/// Transcript of one channel of a file transcribed with [`Channels::Split`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ChannelTranscript {
+    /// Channel index (0 = left).
+    pub channel: u32,
+    pub text: String,
+    pub segments: Vec<Segment>,
+}
+
+/// Combine the results of transcribing each channel separately into one,
+/// with the segments and words of all channels in time order. The text is
+/// that of the merged segments, so a two-sided call reads as a dialogue;
+/// each channel's own transcript is kept in
+/// [`TranscribeResult::channels`].
+pub fn merge_channels(results: Vec<TranscribeResult>) -> TranscribeResult {
+    let mut merged = TranscribeResult {
+        text: String::new(),
+        segments: 0,
+        avg_token_prob: 0.0,
+        timed_segments: Vec::new(),
+        words: Vec::new(),
+        tokens: Vec::new(),
+        language: None,
+        language_probability: None,
+        trimmed: None,
+        non_speech: Vec::new(),
+        channels: Vec::with_capacity(results.len()),
+        warnings: Vec::new(),
+    };
+    let count = results.len().max(1) as f32;
+    for (channel, result) in results.into_iter().enumerate() {
+        merged.avg_token_prob += result.avg_token_prob / count;
+        merged.language = merged.language.or(result.language);
+        merged.language_probability = merged.language_probability.or(result.language_probability);
+        merged.trimmed = merged.trimmed.or(result.trimmed);
+        merged.timed_segments.extend(result.timed_segments.iter().cloned());
+        merged.words.extend(result.words);
+        merged.tokens.extend(result.tokens);
+        for warning in result.warnings {
+            if !merged.warnings.iter().any(|w| w.code == warning.code) {
+                merged.warnings.push(warning);
+            }
+        }
+        merged.channels.push(ChannelTranscript {
+            channel: channel as u32,
+            text: result.text,
+            segments: result.timed_segments,
+        });
+    }
+    // Stable sorts keep the channel order for segments starting together
+    merged.timed_segments.sort_by_key(|segment| segment.start_ms);
+    merged.words.sort_by_key(|word| word.start_ms);
+    merged.tokens.sort_by_key(|segment| segment.start_ms);
+    merged.segments = merged.timed_segments.len();
+    let texts: Vec<&str> = merged.timed_segments.iter().map(|s| s.text.as_str()).collect();
+    merged.text = texts.join(" ");
+    merged
+}
+
+/// Silence cut from the ends of the audio before transcription.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TrimmedSilence {
+    /// Silence cut from the start, in milliseconds. Timestamps already
+    /// include it.
+    pub leading_ms: i64,
+    /// Silence cut from the end, in milliseconds.
+    pub trailing_ms: i64,
+}
+
+/// Cut leading and trailing silence off `samples`, keeping a little padding
+/// around the speech. Audio without any speech is left whole.
+pub fn trim_silence(samples: &[f32]) -> (&[f32], TrimmedSilence) {
+    let speech = vad::speech_range(samples);
+    if speech.is_empty() {
+        return (samples, TrimmedSilence::default());
+    }
+    let trimmed = TrimmedSilence {
+        leading_ms: (speech.start * 1000 / 16000) as i64,
+        trailing_ms: ((samples.len() - speech.end) * 1000 / 16000) as i64,
+    };
+    (&samples[speech], trimmed)
+}
+
+/// Find music and noise in `samples` and silence it, so whisper doesn't
+/// transcribe it. Returns the regions found.
+pub fn exclude_non_speech(samples: &[f32]) -> (Cow<'_, [f32]>, Vec<NonSpeechRegion>) {
+    let regions = music::detect(samples);
+    if regions.is_empty() {
+        return (Cow::Borrowed(samples), regions);
+    }
+    (Cow::Owned(music::silence(samples, &regions)), regions)
+}
+
+impl TranscribeResult {
+    /// Move every segment `offset_ms` later (see
+    /// [`TranscribeOptions::time_offset_ms`]).
+    pub fn shift(&mut self, offset_ms: i64) {
+        for segment in &mut self.timed_segments {
+            segment.start_ms += offset_ms;
+            segment.end_ms += offset_ms;
+        }
+        for word in &mut self.words {
+            word.start_ms += offset_ms;
+            word.end_ms += offset_ms;
+        }
+        for segment in &mut self.tokens {
+            segment.start_ms += offset_ms;
+            segment.end_ms += offset_ms;
+            for token in &mut segment.tokens {
+                token.start_ms = token.start_ms.map(|ms| ms + offset_ms);
+                token.end_ms = token.end_ms.map(|ms| ms + offset_ms);
+            }
+        }
+        for region in &mut self.non_speech {
+            region.start_ms += offset_ms;
+            region.end_ms += offset_ms;
+        }
+    }
+
+    /// Report the non-speech `regions` left out of transcription, with a
+    /// `[music]` or `[noise]` segment for each if `mode` is
+    /// [`NonSpeech::Mark`].
+    pub fn add_non_speech(&mut self, regions: Vec<NonSpeechRegion>, mode: NonSpeech) {
+        if mode == NonSpeech::Mark && !regions.is_empty() {
+            self.timed_segments.extend(regions.iter().map(|region| Segment {
+                start_ms: region.start_ms,
+                end_ms: region.end_ms,
+                text: region.kind.marker().to_string(),
+            }));
+            self.timed_segments.sort_by_key(|segment| segment.start_ms);
+            self.segments = self.timed_segments.len();
+            let texts: Vec<&str> = self.timed_segments.iter().map(|s| s.text.as_str()).collect();
+            self.text = texts.join(" ");
+        }
+        self.non_speech = regions;
+    }
+}
+
+/// Transcribe audio samples using Whisper.
+///
+/// Expects audio as f32 samples in range [-1.0, 1.0] at 16kHz mono.
+pub fn transcribe(samples: &[f32], options: TranscribeOptions) -> Result<TranscribeResult> {
+    transcribe_with_callbacks(samples, options, |_| {}, |_| {})
+}
+
+/// Transcribe audio samples, reporting decoded segments and progress.
+///
+/// Whisper reports segments while `full()` is still running, so callers can
+/// forward them to clients long before a multi-minute file has finished.
+/// `on_progress` receives the completion percentage (0-100).
+#[instrument(skip(samples, on_segment, on_progress), fields(sample_count = samples.len()))]
+pub fn transcribe_with_callbacks<F, P>(
+    samples: &[f32],
+    options: TranscribeOptions,
+    on_segment: F,
+    on_progress: P,
+) -> Result<TranscribeResult>
+where
+    F: FnMut(&Segment),
+    P: FnMut(i32),
+{
+    transcribe_in(&context()?, samples, options, on_segment, on_progress)
+}
+
+/// Transcribe audio samples with the model at `path`: the configured model,
+/// or one loaded with [`load_model`].
+#[instrument(skip(samples, options), fields(sample_count = samples.len()))]
+pub fn transcribe_with_model(
+    path: &str,
+    samples: &[f32],
+    options: TranscribeOptions,
+) -> Result<TranscribeResult> {
+    let ctx = if model_path().as_deref() == Some(path) {
+        context()?
+    } else {
+        extra_context(path).with_context(|| format!("Whisper model '{}' is not loaded", path))?
+    };
+    transcribe_in(&ctx, samples, options, |_| {}, |_| {})
+}
+
+/// Transcribe audio samples with `ctx`.
+fn transcribe_in<F, P>(
+    ctx: &Arc<Model>,
+    samples: &[f32],
+    options: TranscribeOptions,
+    mut on_segment: F,
+    mut on_progress: P,
+) -> Result<TranscribeResult>
+where
+    F: FnMut(&Segment),
+    P: FnMut(i32),
+{
+    let options = constraint::bias(options);
+    let warnings = quality::analyze(samples);
+    let (samples, trimmed) = if options.trim_silence {
+        let (samples, trimmed) = trim_silence(samples);
+        (samples, Some(trimmed))
+    } else {
+        (samples, None)
+    };
+    let (samples, non_speech) = match options.non_speech {
+        Some(_) => exclude_non_speech(samples),
+        None => (Cow::Borrowed(samples), Vec::new()),
+    };
+    // Silenced regions are stripped like any other silence
+    let (samples, time_map) = if options.vad || !non_speech.is_empty() {
+        let (compacted, time_map) = vad::strip_silence(&samples);
+        debug!(
+            removed_ms = time_map.removed_samples(samples.len()) * 1000 / 16000,
+            "VAD pre-filtering complete"
+        );
+        (Cow::Owned(compacted), time_map)
+    } else {
+        (samples, TimeMap::default())
+    };
+
+    // Report segments on the original timeline, moved by the requested offset
+    let offset_ms = options.offset_ms() + trimmed.map_or(0, |t| t.leading_ms);
+    let mut on_segment = |segment: &Segment| {
+        on_segment(&Segment {
+            start_ms: time_map.to_original_ms(segment.start_ms) + offset_ms,
+            end_ms: time_map.to_original_ms(segment.end_ms) + offset_ms,
+            text: segment.text.clone(),
+        })
+    };
+
+    debug!("Starting transcription...");
+    let decoded = match ctx.as_ref() {
+        #[cfg(feature = "whisper-cpp")]
+        Model::WhisperCpp(model) => {
+            model.transcribe(&samples, &options, &mut on_segment, &mut on_progress)?
+        }
+        #[cfg(feature = "candle")]
+        Model::Candle(model) => {
+            model.transcribe(&samples, &options, &mut on_segment, &mut on_progress)?
+        }
+    };
+
+    let timed_segments: Vec<Segment> = decoded
+        .segments
+        .into_iter()
+        .map(|segment| Segment {
+            start_ms: time_map.to_original_ms(segment.start_ms) + offset_ms,
+            end_ms: time_map.to_original_ms(segment.end_ms) + offset_ms,
+            text: segment.text,
+        })
+        .collect();
+
+    let words: Vec<Word> = decoded
+        .words
+        .into_iter()
+        .map(|word| Word {
+            start_ms: time_map.to_original_ms(word.start_ms) + offset_ms,
+            end_ms: time_map.to_original_ms(word.end_ms) + offset_ms,
+            ..word
+        })
+        .collect();
+
+    let to_original = |ms: i64| time_map.to_original_ms(ms) + offset_ms;
+    let tokens: Vec<SegmentTokens> = decoded
+        .tokens
+        .into_iter()
+        .map(|segment| SegmentTokens {
+            start_ms: to_original(segment.start_ms),
+            end_ms: to_original(segment.end_ms),
+            tokens: segment
+                .tokens
+                .into_iter()
+                .map(|token| Token {
+                    start_ms: token.start_ms.map(to_original),
+                    end_ms: token.end_ms.map(to_original),
+                    ..token
+                })
+                .collect(),
+        })
+        .collect();
+
+    // Clean up the text (remove leading/trailing whitespace)
+    let text = decoded.text.trim().to_string();
+
+    debug!(
+        segments = timed_segments.len(),
+        text_len = text.len(),
+        "Transcription complete"
+    );
+
+    let mut result = TranscribeResult {
+        text,
+        segments: timed_segments.len(),
+        avg_token_prob: decoded.avg_token_prob,
+        timed_segments,
+        words,
+        tokens,
+        language: decoded.language,
+        language_probability: decoded.language_probability,
+        trimmed,
+        non_speech: Vec::new(),
+        channels: Vec::new(),
+        warnings,
+    };
+    constraint::apply(options.constraint, &mut result);
+    formatting::apply(options.text_format.steps(), &mut result);
+    if let Some(mode) = options.non_speech {
+        let regions = non_speech
+            .into_iter()
+            .map(|region| NonSpeechRegion {
+                start_ms: region.start_ms + offset_ms,
+                end_ms: region.end_ms + offset_ms,
+                ..region
+            })
+            .collect();
+        result.add_non_speech(regions, mode);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_context_fits_short_audio() {
+        assert_eq!(AudioContext::Full.frames(16000), None);
+        // 1 s and 3 s chunks get the smallest window
+        assert_eq!(AudioContext::Fit.frames(16000), Some(256));
+        assert_eq!(AudioContext::Fit.frames(3 * 16000), Some(256));
+        // 6 s: 300 frames and padding, in whole blocks of 64
+        assert_eq!(AudioContext::Fit.frames(6 * 16000), Some(384));
+        assert_eq!(AudioContext::Fit.frames(29 * 16000), None);
+        assert_eq!("fit".parse::<AudioContext>().unwrap(), AudioContext::Fit);
+        assert!("half".parse::<AudioContext>().is_err());
+    }
+
+    #[test]
+    fn test_shift_moves_segments() {
+        let mut result = TranscribeResult {
+            text: "one two".to_string(),
+            segments: 2,
+            avg_token_prob: 0.9,
+            timed_segments: vec![
+                Segment { start_ms: 0, end_ms: 1200, text: "one".to_string() },
+                Segment { start_ms: 1200, end_ms: 2000, text: "two".to_string() },
+            ],
+            words: vec![Word {
+                start_ms: 1200,
+                end_ms: 2000,
+                text: "two".to_string(),
+                probability: 0.9,
+            }],
+            tokens: vec![SegmentTokens {
+                start_ms: 1200,
+                end_ms: 2000,
+                tokens: vec![Token {
+                    id: 734,
+                    text: " two".to_string(),
+                    probability: 0.9,
+                    start_ms: Some(1250),
+                    end_ms: None,
+                }],
+            }],
+            language: None,
+            language_probability: None,
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        };
+        result.shift(60_000);
+        assert_eq!(result.timed_segments[0].start_ms, 60_000);
+        assert_eq!(result.timed_segments[1].end_ms, 62_000);
+        assert_eq!(result.words[0].start_ms, 61_200);
+        assert_eq!(result.tokens[0].start_ms, 61_200);
+        assert_eq!(result.tokens[0].tokens[0].start_ms, Some(61_250));
+        assert_eq!(result.tokens[0].tokens[0].end_ms, None);
+    }
+
+    #[test]
+    fn test_trim_silence() {
+        let tone: Vec<f32> = (0..16000).map(|i| (i as f32 * 0.05).sin() * 0.3).collect();
+        let audio = [vec![0.0; 48000], tone, vec![0.0; 32000]].concat();
+        let (trimmed, silence) = trim_silence(&audio);
+        // Speech is padded by 250ms on each side
+        assert_eq!(silence, TrimmedSilence { leading_ms: 2750, trailing_ms: 1750 });
+        assert_eq!(trimmed.len(), 24000);
+
+        let quiet = vec![0.0; 32000];
+        let (untouched, silence) = trim_silence(&quiet);
+        assert_eq!(untouched.len(), quiet.len());
+        assert_eq!(silence, TrimmedSilence::default());
+    }
+
+    #[test]
+    fn test_non_speech_marks() {
+        let segment =
+            |start_ms, end_ms, text: &str| Segment { start_ms, end_ms, text: text.into() };
+        let mut result = TranscribeResult {
+            text: "Welcome to the show.".to_string(),
+            segments: 1,
+            avg_token_prob: 0.9,
+            timed_segments: vec![segment(12_000, 14_000, "Welcome to the show.")],
+            words: Vec::new(),
+            tokens: Vec::new(),
+            language: None,
+            language_probability: None,
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let intro =
+            NonSpeechRegion { start_ms: 0, end_ms: 11_000, kind: music::NonSpeechKind::Music };
+        result.add_non_speech(vec![intro], NonSpeech::Mark);
+        assert_eq!(result.text, "[music] Welcome to the show.");
+        assert_eq!(result.segments, 2);
+        assert_eq!(result.timed_segments[0], segment(0, 11_000, "[music]"));
+        result.shift(1000);
+        assert_eq!(result.non_speech[0].start_ms, 1000);
+        assert_eq!(result.timed_segments[1].start_ms, 13_000);
+    }
+
+    #[test]
+    fn test_merge_channels() {
+        let segment =
+            |start_ms, end_ms, text: &str| Segment { start_ms, end_ms, text: text.into() };
+        let channel = |segments: Vec<Segment>, avg_token_prob| TranscribeResult {
+            text: segments.iter().map(|s| s.text.as_str()).collect::<Vec<_>>().join(" "),
+            segments: segments.len(),
+            avg_token_prob,
+            timed_segments: segments,
+            words: Vec::new(),
+            tokens: Vec::new(),
+            language: Some("en".to_string()),
+            language_probability: None,
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let agent = channel(
+            vec![segment(0, 2000, "Thanks for calling."), segment(5000, 6000, "Sure.")],
+            0.9,
+        );
+        let caller = channel(vec![segment(2500, 4500, "Can you check my order?")], 0.7);
+
+        let merged = merge_channels(vec![agent, caller]);
+        assert_eq!(merged.text, "Thanks for calling. Can you check my order? Sure.");
+        assert_eq!(merged.segments, 3);
+        assert!((merged.avg_token_prob - 0.8).abs() < 1e-6);
+        assert_eq!(merged.language.as_deref(), Some("en"));
+        assert_eq!(merged.channels.len(), 2);
+        assert_eq!(merged.channels[1].channel, 1);
+        assert_eq!(merged.channels[1].text, "Can you check my order?");
+        assert_eq!(merged.channels[0].segments.len(), 2);
+    }
+
+    #[test]
+    fn test_words_from_tokens() {
+        let tokens: [(&[u8], i64, i64, f32); 6] = [
+            (b" Hello", 0, 300, 0.9),
+            (b",", 300, 320, 0.7),
+            (b" na", 400, 500, 0.8),
+            // "ï" split across two tokens
+            (b"\xC3", 500, 520, 0.6),
+            (b"\xAFve", 520, 700, 0.6),
+            (b" ", 700, 700, 0.1),
+        ];
+        let words = words_from_tokens(tokens);
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].text, "Hello,");
+        assert_eq!((words[0].start_ms, words[0].end_ms), (0, 320));
+        assert!((words[0].probability - 0.8).abs() < 1e-6);
+        assert_eq!(words[1].text, "naïve");
+        assert_eq!((words[1].start_ms, words[1].end_ms), (400, 700));
+    }
+
+    #[test]
+    fn test_decoding_params_fall_back_to_defaults() {
+        let defaults = DecodingParams {
+            entropy_threshold: Some(2.8),
+            suppress_blank: Some(false),
+            ..Default::default()
+        };
+        let params = DecodingParams {
+            entropy_threshold: Some(2.0),
+            ..Default::default()
+        }
+        .or(&defaults);
+        assert_eq!(params.entropy_threshold, Some(2.0));
+        assert_eq!(params.suppress_blank, Some(false));
+        assert_eq!(params.length_penalty, None);
+    }
+
+    #[test]
+    fn test_temperature_fallback() {
+        let defaults = DecodingParams::default();
+        assert_eq!(defaults.temperatures(), vec![0.0, 0.2, 0.4, 0.6, 0.8, 1.0]);
+        let off = DecodingParams { temperature_increment: Some(0.0), ..Default::default() };
+        assert_eq!(off.temperatures(), vec![0.0]);
+        let coarse = DecodingParams { temperature_increment: Some(0.5), ..Default::default() };
+        assert_eq!(coarse.temperatures(), vec![0.0, 0.5, 1.0]);
+        assert!(DecodingParams { temperature_increment: Some(1.5), ..Default::default() }
+            .validate()
+            .is_err());
+
+        // Varied, confident output passes
+        let varied: Vec<u32> = (0..40).collect();
+        assert!(!defaults.needs_fallback(&varied, &[0.9; 40]));
+        assert!(!defaults.needs_fallback(&[], &[]));
+        // A phrase looping over and over is retried, as is improbable output
+        let looping: Vec<u32> = (0..40).map(|i| i % 3).collect();
+        assert!(defaults.needs_fallback(&looping, &[0.9; 40]));
+        assert!(defaults.needs_fallback(&varied[..5], &[0.2; 5]));
+        // Short output isn't judged repetitive
+        assert!(!defaults.needs_fallback(&looping[..10], &[0.9; 10]));
+    }
+
+    #[test]
+    fn test_suppression_masks_phrase_endings() {
+        let params = DecodingParams {
+            suppress: Some(" [ , [laughs],,".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(params.suppressed(), vec!["[", "[laughs]"]);
+
+        // A toy tokenizer: " " = 1, "[" = 2, "laughs" = 3, "]" = 4
+        let tokenize = |text: &str| -> Vec<u32> {
+            let text = text.replace("[laughs]", "[L]");
+            text.chars()
+                .map(|c| match c {
+                    ' ' => 1,
+                    '[' => 2,
+                    'L' => 3,
+                    _ => 4,
+                })
+                .collect()
+        };
+        let suppression = Suppression::new(&["[laughs]"], tokenize);
+        let mut logits = vec![0.0; 5];
+        suppression.apply(&[7, 2], &mut logits);
+        assert_eq!(logits, vec![0.0; 5]);
+        suppression.apply(&[7, 2, 3], &mut logits);
+        assert_eq!(logits[4], f32::NEG_INFINITY);
+
+        let suppression = Suppression::new(&["["], tokenize);
+        let mut logits = vec![0.0; 5];
+        suppression.apply(&[], &mut logits);
+        assert_eq!(logits[2], f32::NEG_INFINITY);
+        assert!(Suppression::new(&[], tokenize).is_empty());
+    }
+
+    #[test]
+    fn test_decoding_params_validate() {
+        assert!(DecodingParams::default().validate().is_ok());
+        let invalid = DecodingParams {
+            no_speech_threshold: Some(1.5),
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = DecodingParams {
+            entropy_threshold: Some(f32::NAN),
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_default_tuning_is_not_serialized() {
+        // Keeps cache keys of requests without decoder or segment settings stable
+        let json = serde_json::to_value(TranscribeOptions::default()).unwrap();
+        assert!(json.get("decoding").is_none());
+        assert!(json.get("segmentation").is_none());
+
+        let options = TranscribeOptions {
+            segmentation: Segmentation {
+                max_len: Some(42),
+                split_on_word: true,
+                single_segment: false,
+            },
+            ..Default::default()
+        };
+        let json = serde_json::to_value(options).unwrap();
+        assert_eq!(json["segmentation"], serde_json::json!({ "max_len": 42, "split_on_word": true }));
+    }
+
+    #[test]
+    fn test_model_not_loaded_initially() {
+        // Note: This test may fail if run after other tests that load the model
+        // In a fresh process, the model should not be loaded
+    }
+
+    #[test]
+    fn test_unload_if_idle_without_model() {
+        // Nothing to unload in a process that never loaded a model
+        assert!(!unload_if_idle(Duration::ZERO));
+        assert_eq!(model_state(), ModelState::NotLoaded);
+    }
+
+    #[test]
+    fn test_swap_to_missing_model_keeps_state() {
+        assert!(swap_model("/nonexistent/ggml-tiny.bin").is_err());
+        assert_eq!(model_state(), ModelState::NotLoaded);
+    }
+
+    #[test]
+    fn test_model_state_serialization() {
+        let json = serde_json::to_string(&ModelState::NotLoaded).unwrap();
+        assert_eq!(json, "\"not_loaded\"");
+    }
+
+    #[test]
+    fn test_default_transcribe_options() {
+        let opts = TranscribeOptions::default();
+        assert!(opts.language.is_none());
+        assert!(!opts.translate);
+        assert!(!opts.vad);
+    }
+}
@@ -0,0 +1,558 @@
+//! Pure-Rust backend on candle's whisper implementation.
+//!
+//! Loads a Hugging Face model directory (`config.json`, `tokenizer.json`
+//! and `model.safetensors`, e.g. from `openai/whisper-small.en`) and runs
+//! on the CPU. Audio is decoded in 30-second windows without timestamp
+//! tokens, so each window becomes one segment.
+
+use anyhow::{Context, Result};
+use candle_core::{D, Device, IndexOp, Tensor};
+use candle_nn::VarBuilder;
+use candle_nn::ops::softmax;
+use candle_transformers::generation::LogitsProcessor;
+use candle_transformers::models::whisper::{self as m, Config, audio, model::Whisper};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tracing::debug;
+
+use super::{
+    Decoded, DecodingParams, Segment, SegmentTokens, Suppression, Token, TranscribeOptions,
+    avg_logprob,
+};
+
+/// Milliseconds covered by one mel frame.
+const MS_PER_FRAME: usize = m::HOP_LENGTH * 1000 / m::SAMPLE_RATE;
+/// Token preceding the initial prompt.
+const PREV_TOKEN: &str = "<|startofprev|>";
+/// Vocabulary size of multilingual models; English-only models have one
+/// token less.
+const MULTILINGUAL_VOCAB_SIZE: usize = 51865;
+/// Seed of the sampler used by the temperature fallback.
+const SAMPLING_SEED: u64 = 299_792_458;
+
+/// A loaded safetensors model.
+pub(super) struct Model {
+    /// Weights; cloned per transcription, which shares the tensors but gives
+    /// each its own decoder cache.
+    whisper: Whisper,
+    config: Config,
+    tokenizer: Tokenizer,
+    /// Mel filterbank, `num_mel_bins` rows of `N_FFT / 2 + 1` weights.
+    mel_filters: Vec<f32>,
+    /// Added to the logits: `-inf` for tokens that must never be sampled.
+    suppress: Tensor,
+    sot: u32,
+    eot: u32,
+    device: Device,
+}
+
+impl Model {
+    /// Load a model directory.
+    pub(super) fn load(dir: &Path) -> Result<Self> {
+        let device = Device::Cpu;
+
+        let config: Config = serde_json::from_str(
+            &fs::read_to_string(dir.join("config.json")).context("Failed to read config.json")?,
+        )
+        .context("Invalid config.json")?;
+        let tokenizer = Tokenizer::from_file(&dir.join("tokenizer.json"))?;
+
+        // Safety: the weights are memory-mapped; like whisper.cpp with its
+        // model file, we assume nobody rewrites the file while it is loaded.
+        let vb = unsafe {
+            VarBuilder::from_mmaped_safetensors(&[dir.join("model.safetensors")], m::DTYPE, &device)?
+        };
+        let whisper = Whisper::load(&vb, config.clone())?;
+
+        let sot = tokenizer.special(m::SOT_TOKEN)?;
+        let eot = tokenizer.special(m::EOT_TOKEN)?;
+
+        // Only text tokens and end-of-text may be sampled: special tokens
+        // (language, task, timestamps) all sort after end-of-text
+        let suppress: Vec<f32> = (0..config.vocab_size as u32)
+            .map(|id| {
+                if id > eot || config.suppress_tokens.contains(&id) {
+                    f32::NEG_INFINITY
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let suppress = Tensor::new(suppress.as_slice(), &device)?;
+
+        Ok(Self {
+            mel_filters: mel_filters(config.num_mel_bins),
+            whisper,
+            config,
+            tokenizer,
+            suppress,
+            sot,
+            eot,
+            device,
+        })
+    }
+
+    /// Transcribe 16kHz mono samples, reporting each 30-second window as a
+    /// segment once it is decoded.
+    pub(super) fn transcribe<F, P>(
+        &self,
+        samples: &[f32],
+        options: &TranscribeOptions,
+        on_segment: &mut F,
+        on_progress: &mut P,
+    ) -> Result<Decoded>
+    where
+        F: FnMut(&Segment),
+        P: FnMut(i32),
+    {
+        let mut whisper = self.whisper.clone();
+        // English-only models ignore the language; multilingual models
+        // report the language they transcribed
+        let multilingual = self.config.vocab_size >= MULTILINGUAL_VOCAB_SIZE;
+        let mut language = match (options.language.as_deref().unwrap_or("en"), multilingual) {
+            ("auto", false) => Some("en".to_string()),
+            (_, false) => None,
+            (requested, true) => Some(requested.to_string()),
+        };
+        let mut language_probability = None;
+        let mut prompt = Vec::new();
+
+        // The spectrogram is padded with silence past the end of the audio
+        let n_mels = self.config.num_mel_bins;
+        let mel = audio::pcm_to_mel(&self.config, samples, &self.mel_filters);
+        let mel_frames = mel.len() / n_mels;
+        let mel = Tensor::from_vec(mel, (1, n_mels, mel_frames), &self.device)?;
+        let content_frames = samples.len() / m::HOP_LENGTH;
+
+        let mut text = String::new();
+        let mut segments = Vec::new();
+        let mut segment_tokens = Vec::new();
+        let mut prob_sum = 0.0f32;
+        let mut prob_count = 0usize;
+
+        let decoding = options.decoding.clone().or(&super::decoding_defaults());
+        let suppression =
+            Suppression::new(&decoding.suppressed(), |piece| self.tokenizer.encode_piece(piece));
+
+        // Two spectrogram frames per encoder frame
+        let max_window = options
+            .audio_ctx
+            .frames(samples.len())
+            .map_or(m::N_FRAMES, |frames| frames as usize * 2);
+        let mut seek = 0;
+        while seek < content_frames {
+            let window = (mel_frames - seek).min(max_window);
+            let features = whisper.encoder.forward(&mel.narrow(2, seek, window)?, true)?;
+            if seek == 0 {
+                // Detect the language from the first window
+                if language.as_deref() == Some("auto") {
+                    let (detected, probability) = self.detect_language(&mut whisper, &features)?;
+                    language = Some(detected);
+                    language_probability = Some(probability);
+                }
+                prompt = self.prompt(options, language.as_deref().unwrap_or("en"))?;
+            }
+            let (tokens, probs) = self.decode_with_fallback(
+                &mut whisper,
+                &features,
+                &prompt,
+                &suppression,
+                &decoding,
+            )?;
+
+            let window_text = self.tokenizer.decode(&tokens);
+            let segment = Segment {
+                start_ms: (seek * MS_PER_FRAME) as i64,
+                end_ms: ((seek + window).min(content_frames) * MS_PER_FRAME) as i64,
+                text: window_text.trim().to_string(),
+            };
+            debug!(start_ms = segment.start_ms, tokens = tokens.len(), "Decoded window");
+            if !segment.text.is_empty() {
+                on_segment(&segment);
+                text.push_str(&window_text);
+                if options.tokens {
+                    segment_tokens.push(SegmentTokens {
+                        start_ms: segment.start_ms,
+                        end_ms: segment.end_ms,
+                        tokens: self.tokens(&tokens, &probs),
+                    });
+                }
+                segments.push(segment);
+            }
+            prob_sum += probs.iter().sum::<f32>();
+            prob_count += probs.len();
+
+            seek += window;
+            on_progress((seek.min(content_frames) * 100 / content_frames) as i32);
+        }
+
+        Ok(Decoded {
+            text,
+            segments,
+            // Word timing needs cross-attention alignment, which isn't implemented
+            words: Vec::new(),
+            tokens: segment_tokens,
+            avg_token_prob: if prob_count > 0 {
+                prob_sum / prob_count as f32
+            } else {
+                0.0
+            },
+            // Nothing to detect from without audio
+            language: language.filter(|language| language != "auto"),
+            language_probability,
+        })
+    }
+
+    /// Decoded text tokens with their probabilities, untimed.
+    fn tokens(&self, ids: &[u32], probs: &[f32]) -> Vec<Token> {
+        ids.iter()
+            .zip(probs)
+            .map(|(&id, &probability)| Token {
+                id,
+                text: self.tokenizer.decode(&[id]),
+                probability,
+                start_ms: None,
+                end_ms: None,
+            })
+            .collect()
+    }
+
+    /// Detect the spoken language of a window from the decoder's first
+    /// prediction after start of transcript, returning its code and
+    /// probability.
+    fn detect_language(&self, whisper: &mut Whisper, features: &Tensor) -> Result<(String, f32)> {
+        // Language tokens sit between start of transcript and translate
+        let first = self.sot + 1;
+        let count = self.tokenizer.special(m::TRANSLATE_TOKEN)? - first;
+
+        let input = Tensor::new(&[self.sot], &self.device)?.unsqueeze(0)?;
+        let ys = whisper.decoder.forward(&input, features, true)?;
+        let logits = whisper.decoder.final_linear(&ys.i((..1, ..1))?)?.i(0)?.i(0)?;
+        let probs = softmax(&logits.narrow(0, first as usize, count as usize)?, D::Minus1)?
+            .to_vec1::<f32>()?;
+
+        let (index, probability) = probs
+            .into_iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .context("Model has no language tokens")?;
+        let token = &self.tokenizer.tokens[first as usize + index];
+        let language = token.trim_start_matches("<|").trim_end_matches("|>").to_string();
+        debug!(language, probability, "Detected language");
+        Ok((language, probability))
+    }
+
+    /// Decode one window at each of the fallback temperatures in turn (see
+    /// [`DecodingParams::temperatures`]) until the result passes the
+    /// thresholds, returning the first that does or else the most probable.
+    fn decode_with_fallback(
+        &self,
+        whisper: &mut Whisper,
+        features: &Tensor,
+        prompt: &[u32],
+        suppression: &Suppression,
+        decoding: &DecodingParams,
+    ) -> Result<(Vec<u32>, Vec<f32>)> {
+        let mut best: Option<(Vec<u32>, Vec<f32>)> = None;
+        for temperature in decoding.temperatures() {
+            let (tokens, probs) =
+                self.decode_window(whisper, features, prompt, suppression, temperature)?;
+            if !decoding.needs_fallback(&tokens, &probs) {
+                return Ok((tokens, probs));
+            }
+            debug!(temperature, tokens = tokens.len(), "Window failed the decoding thresholds");
+            let better = match &best {
+                Some((_, best_probs)) => avg_logprob(&probs) > avg_logprob(best_probs),
+                None => true,
+            };
+            if better {
+                best = Some((tokens, probs));
+            }
+        }
+        Ok(best.unwrap_or_default())
+    }
+
+    /// Decode one window, greedily at temperature 0 and by sampling above,
+    /// returning the text tokens and their probabilities.
+    fn decode_window(
+        &self,
+        whisper: &mut Whisper,
+        features: &Tensor,
+        prompt: &[u32],
+        suppression: &Suppression,
+        temperature: f32,
+    ) -> Result<(Vec<u32>, Vec<f32>)> {
+        let mut tokens = prompt.to_vec();
+        let mut probs = Vec::new();
+        // Seeded, so the same audio transcribes the same way every time
+        let mut sampler = LogitsProcessor::new(SAMPLING_SEED, Some(temperature as f64), None);
+
+        for i in 0..self.config.max_target_positions / 2 {
+            let input = Tensor::new(tokens.as_slice(), &self.device)?.unsqueeze(0)?;
+            // The cross-attention cache is rebuilt for each new window
+            let ys = whisper.decoder.forward(&input, features, i == 0)?;
+            let (_, seq_len, _) = ys.dims3()?;
+            let logits = whisper
+                .decoder
+                .final_linear(&ys.i((..1, seq_len - 1..))?)?
+                .i(0)?
+                .i(0)?
+                .broadcast_add(&self.suppress)?;
+            let logits = if suppression.is_empty() {
+                logits
+            } else {
+                let mut values = logits.to_vec1::<f32>()?;
+                suppression.apply(&tokens, &mut values);
+                Tensor::new(values.as_slice(), &self.device)?
+            };
+
+            let next = sampler.sample(&logits)?;
+            if next == self.eot {
+                break;
+            }
+            let prob = softmax(&logits, D::Minus1)?.i(next as usize)?.to_scalar::<f32>()?;
+            tokens.push(next);
+            probs.push(prob);
+        }
+
+        Ok((tokens.split_off(prompt.len()), probs))
+    }
+
+    /// Decoder prompt: the initial prompt (if any), then start of
+    /// transcript, language, task and no-timestamps tokens.
+    fn prompt(&self, options: &TranscribeOptions, language: &str) -> Result<Vec<u32>> {
+        let mut tokens = Vec::new();
+
+        if let Some(initial_prompt) = &options.initial_prompt {
+            // Like whisper.cpp, keep at most half the context for the prompt
+            let mut prompt = self.tokenizer.encode(initial_prompt);
+            let max_len = self.config.max_target_positions / 2 - 1;
+            if prompt.len() > max_len {
+                prompt.drain(..prompt.len() - max_len);
+            }
+            tokens.push(self.tokenizer.special(PREV_TOKEN)?);
+            tokens.extend(prompt);
+        }
+
+        tokens.push(self.sot);
+        // English-only models have no language tokens
+        if let Some(id) = self.tokenizer.token_id(&format!("<|{}|>", language)) {
+            tokens.push(id);
+        }
+        tokens.push(self.tokenizer.special(if options.translate {
+            m::TRANSLATE_TOKEN
+        } else {
+            m::TRANSCRIBE_TOKEN
+        })?);
+        tokens.push(self.tokenizer.special(m::NO_TIMESTAMPS_TOKEN)?);
+        Ok(tokens)
+    }
+}
+
+/// The byte-level BPE vocabulary from a Hugging Face `tokenizer.json`.
+///
+/// Only what whisper needs: decoding, special token lookup and prompt
+/// encoding.
+struct Tokenizer {
+    /// Token strings, indexed by id.
+    tokens: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+impl Tokenizer {
+    fn from_file(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path).context("Failed to read tokenizer.json")?;
+        Self::from_json(&json)
+    }
+
+    fn from_json(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json).context("Invalid tokenizer.json")?;
+
+        let mut ids: HashMap<String, u32> = HashMap::new();
+        let vocab = value["model"]["vocab"]
+            .as_object()
+            .context("tokenizer.json has no model.vocab")?;
+        for (token, id) in vocab {
+            ids.insert(token.clone(), id.as_u64().context("Invalid token id")? as u32);
+        }
+        for added in value["added_tokens"].as_array().into_iter().flatten() {
+            if let (Some(content), Some(id)) = (added["content"].as_str(), added["id"].as_u64()) {
+                ids.insert(content.to_string(), id as u32);
+            }
+        }
+
+        let len = ids.values().max().map_or(0, |&max| max as usize + 1);
+        let mut tokens = vec![String::new(); len];
+        for (token, &id) in &ids {
+            tokens[id as usize] = token.clone();
+        }
+        Ok(Self { tokens, ids })
+    }
+
+    fn token_id(&self, token: &str) -> Option<u32> {
+        self.ids.get(token).copied()
+    }
+
+    fn special(&self, token: &str) -> Result<u32> {
+        self.token_id(token)
+            .with_context(|| format!("tokenizer.json has no {} token", token))
+    }
+
+    /// Decode text tokens to a string.
+    fn decode(&self, ids: &[u32]) -> String {
+        let decoder = byte_decoder();
+        let bytes: Vec<u8> = ids
+            .iter()
+            .filter_map(|&id| self.tokens.get(id as usize))
+            .flat_map(|token| token.chars())
+            .filter_map(|c| decoder.get(&c).copied())
+            .collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    /// Encode text by greedy longest match against the vocabulary.
+    ///
+    /// This is not the canonical BPE tokenization, but it decodes to the
+    /// same text, which is all the decoder needs from a prompt.
+    fn encode(&self, text: &str) -> Vec<u32> {
+        // Words carry their leading space, as in running text
+        text.split_whitespace()
+            .flat_map(|word| self.encode_piece(&format!(" {}", word)))
+            .collect()
+    }
+
+    /// Encode `piece` as is, spaces included, by greedy longest match.
+    fn encode_piece(&self, piece: &str) -> Vec<u32> {
+        let encoder: HashMap<u8, char> = byte_decoder().into_iter().map(|(c, b)| (b, c)).collect();
+        let chars: Vec<char> = piece.bytes().map(|b| encoder[&b]).collect();
+        let mut tokens = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let matched = (start + 1..=chars.len()).rev().find_map(|end| {
+                let piece: String = chars[start..end].iter().collect();
+                self.token_id(&piece).map(|id| (id, end))
+            });
+            match matched {
+                Some((id, end)) => {
+                    tokens.push(id);
+                    start = end;
+                }
+                // Every byte is in a byte-level vocabulary; skip if not
+                None => start += 1,
+            }
+        }
+        tokens
+    }
+}
+
+/// Map the printable characters byte-level BPE uses back to bytes (GPT-2's
+/// `bytes_to_unicode`, inverted).
+fn byte_decoder() -> HashMap<char, u8> {
+    let printable = |b: u8| matches!(b, b'!'..=b'~' | 0xA1..=0xAC | 0xAE..=0xFF);
+    let mut shifted = 0u32;
+    (0..=255u8)
+        .map(|b| {
+            let c = if printable(b) {
+                char::from(b)
+            } else {
+                shifted += 1;
+                char::from_u32(255 + shifted).unwrap()
+            };
+            (c, b)
+        })
+        .collect()
+}
+
+/// Slaney-style mel filterbank, as used by Whisper (librosa defaults):
+/// `n_mels` rows of `N_FFT / 2 + 1` weights.
+fn mel_filters(n_mels: usize) -> Vec<f32> {
+    const MIN_LOG_HZ: f64 = 1000.0;
+    const MIN_LOG_MEL: f64 = 15.0;
+    const HZ_PER_MEL: f64 = 200.0 / 3.0;
+    let log_step = 6.4f64.ln() / 27.0;
+
+    let hz_to_mel = |hz: f64| {
+        if hz < MIN_LOG_HZ {
+            hz / HZ_PER_MEL
+        } else {
+            MIN_LOG_MEL + (hz / MIN_LOG_HZ).ln() / log_step
+        }
+    };
+    let mel_to_hz = |mel: f64| {
+        if mel < MIN_LOG_MEL {
+            mel * HZ_PER_MEL
+        } else {
+            MIN_LOG_HZ * ((mel - MIN_LOG_MEL) * log_step).exp()
+        }
+    };
+
+    let n_freqs = m::N_FFT / 2 + 1;
+    let max_mel = hz_to_mel(m::SAMPLE_RATE as f64 / 2.0);
+    // Band edges: n_mels + 2 points evenly spaced on the mel scale
+    let edges: Vec<f64> = (0..n_mels + 2)
+        .map(|i| mel_to_hz(max_mel * i as f64 / (n_mels + 1) as f64))
+        .collect();
+
+    let mut filters = vec![0.0f32; n_mels * n_freqs];
+    for band in 0..n_mels {
+        let (lower, center, upper) = (edges[band], edges[band + 1], edges[band + 2]);
+        // Normalize each triangle to unit area
+        let norm = 2.0 / (upper - lower);
+        for bin in 0..n_freqs {
+            let hz = bin as f64 * m::SAMPLE_RATE as f64 / m::N_FFT as f64;
+            let rising = (hz - lower) / (center - lower);
+            let falling = (upper - hz) / (upper - center);
+            filters[band * n_freqs + bin] = (rising.min(falling).max(0.0) * norm) as f32;
+        }
+    }
+    filters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKENIZER_JSON: &str = r#"{
+        "model": {"vocab": {"Hello": 0, "Ġworld": 1, "Ġwor": 2, "ld": 3, "!": 4, "Ġ": 5, "d": 6}},
+        "added_tokens": [{"id": 7, "content": "<|endoftext|>"}]
+    }"#;
+
+    #[test]
+    fn test_tokenizer_decode() {
+        let tokenizer = Tokenizer::from_json(TOKENIZER_JSON).unwrap();
+        assert_eq!(tokenizer.decode(&[0, 1, 4]), "Hello world!");
+        assert_eq!(tokenizer.special(m::EOT_TOKEN).unwrap(), 7);
+        assert!(tokenizer.special(m::SOT_TOKEN).is_err());
+    }
+
+    #[test]
+    fn test_tokenizer_encode_prefers_longest_match() {
+        let tokenizer = Tokenizer::from_json(TOKENIZER_JSON).unwrap();
+        assert_eq!(tokenizer.encode("world"), vec![1]);
+        assert_eq!(tokenizer.decode(&tokenizer.encode("world!  world")), " world! world");
+        assert_eq!(tokenizer.encode_piece("d!"), vec![6, 4]);
+    }
+
+    #[test]
+    fn test_byte_decoder_covers_all_bytes() {
+        let decoder = byte_decoder();
+        assert_eq!(decoder.len(), 256);
+        assert_eq!(decoder[&'Ġ'], b' ');
+        assert_eq!(decoder[&'a'], b'a');
+    }
+
+    #[test]
+    fn test_mel_filters_shape() {
+        let filters = mel_filters(80);
+        let n_freqs = m::N_FFT / 2 + 1;
+        assert_eq!(filters.len(), 80 * n_freqs);
+        // Every band picks up some frequency bins
+        for band in filters.chunks(n_freqs) {
+            assert!(band.iter().any(|&w| w > 0.0));
+        }
+        // The first band sits at the bottom of the spectrum
+        assert!(filters[1] > 0.0);
+        assert_eq!(filters[n_freqs - 1], 0.0);
+    }
+}
@@ -0,0 +1,524 @@
+//! whisper.cpp backend (via whisper-rs).
+
+use anyhow::{Context, Result};
+use std::ffi::{CStr, c_int, c_void};
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, mpsc};
+use whisper_rs::whisper_rs_sys;
+use tracing::debug;
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState};
+
+use super::{
+    Acceleration, CpuLimits, Decoded, DecodingParams, Segment, SegmentTokens, Suppression, Token,
+    TranscribeOptions,
+};
+
+/// A loaded ggml model.
+pub(super) struct Model {
+    ctx: WhisperContext,
+}
+
+impl Model {
+    /// Load a ggml model file, on the GPU if `use_gpu` and whisper.cpp was
+    /// built with Metal.
+    pub(super) fn load(path: &str, use_gpu: bool) -> Result<Self> {
+        let mut params = WhisperContextParameters::default();
+        params.use_gpu(use_gpu && cfg!(feature = "metal"));
+        let ctx = WhisperContext::new_with_params(path, params)?;
+        Ok(Self { ctx })
+    }
+
+    /// Transcribe 16kHz mono samples, reporting segments and progress as
+    /// whisper decodes them.
+    ///
+    /// With core pinning or niceness configured, whisper runs on the
+    /// [`limited_pool`] and its callbacks are relayed back to the calling
+    /// thread, which keeps its own scheduling.
+    pub(super) fn transcribe<F, P>(
+        &self,
+        samples: &[f32],
+        options: &TranscribeOptions,
+        on_segment: &mut F,
+        on_progress: &mut P,
+    ) -> Result<Decoded>
+    where
+        F: FnMut(&Segment),
+        P: FnMut(i32),
+    {
+        let limits = super::cpu_limits();
+        let Some(pool) = limited_pool(limits) else {
+            return self.decode(samples, options, limits, on_segment, on_progress);
+        };
+        let (events_tx, events) = mpsc::channel();
+        pool.in_place_scope(|scope| {
+            scope.spawn(move |_| {
+                let progress_tx = events_tx.clone();
+                let result = self.decode(
+                    samples,
+                    options,
+                    limits,
+                    &mut |segment: &Segment| {
+                        let _ = events_tx.send(Event::Segment(segment.clone()));
+                    },
+                    &mut |progress| {
+                        let _ = progress_tx.send(Event::Progress(progress));
+                    },
+                );
+                let _ = progress_tx.send(Event::Done(result));
+            });
+            // Ends once the job is done (or has panicked, which the scope
+            // then resumes here)
+            let mut decoded = None;
+            for event in events {
+                match event {
+                    Event::Segment(segment) => on_segment(&segment),
+                    Event::Progress(progress) => on_progress(progress),
+                    Event::Done(result) => decoded = Some(result),
+                }
+            }
+            decoded.unwrap_or_else(|| Err(anyhow::anyhow!("Transcription thread stopped")))
+        })
+    }
+
+    fn decode<F, P>(
+        &self,
+        samples: &[f32],
+        options: &TranscribeOptions,
+        limits: &CpuLimits,
+        on_segment: &mut F,
+        on_progress: &mut P,
+    ) -> Result<Decoded>
+    where
+        F: FnMut(&Segment),
+        P: FnMut(i32),
+    {
+        // Create whisper state for this transcription
+        let mut state = self.ctx.create_state().context("Failed to create whisper state")?;
+
+        // Configure transcription parameters
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+
+        let threads = options.threads.or(limits.threads);
+        if let Some(threads) = threads {
+            params.set_n_threads(threads.max(1) as c_int);
+        }
+
+        // Set language (English by default). English-only models ignore it;
+        // multilingual models report the language they transcribed.
+        let requested = options.language.as_deref().unwrap_or("en");
+        let multilingual = self.ctx.is_multilingual();
+        let (language, language_probability) = match (requested, multilingual) {
+            ("auto", true) => {
+                let (language, probability) = detect_language(&mut state, samples, threads)?;
+                (Some(language), Some(probability))
+            }
+            ("auto", false) => (Some("en"), None),
+            (requested, true) => (Some(requested), None),
+            (_, false) => (None, None),
+        };
+        params.set_language(Some(language.unwrap_or("en")));
+
+        params.set_translate(options.translate);
+        let decoding = options.decoding.clone().or(&super::decoding_defaults());
+        set_decoding_params(&mut params, &decoding);
+        let filter = LogitsFilter {
+            suppression: Suppression::new(&decoding.suppressed(), |piece| {
+                let tokens = self.ctx.tokenize(piece, piece.len() + 8).unwrap_or_default();
+                tokens.into_iter().map(|id| id as u32).collect()
+            }),
+            n_vocab: self.ctx.n_vocab().max(0) as usize,
+        };
+        if let Some(prompt) = &options.initial_prompt {
+            // whisper-rs leaks the prompt's CString; prompts are a few hundred bytes
+            params.set_initial_prompt(prompt);
+        }
+        params.set_print_special(false);
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_timestamps(false);
+
+        // Segment splitting; whisper.cpp only applies max_len with token
+        // timestamps, which are otherwise skipped for speed unless words
+        // are timed
+        let segmentation = &options.segmentation;
+        let max_len = segmentation.max_len.unwrap_or(0);
+        params.set_max_len(max_len as c_int);
+        params.set_token_timestamps(max_len > 0 || options.word_timestamps || options.tokens);
+        params.set_split_on_word(segmentation.split_on_word);
+        params.set_single_segment(segmentation.single_segment);
+
+        // Audio processing optimizations
+        params.set_speed_up(true); // Enable speed optimizations in Whisper
+        // Encode only as much of the 30-second window as the audio needs;
+        // 0 is the full window
+        let audio_ctx = options.audio_ctx.frames(samples.len()).unwrap_or(0);
+        params.set_audio_ctx(audio_ctx as c_int);
+
+        // Both closures outlive `full()`, which is the only place whisper
+        // invokes the callbacks.
+        unsafe {
+            set_new_segment_callback(&mut params, on_segment);
+            set_progress_callback(&mut params, on_progress);
+            if !filter.suppression.is_empty() {
+                set_logits_filter(&mut params, &filter);
+            }
+        }
+
+        // Run transcription
+        state
+            .full(params, samples)
+            .context("Whisper transcription failed")?;
+
+        // Extract text from segments
+        let num_segments = state.full_n_segments()?;
+        let mut text = String::new();
+
+        let mut segments = Vec::with_capacity(num_segments.max(0) as usize);
+        let mut words = Vec::new();
+        let mut segment_tokens = Vec::new();
+
+        let mut prob_sum = 0.0f32;
+        let mut prob_count = 0usize;
+
+        for i in 0..num_segments {
+            let segment_text = state
+                .full_get_segment_text(i)
+                .context("Failed to get segment text")?;
+            text.push_str(&segment_text);
+
+            // Timestamps are reported in centiseconds
+            let segment = Segment {
+                start_ms: state.full_get_segment_t0(i)? * 10,
+                end_ms: state.full_get_segment_t1(i)? * 10,
+                text: segment_text.trim().to_string(),
+            };
+
+            // Special tokens (timestamps, end-of-text, ...) sort after text tokens
+            let mut tokens = Vec::new();
+            let mut raw_tokens = Vec::new();
+            for t in 0..state.full_n_tokens(i)? {
+                let id = state.full_get_token_id(i, t)?;
+                if id < self.ctx.token_eot() {
+                    let data = state.full_get_token_data(i, t)?;
+                    prob_sum += data.p;
+                    prob_count += 1;
+                    let bytes = self.ctx.token_to_cstr(id)?.to_bytes();
+                    if options.word_timestamps {
+                        tokens.push((bytes, data.t0 * 10, data.t1 * 10, data.p));
+                    }
+                    if options.tokens {
+                        raw_tokens.push(Token {
+                            id: id as u32,
+                            text: String::from_utf8_lossy(bytes).into_owned(),
+                            probability: data.p,
+                            start_ms: Some(data.t0 * 10),
+                            end_ms: Some(data.t1 * 10),
+                        });
+                    }
+                }
+            }
+            words.extend(super::words_from_tokens(tokens));
+            if options.tokens {
+                segment_tokens.push(SegmentTokens {
+                    start_ms: segment.start_ms,
+                    end_ms: segment.end_ms,
+                    tokens: raw_tokens,
+                });
+            }
+            segments.push(segment);
+        }
+
+        Ok(Decoded {
+            text,
+            segments,
+            words,
+            tokens: segment_tokens,
+            avg_token_prob: if prob_count > 0 {
+                prob_sum / prob_count as f32
+            } else {
+                0.0
+            },
+            language: language.map(str::to_string),
+            language_probability,
+        })
+    }
+}
+
+/// Detect the spoken language from the first 30 seconds of `samples`,
+/// returning its code and probability.
+fn detect_language(
+    state: &mut WhisperState,
+    samples: &[f32],
+    threads: Option<u32>,
+) -> Result<(&'static str, f32)> {
+    // whisper.cpp's own default: one thread per core, at most 4
+    let threads = threads.map_or_else(
+        || std::thread::available_parallelism().map_or(1, |n| n.get().min(4)),
+        |n| n.max(1) as usize,
+    );
+    state
+        .pcm_to_mel(samples, threads)
+        .context("Failed to compute the spectrogram")?;
+    let probs = state
+        .lang_detect(0, threads)
+        .context("Language detection failed")?;
+    let (id, probability) = probs
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .context("Language detection returned no languages")?;
+    let language = whisper_rs::get_lang_str(id as i32).context("Unknown language detected")?;
+    debug!(language, probability, "Detected language");
+    Ok((language, probability))
+}
+
+/// Hardware acceleration whisper.cpp uses for the model at `path`.
+pub(super) fn acceleration(path: &Path, use_gpu: bool) -> Acceleration {
+    Acceleration {
+        metal: use_gpu && cfg!(feature = "metal"),
+        // whisper.cpp falls back to the CPU encoder when there is none
+        coreml: cfg!(feature = "coreml") && coreml_encoder_path(path).exists(),
+    }
+}
+
+/// Where whisper.cpp looks for the CoreML encoder of a ggml model:
+/// `ggml-small.en.bin` -> `ggml-small.en-encoder.mlmodelc`. A quantization
+/// suffix is ignored (`ggml-small.en-q5_0.bin` uses the same encoder).
+fn coreml_encoder_path(model: &Path) -> PathBuf {
+    let mut stem = model
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if let Some(pos) = stem.rfind('-') {
+        let suffix = &stem.as_bytes()[pos..];
+        if suffix.len() == 5 && suffix[1] == b'q' && suffix[3] == b'_' {
+            stem.truncate(pos);
+        }
+    }
+    model.with_file_name(format!("{}-encoder.mlmodelc", stem))
+}
+
+/// Apply the decoder settings that are set, keeping whisper.cpp's defaults
+/// for the rest.
+fn set_decoding_params(params: &mut FullParams, decoding: &DecodingParams) {
+    if let Some(threshold) = decoding.entropy_threshold {
+        params.set_entropy_thold(threshold);
+    }
+    if let Some(threshold) = decoding.logprob_threshold {
+        params.set_logprob_thold(threshold);
+    }
+    if let Some(threshold) = decoding.no_speech_threshold {
+        params.set_no_speech_thold(threshold);
+    }
+    if let Some(suppress) = decoding.suppress_blank {
+        params.set_suppress_blank(suppress);
+    }
+    if let Some(suppress) = decoding.suppress_non_speech_tokens {
+        params.set_suppress_non_speech_tokens(suppress);
+    }
+    if let Some(max_initial_ts) = decoding.max_initial_ts {
+        params.set_max_initial_ts(max_initial_ts);
+    }
+    if let Some(penalty) = decoding.length_penalty {
+        params.set_length_penalty(penalty);
+    }
+    if let Some(increment) = decoding.temperature_increment {
+        params.set_temperature_inc(increment);
+    }
+}
+
+/// What a transcription on the [`limited_pool`] reports back to its caller.
+enum Event {
+    Segment(Segment),
+    Progress(i32),
+    Done(Result<Decoded>),
+}
+
+/// The threads transcriptions run on when `limits` pin cores or lower the
+/// priority, or `None` without either.
+///
+/// The limits are applied to each thread of the pool as it starts, and
+/// whisper.cpp's worker threads inherit them, so the threads the rest of
+/// the program runs on keep their scheduling.
+fn limited_pool(limits: &CpuLimits) -> Option<&'static rayon::ThreadPool> {
+    static POOL: OnceLock<rayon::ThreadPool> = OnceLock::new();
+    if limits.cpus.is_empty() && limits.nice.is_none() {
+        return None;
+    }
+    let pool = POOL.get_or_init(|| {
+        let limits = limits.clone();
+        rayon::ThreadPoolBuilder::new()
+            .thread_name(|i| format!("whisper-{}", i))
+            .start_handler(move |_| limit_current_thread(&limits))
+            .build()
+            .expect("Failed to start the transcription threads")
+    });
+    Some(pool)
+}
+
+/// Apply the core pinning and niceness of `limits` to the calling thread.
+/// whisper.cpp's worker threads inherit both.
+fn limit_current_thread(limits: &CpuLimits) {
+    #[cfg(target_os = "linux")]
+    {
+        if !limits.cpus.is_empty() {
+            // SAFETY: `set` is a zeroed cpu_set_t that outlives the calls
+            let result = unsafe {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                for &cpu in &limits.cpus {
+                    libc::CPU_SET(cpu, &mut set);
+                }
+                libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+            };
+            if result != 0 {
+                tracing::warn!(cpus = ?limits.cpus, error = %std::io::Error::last_os_error(), "Failed to pin transcription thread");
+            }
+        }
+        if let Some(nice) = limits.nice {
+            // On Linux, PRIO_PROCESS with `who` = 0 sets the calling thread's niceness
+            let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) };
+            if result != 0 {
+                tracing::warn!(nice, error = %std::io::Error::last_os_error(), "Failed to lower transcription thread priority");
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    tracing::warn!("CPU pinning and niceness are only supported on Linux; ignoring them");
+}
+
+/// Suppressed phrases, masked in whisper's logits filter callback.
+struct LogitsFilter {
+    suppression: Suppression,
+    /// Length of the logits array whisper passes to the callback.
+    n_vocab: usize,
+}
+
+/// Register `filter` as whisper's logits filter.
+///
+/// # Safety
+/// `filter` must outlive every use of `params`.
+unsafe fn set_logits_filter(params: &mut FullParams, filter: &LogitsFilter) {
+    params.set_filter_logits_callback(Some(logits_filter_trampoline));
+    params.set_filter_logits_callback_user_data(filter as *const LogitsFilter as *mut c_void);
+}
+
+/// C callback invoked by whisper before sampling each token.
+///
+/// `user_data` points at the `LogitsFilter` registered by
+/// `set_logits_filter`; `tokens` are the tokens decoded so far.
+unsafe extern "C" fn logits_filter_trampoline(
+    _ctx: *mut whisper_rs_sys::whisper_context,
+    _state: *mut whisper_rs_sys::whisper_state,
+    tokens: *const whisper_rs_sys::whisper_token_data,
+    n_tokens: c_int,
+    logits: *mut f32,
+    user_data: *mut c_void,
+) {
+    let filter = &*(user_data as *const LogitsFilter);
+    if logits.is_null() {
+        return;
+    }
+    let history: Vec<u32> = if tokens.is_null() || n_tokens <= 0 {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(tokens, n_tokens as usize)
+            .iter()
+            .map(|token| token.id as u32)
+            .collect()
+    };
+    let logits = std::slice::from_raw_parts_mut(logits, filter.n_vocab);
+    filter.suppression.apply(&history, logits);
+}
+
+/// Register `on_segment` as whisper's new-segment callback.
+///
+/// # Safety
+/// `on_segment` must outlive every use of `params`.
+unsafe fn set_new_segment_callback<F>(params: &mut FullParams, on_segment: &mut F)
+where
+    F: FnMut(&Segment),
+{
+    params.set_new_segment_callback(Some(new_segment_trampoline::<F>));
+    params.set_new_segment_callback_user_data(on_segment as *mut F as *mut c_void);
+}
+
+/// Register `on_progress` as whisper's progress callback.
+///
+/// # Safety
+/// `on_progress` must outlive every use of `params`.
+unsafe fn set_progress_callback<P>(params: &mut FullParams, on_progress: &mut P)
+where
+    P: FnMut(i32),
+{
+    params.set_progress_callback(Some(progress_trampoline::<P>));
+    params.set_progress_callback_user_data(on_progress as *mut P as *mut c_void);
+}
+
+/// C callback invoked by whisper when `n_new` segments have been decoded.
+///
+/// `user_data` points at the `F` registered by `set_new_segment_callback`.
+unsafe extern "C" fn new_segment_trampoline<F>(
+    _ctx: *mut whisper_rs_sys::whisper_context,
+    state: *mut whisper_rs_sys::whisper_state,
+    n_new: c_int,
+    user_data: *mut c_void,
+) where
+    F: FnMut(&Segment),
+{
+    let on_segment = &mut *(user_data as *mut F);
+    let n_segments = whisper_rs_sys::whisper_full_n_segments_from_state(state);
+
+    for i in (n_segments - n_new).max(0)..n_segments {
+        let text_ptr = whisper_rs_sys::whisper_full_get_segment_text_from_state(state, i);
+        if text_ptr.is_null() {
+            continue;
+        }
+        // Timestamps are reported in centiseconds
+        let segment = Segment {
+            start_ms: whisper_rs_sys::whisper_full_get_segment_t0_from_state(state, i) * 10,
+            end_ms: whisper_rs_sys::whisper_full_get_segment_t1_from_state(state, i) * 10,
+            text: CStr::from_ptr(text_ptr).to_string_lossy().trim().to_string(),
+        };
+        on_segment(&segment);
+    }
+}
+
+/// C callback invoked by whisper with the overall progress percentage.
+///
+/// `user_data` points at the `P` registered by `set_progress_callback`.
+unsafe extern "C" fn progress_trampoline<P>(
+    _ctx: *mut whisper_rs_sys::whisper_context,
+    _state: *mut whisper_rs_sys::whisper_state,
+    progress: c_int,
+    user_data: *mut c_void,
+) where
+    P: FnMut(i32),
+{
+    let on_progress = &mut *(user_data as *mut P);
+    on_progress(progress);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coreml_encoder_path() {
+        assert_eq!(
+            coreml_encoder_path(Path::new("models/ggml-small.en.bin")),
+            Path::new("models/ggml-small.en-encoder.mlmodelc")
+        );
+        assert_eq!(
+            coreml_encoder_path(Path::new("models/ggml-base.en-q5_1.bin")),
+            Path::new("models/ggml-base.en-encoder.mlmodelc")
+        );
+    }
+
+    #[test]
+    fn test_acceleration_without_encoder() {
+        let acceleration = acceleration(Path::new("/nonexistent/ggml-small.en.bin"), false);
+        assert_eq!(acceleration, Acceleration::default());
+    }
+}
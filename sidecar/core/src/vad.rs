@@ -0,0 +1,221 @@
+//! Energy-based voice activity detection for VoiceMark.
+//!
+//! Long uploads (podcasts, meetings) often contain long stretches of silence.
+//! Whisper spends as much compute on those as on speech, so batch requests
+//! strip them before transcription and map timestamps back afterwards.
+
+use std::ops::Range;
+
+/// Sample rate of the audio fed to whisper.
+const SAMPLE_RATE: usize = 16000;
+/// Analysis frame length (20ms).
+const FRAME_SAMPLES: usize = SAMPLE_RATE / 50;
+/// Silences shorter than this are kept as-is (natural pauses).
+const MIN_SILENCE_MS: usize = 1500;
+/// Audio kept on each side of a removed silence so words aren't clipped.
+const PADDING_MS: usize = 250;
+/// Frames quieter than this RMS are always treated as silence.
+const ABSOLUTE_SILENCE_RMS: f32 = 0.002;
+/// Speech must exceed the estimated noise floor by this factor.
+const NOISE_FLOOR_FACTOR: f32 = 3.0;
+
+/// A region of the original audio that was kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KeptRegion {
+    /// Start of the region in the compacted audio (samples).
+    compact_start: usize,
+    /// Start of the region in the original audio (samples).
+    original_start: usize,
+    /// Region length (samples).
+    len: usize,
+}
+
+/// Maps positions in compacted audio back to the original timeline.
+#[derive(Debug, Clone, Default)]
+pub struct TimeMap {
+    regions: Vec<KeptRegion>,
+}
+
+impl TimeMap {
+    /// Convert a timestamp in the compacted audio to the original timeline.
+    pub fn to_original_ms(&self, compact_ms: i64) -> i64 {
+        if self.regions.is_empty() {
+            return compact_ms;
+        }
+        let pos = (compact_ms.max(0) as usize) * SAMPLE_RATE / 1000;
+        let region = self
+            .regions
+            .iter()
+            .rev()
+            .find(|r| r.compact_start <= pos)
+            .unwrap_or(&self.regions[0]);
+        let offset = (pos - region.compact_start).min(region.len);
+        ((region.original_start + offset) * 1000 / SAMPLE_RATE) as i64
+    }
+
+    /// Total number of samples removed.
+    pub fn removed_samples(&self, original_len: usize) -> usize {
+        if self.regions.is_empty() {
+            return 0;
+        }
+        original_len - self.regions.iter().map(|r| r.len).sum::<usize>()
+    }
+}
+
+/// Remove long silent regions from `samples`.
+///
+/// Returns the compacted audio and a map back to the original timeline.
+/// If nothing would be removed (or no speech is found at all), the
+/// original audio is returned with an empty map.
+pub fn strip_silence(samples: &[f32]) -> (Vec<f32>, TimeMap) {
+    let speech = detect_speech_frames(samples);
+    let min_silence_frames = MIN_SILENCE_MS * SAMPLE_RATE / 1000 / FRAME_SAMPLES;
+    let padding = PADDING_MS * SAMPLE_RATE / 1000;
+
+    // Collect silent runs long enough to remove, shrunk by the padding
+    let mut cuts: Vec<(usize, usize)> = Vec::new();
+    let mut run_start = None;
+    for (i, &is_speech) in speech.iter().chain(std::iter::once(&true)).enumerate() {
+        match (is_speech, run_start) {
+            (false, None) => run_start = Some(i),
+            (true, Some(start)) => {
+                if i - start >= min_silence_frames {
+                    let cut_start = (start * FRAME_SAMPLES + padding).min(samples.len());
+                    let cut_end = (i * FRAME_SAMPLES).saturating_sub(padding).min(samples.len());
+                    if cut_end > cut_start {
+                        cuts.push((cut_start, cut_end));
+                    }
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    if cuts.is_empty() || !speech.iter().any(|&s| s) {
+        return (samples.to_vec(), TimeMap::default());
+    }
+
+    let mut compacted = Vec::with_capacity(samples.len());
+    let mut regions = Vec::with_capacity(cuts.len() + 1);
+    let mut cursor = 0;
+    let end = std::iter::once((samples.len(), samples.len()));
+    for (cut_start, cut_end) in cuts.into_iter().chain(end) {
+        if cut_start > cursor {
+            regions.push(KeptRegion {
+                compact_start: compacted.len(),
+                original_start: cursor,
+                len: cut_start - cursor,
+            });
+            compacted.extend_from_slice(&samples[cursor..cut_start]);
+        }
+        cursor = cut_end;
+    }
+
+    (compacted, TimeMap { regions })
+}
+
+/// The part of `samples` from the first to the last speech, with padding
+/// so the first and last words aren't clipped. Empty if there is no speech.
+pub fn speech_range(samples: &[f32]) -> Range<usize> {
+    let speech = detect_speech_frames(samples);
+    let (Some(first), Some(last)) =
+        (speech.iter().position(|&s| s), speech.iter().rposition(|&s| s))
+    else {
+        return 0..0;
+    };
+    let padding = PADDING_MS * SAMPLE_RATE / 1000;
+    let start = (first * FRAME_SAMPLES).saturating_sub(padding);
+    let end = ((last + 1) * FRAME_SAMPLES + padding).min(samples.len());
+    start..end
+}
+
+/// Root-mean-square level of `samples` (0.0 for empty input).
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// Classify each 20ms frame as speech (`true`) or silence.
+fn detect_speech_frames(samples: &[f32]) -> Vec<bool> {
+    let energies: Vec<f32> = samples.chunks(FRAME_SAMPLES).map(rms).collect();
+
+    if energies.is_empty() {
+        return Vec::new();
+    }
+
+    // Estimate the noise floor from the quietest 10% of frames
+    let mut sorted = energies.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let noise_floor = sorted[sorted.len() / 10];
+    let threshold = (noise_floor * NOISE_FLOOR_FACTOR).max(ABSOLUTE_SILENCE_RMS);
+
+    energies.iter().map(|&e| e > threshold).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(seconds: f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|i| (i as f32 * 0.05).sin() * 0.3)
+            .collect()
+    }
+
+    fn silence(seconds: f32) -> Vec<f32> {
+        vec![0.0; (seconds * SAMPLE_RATE as f32) as usize]
+    }
+
+    #[test]
+    fn test_short_pauses_are_kept() {
+        let audio = [tone(1.0), silence(0.5), tone(1.0)].concat();
+        let (compacted, map) = strip_silence(&audio);
+        assert_eq!(compacted.len(), audio.len());
+        assert_eq!(map.to_original_ms(1500), 1500);
+        assert_eq!(map.removed_samples(audio.len()), 0);
+    }
+
+    #[test]
+    fn test_long_silence_is_removed() {
+        let audio = [tone(1.0), silence(10.0), tone(1.0)].concat();
+        let (compacted, map) = strip_silence(&audio);
+
+        // 10s of silence minus 250ms padding on each side
+        let expected = audio.len() - (9.5 * SAMPLE_RATE as f32) as usize;
+        assert_eq!(compacted.len(), expected);
+        assert_eq!(map.removed_samples(audio.len()), audio.len() - expected);
+    }
+
+    #[test]
+    fn test_timestamps_map_back_to_original() {
+        let audio = [tone(1.0), silence(10.0), tone(1.0)].concat();
+        let (_, map) = strip_silence(&audio);
+
+        // Before the cut, timestamps are unchanged
+        assert_eq!(map.to_original_ms(500), 500);
+        // The second tone starts at 1.5s in compacted audio, 11s originally
+        assert_eq!(map.to_original_ms(1500), 11000);
+        assert_eq!(map.to_original_ms(2000), 11500);
+    }
+
+    #[test]
+    fn test_speech_range_pads_speech() {
+        let audio = [silence(4.0), tone(1.0), silence(2.0)].concat();
+        let range = speech_range(&audio);
+        // 250ms of padding on each side of the tone
+        assert_eq!(range, SAMPLE_RATE * 15 / 4..SAMPLE_RATE * 21 / 4);
+        let audio = [tone(1.0), silence(1.0)].concat();
+        assert_eq!(speech_range(&audio), 0..SAMPLE_RATE * 5 / 4);
+        assert!(speech_range(&silence(2.0)).is_empty());
+    }
+
+    #[test]
+    fn test_all_silence_is_left_untouched() {
+        let audio = silence(5.0);
+        let (compacted, _) = strip_silence(&audio);
+        assert_eq!(compacted.len(), audio.len());
+    }
+}
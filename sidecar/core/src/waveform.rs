@@ -0,0 +1,74 @@
+//! Waveform peaks for VoiceMark.
+//!
+//! Downsamples decoded audio to per-bucket amplitude peaks so clients can
+//! draw a waveform aligned with the transcript timestamps without decoding
+//! the audio themselves.
+
+use serde::Serialize;
+
+/// Sample rate of decoded audio.
+const SAMPLE_RATE: u32 = 16000;
+
+/// Highest supported peak resolution.
+pub const MAX_PEAKS_PER_SECOND: u32 = 1000;
+
+/// Downsampled amplitude envelope of an upload.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Waveform {
+    /// Number of peaks per second of audio.
+    pub peaks_per_second: u32,
+    /// Peak absolute amplitude (0.0-1.0) of each bucket, in order.
+    pub peaks: Vec<f32>,
+}
+
+/// Compute `peaks_per_second` peaks per second of 16kHz `samples`.
+///
+/// `peaks_per_second` is clamped to 1..=[`MAX_PEAKS_PER_SECOND`]. Peaks are
+/// rounded to three decimals to keep responses small.
+pub fn peaks(samples: &[f32], peaks_per_second: u32) -> Waveform {
+    let peaks_per_second = peaks_per_second.clamp(1, MAX_PEAKS_PER_SECOND);
+    let bucket = (SAMPLE_RATE / peaks_per_second) as usize;
+
+    let peaks = samples
+        .chunks(bucket)
+        .map(|chunk| {
+            let peak = chunk.iter().fold(0.0f32, |max, s| max.max(s.abs())).min(1.0);
+            (peak * 1000.0).round() / 1000.0
+        })
+        .collect();
+
+    Waveform {
+        peaks_per_second,
+        peaks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_peak_per_bucket() {
+        // 1 second of silence followed by 0.5s at half amplitude
+        let mut samples = vec![0.0; 16000];
+        samples.extend((0..8000).map(|i| if i % 2 == 0 { 0.5 } else { -0.5 }));
+
+        let waveform = peaks(&samples, 10);
+        assert_eq!(waveform.peaks_per_second, 10);
+        assert_eq!(waveform.peaks.len(), 15);
+        assert!(waveform.peaks[..10].iter().all(|&p| p == 0.0));
+        assert!(waveform.peaks[10..].iter().all(|&p| p == 0.5));
+    }
+
+    #[test]
+    fn test_partial_last_bucket_and_clamping() {
+        let samples = vec![0.25; 16000 + 10];
+        let waveform = peaks(&samples, 0);
+        assert_eq!(waveform.peaks_per_second, 1);
+        assert_eq!(waveform.peaks, vec![0.25, 0.25]);
+
+        let waveform = peaks(&samples, 1_000_000);
+        assert_eq!(waveform.peaks_per_second, MAX_PEAKS_PER_SECOND);
+    }
+}
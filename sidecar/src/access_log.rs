@@ -0,0 +1,318 @@
+//! Access log for VoiceMark sidecar.
+//!
+//! With `VOICEMARK_ACCESS_LOG`, every request and `/stream` session is
+//! recorded as one JSON line, separate from the diagnostic log, as an audit
+//! trail of transcription activity:
+//!
+//! ```json
+//! {"time":"2026-10-16T09:30:12.481Z","method":"POST","path":"/v1/transcribe","status":200,
+//!  "audio_ms":31500,"elapsed_ms":2210,"client_ip":"10.0.0.7","key_id":"k_3f9a","tenant":"acme"}
+//! ```
+//!
+//! A line is written once the request is done with: after the response for
+//! most endpoints, when the transcription finishes for `/transcribe/stream`,
+//! and when the WebSocket closes for `/stream` (status `101`, with the whole
+//! session's audio and duration). `audio_ms` is the audio transcribed for
+//! the request, as charged to API keys.
+//!
+//! The log goes to a file, rotated by size to `<file>.1`, `<file>.2`, ...,
+//! or to stdout with `VOICEMARK_ACCESS_LOG=stdout`.
+
+use anyhow::{Context, Result, bail};
+use axum::{
+    extract::{ConnectInfo, Request},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+use tracing::{error, info};
+
+use crate::clock::now_millis;
+use crate::tenants::{self, Tenant};
+
+/// Sample rate of transcribed audio.
+const SAMPLE_RATE: u64 = 16000;
+
+/// Default size at which the log file is rotated.
+pub const DEFAULT_MAX_MB: u64 = 100;
+
+/// Default number of rotated files kept.
+pub const DEFAULT_KEEP: usize = 5;
+
+/// Access log settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessLogConfig {
+    /// Log file, or `None` for stdout.
+    pub path: Option<PathBuf>,
+    /// Rotate the file when it would grow past this size; 0 never rotates.
+    pub max_bytes: u64,
+    /// Rotated files to keep.
+    pub keep: usize,
+}
+
+/// Where log lines go.
+enum Sink {
+    Stdout,
+    File(LogFile),
+}
+
+static LOG: OnceLock<Mutex<Sink>> = OnceLock::new();
+
+/// Enable the access log. Call once at startup.
+pub fn configure(config: AccessLogConfig) -> Result<()> {
+    let sink = match &config.path {
+        None => Sink::Stdout,
+        Some(path) => Sink::File(LogFile::open(path.clone(), config.max_bytes, config.keep)?),
+    };
+    info!(path = ?config.path, "Access log enabled");
+    if LOG.set(Mutex::new(sink)).is_err() {
+        bail!("Access log already configured");
+    }
+    Ok(())
+}
+
+/// Whether requests are logged.
+pub fn enabled() -> bool {
+    LOG.get().is_some()
+}
+
+/// A log file rotated by size.
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl LogFile {
+    fn open(path: PathBuf, max_bytes: u64, keep: usize) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let file = append(&path)?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(Self { path, file, size, max_bytes, keep })
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.max_bytes > 0 && self.size > 0 && self.size + len > self.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Shift `<file>.N` to `<file>.N+1`, dropping the oldest, and start a
+    /// new file.
+    fn rotate(&mut self) -> Result<()> {
+        if self.keep == 0 {
+            self.file.set_len(0)?;
+        } else {
+            let _ = std::fs::remove_file(rotated(&self.path, self.keep));
+            for n in (1..self.keep).rev() {
+                let _ = std::fs::rename(rotated(&self.path, n), rotated(&self.path, n + 1));
+            }
+            std::fs::rename(&self.path, rotated(&self.path, 1))
+                .with_context(|| format!("Failed to rotate {}", self.path.display()))?;
+            self.file = append(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn append(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+/// Path of the `n`th rotated file.
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// One line of the access log.
+#[derive(Debug, Serialize)]
+struct Line<'a> {
+    time: String,
+    method: &'a str,
+    path: &'a str,
+    status: u16,
+    audio_ms: u64,
+    elapsed_ms: u64,
+    client_ip: Option<String>,
+    /// `X-Forwarded-For`, when behind a proxy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forwarded_for: Option<&'a str>,
+    key_id: Option<&'a str>,
+    tenant: Option<&'a str>,
+}
+
+/// A request being logged, shared through request extensions with the
+/// handlers that transcribe audio. Its line is written when the last clone
+/// is dropped.
+#[derive(Debug, Clone)]
+pub struct Entry(Arc<EntryState>);
+
+#[derive(Debug)]
+struct EntryState {
+    started: Instant,
+    method: String,
+    path: String,
+    client_ip: Mutex<Option<String>>,
+    forwarded_for: Option<String>,
+    status: AtomicU16,
+    samples: AtomicU64,
+    tenant: OnceLock<Tenant>,
+}
+
+impl Entry {
+    /// Count `samples` of transcribed 16kHz audio for this request.
+    pub fn add_audio(&self, samples: usize) {
+        self.0.samples.fetch_add(samples as u64, Ordering::Relaxed);
+    }
+
+    /// Record the API key that made the request.
+    pub fn set_tenant(&self, tenant: &Tenant) {
+        let _ = self.0.tenant.set(tenant.clone());
+    }
+
+    /// Record the client a cluster coordinator proxied the request for.
+    pub fn set_client_ip(&self, ip: IpAddr) {
+        *self.0.client_ip.lock().unwrap() = Some(ip.to_string());
+    }
+}
+
+/// Count `samples` of transcribed audio against the request's log entry, if
+/// the access log is enabled.
+pub fn add_audio(entry: Option<&Entry>, samples: usize) {
+    if let Some(entry) = entry {
+        entry.add_audio(samples);
+    }
+}
+
+impl Drop for EntryState {
+    fn drop(&mut self) {
+        let Some(log) = LOG.get() else {
+            return;
+        };
+        let tenant = self.tenant.get();
+        let line = Line {
+            time: timestamp(now_millis()),
+            method: &self.method,
+            path: &self.path,
+            status: self.status.load(Ordering::Relaxed),
+            audio_ms: self.samples.load(Ordering::Relaxed) * 1000 / SAMPLE_RATE,
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            client_ip: self.client_ip.lock().unwrap().clone(),
+            forwarded_for: self.forwarded_for.as_deref(),
+            key_id: tenant.map(|t| t.key_id.as_str()),
+            tenant: tenant.map(|t| t.tenant.as_str()),
+        };
+        let Ok(line) = serde_json::to_string(&line) else {
+            return;
+        };
+        match &mut *log.lock().unwrap() {
+            Sink::Stdout => println!("{}", line),
+            Sink::File(file) => {
+                if let Err(e) = file.write_line(&line) {
+                    error!("Failed to write access log: {:#}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Access log middleware: starts an [`Entry`] for each request and records
+/// the response status.
+pub async fn record(mut request: Request, next: Next) -> Response {
+    if !enabled() {
+        return next.run(request).await;
+    }
+
+    let entry = Entry(Arc::new(EntryState {
+        started: Instant::now(),
+        method: request.method().to_string(),
+        path: request.uri().path().to_string(),
+        client_ip: Mutex::new(
+            request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string()),
+        ),
+        forwarded_for: request
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from),
+        status: AtomicU16::new(0),
+        samples: AtomicU64::new(0),
+        tenant: OnceLock::new(),
+    }));
+    request.extensions_mut().insert(entry.clone());
+
+    let response = next.run(request).await;
+    entry.0.status.store(response.status().as_u16(), Ordering::Relaxed);
+    response
+}
+
+/// UTC timestamp with milliseconds (`YYYY-MM-DDTHH:MM:SS.mmmZ`).
+pub(crate) fn timestamp(unix_millis: u64) -> String {
+    let secs = unix_millis / 1000 % 86400;
+    format!(
+        "{}T{:02}:{:02}:{:02}.{:03}Z",
+        tenants::utc_date(unix_millis / 1000),
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        unix_millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(timestamp(1_767_225_599_042), "2025-12-31T23:59:59.042Z");
+    }
+
+    #[test]
+    fn test_log_file_rotates_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("access.log");
+        let mut log = LogFile::open(path.clone(), 20, 2).unwrap();
+        for line in ["first line", "second line", "third line", "fourth line"] {
+            log.write_line(line).unwrap();
+        }
+
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), "fourth line\n");
+        assert_eq!(read(&rotated(&path, 1)), "third line\n");
+        assert_eq!(read(&rotated(&path, 2)), "second line\n");
+        assert!(!rotated(&path, 3).exists());
+
+        // Reopening appends to the current file
+        let mut log = LogFile::open(path.clone(), 0, 2).unwrap();
+        log.write_line("fifth line").unwrap();
+        assert_eq!(read(&path), "fourth line\nfifth line\n");
+    }
+}
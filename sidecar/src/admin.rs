@@ -0,0 +1,233 @@
+//! Admin API for VoiceMark sidecar.
+//!
+//! Manages API keys at runtime, so keys can be issued, rotated and revoked
+//! and quotas adjusted without restarting the server. Requests must carry
+//! `Authorization: Bearer <VOICEMARK_ADMIN_TOKEN>`; tenant API keys are
+//! refused with `403`.
+//!
+//! - `GET /admin/keys` - List keys (including revoked ones)
+//! - `POST /admin/keys` - Create a key
+//! - `DELETE /admin/keys/:id` - Revoke a key
+//! - `PUT /admin/keys/:id/quota` - Replace a key's quota
+//!
+//! The per-user export and erasure endpoints (see [`crate::users`]) reach
+//! every tenant's data, and `POST /model` (see [`crate::models`]) swaps the
+//! model every tenant is served by, so they require the admin token too.
+
+use axum::{
+    Json,
+    extract::{Path, Request, rejection::JsonRejection},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::error::{ApiError, Problem};
+use crate::tenants::{self, ApiKey, Quota};
+
+/// SHA-256 of the admin token, unset when the admin API is disabled.
+static TOKEN_HASH: OnceLock<[u8; 32]> = OnceLock::new();
+
+/// Body of `POST /admin/keys`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateKeyRequest {
+    pub tenant: String,
+    #[serde(flatten)]
+    pub quota: Quota,
+}
+
+/// A newly created key. `key` is only ever returned here.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedKey {
+    #[serde(flatten)]
+    pub info: ApiKey,
+    pub key: String,
+}
+
+/// Enable the admin API with `token`. Call once at startup.
+pub fn configure(token: &str) {
+    let _ = TOKEN_HASH.set(Sha256::digest(token.as_bytes()).into());
+    info!("Admin API enabled");
+}
+
+/// Whether `token` is the admin token.
+pub fn is_admin_token(token: &str) -> bool {
+    let actual: [u8; 32] = Sha256::digest(token.trim().as_bytes()).into();
+    TOKEN_HASH.get().is_some_and(|expected| actual == *expected)
+}
+
+/// Admin authentication middleware.
+pub async fn require_admin(request: Request, next: Next) -> Result<Response, ApiError> {
+    let expected = TOKEN_HASH
+        .get()
+        .ok_or_else(|| ApiError::Unauthorized("Admin API is not enabled".to_string()))?;
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| ApiError::Unauthorized("Missing admin token".to_string()))?;
+
+    check_token(expected, token, |key| {
+        tenants::db().is_some_and(|conn| tenants::is_valid_key(&conn, key))
+    })?;
+    Ok(next.run(request).await)
+}
+
+/// Check `token` against the admin token's hash `expected`: `403` for a
+/// tenant's API key (`is_tenant_key`), `401` for anything else.
+fn check_token(
+    expected: &[u8; 32],
+    token: &str,
+    is_tenant_key: impl FnOnce(&str) -> bool,
+) -> Result<(), ApiError> {
+    // Compare hashes so the comparison time doesn't depend on the token
+    let token = token.trim();
+    let actual: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+    if actual == *expected {
+        return Ok(());
+    }
+    if is_tenant_key(token) {
+        return Err(ApiError::Forbidden(
+            "API keys can't use the admin API; send the admin token".to_string(),
+        ));
+    }
+    Err(ApiError::Unauthorized("Invalid admin token".to_string()))
+}
+
+fn db() -> Result<std::sync::MutexGuard<'static, rusqlite::Connection>, ApiError> {
+    tenants::db().ok_or_else(|| {
+        ApiError::InvalidRequest("API keys are not enabled (set VOICEMARK_TENANTS_DB)".to_string())
+    })
+}
+
+fn db_error(e: rusqlite::Error) -> ApiError {
+    ApiError::Internal(format!("Tenant database error: {}", e))
+}
+
+/// Key listing endpoint (`GET /admin/keys`).
+#[utoipa::path(
+    get,
+    path = "/admin/keys",
+    tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "All keys, including revoked ones", body = Vec<ApiKey>),
+        (status = 401, description = "Missing or invalid admin token", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn list_keys() -> Result<Json<Vec<ApiKey>>, ApiError> {
+    let conn = db()?;
+    tenants::list_keys(&conn).map(Json).map_err(db_error)
+}
+
+/// Key creation endpoint (`POST /admin/keys`).
+#[utoipa::path(
+    post,
+    path = "/admin/keys",
+    tag = "admin",
+    security(("admin_token" = [])),
+    request_body = CreateKeyRequest,
+    responses(
+        (status = 201, description = "Key created; `key` is only returned here", body = CreatedKey),
+        (status = 400, description = "Invalid request", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid admin token", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn create_key(
+    payload: Result<Json<CreateKeyRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<CreatedKey>), ApiError> {
+    let Json(request) = payload?;
+    let tenant = request.tenant.trim();
+    if tenant.is_empty() {
+        return Err(ApiError::InvalidRequest("tenant must not be empty".to_string()));
+    }
+
+    let conn = db()?;
+    let (id, key) = tenants::create_key(&conn, tenant, &request.quota).map_err(db_error)?;
+    let info = tenants::get_key(&conn, &id)
+        .map_err(db_error)?
+        .ok_or_else(|| ApiError::Internal("Created key not found".to_string()))?;
+    info!(key_id = %id, tenant, "API key created");
+    Ok((StatusCode::CREATED, Json(CreatedKey { info, key })))
+}
+
+/// Key revocation endpoint (`DELETE /admin/keys/:id`).
+#[utoipa::path(
+    delete,
+    path = "/admin/keys/{id}",
+    tag = "admin",
+    security(("admin_token" = [])),
+    params(("id" = String, Path, description = "Key ID")),
+    responses(
+        (status = 200, description = "Revoked key", body = ApiKey),
+        (status = 401, description = "Missing or invalid admin token", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown key", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn revoke_key(Path(id): Path<String>) -> Result<Json<ApiKey>, ApiError> {
+    let conn = db()?;
+    if !tenants::revoke_key(&conn, &id).map_err(db_error)? {
+        return Err(ApiError::KeyNotFound(id));
+    }
+    info!(key_id = %id, "API key revoked");
+    tenants::get_key(&conn, &id)
+        .map_err(db_error)?
+        .map(Json)
+        .ok_or(ApiError::KeyNotFound(id))
+}
+
+/// Quota update endpoint (`PUT /admin/keys/:id/quota`).
+#[utoipa::path(
+    put,
+    path = "/admin/keys/{id}/quota",
+    tag = "admin",
+    security(("admin_token" = [])),
+    params(("id" = String, Path, description = "Key ID")),
+    request_body = Quota,
+    responses(
+        (status = 200, description = "Updated key", body = ApiKey),
+        (status = 400, description = "Invalid request", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid admin token", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown key", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn set_quota(
+    Path(id): Path<String>,
+    payload: Result<Json<Quota>, JsonRejection>,
+) -> Result<Json<ApiKey>, ApiError> {
+    let Json(quota) = payload?;
+    let conn = db()?;
+    if !tenants::set_quota(&conn, &id, &quota).map_err(db_error)? {
+        return Err(ApiError::KeyNotFound(id));
+    }
+    info!(key_id = %id, ?quota, "API key quota updated");
+    tenants::get_key(&conn, &id)
+        .map_err(db_error)?
+        .map(Json)
+        .ok_or(ApiError::KeyNotFound(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_keys_are_forbidden() {
+        let conn = tenants::open(std::path::Path::new(":memory:")).unwrap();
+        let (_, key) = tenants::create_key(&conn, "acme", &Quota::default()).unwrap();
+        let expected: [u8; 32] = Sha256::digest(b"admin-secret").into();
+        let check = |token: &str| {
+            check_token(&expected, token, |key| tenants::is_valid_key(&conn, key))
+        };
+
+        assert!(check("admin-secret").is_ok());
+        assert_eq!(check(&key).unwrap_err().status(), StatusCode::FORBIDDEN);
+        assert_eq!(check("guess").unwrap_err().status(), StatusCode::UNAUTHORIZED);
+    }
+}
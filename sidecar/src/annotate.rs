@@ -0,0 +1,267 @@
+//! Entity and keyword annotations for VoiceMark sidecar.
+//!
+//! Jobs submitted with `entities=true` have the LLM (see [`crate::llm`])
+//! name the people, organizations, places and dates in the transcript;
+//! `keywords=true` ranks its most frequent content words locally. Both are
+//! returned as spans with character offsets into the result's `text`, so
+//! clients can highlight them or build search facets without re-processing
+//! the transcript.
+//!
+//! Offsets count Unicode code points (like Python string indices), with an
+//! exclusive `end`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// Number of keywords returned.
+const MAX_KEYWORDS: usize = 10;
+
+/// Shortest word considered a keyword, in characters.
+const MIN_KEYWORD_LEN: usize = 3;
+
+/// English function words and fillers never reported as keywords.
+const STOPWORDS: &[&str] = &[
+    "about", "after", "again", "all", "also", "and", "any", "are", "because", "been", "before",
+    "being", "but", "can", "could", "did", "does", "doing", "don't", "down", "each", "even",
+    "for", "from", "get", "going", "gonna", "got", "had", "has", "have", "having", "her", "here",
+    "hers", "him", "his", "how", "i'm", "into", "it's", "its", "just", "know", "let's", "like",
+    "make", "mean", "more", "most", "much", "not", "now", "off", "okay", "one", "only", "other",
+    "our", "out", "over", "really", "right", "said", "say", "see", "she", "should", "some", "so",
+    "than", "that", "that's", "the", "their", "them", "then", "there", "these", "they", "thing",
+    "things", "think", "this", "those", "through", "too", "uh", "um", "under", "until", "very",
+    "want", "was", "way", "we're", "well", "were", "what", "when", "where", "which", "while",
+    "who", "why", "will", "with", "would", "yeah", "yes", "you", "you're", "your",
+];
+
+/// Kind of a named entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityType {
+    Person,
+    Organization,
+    Location,
+    Date,
+    Other,
+}
+
+impl EntityType {
+    /// Parse the LLM's name for a type; unknown names become `Other`.
+    pub fn from_name(name: &str) -> Self {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "person" | "people" | "per" => EntityType::Person,
+            "organization" | "organisation" | "org" | "company" => EntityType::Organization,
+            "location" | "place" | "loc" | "gpe" => EntityType::Location,
+            "date" | "time" => EntityType::Date,
+            _ => EntityType::Other,
+        }
+    }
+}
+
+/// One occurrence of a named entity in the text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Entity {
+    pub text: String,
+    #[serde(rename = "type")]
+    pub kind: EntityType,
+    /// Character offset of the first character.
+    pub start: usize,
+    /// Character offset just past the last character.
+    pub end: usize,
+}
+
+/// A character range in the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A frequent content word and where it occurs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Keyword {
+    /// The keyword, lowercased.
+    pub keyword: String,
+    /// Number of occurrences.
+    pub count: usize,
+    pub spans: Vec<Span>,
+}
+
+/// Locate each named entity in `text`.
+///
+/// Every whole-word occurrence is reported, matching case-sensitively if the
+/// name occurs that way at all and case-insensitively otherwise. Names that
+/// don't occur (the LLM made them up or reworded them) are dropped, and
+/// where occurrences overlap the longer one wins.
+pub fn locate_entities(text: &str, names: &[(String, EntityType)]) -> Vec<Entity> {
+    let offsets = CharOffsets::new(text);
+    let mut found: Vec<Entity> = Vec::new();
+    for (name, kind) in names {
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+        let mut matches = word_matches(text, name, false);
+        if matches.is_empty() {
+            matches = word_matches(text, name, true);
+        }
+        found.extend(matches.into_iter().map(|byte| Entity {
+            text: text[byte..byte + name.len()].to_string(),
+            kind: *kind,
+            start: offsets.get(byte),
+            end: offsets.get(byte + name.len()),
+        }));
+    }
+
+    found.sort_by_key(|entity| (entity.start, std::cmp::Reverse(entity.end)));
+    let mut entities: Vec<Entity> = Vec::with_capacity(found.len());
+    for entity in found {
+        if entities.last().is_none_or(|last| entity.start >= last.end) {
+            entities.push(entity);
+        }
+    }
+    entities
+}
+
+/// The most frequent content words of `text`, most frequent first (ties
+/// in order of first occurrence).
+pub fn keywords(text: &str) -> Vec<Keyword> {
+    let offsets = CharOffsets::new(text);
+    let mut keywords: Vec<Keyword> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for (byte, word) in words(text) {
+        let lower = word.to_lowercase();
+        if lower.chars().count() < MIN_KEYWORD_LEN
+            || lower.chars().all(|c| c.is_numeric())
+            || STOPWORDS.contains(&lower.as_str())
+        {
+            continue;
+        }
+        let span = Span {
+            start: offsets.get(byte),
+            end: offsets.get(byte + word.len()),
+        };
+        let i = *index.entry(lower.clone()).or_insert_with(|| {
+            keywords.push(Keyword { keyword: lower, count: 0, spans: Vec::new() });
+            keywords.len() - 1
+        });
+        keywords[i].count += 1;
+        keywords[i].spans.push(span);
+    }
+
+    // Stable, so ties keep their order of first occurrence
+    keywords.sort_by_key(|keyword| std::cmp::Reverse(keyword.count));
+    keywords.truncate(MAX_KEYWORDS);
+    keywords
+}
+
+/// Words of `text` (letters, digits and inner apostrophes) with their byte
+/// offsets.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '\'' || c == '’';
+    text.split(move |c: char| !is_word(c))
+        .filter(|word| !word.is_empty())
+        .map(move |word| {
+            let word = word.trim_matches(|c| c == '\'' || c == '’');
+            (word.as_ptr() as usize - text.as_ptr() as usize, word)
+        })
+        .filter(|(_, word)| !word.is_empty())
+}
+
+/// Byte offsets of the whole-word occurrences of `name` in `text`.
+fn word_matches(text: &str, name: &str, ignore_case: bool) -> Vec<usize> {
+    let is_boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+    let lower_name = name.to_lowercase();
+    let mut matches = Vec::new();
+    for (byte, _) in text.char_indices() {
+        let Some(candidate) = text.get(byte..byte + name.len()) else {
+            continue;
+        };
+        let equal = if ignore_case {
+            candidate.to_lowercase() == lower_name
+        } else {
+            candidate == name
+        };
+        if equal
+            && is_boundary(text[..byte].chars().next_back())
+            && is_boundary(text[byte + name.len()..].chars().next())
+        {
+            matches.push(byte);
+        }
+    }
+    matches
+}
+
+/// Converts byte offsets of a string to character offsets.
+struct CharOffsets {
+    /// Byte offset of each character, plus the string length.
+    bytes: Vec<usize>,
+}
+
+impl CharOffsets {
+    fn new(text: &str) -> Self {
+        let mut bytes: Vec<usize> = text.char_indices().map(|(byte, _)| byte).collect();
+        bytes.push(text.len());
+        Self { bytes }
+    }
+
+    /// Character offset of `byte`, which must be a character boundary.
+    fn get(&self, byte: usize) -> usize {
+        self.bytes.binary_search(&byte).unwrap_or_else(|i| i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locate_entities() {
+        let text = "Ana from Acme Corp met ANA in Zürich on Friday. Acme is growing.";
+        let names = vec![
+            ("Ana".to_string(), EntityType::Person),
+            ("Acme Corp".to_string(), EntityType::Organization),
+            ("Acme".to_string(), EntityType::Organization),
+            ("Zürich".to_string(), EntityType::Location),
+            ("friday".to_string(), EntityType::Date),
+            ("Bob".to_string(), EntityType::Person),
+        ];
+        let entities = locate_entities(text, &names);
+        let found: Vec<(&str, EntityType, usize, usize)> = entities
+            .iter()
+            .map(|e| (e.text.as_str(), e.kind, e.start, e.end))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("Ana", EntityType::Person, 0, 3),
+                ("Acme Corp", EntityType::Organization, 9, 18),
+                ("Zürich", EntityType::Location, 30, 36),
+                ("Friday", EntityType::Date, 40, 46),
+                ("Acme", EntityType::Organization, 48, 52),
+            ]
+        );
+        let chars: Vec<char> = text.chars().collect();
+        assert_eq!(chars[30..36].iter().collect::<String>(), "Zürich");
+    }
+
+    #[test]
+    fn test_keywords() {
+        let text = "The budget is tight. Um, the budget review moves to Q3; budget owners, review it. \
+                    We'll ship in 2025.";
+        let keywords = keywords(text);
+        assert_eq!(keywords[0].keyword, "budget");
+        assert_eq!(keywords[0].count, 3);
+        assert_eq!(keywords[0].spans[0], Span { start: 4, end: 10 });
+        assert_eq!(keywords[1].keyword, "review");
+        assert!(keywords.iter().all(|k| !["the", "um", "2025", "q3"].contains(&k.keyword.as_str())));
+    }
+
+    #[test]
+    fn test_entity_type_from_name() {
+        assert_eq!(EntityType::from_name("ORG"), EntityType::Organization);
+        assert_eq!(EntityType::from_name(" person "), EntityType::Person);
+        assert_eq!(EntityType::from_name("product"), EntityType::Other);
+    }
+}
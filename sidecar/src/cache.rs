@@ -0,0 +1,320 @@
+//! Transcription result cache for VoiceMark sidecar.
+//!
+//! Results are keyed by a SHA-256 of the uploaded bytes, the transcription
+//! options, and the model, so re-uploading the same recording returns
+//! instantly. Entries live in a bounded in-memory LRU and, optionally, as
+//! JSON files in a cache directory that survives restarts. Deleting a
+//! transcript removes its entry (see [`crate::transcripts::delete`]), and
+//! the cache directory is pruned by the transcript retention policy, since
+//! it holds the same text.
+
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use tracing::{debug, info, warn};
+
+use crate::encryption::{self, Kind};
+use crate::remote;
+use crate::transcribe::{TranscribeOptions, TranscribeResult};
+use crate::transcripts::TranscriptRetention;
+
+/// Default number of results kept in memory.
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// Bounded LRU of transcription results with an optional disk tier.
+struct ResultCache {
+    capacity: usize,
+    dir: Option<PathBuf>,
+    entries: HashMap<String, TranscribeResult>,
+    /// Keys from least to most recently used.
+    order: VecDeque<String>,
+}
+
+impl ResultCache {
+    fn new(capacity: usize, dir: Option<PathBuf>) -> Self {
+        Self {
+            capacity,
+            dir,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<TranscribeResult> {
+        if let Some(result) = self.entries.get(key).cloned() {
+            self.touch(key);
+            return Some(result);
+        }
+
+        // Fall back to disk, promoting hits into memory
+        let result = self.read_from_disk(key)?;
+        self.insert_in_memory(key, result.clone());
+        Some(result)
+    }
+
+    fn put(&mut self, key: &str, result: &TranscribeResult) {
+        if let Some(dir) = &self.dir {
+            let path = dir.join(format!("{}.json", key));
+            let written = std::fs::create_dir_all(dir)
+                .and_then(|_| encryption::write(&path, Kind::Cache, serde_json::to_vec(result)?));
+            if let Err(e) = written {
+                warn!(path = ?path, "Failed to write cache entry: {}", e);
+            }
+        }
+        self.insert_in_memory(key, result.clone());
+    }
+
+    fn insert_in_memory(&mut self, key: &str, result: TranscribeResult) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.insert(key.to_string(), result).is_some() {
+            self.touch(key);
+            return;
+        }
+        self.order.push_back(key.to_string());
+        while self.entries.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    /// Mark `key` as most recently used.
+    fn touch(&mut self, key: &str) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            if let Some(k) = self.order.remove(pos) {
+                self.order.push_back(k);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if self.entries.remove(key).is_some() {
+            self.order.retain(|k| k != key);
+        }
+        if let Some(dir) = &self.dir {
+            let path = dir.join(format!("{}.json", key));
+            if let Err(e) = std::fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!(path = ?path, "Failed to remove cache entry: {}", e);
+                }
+            }
+        }
+    }
+
+    fn read_from_disk(&self, key: &str) -> Option<TranscribeResult> {
+        let path = self.dir.as_ref()?.join(format!("{}.json", key));
+        let bytes = encryption::read(path, Kind::Cache).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+/// Global result cache.
+static CACHE: OnceLock<Mutex<ResultCache>> = OnceLock::new();
+
+fn cache() -> &'static Mutex<ResultCache> {
+    CACHE.get_or_init(|| Mutex::new(ResultCache::new(DEFAULT_CAPACITY, None)))
+}
+
+/// Configure the cache. Call once at startup, before any requests.
+///
+/// A `capacity` of 0 disables the in-memory tier; `dir` enables the disk tier.
+pub fn configure(capacity: usize, dir: Option<PathBuf>) {
+    if CACHE.set(Mutex::new(ResultCache::new(capacity, dir))).is_err() {
+        warn!("Result cache already configured");
+    }
+}
+
+/// Compute the cache key for an upload transcribed with `options`.
+pub fn cache_key(audio: &[u8], options: &TranscribeOptions) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(audio);
+    hasher.update(serde_json::to_vec(options).unwrap_or_default());
+    hasher.update(remote::model_id().unwrap_or_default());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Look up a cached result.
+pub fn get(key: &str) -> Option<TranscribeResult> {
+    let result = cache().lock().unwrap().get(key);
+    if result.is_some() {
+        debug!(key, "Result cache hit");
+    }
+    result
+}
+
+/// Store a result.
+pub fn put(key: &str, result: &TranscribeResult) {
+    cache().lock().unwrap().put(key, result);
+}
+
+/// Keys of the entries in the cache directory `dir` that violate
+/// `retention` at `now`: those written longer than `max_age` ago, then the
+/// oldest until the rest are within `max_bytes` and `max_count`.
+fn expired_keys(dir: &Path, retention: &TranscriptRetention, now: SystemTime) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<(String, SystemTime, u64)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok().filter(|meta| meta.is_file())?;
+            let key = entry.file_name().to_str()?.strip_suffix(".json")?.to_string();
+            Some((key, meta.modified().unwrap_or(now), meta.len()))
+        })
+        .collect();
+    files.sort_by_key(|(_, modified, _)| *modified);
+
+    let mut total: u64 = files.iter().map(|(_, _, len)| len).sum();
+    let mut count = files.len();
+    let mut expired = Vec::new();
+    for (key, modified, len) in files {
+        let too_old = retention
+            .max_age
+            .is_some_and(|max_age| now.duration_since(modified).unwrap_or_default() > max_age);
+        let over_budget = retention.max_bytes.is_some_and(|max| total > max);
+        let over_count = retention.max_count.is_some_and(|max| count > max);
+        if too_old || over_budget || over_count {
+            total -= len;
+            count -= 1;
+            expired.push(key);
+        }
+    }
+    expired
+}
+
+/// Delete the cached results that violate `retention`, on disk and in
+/// memory. Returns the number removed.
+pub fn enforce_retention(retention: &TranscriptRetention) -> usize {
+    let Some(dir) = cache().lock().unwrap().dir.clone() else {
+        return 0;
+    };
+    let expired = expired_keys(&dir, retention, SystemTime::now());
+    for key in &expired {
+        remove(key);
+    }
+    if !expired.is_empty() {
+        info!(removed = expired.len(), "Pruned cached results");
+    }
+    expired.len()
+}
+
+/// Forget a result, in memory and on disk.
+pub fn remove(key: &str) {
+    // Keys are hex digests; anything else could name a file elsewhere
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+        return;
+    }
+    cache().lock().unwrap().remove(key);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(text: &str) -> TranscribeResult {
+        TranscribeResult {
+            text: text.to_string(),
+            segments: 1,
+            avg_token_prob: 0.9,
+            timed_segments: Vec::new(),
+            words: Vec::new(),
+            tokens: Vec::new(),
+            language: None,
+            language_probability: None,
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let mut cache = ResultCache::new(2, None);
+        cache.put("a", &result("a"));
+        cache.put("b", &result("b"));
+
+        // Touch "a" so "b" becomes the eviction candidate
+        assert!(cache.get("a").is_some());
+        cache.put("c", &result("c"));
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn test_disk_tier_survives_memory_eviction() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = ResultCache::new(1, Some(dir.path().to_path_buf()));
+        cache.put("a", &result("first"));
+        cache.put("b", &result("second"));
+
+        // "a" was evicted from memory but is still on disk
+        assert!(!cache.entries.contains_key("a"));
+        assert_eq!(cache.get("a").unwrap().text, "first");
+    }
+
+    #[test]
+    fn test_remove_forgets_both_tiers() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = ResultCache::new(2, Some(dir.path().to_path_buf()));
+        cache.put("a", &result("a"));
+        cache.put("b", &result("b"));
+
+        cache.remove("a");
+        assert!(cache.get("a").is_none());
+        assert!(!dir.path().join("a.json").exists());
+        assert_eq!(cache.order, ["b"]);
+        assert!(cache.get("b").is_some());
+    }
+
+    #[test]
+    fn test_expired_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        for (key, age) in [("a", 3), ("b", 2), ("c", 1), ("d", 0)] {
+            let path = dir.path().join(format!("{}.json", key));
+            std::fs::write(&path, "{}").unwrap();
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(now - day * age).unwrap();
+        }
+        std::fs::write(dir.path().join("e.json.tmp"), "{}").unwrap();
+
+        let keys = |retention| expired_keys(dir.path(), &retention, now);
+        assert!(keys(TranscriptRetention::default()).is_empty());
+        let by_age = TranscriptRetention { max_age: Some(day + day / 2), ..Default::default() };
+        assert_eq!(keys(by_age), ["a", "b"]);
+        let by_count = TranscriptRetention { max_count: Some(1), ..Default::default() };
+        assert_eq!(keys(by_count), ["a", "b", "c"]);
+        let by_size = TranscriptRetention { max_bytes: Some(5), ..Default::default() };
+        assert_eq!(keys(by_size), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_zero_capacity_disables_memory_tier() {
+        let mut cache = ResultCache::new(0, None);
+        cache.put("a", &result("a"));
+        assert!(cache.get("a").is_none());
+    }
+
+    #[test]
+    fn test_cache_key_depends_on_options() {
+        let audio = b"RIFF....WAVE";
+        let plain = cache_key(audio, &TranscribeOptions::default());
+        let translated = cache_key(
+            audio,
+            &TranscribeOptions {
+                translate: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(plain, cache_key(audio, &TranscribeOptions::default()));
+        assert_ne!(plain, translated);
+    }
+}
@@ -0,0 +1,36 @@
+//! Wall-clock timestamps for VoiceMark sidecar.
+//!
+//! Stored and reported times are Unix timestamps, in milliseconds unless an
+//! external format wants seconds (JWT claims, quota periods). A clock set
+//! before 1970 reads as 0 rather than failing.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `time` in Unix milliseconds.
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// The current time in Unix milliseconds.
+pub fn now_millis() -> u64 {
+    unix_millis(SystemTime::now())
+}
+
+/// The current time in Unix seconds.
+pub fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_unix_timestamps() {
+        assert_eq!(unix_millis(UNIX_EPOCH + Duration::from_millis(1_500)), 1_500);
+        assert_eq!(unix_millis(UNIX_EPOCH - Duration::from_secs(1)), 0);
+        let (millis, secs) = (now_millis(), now_secs());
+        assert!(millis / 1000 <= secs && secs - millis / 1000 <= 1);
+    }
+}
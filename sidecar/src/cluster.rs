@@ -0,0 +1,793 @@
+//! Coordinator/worker mode for VoiceMark sidecar.
+//!
+//! One machine can't always keep up with a whole office's meetings, so
+//! several sidecars can share the load. Each worker
+//! (`VOICEMARK_CLUSTER_ROLE=worker`) registers with the coordinator at
+//! `VOICEMARK_COORDINATOR_URL` and reports its model and load every few
+//! seconds. The coordinator (`VOICEMARK_CLUSTER_ROLE=coordinator`) hands
+//! background jobs (`POST /jobs`) and streaming sessions (`/stream` and
+//! `/listen`) to the least busy worker whose model suits the requested
+//! language, and proxies them, so clients only ever talk to the coordinator.
+//! With no worker available, it serves them itself.
+//!
+//! - `POST /cluster/workers` - Worker heartbeat
+//! - `GET /cluster/workers` - Workers currently registered
+//!
+//! Sidecars authenticate to each other with
+//! `Authorization: Bearer <VOICEMARK_CLUSTER_TOKEN>`. The coordinator
+//! authenticates clients, checks their quota and claims their stream slots
+//! itself; their credentials are not passed on. Instead, proxied requests
+//! carry the cluster token, the tenant the coordinator authenticated
+//! ([`TENANT_HEADER`]) and the client's address ([`CLIENT_HEADER`]), which
+//! workers trust from the coordinator only. Workers report the audio they
+//! transcribe for those tenants with their next heartbeat, and the
+//! coordinator records it as the tenants' usage. Workers are reached over
+//! plain HTTP.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::{
+        ConnectInfo, FromRequestParts, Query, Request,
+        rejection::JsonRejection,
+        ws::{self, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, tungstenite};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::error::{ApiError, Problem};
+use crate::scheduler::{self, Load};
+use crate::tenants::{self, Tenant};
+use crate::{remote, stream};
+
+/// How often workers report to the coordinator.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Workers that haven't reported for this long are considered gone.
+const WORKER_TTL: Duration = Duration::from_secs(15);
+
+/// Jobs whose worker is remembered, oldest forgotten first.
+const MAX_JOB_ROUTES: usize = 10_000;
+
+/// Timeout for forwarding a job (the upload may be large).
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Request headers passed on to workers. Client credentials are not: the
+/// coordinator sends the cluster token instead.
+const FORWARDED_HEADERS: [HeaderName; 2] = [header::CONTENT_TYPE, header::ACCEPT];
+
+/// Header carrying the tenant the coordinator authenticated (base64 JSON).
+pub const TENANT_HEADER: &str = "x-voicemark-tenant";
+
+/// Header carrying the address of the client the coordinator proxies.
+pub const CLIENT_HEADER: &str = "x-voicemark-client";
+
+/// What this sidecar does in a cluster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Dispatch jobs and streams to the registered workers.
+    Coordinator,
+    /// Register with a coordinator and take its jobs and streams.
+    Worker,
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "coordinator" => Ok(Self::Coordinator),
+            "worker" => Ok(Self::Worker),
+            other => {
+                anyhow::bail!("Unknown cluster role {:?} (expected coordinator or worker)", other)
+            }
+        }
+    }
+}
+
+/// A worker's heartbeat: where to reach it, what it runs and how busy it is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct WorkerStatus {
+    /// Random ID, new each time the worker starts
+    pub id: String,
+    /// Base URL the coordinator reaches the worker at
+    pub url: String,
+    /// Whether the worker can transcribe (a model is loaded, or it falls
+    /// back to a remote API)
+    pub ready: bool,
+    /// Model in use, as reported by `GET /model`
+    pub model: Option<String>,
+    #[serde(flatten)]
+    pub load: Load,
+    /// Open streaming sessions
+    pub streams: usize,
+    /// Audio transcribed for the coordinator's tenants since the last
+    /// heartbeat
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub usage: Vec<Usage>,
+}
+
+/// Audio a worker transcribed for a tenant's key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Usage {
+    pub key_id: String,
+    /// Transcribed audio, in milliseconds
+    pub audio_ms: u64,
+}
+
+impl WorkerStatus {
+    /// Work per slot, running, queued and streaming.
+    fn busyness(&self) -> f64 {
+        let work = self.load.running + self.load.waiting + self.streams;
+        work as f64 / self.load.slots.max(1) as f64
+    }
+
+    /// Whether the model only transcribes English (`ggml-small.en.bin`).
+    fn english_only(&self) -> bool {
+        let Some(name) = self.model.as_deref().and_then(|m| Path::new(m).file_name()) else {
+            return false;
+        };
+        let name = name.to_string_lossy();
+        name.ends_with(".en") || name.contains(".en.")
+    }
+}
+
+/// The worker best placed to take work in `language`: among the ready
+/// workers, the least busy, preferring multilingual models for any
+/// language but English.
+fn pick<'a>(workers: &'a [WorkerStatus], language: Option<&str>) -> Option<&'a WorkerStatus> {
+    let multilingual = language.is_some_and(|l| !l.eq_ignore_ascii_case("en"));
+    workers.iter().filter(|w| w.ready).min_by(|a, b| {
+        (multilingual && a.english_only())
+            .cmp(&(multilingual && b.english_only()))
+            .then(a.busyness().total_cmp(&b.busyness()))
+    })
+}
+
+#[derive(Default)]
+struct Registry {
+    /// Workers by ID, with when they last reported
+    workers: HashMap<String, (WorkerStatus, Instant)>,
+    /// Base URL of the worker running each dispatched job
+    jobs: HashMap<String, String>,
+    /// Job IDs in dispatch order, to forget the oldest
+    job_order: VecDeque<String>,
+}
+
+struct Coordinator {
+    client: reqwest::Client,
+    /// Cluster token, sent to workers with each proxied request
+    token: String,
+    registry: Mutex<Registry>,
+}
+
+impl Coordinator {
+    /// Workers that have reported recently, other than `exclude`.
+    fn workers(&self, exclude: &[String]) -> Vec<WorkerStatus> {
+        let mut registry = self.registry.lock().unwrap();
+        registry.workers.retain(|_, (_, seen)| seen.elapsed() < WORKER_TTL);
+        registry
+            .workers
+            .values()
+            .filter(|(worker, _)| !exclude.contains(&worker.id))
+            .map(|(worker, _)| worker.clone())
+            .collect()
+    }
+
+    fn register(&self, worker: WorkerStatus) {
+        let mut registry = self.registry.lock().unwrap();
+        if !registry.workers.contains_key(&worker.id) {
+            info!(worker = %worker.url, model = ?worker.model, "Worker joined the cluster");
+        }
+        registry.workers.insert(worker.id.clone(), (worker, Instant::now()));
+    }
+
+    /// Stop dispatching to a worker that failed, until it reports again.
+    fn forget(&self, worker: &WorkerStatus) {
+        self.registry.lock().unwrap().workers.remove(&worker.id);
+    }
+
+    fn remember_job(&self, job_id: String, worker: &WorkerStatus) {
+        let mut registry = self.registry.lock().unwrap();
+        if registry.job_order.len() >= MAX_JOB_ROUTES {
+            if let Some(oldest) = registry.job_order.pop_front() {
+                registry.jobs.remove(&oldest);
+            }
+        }
+        registry.job_order.push_back(job_id.clone());
+        registry.jobs.insert(job_id, worker.url.clone());
+    }
+
+    fn job_worker(&self, job_id: &str) -> Option<String> {
+        self.registry.lock().unwrap().jobs.get(job_id).cloned()
+    }
+}
+
+/// SHA-256 of the cluster token, unset outside a cluster.
+static TOKEN_HASH: OnceLock<[u8; 32]> = OnceLock::new();
+
+/// Set on the coordinator.
+static COORDINATOR: OnceLock<Coordinator> = OnceLock::new();
+
+/// Set on workers: usage not yet reported to the coordinator.
+static UNREPORTED: OnceLock<Mutex<Vec<Usage>>> = OnceLock::new();
+
+/// Dispatch jobs and streams to the workers that register with `token`.
+/// Call once at startup.
+pub fn start_coordinator(token: &str) -> Result<()> {
+    let client = reqwest::Client::builder().timeout(FORWARD_TIMEOUT).build()?;
+    let token = token.trim().to_string();
+    let _ = TOKEN_HASH.set(Sha256::digest(token.as_bytes()).into());
+    let coordinator = Coordinator { client, token, registry: Mutex::default() };
+    if COORDINATOR.set(coordinator).is_err() {
+        anyhow::bail!("Cluster coordinator already started");
+    }
+    info!("Coordinating a cluster of workers");
+    Ok(())
+}
+
+/// Register with the coordinator at `coordinator_url` as reachable at
+/// `url`, and keep reporting this sidecar's load. Call once at startup,
+/// from the runtime.
+pub fn start_worker(coordinator_url: &str, token: &str, url: &str) -> Result<()> {
+    let endpoint = reqwest::Url::parse(coordinator_url.trim().trim_end_matches('/'))
+        .and_then(|base| reqwest::Url::parse(&format!("{}/v1/cluster/workers", base)))
+        .context("Invalid VOICEMARK_COORDINATOR_URL")?;
+    let url = url.trim().trim_end_matches('/').to_string();
+    reqwest::Url::parse(&url).context("Invalid VOICEMARK_ADVERTISE_URL")?;
+    let client = reqwest::Client::builder().timeout(HEARTBEAT_INTERVAL).build()?;
+    let token = token.trim().to_string();
+    let _ = TOKEN_HASH.set(Sha256::digest(token.as_bytes()).into());
+    let id = uuid::Uuid::new_v4().to_string();
+    let unreported = UNREPORTED.get_or_init(Mutex::default);
+    info!(coordinator = %endpoint, url, "Joining the cluster as a worker");
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        // Log changes only, not every heartbeat
+        let mut registered = None;
+        loop {
+            interval.tick().await;
+            let usage = std::mem::take(&mut *unreported.lock().unwrap());
+            let heartbeat = client
+                .post(endpoint.clone())
+                .bearer_auth(&token)
+                .json(&WorkerStatus { usage: usage.clone(), ..status(&id, &url) })
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if heartbeat.is_err() {
+                // Report it with the next heartbeat instead
+                unreported.lock().unwrap().splice(0..0, usage);
+            }
+            match heartbeat {
+                Ok(_) if registered != Some(true) => {
+                    info!("Registered with the coordinator");
+                    registered = Some(true);
+                }
+                Err(e) if registered != Some(false) => {
+                    warn!("Failed to report to the coordinator: {}", e);
+                    registered = Some(false);
+                }
+                _ => {}
+            }
+        }
+    });
+    Ok(())
+}
+
+/// This sidecar's heartbeat.
+fn status(id: &str, url: &str) -> WorkerStatus {
+    WorkerStatus {
+        id: id.to_string(),
+        url: url.to_string(),
+        ready: remote::backend().is_some(),
+        model: remote::model_id(),
+        // Remote-only transcription isn't scheduled; count it as one slot
+        load: scheduler::load().unwrap_or(Load { slots: 1, ..Load::default() }),
+        streams: stream::active_streams(),
+        usage: Vec::new(),
+    }
+}
+
+/// Whether this sidecar is a cluster worker.
+pub fn is_worker() -> bool {
+    UNREPORTED.get().is_some()
+}
+
+/// On a worker, report `audio_ms` transcribed for key `key_id` to the
+/// coordinator with the next heartbeat.
+pub fn report_usage(key_id: &str, audio_ms: u64) {
+    if let Some(unreported) = UNREPORTED.get() {
+        unreported.lock().unwrap().push(Usage { key_id: key_id.to_string(), audio_ms });
+    }
+}
+
+/// The bearer token of a request.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Whether `token` is the cluster token.
+fn is_cluster_token(expected: &[u8; 32], token: &str) -> bool {
+    // Compare hashes so the comparison time doesn't depend on the token
+    let actual: [u8; 32] = Sha256::digest(token.trim().as_bytes()).into();
+    actual == *expected
+}
+
+/// Cluster token authentication middleware.
+pub async fn require_token(request: Request, next: Next) -> Result<Response, ApiError> {
+    let expected = TOKEN_HASH
+        .get()
+        .ok_or_else(|| ApiError::Unauthorized("Cluster mode is not enabled".to_string()))?;
+    let token = bearer_token(request.headers())
+        .ok_or_else(|| ApiError::Unauthorized("Missing cluster token".to_string()))?;
+    if !is_cluster_token(expected, token) {
+        return Err(ApiError::Unauthorized("Invalid cluster token".to_string()));
+    }
+    Ok(next.run(request).await)
+}
+
+/// On a worker, whether the request was proxied by the coordinator (it
+/// carries the cluster token). The coordinator has authenticated the client
+/// already; see [`forwarded_tenant`].
+pub fn from_coordinator(headers: &HeaderMap) -> bool {
+    match (TOKEN_HASH.get(), bearer_token(headers)) {
+        (Some(expected), Some(token)) => {
+            COORDINATOR.get().is_none() && is_cluster_token(expected, token)
+        }
+        _ => false,
+    }
+}
+
+/// The tenant the coordinator authenticated for a proxied request, if any.
+pub fn forwarded_tenant(headers: &HeaderMap) -> Option<Tenant> {
+    let value = headers.get(TENANT_HEADER)?.to_str().ok()?;
+    serde_json::from_slice(&STANDARD.decode(value).ok()?).ok()
+}
+
+/// The address of the client the coordinator proxied a request for.
+pub fn forwarded_client(headers: &HeaderMap) -> Option<SocketAddr> {
+    headers.get(CLIENT_HEADER)?.to_str().ok()?.parse().ok()
+}
+
+fn coordinator() -> Result<&'static Coordinator, ApiError> {
+    COORDINATOR
+        .get()
+        .ok_or_else(|| ApiError::InvalidRequest("This sidecar is not a coordinator".to_string()))
+}
+
+/// Worker heartbeat endpoint (`POST /cluster/workers`).
+#[utoipa::path(
+    post,
+    path = "/cluster/workers",
+    tag = "cluster",
+    security(("cluster_token" = [])),
+    request_body = WorkerStatus,
+    responses(
+        (status = 204, description = "Heartbeat recorded"),
+        (status = 400, description = "Not a coordinator, or invalid heartbeat", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid cluster token", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn register_worker(
+    payload: Result<Json<WorkerStatus>, JsonRejection>,
+) -> Result<StatusCode, ApiError> {
+    let coordinator = coordinator()?;
+    let Json(mut worker) = payload?;
+    if reqwest::Url::parse(&worker.url).is_err() {
+        return Err(ApiError::InvalidRequest(format!("Invalid worker URL '{}'", worker.url)));
+    }
+    for usage in std::mem::take(&mut worker.usage) {
+        tenants::record_usage(&usage.key_id, usage.audio_ms);
+    }
+    coordinator.register(worker);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Worker listing endpoint (`GET /cluster/workers`).
+#[utoipa::path(
+    get,
+    path = "/cluster/workers",
+    tag = "cluster",
+    security(("cluster_token" = [])),
+    responses(
+        (status = 200, description = "Workers that reported recently", body = Vec<WorkerStatus>),
+        (status = 400, description = "Not a coordinator", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid cluster token", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn list_workers() -> Result<Json<Vec<WorkerStatus>>, ApiError> {
+    let mut workers = coordinator()?.workers(&[]);
+    workers.sort_by(|a, b| a.url.cmp(&b.url));
+    Ok(Json(workers))
+}
+
+/// Dispatch middleware: on the coordinator, jobs and streams go to workers.
+pub async fn dispatch(request: Request, next: Next) -> Response {
+    let Some(coordinator) = COORDINATOR.get() else {
+        return next.run(request).await;
+    };
+    let path = request.uri().path();
+    match *request.method() {
+        Method::POST if path == "/jobs" => dispatch_job(coordinator, request, next).await,
+        Method::GET if path == "/stream" || path == "/listen" => dispatch_stream(coordinator, request, next).await,
+        Method::GET if path.starts_with("/jobs/") => {
+            match coordinator.job_worker(&path["/jobs/".len()..]) {
+                Some(url) => job_status(coordinator, &url, request).await,
+                // Submitted before a worker joined, or forgotten
+                None => next.run(request).await,
+            }
+        }
+        _ => next.run(request).await,
+    }
+}
+
+/// Requested language, to pick a worker with a suitable model.
+fn language(uri: &Uri) -> Option<String> {
+    let Query(query) = Query::<HashMap<String, String>>::try_from_uri(uri).ok()?;
+    query.get("language").cloned().filter(|l| !l.is_empty() && l != "auto")
+}
+
+/// `uri` on the worker at `base`, without the client's `api_key`.
+fn worker_url(base: &str, uri: &Uri) -> String {
+    let query = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty() && !pair.starts_with("api_key="))
+        .collect::<Vec<_>>()
+        .join("&");
+    let base = base.trim_end_matches('/');
+    if query.is_empty() {
+        format!("{}/v1{}", base, uri.path())
+    } else {
+        format!("{}/v1{}?{}", base, uri.path(), query)
+    }
+}
+
+/// Headers for proxying a request with `headers` from `tenant` at `client`
+/// to a worker.
+fn forwarded_headers(
+    headers: &HeaderMap,
+    token: &str,
+    tenant: Option<&Tenant>,
+    client: Option<SocketAddr>,
+) -> HeaderMap {
+    let mut forwarded = HeaderMap::new();
+    for name in &FORWARDED_HEADERS {
+        if let Some(value) = headers.get(name) {
+            forwarded.insert(name.clone(), value.clone());
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&format!("Bearer {}", token)) {
+        forwarded.insert(header::AUTHORIZATION, value);
+    }
+    let tenant = tenant.and_then(|tenant| serde_json::to_vec(tenant).ok());
+    if let Some(value) = tenant.and_then(|json| HeaderValue::from_str(&STANDARD.encode(json)).ok())
+    {
+        forwarded.insert(TENANT_HEADER, value);
+    }
+    if let Some(value) = client.and_then(|addr| HeaderValue::from_str(&addr.to_string()).ok()) {
+        forwarded.insert(CLIENT_HEADER, value);
+    }
+    forwarded
+}
+
+/// Headers for proxying `request` to a worker.
+fn proxy_headers(coordinator: &Coordinator, request: &Request) -> HeaderMap {
+    let tenant = request.extensions().get::<Tenant>();
+    forwarded_headers(request.headers(), &coordinator.token, tenant, peer(request))
+}
+
+/// Address of the client that sent `request`.
+fn peer(request: &Request) -> Option<SocketAddr> {
+    request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| *addr)
+}
+
+async fn forward(
+    coordinator: &Coordinator,
+    method: Method,
+    url: String,
+    headers: HeaderMap,
+    body: Bytes,
+) -> reqwest::Result<(StatusCode, HeaderMap, Bytes)> {
+    let response =
+        coordinator.client.request(method, url).headers(headers).body(body).send().await?;
+    let status = response.status();
+    let mut headers = HeaderMap::new();
+    for name in [header::CONTENT_TYPE, header::RETRY_AFTER] {
+        if let Some(value) = response.headers().get(&name) {
+            headers.insert(name, value.clone());
+        }
+    }
+    Ok((status, headers, response.bytes().await?))
+}
+
+/// Submit a job to the least busy worker, or run it here if none can.
+async fn dispatch_job(coordinator: &Coordinator, request: Request, next: Next) -> Response {
+    let headers = proxy_headers(coordinator, &request);
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, crate::MAX_BODY_BYTES).await {
+        Ok(body) => body,
+        Err(_) => return ApiError::AudioTooLarge.into_response(),
+    };
+    let language = language(&parts.uri);
+
+    let mut tried = Vec::new();
+    loop {
+        let workers = coordinator.workers(&tried);
+        let Some(worker) = pick(&workers, language.as_deref()) else {
+            break;
+        };
+        let url = worker_url(&worker.url, &parts.uri);
+        match forward(coordinator, Method::POST, url, headers.clone(), body.clone()).await {
+            Ok((status, headers, body)) => {
+                if status.is_success() {
+                    let reply: serde_json::Value =
+                        serde_json::from_slice(&body).unwrap_or_default();
+                    if let Some(job_id) = reply["id"].as_str() {
+                        info!(job_id, worker = %worker.url, "Dispatched job");
+                        coordinator.remember_job(job_id.to_string(), worker);
+                    }
+                }
+                return (status, headers, body).into_response();
+            }
+            Err(e) => {
+                warn!(worker = %worker.url, "Failed to dispatch job: {}", e);
+                coordinator.forget(worker);
+                tried.push(worker.id.clone());
+            }
+        }
+    }
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+/// Status of a job, from the worker running it.
+async fn job_status(coordinator: &Coordinator, url: &str, request: Request) -> Response {
+    let url = worker_url(url, request.uri());
+    let headers = proxy_headers(coordinator, &request);
+    match forward(coordinator, Method::GET, url, headers, Bytes::new()).await {
+        Ok(response) => response.into_response(),
+        Err(e) => ApiError::WorkerUnreachable(format!(
+            "The worker running this job is unreachable: {}",
+            e
+        ))
+        .into_response(),
+    }
+}
+
+type Upstream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Connect the client's stream to the least busy worker, or serve it here if
+/// none can take it. The client's stream slots are claimed here either way,
+/// so its limits hold across workers.
+async fn dispatch_stream(coordinator: &Coordinator, request: Request, next: Next) -> Response {
+    let slots = match stream::open_slots(request.extensions().get::<Tenant>(), peer(&request)) {
+        Ok(slots) => slots,
+        Err(e) => return e.into_response(),
+    };
+    let language = language(request.uri());
+    let headers = proxy_headers(coordinator, &request);
+    let mut tried = Vec::new();
+    loop {
+        let workers = coordinator.workers(&tried);
+        let Some(worker) = pick(&workers, language.as_deref()) else {
+            break;
+        };
+        match connect_worker(worker, request.uri(), &headers).await {
+            Ok(upstream) => {
+                info!(worker = %worker.url, "Dispatched stream");
+                let (mut parts, _) = request.into_parts();
+                return match WebSocketUpgrade::from_request_parts(&mut parts, &()).await {
+                    Ok(upgrade) => upgrade.on_upgrade(|socket| async move {
+                        relay(socket, upstream).await;
+                        drop(slots);
+                    }),
+                    Err(rejection) => rejection.into_response(),
+                };
+            }
+            // The worker refused the stream (bad query, quota); tell the client
+            Err(tungstenite::Error::Http(response)) => {
+                let (parts, body) = response.into_parts();
+                let mut headers = HeaderMap::new();
+                if let Some(content_type) = parts.headers.get(header::CONTENT_TYPE) {
+                    headers.insert(header::CONTENT_TYPE, content_type.clone());
+                }
+                return (parts.status, headers, body.unwrap_or_default()).into_response();
+            }
+            Err(e) => {
+                warn!(worker = %worker.url, "Failed to dispatch stream: {}", e);
+                coordinator.forget(worker);
+                tried.push(worker.id.clone());
+            }
+        }
+    }
+    // The stream handler claims them again
+    drop(slots);
+    next.run(request).await
+}
+
+async fn connect_worker(
+    worker: &WorkerStatus,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> tungstenite::Result<Upstream> {
+    use tungstenite::client::IntoClientRequest;
+
+    let url = worker_url(&worker.url, uri).replacen("http", "ws", 1);
+    let mut upstream = url.into_client_request()?;
+    upstream.headers_mut().extend(headers.clone());
+    let (socket, _) =
+        tokio::time::timeout(HEARTBEAT_INTERVAL, tokio_tungstenite::connect_async(upstream))
+            .await
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+    Ok(socket)
+}
+
+/// Pass messages between the client and the worker until either side closes.
+async fn relay(client: WebSocket, upstream: Upstream) {
+    let (mut client_tx, mut client_rx) = client.split();
+    let (mut worker_tx, mut worker_rx) = upstream.split();
+
+    let to_worker = async {
+        while let Some(Ok(message)) = client_rx.next().await {
+            let message = match message {
+                ws::Message::Text(text) => tungstenite::Message::Text(text),
+                ws::Message::Binary(data) => tungstenite::Message::Binary(data),
+                ws::Message::Close(_) => break,
+                ws::Message::Ping(_) | ws::Message::Pong(_) => continue,
+            };
+            if worker_tx.send(message).await.is_err() {
+                return;
+            }
+        }
+        let _ = worker_tx.close().await;
+    };
+    let to_client = async {
+        while let Some(Ok(message)) = worker_rx.next().await {
+            let message = match message {
+                tungstenite::Message::Text(text) => ws::Message::Text(text),
+                tungstenite::Message::Binary(data) => ws::Message::Binary(data),
+                tungstenite::Message::Close(_) => break,
+                _ => continue,
+            };
+            if client_tx.send(message).await.is_err() {
+                return;
+            }
+        }
+        let _ = client_tx.close().await;
+    };
+    tokio::select! {
+        _ = to_worker => {}
+        _ = to_client => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tenants::Quota;
+
+    fn worker(id: &str, model: &str, running: usize, streams: usize) -> WorkerStatus {
+        WorkerStatus {
+            id: id.to_string(),
+            url: format!("http://{}:3001", id),
+            ready: true,
+            model: Some(model.to_string()),
+            load: Load { slots: 2, running, waiting: 0 },
+            streams,
+            usage: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_role_parse() {
+        assert_eq!("coordinator".parse::<Role>().unwrap(), Role::Coordinator);
+        assert_eq!(" worker ".parse::<Role>().unwrap(), Role::Worker);
+        assert!("leader".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn test_pick_least_busy_ready_worker() {
+        let mut idle = worker("idle", "/models/ggml-small.bin", 0, 0);
+        let busy = worker("busy", "/models/ggml-small.bin", 2, 1);
+        let streaming = worker("streaming", "/models/ggml-small.bin", 0, 1);
+        let workers = vec![busy.clone(), idle.clone(), streaming.clone()];
+        assert_eq!(pick(&workers, None).unwrap().id, "idle");
+
+        idle.ready = false;
+        let workers = vec![busy, idle, streaming];
+        assert_eq!(pick(&workers, None).unwrap().id, "streaming");
+        assert!(pick(&[], None).is_none());
+    }
+
+    #[test]
+    fn test_pick_prefers_multilingual_models() {
+        let english = worker("english", "/models/ggml-small.en.bin", 0, 0);
+        let multilingual = worker("multilingual", "/models/ggml-small.bin", 1, 0);
+        let workers = vec![english.clone(), multilingual];
+        assert!(english.english_only());
+        assert_eq!(pick(&workers, Some("de")).unwrap().id, "multilingual");
+        assert_eq!(pick(&workers, Some("en")).unwrap().id, "english");
+        assert_eq!(pick(&workers, None).unwrap().id, "english");
+
+        // Better an English-only model than none at all
+        assert_eq!(pick(&workers[..1], Some("de")).unwrap().id, "english");
+    }
+
+    #[test]
+    fn test_worker_url_keeps_query() {
+        let uri: Uri = "/stream?language=de&api_key=k&model=x".parse().unwrap();
+        assert_eq!(
+            worker_url("http://10.0.0.2:3001/", &uri),
+            "http://10.0.0.2:3001/v1/stream?language=de&model=x"
+        );
+        assert_eq!(language(&uri).as_deref(), Some("de"));
+        assert_eq!(language(&"/jobs?language=auto".parse().unwrap()), None);
+        let uri: Uri = "/jobs?api_key=k".parse().unwrap();
+        assert_eq!(worker_url("http://w", &uri), "http://w/v1/jobs");
+    }
+
+    #[test]
+    fn test_forwarded_headers_replace_client_credentials() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer client-key"));
+        headers.insert("x-api-key", HeaderValue::from_static("client-key"));
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("audio/wav"));
+        let tenant = Tenant {
+            key_id: "k-1".to_string(),
+            tenant: "acme".to_string(),
+            quota: Quota { audio_seconds_per_day: None, max_concurrent_streams: Some(2) },
+        };
+
+        let client: SocketAddr = "10.0.0.7:51234".parse().unwrap();
+        let forwarded = forwarded_headers(&headers, "s3cret", Some(&tenant), Some(client));
+        assert_eq!(forwarded[header::AUTHORIZATION], "Bearer s3cret");
+        assert!(!forwarded.contains_key("x-api-key"));
+        assert_eq!(forwarded[header::CONTENT_TYPE], "audio/wav");
+        let received = forwarded_tenant(&forwarded).unwrap();
+        assert_eq!((received.key_id.as_str(), received.tenant.as_str()), ("k-1", "acme"));
+        assert_eq!(received.quota, tenant.quota);
+        assert_eq!(forwarded_client(&forwarded), Some(client));
+
+        let anonymous = forwarded_headers(&headers, "s3cret", None, None);
+        assert!(forwarded_tenant(&anonymous).is_none());
+        assert!(forwarded_client(&anonymous).is_none());
+    }
+
+    #[test]
+    fn test_status_serializes_flat_load() {
+        let json = serde_json::to_value(worker("a", "m.bin", 1, 2)).unwrap();
+        assert_eq!(json["slots"], 2);
+        assert_eq!(json["running"], 1);
+        assert_eq!(json["streams"], 2);
+        assert!(json.get("usage").is_none());
+
+        let mut status = worker("a", "m.bin", 0, 0);
+        status.usage = vec![Usage { key_id: "k-1".to_string(), audio_ms: 31_500 }];
+        let json = serde_json::to_string(&status).unwrap();
+        assert_eq!(serde_json::from_str::<WorkerStatus>(&json).unwrap(), status);
+    }
+}
@@ -0,0 +1,242 @@
+//! Model comparison for VoiceMark sidecar.
+//!
+//! `POST /compare` transcribes one upload with several local models and
+//! returns their results side by side, with timings and a word-level diff
+//! against the first model (see [`crate::wordiff`]), to help choose a model
+//! size per device class.
+//!
+//! The models are the configured one plus those listed in
+//! `VOICEMARK_COMPARE_MODELS`. They are loaded on first use and unloaded
+//! with the configured model when idle (`VOICEMARK_IDLE_UNLOAD_MINS`).
+
+use axum::{
+    Extension, Json,
+    extract::{Query, rejection::QueryRejection},
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Instant;
+use tracing::{info, instrument, warn};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::access_log::{self, Entry};
+use crate::error::{ApiError, Problem};
+use crate::scheduler::{self, Priority};
+use crate::tenants::{self, Tenant};
+use crate::transcribe::{self, DecodingParams, Segmentation, TranscribeOptions};
+use crate::upload::{AudioFile, AudioUpload, UploadForm};
+use crate::wordiff::{self, DiffChunk};
+use crate::BatchQuery;
+
+/// Audio sample rate, for real-time factors.
+const SAMPLE_RATE: u64 = 16000;
+
+/// Model paths from `VOICEMARK_COMPARE_MODELS`.
+static MODELS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Set the models offered for comparison besides the configured one. Call
+/// once at startup.
+pub fn configure(models: Vec<String>) {
+    info!(?models, "Comparison models configured");
+    if MODELS.set(models).is_err() {
+        warn!("Comparison models already configured");
+    }
+}
+
+/// Query parameters of `POST /compare`, besides [`BatchQuery`].
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CompareQuery {
+    /// Comma-separated model file names to compare, in order; the first is
+    /// the baseline. Defaults to the configured model, then those in
+    /// `VOICEMARK_COMPARE_MODELS`.
+    models: Option<String>,
+}
+
+/// Response of `POST /compare`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CompareResponse {
+    /// Length of the audio, in milliseconds.
+    duration_ms: u64,
+    /// One result per model, in the requested order.
+    results: Vec<ModelResult>,
+}
+
+/// One model's transcription of the compared audio.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ModelResult {
+    /// Model file name.
+    model: String,
+    text: String,
+    segments: usize,
+    /// Time spent loading the model for this request; 0 if it was resident.
+    load_ms: u64,
+    /// Transcription time, in milliseconds.
+    elapsed_ms: u64,
+    /// Real-time factor: transcription time over audio duration (below 1 is
+    /// faster than real time).
+    rtf: f64,
+    /// Word-level differences from the first result; omitted for the first
+    /// result itself, and for transcripts too long to align.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<Vec<DiffChunk>>,
+}
+
+/// Models that can be compared, as (file name, path).
+fn available_models() -> Vec<(String, String)> {
+    let configured = transcribe::model_path().filter(|_| transcribe::is_model_loaded());
+    let mut models: Vec<(String, String)> = Vec::new();
+    for path in configured.iter().chain(MODELS.get().into_iter().flatten()) {
+        let name = Path::new(path)
+            .file_name()
+            .map_or_else(|| path.clone(), |name| name.to_string_lossy().into_owned());
+        if !models.iter().any(|(_, p)| p == path) {
+            models.push((name, path.clone()));
+        }
+    }
+    models
+}
+
+/// Resolve the requested model names to paths.
+fn select_models(requested: Option<&str>) -> Result<Vec<(String, String)>, ApiError> {
+    let available = available_models();
+    let selected = match requested {
+        None => available.clone(),
+        Some(names) => names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                available.iter().find(|(n, _)| n == name).cloned().ok_or_else(|| {
+                    let names: Vec<&str> = available.iter().map(|(n, _)| n.as_str()).collect();
+                    ApiError::InvalidRequest(format!(
+                        "Unknown model '{}'; available: {}",
+                        name,
+                        names.join(", ")
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    if selected.len() < 2 {
+        return Err(ApiError::InvalidRequest(format!(
+            "Comparing needs at least two models; {} available (set VOICEMARK_COMPARE_MODELS)",
+            available.len()
+        )));
+    }
+    Ok(selected)
+}
+
+/// Model comparison endpoint (`POST /compare`).
+///
+/// Accepts the same uploads and options as `/transcribe` and transcribes
+/// the audio with each model in turn. Every model's transcription is
+/// charged to the API key.
+#[utoipa::path(
+    post,
+    path = "/compare",
+    tag = "transcription",
+    params(CompareQuery, BatchQuery, DecodingParams, Segmentation),
+    request_body(
+        description = "Audio as a multipart form, or as the raw request body",
+        content(
+            (UploadForm = "multipart/form-data"),
+            (AudioFile = "audio/*"),
+            (AudioFile = "video/*"),
+            (AudioFile = "application/octet-stream"),
+        ),
+    ),
+    responses(
+        (status = 200, description = "Results per model", body = CompareResponse),
+        (status = 400, description = "Invalid request, unknown model, or fewer than two models", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 402, description = "Daily audio quota used up", body = Problem, content_type = "application/problem+json"),
+        (status = 413, description = "Upload too large", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "Unsupported request content type", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Unsupported or corrupt audio", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "A model failed to load or transcribe", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "ffmpeg needed but unavailable", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+#[instrument(skip_all)]
+pub async fn compare_models(
+    tenant: Option<Extension<Tenant>>,
+    entry: Option<Extension<Entry>>,
+    query: Result<Query<CompareQuery>, QueryRejection>,
+    batch: Result<Query<BatchQuery>, QueryRejection>,
+    decoding: Result<Query<DecodingParams>, QueryRejection>,
+    segmentation: Result<Query<Segmentation>, QueryRejection>,
+    upload: AudioUpload,
+) -> Result<Json<CompareResponse>, ApiError> {
+    let Query(query) = query?;
+    let Query(batch) = batch?;
+    let Query(decoding) = decoding?;
+    let Query(segmentation) = segmentation?;
+    let AudioUpload(audio_bytes) = upload;
+    let models = select_models(query.models.as_deref())?;
+    let options = crate::batch_options(batch, decoding, segmentation)?;
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    let entry = entry.map(|Extension(entry)| entry);
+
+    tokio::task::spawn_blocking(move || {
+        let samples = crate::decode_upload(&audio_bytes, None, &options)?;
+        compare(tenant.as_ref(), entry.as_ref(), &samples, &models, options).map(Json)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))?
+}
+
+/// Transcribe `samples` with each model. Blocks.
+fn compare(
+    tenant: Option<&Tenant>,
+    entry: Option<&Entry>,
+    samples: &[f32],
+    models: &[(String, String)],
+    options: TranscribeOptions,
+) -> Result<CompareResponse, ApiError> {
+    let duration_ms = samples.len() as u64 * 1000 / SAMPLE_RATE;
+    let mut results: Vec<ModelResult> = Vec::with_capacity(models.len());
+    for (name, path) in models {
+        let started = Instant::now();
+        let loaded = transcribe::load_model(path)
+            .map_err(|e| ApiError::TranscriptionFailed(format!("{}: {:#}", name, e)))?;
+        let load_ms = if loaded { started.elapsed().as_millis() as u64 } else { 0 };
+
+        let permit = scheduler::acquire(Priority::Batch);
+        let started = Instant::now();
+        let result = transcribe::transcribe_with_model(path, samples, options.clone())
+            .map_err(|e| ApiError::TranscriptionFailed(format!("{}: {:#}", name, e)))?;
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        drop(permit);
+        tenants::charge(tenant, samples.len());
+        access_log::add_audio(entry, samples.len());
+        info!(model = %name, elapsed_ms, load_ms, "Comparison transcription complete");
+
+        let diff = results.first().and_then(|baseline| wordiff::diff(&baseline.text, &result.text));
+        results.push(ModelResult {
+            model: name.clone(),
+            text: result.text,
+            segments: result.segments,
+            load_ms,
+            elapsed_ms,
+            rtf: if duration_ms > 0 { elapsed_ms as f64 / duration_ms as f64 } else { 0.0 },
+            diff,
+        });
+    }
+    Ok(CompareResponse { duration_ms, results })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_models_needs_two() {
+        // No model is loaded in tests, and no comparison models configured
+        let err = select_models(None).unwrap_err();
+        assert_eq!(err.code(), "invalid_request");
+        let err = select_models(Some("ggml-tiny.bin")).unwrap_err();
+        assert!(err.to_string().contains("Unknown model 'ggml-tiny.bin'"));
+    }
+}
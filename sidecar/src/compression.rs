@@ -0,0 +1,224 @@
+//! Response compression.
+//!
+//! Transcripts with word timestamps are multi-megabyte JSON, slow to fetch
+//! over poor links but compressing roughly tenfold. Responses are therefore
+//! compressed with brotli, gzip or deflate, whichever the client's
+//! `Accept-Encoding` prefers (brotli when it accepts several equally). Small
+//! bodies, bodies of unknown length (Server-Sent Events), audio and WebSocket
+//! upgrades are sent as is.
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::Request,
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use flate2::Compression;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use std::io::Write;
+use tracing::warn;
+
+/// Bodies smaller than this aren't worth compressing.
+const MIN_SIZE: u64 = 1024;
+
+/// Brotli quality (0-11). Higher levels are several times slower for a few
+/// percent, too slow for responses built on every request.
+const BROTLI_QUALITY: u32 = 5;
+
+/// Brotli window size (log2 bytes), the encoder's default.
+const BROTLI_WINDOW: u32 = 22;
+
+/// Brotli encoder buffer size, in bytes.
+const BROTLI_BUFFER: usize = 4096;
+
+/// A content coding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    /// In order of preference when the client accepts both equally.
+    const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    fn encode(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(
+                    Vec::new(),
+                    BROTLI_BUFFER,
+                    BROTLI_QUALITY,
+                    BROTLI_WINDOW,
+                );
+                encoder.write_all(data)?;
+                // Finishes the stream
+                Ok(encoder.into_inner())
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Encoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    /// The coding `accept_encoding` prefers, or `None` for no compression.
+    fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+        let codings: Vec<(String, f32)> = accept_encoding
+            .split(',')
+            .filter_map(|coding| {
+                let mut params = coding.split(';');
+                let name = params.next()?.trim().to_ascii_lowercase();
+                let q = params
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                Some((name, q))
+            })
+            .collect();
+        let quality = |encoding: Encoding| {
+            let named = codings.iter().find(|(name, _)| name == encoding.name());
+            named.or_else(|| codings.iter().find(|(name, _)| name == "*")).map(|(_, q)| *q)
+        };
+        Encoding::ALL
+            .into_iter()
+            .filter_map(|encoding| Some((encoding, quality(encoding).filter(|q| *q > 0.0)?)))
+            .reduce(|best, next| if next.1 > best.1 { next } else { best })
+            .map(|(encoding, _)| encoding)
+    }
+}
+
+/// Whether `response` is worth compressing.
+fn compressible(response: &Response) -> bool {
+    if response.status() == StatusCode::SWITCHING_PROTOCOLS
+        || response.status() == StatusCode::PARTIAL_CONTENT
+        || response.headers().contains_key(header::CONTENT_ENCODING)
+        || response.body().size_hint().exact().is_none_or(|size| size < MIN_SIZE)
+    {
+        return false;
+    }
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    media_type.starts_with("text/")
+        || media_type.ends_with("json")
+        || media_type.ends_with("xml")
+        || media_type == "application/x-subrip"
+        || media_type == "application/javascript"
+}
+
+/// Compression middleware.
+pub async fn compress(request: Request, next: Next) -> Response {
+    let encoding = request
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .and_then(Encoding::negotiate);
+    let response = next.run(request).await;
+    let Some(encoding) = encoding.filter(|_| compressible(&response)) else {
+        return response;
+    };
+
+    let (mut parts, body) = response.into_parts();
+    let compressed = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => tokio::task::spawn_blocking(move || encoding.encode(&bytes)).await,
+        Err(e) => {
+            warn!("Failed to read the response body: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let Ok(Ok(compressed)) = compressed else {
+        warn!("Failed to compress the response");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+    parts.headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    Response::from_parts(parts, Body::from(compressed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, middleware, routing::get};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Encoding::negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(Encoding::negotiate("gzip, deflate"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("deflate"), Some(Encoding::Deflate));
+        assert_eq!(Encoding::negotiate("br;q=0.5, gzip;q=0.5, deflate"), Some(Encoding::Deflate));
+        assert_eq!(Encoding::negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(Encoding::negotiate("*, br;q=0, gzip;q=0"), Some(Encoding::Deflate));
+        assert_eq!(Encoding::negotiate("compress, identity"), None);
+        assert_eq!(Encoding::negotiate(""), None);
+    }
+
+    async fn fetch(text: String, accept_encoding: &str) -> Response {
+        let app = Router::new()
+            .route("/", get(move || async move { text }))
+            .layer(middleware::from_fn(compress));
+        let request = Request::builder()
+            .uri("/")
+            .header(header::ACCEPT_ENCODING, accept_encoding)
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_compresses_large_text() {
+        let text = "the quick brown fox ".repeat(500);
+        let response = fetch(text.clone(), "gzip").await;
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[header::VARY], "accept-encoding");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.len() < text.len() / 10);
+        let mut decoded = String::new();
+        GzDecoder::new(&body[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+    }
+
+    #[tokio::test]
+    async fn test_compresses_with_brotli() {
+        let text = "the quick brown fox ".repeat(500);
+        let response = fetch(text.clone(), "gzip, deflate, br").await;
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.len() < text.len() / 10);
+        let mut decoded = String::new();
+        brotli::Decompressor::new(&body[..], 4096).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+    }
+
+    #[tokio::test]
+    async fn test_leaves_small_or_unrequested_bodies() {
+        let small = fetch("short".to_string(), "gzip").await;
+        assert!(!small.headers().contains_key(header::CONTENT_ENCODING));
+
+        let identity = fetch("the quick brown fox ".repeat(500), "identity").await;
+        assert!(!identity.headers().contains_key(header::CONTENT_ENCODING));
+    }
+}
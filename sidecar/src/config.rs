@@ -0,0 +1,802 @@
+//! Runtime configuration for VoiceMark sidecar.
+//!
+//! All settings come from `VOICEMARK_*` environment variables and are read
+//! once at startup.
+
+use anyhow::{Context, Result};
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::access_log::{self, AccessLogConfig};
+use crate::cache;
+use crate::email::SmtpConfig;
+use crate::embeddings::{self, EmbeddingsConfig};
+use crate::export::FileFormat;
+use crate::jwt::JwtConfig;
+use crate::llm::{self, LlmConfig};
+use crate::recordings::RecordingFormat;
+use crate::remote::{self, RemoteConfig};
+use crate::cluster::Role;
+use crate::stream::{self, Backpressure, LagPolicy};
+use crate::transcribe::{AudioContext, CpuLimits, DecodingParams};
+use crate::tls::TlsConfig;
+use crate::jobs::{self, JobRetention};
+use crate::transcripts::{AudioRetention, TranscriptRetention};
+use crate::watch::{WatchConfig, WatchFolder};
+use crate::webhooks::WebhookConfig;
+
+/// Default port for the sidecar server.
+pub const DEFAULT_PORT: u16 = 3001;
+
+/// Default bind address (loopback only).
+const DEFAULT_BIND: &str = "127.0.0.1";
+
+/// Sidecar configuration.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Addresses to listen on (`VOICEMARK_BIND`, `VOICEMARK_PORT`).
+    pub bind: Vec<SocketAddr>,
+    /// Whisper model path, or `auto` to pick one in `models_dir` for this
+    /// machine (`VOICEMARK_MODEL_PATH`).
+    pub model_path: Option<String>,
+    /// Where `auto` looks for models (`VOICEMARK_MODELS_DIR`).
+    pub models_dir: PathBuf,
+    /// Further Whisper models `POST /compare` can transcribe with,
+    /// comma-separated (`VOICEMARK_COMPARE_MODELS`).
+    pub compare_models: Vec<String>,
+    /// Smaller, faster Whisper model for streaming partials; finals keep
+    /// using the configured model (`VOICEMARK_PARTIAL_MODEL`).
+    pub partial_model: Option<String>,
+    /// Use GPU acceleration when built with it (`VOICEMARK_ACCELERATION`).
+    pub acceleration: bool,
+    /// whisper.cpp decoding threads (`VOICEMARK_THREADS`).
+    pub threads: Option<u32>,
+    /// Cores to pin transcription to, e.g. `0-1,3`
+    /// (`VOICEMARK_CPU_AFFINITY`, Linux only).
+    pub cpu_affinity: Vec<usize>,
+    /// Niceness of transcription threads (`VOICEMARK_NICE`, Linux only).
+    pub nice: Option<i32>,
+    /// Default whisper.cpp decoder settings (`VOICEMARK_ENTROPY_THRESHOLD`,
+    /// `VOICEMARK_LOGPROB_THRESHOLD`, `VOICEMARK_NO_SPEECH_THRESHOLD`,
+    /// `VOICEMARK_SUPPRESS_BLANK`, `VOICEMARK_SUPPRESS_NON_SPEECH`,
+    /// `VOICEMARK_MAX_INITIAL_TS`, `VOICEMARK_LENGTH_PENALTY`,
+    /// `VOICEMARK_TEMPERATURE_INCREMENT`, `VOICEMARK_SUPPRESS`).
+    pub decoding: DecodingParams,
+    /// Run a warmup transcription at startup (`VOICEMARK_WARMUP`).
+    pub warmup: bool,
+    /// Unload the model after this many idle minutes, 0 = never
+    /// (`VOICEMARK_IDLE_UNLOAD_MINS`).
+    pub idle_unload_mins: u64,
+    /// In-memory result cache entries (`VOICEMARK_CACHE_SIZE`).
+    pub cache_size: usize,
+    /// On-disk result cache directory (`VOICEMARK_CACHE_DIR`).
+    pub cache_dir: Option<PathBuf>,
+    /// ffmpeg binary used when no bundled one exists (`VOICEMARK_FFMPEG`).
+    pub ffmpeg: Option<PathBuf>,
+    /// Persist transcripts under this directory (`VOICEMARK_DATA_DIR`).
+    pub data_dir: Option<PathBuf>,
+    /// Keep uploaded audio next to persisted transcripts
+    /// (`VOICEMARK_RETAIN_AUDIO`).
+    pub retain_audio: bool,
+    /// Retained audio directory, default `<data_dir>/audio`
+    /// (`VOICEMARK_AUDIO_DIR`).
+    pub audio_dir: Option<PathBuf>,
+    /// Delete retained audio after this many days, 0 = never
+    /// (`VOICEMARK_AUDIO_MAX_AGE_DAYS`).
+    pub audio_max_age_days: u64,
+    /// Cap retained audio at this many megabytes, 0 = unlimited
+    /// (`VOICEMARK_AUDIO_MAX_MB`).
+    pub audio_max_mb: u64,
+    /// Keep at most this many retained recordings, 0 = unlimited
+    /// (`VOICEMARK_AUDIO_MAX_COUNT`).
+    pub audio_max_count: usize,
+    /// Delete stored transcripts and cached results after this many days,
+    /// 0 = never (`VOICEMARK_TRANSCRIPTS_MAX_AGE_DAYS`).
+    pub transcripts_max_age_days: u64,
+    /// Cap stored transcripts, and cached results, at this many megabytes
+    /// each, 0 = unlimited (`VOICEMARK_TRANSCRIPTS_MAX_MB`).
+    pub transcripts_max_mb: u64,
+    /// Keep at most this many stored transcripts, and cached results,
+    /// 0 = unlimited (`VOICEMARK_TRANSCRIPTS_MAX_COUNT`).
+    pub transcripts_max_count: usize,
+    /// Jobs kept in memory (`VOICEMARK_JOBS_MAX_COUNT`).
+    pub jobs_max_count: usize,
+    /// Forget jobs this many hours after they finish, 0 = only when evicted
+    /// (`VOICEMARK_JOBS_MAX_AGE_HOURS`).
+    pub jobs_max_age_hours: u64,
+    /// Checkpoint long jobs and streams this often, 0 = never
+    /// (`VOICEMARK_AUTOSAVE_SECS`).
+    pub autosave_secs: u64,
+    /// Session recordings directory, default `<data_dir>/recordings`
+    /// (`VOICEMARK_RECORDINGS_DIR`).
+    pub recordings_dir: Option<PathBuf>,
+    /// Delete session recordings after this many days, 0 = never
+    /// (`VOICEMARK_RECORDINGS_MAX_AGE_DAYS`).
+    pub recordings_max_age_days: u64,
+    /// Cap session recordings at this many megabytes, 0 = unlimited
+    /// (`VOICEMARK_RECORDINGS_MAX_MB`).
+    pub recordings_max_mb: u64,
+    /// Keep at most this many session recordings, 0 = unlimited
+    /// (`VOICEMARK_RECORDINGS_MAX_COUNT`).
+    pub recordings_max_count: usize,
+    /// How finished recordings are stored
+    /// (`VOICEMARK_RECORDING_FORMAT`, `opus` or `wav`).
+    pub recording_format: RecordingFormat,
+    /// Require API keys stored in this SQLite database
+    /// (`VOICEMARK_TENANTS_DB`).
+    pub tenants_db: Option<PathBuf>,
+    /// PEM certificate chain; with the key, serves HTTPS
+    /// (`VOICEMARK_TLS_CERT`).
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key of the certificate (`VOICEMARK_TLS_KEY`).
+    pub tls_key: Option<PathBuf>,
+    /// PEM bundle of CAs; requires client certificates issued by them
+    /// (`VOICEMARK_TLS_CLIENT_CA`).
+    pub tls_client_ca: Option<PathBuf>,
+    /// JSON Web Key Set URL; enables JWT bearer authentication
+    /// (`VOICEMARK_JWT_JWKS_URL`).
+    pub jwt_jwks_url: Option<String>,
+    /// Required JWT issuer (`VOICEMARK_JWT_ISSUER`).
+    pub jwt_issuer: Option<String>,
+    /// Required JWT audience (`VOICEMARK_JWT_AUDIENCE`).
+    pub jwt_audience: Option<String>,
+    /// Bearer token for the admin API (`VOICEMARK_ADMIN_TOKEN`).
+    pub admin_token: Option<String>,
+    /// Base64 key stored data is encrypted with (`VOICEMARK_ENCRYPTION_KEY`).
+    pub encryption_key: Option<String>,
+    /// File holding the encryption key (`VOICEMARK_ENCRYPTION_KEY_FILE`).
+    pub encryption_key_file: Option<PathBuf>,
+    /// Serve the test console at `/console` (`VOICEMARK_CONSOLE`).
+    pub console: bool,
+    /// Sentry DSN to report crashes to, in builds with the `sentry` feature
+    /// (`VOICEMARK_SENTRY_DSN`).
+    pub sentry_dsn: Option<String>,
+    /// Send full error messages and panic backtraces in crash reports
+    /// (`VOICEMARK_SENTRY_DETAILS`).
+    pub sentry_details: bool,
+    /// Kafka REST proxy to produce transcript events through, in builds
+    /// with the `kafka` feature (`VOICEMARK_KAFKA_REST_URL`).
+    pub kafka_rest_url: Option<String>,
+    /// Topic for transcript events (`VOICEMARK_KAFKA_TOPIC`).
+    pub kafka_topic: String,
+    /// NATS server to also serve transcription requests from, in builds
+    /// with the `nats` feature (`VOICEMARK_NATS_URL`).
+    pub nats_url: Option<String>,
+    /// Subject of transcription requests (`VOICEMARK_NATS_SUBJECT`).
+    pub nats_subject: String,
+    /// Queue group shared by sidecars, empty for none
+    /// (`VOICEMARK_NATS_QUEUE`).
+    pub nats_queue: String,
+    /// Redis server to relay live captions to, in builds with the `redis`
+    /// feature (`VOICEMARK_REDIS_URL`).
+    pub redis_url: Option<String>,
+    /// Prefix of the per-session caption channels
+    /// (`VOICEMARK_REDIS_CHANNEL_PREFIX`).
+    pub redis_channel_prefix: String,
+    /// Hosts `/stream?webhook=` may deliver to, comma-separated
+    /// (`VOICEMARK_WEBHOOK_HOSTS`); webhooks are off without any.
+    pub webhook_hosts: Vec<String>,
+    /// Secret webhook bodies are signed with (`VOICEMARK_WEBHOOK_SECRET`).
+    pub webhook_secret: Option<String>,
+    /// Mail server finished jobs are emailed through
+    /// (`VOICEMARK_SMTP_URL`); email is off when unset.
+    pub smtp_url: Option<String>,
+    /// Sender of those emails (`VOICEMARK_SMTP_FROM`).
+    pub smtp_from: Option<String>,
+    /// Directories whose audio files are transcribed as they appear,
+    /// comma-separated, each `<dir>` or `<dir>=<output dir>`
+    /// (`VOICEMARK_WATCH_DIRS`).
+    pub watch_dirs: Vec<WatchFolder>,
+    /// Formats their transcripts are written in (`VOICEMARK_WATCH_FORMATS`).
+    pub watch_formats: Vec<FileFormat>,
+    /// Seconds between scans of the watched directories
+    /// (`VOICEMARK_WATCH_INTERVAL_SECS`).
+    pub watch_interval_secs: u64,
+    /// Role in a cluster of sidecars (`VOICEMARK_CLUSTER_ROLE`,
+    /// `coordinator` or `worker`).
+    pub cluster_role: Option<Role>,
+    /// Shared token the sidecars of a cluster authenticate with
+    /// (`VOICEMARK_CLUSTER_TOKEN`).
+    pub cluster_token: Option<String>,
+    /// Coordinator a worker registers with (`VOICEMARK_COORDINATOR_URL`).
+    pub coordinator_url: Option<String>,
+    /// URL the coordinator reaches this worker at
+    /// (`VOICEMARK_ADVERTISE_URL`); defaults to the first bind address.
+    pub advertise_url: Option<String>,
+    /// Untranscribed audio a stream may build up before it is lagging
+    /// (`VOICEMARK_STREAM_MAX_LAG_SECS`).
+    pub stream_max_lag_secs: u64,
+    /// Transcriptions run at once (`VOICEMARK_WORKERS`); `None` fits them
+    /// to the CPU cores.
+    pub workers: Option<usize>,
+    /// What to do with a lagging stream's backlog
+    /// (`VOICEMARK_STREAM_LAG_POLICY`, `drop` or `coalesce`).
+    pub stream_lag_policy: LagPolicy,
+    /// `/stream` connections one client (API key, or IP address without
+    /// keys) may have open at once, 0 = no limit
+    /// (`VOICEMARK_MAX_STREAMS_PER_CLIENT`).
+    pub max_streams_per_client: u32,
+    /// Encoder window for streaming chunks: `fit` to the chunk or `full`
+    /// (`VOICEMARK_STREAM_AUDIO_CTX`).
+    pub stream_audio_ctx: AudioContext,
+    /// Requests each group of endpoints may run at once, beyond which
+    /// further ones are refused (`VOICEMARK_CONCURRENCY_LIMITS`).
+    pub concurrency_limits: Vec<(String, usize)>,
+    /// Access log file, or `stdout` (`VOICEMARK_ACCESS_LOG`).
+    pub access_log: Option<String>,
+    /// Rotate the access log at this many megabytes, 0 = never
+    /// (`VOICEMARK_ACCESS_LOG_MAX_MB`).
+    pub access_log_max_mb: u64,
+    /// Rotated access logs to keep (`VOICEMARK_ACCESS_LOG_KEEP`).
+    pub access_log_keep: usize,
+    /// Base URL of a Whisper-compatible API to fall back to
+    /// (`VOICEMARK_REMOTE_URL`).
+    pub remote_url: Option<String>,
+    /// Bearer token for the remote API (`VOICEMARK_REMOTE_API_KEY`).
+    pub remote_api_key: Option<String>,
+    /// Model name sent to the remote API (`VOICEMARK_REMOTE_MODEL`).
+    pub remote_model: String,
+    /// Always use the remote API and never load a local model
+    /// (`VOICEMARK_REMOTE_ONLY`).
+    pub remote_only: bool,
+    /// Base URL of an OpenAI-compatible chat API that polishes and
+    /// summarizes transcripts (`VOICEMARK_LLM_URL`).
+    pub llm_url: Option<String>,
+    /// Bearer token for the LLM API (`VOICEMARK_LLM_API_KEY`).
+    pub llm_api_key: Option<String>,
+    /// Model name sent to the LLM API (`VOICEMARK_LLM_MODEL`).
+    pub llm_model: String,
+    /// Polish every batch transcription with the LLM (`VOICEMARK_POLISH`).
+    pub polish: bool,
+    /// Base URL of an OpenAI-compatible embeddings API for semantic search
+    /// (`VOICEMARK_EMBEDDINGS_URL`).
+    pub embeddings_url: Option<String>,
+    /// Bearer token for the embeddings API (`VOICEMARK_EMBEDDINGS_API_KEY`).
+    pub embeddings_api_key: Option<String>,
+    /// Embedding model name (`VOICEMARK_EMBEDDINGS_MODEL`).
+    pub embeddings_model: String,
+}
+
+impl Config {
+    /// Load the configuration from the environment.
+    pub fn from_env() -> Result<Self> {
+        let port = env_parse("VOICEMARK_PORT", DEFAULT_PORT);
+        let bind = env::var("VOICEMARK_BIND").unwrap_or_else(|_| DEFAULT_BIND.to_string());
+
+        Ok(Self {
+            bind: parse_bind(&bind, port).context("Invalid VOICEMARK_BIND")?,
+            model_path: env::var("VOICEMARK_MODEL_PATH").ok(),
+            models_dir: env::var("VOICEMARK_MODELS_DIR")
+                .map_or_else(|_| PathBuf::from("./models"), PathBuf::from),
+            compare_models: env::var("VOICEMARK_COMPARE_MODELS")
+                .map(|paths| {
+                    paths
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|path| !path.is_empty())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            partial_model: env::var("VOICEMARK_PARTIAL_MODEL").ok().filter(|path| !path.is_empty()),
+            acceleration: env::var("VOICEMARK_ACCELERATION").map_or(true, |v| v != "0"),
+            threads: env_opt("VOICEMARK_THREADS").filter(|&n| n > 0),
+            cpu_affinity: match env::var("VOICEMARK_CPU_AFFINITY") {
+                Ok(cpus) => parse_cpu_list(&cpus).context("Invalid VOICEMARK_CPU_AFFINITY")?,
+                Err(_) => Vec::new(),
+            },
+            nice: env_opt("VOICEMARK_NICE"),
+            decoding: decoding_from_env().context("Invalid decoder settings")?,
+            warmup: env::var("VOICEMARK_WARMUP").map_or(true, |v| v != "0"),
+            idle_unload_mins: env_parse("VOICEMARK_IDLE_UNLOAD_MINS", 0),
+            cache_size: env_parse("VOICEMARK_CACHE_SIZE", cache::DEFAULT_CAPACITY),
+            cache_dir: env::var("VOICEMARK_CACHE_DIR").ok().map(PathBuf::from),
+            ffmpeg: env::var("VOICEMARK_FFMPEG").ok().map(PathBuf::from),
+            data_dir: env::var("VOICEMARK_DATA_DIR").ok().map(PathBuf::from),
+            retain_audio: env::var("VOICEMARK_RETAIN_AUDIO").is_ok_and(|v| v == "1"),
+            audio_dir: env::var("VOICEMARK_AUDIO_DIR").ok().map(PathBuf::from),
+            audio_max_age_days: env_parse("VOICEMARK_AUDIO_MAX_AGE_DAYS", 0),
+            audio_max_mb: env_parse("VOICEMARK_AUDIO_MAX_MB", 0),
+            audio_max_count: env_parse("VOICEMARK_AUDIO_MAX_COUNT", 0),
+            transcripts_max_age_days: env_parse("VOICEMARK_TRANSCRIPTS_MAX_AGE_DAYS", 0),
+            transcripts_max_mb: env_parse("VOICEMARK_TRANSCRIPTS_MAX_MB", 0),
+            transcripts_max_count: env_parse("VOICEMARK_TRANSCRIPTS_MAX_COUNT", 0),
+            jobs_max_count: env_parse("VOICEMARK_JOBS_MAX_COUNT", jobs::DEFAULT_MAX_JOBS).max(1),
+            jobs_max_age_hours: env_parse("VOICEMARK_JOBS_MAX_AGE_HOURS", 0),
+            autosave_secs: env_parse("VOICEMARK_AUTOSAVE_SECS", 30),
+            recordings_dir: env::var("VOICEMARK_RECORDINGS_DIR").ok().map(PathBuf::from),
+            recordings_max_age_days: env_parse("VOICEMARK_RECORDINGS_MAX_AGE_DAYS", 0),
+            recordings_max_mb: env_parse("VOICEMARK_RECORDINGS_MAX_MB", 0),
+            recordings_max_count: env_parse("VOICEMARK_RECORDINGS_MAX_COUNT", 0),
+            recording_format: match env::var("VOICEMARK_RECORDING_FORMAT") {
+                Ok(format) => format.parse().context("Invalid VOICEMARK_RECORDING_FORMAT")?,
+                Err(_) => RecordingFormat::default(),
+            },
+            tenants_db: env::var("VOICEMARK_TENANTS_DB").ok().map(PathBuf::from),
+            tls_cert: env::var("VOICEMARK_TLS_CERT").ok().map(PathBuf::from),
+            tls_key: env::var("VOICEMARK_TLS_KEY").ok().map(PathBuf::from),
+            tls_client_ca: env::var("VOICEMARK_TLS_CLIENT_CA").ok().map(PathBuf::from),
+            jwt_jwks_url: env::var("VOICEMARK_JWT_JWKS_URL").ok().filter(|u| !u.trim().is_empty()),
+            jwt_issuer: env::var("VOICEMARK_JWT_ISSUER").ok().filter(|i| !i.trim().is_empty()),
+            jwt_audience: env::var("VOICEMARK_JWT_AUDIENCE").ok().filter(|a| !a.trim().is_empty()),
+            admin_token: env::var("VOICEMARK_ADMIN_TOKEN").ok().filter(|t| !t.trim().is_empty()),
+            encryption_key: env::var("VOICEMARK_ENCRYPTION_KEY")
+                .ok()
+                .filter(|k| !k.trim().is_empty()),
+            encryption_key_file: env::var("VOICEMARK_ENCRYPTION_KEY_FILE").ok().map(PathBuf::from),
+            console: env::var("VOICEMARK_CONSOLE").is_ok_and(|v| v == "1"),
+            sentry_dsn: env::var("VOICEMARK_SENTRY_DSN").ok().filter(|d| !d.trim().is_empty()),
+            sentry_details: env::var("VOICEMARK_SENTRY_DETAILS").is_ok_and(|v| v == "1"),
+            kafka_rest_url: env::var("VOICEMARK_KAFKA_REST_URL")
+                .ok()
+                .filter(|u| !u.trim().is_empty()),
+            kafka_topic: env::var("VOICEMARK_KAFKA_TOPIC")
+                .ok()
+                .filter(|t| !t.trim().is_empty())
+                .unwrap_or_else(|| "voicemark.transcripts".to_string()),
+            nats_url: env::var("VOICEMARK_NATS_URL").ok().filter(|u| !u.trim().is_empty()),
+            nats_subject: env::var("VOICEMARK_NATS_SUBJECT")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .unwrap_or_else(|| "voicemark.transcribe".to_string()),
+            nats_queue: env::var("VOICEMARK_NATS_QUEUE")
+                .unwrap_or_else(|_| "voicemark".to_string()),
+            redis_url: env::var("VOICEMARK_REDIS_URL").ok().filter(|u| !u.trim().is_empty()),
+            redis_channel_prefix: env::var("VOICEMARK_REDIS_CHANNEL_PREFIX")
+                .unwrap_or_else(|_| "voicemark:stream:".to_string()),
+            webhook_hosts: env::var("VOICEMARK_WEBHOOK_HOSTS")
+                .map(|hosts| {
+                    hosts
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|host| !host.is_empty())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            webhook_secret: env::var("VOICEMARK_WEBHOOK_SECRET").ok().filter(|s| !s.is_empty()),
+            smtp_url: env::var("VOICEMARK_SMTP_URL").ok().filter(|u| !u.trim().is_empty()),
+            smtp_from: env::var("VOICEMARK_SMTP_FROM").ok().filter(|f| !f.trim().is_empty()),
+            watch_dirs: env::var("VOICEMARK_WATCH_DIRS")
+                .map(|dirs| dirs.split(',').filter_map(WatchFolder::parse).collect())
+                .unwrap_or_default(),
+            watch_formats: match env::var("VOICEMARK_WATCH_FORMATS") {
+                Ok(formats) if !formats.trim().is_empty() => formats
+                    .split(',')
+                    .filter(|f| !f.trim().is_empty())
+                    .map(str::parse)
+                    .collect::<Result<_>>()
+                    .context("Invalid VOICEMARK_WATCH_FORMATS")?,
+                _ => vec![FileFormat::Txt],
+            },
+            watch_interval_secs: env_parse("VOICEMARK_WATCH_INTERVAL_SECS", 10).max(1),
+            cluster_role: match env::var("VOICEMARK_CLUSTER_ROLE") {
+                Ok(role) if !role.trim().is_empty() => {
+                    Some(role.parse().context("Invalid VOICEMARK_CLUSTER_ROLE")?)
+                }
+                _ => None,
+            },
+            cluster_token: env::var("VOICEMARK_CLUSTER_TOKEN")
+                .ok()
+                .filter(|t| !t.trim().is_empty()),
+            coordinator_url: env::var("VOICEMARK_COORDINATOR_URL")
+                .ok()
+                .filter(|u| !u.trim().is_empty()),
+            advertise_url: env::var("VOICEMARK_ADVERTISE_URL")
+                .ok()
+                .filter(|u| !u.trim().is_empty()),
+            workers: env_opt("VOICEMARK_WORKERS").filter(|&n| n > 0),
+            stream_max_lag_secs: env_parse(
+                "VOICEMARK_STREAM_MAX_LAG_SECS",
+                stream::DEFAULT_MAX_LAG_SECS,
+            )
+            .max(1),
+            stream_lag_policy: match env::var("VOICEMARK_STREAM_LAG_POLICY") {
+                Ok(policy) => policy.parse().context("Invalid VOICEMARK_STREAM_LAG_POLICY")?,
+                Err(_) => LagPolicy::default(),
+            },
+            max_streams_per_client: env_parse("VOICEMARK_MAX_STREAMS_PER_CLIENT", 0),
+            stream_audio_ctx: match env::var("VOICEMARK_STREAM_AUDIO_CTX") {
+                Ok(audio_ctx) => {
+                    audio_ctx.parse().context("Invalid VOICEMARK_STREAM_AUDIO_CTX")?
+                }
+                Err(_) => AudioContext::Fit,
+            },
+            concurrency_limits: match env::var("VOICEMARK_CONCURRENCY_LIMITS") {
+                Ok(limits) => parse_concurrency_limits(&limits)
+                    .context("Invalid VOICEMARK_CONCURRENCY_LIMITS")?,
+                Err(_) => Vec::new(),
+            },
+            access_log: env::var("VOICEMARK_ACCESS_LOG").ok().filter(|p| !p.trim().is_empty()),
+            access_log_max_mb: env_parse("VOICEMARK_ACCESS_LOG_MAX_MB", access_log::DEFAULT_MAX_MB),
+            access_log_keep: env_parse("VOICEMARK_ACCESS_LOG_KEEP", access_log::DEFAULT_KEEP),
+            remote_url: env::var("VOICEMARK_REMOTE_URL").ok().filter(|u| !u.trim().is_empty()),
+            remote_api_key: env::var("VOICEMARK_REMOTE_API_KEY").ok().filter(|k| !k.trim().is_empty()),
+            remote_model: env::var("VOICEMARK_REMOTE_MODEL")
+                .unwrap_or_else(|_| remote::DEFAULT_MODEL.to_string()),
+            remote_only: env::var("VOICEMARK_REMOTE_ONLY").is_ok_and(|v| v == "1"),
+            llm_url: env::var("VOICEMARK_LLM_URL").ok().filter(|u| !u.trim().is_empty()),
+            llm_api_key: env::var("VOICEMARK_LLM_API_KEY").ok().filter(|k| !k.trim().is_empty()),
+            llm_model: env::var("VOICEMARK_LLM_MODEL")
+                .unwrap_or_else(|_| llm::DEFAULT_MODEL.to_string()),
+            polish: env::var("VOICEMARK_POLISH").map_or(true, |v| v != "0"),
+            embeddings_url: env::var("VOICEMARK_EMBEDDINGS_URL").ok().filter(|u| !u.trim().is_empty()),
+            embeddings_api_key: env::var("VOICEMARK_EMBEDDINGS_API_KEY")
+                .ok()
+                .filter(|k| !k.trim().is_empty()),
+            embeddings_model: env::var("VOICEMARK_EMBEDDINGS_MODEL")
+                .unwrap_or_else(|_| embeddings::DEFAULT_MODEL.to_string()),
+        })
+    }
+
+    /// Remote API settings, if a remote API is configured.
+    pub fn remote(&self) -> Option<RemoteConfig> {
+        Some(RemoteConfig {
+            url: self.remote_url.clone()?,
+            api_key: self.remote_api_key.clone(),
+            model: self.remote_model.clone(),
+            only: self.remote_only,
+        })
+    }
+
+    /// Kafka REST proxy URL and topic, if transcript events go to Kafka.
+    pub fn kafka(&self) -> Option<(&str, &str)> {
+        Some((self.kafka_rest_url.as_deref()?, &self.kafka_topic))
+    }
+
+    /// NATS server URL, subject and queue group, if transcription is served
+    /// over NATS.
+    pub fn nats(&self) -> Option<(&str, &str, &str)> {
+        Some((self.nats_url.as_deref()?, &self.nats_subject, &self.nats_queue))
+    }
+
+    /// Redis URL and channel prefix, if live captions are relayed to Redis.
+    pub fn redis(&self) -> Option<(&str, &str)> {
+        Some((self.redis_url.as_deref()?, &self.redis_channel_prefix))
+    }
+
+    /// Stream webhook settings, if any webhook hosts are allowed.
+    pub fn webhooks(&self) -> Option<WebhookConfig> {
+        if self.webhook_hosts.is_empty() {
+            return None;
+        }
+        Some(WebhookConfig {
+            hosts: self.webhook_hosts.clone(),
+            secret: self.webhook_secret.clone(),
+        })
+    }
+
+    /// SMTP settings, if finished jobs can be emailed.
+    pub fn smtp(&self) -> Result<Option<SmtpConfig>> {
+        match (&self.smtp_url, &self.smtp_from) {
+            (Some(url), Some(from)) => {
+                Ok(Some(SmtpConfig { url: url.clone(), from: from.clone() }))
+            }
+            (Some(_), None) => anyhow::bail!("VOICEMARK_SMTP_URL requires VOICEMARK_SMTP_FROM"),
+            (None, _) => Ok(None),
+        }
+    }
+
+    /// The at-rest encryption key, if one is configured, read from
+    /// `VOICEMARK_ENCRYPTION_KEY_FILE` if need be.
+    pub fn encryption_key(&self) -> Result<Option<String>> {
+        match (&self.encryption_key, &self.encryption_key_file) {
+            (Some(_), Some(_)) => anyhow::bail!(
+                "Set only one of VOICEMARK_ENCRYPTION_KEY and VOICEMARK_ENCRYPTION_KEY_FILE"
+            ),
+            (Some(key), None) => Ok(Some(key.clone())),
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))
+                .map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Watch-folder settings, if any directories are watched.
+    pub fn watch(&self) -> Option<WatchConfig> {
+        if self.watch_dirs.is_empty() {
+            return None;
+        }
+        Some(WatchConfig {
+            folders: self.watch_dirs.clone(),
+            formats: self.watch_formats.clone(),
+            interval: Duration::from_secs(self.watch_interval_secs),
+        })
+    }
+
+    /// LLM endpoint settings, if an LLM endpoint is configured.
+    pub fn llm(&self) -> Option<LlmConfig> {
+        Some(LlmConfig {
+            url: self.llm_url.clone()?,
+            api_key: self.llm_api_key.clone(),
+            model: self.llm_model.clone(),
+            polish: self.polish,
+        })
+    }
+
+    /// Embeddings endpoint settings, if one is configured.
+    pub fn embeddings(&self) -> Option<EmbeddingsConfig> {
+        Some(EmbeddingsConfig {
+            url: self.embeddings_url.clone()?,
+            api_key: self.embeddings_api_key.clone(),
+            model: self.embeddings_model.clone(),
+        })
+    }
+
+    /// HTTPS listener settings, if a certificate and key are configured.
+    pub fn tls(&self) -> Result<Option<TlsConfig>> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Ok(Some(TlsConfig {
+                cert: cert.clone(),
+                key: key.clone(),
+                client_ca: self.tls_client_ca.clone(),
+            })),
+            (None, None) if self.tls_client_ca.is_some() => {
+                anyhow::bail!("VOICEMARK_TLS_CLIENT_CA requires VOICEMARK_TLS_CERT and VOICEMARK_TLS_KEY")
+            }
+            (None, None) => Ok(None),
+            _ => anyhow::bail!("VOICEMARK_TLS_CERT and VOICEMARK_TLS_KEY must be set together"),
+        }
+    }
+
+    /// JWT validation settings, if JWT authentication is enabled.
+    pub fn jwt(&self) -> Option<JwtConfig> {
+        Some(JwtConfig {
+            jwks_url: self.jwt_jwks_url.clone()?,
+            issuer: self.jwt_issuer.clone(),
+            audience: self.jwt_audience.clone(),
+        })
+    }
+
+    /// Access log settings, if the access log is enabled.
+    pub fn access_log(&self) -> Option<AccessLogConfig> {
+        let target = self.access_log.as_deref()?;
+        Some(AccessLogConfig {
+            path: (target != "stdout").then(|| PathBuf::from(target)),
+            max_bytes: self.access_log_max_mb * 1024 * 1024,
+            keep: self.access_log_keep,
+        })
+    }
+
+    /// Backpressure policy for `/stream` sessions.
+    pub fn backpressure(&self) -> Backpressure {
+        Backpressure {
+            max_lag_ms: self.stream_max_lag_secs * 1000,
+            policy: self.stream_lag_policy,
+        }
+    }
+
+    /// Transcriptions to run at once: `VOICEMARK_WORKERS`, or as many as
+    /// the cores allow at the configured (or default) threads each.
+    pub fn workers(&self) -> usize {
+        self.workers.unwrap_or_else(|| {
+            let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+            let threads = self.threads.map_or(cores.min(4), |t| t as usize);
+            (cores / threads.max(1)).max(1)
+        })
+    }
+
+    /// CPU limits for whisper.cpp.
+    pub fn cpu_limits(&self) -> CpuLimits {
+        CpuLimits {
+            threads: self.threads,
+            cpus: self.cpu_affinity.clone(),
+            nice: self.nice,
+        }
+    }
+
+    /// Audio retention policy, if audio retention is enabled.
+    pub fn audio_retention(&self) -> Option<AudioRetention> {
+        if !self.retain_audio {
+            return None;
+        }
+        let dir = self
+            .audio_dir
+            .clone()
+            .or_else(|| self.data_dir.as_ref().map(|d| d.join("audio")))?;
+        Some(AudioRetention {
+            dir,
+            max_age: (self.audio_max_age_days > 0)
+                .then(|| Duration::from_secs(self.audio_max_age_days * 24 * 60 * 60)),
+            max_bytes: (self.audio_max_mb > 0).then(|| self.audio_max_mb * 1024 * 1024),
+            max_count: (self.audio_max_count > 0).then_some(self.audio_max_count),
+        })
+    }
+
+    /// Transcript retention policy.
+    pub fn transcript_retention(&self) -> TranscriptRetention {
+        TranscriptRetention {
+            max_age: (self.transcripts_max_age_days > 0)
+                .then(|| Duration::from_secs(self.transcripts_max_age_days * 24 * 60 * 60)),
+            max_bytes: (self.transcripts_max_mb > 0)
+                .then(|| self.transcripts_max_mb * 1024 * 1024),
+            max_count: (self.transcripts_max_count > 0).then_some(self.transcripts_max_count),
+        }
+    }
+
+    /// Job retention policy.
+    pub fn job_retention(&self) -> JobRetention {
+        JobRetention {
+            max_count: self.jobs_max_count,
+            max_age: (self.jobs_max_age_hours > 0)
+                .then(|| Duration::from_secs(self.jobs_max_age_hours * 60 * 60)),
+        }
+    }
+
+    /// How often long transcriptions are checkpointed, if at all.
+    pub fn autosave(&self) -> Option<Duration> {
+        (self.autosave_secs > 0).then(|| Duration::from_secs(self.autosave_secs))
+    }
+
+    /// Where and how long session recordings are kept, if recording is
+    /// possible.
+    pub fn recordings(&self) -> Option<AudioRetention> {
+        let dir = self
+            .recordings_dir
+            .clone()
+            .or_else(|| self.data_dir.as_ref().map(|d| d.join("recordings")))?;
+        Some(AudioRetention {
+            dir,
+            max_age: (self.recordings_max_age_days > 0)
+                .then(|| Duration::from_secs(self.recordings_max_age_days * 24 * 60 * 60)),
+            max_bytes: (self.recordings_max_mb > 0)
+                .then(|| self.recordings_max_mb * 1024 * 1024),
+            max_count: (self.recordings_max_count > 0).then_some(self.recordings_max_count),
+        })
+    }
+}
+
+/// Parse an environment variable, falling back to `default` if it is unset
+/// or invalid.
+fn env_parse<T: FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+/// Parse an optional environment variable; unset or invalid is `None`.
+fn env_opt<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().and_then(|v| v.trim().parse().ok())
+}
+
+/// Read the default decoder settings. Booleans are `1` or `0`.
+fn decoding_from_env() -> Result<DecodingParams> {
+    let flag = |name: &str| env::var(name).ok().map(|v| v.trim() == "1");
+    let decoding = DecodingParams {
+        entropy_threshold: env_opt("VOICEMARK_ENTROPY_THRESHOLD"),
+        logprob_threshold: env_opt("VOICEMARK_LOGPROB_THRESHOLD"),
+        no_speech_threshold: env_opt("VOICEMARK_NO_SPEECH_THRESHOLD"),
+        suppress_blank: flag("VOICEMARK_SUPPRESS_BLANK"),
+        suppress_non_speech_tokens: flag("VOICEMARK_SUPPRESS_NON_SPEECH"),
+        max_initial_ts: env_opt("VOICEMARK_MAX_INITIAL_TS"),
+        length_penalty: env_opt("VOICEMARK_LENGTH_PENALTY"),
+        temperature_increment: env_opt("VOICEMARK_TEMPERATURE_INCREMENT"),
+        suppress: env::var("VOICEMARK_SUPPRESS").ok(),
+    };
+    decoding.validate()?;
+    Ok(decoding)
+}
+
+/// Parse a comma-separated list of listen addresses.
+///
+/// Each entry is either a bare IP (`0.0.0.0`, `::`, `[::1]`), which uses
+/// `default_port`, or a full socket address (`192.168.1.5:8080`, `[::]:3001`).
+pub fn parse_bind(value: &str, default_port: u16) -> Result<Vec<SocketAddr>> {
+    let addrs = value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            if let Ok(addr) = entry.parse::<SocketAddr>() {
+                return Ok(addr);
+            }
+            let ip = entry.trim_start_matches('[').trim_end_matches(']');
+            ip.parse::<IpAddr>()
+                .map(|ip| SocketAddr::new(ip, default_port))
+                .with_context(|| format!("'{}' is not an IP or socket address", entry))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if addrs.is_empty() {
+        anyhow::bail!("no addresses given");
+    }
+    Ok(addrs)
+}
+
+/// Parse per-endpoint concurrency limits such as `transcribe=4,*=64`.
+pub fn parse_concurrency_limits(value: &str) -> Result<Vec<(String, usize)>> {
+    let mut limits: Vec<(String, usize)> = Vec::new();
+    for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let invalid = || format!("'{}' is not <endpoint>=<positive count>", entry);
+        let (group, limit) = entry.split_once('=').with_context(invalid)?;
+        let group = group.trim().trim_start_matches('/').to_string();
+        let limit: usize = limit.trim().parse().with_context(invalid)?;
+        if group.is_empty() || limit == 0 {
+            anyhow::bail!(invalid());
+        }
+        if limits.iter().any(|(existing, _)| *existing == group) {
+            anyhow::bail!("'{}' is limited twice", group);
+        }
+        limits.push((group, limit));
+    }
+    Ok(limits)
+}
+
+/// Parse a list of CPU cores and ranges such as `0-1,3`.
+pub fn parse_cpu_list(value: &str) -> Result<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let invalid = || format!("'{}' is not a core or range of cores", entry);
+        match entry.split_once('-') {
+            Some((first, last)) => {
+                let first: usize = first.trim().parse().with_context(invalid)?;
+                let last: usize = last.trim().parse().with_context(invalid)?;
+                if first > last {
+                    anyhow::bail!(invalid());
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(entry.parse().with_context(invalid)?),
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bind_bare_ips_use_default_port() {
+        let addrs = parse_bind("0.0.0.0, ::1", 3001).unwrap();
+        assert_eq!(addrs[0], "0.0.0.0:3001".parse().unwrap());
+        assert_eq!(addrs[1], "[::1]:3001".parse().unwrap());
+    }
+
+    #[test]
+    fn test_parse_bind_socket_addresses() {
+        let addrs = parse_bind("192.168.1.5:8080,[::]:9000,[fe80::1]", 3001).unwrap();
+        assert_eq!(addrs[0], "192.168.1.5:8080".parse().unwrap());
+        assert_eq!(addrs[1], "[::]:9000".parse().unwrap());
+        assert_eq!(addrs[2], "[fe80::1]:3001".parse().unwrap());
+    }
+
+    #[test]
+    fn test_parse_bind_rejects_invalid_entries() {
+        assert!(parse_bind("localhost", 3001).is_err());
+        assert!(parse_bind(" , ", 3001).is_err());
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0-1, 3").unwrap(), vec![0, 1, 3]);
+        assert_eq!(parse_cpu_list("2,2,1").unwrap(), vec![1, 2]);
+        assert!(parse_cpu_list("").unwrap().is_empty());
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("a").is_err());
+    }
+
+    #[test]
+    fn test_parse_concurrency_limits() {
+        let limits = parse_concurrency_limits("transcribe=4, /jobs = 16,*=64").unwrap();
+        assert_eq!(
+            limits,
+            vec![
+                ("transcribe".to_string(), 4),
+                ("jobs".to_string(), 16),
+                ("*".to_string(), 64)
+            ]
+        );
+        assert!(parse_concurrency_limits("").unwrap().is_empty());
+        assert!(parse_concurrency_limits("transcribe").is_err());
+        assert!(parse_concurrency_limits("transcribe=0").is_err());
+        assert!(parse_concurrency_limits("=4").is_err());
+        assert!(parse_concurrency_limits("jobs=1,jobs=2").is_err());
+    }
+}
@@ -0,0 +1,35 @@
+//! Test console for VoiceMark sidecar.
+//!
+//! A single static page at `/console` for trying the API from a browser:
+//! record from the microphone, upload a file (results stream in over
+//! `/transcribe/stream`), or stream live audio over the `/stream` WebSocket.
+//! It is meant for development and integration work, so it is only served
+//! when `VOICEMARK_CONSOLE=1`.
+//!
+//! The page itself needs no API key; when API keys are enabled, it sends the
+//! key entered on the page with each request.
+
+use axum::response::Html;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::info;
+
+/// The console page, embedded in the binary.
+const PAGE: &str = include_str!("console.html");
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Serve the console. Call at startup, before the router is built.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+    info!("Test console enabled at /console");
+}
+
+/// Whether the console is served.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Console page (`GET /console`).
+pub async fn page() -> Html<&'static str> {
+    Html(PAGE)
+}
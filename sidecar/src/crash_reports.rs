@@ -0,0 +1,305 @@
+//! Crash reporting for VoiceMark sidecar (`sentry` feature).
+//!
+//! With `VOICEMARK_SENTRY_DSN`, panics and transcription failures are sent
+//! to a Sentry project (or a Sentry-compatible server such as GlitchTip).
+//! Reports are redacted: they carry the kind of error, where it happened, a
+//! scrubbed message (quoted text, paths, URLs and addresses removed) and the
+//! build and platform, never audio, transcript text, request bodies or API
+//! keys. With `VOICEMARK_SENTRY_DETAILS=1`, they also carry the full error
+//! chain or panic message and a panic's backtrace, which may quote file
+//! names or server responses.
+//!
+//! Reports are queued and sent by a background thread, so failing requests
+//! don't wait on Sentry; a panicking thread waits briefly for its report, in
+//! case the panic takes the process down.
+
+use anyhow::{Context, Result, bail};
+use serde_json::{Value, json};
+use std::panic::Location;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::clock;
+
+const CLIENT: &str = env!("CARGO_PKG_NAME");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Reports waiting to be sent; more are dropped.
+const QUEUE_LEN: usize = 64;
+
+/// Timeout for sending one report.
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a panicking thread waits for its report to be sent.
+const PANIC_FLUSH: Duration = Duration::from_secs(2);
+
+/// Longest scrubbed message sent, in characters.
+const MAX_MESSAGE_CHARS: usize = 200;
+
+/// Stands in for what [`scrub`] removes.
+const REDACTED: &str = "<redacted>";
+
+/// Whether reports carry full messages and backtraces.
+static DETAILS: AtomicBool = AtomicBool::new(false);
+
+/// Where and how to send reports, from a DSN
+/// (`https://<key>@<host>/<project>`).
+#[derive(Debug, PartialEq)]
+struct Dsn {
+    store_url: String,
+    auth: String,
+}
+
+fn parse_dsn(dsn: &str) -> Result<Dsn> {
+    let url = reqwest::Url::parse(dsn.trim()).context("Not a URL")?;
+    let key = url.username();
+    if key.is_empty() {
+        bail!("Missing public key");
+    }
+    let host = url.host_str().context("Missing host")?;
+    let mut path: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    let project = path.pop().context("Missing project ID")?;
+    let port = url.port().map(|port| format!(":{}", port)).unwrap_or_default();
+    let prefix: String = path.iter().map(|segment| format!("/{}", segment)).collect();
+    Ok(Dsn {
+        store_url: format!("{}://{}{}{}/api/{}/store/", url.scheme(), host, port, prefix, project),
+        auth: format!(
+            "Sentry sentry_version=7, sentry_client={}/{}, sentry_key={}",
+            CLIENT, VERSION, key
+        ),
+    })
+}
+
+/// A report, and who to tell once it is sent.
+type Report = (Value, Option<Sender<()>>);
+
+static QUEUE: OnceLock<SyncSender<Report>> = OnceLock::new();
+
+/// Report panics and transcription failures to `dsn`, with full messages
+/// and backtraces if `details`. Call once at startup.
+pub fn configure(dsn: &str, details: bool) -> Result<()> {
+    let dsn = parse_dsn(dsn)?;
+    DETAILS.store(details, Ordering::Relaxed);
+    let (tx, rx) = mpsc::sync_channel(QUEUE_LEN);
+    if QUEUE.set(tx).is_err() {
+        bail!("Crash reporting already configured");
+    }
+    std::thread::Builder::new()
+        .name("crash-reports".to_string())
+        .spawn(move || send_reports(dsn, rx))
+        .context("Failed to start the crash reporting thread")?;
+    install_panic_hook();
+    info!("Crash reporting enabled");
+    Ok(())
+}
+
+/// Send queued reports until the process exits.
+fn send_reports(dsn: Dsn, reports: Receiver<Report>) {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build();
+    let client = reqwest::Client::builder().timeout(SEND_TIMEOUT).build();
+    let (Ok(runtime), Ok(client)) = (runtime, client) else {
+        warn!("Failed to set up crash reporting; reports will not be sent");
+        return;
+    };
+    for (event, sent) in reports {
+        let request = client.post(&dsn.store_url).header("X-Sentry-Auth", &dsn.auth).json(&event);
+        match runtime.block_on(request.send()) {
+            Ok(response) if !response.status().is_success() => {
+                warn!(status = response.status().as_u16(), "Crash report rejected");
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to send crash report: {}", e),
+        }
+        if let Some(sent) = sent {
+            let _ = sent.send(());
+        }
+    }
+}
+
+/// Queue a report, unless reporting is disabled or the queue is full.
+fn enqueue(event: Value, sent: Option<Sender<()>>) -> bool {
+    QUEUE.get().is_some_and(|queue| queue.try_send((event, sent)).is_ok())
+}
+
+/// A Sentry event. `context` says what failed, e.g. `stream`.
+fn event(level: &str, context: &str, message: String, extra: Value) -> Value {
+    let timestamp = clock::now_millis() as f64 / 1000.0;
+    json!({
+        "event_id": uuid::Uuid::new_v4().simple().to_string(),
+        "timestamp": timestamp,
+        "platform": "native",
+        "level": level,
+        "logger": CLIENT,
+        "release": format!("{}@{}", CLIENT, VERSION),
+        "message": { "formatted": message },
+        "tags": {
+            "context": context,
+            "os": std::env::consts::OS,
+            "arch": std::env::consts::ARCH,
+        },
+        "extra": extra,
+    })
+}
+
+/// `message` without quoted text, paths, URLs, addresses and long tokens
+/// (keys, IDs), which may identify users or their data, and cut short.
+fn scrub(message: &str) -> String {
+    let mut unquoted = String::new();
+    let mut quote = None;
+    let mut previous = ' ';
+    for c in message.chars() {
+        match quote {
+            Some(open) if c == open => {
+                unquoted.push_str(REDACTED);
+                quote = None;
+            }
+            Some(_) => {}
+            // An apostrophe within a word ("doesn't") opens no quote
+            None if matches!(c, '"' | '\'' | '`') && !previous.is_alphanumeric() => {
+                quote = Some(c)
+            }
+            None => unquoted.push(c),
+        }
+        previous = c;
+    }
+    if quote.is_some() {
+        unquoted.push_str(REDACTED);
+    }
+    let words: Vec<&str> = unquoted
+        .split_whitespace()
+        .map(|word| {
+            let sensitive = word.contains(['/', '\\', '@', '='])
+                || word.chars().filter(char::is_ascii_alphanumeric).count() > 24;
+            if sensitive { REDACTED } else { word }
+        })
+        .collect();
+    words.join(" ").chars().take(MAX_MESSAGE_CHARS).collect()
+}
+
+/// What kind of error `error` is, from its root cause.
+fn error_type(error: &anyhow::Error) -> &'static str {
+    let cause = error.root_cause();
+    if cause.is::<std::io::Error>() {
+        "io"
+    } else if cause.is::<reqwest::Error>() {
+        "http"
+    } else if cause.is::<serde_json::Error>() {
+        "json"
+    } else {
+        "other"
+    }
+}
+
+/// The event for `error`, which happened in `context` at `location`.
+fn error_event(context: &str, error: &anyhow::Error, location: &Location, details: bool) -> Value {
+    let message = if details { format!("{:#}", error) } else { scrub(&error.to_string()) };
+    let extra = json!({
+        "error_type": error_type(error),
+        "location": format!("{}:{}", location.file(), location.line()),
+    });
+    event("error", context, message, extra)
+}
+
+/// Report a failed transcription. `context` names the endpoint or job kind.
+#[track_caller]
+pub fn capture_error(context: &str, error: &anyhow::Error) {
+    let details = DETAILS.load(Ordering::Relaxed);
+    enqueue(error_event(context, error, Location::caller(), details), None);
+}
+
+/// Report panics, after the default hook has printed them.
+fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "panic".to_string());
+        let details = DETAILS.load(Ordering::Relaxed);
+        let mut extra = json!({
+            "location": info.location().map(|l| format!("{}:{}", l.file(), l.line())),
+            "thread": std::thread::current().name().unwrap_or("unnamed"),
+        });
+        if details {
+            let backtrace = std::backtrace::Backtrace::force_capture().to_string();
+            extra["backtrace"] = backtrace.into();
+        }
+        let message = if details { message } else { scrub(&message) };
+        let (sent_tx, sent_rx) = mpsc::channel();
+        if enqueue(event("fatal", "panic", message, extra), Some(sent_tx)) {
+            let _ = sent_rx.recv_timeout(PANIC_FLUSH);
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dsn() {
+        let dsn = parse_dsn("https://abc123@o42.ingest.sentry.io/4505").unwrap();
+        assert_eq!(dsn.store_url, "https://o42.ingest.sentry.io/api/4505/store/");
+        assert!(dsn.auth.contains("sentry_key=abc123"));
+
+        let dsn = parse_dsn("http://key@glitchtip.local:8000/errors/7").unwrap();
+        assert_eq!(dsn.store_url, "http://glitchtip.local:8000/errors/api/7/store/");
+
+        assert!(parse_dsn("https://o42.ingest.sentry.io/4505").is_err());
+        assert!(parse_dsn("https://key@o42.ingest.sentry.io/").is_err());
+        assert!(parse_dsn("not a dsn").is_err());
+    }
+
+    #[test]
+    fn test_event_is_tagged() {
+        let event = event("error", "stream", "Transcription failed".to_string(), json!({}));
+        assert_eq!(event["level"], "error");
+        assert_eq!(event["message"]["formatted"], "Transcription failed");
+        assert_eq!(event["tags"]["context"], "stream");
+        assert_eq!(event["event_id"].as_str().unwrap().len(), 32);
+    }
+
+    #[test]
+    fn test_scrub() {
+        assert_eq!(
+            scrub("Failed to open '/home/ann/Meeting with Bob.wav': not found"),
+            "Failed to open <redacted>: not found"
+        );
+        assert_eq!(
+            scrub("error sending request for url (https://api.example.com/v1?key=sk-123)"),
+            "error sending request for url <redacted>"
+        );
+        assert_eq!(scrub("No speech in \"hello there"), "No speech in <redacted>");
+        assert_eq!(scrub("Bad token abcdefghijklmnopqrstuvwxyz0123"), "Bad token <redacted>");
+        assert_eq!(scrub("Model doesn't fit in 4 GB"), "Model doesn't fit in 4 GB");
+        assert_eq!(scrub(&"word ".repeat(100)).chars().count(), MAX_MESSAGE_CHARS);
+    }
+
+    #[test]
+    fn test_error_event_is_redacted() {
+        let error = anyhow::Error::from(std::io::Error::other("read /data/ann.wav failed"))
+            .context("Transcription failed for \"Quarterly results\"");
+        let location = Location::caller();
+
+        let event = error_event("jobs", &error, location, false);
+        assert_eq!(event["message"]["formatted"], "Transcription failed for <redacted>");
+        assert_eq!(event["extra"]["error_type"], "io");
+        assert!(event["extra"]["location"].as_str().unwrap().starts_with("src/crash_reports.rs:"));
+        let json = event.to_string();
+        assert!(!json.contains("Quarterly") && !json.contains("ann.wav"), "{}", json);
+
+        // Opted in: the whole chain
+        let event = error_event("jobs", &error, location, true);
+        let message = event["message"]["formatted"].as_str().unwrap();
+        assert!(message.contains("Quarterly results") && message.contains("/data/ann.wav"));
+    }
+}
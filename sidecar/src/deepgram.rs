@@ -0,0 +1,618 @@
+//! Deepgram-compatible live transcription for VoiceMark sidecar.
+//!
+//! `GET /listen` speaks the message schema of Deepgram's live streaming API,
+//! so existing Deepgram integrations can be pointed at the sidecar by
+//! changing their base URL. Clients send raw `linear16` audio as binary
+//! frames at any `sample_rate` and `channels` (mixed down and resampled to
+//! 16 kHz mono), plus `KeepAlive`, `Finalize` and `CloseStream` control
+//! messages; the server replies with `Results` messages and, after
+//! `CloseStream`, a `Metadata` message.
+//!
+//! Transcription is the same as for `/stream` (see [`crate::stream`]):
+//! partials become `Results` with `is_final: false` (with
+//! `interim_results=true`) and finals become `Results` with `is_final: true`.
+//! Finals are 6-second chunks rather than endpointed utterances, so
+//! `speech_final` is set on finals that end a sentence and on those flushed
+//! by `Finalize` or `CloseStream`. `UtteranceEnd` and `SpeechStarted` events
+//! are not sent.
+
+use axum::{
+    Extension,
+    extract::{
+        ConnectInfo, Query,
+        rejection::QueryRejection,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    response::IntoResponse,
+};
+use futures_util::{SinkExt, StreamExt, stream::SplitSink};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tokio::sync::mpsc;
+use tracing::{error, info, instrument};
+use utoipa::IntoParams;
+use voicemark_core::session::SAMPLE_RATE;
+use voicemark_core::transcribe::Word;
+
+use crate::access_log::{self, Entry};
+use crate::clock::now_millis;
+use crate::error::{ApiError, Problem};
+use crate::stream::{
+    self, Input, QueueError, ServerMessage, SessionStats, StreamQuery, StreamSlots,
+};
+use crate::tenants::Tenant;
+
+fn default_encoding() -> String {
+    "linear16".to_string()
+}
+
+fn default_sample_rate() -> u32 {
+    SAMPLE_RATE
+}
+
+fn default_channels() -> u16 {
+    1
+}
+
+/// Query parameters for `GET /listen`. Other Deepgram options (`model`,
+/// `punctuate`, `smart_format`, ...) are accepted and ignored.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListenQuery {
+    /// Audio encoding; only `linear16` (16-bit little-endian PCM) is
+    /// supported.
+    #[serde(default = "default_encoding")]
+    encoding: String,
+    /// Sample rate of the audio in Hz (8000-48000, default 16000).
+    #[serde(default = "default_sample_rate")]
+    sample_rate: u32,
+    /// Number of interleaved channels (1-8, default 1), mixed down to mono.
+    #[serde(default = "default_channels")]
+    channels: u16,
+    /// Language tag (`en-US` is transcribed as `en`), or `multi` to detect
+    /// the language of every chunk; defaults to English.
+    language: Option<String>,
+    /// Also send partials, as `Results` with `is_final: false`.
+    #[serde(default)]
+    interim_results: bool,
+}
+
+impl ListenQuery {
+    fn validate(&self) -> Result<(), ApiError> {
+        if self.encoding != "linear16" {
+            return Err(ApiError::InvalidRequest(format!(
+                "Unsupported encoding '{}': only linear16 is supported",
+                self.encoding
+            )));
+        }
+        if !(8000..=48_000).contains(&self.sample_rate) {
+            return Err(ApiError::InvalidRequest(
+                "sample_rate must be between 8000 and 48000".to_string(),
+            ));
+        }
+        if !(1..=8).contains(&self.channels) {
+            return Err(ApiError::InvalidRequest("channels must be between 1 and 8".to_string()));
+        }
+        Ok(())
+    }
+
+    /// The equivalent `/stream` options.
+    fn stream_query(&self) -> StreamQuery {
+        let (language, lock_language) = match self.language.as_deref() {
+            None => (None, true),
+            Some("multi") => (Some("auto".to_string()), false),
+            Some(tag) => {
+                let primary = tag.split(['-', '_']).next().unwrap_or(tag);
+                (Some(primary.to_ascii_lowercase()), true)
+            }
+        };
+        StreamQuery::with_language(language, lock_language)
+    }
+}
+
+/// A control message from the client.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+enum Control {
+    /// Keep an idle connection open.
+    KeepAlive,
+    /// Transcribe the audio received so far as a final.
+    Finalize,
+    /// Transcribe what's left, send `Metadata` and close.
+    CloseStream,
+}
+
+/// A message to the client.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+enum Response {
+    Results(Results),
+    Metadata(Metadata),
+    Error { err_code: String, err_msg: String },
+}
+
+#[derive(Debug, Serialize)]
+struct Results {
+    channel_index: [u32; 2],
+    /// Length of the transcribed audio, in seconds.
+    duration: f64,
+    /// Position of the transcribed audio in the stream, in seconds.
+    start: f64,
+    is_final: bool,
+    speech_final: bool,
+    from_finalize: bool,
+    channel: Channel,
+    metadata: ResultsMetadata,
+}
+
+#[derive(Debug, Serialize)]
+struct Channel {
+    alternatives: Vec<Alternative>,
+}
+
+#[derive(Debug, Serialize)]
+struct Alternative {
+    transcript: String,
+    /// Mean probability of the words, or 1.0 when words aren't timed.
+    confidence: f32,
+    words: Vec<DeepgramWord>,
+}
+
+#[derive(Debug, Serialize)]
+struct DeepgramWord {
+    /// The word in lower case, without punctuation.
+    word: String,
+    start: f64,
+    end: f64,
+    confidence: f32,
+    punctuated_word: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ResultsMetadata {
+    request_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Metadata {
+    request_id: String,
+    /// When the stream was opened (`YYYY-MM-DDTHH:MM:SS.mmmZ`).
+    created: String,
+    /// Length of the streamed audio, in seconds.
+    duration: f64,
+    channels: u16,
+}
+
+/// Turns `/stream` replies into Deepgram messages, keeping stream positions
+/// across `Finalize`s (which start a new `/stream` session).
+#[derive(Debug)]
+struct Converter {
+    request_id: String,
+    interim_results: bool,
+    /// Audio of the sessions ended so far, in milliseconds
+    offset_ms: i64,
+    /// `Finalize` and `CloseStream` messages not yet answered
+    finalizing: usize,
+}
+
+impl Converter {
+    fn new(request_id: String, interim_results: bool) -> Self {
+        Self { request_id, interim_results, offset_ms: 0, finalizing: 0 }
+    }
+
+    /// The Deepgram message for a reply, if it has one.
+    fn convert(&mut self, reply: ServerMessage) -> Option<Response> {
+        match reply {
+            ServerMessage::Partial { text, start_ms, end_ms, .. } if self.interim_results => {
+                Some(self.results(text, Vec::new(), start_ms, end_ms, false))
+            }
+            ServerMessage::Final { text, words, start_ms, end_ms, .. } => {
+                Some(self.results(text, words, start_ms, end_ms, true))
+            }
+            ServerMessage::SessionComplete { duration_ms, .. } => {
+                self.offset_ms += duration_ms;
+                self.finalizing = self.finalizing.saturating_sub(1);
+                None
+            }
+            ServerMessage::Error { code, message } => {
+                Some(Response::Error { err_code: code, err_msg: message })
+            }
+            _ => None,
+        }
+    }
+
+    fn results(
+        &self,
+        text: String,
+        words: Vec<Word>,
+        start_ms: i64,
+        end_ms: i64,
+        is_final: bool,
+    ) -> Response {
+        let transcript = text.trim().to_string();
+        let from_finalize = is_final && self.finalizing > 0;
+        let speech_final = from_finalize || (is_final && ends_sentence(&transcript));
+        let confidence = if words.is_empty() {
+            1.0
+        } else {
+            words.iter().map(|w| w.probability).sum::<f32>() / words.len() as f32
+        };
+        let words = words
+            .into_iter()
+            .map(|w| DeepgramWord {
+                word: w
+                    .text
+                    .trim()
+                    .trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_lowercase(),
+                start: self.seconds(w.start_ms),
+                end: self.seconds(w.end_ms),
+                confidence: w.probability,
+                punctuated_word: w.text.trim().to_string(),
+            })
+            .collect();
+        Response::Results(Results {
+            channel_index: [0, 1],
+            duration: (end_ms - start_ms).max(0) as f64 / 1000.0,
+            start: self.seconds(start_ms),
+            is_final,
+            speech_final,
+            from_finalize,
+            channel: Channel { alternatives: vec![Alternative { transcript, confidence, words }] },
+            metadata: ResultsMetadata { request_id: self.request_id.clone() },
+        })
+    }
+
+    /// Seconds from the start of the stream of a position in the current
+    /// session.
+    fn seconds(&self, ms: i64) -> f64 {
+        (self.offset_ms + ms) as f64 / 1000.0
+    }
+}
+
+/// Whether a transcript ends a sentence.
+fn ends_sentence(text: &str) -> bool {
+    text.ends_with(['.', '?', '!'])
+}
+
+/// Turns `linear16` frames into 16 kHz mono samples.
+#[derive(Debug)]
+struct Decoder {
+    channels: usize,
+    /// Bytes of a frame split across messages
+    pending: Vec<u8>,
+    resampler: Resampler,
+}
+
+impl Decoder {
+    fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            channels: channels as usize,
+            pending: Vec::new(),
+            resampler: Resampler::new(sample_rate),
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) -> Vec<f32> {
+        self.pending.extend_from_slice(data);
+        let frame = 2 * self.channels;
+        let whole = self.pending.len() / frame * frame;
+        let samples = voicemark_core::session::pcm16_to_f32(&self.pending[..whole]);
+        self.pending.drain(..whole);
+        let mono: Vec<f32> = samples
+            .chunks(self.channels)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect();
+        self.resampler.process(&mono)
+    }
+}
+
+/// Linear-interpolation resampler to [`SAMPLE_RATE`], for a stream of blocks.
+#[derive(Debug)]
+struct Resampler {
+    /// Input samples per output sample
+    step: f64,
+    /// Position of the next output sample, where 0 is `previous` and 1 is
+    /// the first sample of the next block
+    position: f64,
+    previous: f32,
+}
+
+impl Resampler {
+    fn new(sample_rate: u32) -> Self {
+        Self { step: sample_rate as f64 / SAMPLE_RATE as f64, position: 1.0, previous: 0.0 }
+    }
+
+    fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.step == 1.0 {
+            return input.to_vec();
+        }
+        let at = |i: usize| if i == 0 { self.previous } else { input[i - 1] };
+        let mut output = Vec::with_capacity((input.len() as f64 / self.step) as usize + 1);
+        while (self.position as usize) < input.len() {
+            let i = self.position as usize;
+            let fraction = (self.position - i as f64) as f32;
+            output.push(at(i) + (at(i + 1) - at(i)) * fraction);
+            self.position += self.step;
+        }
+        if let Some(&last) = input.last() {
+            self.position -= input.len() as f64;
+            self.previous = last;
+        }
+        output
+    }
+}
+
+/// Deepgram-compatible WebSocket upgrade handler
+///
+/// Holds the same stream slots as `/stream` (see
+/// [`stream::ws_handler`]); Deepgram clients can authenticate with
+/// `Authorization: Token <key>`.
+#[utoipa::path(
+    get,
+    path = "/listen",
+    operation_id = "listen",
+    tag = "streaming",
+    params(ListenQuery),
+    description = "WebSocket upgrade speaking Deepgram's live transcription schema. The client \
+                   sends linear16 audio as binary frames and `KeepAlive`, `Finalize` or \
+                   `CloseStream` JSON text frames; the server replies with `Results` and \
+                   `Metadata` JSON text frames.",
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol"),
+        (status = 400, description = "Unsupported encoding, sample rate or channel count", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 402, description = "Daily audio quota used up", body = Problem, content_type = "application/problem+json"),
+        (status = 429, description = "Too many concurrent streams for this key or client", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn listen(
+    ws: WebSocketUpgrade,
+    tenant: Option<Extension<Tenant>>,
+    entry: Option<Extension<Entry>>,
+    peer: Option<Extension<ConnectInfo<SocketAddr>>>,
+    query: Result<Query<ListenQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Query(query) = query?;
+    query.validate()?;
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    let entry = entry.map(|Extension(entry)| entry);
+    let peer = peer.map(|Extension(ConnectInfo(addr))| addr);
+    let slots = stream::open_slots(tenant.as_ref(), peer)?;
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, tenant, entry, slots, query)))
+}
+
+/// Serve a `/listen` connection with a `/stream` session task (see
+/// [`stream::run_session`]), translating messages both ways.
+#[instrument(skip_all)]
+async fn handle_socket(
+    socket: WebSocket,
+    tenant: Option<Tenant>,
+    entry: Option<Entry>,
+    _slots: StreamSlots,
+    query: ListenQuery,
+) {
+    let request_id = uuid::Uuid::new_v4().to_string();
+    let created = access_log::timestamp(now_millis());
+    info!(request_id = %request_id, "New Deepgram-compatible connection established");
+    stream::ACTIVE_STREAMS.fetch_add(1, Ordering::Relaxed);
+
+    let (mut sender, mut receiver) = socket.split();
+    let stats = Arc::new(SessionStats::default());
+    let (inputs, queue) = stream::input_queue(stats.clone(), stream::backpressure());
+    let (replies_tx, mut replies) = mpsc::unbounded_channel();
+    let transcriber = tokio::spawn(stream::run_session(
+        queue,
+        replies_tx,
+        tenant,
+        entry,
+        query.stream_query(),
+        stats.clone(),
+        None,
+    ));
+    let mut decoder = Decoder::new(query.sample_rate, query.channels);
+    let mut converter = Converter::new(request_id.clone(), query.interim_results);
+    let mut closing = false;
+
+    loop {
+        tokio::select! {
+            msg = receiver.next(), if !closing => {
+                let input = match msg {
+                    Some(Ok(Message::Binary(data))) => Input::Audio(decoder.push(&data)),
+                    Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                        Ok(Control::KeepAlive) => continue,
+                        Ok(Control::Finalize) => {
+                            converter.finalizing += 1;
+                            Input::End
+                        }
+                        Ok(Control::CloseStream) => {
+                            converter.finalizing += 1;
+                            closing = true;
+                            Input::End
+                        }
+                        Err(e) => {
+                            let e = ApiError::InvalidMessage(e.to_string());
+                            let reply = converter.convert(e.into());
+                            if send(&mut sender, reply).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                    },
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Err(e)) => {
+                        error!("WebSocket error: {}", e);
+                        break;
+                    }
+                    Some(Ok(_)) => continue,
+                };
+                match inputs.send(input).await {
+                    Ok(()) => {}
+                    Err(QueueError::Closed) => break,
+                    Err(QueueError::Full(e)) => {
+                        let _ = send(&mut sender, converter.convert(e.into())).await;
+                        break;
+                    }
+                }
+            }
+            Some(reply) = replies.recv() => {
+                let complete = matches!(reply, ServerMessage::SessionComplete { .. });
+                if send(&mut sender, converter.convert(reply)).await.is_err() {
+                    break;
+                }
+                if closing && complete && converter.finalizing == 0 {
+                    let metadata = Response::Metadata(Metadata {
+                        request_id: request_id.clone(),
+                        created: created.clone(),
+                        duration: converter.offset_ms as f64 / 1000.0,
+                        channels: query.channels,
+                    });
+                    let _ = send(&mut sender, Some(metadata)).await;
+                    let _ = sender.close().await;
+                    break;
+                }
+            }
+        }
+    }
+
+    drop(inputs);
+    drop(replies);
+    if let Err(e) = transcriber.await {
+        error!("Streaming session task failed: {}", e);
+    }
+    stream::ACTIVE_STREAMS.fetch_sub(1, Ordering::Relaxed);
+    info!("Deepgram-compatible connection closed");
+}
+
+/// Send a message to the client, if there is one.
+async fn send(
+    sender: &mut SplitSink<WebSocket, Message>,
+    response: Option<Response>,
+) -> Result<(), axum::Error> {
+    match response.map(|response| serde_json::to_string(&response)) {
+        Some(Ok(json)) => sender.send(Message::Text(json)).await,
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(params: &str) -> ListenQuery {
+        let uri: axum::http::Uri = format!("/listen?{}", params).parse().unwrap();
+        Query::<ListenQuery>::try_from_uri(&uri).unwrap().0
+    }
+
+    #[test]
+    fn test_query_maps_to_stream_options() {
+        let q = query("encoding=linear16&sample_rate=48000&language=en-US&model=nova-2");
+        assert!(q.validate().is_ok());
+        let stream = q.stream_query();
+        assert_eq!(stream.language.as_deref(), Some("en"));
+        assert!(stream.lock_language);
+
+        let stream = query("language=multi").stream_query();
+        assert_eq!(stream.language.as_deref(), Some("auto"));
+        assert!(!stream.lock_language);
+
+        assert!(query("encoding=opus").validate().is_err());
+        assert!(query("sample_rate=96000").validate().is_err());
+        assert!(query("channels=0").validate().is_err());
+    }
+
+    #[test]
+    fn test_finals_become_results() {
+        let mut converter = Converter::new("req".to_string(), false);
+        let partial = ServerMessage::Partial {
+            text: "hello".to_string(),
+            translation: None,
+            timestamp: 0,
+            start_ms: 0,
+            end_ms: 1000,
+        };
+        assert!(converter.convert(partial).is_none());
+
+        let word = |text: &str, start_ms, end_ms| Word {
+            start_ms,
+            end_ms,
+            text: text.to_string(),
+            probability: 0.8,
+        };
+        let done = |duration_ms| ServerMessage::SessionComplete {
+            text: String::new(),
+            segments: Vec::new(),
+            duration_ms,
+            timestamp: 0,
+        };
+        let fin = |text: &str, words| ServerMessage::Final {
+            text: text.to_string(),
+            translation: None,
+            timestamp: 0,
+            language: None,
+            language_probability: None,
+            words,
+            speaker: None,
+            speaker_changed: None,
+            start_ms: 0,
+            end_ms: 1500,
+        };
+
+        // A Finalize ends the session; positions carry on from its length
+        converter.finalizing = 1;
+        let json = serde_json::to_value(converter.convert(fin(" Hi, there.", Vec::new()))).unwrap();
+        assert_eq!(json["type"], "Results");
+        assert_eq!(json["is_final"], true);
+        assert_eq!(json["from_finalize"], true);
+        assert_eq!(json["speech_final"], true);
+        assert_eq!(json["channel"]["alternatives"][0]["transcript"], "Hi, there.");
+        assert!(converter.convert(done(2000)).is_none());
+        assert_eq!(converter.finalizing, 0);
+
+        let words = vec![word(" Hello,", 100, 400), word(" world", 400, 900)];
+        let json = serde_json::to_value(converter.convert(fin("Hello, world", words))).unwrap();
+        assert_eq!(json["start"], 2.0);
+        assert_eq!(json["duration"], 1.5);
+        assert_eq!(json["from_finalize"], false);
+        assert_eq!(json["speech_final"], false);
+        assert_eq!(json["metadata"]["request_id"], "req");
+        let word = &json["channel"]["alternatives"][0]["words"][0];
+        assert_eq!(word["word"], "hello");
+        assert_eq!(word["punctuated_word"], "Hello,");
+        assert_eq!(word["start"], 2.1);
+    }
+
+    #[test]
+    fn test_control_messages() {
+        assert_eq!(
+            serde_json::from_str::<Control>(r#"{"type":"KeepAlive"}"#).unwrap(),
+            Control::KeepAlive
+        );
+        assert_eq!(
+            serde_json::from_str::<Control>(r#"{"type":"CloseStream"}"#).unwrap(),
+            Control::CloseStream
+        );
+        assert!(serde_json::from_str::<Control>(r#"{"type":"Other"}"#).is_err());
+    }
+
+    #[test]
+    fn test_decoder_mixes_down_and_resamples() {
+        // Stereo frames split across messages
+        let mut decoder = Decoder::new(16000, 2);
+        let frame = [0x00, 0x40, 0x00, 0x00]; // 0.5 and 0.0
+        assert!(decoder.push(&frame[..3]).is_empty());
+        assert_eq!(decoder.push(&frame[3..]), vec![0.25]);
+
+        // 48 kHz is a third as many samples, in any block sizes
+        let mut decoder = Decoder::new(48000, 1);
+        let second = vec![0u8; 96000];
+        let total: usize = second.chunks(998).map(|block| decoder.push(block).len()).sum();
+        assert!((15999..=16000).contains(&total), "{}", total);
+
+        let mut resampler = Resampler::new(8000);
+        assert_eq!(resampler.process(&[0.0, 1.0]), vec![0.0, 0.5]);
+        assert_eq!(resampler.process(&[0.0]), vec![1.0, 0.5]);
+    }
+}
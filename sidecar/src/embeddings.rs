@@ -0,0 +1,226 @@
+//! Segment embeddings for semantic transcript search.
+//!
+//! With `VOICEMARK_EMBEDDINGS_URL` set, each persisted transcript's segments
+//! are embedded by an OpenAI-compatible `/embeddings` endpoint (for example
+//! a llama.cpp server running all-MiniLM-L6-v2 with `--embedding`) and the
+//! vectors are stored next to the transcript, so
+//! `GET /transcripts/semantic-search?q=...` can rank segments by meaning
+//! rather than exact words.
+//!
+//! Vectors are normalized to unit length, so cosine similarity is a dot
+//! product.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// Default embedding model name.
+pub const DEFAULT_MODEL: &str = "all-MiniLM-L6-v2";
+
+/// Upper bound on a single embeddings request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Longest error body quoted from the embeddings endpoint.
+const MAX_ERROR_BODY: usize = 300;
+
+/// Texts sent per request; long transcripts are embedded in batches.
+const MAX_BATCH: usize = 64;
+
+/// Embeddings endpoint settings.
+#[derive(Debug, Clone)]
+pub struct EmbeddingsConfig {
+    /// API base URL, e.g. `http://127.0.0.1:8081/v1`.
+    pub url: String,
+    /// Bearer token sent to the API.
+    pub api_key: Option<String>,
+    /// Model name sent with each request, and stored with the vectors.
+    pub model: String,
+}
+
+/// Client for an OpenAI-compatible embeddings API.
+struct Embedder {
+    client: reqwest::Client,
+    config: EmbeddingsConfig,
+}
+
+static EMBEDDER: OnceLock<Embedder> = OnceLock::new();
+
+/// Enable segment embeddings. Call once at startup.
+pub fn configure(config: EmbeddingsConfig) -> Result<()> {
+    info!(url = %config.url, model = %config.model, "Segment embeddings configured");
+    let embedder = Embedder::new(config)?;
+    if EMBEDDER.set(embedder).is_err() {
+        bail!("Segment embeddings already configured");
+    }
+    Ok(())
+}
+
+/// Name of the embedding model, if configured. Vectors from different
+/// models can't be compared.
+pub fn model() -> Option<&'static str> {
+    EMBEDDER.get().map(|embedder| embedder.config.model.as_str())
+}
+
+/// Unit-length embeddings of `texts`, in order.
+pub async fn embed(texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    let Some(embedder) = EMBEDDER.get() else {
+        bail!("No embeddings endpoint configured");
+    };
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(MAX_BATCH) {
+        vectors.extend(embedder.embed(batch).await?);
+    }
+    Ok(vectors)
+}
+
+/// Dot product of two unit vectors, i.e. their cosine similarity. Vectors
+/// of different lengths score 0.
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+impl Embedder {
+    fn new(config: EmbeddingsConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(Self { client, config })
+    }
+
+    /// Embed one batch of texts.
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/embeddings", self.config.url.trim_end_matches('/'));
+        let body = EmbeddingsRequest { model: &self.config.model, input: texts };
+
+        let mut request = self.client.post(&url).json(&body);
+        if let Some(key) = &self.config.api_key {
+            request = request.bearer_auth(key);
+        }
+
+        debug!(url = %url, texts = texts.len(), "Requesting embeddings");
+        let response = request.send().await.context("Embeddings request failed")?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let body: String = body.trim().chars().take(MAX_ERROR_BODY).collect();
+            bail!("Embeddings endpoint returned {}: {}", status, body);
+        }
+
+        let embeddings: EmbeddingsResponse = response
+            .json()
+            .await
+            .context("Invalid response from the embeddings endpoint")?;
+        embeddings.into_vectors(texts.len())
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+/// Embeddings response, reduced to what we read.
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl EmbeddingsResponse {
+    /// Normalized vectors in input order; fails unless there is exactly one
+    /// per input.
+    fn into_vectors(mut self, expected: usize) -> Result<Vec<Vec<f32>>> {
+        if self.data.len() != expected {
+            bail!("Expected {} embeddings, got {}", expected, self.data.len());
+        }
+        self.data.sort_by_key(|data| data.index);
+        Ok(self.data.into_iter().map(|data| normalize(data.embedding)).collect())
+    }
+}
+
+/// Scale `vector` to unit length (zero vectors are left alone).
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, routing::post};
+    use std::future::IntoFuture;
+
+    #[test]
+    fn test_into_vectors_orders_and_normalizes() {
+        let response: EmbeddingsResponse = serde_json::from_value(serde_json::json!({
+            "data": [
+                { "index": 1, "embedding": [0.0, 2.0] },
+                { "index": 0, "embedding": [3.0, 4.0] }
+            ]
+        }))
+        .unwrap();
+        let vectors = response.into_vectors(2).unwrap();
+        assert_eq!(vectors, vec![vec![0.6, 0.8], vec![0.0, 1.0]]);
+        assert!((similarity(&vectors[0], &vectors[1]) - 0.8).abs() < 1e-6);
+        assert_eq!(similarity(&vectors[0], &[1.0]), 0.0);
+    }
+
+    #[test]
+    fn test_into_vectors_rejects_missing_embeddings() {
+        let response: EmbeddingsResponse =
+            serde_json::from_value(serde_json::json!({ "data": [{ "embedding": [1.0] }] }))
+                .unwrap();
+        assert!(response.into_vectors(2).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_embed_request() {
+        // Embeds each input as [length, 1]
+        let app = Router::new().route(
+            "/v1/embeddings",
+            post(|Json(request): Json<serde_json::Value>| async move {
+                assert_eq!(request["model"], DEFAULT_MODEL);
+                let data: Vec<serde_json::Value> = request["input"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .enumerate()
+                    .map(|(index, text)| {
+                        let len = text.as_str().unwrap().len() as f32;
+                        serde_json::json!({ "index": index, "embedding": [len, 1.0] })
+                    })
+                    .collect();
+                Json(serde_json::json!({ "object": "list", "data": data }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let embedder = Embedder::new(EmbeddingsConfig {
+            url: format!("http://{}/v1", addr),
+            api_key: None,
+            model: DEFAULT_MODEL.to_string(),
+        })
+        .unwrap();
+        let vectors = embedder.embed(&["".to_string(), "abc".to_string()]).await.unwrap();
+        assert_eq!(vectors[0], vec![0.0, 1.0]);
+        assert!((vectors[1][0] - 3.0 / 10f32.sqrt()).abs() < 1e-6);
+    }
+}
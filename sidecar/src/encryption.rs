@@ -0,0 +1,203 @@
+//! At-rest encryption for VoiceMark sidecar.
+//!
+//! With `VOICEMARK_ENCRYPTION_KEY` (or `VOICEMARK_ENCRYPTION_KEY_FILE`), a
+//! base64-encoded 256-bit key, stored transcripts, retained audio, segment
+//! embeddings, session recordings and the on-disk result cache are written
+//! encrypted with AES-256-GCM, so a copy of the data directory (a lost
+//! laptop, a backup) doesn't give away what was said. The API decrypts them
+//! transparently.
+//!
+//! Each file is sealed on its own: [`MAGIC`], a random 96-bit nonce, then
+//! the ciphertext and its tag. The file's [`Kind`] is authenticated with
+//! it, so one kind of file can't be passed off as another. Files without
+//! the header are read as they are, so data written before encryption was
+//! turned on stays readable, and is encrypted in place at startup (see
+//! [`crate::transcripts::seal_existing`] and
+//! [`crate::recordings::seal_existing`]). A sealed file can't be read
+//! without its key: losing the key loses the data.
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::info;
+
+/// Header of every encrypted file.
+pub const MAGIC: &[u8; 8] = b"VMSEAL01";
+
+static KEY: OnceLock<LessSafeKey> = OnceLock::new();
+
+/// What an encrypted file holds, authenticated as its associated data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Transcript,
+    /// Retained upload.
+    Audio,
+    Embeddings,
+    /// Session recording or its transcript.
+    Recording,
+    /// Cached transcription result.
+    Cache,
+}
+
+impl Kind {
+    fn aad(self) -> Aad<&'static [u8]> {
+        Aad::from(match self {
+            Self::Transcript => b"transcript".as_slice(),
+            Self::Audio => b"audio",
+            Self::Embeddings => b"embeddings",
+            Self::Recording => b"recording",
+            Self::Cache => b"cache",
+        })
+    }
+}
+
+/// Parse a base64-encoded 256-bit key.
+fn parse_key(key: &str) -> Result<LessSafeKey> {
+    let bytes = STANDARD.decode(key.trim()).context("Key is not valid base64")?;
+    if bytes.len() != 32 {
+        bail!("Key must be 32 bytes (base64 of 256 random bits), got {}", bytes.len());
+    }
+    let key = UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| anyhow!("Invalid key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Encrypt stored data with the base64-encoded `key`. Call once at
+/// startup, before anything is stored.
+pub fn configure(key: &str) -> Result<()> {
+    let key = parse_key(key).context("Invalid VOICEMARK_ENCRYPTION_KEY")?;
+    if KEY.set(key).is_err() {
+        bail!("Encryption already configured");
+    }
+    info!("At-rest encryption enabled");
+    Ok(())
+}
+
+/// Whether stored data is encrypted.
+pub fn enabled() -> bool {
+    KEY.get().is_some()
+}
+
+/// Whether `data` is an encrypted file.
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+fn seal_with(key: &LessSafeKey, kind: Kind, mut data: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce).map_err(|_| io::Error::other("No randomness"))?;
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), kind.aad(), &mut data)
+        .map_err(|_| io::Error::other("Encryption failed"))?;
+    let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_LEN + data.len());
+    sealed.extend_from_slice(MAGIC);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&data);
+    Ok(sealed)
+}
+
+fn open_with(key: Option<&LessSafeKey>, kind: Kind, data: Vec<u8>) -> io::Result<Vec<u8>> {
+    if !is_sealed(&data) {
+        return Ok(data);
+    }
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+    let key = key.ok_or_else(|| invalid("File is encrypted but no key is configured"))?;
+    let body = &data[MAGIC.len()..];
+    if body.len() < NONCE_LEN {
+        return Err(invalid("Encrypted file is truncated"));
+    }
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| invalid("Bad nonce"))?;
+    let mut plaintext = ciphertext.to_vec();
+    let len = key
+        .open_in_place(nonce, kind.aad(), &mut plaintext)
+        .map_err(|_| invalid("Decryption failed (wrong key or corrupted file)"))?
+        .len();
+    plaintext.truncate(len);
+    Ok(plaintext)
+}
+
+/// `data` encrypted as `kind`, or as it is if encryption is off.
+pub fn seal(kind: Kind, data: Vec<u8>) -> io::Result<Vec<u8>> {
+    match KEY.get() {
+        Some(key) => seal_with(key, kind, data),
+        None => Ok(data),
+    }
+}
+
+/// `data` decrypted as `kind` if it is encrypted, or as it is otherwise.
+pub fn open(kind: Kind, data: Vec<u8>) -> io::Result<Vec<u8>> {
+    open_with(KEY.get(), kind, data)
+}
+
+/// Read the `kind` file at `path`, decrypting it if needed.
+pub fn read(path: impl AsRef<Path>, kind: Kind) -> io::Result<Vec<u8>> {
+    open(kind, std::fs::read(path)?)
+}
+
+/// Write `data` to `path` as `kind`, encrypted if encryption is on.
+pub fn write(path: impl AsRef<Path>, kind: Kind, data: impl Into<Vec<u8>>) -> io::Result<()> {
+    std::fs::write(path, seal(kind, data.into())?)
+}
+
+/// Encrypt the `kind` file at `path` in place, keeping its modification
+/// time (which retention goes by), unless encryption is off or it already
+/// is encrypted. Returns whether it was encrypted now.
+pub fn seal_file(path: &Path, kind: Kind) -> io::Result<bool> {
+    if !enabled() {
+        return Ok(false);
+    }
+    let mut file = std::fs::File::open(path)?;
+    if !file.metadata()?.is_file() {
+        return Ok(false);
+    }
+    let mut header = Vec::new();
+    (&mut file).take(MAGIC.len() as u64).read_to_end(&mut header)?;
+    if is_sealed(&header) {
+        return Ok(false);
+    }
+    let modified = file.metadata()?.modified()?;
+    let mut data = header;
+    file.read_to_end(&mut data)?;
+
+    let mut tmp = path.as_os_str().to_os_string();
+    tmp.push(".tmp");
+    write(&tmp, kind, data)?;
+    std::fs::File::options().write(true).open(&tmp)?.set_modified(modified)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_B64: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = parse_key(KEY_B64).unwrap();
+        let json = b"{\"text\":\"Hello\"}";
+        let sealed = seal_with(&key, Kind::Transcript, json.to_vec()).unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.windows(5).any(|w| w == b"Hello"));
+        assert_eq!(open_with(Some(&key), Kind::Transcript, sealed.clone()).unwrap(), json);
+
+        // Plaintext passes through; sealed data needs the right key and kind
+        assert_eq!(open_with(None, Kind::Cache, b"plain".to_vec()).unwrap(), b"plain");
+        assert!(open_with(None, Kind::Transcript, sealed.clone()).is_err());
+        assert!(open_with(Some(&key), Kind::Cache, sealed.clone()).is_err());
+        let other = parse_key(&STANDARD.encode([7u8; 32])).unwrap();
+        assert!(open_with(Some(&other), Kind::Transcript, sealed).is_err());
+    }
+
+    #[test]
+    fn test_parse_key_requires_256_bits() {
+        assert!(parse_key(KEY_B64).is_ok());
+        assert!(parse_key(&STANDARD.encode([0u8; 16])).is_err());
+        assert!(parse_key("not base64!").is_err());
+    }
+}
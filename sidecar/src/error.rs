@@ -0,0 +1,379 @@
+//! Error types for VoiceMark sidecar.
+//!
+//! Every error maps to a stable, machine-readable `code` so clients never
+//! have to match on message text. HTTP handlers return errors as RFC 7807
+//! `application/problem+json` bodies; SSE `error` events and NATS error
+//! replies carry the same body, and WebSocket `error` messages carry the
+//! same `code`.
+
+use axum::{
+    Json,
+    extract::rejection::{BytesRejection, JsonRejection, QueryRejection},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::multipart::MultipartRejection;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Content type of problem detail responses.
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Errors reported to API clients.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    /// No model is configured, so nothing can be transcribed.
+    #[error("No model is loaded; load one with POST /model or set VOICEMARK_MODEL_PATH")]
+    ModelNotLoaded,
+    /// A model couldn't be loaded (missing or invalid file).
+    #[error("{0}")]
+    ModelLoadFailed(String),
+    /// A model load conflicts with the model already loaded.
+    #[error("{0}")]
+    ModelConflict(String),
+    /// The request is malformed (bad JSON, bad base64, bad multipart, ...).
+    #[error("{0}")]
+    InvalidRequest(String),
+    /// The request does not contain any audio.
+    #[error("{0}")]
+    MissingAudio(String),
+    /// The uploaded audio is empty.
+    #[error("Uploaded audio is empty")]
+    EmptyAudio,
+    /// The request body is not a supported upload type.
+    #[error("Expected multipart/form-data or an audio/* request body")]
+    UnsupportedMediaType,
+    /// The audio could not be decoded.
+    #[error("Unsupported or corrupt audio: {0}")]
+    UnsupportedFormat(String),
+    /// The upload exceeds the request body limit.
+    #[error("Audio exceeds the maximum upload size")]
+    AudioTooLarge,
+    /// A streaming audio frame is invalid (wrong sample rate, bad encoding).
+    #[error("{0}")]
+    InvalidAudio(String),
+    /// A WebSocket message could not be parsed.
+    #[error("Invalid message format: {0}")]
+    InvalidMessage(String),
+    /// No job with this ID exists (or it has been evicted).
+    #[error("Job '{0}' not found")]
+    JobNotFound(String),
+    /// No persisted transcript with this ID exists.
+    #[error("Transcript '{0}' not found")]
+    TranscriptNotFound(String),
+    /// The transcript's audio was not retained or has been pruned.
+    #[error("Audio for transcript '{0}' is not retained")]
+    AudioNotRetained(String),
+    /// No upload with this ID exists (or it was completed or has expired).
+    #[error("Upload '{0}' not found")]
+    UploadNotFound(String),
+    /// A chunk or completion request doesn't match the upload's state.
+    #[error("{0}")]
+    UploadConflict(String),
+    /// The user's data can't be erased while their jobs are still running.
+    #[error("{0}")]
+    JobsRunning(String),
+    /// No API key with this ID exists.
+    #[error("API key '{0}' not found")]
+    KeyNotFound(String),
+    /// ffmpeg is required to decode this upload but is not available.
+    #[error("{0}")]
+    FfmpegUnavailable(String),
+    /// The request has no valid API key.
+    #[error("{0}")]
+    Unauthorized(String),
+    /// The request is authenticated, but not allowed to do this.
+    #[error("{0}")]
+    Forbidden(String),
+    /// The JSON Web Key Set for verifying tokens couldn't be fetched.
+    #[error("{0}")]
+    JwksFailed(String),
+    /// The caller's daily audio quota is used up.
+    #[error("{0}")]
+    QuotaExceeded(String),
+    /// The caller has too many concurrent streams open.
+    #[error("{0}")]
+    TooManyStreams(String),
+    /// Too many requests to this endpoint are already running.
+    #[error("{0}")]
+    Overloaded(String),
+    /// No LLM endpoint is configured (`VOICEMARK_LLM_URL`).
+    #[error("No LLM endpoint is configured")]
+    LlmUnavailable,
+    /// The LLM endpoint failed or returned an unusable reply.
+    #[error("LLM request failed: {0}")]
+    LlmFailed(String),
+    /// Semantic search needs `VOICEMARK_EMBEDDINGS_URL` and
+    /// `VOICEMARK_DATA_DIR`.
+    #[error("Semantic search needs an embeddings endpoint and transcript persistence")]
+    EmbeddingsUnavailable,
+    /// The embeddings endpoint failed.
+    #[error("Embeddings request failed: {0}")]
+    EmbeddingsFailed(String),
+    /// Session recording needs `VOICEMARK_DATA_DIR` or
+    /// `VOICEMARK_RECORDINGS_DIR`.
+    #[error("Session recording is not enabled on this server")]
+    RecordingUnavailable,
+    /// Stream webhooks need `VOICEMARK_WEBHOOK_HOSTS`.
+    #[error("Webhooks are not enabled on this server")]
+    WebhookUnavailable,
+    /// Emailing jobs needs `VOICEMARK_SMTP_URL`.
+    #[error("Email delivery is not enabled on this server")]
+    EmailUnavailable,
+    /// The response can't be given in any format the `Accept` header allows.
+    #[error("{0}")]
+    NotAcceptable(String),
+    /// The cluster worker running a job could not be reached.
+    #[error("{0}")]
+    WorkerUnreachable(String),
+    /// Whisper failed to transcribe the audio.
+    #[error("Transcription failed: {0}")]
+    TranscriptionFailed(String),
+    /// Any other server-side failure.
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    /// Stable machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::ModelNotLoaded => "model_not_loaded",
+            ApiError::ModelLoadFailed(_) => "model_load_failed",
+            ApiError::ModelConflict(_) => "model_conflict",
+            ApiError::InvalidRequest(_) => "invalid_request",
+            ApiError::MissingAudio(_) => "missing_audio",
+            ApiError::EmptyAudio => "empty_audio",
+            ApiError::UnsupportedMediaType => "unsupported_media_type",
+            ApiError::UnsupportedFormat(_) => "unsupported_format",
+            ApiError::AudioTooLarge => "audio_too_large",
+            ApiError::InvalidAudio(_) => "invalid_audio",
+            ApiError::InvalidMessage(_) => "invalid_message",
+            ApiError::JobNotFound(_) => "job_not_found",
+            ApiError::TranscriptNotFound(_) => "transcript_not_found",
+            ApiError::AudioNotRetained(_) => "audio_not_retained",
+            ApiError::UploadNotFound(_) => "upload_not_found",
+            ApiError::UploadConflict(_) => "upload_conflict",
+            ApiError::JobsRunning(_) => "jobs_running",
+            ApiError::KeyNotFound(_) => "key_not_found",
+            ApiError::FfmpegUnavailable(_) => "ffmpeg_unavailable",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::JwksFailed(_) => "jwks_failed",
+            ApiError::QuotaExceeded(_) => "quota_exceeded",
+            ApiError::TooManyStreams(_) => "too_many_streams",
+            ApiError::Overloaded(_) => "overloaded",
+            ApiError::LlmUnavailable => "llm_unavailable",
+            ApiError::LlmFailed(_) => "llm_failed",
+            ApiError::EmbeddingsUnavailable => "embeddings_unavailable",
+            ApiError::EmbeddingsFailed(_) => "embeddings_failed",
+            ApiError::RecordingUnavailable => "recording_unavailable",
+            ApiError::WebhookUnavailable => "webhook_unavailable",
+            ApiError::EmailUnavailable => "email_unavailable",
+            ApiError::NotAcceptable(_) => "not_acceptable",
+            ApiError::WorkerUnreachable(_) => "worker_unreachable",
+            ApiError::TranscriptionFailed(_) => "transcription_failed",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// HTTP status for this error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::ModelNotLoaded
+            | ApiError::FfmpegUnavailable(_)
+            | ApiError::LlmUnavailable
+            | ApiError::EmbeddingsUnavailable
+            | ApiError::RecordingUnavailable
+            | ApiError::WebhookUnavailable
+            | ApiError::EmailUnavailable
+            | ApiError::Overloaded(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::InvalidRequest(_)
+            | ApiError::MissingAudio(_)
+            | ApiError::EmptyAudio
+            | ApiError::InvalidAudio(_)
+            | ApiError::InvalidMessage(_) => StatusCode::BAD_REQUEST,
+            ApiError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::NotAcceptable(_) => StatusCode::NOT_ACCEPTABLE,
+            ApiError::UnsupportedFormat(_) | ApiError::ModelLoadFailed(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ApiError::AudioTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::JobNotFound(_)
+            | ApiError::TranscriptNotFound(_)
+            | ApiError::AudioNotRetained(_)
+            | ApiError::UploadNotFound(_)
+            | ApiError::KeyNotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::UploadConflict(_)
+            | ApiError::ModelConflict(_)
+            | ApiError::JobsRunning(_) => StatusCode::CONFLICT,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::QuotaExceeded(_) => StatusCode::PAYMENT_REQUIRED,
+            ApiError::TooManyStreams(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::LlmFailed(_)
+            | ApiError::EmbeddingsFailed(_)
+            | ApiError::JwksFailed(_)
+            | ApiError::WorkerUnreachable(_) => StatusCode::BAD_GATEWAY,
+            ApiError::TranscriptionFailed(_) | ApiError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// Short human-readable summary of the error type.
+    fn title(&self) -> &'static str {
+        match self {
+            ApiError::ModelNotLoaded => "Model not loaded",
+            ApiError::ModelLoadFailed(_) => "Model load failed",
+            ApiError::ModelConflict(_) => "Model conflict",
+            ApiError::InvalidRequest(_) => "Invalid request",
+            ApiError::MissingAudio(_) => "Missing audio",
+            ApiError::EmptyAudio => "Empty audio",
+            ApiError::UnsupportedMediaType => "Unsupported media type",
+            ApiError::UnsupportedFormat(_) => "Unsupported audio format",
+            ApiError::AudioTooLarge => "Audio too large",
+            ApiError::InvalidAudio(_) => "Invalid audio",
+            ApiError::InvalidMessage(_) => "Invalid message",
+            ApiError::JobNotFound(_) => "Job not found",
+            ApiError::TranscriptNotFound(_) => "Transcript not found",
+            ApiError::AudioNotRetained(_) => "Audio not retained",
+            ApiError::UploadNotFound(_) => "Upload not found",
+            ApiError::UploadConflict(_) => "Upload conflict",
+            ApiError::JobsRunning(_) => "Jobs running",
+            ApiError::KeyNotFound(_) => "API key not found",
+            ApiError::FfmpegUnavailable(_) => "ffmpeg unavailable",
+            ApiError::Unauthorized(_) => "Unauthorized",
+            ApiError::Forbidden(_) => "Forbidden",
+            ApiError::JwksFailed(_) => "Key set unavailable",
+            ApiError::QuotaExceeded(_) => "Quota exceeded",
+            ApiError::TooManyStreams(_) => "Too many streams",
+            ApiError::Overloaded(_) => "Server overloaded",
+            ApiError::LlmUnavailable => "LLM unavailable",
+            ApiError::LlmFailed(_) => "LLM request failed",
+            ApiError::EmbeddingsUnavailable => "Semantic search unavailable",
+            ApiError::EmbeddingsFailed(_) => "Embeddings request failed",
+            ApiError::RecordingUnavailable => "Recording unavailable",
+            ApiError::WebhookUnavailable => "Webhooks unavailable",
+            ApiError::EmailUnavailable => "Email unavailable",
+            ApiError::NotAcceptable(_) => "Not acceptable",
+            ApiError::WorkerUnreachable(_) => "Worker unreachable",
+            ApiError::TranscriptionFailed(_) => "Transcription failed",
+            ApiError::Internal(_) => "Internal error",
+        }
+    }
+
+    /// RFC 7807 problem details for this error.
+    pub fn problem(&self) -> Problem {
+        Problem {
+            type_uri: format!("urn:voicemark:error:{}", self.code()),
+            title: self.title().to_string(),
+            status: self.status().as_u16(),
+            detail: self.to_string(),
+            code: self.code().to_string(),
+        }
+    }
+
+    /// Map an extractor rejection to an error by its status.
+    fn from_rejection(status: StatusCode, text: String) -> Self {
+        match status {
+            StatusCode::PAYLOAD_TOO_LARGE => ApiError::AudioTooLarge,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => ApiError::UnsupportedMediaType,
+            _ => ApiError::InvalidRequest(text),
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::from_rejection(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::InvalidRequest(rejection.body_text())
+    }
+}
+
+impl From<BytesRejection> for ApiError {
+    fn from(rejection: BytesRejection) -> Self {
+        ApiError::from_rejection(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<MultipartRejection> for ApiError {
+    fn from(rejection: MultipartRejection) -> Self {
+        ApiError::from_rejection(rejection.status(), rejection.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        (
+            status,
+            [(header::CONTENT_TYPE, PROBLEM_CONTENT_TYPE)],
+            Json(self.problem()),
+        )
+            .into_response()
+    }
+}
+
+/// RFC 7807 problem details body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Problem {
+    /// URI identifying the problem type (`urn:voicemark:error:<code>`).
+    #[serde(rename = "type")]
+    pub type_uri: String,
+    /// Short summary of the problem type.
+    pub title: String,
+    /// HTTP status code.
+    pub status: u16,
+    /// Explanation specific to this occurrence.
+    pub detail: String,
+    /// Stable machine-readable error code.
+    pub code: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_problem_fields() {
+        let problem = ApiError::JobNotFound("abc".to_string()).problem();
+        assert_eq!(problem.type_uri, "urn:voicemark:error:job_not_found");
+        assert_eq!(problem.title, "Job not found");
+        assert_eq!(problem.status, 404);
+        assert_eq!(problem.detail, "Job 'abc' not found");
+        assert_eq!(problem.code, "job_not_found");
+
+        let json = serde_json::to_value(&problem).unwrap();
+        assert_eq!(json["type"], "urn:voicemark:error:job_not_found");
+    }
+
+    #[tokio::test]
+    async fn test_into_response_uses_problem_json() {
+        let response = ApiError::ModelNotLoaded.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            PROBLEM_CONTENT_TYPE
+        );
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let problem: Problem = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem.code, "model_not_loaded");
+    }
+
+    #[test]
+    fn test_rejection_status_mapping() {
+        let too_large = ApiError::from_rejection(StatusCode::PAYLOAD_TOO_LARGE, String::new());
+        assert_eq!(too_large.code(), "audio_too_large");
+        let other = ApiError::from_rejection(StatusCode::BAD_REQUEST, "bad".to_string());
+        assert_eq!(other.code(), "invalid_request");
+    }
+}
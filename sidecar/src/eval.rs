@@ -0,0 +1,325 @@
+//! `voicemark-sidecar eval` subcommand.
+//!
+//! Transcribes a directory of clips with the local model and scores each
+//! against its reference transcript by word error rate (see
+//! [`crate::wordiff`]), so accuracy can be tracked across changes to models,
+//! VAD or chunking.
+//!
+//! ```bash
+//! voicemark-sidecar eval --audio-dir ./clips --refs refs.jsonl [--model PATH]
+//!     [--language CODE] [--json] [--max-wer RATE]
+//! ```
+//!
+//! Every line of `refs.jsonl` names a clip, relative to `--audio-dir`, and
+//! its reference: `{"file": "clip1.wav", "text": "..."}`. Transcription uses
+//! the same settings as `POST /transcribe`, including the `VOICEMARK_*`
+//! decoder and CPU settings.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tracing::info;
+
+use crate::audio;
+use crate::config::Config;
+use crate::transcribe;
+use crate::wordiff::{self, ErrorCounts};
+use crate::BatchQuery;
+
+/// Audio sample rate, for real-time factors.
+const SAMPLE_RATE: u64 = 16000;
+
+const USAGE: &str = "Usage: voicemark-sidecar eval --audio-dir DIR --refs FILE.jsonl \
+                     [--model PATH] [--language CODE] [--json] [--max-wer RATE]";
+
+/// Command-line options.
+#[derive(Debug, Default, PartialEq)]
+struct EvalOptions {
+    audio_dir: PathBuf,
+    refs: PathBuf,
+    /// Model to evaluate (default: `VOICEMARK_MODEL_PATH`).
+    model: Option<String>,
+    language: Option<String>,
+    /// Print the report as JSON instead of a table.
+    json: bool,
+    /// Fail if the overall word error rate exceeds this (e.g. `0.15`).
+    max_wer: Option<f64>,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<EvalOptions> {
+    let mut options = EvalOptions::default();
+    let (mut audio_dir, mut refs) = (None, None);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{} needs a value\n{}", arg, USAGE));
+        match arg.as_str() {
+            "--audio-dir" => audio_dir = Some(PathBuf::from(value()?)),
+            "--refs" => refs = Some(PathBuf::from(value()?)),
+            "--model" => options.model = Some(value()?),
+            "--language" => options.language = Some(value()?),
+            "--json" => options.json = true,
+            "--max-wer" => options.max_wer = Some(value()?.parse().context("Invalid --max-wer")?),
+            _ => bail!("Unknown argument '{}'\n{}", arg, USAGE),
+        }
+    }
+    options.audio_dir = audio_dir.with_context(|| format!("--audio-dir is required\n{}", USAGE))?;
+    options.refs = refs.with_context(|| format!("--refs is required\n{}", USAGE))?;
+    Ok(options)
+}
+
+/// A line of the references file.
+#[derive(Debug, PartialEq, Deserialize)]
+struct Reference {
+    file: String,
+    text: String,
+}
+
+/// Read a JSON Lines references file; blank lines are skipped.
+fn read_refs(path: &Path) -> Result<Vec<Reference>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{}:{}: invalid reference", path.display(), i + 1))
+        })
+        .collect()
+}
+
+/// Score of one clip.
+#[derive(Debug, Serialize)]
+struct FileScore {
+    file: String,
+    #[serde(flatten)]
+    counts: ErrorCounts,
+    wer: f64,
+    duration_ms: u64,
+    elapsed_ms: u64,
+    rtf: f64,
+    /// The transcription, to inspect errors.
+    text: String,
+}
+
+/// A clip that couldn't be transcribed.
+#[derive(Debug, Serialize)]
+struct FileFailure {
+    file: String,
+    error: String,
+}
+
+/// Scores of every clip, and overall.
+#[derive(Debug, Serialize)]
+struct EvalReport {
+    files: Vec<FileScore>,
+    failed: Vec<FileFailure>,
+    /// Error counts summed over all clips; `wer` is their overall rate,
+    /// weighting clips by length.
+    total: ErrorCounts,
+    wer: f64,
+    duration_ms: u64,
+    elapsed_ms: u64,
+    rtf: f64,
+}
+
+impl EvalReport {
+    fn new(files: Vec<FileScore>, failed: Vec<FileFailure>) -> Self {
+        let mut total = ErrorCounts::default();
+        for score in &files {
+            total += score.counts;
+        }
+        let duration_ms = files.iter().map(|score| score.duration_ms).sum();
+        let elapsed_ms = files.iter().map(|score| score.elapsed_ms).sum();
+        Self {
+            wer: total.wer(),
+            rtf: rtf(elapsed_ms, duration_ms),
+            files,
+            failed,
+            total,
+            duration_ms,
+            elapsed_ms,
+        }
+    }
+
+    /// The report as a table, one clip per line.
+    fn table(&self) -> String {
+        let width = self
+            .files
+            .iter()
+            .map(|score| score.file.len())
+            .chain([5])
+            .max()
+            .unwrap_or(5);
+        let row = |name: &str, counts: &ErrorCounts, wer: f64, rtf: f64| {
+            format!(
+                "{:<width$}  {:>6}  {:>5}  {:>5}  {:>5}  {:>6.1}%  {:>5.2}\n",
+                name,
+                counts.reference_words,
+                counts.substitutions,
+                counts.deletions,
+                counts.insertions,
+                wer * 100.0,
+                rtf,
+            )
+        };
+
+        let mut table = format!(
+            "{:<width$}  {:>6}  {:>5}  {:>5}  {:>5}  {:>7}  {:>5}\n",
+            "file", "words", "sub", "del", "ins", "WER", "RTF"
+        );
+        for score in &self.files {
+            table += &row(&score.file, &score.counts, score.wer, score.rtf);
+        }
+        table += &row("total", &self.total, self.wer, self.rtf);
+        for failure in &self.failed {
+            table += &format!("{}: failed: {}\n", failure.file, failure.error);
+        }
+        table
+    }
+}
+
+fn rtf(elapsed_ms: u64, duration_ms: u64) -> f64 {
+    if duration_ms > 0 { elapsed_ms as f64 / duration_ms as f64 } else { 0.0 }
+}
+
+/// Run the subcommand with the arguments following `eval`. Blocks.
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<()> {
+    let options = parse_args(args)?;
+    let refs = read_refs(&options.refs)?;
+    if refs.is_empty() {
+        bail!("No references in {}", options.refs.display());
+    }
+
+    let config = Config::from_env()?;
+    transcribe::set_acceleration(config.acceleration);
+    transcribe::set_cpu_limits(config.cpu_limits());
+    transcribe::set_decoding_defaults(config.decoding.clone());
+    transcribe::init_model(options.model.as_deref().or(config.model_path.as_deref()))?;
+    audio::configure_ffmpeg(config.ffmpeg.clone());
+
+    let batch = BatchQuery { language: options.language.clone(), ..Default::default() };
+    let transcribe_options = crate::batch_options(batch, Default::default(), Default::default())
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+
+    let mut files = Vec::with_capacity(refs.len());
+    let mut failed = Vec::new();
+    for reference in refs {
+        let path = options.audio_dir.join(&reference.file);
+        let transcribed = std::fs::read(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))
+            .and_then(|bytes| {
+                crate::decode_upload(&bytes, None, &transcribe_options)
+                    .map_err(|e| anyhow::anyhow!("{}", e))
+            })
+            .and_then(|samples| {
+                let started = Instant::now();
+                let result = transcribe::transcribe(&samples, transcribe_options.clone())?;
+                Ok((samples.len(), started.elapsed(), result.text))
+            });
+        match transcribed {
+            Ok((samples, elapsed, text)) => {
+                let counts = wordiff::error_counts(&reference.text, &text);
+                let duration_ms = samples as u64 * 1000 / SAMPLE_RATE;
+                let elapsed_ms = elapsed.as_millis() as u64;
+                info!(file = %reference.file, wer = counts.wer(), "Clip evaluated");
+                files.push(FileScore {
+                    file: reference.file,
+                    wer: counts.wer(),
+                    counts,
+                    duration_ms,
+                    elapsed_ms,
+                    rtf: rtf(elapsed_ms, duration_ms),
+                    text,
+                });
+            }
+            Err(e) => failed.push(FileFailure { file: reference.file, error: format!("{:#}", e) }),
+        }
+    }
+
+    let report = EvalReport::new(files, failed);
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report.table());
+    }
+
+    if !report.failed.is_empty() {
+        let clips = report.failed.len() + report.files.len();
+        bail!("{} of {} clips failed", report.failed.len(), clips);
+    }
+    if let Some(max_wer) = options.max_wer {
+        if report.wer > max_wer {
+            bail!("WER {:.4} exceeds --max-wer {}", report.wer, max_wer);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options =
+            parse_args(args(&["--audio-dir", "clips", "--refs", "refs.jsonl", "--max-wer", "0.2"]))
+                .unwrap();
+        assert_eq!(options.audio_dir, PathBuf::from("clips"));
+        assert_eq!(options.max_wer, Some(0.2));
+        assert!(!options.json);
+        assert!(parse_args(args(&["--audio-dir", "clips"])).is_err());
+        assert!(parse_args(args(&["--refs", "refs.jsonl", "--bogus"])).is_err());
+    }
+
+    #[test]
+    fn test_read_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("refs.jsonl");
+        std::fs::write(
+            &path,
+            "{\"file\": \"a.wav\", \"text\": \"hello\"}\n\n{\"file\": \"b.wav\", \"text\": \"\"}\n",
+        )
+        .unwrap();
+        let refs = read_refs(&path).unwrap();
+        assert_eq!(refs[0], Reference { file: "a.wav".to_string(), text: "hello".to_string() });
+        assert_eq!(refs.len(), 2);
+
+        std::fs::write(&path, "{\"file\": \"a.wav\"}\n").unwrap();
+        assert!(read_refs(&path).unwrap_err().to_string().contains("refs.jsonl:1"));
+    }
+
+    #[test]
+    fn test_report_weights_clips_by_length() {
+        let score = |file: &str, reference: &str, text: &str| {
+            let counts = wordiff::error_counts(reference, text);
+            FileScore {
+                file: file.to_string(),
+                wer: counts.wer(),
+                counts,
+                duration_ms: 1000,
+                elapsed_ms: 250,
+                rtf: 0.25,
+                text: text.to_string(),
+            }
+        };
+        let report = EvalReport::new(
+            vec![
+                score("short.wav", "yes", "no"),
+                score("long.wav", "one two three", "one two three"),
+            ],
+            Vec::new(),
+        );
+        assert!((report.wer - 0.25).abs() < 1e-9);
+        assert!((report.rtf - 0.25).abs() < 1e-9);
+        let table = report.table();
+        assert!(table.contains("short.wav"));
+        assert!(table.lines().last().unwrap().starts_with("total"));
+    }
+}
@@ -0,0 +1,172 @@
+//! Transcript events for VoiceMark sidecar.
+//!
+//! Every completed transcription (a `/transcribe` request, a job, or a
+//! `/stream` session ended with `end`) is published as a
+//! [`TranscriptEvent`] to the configured sinks, for data platforms that
+//! ingest all transcripts. Sinks are optional features:
+//!
+//! - Kafka (`kafka` feature, see [`crate::kafka`])
+//!
+//! Publishing never blocks or fails a transcription; events a sink can't
+//! deliver are logged and dropped.
+
+use serde::Serialize;
+
+use crate::clock::now_millis;
+use crate::jobs::JobMetadata;
+use crate::remote::{self, Backend};
+use crate::transcribe::{Segment, TranscribeResult};
+
+/// Version of the event format, bumped on breaking changes.
+pub const EVENT_VERSION: u32 = 1;
+
+/// What produced a transcript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// `POST /transcribe`, `/transcribe/json`, `/transcribe/stream`, or a
+    /// NATS request.
+    Transcribe,
+    /// A `/jobs` job.
+    Job,
+    /// A `/stream` session.
+    Stream,
+}
+
+/// A completed transcription.
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptEvent {
+    pub event_version: u32,
+    /// Unique event ID; the transcript ID when the transcript is persisted.
+    pub id: String,
+    pub source: Source,
+    /// Completion time (Unix milliseconds).
+    pub created_at: u64,
+    pub text: String,
+    /// Timed segments, in milliseconds from the start of the audio.
+    pub segments: Vec<Segment>,
+    /// Length of the audio, when known (streams).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_probability: Option<f32>,
+    /// Backend that transcribed the audio.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<Backend>,
+    /// Model the transcript was produced with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Metadata and tags of the job, if any.
+    #[serde(flatten)]
+    pub metadata: JobMetadata,
+}
+
+impl TranscriptEvent {
+    /// The event for a batch transcription. `id` is the persisted
+    /// transcript's, if any.
+    pub fn batch(
+        source: Source,
+        id: Option<&str>,
+        result: &TranscribeResult,
+        backend: Backend,
+        metadata: &JobMetadata,
+    ) -> Self {
+        Self {
+            event_version: EVENT_VERSION,
+            id: id.map(str::to_string).unwrap_or_else(new_id),
+            source,
+            created_at: now_millis(),
+            text: result.text.clone(),
+            segments: result.timed_segments.clone(),
+            duration_ms: None,
+            language: result.language.clone(),
+            language_probability: result.language_probability,
+            backend: Some(backend),
+            model: remote::model_id(),
+            metadata: metadata.clone(),
+        }
+    }
+
+    /// The event for a `/stream` session, from its finals.
+    pub fn stream(
+        text: String,
+        segments: Vec<Segment>,
+        duration_ms: i64,
+        language: Option<String>,
+    ) -> Self {
+        Self {
+            event_version: EVENT_VERSION,
+            id: new_id(),
+            source: Source::Stream,
+            created_at: now_millis(),
+            text,
+            segments,
+            duration_ms: Some(duration_ms),
+            language,
+            language_probability: None,
+            backend: remote::backend(),
+            model: remote::model_id(),
+            metadata: JobMetadata::default(),
+        }
+    }
+}
+
+fn new_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Whether any sink is configured, so callers can skip building events.
+pub fn enabled() -> bool {
+    #[cfg(feature = "kafka")]
+    if crate::kafka::enabled() {
+        return true;
+    }
+    false
+}
+
+/// Hand `event` to every configured sink.
+pub fn publish(event: TranscriptEvent) {
+    #[cfg(feature = "kafka")]
+    crate::kafka::send(&event);
+    #[cfg(not(feature = "kafka"))]
+    let _ = event;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_event_format() {
+        let result = TranscribeResult {
+            text: "Hello world".to_string(),
+            segments: 1,
+            avg_token_prob: 0.9,
+            timed_segments: vec![Segment { start_ms: 0, end_ms: 900, text: "Hello world".into() }],
+            words: Vec::new(),
+            tokens: Vec::new(),
+            language: Some("en".to_string()),
+            language_probability: None,
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let metadata: JobMetadata =
+            serde_json::from_str(r#"{"metadata":{"call_id":"c-1"},"tags":["support"]}"#).unwrap();
+        let event =
+            TranscriptEvent::batch(Source::Job, Some("t-1"), &result, Backend::Local, &metadata);
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event_version"], EVENT_VERSION);
+        assert_eq!(json["id"], "t-1");
+        assert_eq!(json["source"], "job");
+        assert_eq!(json["segments"][0]["end_ms"], 900);
+        assert_eq!(json["language"], "en");
+        assert_eq!(json["backend"], "local");
+        assert_eq!(json["metadata"]["call_id"], "c-1");
+        assert_eq!(json["tags"][0], "support");
+        assert!(json.get("duration_ms").is_none());
+    }
+}
@@ -0,0 +1,679 @@
+//! DOCX and PDF export of transcripts.
+//!
+//! Transcripts usually end up with people who read them in Word or print
+//! them, not in a player. `GET /transcripts/:id/export?format=docx|pdf`
+//! renders a stored transcript as a [`Document`]: a title, a heading per
+//! chapter (for jobs run with `chapters=true`), and a paragraph per turn,
+//! headed by the speaker's name and the time it starts.
+//!
+//! Both formats are written directly. DOCX is a zip of WordprocessingML
+//! parts using Word's built-in `Title` and `Heading 1` styles, so chapters
+//! show up in the navigation pane and tables of contents. PDF uses the
+//! standard Helvetica fonts, which every reader has, so no font is
+//! embedded; they only cover Latin-1, and other characters are written as
+//! `?` (export DOCX for other scripts).
+//!
+//! Finished jobs that are emailed or written out by the watch folders use a
+//! [`JobTranscript`], which can also be written as SRT or plain text.
+
+use anyhow::{Result, bail};
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use serde::Deserialize;
+use std::io::{Cursor, Write};
+use std::str::FromStr;
+use utoipa::ToSchema;
+use zip::write::SimpleFileOptions;
+
+use crate::negotiate::{self, Cue};
+use crate::transcribe::Segment;
+
+/// A document format transcripts can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Word document.
+    Docx,
+    /// PDF, A4.
+    Pdf,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Docx => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            }
+            ExportFormat::Pdf => "application/pdf",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Docx => "docx",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+}
+
+/// A format a finished job's transcript can be written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    /// Word document.
+    #[default]
+    Docx,
+    /// PDF, A4.
+    Pdf,
+    /// SRT subtitles.
+    Srt,
+    /// Plain text.
+    Txt,
+}
+
+impl FileFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            FileFormat::Docx => ExportFormat::Docx.content_type(),
+            FileFormat::Pdf => ExportFormat::Pdf.content_type(),
+            FileFormat::Srt => "application/x-subrip",
+            FileFormat::Txt => "text/plain; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            FileFormat::Docx => "docx",
+            FileFormat::Pdf => "pdf",
+            FileFormat::Srt => "srt",
+            FileFormat::Txt => "txt",
+        }
+    }
+}
+
+impl FromStr for FileFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "docx" => Ok(FileFormat::Docx),
+            "pdf" => Ok(FileFormat::Pdf),
+            "srt" => Ok(FileFormat::Srt),
+            "txt" => Ok(FileFormat::Txt),
+            other => bail!("Unknown transcript format '{}' (docx, pdf, srt or txt)", other),
+        }
+    }
+}
+
+/// A finished job's transcript: as a `document` for DOCX and PDF, and as
+/// its `text` and timed `segments` for the others.
+pub struct JobTranscript {
+    pub document: Document,
+    pub text: String,
+    pub segments: Vec<Segment>,
+}
+
+impl JobTranscript {
+    /// The transcript as a file in `format`.
+    pub fn render(&self, format: FileFormat) -> Result<Vec<u8>> {
+        Ok(match format {
+            FileFormat::Docx => render(&self.document, ExportFormat::Docx)?,
+            FileFormat::Pdf => render(&self.document, ExportFormat::Pdf)?,
+            FileFormat::Srt => negotiate::srt(self.segments.iter().map(|segment| Cue {
+                start_ms: segment.start_ms,
+                end_ms: segment.end_ms,
+                text: &segment.text,
+            }))
+            .into_bytes(),
+            FileFormat::Txt => format!("{}\n", self.text.trim()).into_bytes(),
+        })
+    }
+}
+
+/// A transcript laid out for reading.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    pub title: String,
+    /// Line under the title, e.g. when the transcript was made.
+    pub subtitle: Option<String>,
+    pub sections: Vec<Section>,
+}
+
+/// A chapter, or the whole transcript if it has none.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Section {
+    pub heading: Option<String>,
+    pub turns: Vec<Turn>,
+}
+
+/// What one speaker said without interruption or a long pause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    pub speaker: Option<String>,
+    /// Start of the turn, in milliseconds.
+    pub start_ms: i64,
+    pub text: String,
+}
+
+impl Turn {
+    /// The line above the turn's text: speaker, then start time.
+    fn label(&self) -> (Option<&str>, String) {
+        (self.speaker.as_deref(), clock(self.start_ms))
+    }
+}
+
+/// `H:MM:SS`, or `MM:SS` under an hour.
+pub fn clock(ms: i64) -> String {
+    let secs = ms.max(0) / 1000;
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Render `document` in `format`.
+pub fn render(document: &Document, format: ExportFormat) -> Result<Vec<u8>> {
+    match format {
+        ExportFormat::Docx => docx(document),
+        ExportFormat::Pdf => pdf(document),
+    }
+}
+
+const CONTENT_TYPES: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    "\n",
+    r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">"#,
+    r#"<Default Extension="rels""#,
+    r#" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>"#,
+    r#"<Default Extension="xml" ContentType="application/xml"/>"#,
+    r#"<Override PartName="/word/document.xml""#,
+    r#" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml"#,
+    r#".document.main+xml"/>"#,
+    r#"<Override PartName="/word/styles.xml""#,
+    r#" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml"/>"#,
+    r#"</Types>"#,
+);
+
+const PACKAGE_RELS: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    "\n",
+    r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    r#"<Relationship Id="rId1""#,
+    r#" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument""#,
+    r#" Target="word/document.xml"/>"#,
+    r#"</Relationships>"#,
+);
+
+const DOCUMENT_RELS: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    "\n",
+    r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    r#"<Relationship Id="rId1""#,
+    r#" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles""#,
+    r#" Target="styles.xml"/>"#,
+    r#"</Relationships>"#,
+);
+
+const STYLES: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    "\n",
+    r#"<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">"#,
+    r#"<w:docDefaults><w:rPrDefault><w:rPr>"#,
+    r#"<w:rFonts w:ascii="Calibri" w:hAnsi="Calibri" w:cs="Calibri"/><w:sz w:val="22"/>"#,
+    r#"</w:rPr></w:rPrDefault><w:pPrDefault><w:pPr>"#,
+    r#"<w:spacing w:after="160" w:line="259" w:lineRule="auto"/></w:pPr></w:pPrDefault>"#,
+    r#"</w:docDefaults><w:style w:type="paragraph" w:default="1" w:styleId="Normal">"#,
+    r#"<w:name w:val="Normal"/><w:qFormat/></w:style>"#,
+    r#"<w:style w:type="paragraph" w:styleId="Title"><w:name w:val="Title"/>"#,
+    r#"<w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr>"#,
+    r#"<w:spacing w:after="80"/></w:pPr><w:rPr><w:sz w:val="48"/></w:rPr></w:style>"#,
+    r#"<w:style w:type="paragraph" w:styleId="Subtitle"><w:name w:val="Subtitle"/>"#,
+    r#"<w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:rPr>"#,
+    r#"<w:color w:val="595959"/></w:rPr></w:style>"#,
+    r#"<w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/>"#,
+    r#"<w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:qFormat/><w:pPr><w:keepNext/>"#,
+    r#"<w:spacing w:before="360" w:after="120"/><w:outlineLvl w:val="0"/></w:pPr><w:rPr>"#,
+    r#"<w:b/><w:sz w:val="32"/></w:rPr></w:style></w:styles>"#,
+);
+
+/// `document` as a Word document.
+fn docx(document: &Document) -> Result<Vec<u8>> {
+    let mut body = String::new();
+    let styled = |body: &mut String, style: &str, text: &str| {
+        body.push_str(&format!(
+            r#"<w:p><w:pPr><w:pStyle w:val="{}"/></w:pPr>{}</w:p>"#,
+            style,
+            run(text, "")
+        ));
+    };
+    styled(&mut body, "Title", &document.title);
+    if let Some(subtitle) = &document.subtitle {
+        styled(&mut body, "Subtitle", subtitle);
+    }
+    for section in &document.sections {
+        if let Some(heading) = &section.heading {
+            styled(&mut body, "Heading1", heading);
+        }
+        for turn in &section.turns {
+            let (speaker, time) = turn.label();
+            body.push_str("<w:p>");
+            if let Some(speaker) = speaker {
+                body.push_str(&run(&format!("{}  ", speaker), "<w:b/>"));
+            }
+            body.push_str(&run(&time, r#"<w:color w:val="595959"/>"#));
+            body.push_str("<w:r><w:br/></w:r>");
+            body.push_str(&run(&turn.text, ""));
+            body.push_str("</w:p>");
+        }
+    }
+    let document = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <w:document xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">\
+         <w:body>{}<w:sectPr><w:pgSz w:w=\"11906\" w:h=\"16838\"/><w:pgMar w:top=\"1134\" \
+         w:right=\"1134\" w:bottom=\"1134\" w:left=\"1134\" w:header=\"708\" w:footer=\"708\" \
+         w:gutter=\"0\"/></w:sectPr></w:body></w:document>",
+        body
+    );
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+    for (name, part) in [
+        ("[Content_Types].xml", CONTENT_TYPES),
+        ("_rels/.rels", PACKAGE_RELS),
+        ("word/_rels/document.xml.rels", DOCUMENT_RELS),
+        ("word/styles.xml", STYLES),
+        ("word/document.xml", &document),
+    ] {
+        zip.start_file(name, options)?;
+        zip.write_all(part.as_bytes())?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// A WordprocessingML run of `text` with run properties `properties`.
+fn run(text: &str, properties: &str) -> String {
+    let properties = if properties.is_empty() {
+        String::new()
+    } else {
+        format!("<w:rPr>{}</w:rPr>", properties)
+    };
+    format!(r#"<w:r>{}<w:t xml:space="preserve">{}</w:t></w:r>"#, properties, xml_escape(text))
+}
+
+/// `text` escaped for XML, without the control characters XML forbids.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(' '),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A4, in points.
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+/// Page margins (2 cm).
+const MARGIN: f32 = 56.7;
+const BODY_SIZE: f32 = 11.0;
+const HEADING_SIZE: f32 = 16.0;
+const TITLE_SIZE: f32 = 22.0;
+/// Line height, as a multiple of the font size.
+const LEADING: f32 = 1.35;
+
+/// Widths of Helvetica's printable ASCII characters, in 1/1000 em.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Bold characters are at most this much wider than regular ones.
+const BOLD_WIDTH: f32 = 1.1;
+
+/// A text run on a PDF line.
+struct Span<'a> {
+    text: &'a [u8],
+    bold: bool,
+}
+
+/// Pages of a PDF being laid out, top to bottom.
+struct Layout {
+    pages: Vec<String>,
+    /// Baseline of the last line on the current page.
+    y: f32,
+}
+
+impl Layout {
+    fn new() -> Self {
+        Self { pages: vec![String::new()], y: PAGE_HEIGHT - MARGIN }
+    }
+
+    /// Start a new page unless `height` more fits on this one.
+    fn keep(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            self.pages.push(String::new());
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+    }
+
+    /// Leave `space` before what follows, unless at the top of a page.
+    fn space(&mut self, space: f32) {
+        if self.y < PAGE_HEIGHT - MARGIN {
+            self.y -= space;
+        }
+    }
+
+    /// Write one line of `spans` in `size`.
+    fn line(&mut self, spans: &[Span], size: f32) {
+        let height = size * LEADING;
+        self.keep(height);
+        self.y -= height;
+        let page = self.pages.last_mut().expect("layout has a page");
+        page.push_str(&format!("BT {:.1} {:.1} Td", MARGIN, self.y));
+        for span in spans {
+            let font = if span.bold { "F2" } else { "F1" };
+            page.push_str(&format!(" /{} {} Tf ({}) Tj", font, size, pdf_string(span.text)));
+        }
+        page.push_str(" ET\n");
+    }
+
+    /// Write `text` wrapped to the page width.
+    fn paragraph(&mut self, text: &str, size: f32, bold: bool) {
+        let text = win_ansi(text);
+        for line in wrap(&text, size, bold, PAGE_WIDTH - 2.0 * MARGIN) {
+            self.line(&[Span { text: line, bold }], size);
+        }
+    }
+}
+
+/// `text`'s words in lines no wider than `width` (except words that are
+/// wider on their own).
+fn wrap(text: &[u8], size: f32, bold: bool, width: f32) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut start = None;
+    let mut end = 0;
+    let mut line_width = 0.0;
+    let space = text_width(b" ", size, bold);
+    let mut offset = 0;
+    for word in text.split(|&b| b == b' ') {
+        let word_start = offset;
+        offset += word.len() + 1;
+        if word.is_empty() {
+            continue;
+        }
+        let word_width = text_width(word, size, bold);
+        match start {
+            Some(line_start) if line_width + space + word_width > width => {
+                lines.push(&text[line_start..end]);
+                start = Some(word_start);
+                line_width = word_width;
+            }
+            Some(_) => line_width += space + word_width,
+            None => {
+                start = Some(word_start);
+                line_width = word_width;
+            }
+        }
+        end = word_start + word.len();
+    }
+    if let Some(line_start) = start {
+        lines.push(&text[line_start..end]);
+    }
+    lines
+}
+
+/// Width of WinAnsi `text` in Helvetica of `size`, in points.
+fn text_width(text: &[u8], size: f32, bold: bool) -> f32 {
+    let em: u32 = text
+        .iter()
+        .map(|&b| match b {
+            32..=126 => HELVETICA_WIDTHS[(b - 32) as usize] as u32,
+            _ => 556,
+        })
+        .sum();
+    let width = em as f32 / 1000.0 * size;
+    if bold { width * BOLD_WIDTH } else { width }
+}
+
+/// `text` in WinAnsiEncoding, the standard fonts' encoding: Latin-1 and
+/// typographic punctuation, with whitespace runs collapsed and anything
+/// else replaced by `?`.
+fn win_ansi(text: &str) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(text.len());
+    for word in text.split_whitespace() {
+        if !encoded.is_empty() {
+            encoded.push(b' ');
+        }
+        encoded.extend(word.chars().map(|c| match c {
+            '€' => 0x80,
+            '…' => 0x85,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            ' '..='~' | '\u{a0}'..='ÿ' => c as u8,
+            _ => b'?',
+        }));
+    }
+    encoded
+}
+
+/// `text` as a PDF literal string body, in printable ASCII.
+fn pdf_string(text: &[u8]) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for &b in text {
+        match b {
+            b'(' | b')' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(b as char);
+            }
+            32..=126 => escaped.push(b as char),
+            _ => escaped.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    escaped
+}
+
+/// `document` as an A4 PDF.
+fn pdf(document: &Document) -> Result<Vec<u8>> {
+    let mut layout = Layout::new();
+    layout.paragraph(&document.title, TITLE_SIZE, true);
+    if let Some(subtitle) = &document.subtitle {
+        layout.paragraph(subtitle, BODY_SIZE, false);
+    }
+    for section in &document.sections {
+        if let Some(heading) = &section.heading {
+            layout.space(HEADING_SIZE);
+            // Keep the heading with the first line after it
+            layout.keep((HEADING_SIZE + BODY_SIZE * 2.0) * LEADING);
+            layout.paragraph(heading, HEADING_SIZE, true);
+        }
+        for turn in &section.turns {
+            layout.space(BODY_SIZE * 0.6);
+            layout.keep(BODY_SIZE * 2.0 * LEADING);
+            let (speaker, time) = turn.label();
+            let speaker = speaker.map(|speaker| [win_ansi(speaker), b"  ".to_vec()].concat());
+            let time = win_ansi(&time);
+            let mut label = Vec::new();
+            if let Some(speaker) = &speaker {
+                label.push(Span { text: speaker, bold: true });
+            }
+            label.push(Span { text: &time, bold: false });
+            layout.line(&label, BODY_SIZE);
+            layout.paragraph(&turn.text, BODY_SIZE, false);
+        }
+    }
+
+    let mut objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        Vec::new(), // The page tree, once the pages are numbered
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_vec(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+            .to_vec(),
+        format!(
+            "<< /Title ({}) /Producer (VoiceMark) >>",
+            pdf_string(&win_ansi(&document.title))
+        )
+        .into_bytes(),
+    ];
+    let mut kids = Vec::with_capacity(layout.pages.len());
+    for content in &layout.pages {
+        let page = objects.len() + 1;
+        kids.push(format!("{} 0 R", page));
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << \
+                 /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                page + 1
+            )
+            .into_bytes(),
+        );
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content.as_bytes())?;
+        let stream = encoder.finish()?;
+        let mut object =
+            format!("<< /Length {} /Filter /FlateDecode >>\nstream\n", stream.len()).into_bytes();
+        object.extend(stream);
+        object.extend(b"\nendstream");
+        objects.push(object);
+    }
+    objects[1] = format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        kids.join(" "),
+        layout.pages.len()
+    )
+    .into_bytes();
+
+    let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n", i + 1).as_bytes());
+        pdf.extend(object);
+        pdf.extend(b"\nendobj\n");
+    }
+    let xref = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        pdf.extend(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    pdf.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R /Info 5 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        )
+        .as_bytes(),
+    );
+    Ok(pdf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn document(turns: usize) -> Document {
+        let turn = |i: usize| Turn {
+            speaker: Some(["Ada", "Grace"][i % 2].to_string()),
+            start_ms: i as i64 * 61_000,
+            text: "We reviewed the budget & agreed on <next> steps. ".repeat(4),
+        };
+        Document {
+            title: "Planning – Q3".to_string(),
+            subtitle: Some("Transcribed 2026-10-16 14:03 UTC".to_string()),
+            sections: vec![
+                Section {
+                    heading: Some("Budget".to_string()),
+                    turns: (0..turns).map(turn).collect(),
+                },
+                Section { heading: Some("Hiring".to_string()), turns: vec![turn(turns)] },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_clock() {
+        assert_eq!(clock(0), "00:00");
+        assert_eq!(clock(61_500), "01:01");
+        assert_eq!(clock(3_723_000), "1:02:03");
+    }
+
+    #[test]
+    fn test_docx_has_styled_headings_and_escaped_text() {
+        let bytes = render(&document(2), ExportFormat::Docx).unwrap();
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        for part in ["[Content_Types].xml", "_rels/.rels", "word/styles.xml"] {
+            assert!(zip.by_name(part).is_ok(), "{}", part);
+        }
+        let mut xml = String::new();
+        zip.by_name("word/document.xml").unwrap().read_to_string(&mut xml).unwrap();
+        let paragraph = |style: &str, text: &str| {
+            format!(
+                r#"<w:p><w:pPr><w:pStyle w:val="{}"/></w:pPr><w:r><w:t xml:space="preserve">{}<"#,
+                style, text
+            )
+        };
+        assert!(xml.contains(&paragraph("Title", "Planning – Q3")));
+        assert!(xml.contains(&paragraph("Heading1", "Hiring")));
+        assert!(xml.contains("Grace  </w:t>"));
+        assert!(xml.contains("01:01"));
+        assert!(xml.contains("budget &amp; agreed on &lt;next&gt; steps"));
+    }
+
+    #[test]
+    fn test_pdf_pages_and_cross_references() {
+        let pdf = render(&document(60), ExportFormat::Pdf).unwrap();
+        assert!(pdf.starts_with(b"%PDF-1.4\n"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        let text = String::from_utf8_lossy(&pdf);
+        let count: usize = text
+            .split("/Count ")
+            .nth(1)
+            .and_then(|rest| rest.split(' ').next())
+            .and_then(|count| count.parse().ok())
+            .unwrap();
+        assert!(count > 1, "{} pages", count);
+
+        // Every cross-reference points at its object
+        let xref = text.rsplit("startxref\n").next().unwrap().lines().next().unwrap();
+        let xref: usize = xref.parse().unwrap();
+        let table = std::str::from_utf8(&pdf[xref..]).unwrap();
+        let entries = table.lines().skip(3).take_while(|line| line.ends_with(" n "));
+        for (i, entry) in entries.enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            let object = format!("{} 0 obj", i + 1);
+            assert!(pdf[offset..].starts_with(object.as_bytes()), "{}", object);
+        }
+    }
+
+    #[test]
+    fn test_wrap_and_encode() {
+        let text = win_ansi("Café  “quoted”\nline — 日本");
+        assert_eq!(text, b"Caf\xe9 \x93quoted\x94 line \x97 ??");
+        assert_eq!(pdf_string(b"(a\\b) \xe9"), "\\(a\\\\b\\) \\351");
+
+        let text = win_ansi(&"word ".repeat(100));
+        let lines = wrap(&text, BODY_SIZE, false, 200.0);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| text_width(line, BODY_SIZE, false) <= 200.0));
+        assert_eq!(lines.concat().len() + lines.len() - 1, text.len());
+    }
+}
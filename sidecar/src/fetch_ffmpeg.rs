@@ -0,0 +1,333 @@
+//! `voicemark-sidecar fetch-ffmpeg` subcommand.
+//!
+//! Downloads a static ffmpeg build for the current platform, verifies its
+//! SHA-256, and installs it as the bundled binary under `resources/`. The
+//! build and its hash are pinned in `resources/ffmpeg/pinned.sha256`, which
+//! is compiled in, so nothing fetched at run time decides what is trusted.
+//!
+//! ```bash
+//! voicemark-sidecar fetch-ffmpeg [--resources DIR] [--url URL --sha256 HEX]
+//! ```
+
+use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+use tracing::info;
+
+use crate::audio;
+
+/// Pinned builds: `<sha256>  <url>` lines, in a dated release.
+const PINNED: &str = include_str!("../resources/ffmpeg/pinned.sha256");
+
+/// Where pinned builds are downloaded from.
+const RELEASES_URL: &str = "https://github.com/BtbN/FFmpeg-Builds/releases/download/";
+
+/// Part of the archive name identifying this platform's static build.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const PLATFORM_BUILD: Option<&str> = Some("-linux64-gpl");
+
+#[cfg(all(target_os = "windows", target_arch = "x86_64"))]
+const PLATFORM_BUILD: Option<&str> = Some("-win64-gpl");
+
+/// No static build is published for this platform; `--url` is required.
+#[cfg(not(any(
+    all(target_os = "linux", target_arch = "x86_64"),
+    all(target_os = "windows", target_arch = "x86_64")
+)))]
+const PLATFORM_BUILD: Option<&str> = None;
+
+const USAGE: &str = "Usage: voicemark-sidecar fetch-ffmpeg [--resources DIR] [--url URL --sha256 HEX]";
+
+/// Command-line options.
+#[derive(Debug, Default, PartialEq)]
+struct FetchOptions {
+    /// Resources directory to install into (default: next to the executable).
+    resources: Option<PathBuf>,
+    /// Archive (`.tar.xz`, `.zip`) or bare binary to download instead.
+    url: Option<String>,
+    /// Expected SHA-256 of the download.
+    sha256: Option<String>,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<FetchOptions> {
+    let mut options = FetchOptions::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{} needs a value\n{}", arg, USAGE));
+        match arg.as_str() {
+            "--resources" => options.resources = Some(PathBuf::from(value()?)),
+            "--url" => options.url = Some(value()?),
+            "--sha256" => options.sha256 = Some(value()?.to_ascii_lowercase()),
+            _ => bail!("Unknown argument '{}'\n{}", arg, USAGE),
+        }
+    }
+    if options.url.is_some() && options.sha256.is_none() {
+        bail!("--url requires --sha256\n{}", USAGE);
+    }
+    Ok(options)
+}
+
+/// Run the subcommand with the arguments following `fetch-ffmpeg`.
+pub async fn run(args: impl IntoIterator<Item = String>) -> Result<()> {
+    let options = parse_args(args)?;
+    let client = reqwest::Client::new();
+
+    let (url, expected) = match (options.url, options.sha256) {
+        (Some(url), Some(sha256)) => (url, sha256),
+        (_, sha256) => {
+            let build = PLATFORM_BUILD.context(
+                "No static ffmpeg build is published for this platform; \
+                 pass --url and --sha256, or install ffmpeg on PATH",
+            )?;
+            let (pinned_sha256, url) = pinned_build(PINNED, build)?.with_context(|| {
+                format!(
+                    "No {} ffmpeg build is pinned in this release; \
+                     pass --url and --sha256, or install ffmpeg on PATH",
+                    build.trim_matches('-')
+                )
+            })?;
+            (url, sha256.unwrap_or(pinned_sha256))
+        }
+    };
+
+    let resources = match options.resources {
+        Some(dir) => dir,
+        None => audio::resources_dir()?,
+    };
+    let dest = audio::bundled_ffmpeg_in(&resources);
+
+    info!("Downloading ffmpeg from {}", url);
+    let (download, actual) = download(&client, &url).await?;
+    if actual != expected {
+        bail!("Checksum mismatch for {}: expected {}, got {}", url, expected, actual);
+    }
+    info!(sha256 = %actual, "Checksum verified");
+
+    let install_dest = dest.clone();
+    tokio::task::spawn_blocking(move || install(download.path(), &url, &install_dest)).await??;
+
+    info!(
+        path = ?dest,
+        version = ?audio::ffmpeg_version(&dest),
+        "Bundled ffmpeg installed"
+    );
+    Ok(())
+}
+
+/// Parse a `sha256sum`-style manifest of URLs (`<hex>  <url>`), skipping
+/// blank lines and `#` comments. Every URL must be in a dated release.
+fn parse_pinned(manifest: &str) -> Result<Vec<(String, String)>> {
+    let lines = manifest.lines().map(str::trim);
+    lines
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (hash, url) = line
+                .split_once(char::is_whitespace)
+                .with_context(|| format!("Invalid pinned build '{}'", line))?;
+            let url = url.trim().trim_start_matches('*');
+            if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                bail!("Invalid SHA-256 for {}", url);
+            }
+            let release = url.strip_prefix(RELEASES_URL).and_then(|rest| rest.split_once('/'));
+            if !release.is_some_and(|(tag, _)| tag.starts_with("autobuild-")) {
+                bail!("{} is not in a dated release", url);
+            }
+            Ok((hash.to_ascii_lowercase(), url.to_string()))
+        })
+        .collect()
+}
+
+/// The pinned SHA-256 and URL of the `build` archive in `manifest`.
+fn pinned_build(manifest: &str, build: &str) -> Result<Option<(String, String)>> {
+    let pinned = parse_pinned(manifest)?;
+    Ok(pinned.into_iter().find(|(_, url)| {
+        let file = url.rsplit('/').next().unwrap_or_default();
+        file.contains(build) && (file.ends_with(".tar.xz") || file.ends_with(".zip"))
+    }))
+}
+
+/// Stream `url` to a temp file, returning it with its SHA-256.
+async fn download(client: &reqwest::Client, url: &str) -> Result<(NamedTempFile, String)> {
+    let mut response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Failed to download {}", url))?;
+
+    let mut file = NamedTempFile::new().context("Failed to create temp download file")?;
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = response.chunk().await.context("Download interrupted")? {
+        hasher.update(&chunk);
+        file.write_all(&chunk).context("Failed to write download")?;
+    }
+    file.flush()?;
+    Ok((file, format!("{:x}", hasher.finalize())))
+}
+
+/// Extract the ffmpeg binary from the download and move it to `dest`.
+fn install(download: &Path, url: &str, dest: &Path) -> Result<()> {
+    let dir = dest.parent().context("Invalid destination")?;
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    // Write next to the destination so the final rename is atomic
+    let mut output = NamedTempFile::new_in(dir).context("Failed to create temp file")?;
+    if url.ends_with(".tar.xz") {
+        extract_tar_xz(download, output.as_file_mut())?;
+    } else if url.ends_with(".zip") {
+        extract_zip(download, output.as_file_mut())?;
+    } else {
+        io::copy(&mut File::open(download)?, output.as_file_mut())?;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(output.path(), std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    output
+        .persist(dest)
+        .with_context(|| format!("Failed to install {}", dest.display()))?;
+    Ok(())
+}
+
+/// Whether an archive entry is the ffmpeg executable.
+fn is_ffmpeg_entry(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == audio::FFMPEG_EXE)
+}
+
+fn extract_tar_xz(archive: &Path, output: &mut File) -> Result<()> {
+    let mut tar_file = tempfile::tempfile().context("Failed to create temp file")?;
+    lzma_rs::xz_decompress(&mut BufReader::new(File::open(archive)?), &mut tar_file)
+        .map_err(|e| anyhow::anyhow!("Failed to decompress archive: {:?}", e))?;
+
+    use std::io::Seek;
+    tar_file.rewind()?;
+    let mut tar = tar::Archive::new(tar_file);
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_file() && is_ffmpeg_entry(&entry.path()?) {
+            io::copy(&mut entry, output)?;
+            return Ok(());
+        }
+    }
+    bail!("No {} found in archive", audio::FFMPEG_EXE)
+}
+
+fn extract_zip(archive: &Path, output: &mut File) -> Result<()> {
+    let mut zip = zip::ZipArchive::new(File::open(archive)?).context("Invalid zip archive")?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        if entry.is_file() && entry.enclosed_name().is_some_and(|p| is_ffmpeg_entry(&p)) {
+            io::copy(&mut entry, output)?;
+            return Ok(());
+        }
+    }
+    bail!("No {} found in archive", audio::FFMPEG_EXE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args(args(&[])).unwrap(), FetchOptions::default());
+
+        let options = parse_args(args(&["--resources", "out", "--url", "u", "--sha256", "AB"])).unwrap();
+        assert_eq!(options.resources, Some(PathBuf::from("out")));
+        assert_eq!(options.url.as_deref(), Some("u"));
+        assert_eq!(options.sha256.as_deref(), Some("ab"));
+
+        assert!(parse_args(args(&["--url", "u"])).is_err());
+        assert!(parse_args(args(&["--resources"])).is_err());
+        assert!(parse_args(args(&["--bogus"])).is_err());
+    }
+
+    #[test]
+    fn test_pinned_build() {
+        let release = format!("{}autobuild-2000-01-01-00-00", RELEASES_URL);
+        let (linux, windows) = ("a".repeat(64), "B".repeat(64));
+        let manifest = format!(
+            "# Pinned\n\n{}  {}/ffmpeg-n7.1-linux64-gpl-7.1.tar.xz\n\
+             {} *{}/ffmpeg-n7.1-win64-gpl-7.1.zip\n",
+            linux, release, windows, release
+        );
+        let (sha256, url) = pinned_build(&manifest, "-linux64-gpl").unwrap().unwrap();
+        assert_eq!(sha256, linux);
+        assert_eq!(url, format!("{}/ffmpeg-n7.1-linux64-gpl-7.1.tar.xz", release));
+        let (sha256, _) = pinned_build(&manifest, "-win64-gpl").unwrap().unwrap();
+        assert_eq!(sha256, "b".repeat(64));
+        assert!(pinned_build(&manifest, "-linuxarm64-gpl").unwrap().is_none());
+
+        // Rolling releases and malformed hashes are refused
+        let latest = format!("{}latest/ffmpeg-master-latest-linux64-gpl.tar.xz", RELEASES_URL);
+        assert!(parse_pinned(&format!("{}  {}", linux, latest)).is_err());
+        assert!(parse_pinned(&format!("abc123  {}/ffmpeg.zip", release)).is_err());
+    }
+
+    #[test]
+    fn test_compiled_in_builds_are_pinned() {
+        parse_pinned(PINNED).unwrap();
+    }
+
+    #[test]
+    #[ignore = "no linux64 or win64 build is pinned in resources/ffmpeg/pinned.sha256 yet"]
+    fn test_every_platform_build_is_pinned() {
+        for build in ["-linux64-gpl", "-win64-gpl"].into_iter().chain(PLATFORM_BUILD) {
+            assert!(pinned_build(PINNED, build).unwrap().is_some(), "{} is not pinned", build);
+        }
+    }
+
+    #[test]
+    fn test_install_from_tar_xz() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Build build/bin/ffmpeg inside a .tar.xz
+        let mut tar = tar::Builder::new(Vec::new());
+        for (path, data) in [("build/README.txt", &b"readme"[..]), ("build/bin/ffmpeg", b"BINARY")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            tar.append_data(&mut header, path.replace("ffmpeg", audio::FFMPEG_EXE), data)
+                .unwrap();
+        }
+        let tar = tar.into_inner().unwrap();
+        let archive = dir.path().join("ffmpeg.tar.xz");
+        let mut xz = Vec::new();
+        lzma_rs::xz_compress(&mut &tar[..], &mut xz).unwrap();
+        std::fs::write(&archive, xz).unwrap();
+
+        let dest = audio::bundled_ffmpeg_in(&dir.path().join("resources"));
+        install(&archive, "https://example.com/ffmpeg.tar.xz", &dest).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"BINARY");
+    }
+
+    #[test]
+    fn test_install_from_zip() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let archive = dir.path().join("ffmpeg.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("build/doc.txt", options).unwrap();
+        zip.write_all(b"doc").unwrap();
+        zip.start_file(format!("build/bin/{}", audio::FFMPEG_EXE), options)
+            .unwrap();
+        zip.write_all(b"BINARY").unwrap();
+        zip.finish().unwrap();
+
+        let dest = dir.path().join("out").join(audio::FFMPEG_EXE);
+        install(&archive, "https://example.com/ffmpeg.zip", &dest).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"BINARY");
+    }
+}
@@ -0,0 +1,255 @@
+//! Automatic model selection for VoiceMark sidecar.
+//!
+//! With `VOICEMARK_MODEL_PATH=auto`, the sidecar inspects the machine at
+//! startup (available RAM, CPU cores, GPU) and loads the largest Whisper
+//! model in `VOICEMARK_MODELS_DIR` that should still transcribe in real
+//! time on it. The decision is logged and reported by `/health`.
+//!
+//! The thresholds are rough: whisper.cpp's memory use per model plus
+//! headroom for the rest of the app, and the cores needed to keep up with
+//! live audio on a CPU of the last few years. The only GPU detected is
+//! Metal, in builds with the `metal` feature.
+
+use serde::Serialize;
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// RAM assumed when it can't be measured.
+const FALLBACK_RAM_MB: u64 = 4096;
+
+/// A model size and what it needs to run in real time.
+struct Tier {
+    size: &'static str,
+    /// whisper.cpp memory use, in megabytes.
+    memory_mb: u64,
+    /// Cores needed on the CPU alone, and with a GPU.
+    cpu_cores: usize,
+    gpu_cores: usize,
+}
+
+/// Model sizes, smallest first.
+const TIERS: [Tier; 5] = [
+    Tier { size: "tiny", memory_mb: 273, cpu_cores: 1, gpu_cores: 1 },
+    Tier { size: "base", memory_mb: 388, cpu_cores: 2, gpu_cores: 1 },
+    Tier { size: "small", memory_mb: 852, cpu_cores: 4, gpu_cores: 2 },
+    Tier { size: "medium", memory_mb: 2100, cpu_cores: 8, gpu_cores: 4 },
+    Tier { size: "large-v3", memory_mb: 3900, cpu_cores: 16, gpu_cores: 8 },
+];
+
+/// The machine, as far as model selection is concerned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Hardware {
+    /// Available memory in megabytes, if it could be measured.
+    pub ram_mb: Option<u64>,
+    pub cores: usize,
+    /// Whether whisper.cpp can use a GPU.
+    pub gpu: bool,
+}
+
+impl Hardware {
+    /// Inspect this machine. `acceleration` is `VOICEMARK_ACCELERATION`.
+    pub fn detect(acceleration: bool) -> Self {
+        Self {
+            ram_mb: available_ram_mb(),
+            cores: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            gpu: cfg!(feature = "metal") && acceleration,
+        }
+    }
+
+    /// Whether `tier` should run in real time here.
+    fn fits(&self, tier: &Tier) -> bool {
+        let cores = if self.gpu { tier.gpu_cores } else { tier.cpu_cores };
+        self.ram_mb.unwrap_or(FALLBACK_RAM_MB) >= tier.memory_mb * 2 && self.cores >= cores
+    }
+}
+
+/// Outcome of automatic model selection, as reported by `/health`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ModelSelection {
+    pub hardware: Hardware,
+    /// Largest model size this machine should run in real time.
+    pub recommended: String,
+    /// Model picked among those installed, if any is.
+    pub path: Option<String>,
+    /// Why this model was picked, for logs and support.
+    pub reason: String,
+}
+
+static SELECTION: OnceLock<ModelSelection> = OnceLock::new();
+
+/// Pick a model in `dir` for `hardware`, and remember the decision for
+/// `/health`.
+pub fn select_model(dir: &Path, hardware: Hardware) -> &'static ModelSelection {
+    let selection = choose(dir, hardware);
+    info!(
+        model = ?selection.path,
+        recommended = %selection.recommended,
+        ram_mb = ?selection.hardware.ram_mb,
+        cores = selection.hardware.cores,
+        gpu = selection.hardware.gpu,
+        "Automatic model selection: {}",
+        selection.reason
+    );
+    SELECTION.get_or_init(|| selection)
+}
+
+/// The automatic model selection made at startup, if any.
+pub fn selection() -> Option<&'static ModelSelection> {
+    SELECTION.get()
+}
+
+fn choose(dir: &Path, hardware: Hardware) -> ModelSelection {
+    let fitting = TIERS.iter().rposition(|tier| hardware.fits(tier)).unwrap_or(0);
+    let recommended = TIERS[fitting].size.to_string();
+
+    // The largest installed model that fits, else the smallest installed
+    let installed = |tier: &Tier| installed_model(dir, tier.size);
+    let picked = TIERS[..=fitting]
+        .iter()
+        .rev()
+        .find_map(|tier| installed(tier).map(|path| (tier, path, true)))
+        .or_else(|| {
+            TIERS[fitting + 1..]
+                .iter()
+                .find_map(|tier| installed(tier).map(|path| (tier, path, false)))
+        });
+
+    let ram = match hardware.ram_mb {
+        Some(mb) => format!("{} MB RAM", mb),
+        None => format!("unknown RAM (assuming {} MB)", FALLBACK_RAM_MB),
+    };
+    let machine = format!(
+        "{}, {} cores, {}",
+        ram,
+        hardware.cores,
+        if hardware.gpu { "GPU" } else { "no GPU" }
+    );
+    let (path, reason) = match picked {
+        Some((tier, path, true)) if tier.size == recommended => {
+            (Some(path), format!("{} fits {}", tier.size, machine))
+        }
+        Some((tier, path, true)) => (
+            Some(path),
+            format!(
+                "{} fits {}; {} would too but isn't installed",
+                tier.size, machine, recommended
+            ),
+        ),
+        Some((tier, path, false)) => (
+            Some(path),
+            format!(
+                "only {} is installed, which may not keep up with {} (recommended: {})",
+                tier.size, machine, recommended
+            ),
+        ),
+        None => (
+            None,
+            format!(
+                "no model installed in {}; {} would fit {}",
+                dir.display(),
+                recommended,
+                machine
+            ),
+        ),
+    };
+    ModelSelection { hardware, recommended, path, reason }
+}
+
+/// Path of an installed model of the given size, preferring English-only
+/// models, in a format this build can load.
+fn installed_model(dir: &Path, size: &str) -> Option<String> {
+    let mut names = Vec::new();
+    if cfg!(feature = "whisper-cpp") {
+        names.push(format!("ggml-{}.en.bin", size));
+        names.push(format!("ggml-{}.bin", size));
+    }
+    if cfg!(feature = "candle") {
+        names.push(format!("whisper-{}.en", size));
+        names.push(format!("whisper-{}", size));
+    }
+    names
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+/// Memory available for new processes, in megabytes.
+#[cfg(target_os = "linux")]
+fn available_ram_mb() -> Option<u64> {
+    parse_meminfo(&std::fs::read_to_string("/proc/meminfo").ok()?)
+}
+
+/// Installed memory, in megabytes (macOS has no cheap "available" figure).
+#[cfg(target_os = "macos")]
+fn available_ram_mb() -> Option<u64> {
+    let output = std::process::Command::new("sysctl").args(["-n", "hw.memsize"]).output().ok()?;
+    let bytes: u64 = String::from_utf8_lossy(&output.stdout).trim().parse().ok()?;
+    Some(bytes / 1024 / 1024)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn available_ram_mb() -> Option<u64> {
+    None
+}
+
+/// `MemAvailable` from `/proc/meminfo`, in megabytes.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_meminfo(meminfo: &str) -> Option<u64> {
+    meminfo.lines().find_map(|line| {
+        let kb = line.strip_prefix("MemAvailable:")?.trim().strip_suffix("kB")?;
+        kb.trim().parse::<u64>().ok().map(|kb| kb / 1024)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hardware(ram_mb: u64, cores: usize, gpu: bool) -> Hardware {
+        Hardware { ram_mb: Some(ram_mb), cores, gpu }
+    }
+
+    #[test]
+    fn test_parse_meminfo() {
+        let meminfo = "MemTotal:  16314412 kB\nMemFree:  1000 kB\nMemAvailable:  8388608 kB\n";
+        assert_eq!(parse_meminfo(meminfo), Some(8192));
+        assert_eq!(parse_meminfo("MemTotal: 1 kB\n"), None);
+    }
+
+    #[test]
+    fn test_fits() {
+        let small = &TIERS[2];
+        assert!(hardware(4096, 4, false).fits(small));
+        assert!(!hardware(4096, 2, false).fits(small));
+        assert!(hardware(4096, 2, true).fits(small));
+        assert!(!hardware(1024, 8, true).fits(small));
+    }
+
+    #[cfg(feature = "whisper-cpp")]
+    #[test]
+    fn test_choose_installed_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let none = choose(dir.path(), hardware(16384, 8, false));
+        assert_eq!(none.recommended, "medium");
+        assert!(none.path.is_none());
+
+        for name in ["ggml-tiny.en.bin", "ggml-small.bin", "ggml-large-v3.bin"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let picked = choose(dir.path(), hardware(16384, 8, false));
+        assert!(picked.path.unwrap().ends_with("ggml-small.bin"));
+        assert!(picked.reason.contains("medium would too"));
+
+        let weak = choose(dir.path(), hardware(16384, 1, false));
+        assert_eq!(weak.recommended, "tiny");
+        assert!(weak.path.unwrap().ends_with("ggml-tiny.en.bin"));
+
+        std::fs::remove_file(dir.path().join("ggml-tiny.en.bin")).unwrap();
+        let fallback = choose(dir.path(), hardware(16384, 1, false));
+        assert!(fallback.path.unwrap().ends_with("ggml-small.bin"));
+        assert!(fallback.reason.starts_with("only small"));
+    }
+}
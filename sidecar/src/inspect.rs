@@ -0,0 +1,225 @@
+//! Audio inspection for VoiceMark sidecar.
+//!
+//! Probes an upload's container and audio stream without transcribing it,
+//! so clients can warn about unsupported or corrupted files up front. WAV
+//! headers are parsed directly; everything else is decoded once with ffmpeg.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::process::Command;
+use tracing::{debug, instrument};
+
+use crate::audio;
+use crate::error::ApiError;
+
+/// Result of probing an upload (`POST /inspect`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct AudioInfo {
+    /// Whether the sidecar can transcribe this file.
+    pub supported: bool,
+    /// Container format, e.g. `"wav"` or `"matroska,webm"`.
+    pub container: Option<String>,
+    /// Codec of the first audio stream, e.g. `"opus"`.
+    pub codec: Option<String>,
+    pub duration_ms: Option<u64>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    /// Number of audio tracks; pick one with `track=<index>` when
+    /// transcribing.
+    pub audio_tracks: usize,
+    /// Why the file is not supported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Probe uploaded audio.
+///
+/// Unsupported or corrupted files are reported with `supported: false`; an
+/// error is only returned if the file can't be probed at all (no ffmpeg).
+#[instrument(skip_all, fields(input_size = bytes.len()))]
+pub fn inspect(bytes: &[u8]) -> Result<AudioInfo, ApiError> {
+    if let Some(wav) = audio::parse_wav_header(bytes) {
+        let mut info = AudioInfo {
+            supported: true,
+            container: Some("wav".to_string()),
+            codec: Some(wav.codec()),
+            duration_ms: wav.duration_ms(),
+            sample_rate: Some(wav.sample_rate),
+            channels: Some(wav.channels),
+            audio_tracks: 1,
+            error: None,
+        };
+        if wav.data_len == 0 {
+            info.supported = false;
+            info.error = Some("WAV file contains no audio data".to_string());
+        } else if !wav.is_whisper_ready() && audio::ffmpeg_path().is_err() {
+            info.supported = false;
+            info.error = Some("ffmpeg is required to convert this WAV file".to_string());
+        }
+        return Ok(info);
+    }
+
+    let ffmpeg = audio::ffmpeg_path().map_err(|e| ApiError::FfmpegUnavailable(e.to_string()))?;
+    let input = audio::write_temp_input(bytes, None).map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    // Decode the first audio stream to nowhere, stopping at the first error,
+    // so corrupted files are caught as well as unknown formats
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-nostdin", "-xerror", "-i"])
+        .arg(input.path())
+        .args(["-map", "0:a:0", "-f", "null", "-"])
+        .output()
+        .map_err(|e| ApiError::Internal(format!("Failed to execute ffmpeg: {}", e)))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    debug!(success = output.status.success(), "Probed audio with ffmpeg");
+    let mut info = parse_ffmpeg_output(&stderr);
+
+    if info.codec.is_none() {
+        info.error = Some(if info.container.is_some() {
+            "No audio stream found".to_string()
+        } else {
+            last_line(&stderr).unwrap_or("Unrecognized audio format").to_string()
+        });
+    } else if !output.status.success() {
+        info.error = Some(last_line(&stderr).unwrap_or("Failed to decode audio").to_string());
+    } else {
+        info.supported = true;
+    }
+    Ok(info)
+}
+
+/// Last non-empty line of ffmpeg's output (usually the error).
+fn last_line(output: &str) -> Option<&str> {
+    output.lines().map(str::trim).rfind(|l| !l.is_empty())
+}
+
+/// Extract container and audio stream details from `ffmpeg -i` output.
+fn parse_ffmpeg_output(output: &str) -> AudioInfo {
+    let mut info = AudioInfo {
+        audio_tracks: audio::count_audio_streams(output),
+        ..AudioInfo::default()
+    };
+
+    for line in output.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("Input #0, ") {
+            info.container = rest.split(", from '").next().map(str::to_string);
+        } else if let Some(rest) = line.strip_prefix("Duration: ") {
+            info.duration_ms = rest.split(',').next().and_then(parse_duration_ms);
+        } else if line.starts_with("Stream #") && info.codec.is_none() {
+            let Some((_, audio)) = line.split_once("Audio: ") else {
+                continue;
+            };
+            let mut parts = audio.split(", ");
+            info.codec = parts
+                .next()
+                .and_then(|codec| codec.split_whitespace().next())
+                .map(str::to_string);
+            for part in parts {
+                if let Some(hz) = part.strip_suffix(" Hz") {
+                    info.sample_rate = hz.trim().parse().ok();
+                } else if info.sample_rate.is_some() && info.channels.is_none() {
+                    info.channels = parse_channel_layout(part);
+                }
+            }
+        }
+    }
+
+    info
+}
+
+/// Parse an ffmpeg duration (`HH:MM:SS.cc`).
+fn parse_duration_ms(value: &str) -> Option<u64> {
+    let mut parts = value.trim().split(':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some((hours * 3600 + minutes * 60) * 1000 + (seconds * 1000.0).round() as u64)
+}
+
+/// Channel count for an ffmpeg channel layout (`mono`, `stereo`, `5.1(side)`,
+/// `3 channels`).
+fn parse_channel_layout(layout: &str) -> Option<u16> {
+    let layout = layout.split('(').next()?.trim();
+    match layout {
+        "mono" => Some(1),
+        "stereo" => Some(2),
+        "2.1" | "3.0" => Some(3),
+        "quad" | "4.0" => Some(4),
+        "5.0" => Some(5),
+        "5.1" | "6.0" => Some(6),
+        "7.1" => Some(8),
+        _ => layout.strip_suffix(" channels")?.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ffmpeg_output() {
+        let output = "\
+Input #0, matroska,webm, from '/tmp/.tmpX':
+  Metadata:
+    encoder         : Chrome
+  Duration: 00:01:05.02, start: 0.000000, bitrate: N/A
+  Stream #0:0(eng): Audio: opus, 48000 Hz, mono, fltp (default)
+Stream mapping:
+  Stream #0:0 -> #0:0 (opus (native) -> pcm_s16le (native))";
+
+        let info = parse_ffmpeg_output(output);
+        assert_eq!(info.container.as_deref(), Some("matroska,webm"));
+        assert_eq!(info.codec.as_deref(), Some("opus"));
+        assert_eq!(info.duration_ms, Some(65_020));
+        assert_eq!(info.sample_rate, Some(48000));
+        assert_eq!(info.channels, Some(1));
+        assert_eq!(info.audio_tracks, 1);
+    }
+
+    #[test]
+    fn test_parse_ffmpeg_output_skips_video_streams() {
+        let output = "\
+Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'clip.mp4':
+  Duration: 00:00:10.00, start: 0.000000, bitrate: 1205 kb/s
+  Stream #0:0[0x1](und): Video: h264 (High) (avc1 / 0x31637661), yuv420p, 1280x720
+  Stream #0:1[0x2](und): Audio: aac (LC) (mp4a / 0x6134706D), 44100 Hz, 5.1(side), fltp, 128 kb/s
+  Stream #0:2[0x3](deu): Audio: mp3 (mp4a / 0x6134706D), 22050 Hz, mono, fltp, 64 kb/s";
+
+        let info = parse_ffmpeg_output(output);
+        assert_eq!(info.audio_tracks, 2);
+        assert_eq!(info.codec.as_deref(), Some("aac"));
+        assert_eq!(info.sample_rate, Some(44100));
+        assert_eq!(info.channels, Some(6));
+        assert_eq!(info.duration_ms, Some(10_000));
+    }
+
+    #[test]
+    fn test_parse_duration_and_layout() {
+        assert_eq!(parse_duration_ms("01:00:00.50"), Some(3_600_500));
+        assert_eq!(parse_duration_ms("N/A"), None);
+        assert_eq!(parse_channel_layout("stereo"), Some(2));
+        assert_eq!(parse_channel_layout("3 channels"), Some(3));
+        assert_eq!(parse_channel_layout("fltp"), None);
+    }
+
+    #[test]
+    fn test_inspect_wav_without_ffmpeg() {
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF\x00\x00\x00\x00WAVEfmt \x10\x00\x00\x00");
+        wav.extend_from_slice(&[1, 0, 1, 0]); // PCM, mono
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.extend_from_slice(&32000u32.to_le_bytes());
+        wav.extend_from_slice(&[2, 0, 16, 0]); // block align, bits
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.resize(wav.len() + 16000, 0);
+
+        let info = inspect(&wav).unwrap();
+        assert!(info.supported);
+        assert_eq!(info.container.as_deref(), Some("wav"));
+        assert_eq!(info.codec.as_deref(), Some("pcm_s16le"));
+        assert_eq!(info.duration_ms, Some(500));
+        assert_eq!(info.channels, Some(1));
+    }
+}
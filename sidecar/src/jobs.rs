@@ -0,0 +1,774 @@
+//! Background transcription jobs for VoiceMark sidecar.
+//!
+//! Long recordings are transcribed in the background. Clients submit audio
+//! to `POST /jobs` and poll `GET /jobs/:id` for status and progress.
+//!
+//! Large files can also be uploaded in resumable chunks (see
+//! [`uploads`](crate::uploads)) and queued once complete.
+//!
+//! Jobs belong to the tenant that submitted them; other tenants get `404`
+//! for them (see [`tenants::can_access`]).
+//!
+//! Jobs can carry client metadata (`?metadata=<JSON object>&tags=a,b`),
+//! which is returned with the job and stored with its transcript.
+//!
+//! With `?chapters=true`, the transcript is split into titled chapters by
+//! the LLM (see [`crate::llm`]) once transcribed; `?entities=true` and
+//! `?keywords=true` annotate it (see [`crate::annotate`]). `?meeting=true`
+//! adds meeting minutes (see [`crate::minutes`]).
+//!
+//! Jobs with an `email` in their metadata are emailed once completed, if
+//! SMTP is configured (see [`crate::email`]).
+//!
+//! A recording the same tenant already transcribed with the same options and
+//! model, even re-exported with different bytes, completes immediately with
+//! its stored transcript, flagged `deduplicated` (see
+//! [`transcripts::find_duplicate`]).
+
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, rejection::QueryRejection},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{error, info, instrument, warn};
+use utoipa::{IntoParams, ToSchema};
+use voicemark_core::fingerprint::Fingerprint;
+
+use crate::annotate;
+use crate::cache;
+use crate::email::{self, Delivery};
+use crate::events::Source;
+use crate::export::JobTranscript;
+use crate::llm;
+use crate::minutes;
+use crate::remote::{self, Backend};
+use crate::scheduler::{self, Priority};
+use crate::access_log::{self, Entry};
+use crate::tenants::{self, Tenant};
+use crate::transcripts::{self, Checkpoint};
+use crate::transcribe::{DecodingParams, Segmentation, TranscribeOptions, TranscribeResult};
+use crate::upload::{AudioFile, AudioUpload, UploadForm};
+use crate::{BatchQuery, TranscribeResponse};
+use crate::error::{ApiError, Problem};
+
+/// Number of jobs kept in memory by default; the oldest finished jobs are
+/// evicted first.
+pub const DEFAULT_MAX_JOBS: usize = 256;
+/// Maximum size of a job's metadata, in bytes of JSON.
+const MAX_METADATA_BYTES: usize = 16 * 1024;
+/// Maximum number of tags on a job.
+const MAX_TAGS: usize = 32;
+/// Maximum length of a tag, in characters.
+const MAX_TAG_LEN: usize = 64;
+
+/// Lifecycle state of a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed)
+    }
+}
+
+/// Client metadata attached to a job and kept with its transcript.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct JobMetadata {
+    /// Arbitrary JSON object, e.g. `{ "meeting_id": "m-42", "user_id": "u-7" }`.
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    #[schema(value_type = Object)]
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// Metadata query parameters of `POST /jobs`.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MetadataQuery {
+    /// JSON object to store with the job, e.g. `{"meeting_id":"m-42"}`.
+    metadata: Option<String>,
+    /// Comma-separated tags, e.g. `standup,team-a`.
+    tags: Option<String>,
+}
+
+/// Analysis query parameters of `POST /jobs`: passes run on the
+/// transcript once transcribed.
+#[derive(Debug, Clone, Copy, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AnalysisQuery {
+    /// Split the transcript into titled chapters with the LLM
+    /// (requires `VOICEMARK_LLM_URL`).
+    #[serde(default)]
+    pub chapters: bool,
+    /// Find the people, organizations, locations and dates mentioned, with
+    /// the LLM (requires `VOICEMARK_LLM_URL`).
+    #[serde(default)]
+    pub entities: bool,
+    /// Rank the most frequent content words (English stopwords are skipped).
+    #[serde(default)]
+    pub keywords: bool,
+    /// Compose meeting minutes (summary, action items and chapters with
+    /// their text) with the LLM (requires `VOICEMARK_LLM_URL`).
+    #[serde(default)]
+    pub meeting: bool,
+}
+
+impl AnalysisQuery {
+    /// Whether any pass needs the LLM.
+    fn needs_llm(&self) -> bool {
+        self.chapters || self.entities || self.meeting
+    }
+
+    /// Reject passes that can't run on this server.
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.needs_llm() && !llm::enabled() {
+            return Err(ApiError::LlmUnavailable);
+        }
+        Ok(())
+    }
+}
+
+impl JobMetadata {
+    /// Parse and validate metadata query parameters.
+    pub fn from_query(query: MetadataQuery) -> Result<Self, ApiError> {
+        let metadata = match query.metadata.as_deref().map(str::trim) {
+            None | Some("") => serde_json::Map::new(),
+            Some(json) if json.len() > MAX_METADATA_BYTES => {
+                return Err(ApiError::InvalidRequest(format!(
+                    "metadata exceeds {} bytes",
+                    MAX_METADATA_BYTES
+                )));
+            }
+            Some(json) => serde_json::from_str(json).map_err(|e| {
+                ApiError::InvalidRequest(format!("metadata must be a JSON object: {}", e))
+            })?,
+        };
+
+        let mut tags: Vec<String> = Vec::new();
+        for tag in query.tags.iter().flat_map(|tags| tags.split(',')).map(str::trim) {
+            if tag.is_empty() || tags.iter().any(|t| t == tag) {
+                continue;
+            }
+            if tag.chars().count() > MAX_TAG_LEN {
+                return Err(ApiError::InvalidRequest(format!(
+                    "Tags are limited to {} characters",
+                    MAX_TAG_LEN
+                )));
+            }
+            tags.push(tag.to_string());
+        }
+        if tags.len() > MAX_TAGS {
+            return Err(ApiError::InvalidRequest(format!("At most {} tags are allowed", MAX_TAGS)));
+        }
+        Ok(Self { metadata, tags })
+    }
+
+    /// Whether top-level metadata `key` equals `expected`. Strings compare
+    /// by content, other values by their JSON form (e.g. `3` or `true`).
+    pub fn has(&self, key: &str, expected: &str) -> bool {
+        match self.metadata.get(key) {
+            Some(serde_json::Value::String(value)) => value == expected,
+            Some(value) => *value.to_string() == *expected,
+            None => false,
+        }
+    }
+}
+
+/// A transcription job as reported by `GET /jobs/:id`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Job {
+    pub id: String,
+    pub status: JobStatus,
+    /// Completion percentage (0-100).
+    pub progress: u8,
+    #[serde(flatten)]
+    pub metadata: JobMetadata,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<TranscribeResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Problem>,
+    /// Tenant that submitted the job; `None` without authentication.
+    #[serde(skip)]
+    pub tenant: Option<String>,
+    /// When the job completed or failed.
+    #[serde(skip)]
+    finished_at: Option<Instant>,
+}
+
+/// Retention policy for job records.
+#[derive(Debug, Clone, Copy)]
+pub struct JobRetention {
+    /// Most jobs kept in memory; the oldest finished jobs are evicted first.
+    pub max_count: usize,
+    /// Forget jobs this long after they finish; `None` keeps them until
+    /// evicted.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for JobRetention {
+    fn default() -> Self {
+        Self { max_count: DEFAULT_MAX_JOBS, max_age: None }
+    }
+}
+
+/// In-memory job table, in insertion order.
+#[derive(Default)]
+struct JobRegistry {
+    jobs: HashMap<String, Job>,
+    order: VecDeque<String>,
+    retention: JobRetention,
+}
+
+impl JobRegistry {
+    fn insert(&mut self, job: Job) {
+        self.order.push_back(job.id.clone());
+        self.jobs.insert(job.id.clone(), job);
+
+        while self.jobs.len() > self.retention.max_count {
+            let Some(pos) = self
+                .order
+                .iter()
+                .position(|id| self.jobs.get(id).is_some_and(|j| j.status.is_finished()))
+            else {
+                break;
+            };
+            if let Some(id) = self.order.remove(pos) {
+                self.jobs.remove(&id);
+            }
+        }
+    }
+
+    /// Forget jobs that finished more than `max_age` before `now`. Returns
+    /// the number of jobs forgotten.
+    fn prune(&mut self, now: Instant) -> usize {
+        let Some(max_age) = self.retention.max_age else {
+            return 0;
+        };
+        let before = self.jobs.len();
+        self.jobs.retain(|_, job| {
+            job.finished_at.is_none_or(|finished| now.duration_since(finished) <= max_age)
+        });
+        let jobs = &self.jobs;
+        self.order.retain(|id| jobs.contains_key(id));
+        before - self.jobs.len()
+    }
+}
+
+/// Global job registry.
+static JOBS: OnceLock<Mutex<JobRegistry>> = OnceLock::new();
+
+fn registry() -> &'static Mutex<JobRegistry> {
+    JOBS.get_or_init(|| Mutex::new(JobRegistry::default()))
+}
+
+/// Limit how many jobs are kept, and for how long. Call once at startup.
+pub fn configure(retention: JobRetention) {
+    info!(max_count = retention.max_count, max_age = ?retention.max_age, "Job retention set");
+    registry().lock().unwrap().retention = retention;
+}
+
+/// Forget jobs finished longer ago than the retention policy allows.
+/// Returns the number of jobs forgotten.
+pub fn enforce_retention() -> usize {
+    let removed = registry().lock().unwrap().prune(Instant::now());
+    if removed > 0 {
+        info!(removed, "Pruned finished jobs");
+    }
+    removed
+}
+
+/// Register a new queued job of `tenant` and return a snapshot of it.
+pub fn create_job(metadata: JobMetadata, tenant: Option<String>) -> Job {
+    let job = Job {
+        id: uuid::Uuid::new_v4().to_string(),
+        status: JobStatus::Queued,
+        progress: 0,
+        metadata,
+        result: None,
+        error: None,
+        tenant,
+        finished_at: None,
+    };
+    registry().lock().unwrap().insert(job.clone());
+    job
+}
+
+/// Look up a job by ID.
+pub fn get_job(id: &str) -> Option<Job> {
+    registry().lock().unwrap().jobs.get(id).cloned()
+}
+
+/// Jobs whose metadata satisfies `matches`, oldest first.
+pub fn find_jobs(matches: impl Fn(&JobMetadata) -> bool) -> Vec<Job> {
+    let registry = registry().lock().unwrap();
+    registry
+        .order
+        .iter()
+        .filter_map(|id| registry.jobs.get(id))
+        .filter(|job| matches(&job.metadata))
+        .cloned()
+        .collect()
+}
+
+/// Forget the jobs with the given IDs. Returns the number forgotten.
+pub fn forget_jobs(ids: &[String]) -> usize {
+    let mut registry = registry().lock().unwrap();
+    let before = registry.jobs.len();
+    registry.jobs.retain(|id, _| !ids.contains(id));
+    let JobRegistry { jobs, order, .. } = &mut *registry;
+    order.retain(|id| jobs.contains_key(id));
+    before - jobs.len()
+}
+
+/// Apply `f` to the job with the given ID, if it still exists.
+fn update_job(id: &str, f: impl FnOnce(&mut Job)) {
+    if let Some(job) = registry().lock().unwrap().jobs.get_mut(id) {
+        f(job);
+    }
+}
+
+/// Run a job to completion on the current (blocking) thread.
+fn run_job(
+    id: &str,
+    tenant: Option<Tenant>,
+    entry: Option<Entry>,
+    audio_bytes: Vec<u8>,
+    channels: Vec<Vec<f32>>,
+    request: JobRequest,
+    cache_key: String,
+) {
+    let permit = scheduler::acquire(Priority::Batch);
+    update_job(id, |job| job.status = JobStatus::Running);
+
+    // Keep what's transcribed so far on disk in case the process dies
+    let mut checkpoint =
+        Checkpoint::start(&request.options, &request.metadata, request.tenant.as_deref());
+    let mut segments = Vec::new();
+    let result = remote::transcribe_channels_with_callbacks(
+        &channels,
+        request.options.clone(),
+        |segment| {
+            if let Some(checkpoint) = checkpoint.as_mut() {
+                segments.push(segment.clone());
+                checkpoint.autosave(|| segments.clone());
+            }
+        },
+        |progress| update_job(id, |job| job.progress = progress.clamp(0, 100) as u8),
+    );
+    drop(permit);
+
+    match result {
+        Ok((result, backend)) => {
+            if let Some(checkpoint) = checkpoint {
+                checkpoint.discard();
+            }
+            info!(job_id = id, segments = result.segments, "Job completed");
+            cache::put(&cache_key, &result);
+            let transcribed = channels.iter().map(Vec::len).sum();
+            tenants::charge(tenant.as_ref(), transcribed);
+            access_log::add_audio(entry.as_ref(), transcribed);
+            complete_job(id, job_response(&audio_bytes, &request, result, backend));
+        }
+        Err(e) => {
+            error!(job_id = id, "Job failed: {}", e);
+            #[cfg(feature = "sentry")]
+            crate::crash_reports::capture_error("jobs", &e);
+            update_job(id, |job| {
+                job.status = JobStatus::Failed;
+                job.error = Some(ApiError::TranscriptionFailed(e.to_string()).problem());
+                job.finished_at = Some(Instant::now());
+            });
+        }
+    }
+}
+
+/// What a job was asked to do with its audio.
+struct JobRequest {
+    options: TranscribeOptions,
+    metadata: JobMetadata,
+    analysis: AnalysisQuery,
+    /// Fingerprint of the audio, stored with the transcript.
+    fingerprint: Option<Fingerprint>,
+    /// Tenant the transcript belongs to.
+    tenant: Option<String>,
+}
+
+/// Build a job's response, persisting the transcript and running the
+/// requested analysis passes. Analysis failures are logged and leave their
+/// field out rather than failing the job. Blocks while the LLM works.
+fn job_response(
+    audio_bytes: &[u8],
+    request: &JobRequest,
+    result: TranscribeResult,
+    backend: Backend,
+) -> TranscribeResponse {
+    let JobRequest { options, metadata, analysis, fingerprint, tenant } = request;
+    let response = TranscribeResponse::record_job(
+        audio_bytes,
+        options,
+        result,
+        backend,
+        metadata,
+        fingerprint.as_ref(),
+        Source::Job,
+        tenant.as_deref(),
+    );
+    analyze(analysis, response)
+}
+
+/// Add the requested analysis passes to a job's `response`.
+fn analyze(analysis: &AnalysisQuery, response: TranscribeResponse) -> TranscribeResponse {
+    let chapters = if analysis.chapters {
+        llm::chapters(&response.cues)
+            .inspect_err(|e| warn!("Chaptering failed: {:#}", e))
+            .ok()
+    } else {
+        None
+    };
+    if let (Some(id), Some(chapters)) = (&response.id, &chapters) {
+        transcripts::set_chapters(id, chapters);
+    }
+    let entities = if analysis.entities {
+        llm::entities(&response.text)
+            .map(|names| annotate::locate_entities(&response.text, &names))
+            .inspect_err(|e| warn!("Entity extraction failed: {:#}", e))
+            .ok()
+    } else {
+        None
+    };
+    let keywords = analysis.keywords.then(|| annotate::keywords(&response.text));
+    let minutes = if analysis.meeting {
+        minutes::compose(&response.text, &response.cues, chapters.as_deref())
+            .inspect_err(|e| warn!("Meeting minutes failed: {:#}", e))
+            .ok()
+    } else {
+        None
+    };
+    TranscribeResponse { chapters, entities, keywords, minutes, ..response }
+}
+
+/// Mark a job as completed with `response`, and email it if its metadata
+/// asks for that.
+fn complete_job(id: &str, response: TranscribeResponse) {
+    let metadata = registry().lock().unwrap().jobs.get(id).map(|job| job.metadata.clone());
+    if let Some(metadata) = metadata {
+        if let Ok(Some(delivery)) = Delivery::from_metadata(&metadata) {
+            email::send(id, delivery, job_transcript(&metadata, &response));
+        }
+    }
+    update_job(id, |job| {
+        job.status = JobStatus::Completed;
+        job.progress = 100;
+        job.result = Some(response);
+        job.finished_at = Some(Instant::now());
+    });
+}
+
+/// A completed job's transcript, to be written out as a file.
+pub fn transcript(job: &Job) -> Option<JobTranscript> {
+    Some(job_transcript(&job.metadata, job.result.as_ref()?))
+}
+
+fn job_transcript(metadata: &JobMetadata, response: &TranscribeResponse) -> JobTranscript {
+    let chapters = response.chapters.as_deref().unwrap_or_default();
+    JobTranscript {
+        document: transcripts::job_document(
+            metadata,
+            &response.cues,
+            &response.channels,
+            chapters,
+        ),
+        text: response.text.clone(),
+        segments: response.cues.clone(),
+    }
+}
+
+/// Job submission endpoint.
+///
+/// Accepts the same uploads as `/transcribe` and returns `202 Accepted`
+/// with the queued job. Cached results complete immediately.
+#[utoipa::path(
+    post,
+    path = "/jobs",
+    tag = "jobs",
+    params(BatchQuery, MetadataQuery, AnalysisQuery, DecodingParams, Segmentation),
+    request_body(
+        description = "Audio as a multipart form, or as the raw request body",
+        content(
+            (UploadForm = "multipart/form-data"),
+            (AudioFile = "audio/*"),
+            (AudioFile = "video/*"),
+            (AudioFile = "application/octet-stream"),
+        ),
+    ),
+    responses(
+        (status = 202, description = "Job queued (or already completed from the cache)", body = Job),
+        (status = 400, description = "Invalid request or empty audio", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 402, description = "Daily audio quota used up", body = Problem, content_type = "application/problem+json"),
+        (status = 413, description = "Upload too large", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "Unsupported request content type", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Unsupported or corrupt audio", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "ffmpeg needed but unavailable, analysis needs an LLM and none is configured, or email was requested and SMTP isn't configured", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+#[instrument(skip(tenant, entry, query, metadata, analysis, decoding, segmentation, upload))]
+#[allow(clippy::too_many_arguments)] // one per extractor
+pub async fn submit_job(
+    tenant: Option<Extension<Tenant>>,
+    entry: Option<Extension<Entry>>,
+    query: Result<Query<BatchQuery>, QueryRejection>,
+    metadata: Result<Query<MetadataQuery>, QueryRejection>,
+    analysis: Result<Query<AnalysisQuery>, QueryRejection>,
+    decoding: Result<Query<DecodingParams>, QueryRejection>,
+    segmentation: Result<Query<Segmentation>, QueryRejection>,
+    upload: AudioUpload,
+) -> Result<(StatusCode, Json<Job>), ApiError> {
+    let Query(query) = query?;
+    let Query(metadata) = metadata?;
+    let metadata = JobMetadata::from_query(metadata)?;
+    let Query(analysis) = analysis?;
+    analysis.validate()?;
+    let Query(decoding) = decoding?;
+    let Query(segmentation) = segmentation?;
+    let AudioUpload(audio_bytes) = upload;
+    let options = crate::batch_options(query, decoding, segmentation)?;
+
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    let entry = entry.map(|Extension(entry)| entry);
+    let job = queue_job(tenant, entry, audio_bytes, options, metadata, analysis).await?;
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Queue a job for `audio_bytes` and return a snapshot of it.
+///
+/// Cached results and recordings already transcribed complete immediately.
+/// The cache is checked first, so a hit doesn't pay for decoding; decoding
+/// runs on the blocking pool. The access log `entry`, if any, is held until
+/// the job finishes.
+pub async fn queue_job(
+    tenant: Option<Tenant>,
+    entry: Option<Entry>,
+    audio_bytes: Vec<u8>,
+    options: TranscribeOptions,
+    metadata: JobMetadata,
+    analysis: AnalysisQuery,
+) -> Result<Job, ApiError> {
+    email::validate(&metadata)?;
+    let cache_key = cache::cache_key(&audio_bytes, &options);
+    let owner = tenant.as_ref().map(|tenant| tenant.tenant.clone());
+
+    if let Some(result) = cache::get(&cache_key) {
+        let request =
+            JobRequest { options, metadata, analysis, fingerprint: None, tenant: owner };
+        let job = create_job(request.metadata.clone(), request.tenant.clone());
+        let backend = remote::backend().unwrap_or(Backend::Local);
+        let id = job.id.clone();
+        let needs_llm = llm::polish_enabled() || request.analysis.needs_llm();
+        let finish = move || {
+            complete_job(&id, job_response(&audio_bytes, &request, result, backend));
+        };
+        if needs_llm {
+            // The LLM can take a while, so the job completes in the background
+            tokio::task::spawn_blocking(finish);
+        } else {
+            finish();
+        }
+        return Ok(get_job(&job.id).unwrap_or(job));
+    }
+
+    // Decoded before queueing, to reject bad audio with a 4xx and to
+    // recognize re-exports of a transcribed recording
+    let decode_options = options.clone();
+    let decode_owner = owner.clone();
+    let (audio_bytes, channels, fingerprint, duplicate) =
+        tokio::task::spawn_blocking(move || -> Result<_, ApiError> {
+            let channels = crate::decode_channels(&audio_bytes, None, &decode_options)?;
+            let fingerprint = Fingerprint::of(&channels);
+            let duplicate =
+                transcripts::find_duplicate(&fingerprint, &decode_options, decode_owner.as_deref());
+            Ok((audio_bytes, channels, fingerprint, duplicate))
+        })
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))??;
+
+    if let Some(transcript) = duplicate {
+        let job = create_job(metadata, owner);
+        info!(job_id = %job.id, transcript_id = %transcript.id, "Recording already transcribed");
+        let backend = remote::backend().unwrap_or(Backend::Local);
+        let response = TranscribeResponse::deduplicated(transcript, backend, options.paragraphs);
+        let id = job.id.clone();
+        let finish = move || complete_job(&id, analyze(&analysis, response));
+        if analysis.needs_llm() {
+            tokio::task::spawn_blocking(finish);
+        } else {
+            finish();
+        }
+        return Ok(get_job(&job.id).unwrap_or(job));
+    }
+
+    let fingerprint = Some(fingerprint);
+    let request = JobRequest { options, metadata, analysis, fingerprint, tenant: owner };
+    let job = create_job(request.metadata.clone(), request.tenant.clone());
+    info!(job_id = %job.id, "Job queued");
+
+    let id = job.id.clone();
+    tokio::task::spawn_blocking(move || {
+        run_job(&id, tenant, entry, audio_bytes, channels, request, cache_key)
+    });
+
+    Ok(job)
+}
+
+/// Job status endpoint.
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path, description = "Job ID")),
+    responses(
+        (status = 200, description = "Job status, with the result once completed", body = Job),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown or evicted job, or another tenant's", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn job_status(
+    Path(id): Path<String>,
+    tenant: Option<Extension<Tenant>>,
+) -> Result<Json<Job>, ApiError> {
+    get_job(&id)
+        .filter(|job| tenants::can_access(tenant.as_deref(), job.tenant.as_deref()))
+        .map(Json)
+        .ok_or(ApiError::JobNotFound(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_and_update_job() {
+        let job = create_job(JobMetadata::default(), None);
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!(job.progress, 0);
+
+        update_job(&job.id, |j| {
+            j.status = JobStatus::Running;
+            j.progress = 42;
+        });
+
+        let job = get_job(&job.id).unwrap();
+        assert_eq!(job.status, JobStatus::Running);
+        assert_eq!(job.progress, 42);
+    }
+
+    #[test]
+    fn test_registry_evicts_oldest_finished_jobs() {
+        let mut registry = JobRegistry::default();
+        let job = |id: usize, status| Job {
+            id: id.to_string(),
+            status,
+            progress: 0,
+            metadata: JobMetadata::default(),
+            result: None,
+            error: None,
+            tenant: None,
+            finished_at: None,
+        };
+
+        // A running job is never evicted, even if it is the oldest
+        registry.insert(job(0, JobStatus::Running));
+        for i in 1..=DEFAULT_MAX_JOBS {
+            registry.insert(job(i, JobStatus::Completed));
+        }
+
+        assert_eq!(registry.jobs.len(), DEFAULT_MAX_JOBS);
+        assert!(registry.jobs.contains_key("0"));
+        assert!(!registry.jobs.contains_key("1"));
+    }
+
+    #[test]
+    fn test_registry_prunes_jobs_finished_long_ago() {
+        let retention = JobRetention { max_count: 10, max_age: Some(Duration::from_secs(3600)) };
+        let mut registry = JobRegistry { retention, ..Default::default() };
+        let now = Instant::now();
+        let job = |id: &str, finished_at: Option<Instant>| Job {
+            id: id.to_string(),
+            status: if finished_at.is_some() { JobStatus::Completed } else { JobStatus::Running },
+            progress: 0,
+            metadata: JobMetadata::default(),
+            result: None,
+            error: None,
+            tenant: None,
+            finished_at,
+        };
+        registry.insert(job("old", now.checked_sub(Duration::from_secs(7200))));
+        registry.insert(job("recent", now.checked_sub(Duration::from_secs(60))));
+        registry.insert(job("running", None));
+
+        assert_eq!(registry.prune(now), 1);
+        assert!(!registry.jobs.contains_key("old"));
+        assert_eq!(registry.order, ["recent", "running"]);
+        for i in 0..12 {
+            registry.insert(job(&i.to_string(), Some(now)));
+        }
+        assert_eq!(registry.jobs.len(), 10);
+    }
+
+    #[test]
+    fn test_job_serialization() {
+        let job = Job {
+            id: "abc".to_string(),
+            status: JobStatus::Running,
+            progress: 10,
+            metadata: JobMetadata::default(),
+            result: None,
+            error: None,
+            tenant: None,
+            finished_at: Some(Instant::now()),
+        };
+        let json = serde_json::to_string(&job).unwrap();
+        assert!(json.contains("\"status\":\"running\""));
+        assert!(json.contains("\"progress\":10"));
+        assert!(!json.contains("result"));
+        assert!(!json.contains("metadata"));
+        assert!(!json.contains("finished_at"));
+    }
+
+    #[test]
+    fn test_metadata_from_query() {
+        let metadata = JobMetadata::from_query(MetadataQuery {
+            metadata: Some(r#"{"meeting_id":"m-42","attendees":3}"#.to_string()),
+            tags: Some("standup, team-a,,standup".to_string()),
+        })
+        .unwrap();
+        assert_eq!(metadata.metadata["meeting_id"], "m-42");
+        assert_eq!(metadata.tags, vec!["standup", "team-a"]);
+
+        let json = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(json["metadata"]["attendees"], 3);
+        assert_eq!(json["tags"][1], "team-a");
+    }
+
+    #[test]
+    fn test_metadata_must_be_an_object() {
+        for metadata in ["[1, 2]", "\"m-42\"", "{"] {
+            let query = MetadataQuery {
+                metadata: Some(metadata.to_string()),
+                tags: None,
+            };
+            assert!(JobMetadata::from_query(query).is_err(), "{}", metadata);
+        }
+    }
+}
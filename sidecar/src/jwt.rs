@@ -0,0 +1,431 @@
+//! JWT bearer authentication for VoiceMark sidecar.
+//!
+//! With `VOICEMARK_JWT_JWKS_URL` set, `Authorization: Bearer <jwt>` (or
+//! `?api_key=<jwt>` on WebSocket upgrades) is accepted alongside API keys.
+//! Tokens are verified against the issuer's JSON Web Key Set, which is cached
+//! and fetched again when a token names an unknown key ID (key rotation).
+//!
+//! RS256/384/512, PS256/384/512, ES256/384 and EdDSA signatures are
+//! accepted; `none` and shared-secret (HS*) algorithms never are. `exp` is
+//! required, `nbf` is honored, and `iss`/`aud` are checked when configured.
+//! The token's `sub` becomes the caller's identity: usage is recorded under
+//! key ID `jwt:<sub>`.
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::clock::now_secs;
+use crate::error::ApiError;
+
+/// Upper bound on a JWKS request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a fetched key set is trusted before it is fetched again.
+const JWKS_MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// Minimum time between refetches triggered by unknown key IDs, so junk
+/// tokens can't hammer the issuer.
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Clock skew tolerated for `exp` and `nbf`.
+const LEEWAY_SECS: u64 = 60;
+
+/// JWT validation settings.
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    /// URL of the issuer's JSON Web Key Set.
+    pub jwks_url: String,
+    /// Required `iss` claim, if any.
+    pub issuer: Option<String>,
+    /// Required `aud` claim (one of), if any.
+    pub audience: Option<String>,
+}
+
+/// The claims VoiceMark uses.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    #[serde(default)]
+    iss: Option<String>,
+    #[serde(default)]
+    aud: Option<Audience>,
+    exp: Option<u64>,
+    #[serde(default)]
+    nbf: Option<u64>,
+}
+
+/// `aud` is either one string or an array of them.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+/// One key of a JSON Web Key Set. Only the public parameters are read.
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    alg: Option<String>,
+    #[serde(default, rename = "use")]
+    use_: Option<String>,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+/// The cached key set.
+#[derive(Default)]
+struct Keys {
+    keys: Vec<Jwk>,
+    /// Last successful fetch.
+    fetched: Option<Instant>,
+    /// Last fetch attempt, successful or not.
+    attempted: Option<Instant>,
+}
+
+struct Verifier {
+    client: reqwest::Client,
+    config: JwtConfig,
+    keys: Mutex<Keys>,
+}
+
+static VERIFIER: OnceLock<Verifier> = OnceLock::new();
+
+/// Accept JWTs signed by keys from `config.jwks_url`. Call once at startup.
+pub fn configure(config: JwtConfig) -> Result<()> {
+    info!(
+        jwks_url = %config.jwks_url,
+        issuer = ?config.issuer,
+        audience = ?config.audience,
+        "JWT authentication enabled"
+    );
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .context("Failed to create HTTP client")?;
+    let verifier = Verifier { client, config, keys: Mutex::default() };
+    if VERIFIER.set(verifier).is_err() {
+        bail!("JWT authentication already configured");
+    }
+    Ok(())
+}
+
+/// Whether JWT authentication is enabled.
+pub fn enabled() -> bool {
+    VERIFIER.get().is_some()
+}
+
+/// Whether `token` has the shape of a JWT (three dot-separated parts) rather
+/// than an API key.
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.split('.').count() == 3 && token.split('.').all(|part| !part.is_empty())
+}
+
+/// Verify `token` and return its claims. Bad tokens are `401`; a key set that
+/// can't be fetched is `502`.
+pub async fn verify(token: &str) -> Result<Claims, ApiError> {
+    let Some(verifier) = VERIFIER.get() else {
+        return Err(ApiError::Unauthorized("JWT authentication is not enabled".to_string()));
+    };
+    verifier.verify(token).await
+}
+
+impl Verifier {
+    async fn verify(&self, token: &str) -> Result<Claims, ApiError> {
+        let invalid = |reason: &str| ApiError::Unauthorized(format!("Invalid token: {}", reason));
+        let Some((message, signature)) = token.rsplit_once('.') else {
+            return Err(invalid("not a JWT"));
+        };
+        let Some((header, payload)) = message.split_once('.') else {
+            return Err(invalid("not a JWT"));
+        };
+        let header: Header = decode_json(header).ok_or_else(|| invalid("malformed header"))?;
+        let claims: Claims = decode_json(payload).ok_or_else(|| invalid("malformed claims"))?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| invalid("malformed signature"))?;
+
+        let keys = self.keys_for(&header).await?;
+        if keys.is_empty() {
+            return Err(invalid("signed by an unknown key"));
+        }
+        let message = message.as_bytes();
+        if !keys.iter().any(|key| verify_signature(key, &header.alg, message, &signature)) {
+            return Err(invalid("bad signature"));
+        }
+        check_claims(&claims, &self.config, now_secs()).map_err(|reason| invalid(&reason))?;
+        Ok(claims)
+    }
+
+    /// Keys that may have signed a token with `header`, refetching the key
+    /// set if it is stale or doesn't have the token's key ID.
+    async fn keys_for(&self, header: &Header) -> Result<Vec<Jwk>, ApiError> {
+        let mut cached = self.keys.lock().await;
+        let due = cached.attempted.is_none_or(|at| at.elapsed() > MIN_REFETCH_INTERVAL);
+        let stale = cached.fetched.is_none_or(|at| at.elapsed() > JWKS_MAX_AGE);
+        if due && (stale || matching_keys(&cached.keys, header).is_empty()) {
+            cached.attempted = Some(Instant::now());
+            match self.fetch().await {
+                Ok(keys) => {
+                    cached.keys = keys;
+                    cached.fetched = Some(Instant::now());
+                }
+                // Keep serving from the old set rather than locking everyone out
+                Err(e) if cached.fetched.is_some() => warn!("{:#}", e),
+                Err(e) => return Err(ApiError::JwksFailed(format!("{:#}", e))),
+            }
+        }
+        if cached.fetched.is_none() {
+            return Err(ApiError::JwksFailed("JWKS not fetched yet".to_string()));
+        }
+        Ok(matching_keys(&cached.keys, header))
+    }
+
+    async fn fetch(&self) -> Result<Vec<Jwk>> {
+        let url = &self.config.jwks_url;
+        debug!(url = %url, "Fetching JWKS");
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Failed to fetch JWKS from {}", url))?;
+        let set: JwkSet = response.json().await.context("Invalid JWKS")?;
+        Ok(set.keys)
+    }
+}
+
+/// Decode a base64url JSON part of a token.
+fn decode_json<T: serde::de::DeserializeOwned>(part: &str) -> Option<T> {
+    let bytes = URL_SAFE_NO_PAD.decode(part).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Signing keys from `keys` usable for a token with `header`: the key with
+/// its `kid`, or every key if the token names none.
+fn matching_keys(keys: &[Jwk], header: &Header) -> Vec<Jwk> {
+    keys.iter()
+        .filter(|key| key.use_.as_deref().is_none_or(|use_| use_ == "sig"))
+        .filter(|key| key.alg.as_deref().is_none_or(|alg| alg == header.alg))
+        .filter(|key| header.kid.is_none() || key.kid == header.kid)
+        .cloned()
+        .collect()
+}
+
+/// Whether `signature` over `message` is valid for `key` with `alg`.
+fn verify_signature(key: &Jwk, alg: &str, message: &[u8], signature: &[u8]) -> bool {
+    let bytes = |value: &Option<String>| value.as_deref().and_then(|v| URL_SAFE_NO_PAD.decode(v).ok());
+    match (key.kty.as_str(), alg) {
+        ("RSA", "RS256" | "RS384" | "RS512" | "PS256" | "PS384" | "PS512") => {
+            let (Some(n), Some(e)) = (bytes(&key.n), bytes(&key.e)) else {
+                return false;
+            };
+            let params: &signature::RsaParameters = match alg {
+                "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                "RS512" => &signature::RSA_PKCS1_2048_8192_SHA512,
+                "PS256" => &signature::RSA_PSS_2048_8192_SHA256,
+                "PS384" => &signature::RSA_PSS_2048_8192_SHA384,
+                _ => &signature::RSA_PSS_2048_8192_SHA512,
+            };
+            RsaPublicKeyComponents { n, e }.verify(params, message, signature).is_ok()
+        }
+        ("EC", "ES256" | "ES384") => {
+            let (Some(x), Some(y)) = (bytes(&key.x), bytes(&key.y)) else {
+                return false;
+            };
+            let algorithm = match (alg, key.crv.as_deref()) {
+                ("ES256", Some("P-256")) => &signature::ECDSA_P256_SHA256_FIXED,
+                ("ES384", Some("P-384")) => &signature::ECDSA_P384_SHA384_FIXED,
+                _ => return false,
+            };
+            // Uncompressed SEC1 point
+            let point = [&[0x04], &x[..], &y[..]].concat();
+            UnparsedPublicKey::new(algorithm, point).verify(message, signature).is_ok()
+        }
+        ("OKP", "EdDSA") if key.crv.as_deref() == Some("Ed25519") => {
+            let Some(x) = bytes(&key.x) else {
+                return false;
+            };
+            UnparsedPublicKey::new(&signature::ED25519, x).verify(message, signature).is_ok()
+        }
+        _ => false,
+    }
+}
+
+/// Check expiry, `nbf`, issuer and audience at `now` (Unix seconds).
+fn check_claims(claims: &Claims, config: &JwtConfig, now: u64) -> Result<(), String> {
+    let Some(exp) = claims.exp else {
+        return Err("no expiry".to_string());
+    };
+    if now > exp.saturating_add(LEEWAY_SECS) {
+        return Err("expired".to_string());
+    }
+    if claims.nbf.is_some_and(|nbf| now.saturating_add(LEEWAY_SECS) < nbf) {
+        return Err("not valid yet".to_string());
+    }
+    if claims.sub.trim().is_empty() {
+        return Err("no subject".to_string());
+    }
+    if let Some(issuer) = &config.issuer {
+        if claims.iss.as_ref() != Some(issuer) {
+            return Err("wrong issuer".to_string());
+        }
+    }
+    if let Some(audience) = &config.audience {
+        let matches = match &claims.aud {
+            Some(Audience::One(aud)) => aud == audience,
+            Some(Audience::Many(auds)) => auds.contains(audience),
+            None => false,
+        };
+        if !matches {
+            return Err("wrong audience".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    fn config() -> JwtConfig {
+        JwtConfig {
+            jwks_url: "http://127.0.0.1:1/jwks".to_string(),
+            issuer: Some("https://auth.example.com".to_string()),
+            audience: Some("voicemark".to_string()),
+        }
+    }
+
+    fn encode(value: serde_json::Value) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&value).unwrap())
+    }
+
+    /// An Ed25519 key pair and its JWK.
+    fn key(kid: &str) -> (Ed25519KeyPair, Jwk) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let jwk = serde_json::from_value(serde_json::json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "kid": kid,
+            "x": URL_SAFE_NO_PAD.encode(pair.public_key().as_ref()),
+        }))
+        .unwrap();
+        (pair, jwk)
+    }
+
+    fn sign(pair: &Ed25519KeyPair, kid: &str, claims: serde_json::Value) -> String {
+        let header = encode(serde_json::json!({ "alg": "EdDSA", "kid": kid }));
+        let message = format!("{}.{}", header, encode(claims));
+        let signature = URL_SAFE_NO_PAD.encode(pair.sign(message.as_bytes()).as_ref());
+        format!("{}.{}", message, signature)
+    }
+
+    fn verifier(keys: Vec<Jwk>) -> Verifier {
+        let now = Some(Instant::now());
+        let keys = Mutex::new(Keys { keys, fetched: now, attempted: now });
+        Verifier { client: reqwest::Client::new(), config: config(), keys }
+    }
+
+    fn claims_json(exp: u64) -> serde_json::Value {
+        serde_json::json!({
+            "sub": "user-42",
+            "iss": "https://auth.example.com",
+            "aud": ["other", "voicemark"],
+            "exp": exp,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_verify_signed_token() {
+        let (pair, jwk) = key("k1");
+        let verifier = verifier(vec![jwk]);
+        let token = sign(&pair, "k1", claims_json(now_secs() + 300));
+        assert!(looks_like_jwt(&token));
+        assert_eq!(verifier.verify(&token).await.unwrap().sub, "user-42");
+
+        // Tampered payload
+        let forged = sign(&pair, "k1", claims_json(now_secs() + 300)).replacen('.', ".e30", 1);
+        assert!(matches!(verifier.verify(&forged).await, Err(ApiError::Unauthorized(_))));
+
+        // Signed by a key outside the set, under the same key ID
+        let (other, _) = key("k1");
+        let token = sign(&other, "k1", claims_json(now_secs() + 300));
+        assert!(matches!(verifier.verify(&token).await, Err(ApiError::Unauthorized(_))));
+
+        // Algorithm `none` is never accepted
+        let header = encode(serde_json::json!({ "alg": "none", "kid": "k1" }));
+        let token = format!("{}.{}.", header, encode(claims_json(now_secs() + 300)));
+        assert!(verifier.verify(&token).await.is_err());
+    }
+
+    #[test]
+    fn test_check_claims() {
+        let claims = |value| serde_json::from_value::<Claims>(value).unwrap();
+        let now = 1_800_000_000;
+        assert_eq!(check_claims(&claims(claims_json(now + 10)), &config(), now), Ok(()));
+        // Within the leeway
+        assert_eq!(check_claims(&claims(claims_json(now - 30)), &config(), now), Ok(()));
+        assert_eq!(
+            check_claims(&claims(claims_json(now - 120)), &config(), now),
+            Err("expired".to_string())
+        );
+        // Far-future expiry doesn't overflow
+        assert_eq!(check_claims(&claims(claims_json(u64::MAX)), &config(), now), Ok(()));
+
+        let mut value = claims_json(now + 10);
+        value["aud"] = "other".into();
+        assert_eq!(check_claims(&claims(value), &config(), now), Err("wrong audience".to_string()));
+        let mut value = claims_json(now + 10);
+        value["iss"] = "https://evil.example.com".into();
+        assert_eq!(check_claims(&claims(value), &config(), now), Err("wrong issuer".to_string()));
+        let mut value = claims_json(now + 10);
+        value["nbf"] = (now + 600).into();
+        assert_eq!(check_claims(&claims(value), &config(), now), Err("not valid yet".to_string()));
+        let mut value = claims_json(now + 10);
+        value.as_object_mut().unwrap().remove("exp");
+        assert_eq!(check_claims(&claims(value), &config(), now), Err("no expiry".to_string()));
+    }
+
+    #[test]
+    fn test_looks_like_jwt() {
+        assert!(looks_like_jwt("a.b.c"));
+        assert!(!looks_like_jwt("vm_0123456789abcdef"));
+        assert!(!looks_like_jwt("a..c"));
+        assert!(!looks_like_jwt("a.b.c.d"));
+    }
+}
@@ -0,0 +1,583 @@
+//! Local LLM hook for VoiceMark sidecar.
+//!
+//! With `VOICEMARK_LLM_URL` set, transcripts can be post-processed by an
+//! OpenAI-compatible chat completions endpoint, such as a llama.cpp server:
+//!
+//! - **Polishing**: the text of every batch transcription (`/transcribe`,
+//!   `/transcribe/json`, `/transcribe/stream` and jobs) is grammar-corrected
+//!   and returned as `text_polished` next to the raw `text`, unless
+//!   `VOICEMARK_POLISH=0`. Polishing is best-effort: if the LLM is
+//!   unreachable or its reply doesn't look like the same transcript,
+//!   `text_polished` is omitted.
+//! - **Summaries**: `POST /transcripts/:id/summarize` asks for a summary
+//!   and, optionally, action items.
+//! - **Chapters**: jobs submitted with `chapters=true` have their segments
+//!   grouped into titled chapters.
+//! - **Entities**: jobs submitted with `entities=true` have the named
+//!   entities in their text listed (see [`crate::annotate`]).
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
+
+use crate::annotate::EntityType;
+use crate::transcribe::Segment;
+
+/// Default model name; llama.cpp's server ignores it.
+pub const DEFAULT_MODEL: &str = "local";
+
+/// Upper bound on a single LLM request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Longest error body quoted from the LLM endpoint.
+const MAX_ERROR_BODY: usize = 300;
+
+/// Instructions for polishing.
+const POLISH_PROMPT: &str = "You correct speech-to-text transcripts. Fix grammar, \
+punctuation and capitalization, and remove filler words and false starts. Keep the \
+speaker's wording and meaning otherwise, and don't add, summarize or answer anything. \
+Reply with the corrected transcript only.";
+
+/// Instructions for summaries.
+const SUMMARY_PROMPT: &str = "You summarize transcripts of meetings and dictation. \
+Summarize the main points and decisions in a few sentences, in the language of the \
+transcript. Reply with a JSON object with a \"summary\" string.";
+
+/// Added to [`SUMMARY_PROMPT`] when action items are requested.
+const ACTION_ITEMS_PROMPT: &str = " Also include \"action_items\": an array of the \
+follow-up tasks agreed on, each a short sentence naming who will do it if that was \
+said, or an empty array if there are none.";
+
+/// Instructions for chaptering.
+const CHAPTERS_PROMPT: &str = "You split transcripts into chapters by topic. Each \
+line of the transcript is a segment: its number, start time and text. Group \
+consecutive segments into chapters, one per topic, usually several minutes long; a \
+short recording may be a single chapter. Give each chapter a short descriptive title \
+in the language of the transcript. Reply with a JSON object with a \"chapters\" array \
+of objects, in order, each with \"start_segment\" (the number of the chapter's first \
+segment) and \"title\".";
+
+/// Instructions for entity extraction.
+const ENTITIES_PROMPT: &str = "You extract named entities from transcripts. List \
+the people, organizations, locations and dates mentioned, each exactly as written in \
+the transcript. Reply with a JSON object with an \"entities\" array of objects, each \
+with \"text\" and \"type\" (one of \"person\", \"organization\", \"location\", \
+\"date\").";
+
+/// LLM endpoint settings.
+#[derive(Debug, Clone)]
+pub struct LlmConfig {
+    /// API base URL, e.g. `http://127.0.0.1:8080/v1`.
+    pub url: String,
+    /// Bearer token sent to the API.
+    pub api_key: Option<String>,
+    /// Model name sent with each request.
+    pub model: String,
+    /// Polish every batch transcription.
+    pub polish: bool,
+}
+
+/// Client for an OpenAI-compatible chat completions API.
+struct Llm {
+    client: reqwest::Client,
+    config: LlmConfig,
+}
+
+static LLM: OnceLock<Llm> = OnceLock::new();
+
+/// Enable the LLM hook. Call once at startup.
+pub fn configure(config: LlmConfig) -> Result<()> {
+    info!(url = %config.url, model = %config.model, polish = config.polish, "LLM endpoint configured");
+    let llm = Llm::new(config)?;
+    if LLM.set(llm).is_err() {
+        bail!("LLM endpoint already configured");
+    }
+    Ok(())
+}
+
+/// Whether an LLM endpoint is configured.
+pub fn enabled() -> bool {
+    LLM.get().is_some()
+}
+
+/// Whether batch transcriptions are polished.
+pub fn polish_enabled() -> bool {
+    LLM.get().is_some_and(|llm| llm.config.polish)
+}
+
+/// Grammar-corrected `text`, or `None` if polishing is disabled or failed.
+///
+/// Blocks; call it from a blocking thread (e.g. `spawn_blocking`), never
+/// directly from async code.
+pub fn polish(text: &str) -> Option<String> {
+    let llm = LLM.get().filter(|llm| llm.config.polish)?;
+    if text.trim().is_empty() {
+        return None;
+    }
+    let handle = tokio::runtime::Handle::current();
+    match handle.block_on(llm.polish(text)) {
+        Ok(polished) => Some(polished),
+        Err(e) => {
+            warn!("Transcript polishing failed: {:#}", e);
+            None
+        }
+    }
+}
+
+/// A transcript summary.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Summary {
+    pub summary: String,
+    #[serde(default)]
+    pub action_items: Vec<String>,
+}
+
+/// Summarize `text`, with action items if `action_items` is set.
+///
+/// Blocks; call it from a blocking thread. Fails if no LLM endpoint is
+/// configured.
+pub fn summarize(text: &str, action_items: bool) -> Result<Summary> {
+    let Some(llm) = LLM.get() else {
+        bail!("No LLM endpoint configured");
+    };
+    let handle = tokio::runtime::Handle::current();
+    handle.block_on(llm.summarize(text, action_items))
+}
+
+/// A titled section of a transcript.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Chapter {
+    pub title: String,
+    /// Chapter start, in milliseconds (the start of its first segment).
+    pub start_ms: i64,
+    /// Chapter end, in milliseconds (the start of the next chapter, or the
+    /// end of the last segment).
+    pub end_ms: i64,
+}
+
+/// Group `segments` into chapters.
+///
+/// Blocks; call it from a blocking thread. Fails if no LLM endpoint is
+/// configured.
+pub fn chapters(segments: &[Segment]) -> Result<Vec<Chapter>> {
+    let Some(llm) = LLM.get() else {
+        bail!("No LLM endpoint configured");
+    };
+    if segments.is_empty() {
+        return Ok(Vec::new());
+    }
+    let handle = tokio::runtime::Handle::current();
+    handle.block_on(llm.chapters(segments))
+}
+
+/// Named entities in `text`, as written there, with their types.
+///
+/// Blocks; call it from a blocking thread. Fails if no LLM endpoint is
+/// configured.
+pub fn entities(text: &str) -> Result<Vec<(String, EntityType)>> {
+    let Some(llm) = LLM.get() else {
+        bail!("No LLM endpoint configured");
+    };
+    if text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let handle = tokio::runtime::Handle::current();
+    handle.block_on(llm.entities(text))
+}
+
+impl Llm {
+    fn new(config: LlmConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(Self { client, config })
+    }
+
+    /// Ask the LLM to correct `text`.
+    async fn polish(&self, text: &str) -> Result<String> {
+        let reply = self.chat(POLISH_PROMPT, text, false).await?;
+        check_polished(&reply, text)?;
+        Ok(reply)
+    }
+
+    /// Ask the LLM to summarize `text`.
+    async fn summarize(&self, text: &str, action_items: bool) -> Result<Summary> {
+        let prompt = if action_items {
+            format!("{}{}", SUMMARY_PROMPT, ACTION_ITEMS_PROMPT)
+        } else {
+            SUMMARY_PROMPT.to_string()
+        };
+        let reply = self.chat(&prompt, text, true).await?;
+        let mut summary = parse_summary(&reply)?;
+        if !action_items {
+            summary.action_items.clear();
+        }
+        Ok(summary)
+    }
+
+    /// Ask the LLM to group `segments` into chapters.
+    async fn chapters(&self, segments: &[Segment]) -> Result<Vec<Chapter>> {
+        let reply = self.chat(CHAPTERS_PROMPT, &numbered_segments(segments), true).await?;
+        parse_chapters(&reply, segments)
+    }
+
+    /// Ask the LLM for the named entities in `text`.
+    async fn entities(&self, text: &str) -> Result<Vec<(String, EntityType)>> {
+        let reply = self.chat(ENTITIES_PROMPT, text, true).await?;
+        parse_entities(&reply)
+    }
+
+    /// Send a system and a user message and return the trimmed reply.
+    /// With `json`, the endpoint is asked for a JSON object.
+    async fn chat(&self, system: &str, user: &str, json: bool) -> Result<String> {
+        let url = format!("{}/chat/completions", self.config.url.trim_end_matches('/'));
+        let body = ChatRequest {
+            model: &self.config.model,
+            messages: [
+                ChatMessage { role: "system", content: system },
+                ChatMessage { role: "user", content: user },
+            ],
+            temperature: 0.0,
+            stream: false,
+            response_format: json.then_some(ResponseFormat { kind: "json_object" }),
+        };
+
+        let mut request = self.client.post(&url).json(&body);
+        if let Some(key) = &self.config.api_key {
+            request = request.bearer_auth(key);
+        }
+
+        debug!(url = %url, chars = user.len(), "Sending transcript to LLM");
+        let response = request.send().await.context("LLM request failed")?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let body: String = body.trim().chars().take(MAX_ERROR_BODY).collect();
+            bail!("LLM endpoint returned {}: {}", status, body);
+        }
+
+        let completion: ChatResponse = response
+            .json()
+            .await
+            .context("Invalid response from the LLM endpoint")?;
+        let reply = completion
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .unwrap_or_default();
+        let reply = reply.trim();
+        if reply.is_empty() {
+            bail!("LLM returned no text");
+        }
+        Ok(reply.to_string())
+    }
+}
+
+/// Reject a polished `reply` that is much shorter or longer than the
+/// `original`: small models sometimes answer the transcript instead of
+/// correcting it.
+fn check_polished(reply: &str, original: &str) -> Result<()> {
+    let (original_len, reply_len) = (original.chars().count(), reply.chars().count());
+    if reply_len * 2 < original_len || reply_len > original_len * 2 + 20 {
+        bail!(
+            "LLM reply of {} chars doesn't match the {} char transcript",
+            reply_len,
+            original_len
+        );
+    }
+    Ok(())
+}
+
+/// The JSON object in a reply. Endpoints that ignore `response_format` tend
+/// to wrap it in prose or a code fence, so the outermost braces are taken.
+fn json_object(reply: &str) -> Result<&str> {
+    match (reply.find('{'), reply.rfind('}')) {
+        (Some(start), Some(end)) if start < end => Ok(&reply[start..=end]),
+        _ => bail!("LLM reply is not a JSON object"),
+    }
+}
+
+/// Parse a summary reply.
+fn parse_summary(reply: &str) -> Result<Summary> {
+    let mut summary: Summary =
+        serde_json::from_str(json_object(reply)?).context("LLM reply is not a summary object")?;
+    summary.summary = summary.summary.trim().to_string();
+    if summary.summary.is_empty() {
+        bail!("LLM returned an empty summary");
+    }
+    summary.action_items = summary
+        .action_items
+        .into_iter()
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect();
+    Ok(summary)
+}
+
+#[derive(Debug, Deserialize)]
+struct EntityReply {
+    entities: Vec<EntityMention>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EntityMention {
+    text: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+}
+
+/// Parse an entities reply, dropping duplicates.
+fn parse_entities(reply: &str) -> Result<Vec<(String, EntityType)>> {
+    let reply: EntityReply =
+        serde_json::from_str(json_object(reply)?).context("LLM reply is not an entities object")?;
+    let mut entities: Vec<(String, EntityType)> = Vec::new();
+    for mention in reply.entities {
+        let text = mention.text.trim().to_string();
+        if !text.is_empty() && !entities.iter().any(|(seen, _)| *seen == text) {
+            entities.push((text, EntityType::from_name(&mention.kind)));
+        }
+    }
+    Ok(entities)
+}
+
+/// One line per segment: `<index> [<h:mm:ss>] <text>`.
+fn numbered_segments(segments: &[Segment]) -> String {
+    let mut lines = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let secs = segment.start_ms.max(0) / 1000;
+        lines.push_str(&format!(
+            "{} [{}:{:02}:{:02}] {}\n",
+            i,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            segment.text
+        ));
+    }
+    lines
+}
+
+#[derive(Debug, Deserialize)]
+struct ChapterReply {
+    chapters: Vec<ChapterStart>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChapterStart {
+    start_segment: usize,
+    title: String,
+}
+
+/// Parse a chapters reply into chapters covering all of `segments`.
+///
+/// Out-of-range and duplicate starts are dropped and the first chapter is
+/// moved to the first segment, so the chapters always tile the transcript.
+fn parse_chapters(reply: &str, segments: &[Segment]) -> Result<Vec<Chapter>> {
+    let reply: ChapterReply =
+        serde_json::from_str(json_object(reply)?).context("LLM reply is not a chapters object")?;
+    let mut starts: Vec<(usize, String)> = reply
+        .chapters
+        .into_iter()
+        .map(|chapter| (chapter.start_segment, chapter.title.trim().to_string()))
+        .filter(|(start, title)| *start < segments.len() && !title.is_empty())
+        .collect();
+    starts.sort_by_key(|(start, _)| *start);
+    starts.dedup_by_key(|(start, _)| *start);
+    let Some(first) = starts.first_mut() else {
+        bail!("LLM returned no chapters");
+    };
+    first.0 = 0;
+
+    let end_ms = segments.last().map_or(0, |segment| segment.end_ms);
+    Ok(starts
+        .iter()
+        .enumerate()
+        .map(|(i, (start, title))| Chapter {
+            title: title.clone(),
+            start_ms: segments[*start].start_ms,
+            end_ms: starts.get(i + 1).map_or(end_ms, |(next, _)| segments[*next].start_ms),
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: [ChatMessage<'a>; 2],
+    temperature: f32,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+/// Chat completions response, reduced to what we read.
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoice {
+    message: ChatReply,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatReply {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, http::HeaderMap, routing::post};
+    use std::future::IntoFuture;
+
+    #[test]
+    fn test_check_polished_rejects_unrelated_replies() {
+        assert!(check_polished("So, we should ship it on Friday.", "so uh we should ship it on friday").is_ok());
+        assert!(check_polished("Hi", "so uh we should ship it on friday").is_err());
+        let rambling = "Sure! Here is a corrected version of your transcript, along with \
+                        some notes on what I changed and why.";
+        assert!(check_polished(rambling, "ship it").is_err());
+    }
+
+    #[test]
+    fn test_parse_summary() {
+        let reply = "```json\n{\"summary\": \" Ship on Friday. \", \"action_items\": [\"Ana writes the notes\", \" \"]}\n```";
+        assert_eq!(
+            parse_summary(reply).unwrap(),
+            Summary {
+                summary: "Ship on Friday.".to_string(),
+                action_items: vec!["Ana writes the notes".to_string()],
+            }
+        );
+        assert!(parse_summary(r#"{"summary": "Short."}"#).unwrap().action_items.is_empty());
+        assert!(parse_summary("The meeting was about shipping.").is_err());
+        assert!(parse_summary(r#"{"summary": ""}"#).is_err());
+    }
+
+    fn segments() -> Vec<Segment> {
+        (0..4)
+            .map(|i| Segment {
+                start_ms: i * 60_000,
+                end_ms: i * 60_000 + 55_000,
+                text: format!("Part {}.", i),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_numbered_segments() {
+        let mut segments = segments();
+        segments[3].start_ms = 3_723_000;
+        let lines = numbered_segments(&segments);
+        assert!(lines.starts_with("0 [0:00:00] Part 0.\n1 [0:01:00] Part 1.\n"));
+        assert!(lines.ends_with("3 [1:02:03] Part 3.\n"));
+    }
+
+    #[test]
+    fn test_parse_chapters() {
+        let reply = r#"{"chapters": [
+            {"start_segment": 2, "title": " Budget "},
+            {"start_segment": 1, "title": "Intro"},
+            {"start_segment": 2, "title": "Duplicate"},
+            {"start_segment": 9, "title": "Out of range"}
+        ]}"#;
+        let chapters = parse_chapters(reply, &segments()).unwrap();
+        assert_eq!(
+            chapters,
+            vec![
+                Chapter { title: "Intro".to_string(), start_ms: 0, end_ms: 120_000 },
+                Chapter { title: "Budget".to_string(), start_ms: 120_000, end_ms: 235_000 },
+            ]
+        );
+        assert!(parse_chapters(r#"{"chapters": []}"#, &segments()).is_err());
+    }
+
+    #[test]
+    fn test_parse_entities() {
+        let reply = r#"{"entities": [
+            {"text": "Ana", "type": "person"},
+            {"text": " Acme ", "type": "ORG"},
+            {"text": "Ana", "type": "person"},
+            {"text": "", "type": "date"}
+        ]}"#;
+        assert_eq!(
+            parse_entities(reply).unwrap(),
+            vec![
+                ("Ana".to_string(), EntityType::Person),
+                ("Acme".to_string(), EntityType::Organization),
+            ]
+        );
+    }
+
+    /// Fake chat completions endpoint: upper-cases the user message, or
+    /// returns a summary object when JSON is requested.
+    async fn fake_completions(
+        headers: HeaderMap,
+        Json(request): Json<serde_json::Value>,
+    ) -> Json<serde_json::Value> {
+        assert_eq!(headers["authorization"], "Bearer sk-local");
+        assert_eq!(request["model"], "llama");
+        assert_eq!(request["messages"][0]["role"], "system");
+        let text = request["messages"][1]["content"].as_str().unwrap();
+        let content = if request["response_format"]["type"] == "json_object" {
+            serde_json::json!({ "summary": text, "action_items": ["Follow up"] }).to_string()
+        } else {
+            text.to_uppercase()
+        };
+        Json(serde_json::json!({
+            "choices": [{ "message": { "role": "assistant", "content": content } }]
+        }))
+    }
+
+    async fn fake_llm() -> Llm {
+        let app = Router::new().route("/v1/chat/completions", post(fake_completions));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        Llm::new(LlmConfig {
+            url: format!("http://{}/v1/", addr),
+            api_key: Some("sk-local".to_string()),
+            model: "llama".to_string(),
+            polish: true,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_polish_request() {
+        let llm = fake_llm().await;
+        assert_eq!(llm.polish("hello world").await.unwrap(), "HELLO WORLD");
+    }
+
+    #[tokio::test]
+    async fn test_summarize_request() {
+        let llm = fake_llm().await;
+        let summary = llm.summarize("We ship Friday.", true).await.unwrap();
+        assert_eq!(summary.summary, "We ship Friday.");
+        assert_eq!(summary.action_items, vec!["Follow up"]);
+
+        let summary = llm.summarize("We ship Friday.", false).await.unwrap();
+        assert!(summary.action_items.is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+//! Load shedding for VoiceMark sidecar.
+//!
+//! Transcription is CPU-bound: past a point, admitting more requests only
+//! makes every one of them slower, until clients time out and retry into an
+//! even longer queue. With `VOICEMARK_CONCURRENCY_LIMITS`, each group of
+//! endpoints runs at most that many requests at once, and refuses the rest
+//! immediately with `503` (`overloaded`) and `Retry-After`, so the server
+//! degrades predictably and clients can back off or go elsewhere.
+//!
+//! Groups are named by the first path segment (`transcribe`, `jobs`,
+//! `uploads`, `transcripts`, `stream`, ...), with `*` for every endpoint not
+//! listed, e.g. `transcribe=4,jobs=32,*=64`. A request keeps its place until
+//! its response has been sent, including the events of `/transcribe/stream`;
+//! `/stream` and `/listen` hold it only while connecting, as open sessions
+//! are bounded by `VOICEMARK_MAX_STREAMS_PER_CLIENT`. `/health` is never
+//! limited.
+
+use anyhow::{Result, bail};
+use axum::{
+    body::{Body, HttpBody},
+    extract::Request,
+    http::{HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::Semaphore;
+use tracing::{debug, info};
+
+use crate::error::ApiError;
+
+/// Group limiting every endpoint not listed by name.
+pub const DEFAULT_GROUP: &str = "*";
+
+/// Seconds clients are told to wait before retrying.
+const RETRY_AFTER_SECS: u32 = 1;
+
+struct Limits {
+    groups: HashMap<String, Arc<Semaphore>>,
+    default: Option<Arc<Semaphore>>,
+}
+
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+
+/// Limit concurrent requests per endpoint group. Call once at startup.
+pub fn configure(limits: &[(String, usize)]) -> Result<()> {
+    let mut groups: HashMap<String, Arc<Semaphore>> = limits
+        .iter()
+        .map(|(group, limit)| (group.clone(), Arc::new(Semaphore::new(*limit))))
+        .collect();
+    let default = groups.remove(DEFAULT_GROUP);
+    info!(?limits, "Concurrency limits enabled");
+    if LIMITS.set(Limits { groups, default }).is_err() {
+        bail!("Concurrency limits already configured");
+    }
+    Ok(())
+}
+
+/// The group of the endpoint at `path`: its first segment, after `/v1`.
+fn group(path: &str) -> &str {
+    let path = path.strip_prefix("/v1/").unwrap_or(path.trim_start_matches('/'));
+    path.split('/').next().unwrap_or_default()
+}
+
+/// Load-shedding middleware: runs the request if its group has a free slot,
+/// and refuses it with `503` otherwise.
+pub async fn shed(request: Request, next: Next) -> Response {
+    let Some(limits) = LIMITS.get() else {
+        return next.run(request).await;
+    };
+    let group = group(request.uri().path());
+    let Some(semaphore) = limits.groups.get(group).or(limits.default.as_ref()) else {
+        return next.run(request).await;
+    };
+    let Ok(permit) = semaphore.clone().try_acquire_owned() else {
+        let name = if limits.groups.contains_key(group) { group } else { DEFAULT_GROUP };
+        debug!(group = name, path = %request.uri().path(), "Overloaded, request refused");
+        let mut response = ApiError::Overloaded(format!(
+            "Too many concurrent '{}' requests; retry shortly",
+            name
+        ))
+        .into_response();
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+        return response;
+    };
+
+    let response = next.run(request).await;
+    if response.body().size_hint().exact().is_some() {
+        return response;
+    }
+    // Streamed bodies (Server-Sent Events) keep the slot until sent
+    response.map(|body| {
+        Body::from_stream(body.into_data_stream().map(move |chunk| {
+            let _ = &permit;
+            chunk
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_is_first_segment() {
+        assert_eq!(group("/transcribe"), "transcribe");
+        assert_eq!(group("/v1/transcribe/stream"), "transcribe");
+        assert_eq!(group("/jobs/abc"), "jobs");
+        assert_eq!(group("/"), "");
+    }
+}
@@ -0,0 +1,480 @@
+//! `voicemark-sidecar loadtest` subcommand.
+//!
+//! Sends one audio file to a running sidecar from several concurrent
+//! clients, over `POST /v1/transcribe` and `GET /v1/stream`, and reports
+//! throughput, latency percentiles and real-time factors, to size
+//! deployments before rollout.
+//!
+//! ```bash
+//! voicemark-sidecar loadtest --file sample.wav [--url http://127.0.0.1:3001]
+//!     [--concurrency 8] [--requests N] [--mode http|ws|both] [--api-key KEY] [--json]
+//! ```
+//!
+//! Streaming clients send the audio in real time, 100 ms per message, then
+//! `end`, and measure how long the last final takes to arrive. Plain `ws://`
+//! URLs only.
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio_tungstenite::tungstenite::{Message, client::IntoClientRequest};
+
+use crate::audio;
+use crate::config::DEFAULT_PORT;
+
+/// Audio sample rate, as streamed.
+const SAMPLE_RATE: usize = 16000;
+
+/// Audio per streaming message.
+const STREAM_CHUNK: Duration = Duration::from_millis(100);
+
+/// Upper bound on one request or stream, so a stuck server fails the run.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
+
+const USAGE: &str = "Usage: voicemark-sidecar loadtest --file AUDIO [--url URL] \
+                     [--concurrency N] [--requests N] [--mode http|ws|both] \
+                     [--api-key KEY] [--json]";
+
+/// Which endpoints to load.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Http,
+    Ws,
+    Both,
+}
+
+/// Command-line options.
+#[derive(Debug, PartialEq)]
+struct LoadOptions {
+    file: PathBuf,
+    /// Base URL of the sidecar.
+    url: String,
+    /// Concurrent clients per endpoint.
+    concurrency: usize,
+    /// Requests (or streams) per endpoint; default 4 per client.
+    requests: usize,
+    mode: Mode,
+    api_key: Option<String>,
+    /// Print the report as JSON.
+    json: bool,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<LoadOptions> {
+    let (mut file, mut concurrency, mut requests) = (None, 4, None);
+    let mut url = format!("http://127.0.0.1:{}", DEFAULT_PORT);
+    let (mut mode, mut api_key, mut json) = (Mode::Both, None, false);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{} needs a value\n{}", arg, USAGE));
+        match arg.as_str() {
+            "--file" => file = Some(PathBuf::from(value()?)),
+            "--url" => url = value()?.trim_end_matches('/').to_string(),
+            "--concurrency" => concurrency = value()?.parse().context("Invalid --concurrency")?,
+            "--requests" => requests = Some(value()?.parse().context("Invalid --requests")?),
+            "--mode" => {
+                mode = match value()?.as_str() {
+                    "http" => Mode::Http,
+                    "ws" => Mode::Ws,
+                    "both" => Mode::Both,
+                    other => bail!("Invalid --mode '{}'\n{}", other, USAGE),
+                }
+            }
+            "--api-key" => api_key = Some(value()?),
+            "--json" => json = true,
+            _ => bail!("Unknown argument '{}'\n{}", arg, USAGE),
+        }
+    }
+    if concurrency == 0 || requests == Some(0) {
+        bail!("--concurrency and --requests must be at least 1");
+    }
+    Ok(LoadOptions {
+        file: file.with_context(|| format!("--file is required\n{}", USAGE))?,
+        url,
+        concurrency,
+        requests: requests.unwrap_or(concurrency * 4),
+        mode,
+        api_key,
+        json,
+    })
+}
+
+/// Latency distribution, in milliseconds.
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct Percentiles {
+    p50: u64,
+    p90: u64,
+    p95: u64,
+    p99: u64,
+    max: u64,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles of `values`.
+    fn of(mut values: Vec<u64>) -> Self {
+        values.sort_unstable();
+        let rank = |p: usize| match values.len() {
+            0 => 0,
+            n => values[(n * p).div_ceil(100).clamp(1, n) - 1],
+        };
+        Self { p50: rank(50), p90: rank(90), p95: rank(95), p99: rank(99), max: rank(100) }
+    }
+}
+
+/// Results for one endpoint.
+#[derive(Debug, Serialize)]
+struct EndpointReport {
+    endpoint: &'static str,
+    requests: usize,
+    failures: usize,
+    /// First failure, to tell what went wrong.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    first_error: Option<String>,
+    wall_ms: u64,
+    /// Completed requests per second.
+    throughput: f64,
+    /// Seconds of audio transcribed per second of wall time.
+    audio_secs_per_sec: f64,
+    /// Request latency (HTTP), or time from `end` to the last final
+    /// (streaming).
+    latency_ms: Percentiles,
+    /// Median request latency over audio duration (HTTP only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rtf: Option<f64>,
+}
+
+impl EndpointReport {
+    fn new(
+        endpoint: &'static str,
+        outcomes: Vec<Result<Duration, String>>,
+        wall: Duration,
+        audio_ms: u64,
+    ) -> Self {
+        let mut latencies = Vec::with_capacity(outcomes.len());
+        let mut first_error = None;
+        let mut failures = 0;
+        for outcome in outcomes {
+            match outcome {
+                Ok(latency) => latencies.push(latency.as_millis() as u64),
+                Err(e) => {
+                    failures += 1;
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        let completed = latencies.len();
+        let wall_secs = wall.as_secs_f64().max(f64::EPSILON);
+        let latency_ms = Percentiles::of(latencies);
+        Self {
+            endpoint,
+            requests: completed + failures,
+            failures,
+            first_error,
+            wall_ms: wall.as_millis() as u64,
+            throughput: completed as f64 / wall_secs,
+            audio_secs_per_sec: completed as f64 * audio_ms as f64 / 1000.0 / wall_secs,
+            rtf: (endpoint == "http" && completed > 0 && audio_ms > 0)
+                .then(|| latency_ms.p50 as f64 / audio_ms as f64),
+            latency_ms,
+        }
+    }
+
+    fn summary(&self) -> String {
+        let mut summary = format!(
+            "{}: {} requests, {} failed, {:.1}s\n  throughput: {:.2} req/s, {:.1}x real time\n  \
+             latency ms: p50 {}  p90 {}  p95 {}  p99 {}  max {}\n",
+            self.endpoint,
+            self.requests,
+            self.failures,
+            self.wall_ms as f64 / 1000.0,
+            self.throughput,
+            self.audio_secs_per_sec,
+            self.latency_ms.p50,
+            self.latency_ms.p90,
+            self.latency_ms.p95,
+            self.latency_ms.p99,
+            self.latency_ms.max,
+        );
+        if let Some(rtf) = self.rtf {
+            summary += &format!("  RTF (p50): {:.3}\n", rtf);
+        }
+        if let Some(error) = &self.first_error {
+            summary += &format!("  first error: {}\n", error);
+        }
+        summary
+    }
+}
+
+/// Run `requests` calls of `call` from `concurrency` concurrent clients.
+async fn hammer<F, Fut>(
+    concurrency: usize,
+    requests: usize,
+    call: F,
+) -> (Vec<Result<Duration, String>>, Duration)
+where
+    F: Fn() -> Fut + Clone + Send + 'static,
+    Fut: std::future::Future<Output = Result<Duration>> + Send,
+{
+    let remaining = Arc::new(AtomicUsize::new(requests));
+    let outcomes = Arc::new(Mutex::new(Vec::with_capacity(requests)));
+    let started = Instant::now();
+    let clients: Vec<_> = (0..concurrency.min(requests))
+        .map(|_| {
+            let (remaining, outcomes, call) = (remaining.clone(), outcomes.clone(), call.clone());
+            tokio::spawn(async move {
+                while remaining
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                    .is_ok()
+                {
+                    let outcome = match tokio::time::timeout(REQUEST_TIMEOUT, call()).await {
+                        Ok(outcome) => outcome.map_err(|e| format!("{:#}", e)),
+                        Err(_) => Err("Timed out".to_string()),
+                    };
+                    outcomes.lock().await.push(outcome);
+                }
+            })
+        })
+        .collect();
+    for client in clients {
+        let _ = client.await;
+    }
+    let wall = started.elapsed();
+    let outcomes = std::mem::take(&mut *outcomes.lock().await);
+    (outcomes, wall)
+}
+
+/// Transcribe the file once over HTTP; returns the request latency.
+async fn http_request(
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+    body: Arc<Vec<u8>>,
+) -> Result<Duration> {
+    let started = Instant::now();
+    let mut request = client
+        .post(&url)
+        .header("content-type", "application/octet-stream")
+        .body(body.as_ref().clone());
+    if let Some(key) = &api_key {
+        request = request.bearer_auth(key);
+    }
+    let response = request.send().await.context("Request failed")?;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        bail!("{}: {}", status, body.trim());
+    }
+    Ok(started.elapsed())
+}
+
+/// Stream the audio once in real time; returns the time from `end` to the
+/// last final.
+async fn ws_session(
+    url: String,
+    api_key: Option<String>,
+    chunks: Arc<Vec<String>>,
+) -> Result<Duration> {
+    let mut request = url.into_client_request().context("Invalid WebSocket URL")?;
+    if let Some(key) = &api_key {
+        request
+            .headers_mut()
+            .insert("x-api-key", key.parse().context("Invalid API key")?);
+    }
+    let (socket, _) = tokio_tungstenite::connect_async(request)
+        .await
+        .context("WebSocket connection failed")?;
+    let (mut sender, mut receiver) = socket.split();
+
+    // Read replies while sending, so the server's messages don't back up
+    let reader = tokio::spawn(async move {
+        let (mut reset, mut last_final) = (false, None);
+        while let Some(message) = receiver.next().await {
+            let text = match message? {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            let reply: serde_json::Value = serde_json::from_str(&text)?;
+            match reply["type"].as_str() {
+                Some("final") => last_final = Some(Instant::now()),
+                Some("error") => bail!("{}", reply["message"].as_str().unwrap_or_default()),
+                // Replies to our `reset`, which the server handles after `end`
+                Some("ready") if reply["message"] == "Session reset" => {
+                    reset = true;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        Ok::<_, anyhow::Error>((reset, last_final))
+    });
+
+    let mut ticker = tokio::time::interval(STREAM_CHUNK);
+    for chunk in chunks.iter() {
+        ticker.tick().await;
+        let message = serde_json::json!({ "type": "audio", "data": chunk });
+        sender.send(Message::Text(message.to_string())).await?;
+    }
+    let end_sent = Instant::now();
+    sender.send(Message::Text(r#"{"type":"end"}"#.to_string())).await?;
+    sender.send(Message::Text(r#"{"type":"reset"}"#.to_string())).await?;
+
+    let (reset, last_final) = reader.await??;
+    let _ = sender.send(Message::Close(None)).await;
+    if !reset {
+        bail!("Connection closed before the session ended");
+    }
+    let last_final = last_final.context("No final received")?;
+    Ok(last_final.saturating_duration_since(end_sent))
+}
+
+/// Base64-encoded 16-bit PCM messages of [`STREAM_CHUNK`] each.
+fn stream_chunks(samples: &[f32]) -> Vec<String> {
+    let per_chunk = SAMPLE_RATE * STREAM_CHUNK.as_millis() as usize / 1000;
+    samples
+        .chunks(per_chunk)
+        .map(|chunk| {
+            let pcm: Vec<u8> = chunk
+                .iter()
+                .flat_map(|s| ((s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+                .collect();
+            base64::engine::general_purpose::STANDARD.encode(pcm)
+        })
+        .collect()
+}
+
+/// WebSocket URL of `/v1/stream` on the server at `url`.
+fn stream_url(url: &str) -> Result<String> {
+    match url.split_once("://") {
+        Some(("http", rest)) => Ok(format!("ws://{}/v1/stream", rest)),
+        Some(("https", _)) => bail!("Load testing streams over TLS isn't supported; use http://"),
+        _ => bail!("Invalid --url '{}'", url),
+    }
+}
+
+/// Run the subcommand with the arguments following `loadtest`.
+pub async fn run(args: impl IntoIterator<Item = String>) -> Result<()> {
+    let options = parse_args(args)?;
+    let bytes = std::fs::read(&options.file)
+        .with_context(|| format!("Failed to read {}", options.file.display()))?;
+
+    // Decode locally for the audio duration and the streamed PCM
+    audio::configure_ffmpeg(std::env::var_os("VOICEMARK_FFMPEG").map(PathBuf::from));
+    let decode_bytes = bytes.clone();
+    let samples = tokio::task::spawn_blocking(move || {
+        crate::decode_upload(&decode_bytes, None, &Default::default())
+    })
+    .await?
+    .map_err(|e| anyhow::anyhow!("Failed to decode {}: {}", options.file.display(), e))?;
+    let audio_ms = (samples.len() * 1000 / SAMPLE_RATE) as u64;
+    eprintln!(
+        "Load testing {} with {} ({:.1}s of audio), {} clients, {} requests per endpoint",
+        options.url,
+        options.file.display(),
+        audio_ms as f64 / 1000.0,
+        options.concurrency,
+        options.requests
+    );
+
+    let mut reports = Vec::new();
+    if options.mode != Mode::Ws {
+        let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+        let url = format!("{}/v1/transcribe", options.url);
+        let (api_key, body) = (options.api_key.clone(), Arc::new(bytes));
+        let (outcomes, wall) = hammer(options.concurrency, options.requests, move || {
+            http_request(client.clone(), url.clone(), api_key.clone(), body.clone())
+        })
+        .await;
+        reports.push(EndpointReport::new("http", outcomes, wall, audio_ms));
+    }
+    if options.mode != Mode::Http {
+        let url = stream_url(&options.url)?;
+        let (api_key, chunks) = (options.api_key.clone(), Arc::new(stream_chunks(&samples)));
+        let (outcomes, wall) = hammer(options.concurrency, options.requests, move || {
+            ws_session(url.clone(), api_key.clone(), chunks.clone())
+        })
+        .await;
+        reports.push(EndpointReport::new("ws", outcomes, wall, audio_ms));
+    }
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for report in &reports {
+            print!("{}", report.summary());
+        }
+    }
+    if reports.iter().any(|report| report.failures == report.requests) {
+        bail!("Every request to an endpoint failed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::IntoFuture;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(args(&["--file", "a.wav", "--concurrency", "8"])).unwrap();
+        assert_eq!(options.url, "http://127.0.0.1:3001");
+        assert_eq!((options.concurrency, options.requests), (8, 32));
+        assert_eq!(options.mode, Mode::Both);
+        let options =
+            parse_args(args(&["--file", "a.wav", "--mode", "ws", "--url", "http://h:1/"])).unwrap();
+        assert_eq!((options.mode, options.url.as_str()), (Mode::Ws, "http://h:1"));
+        assert!(parse_args(args(&["--concurrency", "8"])).is_err());
+        assert!(parse_args(args(&["--file", "a.wav", "--concurrency", "0"])).is_err());
+        assert!(parse_args(args(&["--file", "a.wav", "--mode", "grpc"])).is_err());
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let percentiles = Percentiles::of((1..=100).rev().collect());
+        assert_eq!(
+            percentiles,
+            Percentiles { p50: 50, p90: 90, p95: 95, p99: 99, max: 100 }
+        );
+        assert_eq!(Percentiles::of(vec![7]).p50, 7);
+        assert_eq!(Percentiles::of(Vec::new()), Percentiles::default());
+    }
+
+    #[test]
+    fn test_stream_chunks_and_url() {
+        let chunks = stream_chunks(&[0.0; 4000]);
+        assert_eq!(chunks.len(), 3);
+        let last = base64::engine::general_purpose::STANDARD.decode(&chunks[2]).unwrap();
+        assert_eq!(last.len(), 800 * 2);
+        assert_eq!(stream_url("http://127.0.0.1:3001").unwrap(), "ws://127.0.0.1:3001/v1/stream");
+        assert!(stream_url("https://example.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_load_against_router() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, crate::build_router()).into_future());
+
+        // No model is loaded in tests, so every request fails the same way
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/v1/transcribe", addr);
+        let body = Arc::new(b"not audio".to_vec());
+        let (outcomes, _) = hammer(2, 5, move || {
+            http_request(client.clone(), url.clone(), None, body.clone())
+        })
+        .await;
+        let report = EndpointReport::new("http", outcomes, Duration::from_secs(1), 1000);
+        assert_eq!((report.requests, report.failures), (5, 5));
+        assert!(report.first_error.is_some() && report.rtf.is_none());
+    }
+}
@@ -4,9 +4,43 @@
 //!
 //! ## Endpoints
 //!
+//! All endpoints are served under `/v1` (e.g. `POST /v1/transcribe`). The
+//! unversioned paths below remain as aliases for existing clients.
+//!
 //! - `GET /health` - Health check
-//! - `POST /transcribe` - Transcribe audio (multipart form, field: `file`)
+//! - `POST /transcribe` - Transcribe audio (multipart form or raw `audio/*` body)
+//! - `POST /transcribe/json` - Transcribe base64-encoded audio from a JSON body
+//! - `POST /transcribe/stream` - Transcribe audio, streaming segments as SSE
+//! - `POST /inspect` - Probe an upload's format without transcribing it
+//! - `POST /compare` - Transcribe with several models side by side
+//! - `POST /warmup` - Run a dummy transcription to warm the model up
+//! - `GET /model` - Model status
+//! - `POST /model` - Load a model at runtime (admin token required)
+//! - `POST /jobs` - Queue a background transcription job (same upload formats)
+//! - `GET /jobs/:id` - Job status and progress
+//! - `GET /transcripts/semantic-search` - Rank transcript segments by meaning
+//! - `GET /transcripts/:id` - Persisted transcript
+//! - `PATCH /transcripts/:id` - Correct transcript segments
+//! - `DELETE /transcripts/:id` - Delete a transcript and its stored data
+//! - `POST /transcripts/:id/summarize` - Summarize a transcript with the LLM
+//! - `GET /transcripts/:id/audio` - Retained audio for a transcript
+//! - `GET /transcripts/:id/export` - Transcript as a Word document or PDF
+//! - `GET /users/:id/export` - Zip of everything stored for a user (admin token required)
+//! - `DELETE /users/:id/data` - Erase everything stored for a user (admin token required)
+//! - `GET /profiles/:profile/vocabulary` - Vocabulary learned from corrections
+//! - `GET /usage` - Usage and quotas of the calling API key
+//! - `/admin/keys` - API key management (admin token required)
 //! - `GET /stream` - WebSocket endpoint for streaming transcription
+//! - `GET /openapi.json` - OpenAPI 3.1 document
+//! - `GET /console` - Browser test console (only with `VOICEMARK_CONSOLE=1`)
+//! - `GET /docs` - Swagger UI (only with `VOICEMARK_CONSOLE=1`)
+//!
+//! Without a local model, transcription can fall back to a remote
+//! Whisper-compatible API (`VOICEMARK_REMOTE_URL`), and transcripts can be
+//! grammar-corrected and summarized by a local LLM (`VOICEMARK_LLM_URL`).
+//!
+//! Requests and streaming sessions can be recorded in an access log
+//! (`VOICEMARK_ACCESS_LOG`), see [`access_log`].
 //!
 //! ## Usage
 //!
@@ -17,169 +51,1129 @@
 //! # Health check
 //! curl http://localhost:3001/health
 //!
+//! # Download the bundled ffmpeg for this platform
+//! cargo run -- fetch-ffmpeg
+//!
 //! # Transcribe audio
 //! curl -X POST -F "file=@audio.webm" http://localhost:3001/transcribe
+//!
+//! # Or send the audio as the raw request body
+//! curl -X POST -H "Content-Type: audio/webm" --data-binary @audio.webm http://localhost:3001/transcribe
 //! ```
 
-mod audio;
+mod access_log;
+mod admin;
+mod annotate;
+mod cache;
+mod clock;
+mod cluster;
+mod compare;
+mod compression;
+mod config;
+mod console;
+#[cfg(feature = "sentry")]
+mod crash_reports;
+mod deepgram;
+mod email;
+mod embeddings;
+mod encryption;
+mod error;
+mod eval;
+mod events;
+mod export;
+mod fetch_ffmpeg;
+mod hardware;
+mod inspect;
+mod jobs;
+mod jwt;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod llm;
+mod load_shed;
+mod loadtest;
+mod minutes;
+mod models;
+#[cfg(feature = "nats")]
+mod nats;
+mod negotiate;
+mod openapi;
+mod recordings;
+#[cfg(feature = "redis")]
+mod redis;
+mod remote;
+mod scheduler;
 mod stream;
-mod transcribe;
+mod systemd;
+mod tenants;
+mod tls;
+mod transcripts;
+mod upload;
+mod uploads;
+mod users;
+mod vocabulary;
+mod watch;
+mod webhooks;
+mod winservice;
+mod wordiff;
 
 use anyhow::{Context, Result};
 use axum::{
-    Json,
+    Extension, Json,
     Router,
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
+    body::Body,
+    extract::{
+        DefaultBodyLimit, Query,
+        rejection::{JsonRejection, QueryRejection},
+    },
+    http::header,
+    middleware,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{delete, get, post, put},
 };
-use axum_extra::extract::Multipart;
-use serde::Serialize;
-use std::env;
-use std::net::SocketAddr;
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::future::IntoFuture;
+use std::time::Duration;
+use tokio::sync::mpsc;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
-use tracing::{error, info, instrument};
+use tracing::{error, info, instrument, warn};
+use utoipa::{IntoParams, ToSchema};
+
+use access_log::Entry;
+use error::{ApiError, Problem};
+use negotiate::Accept;
+use remote::Backend;
+use scheduler::Priority;
+use tenants::Tenant;
+use upload::{AudioFile, AudioUpload, UploadForm};
+use voicemark_core::fingerprint::Fingerprint;
+use voicemark_core::{audio, constraint, formatting, music, quality, transcribe, waveform};
+
+/// Maximum request body size for uploads (base64 JSON bodies included).
+const MAX_BODY_BYTES: usize = 256 * 1024 * 1024;
 
-/// Default port for the sidecar server.
-const DEFAULT_PORT: u16 = 3001;
+/// Version of the transcription response schema, reported as
+/// `schema_version`. Bumped when a response changes incompatibly; new
+/// optional fields don't bump it.
+const SCHEMA_VERSION: u32 = 1;
+
+/// How often to check whether the model has been idle long enough to unload.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Overall server state, as reported by `/health`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum HealthStatus {
+    Ok,
+    /// Nothing can be transcribed: no model is loaded and there is no
+    /// remote API.
+    Degraded,
+}
 
 /// Health check response.
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct HealthResponse {
     ok: bool,
+    status: HealthStatus,
+    /// Why the model failed to load, until one loads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model_error: Option<String>,
     model_loaded: bool,
+    model_state: transcribe::ModelState,
+    /// Hardware acceleration used by the local model.
+    acceleration: transcribe::Acceleration,
+    /// Backend serving transcriptions, or `null` if there is neither a
+    /// local model nor a remote API.
+    backend: Option<Backend>,
+    /// ffmpeg binary in use, or `null` if none was found.
+    ffmpeg: Option<audio::FfmpegInfo>,
+    /// How the model was picked, with `VOICEMARK_MODEL_PATH=auto`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model_selection: Option<&'static hardware::ModelSelection>,
 }
 
 /// Transcription response.
-#[derive(Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 struct TranscribeResponse {
+    /// Response schema version (currently 1).
+    schema_version: u32,
+    /// Persisted transcript ID, if transcript persistence is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    /// The job's audio had already been transcribed with the same options:
+    /// this is that transcript (`id`), with any corrections, instead of a new
+    /// one.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    deduplicated: bool,
     text: String,
+    /// `text` with grammar and punctuation corrected by the LLM at
+    /// `VOICEMARK_LLM_URL`; omitted if polishing is disabled or failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text_polished: Option<String>,
     segments: usize,
+    /// Backend that transcribed the audio.
+    backend: Backend,
+    /// Language of the audio, for `language=auto` or multilingual models.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    /// Probability of the detected language, with `language=auto`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language_probability: Option<f32>,
+    /// Silence cut from the ends of the audio, with `trim_silence=true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trimmed: Option<transcribe::TrimmedSilence>,
+    /// Music and noise left out of transcription, with `non_speech`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    non_speech: Vec<music::NonSpeechRegion>,
+    /// Transcript of each channel, with `channels=split`; `text` then
+    /// interleaves them by time.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    channels: Vec<transcribe::ChannelTranscript>,
+    /// Decoded tokens of each segment, with `tokens=true`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tokens: Vec<transcribe::SegmentTokens>,
+    /// Problems with the recording, like clipping, for the client to show.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<quality::AudioWarning>,
+    /// The text as paragraphs of timed sentences, with `paragraphs=true`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    paragraphs: Vec<formatting::Paragraph>,
+    /// Amplitude peaks, if requested with `waveform`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    waveform: Option<waveform::Waveform>,
+    /// Titled chapters, for jobs submitted with `chapters=true`; omitted if
+    /// chaptering failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chapters: Option<Vec<llm::Chapter>>,
+    /// Named entities in `text`, for jobs submitted with `entities=true`;
+    /// omitted if extraction failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entities: Option<Vec<annotate::Entity>>,
+    /// Most frequent content words of `text`, for jobs submitted with
+    /// `keywords=true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keywords: Option<Vec<annotate::Keyword>>,
+    /// Meeting minutes, for jobs submitted with `meeting=true`; omitted if
+    /// the LLM failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    minutes: Option<minutes::MeetingMinutes>,
+    /// Timed segments, for SRT responses.
+    #[serde(skip)]
+    cues: Vec<transcribe::Segment>,
+}
+
+impl TranscribeResponse {
+    /// Build the response for `result`, persisting the transcript (and the
+    /// audio, if retained) for `tenant` when persistence is enabled, and
+    /// publishing it to the transcript event sinks (see [`events`]).
+    ///
+    /// Blocks while the transcript is polished; call it from a blocking
+    /// thread.
+    fn record(
+        audio_bytes: &[u8],
+        options: &transcribe::TranscribeOptions,
+        result: transcribe::TranscribeResult,
+        backend: Backend,
+        tenant: Option<&Tenant>,
+    ) -> Self {
+        let metadata = jobs::JobMetadata::default();
+        let source = events::Source::Transcribe;
+        let tenant = tenant.map(|tenant| tenant.tenant.as_str());
+        Self::record_job(audio_bytes, options, result, backend, &metadata, None, source, tenant)
+    }
+
+    /// Like [`record`](Self::record), storing a job's `metadata` and audio
+    /// `fingerprint` with the transcript and its event, which comes from
+    /// `source`.
+    #[allow(clippy::too_many_arguments)]
+    fn record_job(
+        audio_bytes: &[u8],
+        options: &transcribe::TranscribeOptions,
+        result: transcribe::TranscribeResult,
+        backend: Backend,
+        metadata: &jobs::JobMetadata,
+        fingerprint: Option<&Fingerprint>,
+        source: events::Source,
+        tenant: Option<&str>,
+    ) -> Self {
+        let id = transcripts::record(audio_bytes, options, &result, metadata, fingerprint, tenant);
+        let paragraphs = if options.paragraphs {
+            formatting::paragraphs(&result.timed_segments)
+        } else {
+            Vec::new()
+        };
+        if events::enabled() {
+            let event =
+                events::TranscriptEvent::batch(source, id.as_deref(), &result, backend, metadata);
+            events::publish(event);
+        }
+        Self {
+            schema_version: SCHEMA_VERSION,
+            id,
+            deduplicated: false,
+            text_polished: llm::polish(&result.text),
+            text: result.text,
+            segments: result.segments,
+            cues: result.timed_segments,
+            backend,
+            language: result.language,
+            language_probability: result.language_probability,
+            trimmed: result.trimmed,
+            non_speech: result.non_speech,
+            channels: result.channels,
+            tokens: result.tokens,
+            warnings: result.warnings,
+            paragraphs,
+            waveform: None,
+            chapters: None,
+            entities: None,
+            keywords: None,
+            minutes: None,
+        }
+    }
+
+    /// The response for a re-submitted recording: its stored `transcript`,
+    /// grouped into `paragraphs` if asked for.
+    fn deduplicated(
+        transcript: transcripts::Transcript,
+        backend: Backend,
+        paragraphs: bool,
+    ) -> Self {
+        let cues: Vec<transcribe::Segment> = transcript
+            .segment_list
+            .iter()
+            .map(|segment| transcribe::Segment {
+                start_ms: segment.start_ms,
+                end_ms: segment.end_ms,
+                text: segment.effective_text().to_string(),
+            })
+            .collect();
+        Self {
+            schema_version: SCHEMA_VERSION,
+            id: Some(transcript.id),
+            deduplicated: true,
+            text: transcript.corrected_text.unwrap_or(transcript.text),
+            text_polished: None,
+            segments: transcript.segments,
+            backend,
+            language: transcript.options.language.filter(|language| language != "auto"),
+            language_probability: None,
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            tokens: Vec::new(),
+            warnings: Vec::new(),
+            paragraphs: if paragraphs { formatting::paragraphs(&cues) } else { Vec::new() },
+            waveform: None,
+            chapters: None,
+            entities: None,
+            keywords: None,
+            minutes: None,
+            cues,
+        }
+    }
+
+    /// The response in the format the client accepts.
+    fn negotiate(self, format: negotiate::Format) -> Response {
+        let cues = self.cues.iter().map(|segment| negotiate::Cue {
+            start_ms: segment.start_ms,
+            end_ms: segment.end_ms,
+            text: &segment.text,
+        });
+        negotiate::respond(format, &self, &self.text, cues)
+    }
+}
+
+/// Query parameters for `POST /transcribe`, besides [`BatchQuery`].
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TranscribeQuery {
+    /// Also return waveform peaks at this many peaks per second.
+    waveform: Option<u32>,
+    /// `ndjson` streams segments as JSON lines while they are decoded.
+    response_format: Option<ResponseFormat>,
+}
+
+/// How `POST /transcribe` sends its result.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum ResponseFormat {
+    /// One body once transcription is done, in the format `Accept` prefers.
+    #[default]
+    Json,
+    /// Newline-delimited JSON (`application/x-ndjson`): a line per segment
+    /// as it is decoded and per progress update, then the result.
+    Ndjson,
 }
 
-/// Error response.
+/// One line of an NDJSON transcription response.
 #[derive(Serialize)]
-struct ErrorResponse {
-    error: String,
+#[serde(tag = "type", rename_all = "snake_case")]
+enum NdjsonLine<'a> {
+    Segment(&'a transcribe::Segment),
+    Progress { progress: i32 },
+    /// The whole result, last.
+    Done(&'a TranscribeResponse),
+    /// Transcription failed after lines were sent, last.
+    Error(&'a Problem),
+}
+
+impl NdjsonLine<'_> {
+    fn to_line(&self) -> Vec<u8> {
+        let mut line = serde_json::to_vec(self).unwrap_or_default();
+        line.push(b'\n');
+        line
+    }
+}
+
+/// Transcription settings of the batch endpoints: query parameters of
+/// `POST /transcribe`, `/transcribe/stream` and `/jobs`, and fields of the
+/// `/transcribe/json` body.
+#[derive(Debug, Default, Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+struct BatchQuery {
+    /// Vocabulary profile to transcribe with.
+    #[serde(default)]
+    profile: Option<String>,
+    /// Language code, or `auto` to detect it; defaults to English.
+    #[serde(default)]
+    language: Option<String>,
+    /// whisper.cpp decoding threads, overriding `VOICEMARK_THREADS`.
+    #[serde(default)]
+    threads: Option<u32>,
+    /// Added to every timestamp, e.g. the clip's position in a longer
+    /// recording.
+    #[serde(default)]
+    time_offset_ms: Option<i64>,
+    /// Audio track to transcribe from files with several (0-based, e.g.
+    /// `1` for the second language of a video); defaults to ffmpeg's pick.
+    #[serde(default)]
+    track: Option<u32>,
+    /// Transcribe only from this position in the file (milliseconds).
+    /// Timestamps stay on the file's timeline.
+    #[serde(default)]
+    start_ms: Option<i64>,
+    /// Transcribe only up to this position in the file (milliseconds).
+    #[serde(default)]
+    end_ms: Option<i64>,
+    /// Mix stereo audio down to mono (`downmix`, the default), or
+    /// transcribe each channel separately (`split`), e.g. for call
+    /// recordings with one speaker per channel.
+    #[serde(default)]
+    channels: Option<audio::Channels>,
+    /// Cut leading and trailing silence before transcribing; the response
+    /// reports how much was cut.
+    #[serde(default)]
+    trim_silence: bool,
+    /// Leave music and noise out of transcription (`exclude`), or also mark
+    /// it with `[music]`/`[noise]` segments (`mark`).
+    #[serde(default)]
+    non_speech: Option<music::NonSpeech>,
+    /// Also return the decoded tokens of each segment: IDs, text,
+    /// probabilities and (whisper.cpp only) timestamps.
+    #[serde(default)]
+    tokens: bool,
+    /// Formatting profile for the text: `raw` (the default), `sentences`,
+    /// `lowercase` or `dictation`.
+    #[serde(default)]
+    text_format: Option<formatting::TextFormat>,
+    /// Also return the text grouped into paragraphs of timed sentences.
+    #[serde(default)]
+    paragraphs: bool,
+    /// Limit the text to `digits`, or to letters and digits spelled out one
+    /// at a time (`spelling`), e.g. for order numbers or licence plates.
+    #[serde(default)]
+    constraint: Option<constraint::Constraint>,
+}
+
+/// JSON transcription request (`POST /transcribe/json`).
+#[derive(Deserialize, ToSchema)]
+struct TranscribeJsonRequest {
+    /// Base64-encoded audio file.
+    audio: String,
+    /// Container format hint, e.g. `"webm"`.
+    #[serde(default)]
+    format: Option<String>,
+    /// Also return waveform peaks at this many peaks per second.
+    #[serde(default)]
+    waveform: Option<u32>,
+    #[serde(flatten)]
+    batch: BatchQuery,
+    /// whisper.cpp decoder settings, overriding the server defaults.
+    #[serde(default)]
+    decoding: transcribe::DecodingParams,
+    /// How to split the text into segments.
+    #[serde(default)]
+    segmentation: transcribe::Segmentation,
+}
+
+/// Warmup response.
+#[derive(Serialize, ToSchema)]
+struct WarmupResponse {
+    ok: bool,
+    duration_ms: u64,
 }
 
 /// Health check endpoint.
 ///
-/// Returns `{ "ok": true, "model_loaded": true/false }`
+/// Returns `{ "ok": true, "status": "ok", "model_loaded": true/false, "model_state": "...",
+/// "ffmpeg": {...} }`. `model_loaded` stays true while the model is
+/// unloaded for idleness, since it is reloaded on demand. The server runs
+/// `degraded`, with `model_error`, when it started without a usable model.
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "system",
+    security(()),
+    responses((status = 200, description = "Server status", body = HealthResponse)),
+)]
 async fn health() -> Json<HealthResponse> {
+    let backend = remote::backend();
     Json(HealthResponse {
         ok: true,
+        status: if backend.is_some() { HealthStatus::Ok } else { HealthStatus::Degraded },
+        model_error: models::load_error().filter(|_| !transcribe::is_model_loaded()),
         model_loaded: transcribe::is_model_loaded(),
+        model_state: transcribe::model_state(),
+        acceleration: transcribe::acceleration(),
+        backend,
+        ffmpeg: audio::ffmpeg().ok(),
+        model_selection: hardware::selection(),
     })
 }
 
-/// Transcription endpoint.
+/// Warmup endpoint.
 ///
-/// Accepts multipart form data with a `file` field containing audio.
-/// Returns `{ "text": "...", "segments": N }`
-#[instrument(skip(multipart))]
-async fn transcribe_audio(mut multipart: Multipart) -> impl IntoResponse {
-    // Extract the audio file from multipart form
-    let audio_bytes = match extract_audio_file(&mut multipart).await {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            error!("Failed to extract audio file: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({ "error": e.to_string() })),
-            );
+/// Runs a short dummy transcription so orchestrators can warm the model
+/// before routing traffic. Returns `{ "ok": true, "duration_ms": N }`.
+/// There is nothing to warm up when the remote API serves transcriptions.
+#[utoipa::path(
+    post,
+    path = "/warmup",
+    tag = "system",
+    responses(
+        (status = 200, description = "Model warmed up", body = WarmupResponse),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Warmup failed", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "Model not loaded", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+async fn warmup() -> Result<Json<WarmupResponse>, ApiError> {
+    match remote::backend() {
+        Some(Backend::Local) => {}
+        Some(Backend::Remote) => {
+            return Ok(Json(WarmupResponse {
+                ok: true,
+                duration_ms: 0,
+            }))
         }
-    };
+        None => return Err(ApiError::ModelNotLoaded),
+    }
 
-    info!(bytes = audio_bytes.len(), "Received audio for transcription");
+    let elapsed = tokio::task::spawn_blocking(transcribe::warmup)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map_err(|e| {
+            error!("Warmup failed: {}", e);
+            ApiError::Internal(format!("Warmup failed: {}", e))
+        })?;
 
-    // Convert to WAV
-    let wav_file = if is_wav(&audio_bytes) {
-        match audio::write_temp_wav(&audio_bytes) {
-            Ok(f) => f,
-            Err(e) => {
-                error!("Failed to write temp WAV: {}", e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(serde_json::json!({ "error": format!("Failed to write temp WAV: {}", e) })),
-                );
-            }
-        }
+    Ok(Json(WarmupResponse {
+        ok: true,
+        duration_ms: elapsed.as_millis() as u64,
+    }))
+}
+
+/// Map a transcription failure to an API error.
+fn transcription_error(e: anyhow::Error) -> ApiError {
+    error!("Transcription failed: {}", e);
+    if remote::backend().is_some() {
+        #[cfg(feature = "sentry")]
+        crash_reports::capture_error("transcribe", &e);
+        ApiError::TranscriptionFailed(e.to_string())
     } else {
-        match audio::convert_to_wav(&audio_bytes) {
-            Ok(f) => f,
-            Err(e) => {
-                error!("Audio conversion failed: {}", e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(serde_json::json!({ "error": format!("Audio conversion failed: {}", e) })),
-                );
-            }
-        }
-};
+        ApiError::ModelNotLoaded
+    }
+}
+
+/// Transcription endpoint.
+///
+/// Accepts multipart form data with the audio in a `file`, `audio`, or
+/// other file field, or a raw body with an `audio/*` content type.
+/// Returns `{ "text": "...", "segments": N }`, plus `waveform` peaks with
+/// `?waveform=<peaks per second>`. With `?response_format=ndjson`, segments
+/// are streamed as JSON lines while they are decoded, ending with the
+/// result.
+#[utoipa::path(
+    post,
+    path = "/transcribe",
+    tag = "transcription",
+    params(TranscribeQuery, BatchQuery, transcribe::DecodingParams, transcribe::Segmentation),
+    request_body(
+        description = "Audio as a multipart form, or as the raw request body",
+        content(
+            (UploadForm = "multipart/form-data"),
+            (AudioFile = "audio/*"),
+            (AudioFile = "video/*"),
+            (AudioFile = "application/octet-stream"),
+        ),
+    ),
+    responses(
+        (status = 200, description = "Transcription, as JSON, text or SRT subtitles per `Accept`; \
+                                        with `response_format=ndjson`, lines of `segment` (a `Segment`) \
+                                        and `progress` (`{\"progress\": N}`), then `done` (a \
+                                        `TranscribeResponse`) or `error` (a `Problem`), tagged by `type`", content(
+            (TranscribeResponse = "application/json"),
+            (String = "text/plain"),
+            (String = "application/x-subrip"),
+            (String = "application/x-ndjson"),
+        )),
+        (status = 400, description = "Invalid request or empty audio", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 402, description = "Daily audio quota used up", body = Problem, content_type = "application/problem+json"),
+        (status = 406, description = "`Accept` allows none of JSON, text or SRT", body = Problem, content_type = "application/problem+json"),
+        (status = 413, description = "Upload too large", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "Unsupported request content type", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Unsupported or corrupt audio", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Transcription failed", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "Model not loaded, or ffmpeg needed but unavailable", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+#[instrument(skip(tenant, entry, accept, query, batch, decoding, segmentation, upload))]
+#[allow(clippy::too_many_arguments)] // one per extractor
+async fn transcribe_audio(
+    tenant: Option<Extension<Tenant>>,
+    entry: Option<Extension<Entry>>,
+    accept: Result<Accept, ApiError>,
+    query: Result<Query<TranscribeQuery>, QueryRejection>,
+    batch: Result<Query<BatchQuery>, QueryRejection>,
+    decoding: Result<Query<transcribe::DecodingParams>, QueryRejection>,
+    segmentation: Result<Query<transcribe::Segmentation>, QueryRejection>,
+    upload: AudioUpload,
+) -> Result<Response, ApiError> {
+    let Query(query) = query?;
+    let Query(batch) = batch?;
+    let Query(decoding) = decoding?;
+    let Query(segmentation) = segmentation?;
+    let AudioUpload(audio_bytes) = upload;
+    let options = batch_options(batch, decoding, segmentation)?;
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    let entry = entry.map(|Extension(entry)| entry);
+    if query.response_format == Some(ResponseFormat::Ndjson) {
+        return transcribe_ndjson(tenant, entry, audio_bytes, options, query.waveform).await;
+    }
+    let Accept(accept) = accept?;
+    tokio::task::spawn_blocking(move || {
+        transcribe_upload(
+            tenant.as_ref(),
+            entry.as_ref(),
+            &audio_bytes,
+            None,
+            options,
+            query.waveform,
+        )
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))?
+    .map(|Json(response)| response.negotiate(accept))
+}
 
+/// JSON transcription endpoint.
+///
+/// Accepts `{ "audio": "<base64>", "format": "webm", "language": "en" }` for
+/// clients where building multipart bodies is awkward. `format`, `language`
+/// and `waveform` are optional. Returns the same body as `/transcribe`.
+#[utoipa::path(
+    post,
+    path = "/transcribe/json",
+    tag = "transcription",
+    request_body = TranscribeJsonRequest,
+    responses(
+        (status = 200, description = "Transcription, as JSON, text or SRT subtitles per `Accept`", content(
+            (TranscribeResponse = "application/json"),
+            (String = "text/plain"),
+            (String = "application/x-subrip"),
+        )),
+        (status = 400, description = "Invalid request or empty audio", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 402, description = "Daily audio quota used up", body = Problem, content_type = "application/problem+json"),
+        (status = 406, description = "`Accept` allows none of JSON, text or SRT", body = Problem, content_type = "application/problem+json"),
+        (status = 413, description = "Upload too large", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "Unsupported request content type", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Unsupported or corrupt audio", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Transcription failed", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "Model not loaded, or ffmpeg needed but unavailable", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+#[instrument(skip(tenant, entry, payload))]
+async fn transcribe_json(
+    tenant: Option<Extension<Tenant>>,
+    entry: Option<Extension<Entry>>,
+    Accept(accept): Accept,
+    payload: Result<Json<TranscribeJsonRequest>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    let Json(request) = payload?;
 
+    use base64::Engine;
+    let audio_bytes = base64::engine::general_purpose::STANDARD
+        .decode(request.audio.trim())
+        .map_err(|e| ApiError::InvalidRequest(format!("Invalid base64 audio: {}", e)))?;
+    if audio_bytes.is_empty() {
+        return Err(ApiError::EmptyAudio);
+    }
+    info!(bytes = audio_bytes.len(), "Received audio for transcription");
 
-    // Read WAV samples
-    let samples = match audio::read_wav_samples(wav_file.path()) {
-        Ok(s) => s,
-        Err(e) => {
-            error!("Failed to read WAV samples: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({ "error": format!("Failed to read audio: {}", e) })),
-            );
-        }
+    let options = batch_options(request.batch, request.decoding, request.segmentation)?;
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    let entry = entry.map(|Extension(entry)| entry);
+    tokio::task::spawn_blocking(move || {
+        transcribe_upload(
+            tenant.as_ref(),
+            entry.as_ref(),
+            &audio_bytes,
+            request.format.as_deref(),
+            options,
+            request.waveform,
+        )
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))?
+    .map(|Json(response)| response.negotiate(accept))
+}
+
+/// Transcribe uploaded audio as an NDJSON response: a line per segment as
+/// whisper decodes it and per progress update, then a `done` line with the
+/// result. Failures before the first line are answered with their status;
+/// later ones end the body with an `error` line.
+async fn transcribe_ndjson(
+    tenant: Option<Tenant>,
+    entry: Option<Entry>,
+    audio_bytes: Vec<u8>,
+    options: transcribe::TranscribeOptions,
+    peaks_per_second: Option<u32>,
+) -> Result<Response, ApiError> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<Result<Vec<u8>, ApiError>>();
+    tokio::task::spawn_blocking(move || {
+        let response = transcribe_upload_with_callbacks(
+            tenant.as_ref(),
+            entry.as_ref(),
+            &audio_bytes,
+            None,
+            options,
+            peaks_per_second,
+            |segment| {
+                let _ = tx.send(Ok(NdjsonLine::Segment(segment).to_line()));
+            },
+            |progress| {
+                let _ = tx.send(Ok(NdjsonLine::Progress { progress }.to_line()));
+            },
+        );
+        let _ = tx.send(response.map(|Json(response)| NdjsonLine::Done(&response).to_line()));
+    });
+
+    let first = match rx.recv().await {
+        Some(Err(e)) => return Err(e),
+        first => first,
     };
+    let lines = futures_util::stream::unfold((first, rx), |(line, mut rx)| async move {
+        let line = match line? {
+            Ok(line) => line,
+            Err(e) => NdjsonLine::Error(&e.problem()).to_line(),
+        };
+        let next = rx.recv().await;
+        Some((Ok::<_, Infallible>(line), (next, rx)))
+    });
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(lines)).into_response())
+}
+
+/// Transcribe uploaded audio bytes, using the result cache.
+///
+/// With `peaks_per_second`, waveform peaks are computed from the decoded
+/// audio (decoding it even on a cache hit). Transcribed audio is charged to
+/// `tenant` and counted in the access log `entry`; cache hits are free.
+/// Blocks while transcribing.
+fn transcribe_upload(
+    tenant: Option<&Tenant>,
+    entry: Option<&Entry>,
+    audio_bytes: &[u8],
+    format: Option<&str>,
+    options: transcribe::TranscribeOptions,
+    peaks_per_second: Option<u32>,
+) -> Result<Json<TranscribeResponse>, ApiError> {
+    transcribe_upload_with_callbacks(
+        tenant,
+        entry,
+        audio_bytes,
+        format,
+        options,
+        peaks_per_second,
+        |_| {},
+        |_| {},
+    )
+}
 
-    // Transcribe
-    let result = match transcribe::transcribe(&samples, transcribe::TranscribeOptions::default()) {
-        Ok(r) => r,
-        Err(e) => {
-            error!("Transcription failed: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({ "error": format!("Transcription failed: {}", e) })),
+/// Like [`transcribe_upload`], calling `on_segment` for each segment as it
+/// is decoded and `on_progress` with the completion percentage. Cache hits
+/// call neither.
+#[allow(clippy::too_many_arguments)]
+fn transcribe_upload_with_callbacks(
+    tenant: Option<&Tenant>,
+    entry: Option<&Entry>,
+    audio_bytes: &[u8],
+    format: Option<&str>,
+    options: transcribe::TranscribeOptions,
+    peaks_per_second: Option<u32>,
+    on_segment: impl FnMut(&transcribe::Segment),
+    on_progress: impl FnMut(i32),
+) -> Result<Json<TranscribeResponse>, ApiError> {
+    let cache_key = cache::cache_key(audio_bytes, &options);
+
+    let mut samples = None;
+    let (result, backend) = match cache::get(&cache_key) {
+        // Cache keys include the model, so the entry is from the current backend
+        Some(result) => (result, remote::backend().unwrap_or(Backend::Local)),
+        None => {
+            let channels = decode_channels(audio_bytes, format, &options)?;
+
+            // Transcribe
+            let permit = scheduler::acquire(Priority::Batch);
+            let (result, backend) = remote::transcribe_channels_with_callbacks(
+                &channels,
+                options.clone(),
+                on_segment,
+                on_progress,
+            )
+            .map_err(transcription_error)?;
+            drop(permit);
+            cache::put(&cache_key, &result);
+            let transcribed = channels.iter().map(Vec::len).sum();
+            tenants::charge(tenant, transcribed);
+            access_log::add_audio(entry, transcribed);
+            samples = Some(audio::downmix(channels));
+
+            info!(
+                text_len = result.text.len(),
+                segments = result.segments,
+                ?backend,
+                "Transcription successful"
             );
+            (result, backend)
         }
     };
 
-    info!(
-        text_len = result.text.len(),
-        segments = result.segments,
-        "Transcription successful"
-    );
+    let waveform = match (peaks_per_second, samples) {
+        (None, _) => None,
+        (Some(rate), Some(samples)) => Some(waveform::peaks(&samples, rate)),
+        (Some(rate), None) => {
+            Some(waveform::peaks(&decode_upload(audio_bytes, format, &options)?, rate))
+        }
+    };
 
-    (
-        StatusCode::OK,
-        Json(serde_json::json!({
-            "text": result.text,
-            "segments": result.segments
-        })),
-    )
+    Ok(Json(TranscribeResponse {
+        waveform,
+        ..TranscribeResponse::record(audio_bytes, &options, result, backend, tenant)
+    }))
 }
 
-/// Extract audio file bytes from multipart form.
-async fn extract_audio_file(multipart: &mut Multipart) -> Result<Vec<u8>> {
-    while let Some(field) = multipart
-        .next_field()
+/// Audio inspection endpoint.
+///
+/// Accepts the same uploads as `/transcribe` and reports the container,
+/// codec, duration, sample rate, channels and whether the file can be
+/// transcribed, without running whisper.
+#[utoipa::path(
+    post,
+    path = "/inspect",
+    tag = "transcription",
+    request_body(
+        description = "Audio as a multipart form, or as the raw request body",
+        content(
+            (UploadForm = "multipart/form-data"),
+            (AudioFile = "audio/*"),
+            (AudioFile = "video/*"),
+            (AudioFile = "application/octet-stream"),
+        ),
+    ),
+    responses(
+        (status = 200, description = "Audio properties; unsupported files have `supported: false`", body = inspect::AudioInfo),
+        (status = 400, description = "Invalid request or empty audio", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 413, description = "Upload too large", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "Unsupported request content type", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "ffmpeg needed but unavailable", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+#[instrument(skip(upload))]
+async fn inspect_audio(upload: AudioUpload) -> Result<Json<inspect::AudioInfo>, ApiError> {
+    let AudioUpload(audio_bytes) = upload;
+    tokio::task::spawn_blocking(move || inspect::inspect(&audio_bytes))
         .await
-        .context("Failed to get next field")?
-    {
-        let name = field.name().unwrap_or_default().to_string();
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map(Json)
+}
 
-        if name == "file" {
-            let bytes = field.bytes().await.context("Failed to read file bytes")?;
-            return Ok(bytes.to_vec());
-        }
+/// Streaming transcription endpoint (Server-Sent Events).
+///
+/// Accepts the same uploads as `/transcribe`. Emits a `segment` event
+/// for each segment as whisper decodes it and `progress` events with the
+/// completion percentage, then a final `done` event with the full
+/// `{ "text": "...", "segments": N }` result (or an `error` event).
+/// Cached results are sent as a single `done` event.
+#[utoipa::path(
+    post,
+    path = "/transcribe/stream",
+    operation_id = "transcribe_stream",
+    tag = "transcription",
+    params(BatchQuery, transcribe::DecodingParams, transcribe::Segmentation),
+    request_body(
+        description = "Audio as a multipart form, or as the raw request body",
+        content(
+            (UploadForm = "multipart/form-data"),
+            (AudioFile = "audio/*"),
+            (AudioFile = "video/*"),
+            (AudioFile = "application/octet-stream"),
+        ),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Server-Sent Events: `progress` (`{\"progress\": N}`), `segment` \
+                           (a `Segment`), then `done` (a `TranscribeResponse`) or `error` (a `Problem`)",
+            content_type = "text/event-stream",
+            body = String,
+        ),
+        (status = 400, description = "Invalid request or empty audio", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 402, description = "Daily audio quota used up", body = Problem, content_type = "application/problem+json"),
+        (status = 413, description = "Upload too large", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "Unsupported request content type", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Unsupported or corrupt audio", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "Model not loaded, or ffmpeg needed but unavailable", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+#[instrument(skip(tenant, entry, query, decoding, segmentation, upload))]
+async fn transcribe_audio_sse(
+    tenant: Option<Extension<Tenant>>,
+    entry: Option<Extension<Entry>>,
+    query: Result<Query<BatchQuery>, QueryRejection>,
+    decoding: Result<Query<transcribe::DecodingParams>, QueryRejection>,
+    segmentation: Result<Query<transcribe::Segmentation>, QueryRejection>,
+    upload: AudioUpload,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let Query(query) = query?;
+    let Query(decoding) = decoding?;
+    let Query(segmentation) = segmentation?;
+    let AudioUpload(audio_bytes) = upload;
+    let options = batch_options(query, decoding, segmentation)?;
+    let cache_key = cache::cache_key(&audio_bytes, &options);
+    let (tx, rx) = mpsc::unbounded_channel::<Event>();
+
+    if let Some(result) = cache::get(&cache_key) {
+        let backend = remote::backend().unwrap_or(Backend::Local);
+        tokio::task::spawn_blocking(move || {
+            let response = TranscribeResponse::record(
+                &audio_bytes,
+                &options,
+                result,
+                backend,
+                tenant.as_deref(),
+            );
+            let _ = tx.send(done_event(Ok(response)));
+        });
+    } else {
+        let channels = decode_channels(&audio_bytes, None, &options)?;
+
+        tokio::task::spawn_blocking(move || {
+            let permit = scheduler::acquire(Priority::Batch);
+            let result = remote::transcribe_channels_with_callbacks(
+                &channels,
+                options.clone(),
+                |segment| {
+                    if let Ok(event) = Event::default().event("segment").json_data(segment) {
+                        let _ = tx.send(event);
+                    }
+                },
+                |progress| {
+                    let data = serde_json::json!({ "progress": progress });
+                    if let Ok(event) = Event::default().event("progress").json_data(data) {
+                        let _ = tx.send(event);
+                    }
+                },
+            );
+            drop(permit);
+            let response = result.map(|(result, backend)| {
+                cache::put(&cache_key, &result);
+                let transcribed = channels.iter().map(Vec::len).sum();
+                let tenant = tenant.as_deref();
+                tenants::charge(tenant, transcribed);
+                access_log::add_audio(entry.as_deref(), transcribed);
+                TranscribeResponse::record(&audio_bytes, &options, result, backend, tenant)
+            });
+            let _ = tx.send(done_event(response));
+        });
+    }
+
+    let events = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|event| (Ok(event), rx))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Build the terminating SSE event: `done` with the result, or `error`.
+fn done_event(result: Result<TranscribeResponse>) -> Event {
+    let event = match result {
+        Ok(response) => Event::default().event("done").json_data(response),
+        Err(e) => Event::default()
+            .event("error")
+            .json_data(transcription_error(e).problem()),
+    };
+    event.unwrap_or_else(|e| Event::default().event("error").data(e.to_string()))
+}
+
+/// Transcription options for uploaded files.
+///
+/// Uploads are often long recordings with extended silences, so VAD
+/// pre-filtering is enabled. With a `profile`, its learned vocabulary is
+/// used as the initial prompt. Unset decoder settings are filled from the
+/// server defaults, so cache keys reflect the settings actually used.
+fn batch_options(
+    batch: BatchQuery,
+    decoding: transcribe::DecodingParams,
+    segmentation: transcribe::Segmentation,
+) -> Result<transcribe::TranscribeOptions, ApiError> {
+    let BatchQuery {
+        profile,
+        language,
+        threads,
+        time_offset_ms,
+        track,
+        start_ms,
+        end_ms,
+        channels,
+        trim_silence,
+        non_speech,
+        tokens,
+        text_format,
+        paragraphs,
+        constraint,
+    } = batch;
+    if let Some(profile) = &profile {
+        vocabulary::validate_profile(profile)?;
+    }
+    if threads == Some(0) {
+        return Err(ApiError::InvalidRequest("threads must be at least 1".to_string()));
     }
+    if time_offset_ms.is_some_and(|offset| offset < 0) {
+        return Err(ApiError::InvalidRequest("time_offset_ms must not be negative".to_string()));
+    }
+    if start_ms.is_some_and(|start| start < 0) {
+        return Err(ApiError::InvalidRequest("start_ms must not be negative".to_string()));
+    }
+    if end_ms.is_some_and(|end| end <= start_ms.unwrap_or(0)) {
+        return Err(ApiError::InvalidRequest("end_ms must be after start_ms".to_string()));
+    }
+    decoding
+        .validate()
+        .map_err(|e| ApiError::InvalidRequest(e.to_string()))?;
+    Ok(transcribe::TranscribeOptions {
+        language,
+        vad: true,
+        initial_prompt: profile.as_deref().and_then(vocabulary::prompt),
+        profile,
+        threads,
+        decoding: decoding.or(&transcribe::decoding_defaults()),
+        segmentation,
+        time_offset_ms: time_offset_ms.unwrap_or(0),
+        track,
+        start_ms,
+        end_ms,
+        channels: channels.unwrap_or_default(),
+        trim_silence,
+        non_speech,
+        tokens,
+        text_format: text_format.unwrap_or_default(),
+        paragraphs,
+        constraint: constraint.unwrap_or_default(),
+        ..Default::default()
+    })
+}
 
-    anyhow::bail!("No 'file' field found in multipart form")
+/// Decode uploaded audio (or the audio of a video) to 16kHz mono f32 samples.
+///
+/// `format` is an optional container hint passed on to ffmpeg. The
+/// `options` select one of several audio tracks
+/// ([`track`](transcribe::TranscribeOptions::track)) and the part of the
+/// file to decode ([`start_ms`](transcribe::TranscribeOptions::start_ms) to
+/// [`end_ms`](transcribe::TranscribeOptions::end_ms)). Channels are always
+/// mixed down.
+fn decode_upload(
+    audio_bytes: &[u8],
+    format: Option<&str>,
+    options: &transcribe::TranscribeOptions,
+) -> Result<Vec<f32>, ApiError> {
+    let options = transcribe::TranscribeOptions {
+        channels: audio::Channels::Downmix,
+        ..options.clone()
+    };
+    decode_channels(audio_bytes, format, &options).map(audio::downmix)
 }
 
-fn is_wav(bytes: &[u8]) -> bool {
-    bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WAVE"
+/// Like [`decode_upload`], keeping the channels separate with
+/// [`Channels::Split`](audio::Channels::Split). Returns one channel
+/// otherwise.
+fn decode_channels(
+    audio_bytes: &[u8],
+    format: Option<&str>,
+    options: &transcribe::TranscribeOptions,
+) -> Result<Vec<Vec<f32>>, ApiError> {
+    let transcribe::TranscribeOptions { track, start_ms, end_ms, channels, .. } = *options;
+    // Convert to WAV (WAVs already in 16kHz mono 16-bit PCM skip ffmpeg)
+    let wav_ready = audio::parse_wav_header(audio_bytes).is_some_and(|f| f.is_whisper_ready())
+        && track.unwrap_or(0) == 0;
+    let wav_file = if wav_ready {
+        audio::write_temp_wav(audio_bytes).map_err(|e| {
+            error!("Failed to write temp WAV: {}", e);
+            ApiError::Internal(format!("Failed to write temp WAV: {}", e))
+        })?
+    } else {
+        audio::ffmpeg_path().map_err(|e| ApiError::FfmpegUnavailable(e.to_string()))?;
+        audio::convert_clip_to_wav(audio_bytes, format, track, start_ms, end_ms, channels)
+            .map_err(|e| {
+                error!("Audio conversion failed: {}", e);
+                match e.downcast_ref::<audio::TrackError>() {
+                    Some(error @ audio::TrackError::NotFound { .. }) => {
+                        ApiError::InvalidRequest(error.to_string())
+                    }
+                    _ => ApiError::UnsupportedFormat(e.to_string()),
+                }
+            })?
+    };
+
+    // Read WAV samples
+    let mut channels = audio::read_wav_channels(wav_file.path()).map_err(|e| {
+        error!("Failed to read WAV samples: {}", e);
+        ApiError::UnsupportedFormat(e.to_string())
+    })?;
+    for samples in &mut channels {
+        if wav_ready {
+            // ffmpeg cuts converted files to the requested part; cut WAVs here
+            let len = samples.len();
+            let to_sample = |ms: i64| (ms as usize * 16).min(len);
+            samples.truncate(end_ms.map_or(len, to_sample));
+            samples.drain(..start_ms.map_or(0, to_sample).min(samples.len()));
+        }
+        if samples.is_empty() && start_ms.is_some() {
+            return Err(ApiError::InvalidRequest(
+                "start_ms is past the end of the audio".to_string(),
+            ));
+        }
+    }
+    Ok(channels)
 }
 
 /// Build the application router.
@@ -190,12 +1184,79 @@ fn build_router() -> Router {
         .allow_methods(Any)
         .allow_headers(Any);
 
-    Router::new()
-        .route("/health", get(health))
+    // Everything but /health requires an API key or JWT when auth is enabled,
+    // and is shed beyond the concurrency limits before authenticating
+    let api = Router::new()
         .route("/transcribe", post(transcribe_audio))
+        .route("/transcribe/json", post(transcribe_json))
+        .route("/transcribe/stream", post(transcribe_audio_sse))
+        .route("/inspect", post(inspect_audio))
+        .route("/compare", post(compare::compare_models))
+        .route("/warmup", post(warmup))
+        .route("/model", get(models::get_model))
+        .route("/jobs", post(jobs::submit_job))
+        .route("/jobs/:id", get(jobs::job_status))
+        .route("/uploads", post(uploads::create_upload))
+        .route(
+            "/uploads/:id",
+            get(uploads::upload_status)
+                .patch(uploads::append_chunk)
+                .delete(uploads::cancel_upload),
+        )
+        .route("/uploads/:id/complete", post(uploads::complete_upload))
+        .route("/transcripts", get(transcripts::list_transcripts))
+        .route("/transcripts/semantic-search", get(transcripts::semantic_search))
+        .route(
+            "/transcripts/:id",
+            get(transcripts::get_transcript)
+                .patch(transcripts::correct_transcript)
+                .delete(transcripts::delete_transcript),
+        )
+        .route("/transcripts/:id/summarize", post(transcripts::summarize_transcript))
+        .route("/transcripts/:id/audio", get(transcripts::get_transcript_audio))
+        .route("/transcripts/:id/export", get(transcripts::export_transcript))
+        .route("/profiles/:profile/vocabulary", get(vocabulary::get_vocabulary))
+        .route("/usage", get(tenants::get_usage))
         .route("/stream", get(stream::ws_handler))
+        .route("/listen", get(deepgram::listen))
+        .route_layer(middleware::from_fn(cluster::dispatch))
+        .route_layer(middleware::from_fn(tenants::authenticate))
+        .route_layer(middleware::from_fn(load_shed::shed));
+
+    let admin = Router::new()
+        .route("/admin/keys", get(admin::list_keys).post(admin::create_key))
+        .route("/admin/keys/:id", delete(admin::revoke_key))
+        .route("/admin/keys/:id/quota", put(admin::set_quota))
+        .route("/model", post(models::load_model))
+        .route("/users/:id/export", get(users::export_user))
+        .route("/users/:id/data", delete(users::delete_user_data))
+        .route_layer(middleware::from_fn(admin::require_admin));
+
+    let cluster = Router::new()
+        .route("/cluster/workers", get(cluster::list_workers).post(cluster::register_worker))
+        .route_layer(middleware::from_fn(cluster::require_token));
+
+    let routes = Router::new()
+        .route("/health", get(health))
+        .route("/openapi.json", get(openapi::openapi_json))
+        .merge(api)
+        .merge(admin)
+        .merge(cluster);
+
+    // Everything is served under /v1; the unversioned paths stay as aliases
+    let mut router = Router::new().nest("/v1", routes.clone()).merge(routes);
+    if console::enabled() {
+        router = router
+            .route("/console", get(console::page))
+            .route("/docs", get(openapi::swagger_ui));
+    }
+
+    router
+        .layer(DefaultBodyLimit::max(MAX_BODY_BYTES))
+        .layer(middleware::from_fn(compression::compress))
         .layer(cors)
         .layer(TraceLayer::new_for_http())
+        .layer(middleware::from_fn(access_log::record))
 }
 
 #[tokio::main]
@@ -208,28 +1269,429 @@ async fn main() -> Result<()> {
         )
         .init();
 
+    // Subcommands
+    let mut args = std::env::args().skip(1);
+    if let Some(command) = args.next() {
+        return match command.as_str() {
+            "fetch-ffmpeg" => fetch_ffmpeg::run(args).await,
+            "create-key" => tenants::run_create_key(args),
+            "eval" => {
+                let args: Vec<String> = args.collect();
+                tokio::task::spawn_blocking(move || eval::run(args)).await?
+            }
+            "loadtest" => loadtest::run(args).await,
+            "--service" => tokio::task::spawn_blocking(winservice::run).await?,
+            "install-service" => winservice::install(),
+            "uninstall-service" => winservice::uninstall(),
+            _ => anyhow::bail!(
+                "Unknown command '{}'. Available: fetch-ffmpeg, create-key, eval, \
+                 loadtest, install-service, uninstall-service, --service",
+                command
+            ),
+        };
+    }
+
+    run_server(
+        async {
+            let _ = tokio::signal::ctrl_c().await;
+        },
+        || systemd::notify("READY=1"),
+    )
+    .await
+}
+
+/// Path of the model to load at startup, picking one for this machine with
+/// `VOICEMARK_MODEL_PATH=auto`.
+fn startup_model(config: &config::Config) -> Result<Option<String>> {
+    match config.model_path.as_deref() {
+        Some("auto") => {
+            let hardware = hardware::Hardware::detect(config.acceleration);
+            let selection = hardware::select_model(&config.models_dir, hardware);
+            match &selection.path {
+                Some(path) => Ok(Some(path.clone())),
+                None => anyhow::bail!("Automatic model selection: {}", selection.reason),
+            }
+        }
+        path => Ok(path.map(String::from)),
+    }
+}
+
+/// Start the server and run it until `shutdown` completes or a listener
+/// fails. `ready` is called once the model is loaded and every listener is
+/// bound.
+async fn run_server(
+    shutdown: impl std::future::Future<Output = ()>,
+    ready: impl FnOnce(),
+) -> Result<()> {
     info!("VoiceMark Transcription Sidecar starting...");
 
-    // Get model path from environment or use default
-    let model_path = env::var("VOICEMARK_MODEL_PATH").ok();
+    let config = config::Config::from_env()?;
+
+    // Report crashes as early as possible
+    #[cfg(feature = "sentry")]
+    if let Some(dsn) = &config.sentry_dsn {
+        crash_reports::configure(dsn, config.sentry_details)
+            .context("Invalid VOICEMARK_SENTRY_DSN")?;
+    }
+    #[cfg(not(feature = "sentry"))]
+    if config.sentry_dsn.is_some() || config.sentry_details {
+        warn!("VOICEMARK_SENTRY_* is set, but this build has no crash reporting");
+    }
+
+    // Publish completed transcripts to the data platform
+    #[cfg(feature = "kafka")]
+    if let Some((url, topic)) = config.kafka() {
+        kafka::configure(url, topic).context("Invalid VOICEMARK_KAFKA_REST_URL")?;
+    }
+    #[cfg(not(feature = "kafka"))]
+    if config.kafka().is_some() {
+        warn!("VOICEMARK_KAFKA_REST_URL is set, but this build has no Kafka support");
+    }
+
+    // Relay live captions for consumers without a WebSocket
+    #[cfg(feature = "redis")]
+    if let Some((url, prefix)) = config.redis() {
+        redis::configure(url, prefix).context("Invalid VOICEMARK_REDIS_URL")?;
+    }
+    #[cfg(not(feature = "redis"))]
+    if config.redis().is_some() {
+        warn!("VOICEMARK_REDIS_URL is set, but this build has no Redis support");
+    }
+
+    // Let streams deliver their transcripts to webhooks
+    if let Some(webhooks) = config.webhooks() {
+        webhooks::configure(webhooks)?;
+    }
+
+    // Email finished jobs to the addresses in their metadata
+    if let Some(smtp) = config.smtp()? {
+        email::configure(smtp)?;
+    }
+
+    // Configure the remote fallback, then initialize the local Whisper model
+    // unless transcription is remote-only
+    if let Some(remote) = config.remote() {
+        remote::configure(remote)?;
+    } else if config.remote_only {
+        anyhow::bail!("VOICEMARK_REMOTE_ONLY requires VOICEMARK_REMOTE_URL");
+    }
+    if let Some(llm) = config.llm() {
+        llm::configure(llm)?;
+    }
+    if let Some(embeddings) = config.embeddings() {
+        embeddings::configure(embeddings)?;
+    }
+    transcribe::set_acceleration(config.acceleration);
+    transcribe::set_cpu_limits(config.cpu_limits());
+    transcribe::set_decoding_defaults(config.decoding.clone());
+    if config.remote_only {
+        info!("Remote-only transcription; not loading a local model");
+    } else if let Err(e) = startup_model(&config)
+        .and_then(|path| transcribe::init_model(path.as_deref()))
+    {
+        // Keep serving (degraded) so a model can be loaded with POST /model
+        models::record_error(&e);
+        if remote::enabled() {
+            warn!("{:#}; falling back to the remote API", e);
+        } else {
+            warn!("{:#}; starting degraded until a model is loaded with POST /model", e);
+        }
+    }
+    if !config.compare_models.is_empty() {
+        compare::configure(config.compare_models.clone());
+    }
+    // Load the partial model up front so the first stream doesn't wait for it
+    if let Some(path) = config.partial_model.as_deref().filter(|_| transcribe::is_model_loaded()) {
+        if let Err(e) = transcribe::load_model(path) {
+            warn!("{:#}; streaming partials will use the configured model", e);
+        }
+    }
+
+    // Locate ffmpeg (bundled, VOICEMARK_FFMPEG, then PATH)
+    audio::configure_ffmpeg(config.ffmpeg.clone());
+    if let Err(e) = audio::ffmpeg() {
+        warn!("{}; only WAV uploads can be transcribed", e);
+    }
+
+    // Require API keys if a tenant database is configured, JWTs if a JWKS is
+    if let Some(path) = &config.tenants_db {
+        tenants::configure(path).context("Failed to set up VOICEMARK_TENANTS_DB")?;
+    }
+    if let Some(jwt) = config.jwt() {
+        jwt::configure(jwt)?;
+    }
+    if let Some(token) = &config.admin_token {
+        if config.tenants_db.is_none() {
+            anyhow::bail!("VOICEMARK_ADMIN_TOKEN requires VOICEMARK_TENANTS_DB");
+        }
+        admin::configure(token);
+    }
+
+    // Record requests in the access log
+    if let Some(access_log) = config.access_log() {
+        access_log::configure(access_log).context("Failed to set up VOICEMARK_ACCESS_LOG")?;
+    }
+
+    // Development test console
+    if config.console {
+        console::enable();
+    }
+
+    // Encrypt what's stored on disk, before anything is
+    if let Some(key) = config.encryption_key()? {
+        encryption::configure(&key)?;
+    }
+
+    // Configure the result cache
+    cache::configure(config.cache_size, config.cache_dir.clone());
+
+    // Persist transcripts (and optionally audio) if a data directory is set
+    let audio_retention = config.audio_retention();
+    let transcript_retention = config.transcript_retention();
+    if let Some(data_dir) = &config.data_dir {
+        transcripts::configure(
+            data_dir.clone(),
+            audio_retention.clone(),
+            transcript_retention,
+            config.autosave(),
+        )
+        .context("Failed to set up VOICEMARK_DATA_DIR")?;
+        vocabulary::configure(data_dir.clone())
+            .context("Failed to set up VOICEMARK_DATA_DIR")?;
+        if encryption::enabled() {
+            tokio::task::spawn_blocking(transcripts::seal_existing);
+        }
+    } else if config.retain_audio {
+        warn!("VOICEMARK_RETAIN_AUDIO needs VOICEMARK_DATA_DIR; audio will not be retained");
+    }
+
+    // Refuse requests beyond the per-endpoint concurrency limits
+    if !config.concurrency_limits.is_empty() {
+        load_shed::configure(&config.concurrency_limits)?;
+    }
+
+    // Bound the backlog of streams that can't keep up, and the streams each
+    // client may open
+    stream::configure(
+        config.backpressure(),
+        config.max_streams_per_client,
+        config.partial_model.clone(),
+        config.stream_audio_ctx,
+    );
+
+    // Share the CPU between transcriptions, streams first
+    if !config.remote_only {
+        scheduler::configure(config.workers());
+    }
+
+    // Record streaming sessions that ask for it
+    let recordings = config.recordings();
+    if let Some(retention) = &recordings {
+        recordings::configure(retention.clone(), config.recording_format)
+            .context("Failed to set up the recordings directory")?;
+        if encryption::enabled() {
+            tokio::task::spawn_blocking(recordings::seal_existing);
+        }
+        if retention.is_bounded() {
+            tokio::spawn(async {
+                let mut interval = tokio::time::interval(transcripts::RETENTION_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let _ = tokio::task::spawn_blocking(recordings::enforce_retention).await;
+                }
+            });
+        }
+    }
+
+    // Keep partial chunked uploads under the data directory, or in temp
+    let upload_dir = match &config.data_dir {
+        Some(data_dir) => data_dir.join("uploads"),
+        None => std::env::temp_dir().join("voicemark-uploads"),
+    };
+    uploads::configure(upload_dir).context("Failed to set up the upload directory")?;
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(uploads::CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let removed = uploads::remove_stale_uploads();
+            if removed > 0 {
+                info!(removed, "Discarded stale uploads");
+            }
+        }
+    });
+
+    // Prune retained audio per the retention policy
+    if config.data_dir.is_some() && audio_retention.is_some_and(|r| r.is_bounded()) {
+        tokio::spawn(async {
+            let mut interval = tokio::time::interval(transcripts::RETENTION_INTERVAL);
+            loop {
+                interval.tick().await;
+                let _ = tokio::task::spawn_blocking(transcripts::enforce_audio_retention).await;
+            }
+        });
+    }
+
+    // Cached results hold transcript text, so they're pruned like it
+    if config.cache_dir.is_some() && transcript_retention.is_bounded() {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(transcripts::RETENTION_INTERVAL);
+            loop {
+                interval.tick().await;
+                let prune = move || cache::enforce_retention(&transcript_retention);
+                let _ = tokio::task::spawn_blocking(prune).await;
+            }
+        });
+    }
+
+    // Prune stored transcripts, and forget finished jobs, per theirs
+    if config.data_dir.is_some() && transcript_retention.is_bounded() {
+        tokio::spawn(async {
+            let mut interval = tokio::time::interval(transcripts::RETENTION_INTERVAL);
+            loop {
+                interval.tick().await;
+                let _ = tokio::task::spawn_blocking(transcripts::enforce_retention).await;
+            }
+        });
+    }
+    let job_retention = config.job_retention();
+    jobs::configure(job_retention);
+    if job_retention.max_age.is_some() {
+        tokio::spawn(async {
+            let mut interval = tokio::time::interval(transcripts::RETENTION_INTERVAL);
+            loop {
+                interval.tick().await;
+                jobs::enforce_retention();
+            }
+        });
+    }
+
+    // Transcribe audio dropped into the watched directories
+    if let Some(watch) = config.watch() {
+        watch::start(watch).context("Invalid VOICEMARK_WATCH_DIRS")?;
+    }
+
+    // Warm the model up so the first request doesn't pay cold-start costs
+    if config.warmup && remote::backend() == Some(Backend::Local) {
+        if let Err(e) = transcribe::warmup() {
+            error!("Model warmup failed: {}", e);
+        }
+    }
+
+    // Optionally unload the model after N idle minutes; it reloads lazily
+    if config.idle_unload_mins > 0 {
+        let idle_timeout = Duration::from_secs(config.idle_unload_mins * 60);
+        info!(idle_unload_mins = config.idle_unload_mins, "Idle model unloading enabled");
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                transcribe::unload_if_idle(idle_timeout);
+            }
+        });
+    }
+
+    // Serve transcription requests over NATS alongside HTTP
+    #[cfg(feature = "nats")]
+    if let Some((url, subject, queue)) = config.nats() {
+        nats::start(url, subject, queue).context("Invalid VOICEMARK_NATS_URL")?;
+    }
+    #[cfg(not(feature = "nats"))]
+    if config.nats().is_some() {
+        warn!("VOICEMARK_NATS_URL is set, but this build has no NATS support");
+    }
 
-    // Initialize the Whisper model
-    transcribe::init_model(model_path.as_deref())?;
+    // Serve HTTPS, optionally requiring client certificates
+    let tls = match config.tls()? {
+        Some(tls) => Some(tls::acceptor(&tls).context("Failed to set up VOICEMARK_TLS_CERT")?),
+        None => None,
+    };
+    let scheme = if tls.is_some() { "https" } else { "http" };
 
-    // Get port from environment or use default
-    let port: u16 = env::var("VOICEMARK_PORT")
-        .ok()
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(DEFAULT_PORT);
+    // Share jobs and streams between sidecars
+    if let Some(role) = config.cluster_role {
+        let token = config
+            .cluster_token
+            .as_deref()
+            .context("VOICEMARK_CLUSTER_ROLE requires VOICEMARK_CLUSTER_TOKEN")?;
+        match role {
+            cluster::Role::Coordinator => cluster::start_coordinator(token)?,
+            cluster::Role::Worker => {
+                let coordinator = config
+                    .coordinator_url
+                    .as_deref()
+                    .context("A cluster worker requires VOICEMARK_COORDINATOR_URL")?;
+                let url = match (&config.advertise_url, config.bind.first()) {
+                    (Some(url), _) => url.clone(),
+                    (None, Some(addr)) if !addr.ip().is_unspecified() => {
+                        format!("{}://{}", scheme, addr)
+                    }
+                    _ => anyhow::bail!(
+                        "A cluster worker bound to all interfaces requires VOICEMARK_ADVERTISE_URL"
+                    ),
+                };
+                cluster::start_worker(coordinator, token, &url)?;
+            }
+        }
+    }
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
-    info!("Server listening on http://{}", addr);
+    // Listen on the sockets passed by systemd, or bind every configured address
+    let mut listeners = Vec::new();
+    let activated = systemd::listen_fds().context("Invalid systemd socket activation")?;
+    if activated.is_empty() {
+        for addr in &config.bind {
+            let listener = tokio::net::TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("Failed to bind {}", addr))?;
+            listeners.push(listener);
+        }
+    } else {
+        info!(sockets = activated.len(), "Using sockets from systemd; ignoring VOICEMARK_BIND");
+        for listener in activated {
+            listeners.push(tokio::net::TcpListener::from_std(listener)?);
+        }
+    }
 
-    // Build and run the server
+    // Build the router and serve it on every listener
     let app = build_router();
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let (stop_tx, stop_rx) = tokio::sync::watch::channel(());
+    let mut servers = tokio::task::JoinSet::new();
+    for listener in listeners {
+        info!("Server listening on {}://{}", scheme, listener.local_addr()?);
+        if let Some(acceptor) = &tls {
+            servers.spawn(tls::serve(listener, acceptor.clone(), app.clone(), stop_rx.clone()));
+            continue;
+        }
+        let mut stop = stop_rx.clone();
+        servers.spawn(
+            axum::serve(
+                listener,
+                app.clone().into_make_service_with_connect_info::<std::net::SocketAddr>(),
+            )
+            .with_graceful_shutdown(async move {
+                let _ = stop.changed().await;
+            })
+            .into_future(),
+        );
+    }
+    ready();
 
+    // Run until shutdown is requested or any listener fails
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            result = servers.join_next() => match result {
+                Some(result) => result??,
+                None => return Ok(()),
+            },
+            _ = &mut shutdown => break,
+        }
+    }
+
+    info!("Shutting down...");
+    let _ = stop_tx.send(());
+    while let Some(result) = servers.join_next().await {
+        result??;
+    }
     Ok(())
 }
 
@@ -255,5 +1717,468 @@ mod tests {
             .unwrap();
 
         assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(health["status"], "degraded");
+    }
+
+    #[tokio::test]
+    async fn test_load_missing_model_returns_422() {
+        admin::configure("test-admin-token");
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/model")
+                    .header("authorization", "Bearer test-admin-token")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"path": "/nonexistent/ggml-tiny.bin"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["code"], "model_load_failed");
+    }
+
+    #[tokio::test]
+    async fn test_warmup_without_model_returns_503() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/warmup")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_job_returns_404() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/jobs/does-not-exist")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_transcript_returns_404() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/transcripts/6f1c2f4e-0000-4000-8000-000000000000/audio")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_stream_requires_file_field() {
+        let app = build_router();
+        let body = "--BOUNDARY\r\n\
+                    Content-Disposition: form-data; name=\"other\"\r\n\r\n\
+                    value\r\n\
+                    --BOUNDARY--\r\n";
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/transcribe/stream")
+                    .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_rejects_unsupported_body() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/transcribe")
+                    .header("content-type", "application/json")
+                    .body(Body::from("{}"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_json_rejects_invalid_base64() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/transcribe/json")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{ "audio": "not base64!", "format": "webm" }"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_json_requires_audio() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/transcribe/json")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{ "format": "webm" }"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.headers()["content-type"],
+            error::PROBLEM_CONTENT_TYPE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_rejects_invalid_waveform_param() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/transcribe?waveform=lots")
+                    .header("content-type", "audio/wav")
+                    .body(Body::from("RIFF"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_rejects_invalid_profile() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/jobs?profile=..%2Fetc")
+                    .header("content-type", "audio/wav")
+                    .body(Body::from("RIFF"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_rejects_zero_threads() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/transcribe?threads=0")
+                    .header("content-type", "audio/wav")
+                    .body(Body::from("RIFF"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_transcribe_rejects_invalid_decoding_params() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/jobs?no_speech_threshold=2")
+                    .header("content-type", "audio/wav")
+                    .body(Body::from("RIFF"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_errors_before_first_line_keep_their_status() {
+        let app = build_router();
+
+        // Not whisper-ready, so it needs ffmpeg, which fails (or is missing)
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/transcribe?response_format=ndjson")
+                    .header("content-type", "audio/wav")
+                    .header("accept", "application/x-ndjson")
+                    .body(Body::from("RIFF"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_ne!(response.status(), StatusCode::OK);
+        assert_ne!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(response.headers()["content-type"], error::PROBLEM_CONTENT_TYPE);
+    }
+
+    #[test]
+    fn test_ndjson_lines_are_tagged() {
+        let segment = transcribe::Segment {
+            start_ms: 0,
+            end_ms: 1200,
+            text: "Hello.".to_string(),
+        };
+        let line = NdjsonLine::Segment(&segment).to_line();
+        assert_eq!(line.last(), Some(&b'\n'));
+        let json: serde_json::Value = serde_json::from_slice(&line).unwrap();
+        assert_eq!(json["type"], "segment");
+        assert_eq!(json["text"], "Hello.");
+
+        let line = NdjsonLine::Progress { progress: 40 }.to_line();
+        assert_eq!(line, b"{\"type\":\"progress\",\"progress\":40}\n");
+    }
+
+    #[tokio::test]
+    async fn test_chapters_without_llm_returns_503() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/jobs?chapters=true")
+                    .header("content-type", "audio/wav")
+                    .body(Body::from("RIFF"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_search_without_embeddings_returns_503() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/transcripts/semantic-search?q=release%20date")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_compare_without_two_models_returns_400() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/compare")
+                    .header("content-type", "audio/wav")
+                    .body(Body::from(vec![0u8; 64]))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_usage_without_api_keys_returns_401() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(Request::builder().uri("/usage").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_admin_api_requires_token() {
+        let app = build_router();
+
+        for (method, uri) in [
+            ("GET", "/admin/keys"),
+            ("POST", "/model"),
+            ("GET", "/users/u-7/export"),
+            ("DELETE", "/v1/users/u-7/data"),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .header("authorization", "Bearer guess")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_console_disabled_by_default() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(Request::builder().uri("/console").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_openapi_endpoint() {
+        let app = build_router();
+
+        let response = app
+            .oneshot(Request::builder().uri("/openapi.json").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_versioned_and_legacy_routes() {
+        let app = build_router();
+
+        for uri in ["/v1/health", "/health", "/v1/openapi.json"] {
+            let response = app
+                .clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        }
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/transcribe?threads=0")
+                    .header("content-type", "audio/wav")
+                    .body(Body::from("RIFF"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_chunked_upload_resumes_at_offset() {
+        let app = build_router();
+        let send = |method: &str, uri: String, body: &'static str| {
+            app.clone().oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header("content-type", "application/octet-stream")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+        };
+        let json = |response: axum::response::Response| async {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+
+        let response = send("POST", "/uploads?length=8".to_string(), "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let id = json(response).await["id"].as_str().unwrap().to_string();
+
+        let response = send("PATCH", format!("/uploads/{}?offset=0", id), "RIFF").await.unwrap();
+        assert_eq!(json(response).await["offset"], 4);
+
+        // A retried chunk at a stale offset is rejected; the client resumes
+        let response = send("PATCH", format!("/uploads/{}?offset=0", id), "RIFF").await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = send("GET", format!("/uploads/{}", id), "").await.unwrap();
+        assert_eq!(json(response).await["offset"], 4);
+
+        let response = send("POST", format!("/uploads/{}/complete", id), "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = send("DELETE", format!("/uploads/{}", id), "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = send("GET", format!("/uploads/{}", id), "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 }
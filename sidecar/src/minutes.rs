@@ -0,0 +1,140 @@
+//! Meeting minutes for VoiceMark sidecar.
+//!
+//! Jobs submitted with `meeting=true` get a single `minutes` document
+//! combining what would otherwise take several requests: a summary, the
+//! action items agreed on and the chapters of the meeting, each with its
+//! text, all produced by the LLM (see [`crate::llm`]) once the job is
+//! transcribed.
+//!
+//! The sidecar doesn't diarize yet, so minutes don't attribute text or
+//! action items to speakers.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::llm::{self, Chapter};
+use crate::transcribe::Segment;
+
+/// Structured minutes of a meeting recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MeetingMinutes {
+    /// A few sentences on what was discussed and decided.
+    pub summary: String,
+    /// Follow-up tasks agreed on, in the order they came up.
+    pub action_items: Vec<String>,
+    pub chapters: Vec<MinutesChapter>,
+    /// End of the last segment, in milliseconds.
+    pub duration_ms: i64,
+}
+
+/// A chapter of the minutes, with the text spoken in it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MinutesChapter {
+    pub title: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+}
+
+/// Minutes of the meeting transcribed as `text` and `segments`.
+///
+/// `chapters` are reused if the job already asked for them; otherwise the
+/// LLM is asked. Blocks; call it from a blocking thread. Fails if any LLM
+/// call fails.
+pub fn compose(
+    text: &str,
+    segments: &[Segment],
+    chapters: Option<&[Chapter]>,
+) -> Result<MeetingMinutes> {
+    let duration_ms = segments.last().map_or(0, |segment| segment.end_ms);
+    if text.trim().is_empty() {
+        return Ok(MeetingMinutes {
+            summary: String::new(),
+            action_items: Vec::new(),
+            chapters: Vec::new(),
+            duration_ms,
+        });
+    }
+
+    let summary = llm::summarize(text, true)?;
+    let chapters = match chapters {
+        Some(chapters) => chapters.to_vec(),
+        None => llm::chapters(segments)?,
+    };
+    Ok(MeetingMinutes {
+        summary: summary.summary,
+        action_items: summary.action_items,
+        chapters: chapter_texts(&chapters, segments),
+        duration_ms,
+    })
+}
+
+/// Attach to each chapter the text of the segments starting within it.
+fn chapter_texts(chapters: &[Chapter], segments: &[Segment]) -> Vec<MinutesChapter> {
+    chapters
+        .iter()
+        .enumerate()
+        .map(|(i, chapter)| {
+            let last = i + 1 == chapters.len();
+            let text = segments
+                .iter()
+                .filter(|segment| {
+                    segment.start_ms >= chapter.start_ms
+                        && (last || segment.start_ms < chapter.end_ms)
+                })
+                .map(|segment| segment.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            MinutesChapter {
+                title: chapter.title.clone(),
+                start_ms: chapter.start_ms,
+                end_ms: chapter.end_ms,
+                text,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start_ms: i64, end_ms: i64, text: &str) -> Segment {
+        Segment { start_ms, end_ms, text: text.to_string() }
+    }
+
+    fn chapter(title: &str, start_ms: i64, end_ms: i64) -> Chapter {
+        Chapter { title: title.to_string(), start_ms, end_ms }
+    }
+
+    #[test]
+    fn test_chapter_texts() {
+        let segments = vec![
+            segment(0, 1000, "Welcome everyone."),
+            segment(1000, 2500, "First, the budget."),
+            segment(2500, 4000, "It is tight."),
+            segment(4000, 5000, "Next, hiring."),
+        ];
+        let chapters = vec![
+            chapter("Intro", 0, 1000),
+            chapter("Budget", 1000, 4000),
+            chapter("Hiring", 4000, 5000),
+        ];
+        let minutes = chapter_texts(&chapters, &segments);
+        let texts: Vec<&str> = minutes.iter().map(|chapter| chapter.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            ["Welcome everyone.", "First, the budget. It is tight.", "Next, hiring."]
+        );
+        assert_eq!(minutes[1].title, "Budget");
+        assert_eq!((minutes[1].start_ms, minutes[1].end_ms), (1000, 4000));
+    }
+
+    #[test]
+    fn test_compose_empty_transcript_skips_llm() {
+        let minutes = compose("  ", &[], None).unwrap();
+        assert!(minutes.summary.is_empty() && minutes.chapters.is_empty());
+        assert_eq!(minutes.duration_ms, 0);
+    }
+}
@@ -0,0 +1,153 @@
+//! Model management API for VoiceMark sidecar.
+//!
+//! The sidecar starts even when its Whisper model is missing or fails to
+//! load. It then runs degraded: `/health` reports `"status": "degraded"`
+//! with the load error, and transcription requests return `503`
+//! (`model_not_loaded`) until a model is loaded through this API.
+//!
+//! - `GET /model` - Model path, state and last load error
+//! - `POST /model` - Load a model, or swap the loaded one
+//!
+//! Loading swaps the model every tenant is served by, from any path on the
+//! server, so it is part of the admin API (see [`crate::admin`]).
+//!
+//! Swapping keeps serving requests on the old model while the new one loads
+//! and warms up, then switches over; requests already running finish on the
+//! old model, which is freed after them.
+
+use axum::{Json, extract::rejection::JsonRejection};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use tracing::{info, instrument, warn};
+use utoipa::ToSchema;
+
+use crate::error::{ApiError, Problem};
+use crate::transcribe::{self, ModelState};
+
+/// Why the last model load failed; cleared once a model loads.
+static LOAD_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+/// Set while `POST /model` is loading a model.
+static LOADING: AtomicBool = AtomicBool::new(false);
+
+/// Clears [`LOADING`] when a load ends, even if its task panics.
+struct LoadingGuard;
+
+impl Drop for LoadingGuard {
+    fn drop(&mut self) {
+        LOADING.store(false, Ordering::Release);
+    }
+}
+
+/// Remember a failed model load, for `/health` and `GET /model`.
+pub fn record_error(error: &anyhow::Error) {
+    *LOAD_ERROR.lock().unwrap() = Some(format!("{:#}", error));
+}
+
+/// Why the last model load failed, if no model has loaded since.
+pub fn load_error() -> Option<String> {
+    LOAD_ERROR.lock().unwrap().clone()
+}
+
+/// Local model status (`GET /model`).
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ModelStatus {
+    /// Path of the loaded model, if any.
+    pub path: Option<String>,
+    pub state: ModelState,
+    /// Why the last load failed, while no model is loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ModelStatus {
+    fn current() -> Self {
+        Self {
+            path: transcribe::model_path(),
+            state: transcribe::model_state(),
+            error: load_error().filter(|_| !transcribe::is_model_loaded()),
+        }
+    }
+}
+
+/// Body of `POST /model`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LoadModelRequest {
+    /// Model file (or candle model directory) on the server.
+    pub path: String,
+}
+
+/// Model status endpoint (`GET /model`).
+#[utoipa::path(
+    get,
+    path = "/model",
+    tag = "system",
+    responses(
+        (status = 200, description = "Model status", body = ModelStatus),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn get_model() -> Json<ModelStatus> {
+    Json(ModelStatus::current())
+}
+
+/// Model load endpoint (`POST /model`).
+///
+/// Loads the model at `path`, e.g. after the server started degraded, or
+/// swaps it in for the loaded one without interrupting transcriptions.
+/// Returns once the model is loaded and serving. Requires the admin token.
+#[utoipa::path(
+    post,
+    path = "/model",
+    tag = "system",
+    security(("admin_token" = [])),
+    request_body = LoadModelRequest,
+    responses(
+        (status = 200, description = "Model loaded (or already the loaded one)", body = ModelStatus),
+        (status = 400, description = "Invalid request", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid admin token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Sent an API key instead of the admin token", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "Another model load is in progress", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Missing or invalid model file", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+#[instrument(skip_all)]
+pub async fn load_model(
+    payload: Result<Json<LoadModelRequest>, JsonRejection>,
+) -> Result<Json<ModelStatus>, ApiError> {
+    let Json(request) = payload?;
+    if request.path.trim().is_empty() {
+        return Err(ApiError::InvalidRequest("path must not be empty".to_string()));
+    }
+    if LOADING.swap(true, Ordering::AcqRel) {
+        return Err(ApiError::ModelConflict("Another model is being loaded".to_string()));
+    }
+    let guard = LoadingGuard;
+
+    let started = Instant::now();
+    let previous = transcribe::model_path();
+    let path = request.path.clone();
+    let swapped = tokio::task::spawn_blocking(move || {
+        let _guard = guard;
+        transcribe::swap_model(&path)
+    })
+    .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map_err(|e| {
+            warn!(path = %request.path, "Failed to load model: {:#}", e);
+            record_error(&e);
+            ApiError::ModelLoadFailed(format!("{:#}", e))
+        })?;
+    *LOAD_ERROR.lock().unwrap() = None;
+    if swapped {
+        info!(
+            path = %request.path,
+            ?previous,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "Model loaded"
+        );
+    }
+    Ok(Json(ModelStatus::current()))
+}
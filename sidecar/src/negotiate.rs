@@ -0,0 +1,230 @@
+//! Content negotiation for transcript responses.
+//!
+//! `POST /transcribe`, `POST /transcribe/json` and `GET /transcripts/:id`
+//! answer in the format the `Accept` header prefers: JSON (the default),
+//! plain text, or SRT subtitles. Requests without `Accept`, or accepting
+//! anything, get JSON; errors are always problem details.
+
+use axum::{
+    Json, async_trait,
+    extract::FromRequestParts,
+    http::{HeaderValue, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::error::ApiError;
+
+/// Media type of SRT subtitles.
+const SRT_CONTENT_TYPE: &str = "application/x-subrip";
+
+/// A representation of a transcript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The full JSON body.
+    Json,
+    /// Just the text.
+    Text,
+    /// Timed segments as SRT subtitles.
+    Srt,
+}
+
+impl Format {
+    /// In order of preference when the client accepts several equally.
+    const ALL: [Format; 3] = [Format::Json, Format::Text, Format::Srt];
+
+    /// Media types served for this format, the first is the canonical one.
+    fn media_types(self) -> &'static [&'static str] {
+        match self {
+            Format::Json => &["application/json"],
+            Format::Text => &["text/plain"],
+            Format::Srt => &[SRT_CONTENT_TYPE, "application/srt", "text/srt"],
+        }
+    }
+
+    /// The format `accept` prefers, or `None` if it accepts none of them.
+    fn from_accept(accept: &str) -> Option<Format> {
+        let ranges: Vec<(&str, f32)> = accept.split(',').filter_map(media_range).collect();
+        // Rank by quality, then by whether a type was named rather than
+        // matched by a wildcard (`text/plain, */*` prefers text)
+        let rank = |format: Format| -> Option<(f32, bool)> {
+            let exact = ranges
+                .iter()
+                .filter(|(range, _)| format.media_types().contains(range))
+                .map(|(_, q)| *q)
+                .reduce(f32::max);
+            let wildcard = || {
+                let family = format.media_types()[0].split('/').next().unwrap_or_default();
+                ranges
+                    .iter()
+                    .filter(|(range, _)| {
+                        *range == "*/*" || range.strip_suffix("/*") == Some(family)
+                    })
+                    .map(|(_, q)| *q)
+                    .reduce(f32::max)
+            };
+            match exact {
+                Some(q) => Some((q, true)),
+                None => wildcard().map(|q| (q, false)),
+            }
+        };
+        Format::ALL
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, format)| {
+                let (q, exact) = rank(format).filter(|(q, _)| *q > 0.0)?;
+                Some((format, q, exact, i))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(a.2.cmp(&b.2)).then(b.3.cmp(&a.3)))
+            .map(|(format, ..)| format)
+    }
+}
+
+/// A media range of an `Accept` header, lowercase, with its quality.
+fn media_range(range: &str) -> Option<(&str, f32)> {
+    let mut params = range.split(';');
+    let media_type = params.next()?.trim();
+    if media_type.is_empty() {
+        return None;
+    }
+    let q = params
+        .filter_map(|param| param.trim().strip_prefix("q="))
+        .find_map(|q| q.trim().parse::<f32>().ok())
+        .unwrap_or(1.0);
+    Some((media_type, q))
+}
+
+/// Extracts the response [`Format`] from the `Accept` header, rejecting
+/// requests that accept none with `406 Not Acceptable`.
+#[derive(Debug, Clone, Copy)]
+pub struct Accept(pub Format);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Accept
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(accept) = parts.headers.get(header::ACCEPT) else {
+            return Ok(Accept(Format::Json));
+        };
+        let accept = accept.to_str().unwrap_or_default().to_ascii_lowercase();
+        Format::from_accept(&accept).map(Accept).ok_or_else(|| {
+            ApiError::NotAcceptable(
+                "Transcripts are available as application/json, text/plain or \
+                 application/x-subrip"
+                    .to_string(),
+            )
+        })
+    }
+}
+
+/// A timed piece of a transcript, for subtitles.
+pub struct Cue<'a> {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: &'a str,
+}
+
+/// `cues` as SRT subtitles, numbered from 1; blank cues are skipped.
+pub fn srt<'a>(cues: impl IntoIterator<Item = Cue<'a>>) -> String {
+    let mut srt = String::new();
+    let cues = cues.into_iter().filter(|cue| !cue.text.trim().is_empty());
+    for (i, cue) in cues.enumerate() {
+        srt.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            srt_timestamp(cue.start_ms),
+            srt_timestamp(cue.end_ms.max(cue.start_ms)),
+            cue.text.trim()
+        ));
+    }
+    srt
+}
+
+/// `HH:MM:SS,mmm`
+fn srt_timestamp(ms: i64) -> String {
+    let ms = ms.max(0);
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+/// Respond with `body` in `format`: serialized as JSON, as `text`, or as
+/// SRT subtitles of `cues`.
+pub fn respond<'a, T: Serialize>(
+    format: Format,
+    body: T,
+    text: &str,
+    cues: impl IntoIterator<Item = Cue<'a>>,
+) -> Response {
+    let mut response = match format {
+        Format::Json => Json(body).into_response(),
+        Format::Text => {
+            let mut text = text.trim().to_string();
+            text.push('\n');
+            ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], text).into_response()
+        }
+        Format::Srt => ([(header::CONTENT_TYPE, SRT_CONTENT_TYPE)], srt(cues)).into_response(),
+    };
+    // Caches must not serve one format for another
+    response.headers_mut().insert(header::VARY, HeaderValue::from_static("accept"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_negotiation() {
+        assert_eq!(Format::from_accept("*/*"), Some(Format::Json));
+        assert_eq!(Format::from_accept("application/json"), Some(Format::Json));
+        assert_eq!(Format::from_accept("text/plain"), Some(Format::Text));
+        assert_eq!(Format::from_accept("text/*"), Some(Format::Text));
+        assert_eq!(Format::from_accept("application/x-subrip"), Some(Format::Srt));
+        assert_eq!(Format::from_accept("text/srt, */*;q=0.1"), Some(Format::Srt));
+        // Named types beat wildcards of the same quality
+        assert_eq!(Format::from_accept("text/plain, */*"), Some(Format::Text));
+        assert_eq!(
+            Format::from_accept("application/json;q=0.5, text/plain;q=0.9"),
+            Some(Format::Text)
+        );
+        assert_eq!(Format::from_accept("*/*, application/json;q=0"), Some(Format::Text));
+        assert_eq!(Format::from_accept("text/html"), None);
+        assert_eq!(Format::from_accept("text/plain;q=0"), None);
+    }
+
+    #[test]
+    fn test_srt() {
+        let cues = [
+            Cue { start_ms: 0, end_ms: 2500, text: " Hello there. " },
+            Cue { start_ms: 2500, end_ms: 2600, text: " " },
+            Cue { start_ms: 3_723_004, end_ms: 3_725_000, text: "Goodbye." },
+        ];
+        assert_eq!(
+            srt(cues),
+            "1\n00:00:00,000 --> 00:00:02,500\nHello there.\n\n\
+             2\n01:02:03,004 --> 01:02:05,000\nGoodbye.\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_respond_sets_content_type() {
+        let body = serde_json::json!({ "text": "Hi" });
+        let response = respond(Format::Text, &body, "Hi", []);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain; charset=utf-8");
+        assert_eq!(response.headers()[header::VARY], "accept");
+        let text = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&text[..], b"Hi\n");
+
+        let response = respond(Format::Json, &body, "Hi", []);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    }
+}
@@ -0,0 +1,204 @@
+//! OpenAPI document for VoiceMark sidecar.
+//!
+//! The document is generated from the handler annotations and schema
+//! derives, so typed clients can be regenerated instead of hand-maintained.
+//! It is served at `/v1/openapi.json` (and `/openapi.json`); with the test
+//! console enabled, a Swagger UI is served at `/docs` as well. Paths are
+//! relative to the `/v1` server; the unversioned aliases are listed as a
+//! second server.
+
+use axum::{Json, response::Html};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::error::Problem;
+
+/// Swagger UI page, loading the UI from a CDN.
+const SWAGGER_UI: &str = r##"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>VoiceMark sidecar API</title>
+<link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+<div id="swagger-ui"></div>
+<script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+<script>SwaggerUIBundle({ url: "/v1/openapi.json", dom_id: "#swagger-ui" });</script>
+</body>
+</html>
+"##;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "VoiceMark Sidecar API",
+        description = "Speech-to-text transcription with whisper.cpp. Errors are RFC 7807 \
+                       problem details (`application/problem+json`) with a stable `code`."
+    ),
+    servers(
+        (url = "/v1", description = "Versioned API"),
+        (url = "/", description = "Unversioned aliases, kept for existing clients"),
+    ),
+    paths(
+        crate::health,
+        crate::warmup,
+        crate::models::get_model,
+        crate::models::load_model,
+        crate::transcribe_audio,
+        crate::transcribe_json,
+        crate::transcribe_audio_sse,
+        crate::inspect_audio,
+        crate::compare::compare_models,
+        crate::jobs::submit_job,
+        crate::jobs::job_status,
+        crate::uploads::create_upload,
+        crate::uploads::upload_status,
+        crate::uploads::append_chunk,
+        crate::uploads::complete_upload,
+        crate::uploads::cancel_upload,
+        crate::transcripts::list_transcripts,
+        crate::transcripts::semantic_search,
+        crate::transcripts::get_transcript,
+        crate::transcripts::correct_transcript,
+        crate::transcripts::delete_transcript,
+        crate::transcripts::summarize_transcript,
+        crate::transcripts::get_transcript_audio,
+        crate::transcripts::export_transcript,
+        crate::users::export_user,
+        crate::users::delete_user_data,
+        crate::vocabulary::get_vocabulary,
+        crate::tenants::get_usage,
+        crate::admin::list_keys,
+        crate::admin::create_key,
+        crate::admin::revoke_key,
+        crate::admin::set_quota,
+        crate::cluster::register_worker,
+        crate::cluster::list_workers,
+        crate::stream::ws_handler,
+        crate::deepgram::listen,
+    ),
+    components(schemas(
+        Problem,
+        crate::transcribe::Segment,
+        crate::transcribe::Word,
+        crate::stream::ClientMessage,
+        crate::stream::ServerMessage,
+        voicemark_core::session::SessionSegment,
+    )),
+    modifiers(&SecuritySchemes),
+    // API keys are only required when VOICEMARK_TENANTS_DB is set
+    security((), ("api_key" = []), ("api_key_header" = []), ("api_key_query" = [])),
+    tags(
+        (name = "transcription", description = "Batch transcription of uploaded audio"),
+        (name = "jobs", description = "Background transcription jobs"),
+        (name = "uploads", description = "Resumable chunked uploads for background jobs"),
+        (name = "transcripts", description = "Persisted transcripts and corrections"),
+        (name = "users", description = "Per-user data export and erasure"),
+        (name = "profiles", description = "Vocabulary learned per profile"),
+        (name = "streaming", description = "Real-time transcription over WebSocket"),
+        (name = "usage", description = "Usage and quotas of the calling API key"),
+        (name = "admin", description = "API key management"),
+        (name = "cluster", description = "Coordinator/worker registration"),
+        (name = "system", description = "Health and warmup"),
+    )
+)]
+struct ApiDoc;
+
+/// Registers the API key, admin token and cluster token authentication
+/// schemes.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "api_key_header",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-api-key"))),
+        );
+        components.add_security_scheme(
+            "api_key_query",
+            SecurityScheme::ApiKey(ApiKey::Query(ApiKeyValue::new("api_key"))),
+        );
+        components.add_security_scheme(
+            "admin_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("VOICEMARK_ADMIN_TOKEN"))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "cluster_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("VOICEMARK_CLUSTER_TOKEN"))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// The OpenAPI document.
+pub fn document() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+}
+
+/// OpenAPI document endpoint (`GET /openapi.json`).
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(document())
+}
+
+/// Swagger UI endpoint (`GET /docs`).
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(SWAGGER_UI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_covers_routes() {
+        let doc = serde_json::to_value(document()).unwrap();
+        assert!(doc["openapi"].as_str().unwrap().starts_with("3.1"));
+
+        let paths = doc["paths"].as_object().unwrap();
+        for path in [
+            "/health",
+            "/transcribe",
+            "/transcribe/json",
+            "/transcribe/stream",
+            "/jobs/{id}",
+            "/transcripts/{id}",
+            "/users/{id}/export",
+            "/users/{id}/data",
+            "/admin/keys/{id}/quota",
+            "/stream",
+            "/listen",
+        ] {
+            assert!(paths.contains_key(path), "missing {}", path);
+        }
+        assert!(doc["paths"]["/transcripts/{id}"]["patch"].is_object());
+        assert!(doc["paths"]["/transcripts/{id}"]["delete"].is_object());
+        let transcribed = &doc["paths"]["/transcribe"]["post"]["responses"]["200"]["content"];
+        assert!(transcribed["application/x-subrip"].is_object());
+    }
+
+    #[test]
+    fn test_errors_reference_problem_schema() {
+        let doc = serde_json::to_value(document()).unwrap();
+        let not_found = &doc["paths"]["/jobs/{id}"]["get"]["responses"]["404"];
+        assert_eq!(
+            not_found["content"]["application/problem+json"]["schema"]["$ref"],
+            "#/components/schemas/Problem"
+        );
+        assert!(doc["components"]["schemas"]["TranscribeResponse"].is_object());
+    }
+}
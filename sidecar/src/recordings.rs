@@ -0,0 +1,344 @@
+//! Session recordings for VoiceMark sidecar.
+//!
+//! `/stream` sessions opened with `?record=true` have the audio they receive
+//! written to `<recordings_dir>/<timestamp>-<id>.wav` (16 kHz mono 16-bit
+//! PCM, as received) and, when the connection closes, their finals to
+//! `<timestamp>-<id>.json` next to it. Recordings can then be replayed, or
+//! re-transcribed later with a bigger model through `POST /transcribe`.
+//!
+//! By default ([`RecordingFormat::Opus`]), the WAV is encoded to Ogg Opus
+//! with ffmpeg once the session ends, about a tenth of the size, and
+//! removed. Uploads are decoded with ffmpeg anyway, so an Opus recording
+//! re-transcribes like the WAV would. Without an ffmpeg that can encode
+//! Opus, the WAV is kept.
+//!
+//! With at-rest encryption on, the finished recording and its transcript
+//! are encrypted (see [`crate::encryption`]); the WAV is only plaintext
+//! while the session is being recorded.
+//!
+//! Recordings are pruned by a background task according to their own max
+//! age and disk budget, like retained uploads (see [`crate::transcripts`]).
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+use crate::audio;
+use crate::clock::now_millis;
+use crate::encryption::{self, Kind};
+use crate::tenants;
+use crate::transcripts::{self, AudioRetention};
+
+/// Sample rate of recordings, as streamed.
+const SAMPLE_RATE: u32 = 16000;
+
+/// Size of the WAV header written before the samples.
+const WAV_HEADER_LEN: u32 = 44;
+
+/// Opus bitrate of encoded recordings; plenty for speech at 16 kHz.
+const OPUS_BITRATE: &str = "24k";
+
+/// How finished recordings are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// 16 kHz mono 16-bit PCM, as received.
+    Wav,
+    /// Ogg Opus, encoded from the WAV when the session ends.
+    #[default]
+    Opus,
+}
+
+impl FromStr for RecordingFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "wav" => Ok(Self::Wav),
+            "opus" => Ok(Self::Opus),
+            other => bail!("Unknown recording format {:?} (expected wav or opus)", other),
+        }
+    }
+}
+
+/// Where recordings go and how long they are kept; unset when recording is
+/// disabled.
+static RETENTION: OnceLock<AudioRetention> = OnceLock::new();
+
+/// Format finished recordings are stored in.
+static FORMAT: OnceLock<RecordingFormat> = OnceLock::new();
+
+/// Enable session recording, storing recordings as `format`. Call once at
+/// startup, after [`audio::configure_ffmpeg`].
+pub fn configure(retention: AudioRetention, format: RecordingFormat) -> Result<()> {
+    std::fs::create_dir_all(&retention.dir)
+        .with_context(|| format!("Failed to create {}", retention.dir.display()))?;
+    if format == RecordingFormat::Opus {
+        if let Err(e) = audio::ffmpeg() {
+            warn!("{}; session recordings will be kept as WAV", e);
+        }
+    }
+    info!(dir = ?retention.dir, ?format, "Session recording enabled");
+    if RETENTION.set(retention).is_err() {
+        bail!("Session recording already configured");
+    }
+    let _ = FORMAT.set(format);
+    Ok(())
+}
+
+/// Whether sessions can be recorded.
+pub fn enabled() -> bool {
+    RETENTION.get().is_some()
+}
+
+/// Encrypt the recordings stored before encryption was turned on. Does
+/// nothing unless both are enabled. Returns the number of files encrypted.
+pub fn seal_existing() -> usize {
+    let Some(retention) = RETENTION.get().filter(|_| encryption::enabled()) else {
+        return 0;
+    };
+    let sealed = transcripts::list_files(&retention.dir)
+        .iter()
+        .map(|path| transcripts::seal_file(path, Kind::Recording))
+        .sum();
+    if sealed > 0 {
+        info!(sealed, "Encrypted previously stored recordings");
+    }
+    sealed
+}
+
+/// Delete recordings that violate the retention policy. Returns the number
+/// of files removed.
+pub fn enforce_retention() -> usize {
+    match RETENTION.get() {
+        Some(retention) => transcripts::prune_audio(retention, SystemTime::now()),
+        None => 0,
+    }
+}
+
+/// Transcript written next to a recording.
+#[derive(Debug, Serialize)]
+struct SessionTranscript<'a> {
+    /// File name of the recording.
+    recording: &'a str,
+    /// Session start and end (Unix milliseconds).
+    started_at: u64,
+    ended_at: u64,
+    /// Length of the recording, in milliseconds.
+    duration_ms: u64,
+    /// The finals, joined with spaces.
+    text: String,
+    finals: &'a [String],
+}
+
+/// Records one streaming session.
+pub struct Recorder {
+    wav: BufWriter<File>,
+    path: PathBuf,
+    started_at: u64,
+    /// Samples written so far.
+    samples: u64,
+    finals: Vec<String>,
+    format: RecordingFormat,
+}
+
+impl Recorder {
+    /// Create the recording file for a new session. Fails if recording is
+    /// disabled.
+    pub fn start() -> Result<Self> {
+        let Some(retention) = RETENTION.get() else {
+            bail!("Session recording is not enabled");
+        };
+        let started_at = now_millis();
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let path = retention
+            .dir
+            .join(format!("{}-{}.wav", file_timestamp(started_at / 1000), &id[..8]));
+        let mut wav = BufWriter::new(
+            File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?,
+        );
+        wav.write_all(&wav_header(0))?;
+        info!(path = ?path, "Recording session");
+        let format = FORMAT.get().copied().unwrap_or_default();
+        Ok(Self { wav, path, started_at, samples: 0, finals: Vec::new(), format })
+    }
+
+    /// Append received samples.
+    pub fn write(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        for sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.wav.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples += samples.len() as u64;
+        Ok(())
+    }
+
+    /// Note a final sent to the client.
+    pub fn add_final(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.finals.push(text.to_string());
+        }
+    }
+
+    /// Complete the WAV header, encode it if recordings are stored as Opus,
+    /// encrypt it if encryption is on, and write the session transcript next
+    /// to it. Blocks.
+    pub fn finish(mut self) {
+        if let Err(e) = self.finish_wav() {
+            warn!(path = ?self.path, "Failed to finish session recording: {}", e);
+            return;
+        }
+        if self.format == RecordingFormat::Opus {
+            match encode_opus(&self.path) {
+                Ok(opus) => {
+                    if let Err(e) = std::fs::remove_file(&self.path) {
+                        warn!(path = ?self.path, "Failed to remove encoded recording: {}", e);
+                    }
+                    self.path = opus;
+                }
+                Err(e) => warn!(path = ?self.path, "Keeping the recording as WAV: {:#}", e),
+            }
+        }
+        if let Err(e) = encryption::seal_file(&self.path, Kind::Recording) {
+            warn!(path = ?self.path, "Failed to encrypt session recording: {}", e);
+        }
+        let name = self.path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        let transcript = SessionTranscript {
+            recording: name,
+            started_at: self.started_at,
+            ended_at: now_millis(),
+            duration_ms: self.samples * 1000 / u64::from(SAMPLE_RATE),
+            text: self.finals.join(" "),
+            finals: &self.finals,
+        };
+        let result = serde_json::to_vec_pretty(&transcript)
+            .map_err(std::io::Error::from)
+            .and_then(|json| {
+                encryption::write(self.path.with_extension("json"), Kind::Recording, json)
+            });
+        match result {
+            Ok(()) => info!(path = ?self.path, duration_ms = transcript.duration_ms, "Session recorded"),
+            Err(e) => warn!(path = ?self.path, "Failed to write session transcript: {}", e),
+        }
+    }
+
+    /// Rewrite the header with the final data length.
+    fn finish_wav(&mut self) -> std::io::Result<()> {
+        let data_len = u32::try_from(self.samples * 2).unwrap_or(u32::MAX - WAV_HEADER_LEN);
+        self.wav.flush()?;
+        let file = self.wav.get_mut();
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&wav_header(data_len))?;
+        file.sync_all()
+    }
+}
+
+/// Encode the WAV recording at `wav` to Ogg Opus next to it, returning the
+/// new file's path. A partial file is removed on failure.
+fn encode_opus(wav: &Path) -> Result<PathBuf> {
+    let opus = wav.with_extension("opus");
+    let output = Command::new(audio::ffmpeg_path()?)
+        .arg("-y")
+        .arg("-i")
+        .arg(wav)
+        .args(["-c:a", "libopus", "-b:a", OPUS_BITRATE, "-application", "voip"])
+        .arg(&opus)
+        .output()
+        .context("Failed to execute ffmpeg")?;
+    if !output.status.success() {
+        let _ = std::fs::remove_file(&opus);
+        bail!("ffmpeg failed to encode Opus: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(opus)
+}
+
+/// Header of a 16 kHz mono 16-bit PCM WAV file with `data_len` bytes of
+/// samples.
+fn wav_header(data_len: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(WAV_HEADER_LEN as usize);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(WAV_HEADER_LEN - 8 + data_len).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+    header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&1u16.to_le_bytes()); // mono
+    header.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    header.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes()); // byte rate
+    header.extend_from_slice(&2u16.to_le_bytes()); // block align
+    header.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+/// UTC timestamp for file names (`YYYY-MM-DDTHH-MM-SSZ`), sorting by time.
+fn file_timestamp(unix_secs: u64) -> String {
+    let secs = unix_secs % 86400;
+    format!(
+        "{}T{:02}-{:02}-{:02}Z",
+        tenants::utc_date(unix_secs),
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_parsing() {
+        assert_eq!("opus".parse::<RecordingFormat>().unwrap(), RecordingFormat::Opus);
+        assert_eq!(" wav".parse::<RecordingFormat>().unwrap(), RecordingFormat::Wav);
+        assert!("flac".parse::<RecordingFormat>().is_err());
+    }
+
+    #[test]
+    fn test_file_timestamp() {
+        assert_eq!(file_timestamp(0), "1970-01-01T00-00-00Z");
+        assert_eq!(file_timestamp(1_767_225_599), "2025-12-31T23-59-59Z");
+    }
+
+    #[test]
+    fn test_recording_is_valid_wav_with_transcript() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.wav");
+        let mut recorder = Recorder {
+            wav: BufWriter::new(File::create(&path).unwrap()),
+            path: path.clone(),
+            started_at: now_millis(),
+            samples: 0,
+            finals: Vec::new(),
+            format: RecordingFormat::Wav,
+        };
+        recorder.wav.write_all(&wav_header(0)).unwrap();
+        recorder.write(&[0.5; 8000]).unwrap();
+        recorder.write(&[-0.5; 8000]).unwrap();
+        recorder.add_final("Hello");
+        recorder.add_final("");
+        recorder.add_final("world");
+        recorder.finish();
+
+        let wav = std::fs::read(&path).unwrap();
+        assert_eq!(wav.len(), 44 + 32000);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 36 + 32000);
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 32000);
+        assert_eq!(i16::from_le_bytes([wav[44], wav[45]]), i16::MAX / 2);
+
+        let transcript: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.path().join("session.json")).unwrap())
+                .unwrap();
+        assert_eq!(transcript["recording"], "session.wav");
+        assert_eq!(transcript["duration_ms"], 1000);
+        assert_eq!(transcript["text"], "Hello world");
+        assert_eq!(transcript["finals"].as_array().unwrap().len(), 2);
+    }
+}
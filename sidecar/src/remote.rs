@@ -0,0 +1,509 @@
+//! Remote transcription fallback for VoiceMark sidecar.
+//!
+//! When no local model is available, or on devices too slow to run one
+//! (`VOICEMARK_REMOTE_ONLY=1`), audio is forwarded to a remote
+//! Whisper-compatible API: OpenAI's `/audio/transcriptions`, or any server
+//! speaking the same protocol. Clients use the same endpoints either way;
+//! responses report which backend served them in `backend`.
+//!
+//! Audio is decoded locally as usual and sent as 16kHz mono WAV.
+
+use anyhow::{Context, Result, bail};
+use reqwest::multipart::{Form, Part};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{debug, info};
+use utoipa::ToSchema;
+use voicemark_core::{constraint, formatting};
+
+use crate::quality;
+use crate::transcribe::{self, Segment, TranscribeOptions, TranscribeResult, Word};
+
+/// Default remote model name.
+pub const DEFAULT_MODEL: &str = "whisper-1";
+
+/// Upper bound on a single remote request, upload included.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Longest error body quoted from the remote API.
+const MAX_ERROR_BODY: usize = 300;
+
+/// Which backend served a transcription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// The local whisper model.
+    Local,
+    /// The remote API (`VOICEMARK_REMOTE_URL`).
+    Remote,
+}
+
+/// Remote API settings.
+#[derive(Debug, Clone)]
+pub struct RemoteConfig {
+    /// API base URL, e.g. `https://api.openai.com/v1`.
+    pub url: String,
+    /// Bearer token sent to the API.
+    pub api_key: Option<String>,
+    /// Model name sent with each request.
+    pub model: String,
+    /// Use the remote API even when a local model is available.
+    pub only: bool,
+}
+
+/// Client for a Whisper-compatible HTTP API.
+struct Remote {
+    client: reqwest::Client,
+    config: RemoteConfig,
+}
+
+static REMOTE: OnceLock<Remote> = OnceLock::new();
+
+/// Enable the remote fallback. Call once at startup.
+pub fn configure(config: RemoteConfig) -> Result<()> {
+    info!(url = %config.url, model = %config.model, only = config.only, "Remote transcription configured");
+    let remote = Remote::new(config)?;
+    if REMOTE.set(remote).is_err() {
+        bail!("Remote transcription already configured");
+    }
+    Ok(())
+}
+
+/// Whether a remote API is configured.
+pub fn enabled() -> bool {
+    REMOTE.get().is_some()
+}
+
+/// The backend that serves transcriptions right now, or `None` if neither
+/// a local model nor a remote API is available.
+pub fn backend() -> Option<Backend> {
+    let remote = REMOTE.get();
+    if transcribe::is_model_loaded() && !remote.is_some_and(|r| r.config.only) {
+        Some(Backend::Local)
+    } else if remote.is_some() {
+        Some(Backend::Remote)
+    } else {
+        None
+    }
+}
+
+/// Identifies the model serving transcriptions, for cache keys and
+/// persisted transcripts: the local model path, or the remote URL and model.
+pub fn model_id() -> Option<String> {
+    match (backend(), REMOTE.get()) {
+        (Some(Backend::Remote), Some(remote)) => {
+            Some(format!("{}#{}", remote.config.url, remote.config.model))
+        }
+        _ => transcribe::model_path(),
+    }
+}
+
+/// Transcribe with whichever backend is in use.
+///
+/// Blocks; call it from a blocking thread (e.g. `spawn_blocking`), never
+/// directly from async code.
+pub fn transcribe(
+    samples: &[f32],
+    options: TranscribeOptions,
+) -> Result<(TranscribeResult, Backend)> {
+    transcribe_with_callbacks(samples, options, |_| {}, |_| {})
+}
+
+/// Like [`transcribe`], reporting segments and progress.
+///
+/// The remote API returns everything at once, so its segments are reported
+/// together when the request completes.
+pub fn transcribe_with_callbacks<F, P>(
+    samples: &[f32],
+    options: TranscribeOptions,
+    mut on_segment: F,
+    mut on_progress: P,
+) -> Result<(TranscribeResult, Backend)>
+where
+    F: FnMut(&Segment),
+    P: FnMut(i32),
+{
+    match (backend(), REMOTE.get()) {
+        (Some(Backend::Remote), Some(remote)) => {
+            let handle = tokio::runtime::Handle::current();
+            let warnings = quality::analyze(samples);
+            let (samples, trimmed) = if options.trim_silence {
+                let (samples, trimmed) = transcribe::trim_silence(samples);
+                (samples, Some(trimmed))
+            } else {
+                (samples, None)
+            };
+            let (samples, non_speech) = match options.non_speech {
+                Some(_) => transcribe::exclude_non_speech(samples),
+                None => (Cow::Borrowed(samples), Vec::new()),
+            };
+            let options = constraint::bias(options);
+            let mut result = handle.block_on(remote.transcribe(&samples, &options))?;
+            constraint::apply(options.constraint, &mut result);
+            formatting::apply(options.text_format.steps(), &mut result);
+            if let Some(mode) = options.non_speech {
+                result.add_non_speech(non_speech, mode);
+            }
+            result.shift(options.offset_ms() + trimmed.map_or(0, |t| t.leading_ms));
+            result.trimmed = trimmed;
+            result.warnings = warnings;
+            for segment in &result.timed_segments {
+                on_segment(segment);
+            }
+            on_progress(100);
+            Ok((result, Backend::Remote))
+        }
+        _ => transcribe::transcribe_with_callbacks(samples, options, on_segment, on_progress)
+            .map(|result| (result, Backend::Local)),
+    }
+}
+
+/// Like [`transcribe_with_callbacks`], for audio decoded channel by
+/// channel: a single channel is transcribed as usual, several one after
+/// another and merged (see [`transcribe::merge_channels`]). Progress covers
+/// all channels.
+pub fn transcribe_channels_with_callbacks<F, P>(
+    channels: &[Vec<f32>],
+    options: TranscribeOptions,
+    mut on_segment: F,
+    mut on_progress: P,
+) -> Result<(TranscribeResult, Backend)>
+where
+    F: FnMut(&Segment),
+    P: FnMut(i32),
+{
+    if let [samples] = channels {
+        return transcribe_with_callbacks(samples, options, on_segment, on_progress);
+    }
+    let count = channels.len() as i32;
+    let mut results = Vec::with_capacity(channels.len());
+    let mut backend = Backend::Local;
+    for (i, samples) in channels.iter().enumerate() {
+        let done = i as i32 * 100;
+        let (result, used) = transcribe_with_callbacks(
+            samples,
+            options.clone(),
+            &mut on_segment,
+            |progress| on_progress((done + progress) / count),
+        )?;
+        results.push(result);
+        backend = used;
+    }
+    Ok((transcribe::merge_channels(results), backend))
+}
+
+impl Remote {
+    fn new(config: RemoteConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(Self { client, config })
+    }
+
+    /// Send 16kHz mono samples to the API.
+    async fn transcribe(
+        &self,
+        samples: &[f32],
+        options: &TranscribeOptions,
+    ) -> Result<TranscribeResult> {
+        // The translations endpoint always produces English and takes no language
+        let endpoint = if options.translate {
+            "audio/translations"
+        } else {
+            "audio/transcriptions"
+        };
+        let url = format!("{}/{}", self.config.url.trim_end_matches('/'), endpoint);
+
+        let file = Part::bytes(wav_bytes(samples))
+            .file_name("audio.wav")
+            .mime_str("audio/wav")?;
+        let mut form = Form::new()
+            .part("file", file)
+            .text("model", self.config.model.clone())
+            .text("response_format", "verbose_json");
+        if !options.translate {
+            // Match the local backend, which defaults to English; without a
+            // language the API detects it
+            let language = options.language.as_deref().unwrap_or("en");
+            if language != "auto" {
+                form = form.text("language", language.to_string());
+            }
+        }
+        if let Some(prompt) = &options.initial_prompt {
+            form = form.text("prompt", prompt.clone());
+        }
+        if options.word_timestamps {
+            form = form
+                .text("timestamp_granularities[]", "segment")
+                .text("timestamp_granularities[]", "word");
+        }
+
+        let mut request = self.client.post(&url).multipart(form);
+        if let Some(key) = &self.config.api_key {
+            request = request.bearer_auth(key);
+        }
+
+        debug!(url = %url, samples = samples.len(), "Sending audio to remote API");
+        let response = request.send().await.context("Remote transcription request failed")?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let body: String = body.trim().chars().take(MAX_ERROR_BODY).collect();
+            bail!("Remote API returned {}: {}", status, body);
+        }
+
+        let transcription: VerboseTranscription = response
+            .json()
+            .await
+            .context("Invalid response from the remote API")?;
+        Ok(transcription.into_result(samples.len() as i64 * 1000 / 16000))
+    }
+}
+
+/// `verbose_json` response of the transcriptions API.
+#[derive(Debug, Deserialize)]
+struct VerboseTranscription {
+    text: String,
+    #[serde(default)]
+    segments: Vec<VerboseSegment>,
+    /// Detected (or requested) language, as named by the server.
+    #[serde(default)]
+    language: Option<String>,
+    /// Word timings, if requested with `timestamp_granularities[]=word`.
+    #[serde(default)]
+    words: Vec<VerboseWord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VerboseWord {
+    /// Seconds from the start of the audio.
+    start: f64,
+    end: f64,
+    word: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct VerboseSegment {
+    /// Seconds from the start of the audio.
+    start: f64,
+    end: f64,
+    text: String,
+    #[serde(default)]
+    avg_logprob: Option<f64>,
+}
+
+impl VerboseTranscription {
+    /// Convert to a local result. Servers that omit segments get a single
+    /// segment spanning `duration_ms`.
+    fn into_result(self, duration_ms: i64) -> TranscribeResult {
+        let text = self.text.trim().to_string();
+
+        let logprobs: Vec<f64> = self.segments.iter().filter_map(|s| s.avg_logprob).collect();
+        let avg_token_prob = if logprobs.is_empty() {
+            0.0
+        } else {
+            (logprobs.iter().map(|lp| lp.exp()).sum::<f64>() / logprobs.len() as f64) as f32
+        };
+
+        let timed_segments: Vec<Segment> = if self.segments.is_empty() && !text.is_empty() {
+            vec![Segment {
+                start_ms: 0,
+                end_ms: duration_ms,
+                text: text.clone(),
+            }]
+        } else {
+            self.segments
+                .into_iter()
+                .map(|s| Segment {
+                    start_ms: (s.start * 1000.0).round() as i64,
+                    end_ms: (s.end * 1000.0).round() as i64,
+                    text: s.text.trim().to_string(),
+                })
+                .collect()
+        };
+
+        let words = self
+            .words
+            .into_iter()
+            .map(|w| Word {
+                start_ms: (w.start * 1000.0).round() as i64,
+                end_ms: (w.end * 1000.0).round() as i64,
+                text: w.word.trim().to_string(),
+                // The API doesn't report per-word probabilities
+                probability: avg_token_prob,
+            })
+            .collect();
+
+        TranscribeResult {
+            text,
+            segments: timed_segments.len(),
+            avg_token_prob,
+            timed_segments,
+            words,
+            tokens: Vec::new(),
+            language: self.language,
+            // The API reports the language but not its probability
+            language_probability: None,
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// Encode 16kHz mono samples as a 16-bit PCM WAV file.
+fn wav_bytes(samples: &[f32]) -> Vec<u8> {
+    const SAMPLE_RATE: u32 = 16000;
+    let data_len = (samples.len() * 2) as u32;
+
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes()); // byte rate
+    wav.extend_from_slice(&2u16.to_le_bytes()); // block align
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio;
+    use axum::{Json, Router, http::HeaderMap, http::StatusCode, routing::post};
+    use axum_extra::extract::Multipart;
+    use std::future::IntoFuture;
+
+    #[test]
+    fn test_wav_bytes_is_whisper_ready() {
+        let wav = wav_bytes(&[0.0, 0.5, -0.5, 1.0]);
+        let format = audio::parse_wav_header(&wav).unwrap();
+        assert!(format.is_whisper_ready());
+        assert_eq!(wav.len(), 44 + 8);
+    }
+
+    #[test]
+    fn test_verbose_transcription_into_result() {
+        let transcription: VerboseTranscription = serde_json::from_value(serde_json::json!({
+            "text": " Hello there. General Kenobi.",
+            "language": "english",
+            "segments": [
+                { "start": 0.0, "end": 1.5, "text": " Hello there.", "avg_logprob": -0.1 },
+                { "start": 1.5, "end": 3.25, "text": " General Kenobi.", "avg_logprob": -0.3 }
+            ],
+            "words": [
+                { "word": "Hello", "start": 0.0, "end": 0.42 },
+                { "word": "there", "start": 0.42, "end": 1.1 }
+            ]
+        }))
+        .unwrap();
+
+        let result = transcription.into_result(3250);
+        assert_eq!(result.text, "Hello there. General Kenobi.");
+        assert_eq!(result.segments, 2);
+        assert_eq!(result.timed_segments[1].start_ms, 1500);
+        assert_eq!(result.timed_segments[1].end_ms, 3250);
+        assert_eq!(result.timed_segments[1].text, "General Kenobi.");
+        assert!(result.avg_token_prob > 0.7 && result.avg_token_prob < 0.9);
+        assert_eq!(result.language.as_deref(), Some("english"));
+        assert_eq!(result.words.len(), 2);
+        assert_eq!((result.words[1].start_ms, result.words[1].end_ms), (420, 1100));
+        assert_eq!(result.words[1].text, "there");
+    }
+
+    #[test]
+    fn test_verbose_transcription_without_segments() {
+        let transcription: VerboseTranscription =
+            serde_json::from_value(serde_json::json!({ "text": "Hi." })).unwrap();
+        let result = transcription.into_result(2000);
+        assert_eq!(result.segments, 1);
+        assert_eq!(result.timed_segments[0].end_ms, 2000);
+    }
+
+    /// Fake transcriptions endpoint that echoes the form fields it received.
+    async fn fake_transcriptions(headers: HeaderMap, mut form: Multipart) -> Json<serde_json::Value> {
+        let mut fields = Vec::new();
+        while let Some(field) = form.next_field().await.unwrap() {
+            let name = field.name().unwrap().to_string();
+            let value = if name == "file" {
+                format!("{} bytes", field.bytes().await.unwrap().len())
+            } else {
+                field.text().await.unwrap()
+            };
+            fields.push(format!("{}={}", name, value));
+        }
+        let auth = headers["authorization"].to_str().unwrap();
+        Json(serde_json::json!({ "text": format!("{} {}", auth, fields.join(" ")) }))
+    }
+
+    /// Serve the fake API on an ephemeral port and return its address.
+    async fn spawn_fake_api() -> std::net::SocketAddr {
+        let app = Router::new()
+            .route("/v1/audio/transcriptions", post(fake_transcriptions))
+            .route(
+                "/expired/audio/transcriptions",
+                post(|| async { (StatusCode::UNAUTHORIZED, "Incorrect API key provided") }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, app).into_future());
+        addr
+    }
+
+    fn remote(url: String) -> Remote {
+        Remote::new(RemoteConfig {
+            url,
+            api_key: Some("sk-test".to_string()),
+            model: DEFAULT_MODEL.to_string(),
+            only: false,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_remote_request() {
+        let addr = spawn_fake_api().await;
+        let options = TranscribeOptions {
+            initial_prompt: Some("VoiceMark".to_string()),
+            ..Default::default()
+        };
+        let result = remote(format!("http://{}/v1/", addr))
+            .transcribe(&[0.0; 16000], &options)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result.text,
+            "Bearer sk-test file=32044 bytes model=whisper-1 response_format=verbose_json \
+             language=en prompt=VoiceMark"
+        );
+        assert_eq!(result.timed_segments[0].end_ms, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_remote_error_status() {
+        let addr = spawn_fake_api().await;
+        let err = remote(format!("http://{}/expired", addr))
+            .transcribe(&[0.0; 160], &TranscribeOptions::default())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Remote API returned 401 Unauthorized: Incorrect API key provided"
+        );
+    }
+}
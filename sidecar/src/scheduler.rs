@@ -0,0 +1,235 @@
+//! Priority scheduling of transcriptions for VoiceMark sidecar.
+//!
+//! Transcriptions share a fixed number of worker slots
+//! (`VOICEMARK_WORKERS`), so they don't fight over the CPU. Streaming chunks
+//! go ahead of batch work (jobs, uploaded files, model comparisons) waiting
+//! for a slot, and a stream is never left waiting behind batch work alone:
+//! if every slot is held by batch transcriptions, one stream chunk may run
+//! on top of them. So batch work isn't starved by busy streams, a waiting
+//! batch transcription gets the next free slot once [`STREAM_BURST`] stream
+//! chunks have gone ahead of it.
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Condvar, Mutex, OnceLock};
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Stream chunks that may go ahead of waiting batch work before it gets a
+/// slot.
+pub const STREAM_BURST: u32 = 4;
+
+/// What a transcription is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// A live `/stream` chunk.
+    Stream,
+    /// Anything else: jobs, uploaded files, comparisons.
+    Batch,
+}
+
+/// Slot bookkeeping.
+#[derive(Debug, Default)]
+struct State {
+    running: usize,
+    running_streams: usize,
+    waiting_streams: usize,
+    waiting_batch: usize,
+    /// Stream chunks started since a batch transcription started waiting
+    skipped: u32,
+}
+
+#[derive(Debug)]
+struct Scheduler {
+    workers: usize,
+    state: Mutex<State>,
+    freed: Condvar,
+}
+
+impl Scheduler {
+    fn new(workers: usize) -> Self {
+        Self {
+            workers: workers.max(1),
+            state: Mutex::new(State::default()),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Whether work of `priority` may start now.
+    fn can_start(&self, state: &State, priority: Priority) -> bool {
+        let slot_free = state.running < self.workers;
+        let batch_due = state.waiting_batch > 0 && state.skipped >= STREAM_BURST;
+        match priority {
+            // On top of batch work, if that's all there is
+            Priority::Stream if !slot_free => state.running_streams == 0,
+            Priority::Stream => !batch_due,
+            Priority::Batch => slot_free && (state.waiting_streams == 0 || batch_due),
+        }
+    }
+
+    /// Block until work of `priority` may start, and take a slot.
+    fn acquire(&self, priority: Priority) -> Permit<'_> {
+        let mut state = self.state.lock().unwrap();
+        match priority {
+            Priority::Stream => state.waiting_streams += 1,
+            Priority::Batch => state.waiting_batch += 1,
+        }
+        while !self.can_start(&state, priority) {
+            state = self.freed.wait(state).unwrap();
+        }
+        state.running += 1;
+        match priority {
+            Priority::Stream => {
+                state.waiting_streams -= 1;
+                state.running_streams += 1;
+                if state.waiting_batch > 0 {
+                    state.skipped += 1;
+                }
+            }
+            Priority::Batch => {
+                state.waiting_batch -= 1;
+                state.skipped = 0;
+            }
+        }
+        drop(state);
+        // Others may be able to start too (e.g. a stream on top of batch work)
+        self.freed.notify_all();
+        Permit { scheduler: self, priority }
+    }
+
+    fn release(&self, priority: Priority) {
+        let mut state = self.state.lock().unwrap();
+        state.running -= 1;
+        if priority == Priority::Stream {
+            state.running_streams -= 1;
+        }
+        drop(state);
+        self.freed.notify_all();
+    }
+}
+
+/// How busy the worker slots are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Load {
+    pub slots: usize,
+    pub running: usize,
+    /// Transcriptions waiting for a slot
+    pub waiting: usize,
+}
+
+/// A worker slot, given back when dropped.
+#[derive(Debug)]
+pub struct Permit<'a> {
+    scheduler: &'a Scheduler,
+    priority: Priority,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.scheduler.release(self.priority);
+    }
+}
+
+static SCHEDULER: OnceLock<Scheduler> = OnceLock::new();
+
+/// Share `workers` slots between transcriptions. Call once at startup;
+/// without it, transcriptions aren't scheduled.
+pub fn configure(workers: usize) {
+    if SCHEDULER.set(Scheduler::new(workers)).is_ok() {
+        info!(workers = workers.max(1), "Transcription scheduling enabled");
+    }
+}
+
+/// Wait for a worker slot for a transcription of `priority`. Blocks; call
+/// it from the blocking thread that will transcribe, and hold the permit
+/// until the transcription is done.
+pub fn acquire(priority: Priority) -> Option<Permit<'static>> {
+    SCHEDULER.get().map(|scheduler| scheduler.acquire(priority))
+}
+
+/// Current load, if transcriptions are scheduled.
+pub fn load() -> Option<Load> {
+    let scheduler = SCHEDULER.get()?;
+    let state = scheduler.state.lock().unwrap();
+    Some(Load {
+        slots: scheduler.workers,
+        running: state.running,
+        waiting: state.waiting_streams + state.waiting_batch,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Start a thread that takes a slot of `priority`, records `label` and
+    /// holds the slot until `release` is sent.
+    fn waiter(
+        scheduler: &Arc<Scheduler>,
+        priority: Priority,
+        label: &'static str,
+        order: &Arc<Mutex<Vec<&'static str>>>,
+    ) -> std::sync::mpsc::Sender<()> {
+        let (release, hold) = std::sync::mpsc::channel::<()>();
+        let scheduler = scheduler.clone();
+        let order = order.clone();
+        std::thread::spawn(move || {
+            let _permit = scheduler.acquire(priority);
+            order.lock().unwrap().push(label);
+            let _ = hold.recv();
+        });
+        std::thread::sleep(Duration::from_millis(30));
+        release
+    }
+
+    fn finish(release: std::sync::mpsc::Sender<()>) {
+        drop(release);
+        std::thread::sleep(Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_streams_go_first_and_never_wait_on_batch_alone() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let long_file = waiter(&scheduler, Priority::Batch, "long file", &order);
+        // The only slot is busy with batch work, but a stream still runs
+        let chunk = waiter(&scheduler, Priority::Stream, "chunk 1", &order);
+        assert_eq!(*order.lock().unwrap(), ["long file", "chunk 1"]);
+
+        // A second stream chunk waits, but goes ahead of waiting batch work
+        let job = waiter(&scheduler, Priority::Batch, "job", &order);
+        let next_chunk = waiter(&scheduler, Priority::Stream, "chunk 2", &order);
+        finish(chunk);
+        finish(long_file);
+        assert_eq!(*order.lock().unwrap(), ["long file", "chunk 1", "chunk 2"]);
+
+        finish(next_chunk);
+        assert_eq!(*order.lock().unwrap(), ["long file", "chunk 1", "chunk 2", "job"]);
+        finish(job);
+    }
+
+    #[test]
+    fn test_batch_work_is_not_starved() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let mut running = waiter(&scheduler, Priority::Stream, "chunk", &order);
+        let job = waiter(&scheduler, Priority::Batch, "job", &order);
+        // Streams keep coming; after a burst the job gets the slot
+        for _ in 0..STREAM_BURST {
+            let next = waiter(&scheduler, Priority::Stream, "chunk", &order);
+            finish(running);
+            running = next;
+        }
+        let next = waiter(&scheduler, Priority::Stream, "chunk", &order);
+        finish(running);
+        // ...and the next chunk runs on top of it
+        let order = order.lock().unwrap().clone();
+        assert_eq!(order[STREAM_BURST as usize + 1..], ["job", "chunk"]);
+
+        finish(job);
+        finish(next);
+    }
+}
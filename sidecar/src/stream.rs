@@ -2,31 +2,240 @@
 //!
 //! Provides real-time transcription via WebSocket connection.
 //! Audio is sent as base64-encoded PCM chunks, partial results
-//! are returned as transcription progresses.
+//! are returned as transcription progresses. Finals include word timings
+//! relative to the start of the stream for karaoke-style highlighting.
+//! With `?language=auto`, the language is detected from the first few
+//! seconds and locked in for the rest of the session. With
+//! `?translate=true`, partials and finals also carry an English translation
+//! for live captions. With `?record=true`, the session's audio and finals
+//! are saved to disk (see [`crate::recordings`]).
+//!
+//! When transcription can't keep up with live audio, the backlog is bounded
+//! by a [`Backpressure`] policy and the client is sent a `lagging` message.
+//! The queue between reading the socket and transcribing is bounded by the
+//! audio it holds: past `max_lag`, its oldest audio is dropped (`drop`), or,
+//! past twice that, the connection is closed (`coalesce`).
+//! With `?stats_interval_ms=`, the client is also sent periodic `stats`
+//! (backlog, latency, real-time factor) so it can adapt.
+//!
+//! With `?webhook=<url>`, finals and the session summary are also POSTed to
+//! a backend (see [`crate::webhooks`]).
+//!
+//! The committed transcript is checkpointed to the transcript store as the
+//! session goes (see [`crate::transcripts::Checkpoint`]), so a crash leaves
+//! it recoverable.
+//!
+//! With `VOICEMARK_PARTIAL_MODEL`, partials are transcribed with that
+//! smaller model for responsive captions, while finals keep using the
+//! configured one: each final replaces the partials of its chunk, so the
+//! committed transcript has the larger model's quality.
 
 use axum::{
-    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    Extension,
+    extract::{
+        ConnectInfo, Query,
+        rejection::QueryRejection,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
     response::IntoResponse,
 };
-use futures_util::{SinkExt, StreamExt};
+use futures_util::{SinkExt, StreamExt, stream::SplitSink};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use std::time::Instant;
-use tokio::sync::Mutex;
-use tracing::{debug, error, info, instrument, warn};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, mpsc};
+use tokio::time::Interval;
+use tracing::{error, info, instrument, warn};
+use utoipa::{IntoParams, ToSchema};
+use voicemark_core::constraint::Constraint;
+use voicemark_core::session::{
+    self, CHUNK_SAMPLES, SAMPLE_RATE, SessionConfig, SessionSegment, StreamingSession, Work,
+};
+use voicemark_core::speaker::{self, SpeakerTracker, SpeakerTurn, Voiceprint};
+use voicemark_core::transcribe::{
+    AudioContext, DecodingParams, Segment, TranscribeOptions, TranscribeResult, Word,
+};
+
+use crate::access_log::{self, Entry};
+use crate::clock::now_millis;
+use crate::error::{ApiError, Problem};
+use crate::events::{self, TranscriptEvent};
+use crate::jobs::JobMetadata;
+use crate::recordings::{self, Recorder};
+use crate::remote::{self, Backend};
+use crate::scheduler::{self, Priority};
+use crate::tenants::{self, Tenant};
+use crate::transcribe;
+use crate::transcripts::Checkpoint;
+use crate::vocabulary;
+use crate::webhooks::{self, Webhook};
+
+/// Default backlog of untranscribed audio before a stream is lagging.
+pub const DEFAULT_MAX_LAG_SECS: u64 = 10;
+
+/// Inputs other than audio a connection may queue for its transcription
+/// task.
+pub(crate) const INPUT_QUEUE_LEN: usize = 256;
+
+/// What to do with a stream's backlog once it is lagging.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Drop the oldest audio, keeping the most recent `max_lag`.
+    #[default]
+    Drop,
+    /// Keep all audio, but skip partials until caught up.
+    Coalesce,
+}
+
+impl FromStr for LagPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "drop" => Ok(Self::Drop),
+            "coalesce" => Ok(Self::Coalesce),
+            other => anyhow::bail!("Unknown lag policy {:?} (expected drop or coalesce)", other),
+        }
+    }
+}
+
+/// How much untranscribed audio a stream may build up, and what happens
+/// beyond that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backpressure {
+    /// Audio received but not yet transcribed, in milliseconds, beyond which
+    /// the stream is lagging.
+    pub max_lag_ms: u64,
+    pub policy: LagPolicy,
+}
+
+impl Backpressure {
+    /// `max_lag_ms` in samples.
+    pub(crate) fn max_lag_samples(self) -> usize {
+        (self.max_lag_ms * SAMPLE_RATE as u64 / 1000) as usize
+    }
+}
+
+impl Default for Backpressure {
+    fn default() -> Self {
+        Self {
+            max_lag_ms: DEFAULT_MAX_LAG_SECS * 1000,
+            policy: LagPolicy::default(),
+        }
+    }
+}
+
+static BACKPRESSURE: OnceLock<Backpressure> = OnceLock::new();
+
+/// Streams one client may have open at once, 0 = no limit.
+static MAX_STREAMS_PER_CLIENT: AtomicU32 = AtomicU32::new(0);
+
+/// Open streams by client (see [`ClientSlot`]).
+static CLIENT_STREAMS: OnceLock<Mutex<HashMap<String, u32>>> = OnceLock::new();
+
+/// Model path partials are transcribed with, from `VOICEMARK_PARTIAL_MODEL`.
+static PARTIAL_MODEL: OnceLock<String> = OnceLock::new();
+
+/// Whether the partial model failed to load, so it is only reported once.
+static PARTIAL_MODEL_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Encoder window for chunks of streams that don't choose one, from
+/// `VOICEMARK_STREAM_AUDIO_CTX`.
+static AUDIO_CTX: OnceLock<AudioContext> = OnceLock::new();
+
+/// Set the backpressure policy for all streams, how many streams one client
+/// may have open at once (0 = no limit), the model partials are transcribed
+/// with, if not the configured one, and the default encoder window for
+/// chunks. Call once at startup.
+pub fn configure(
+    backpressure: Backpressure,
+    max_streams_per_client: u32,
+    partial_model: Option<String>,
+    audio_ctx: AudioContext,
+) {
+    let _ = BACKPRESSURE.set(backpressure);
+    let _ = AUDIO_CTX.set(audio_ctx);
+    MAX_STREAMS_PER_CLIENT.store(max_streams_per_client, Ordering::Relaxed);
+    if let Some(path) = partial_model {
+        info!(partial_model = %path, "Streaming partials use a separate model");
+        let _ = PARTIAL_MODEL.set(path);
+    }
+}
+
+/// Backpressure applied to streams, as configured.
+pub(crate) fn backpressure() -> Backpressure {
+    BACKPRESSURE.get().copied().unwrap_or_default()
+}
+
+/// Open streaming connections.
+pub(crate) static ACTIVE_STREAMS: AtomicUsize = AtomicUsize::new(0);
 
-use crate::transcribe::{self, TranscribeOptions};
+/// Number of open streaming connections.
+pub fn active_streams() -> usize {
+    ACTIVE_STREAMS.load(Ordering::Relaxed)
+}
+
+fn client_streams() -> MutexGuard<'static, HashMap<String, u32>> {
+    CLIENT_STREAMS.get_or_init(Default::default).lock().unwrap()
+}
+
+/// A stream counted against its client's limit
+/// (`VOICEMARK_MAX_STREAMS_PER_CLIENT`), released on drop.
+///
+/// Clients are told apart by API key when keys are enabled, and by IP
+/// address otherwise. This is on top of each key's own
+/// `max_concurrent_streams` quota.
+#[derive(Debug)]
+pub struct ClientSlot {
+    client: String,
+}
+
+impl ClientSlot {
+    /// Claim a slot for `client`, failing with `429` at `max` open streams
+    /// (0 = no limit).
+    fn open(client: String, max: u32) -> Result<Self, ApiError> {
+        let mut streams = client_streams();
+        let active = streams.entry(client.clone()).or_default();
+        if max > 0 && *active >= max {
+            return Err(ApiError::TooManyStreams(format!(
+                "Limit of {} concurrent streams per client reached",
+                max
+            )));
+        }
+        *active += 1;
+        Ok(Self { client })
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        let mut streams = client_streams();
+        if let Some(active) = streams.get_mut(&self.client) {
+            *active = active.saturating_sub(1);
+            if *active == 0 {
+                streams.remove(&self.client);
+            }
+        }
+    }
+}
 
-/// Configuration for streaming transcription
-const SAMPLE_RATE: u32 = 16000;
-/// Chunk size before auto-commit (6 seconds of audio)
-const CHUNK_SECONDS: f32 = 6.0;
-const CHUNK_SAMPLES: usize = (SAMPLE_RATE as f32 * CHUNK_SECONDS) as usize;
-/// Minimum interval between transcriptions (throttle to avoid overload)
-const MIN_TRANSCRIBE_INTERVAL_MS: u128 = 500;
+/// Who a stream counts against: the API key, or else the peer address.
+/// `None` if neither is known.
+fn client_identity(tenant: Option<&Tenant>, peer: Option<SocketAddr>) -> Option<String> {
+    match (tenant, peer) {
+        (Some(tenant), _) => Some(format!("key:{}", tenant.key_id)),
+        (None, Some(peer)) => Some(format!("ip:{}", peer.ip())),
+        (None, None) => None,
+    }
+}
 
 /// Incoming WebSocket message types
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum ClientMessage {
     /// Audio chunk as base64-encoded 16-bit PCM
@@ -41,442 +250,1393 @@ pub enum ClientMessage {
     End,
     /// Reset/clear the audio buffer
     Reset,
+    /// Transcribe in this language from now on (`auto` detects it again)
+    Language { language: String },
+    /// Bias the following chunks toward these terms (names, jargon), most
+    /// important first, replacing any sent before; an empty list clears
+    /// them. Phrases in `suppress` are never transcribed.
+    Vocabulary {
+        terms: Vec<String>,
+        #[serde(default)]
+        suppress: Vec<String>,
+    },
 }
 
 fn default_sample_rate() -> u32 {
     16000
 }
 
+fn default_true() -> bool {
+    true
+}
+
+/// Query parameters for `GET /stream`.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StreamQuery {
+    /// Language code, or `auto` to detect it from the first few seconds and
+    /// keep it; defaults to English.
+    pub(crate) language: Option<String>,
+    /// With `language=auto`, lock in the first language detected (the
+    /// default); `false` detects the language of every chunk instead, for
+    /// mixed-language audio.
+    #[serde(default = "default_true")]
+    pub(crate) lock_language: bool,
+    /// Also translate partials and finals to English, by transcribing each
+    /// chunk a second time (needs a multilingual model).
+    #[serde(default)]
+    translate: bool,
+    /// Number the speakers of finals and flag speaker changes, for live
+    /// captions with a line per speaker (a heuristic, not diarization).
+    #[serde(default)]
+    speakers: bool,
+    /// Save the received audio as a WAV file, with the session's finals,
+    /// for replay or re-transcription (needs `VOICEMARK_DATA_DIR` or
+    /// `VOICEMARK_RECORDINGS_DIR`).
+    #[serde(default)]
+    record: bool,
+    /// Minimum milliseconds between partials (100-10000, default 500):
+    /// lower for snappier captions, higher to save battery.
+    partial_interval_ms: Option<u64>,
+    /// Minimum milliseconds of audio before a partial (100-6000, default
+    /// 500).
+    min_partial_ms: Option<u64>,
+    /// Send `stats` messages this often, in milliseconds (1000-60000);
+    /// none by default.
+    stats_interval_ms: Option<u64>,
+    /// Session ID naming the Redis channel captions are relayed to (up to
+    /// 128 letters, digits, `-`, `_`, `.` or `:`); random by default.
+    session_id: Option<String>,
+    /// URL every final and the session summary are also POSTed to (needs
+    /// `VOICEMARK_WEBHOOK_HOSTS` to allow its host).
+    webhook: Option<String>,
+    /// `fit` encodes only as much of whisper's 30-second window as each
+    /// chunk needs, for much lower latency; `full` always encodes all of it.
+    /// Defaults to `VOICEMARK_STREAM_AUDIO_CTX`.
+    audio_ctx: Option<AudioContext>,
+    /// Limit the text to `digits`, or to letters and digits spelled out one
+    /// at a time (`spelling`), for dictating into a constrained field.
+    constraint: Option<Constraint>,
+}
+
+impl Default for StreamQuery {
+    fn default() -> Self {
+        Self {
+            language: None,
+            lock_language: true,
+            translate: false,
+            speakers: false,
+            record: false,
+            partial_interval_ms: None,
+            min_partial_ms: None,
+            stats_interval_ms: None,
+            session_id: None,
+            webhook: None,
+            audio_ctx: None,
+            constraint: None,
+        }
+    }
+}
+
+impl StreamQuery {
+    /// Default options, transcribing in `language`.
+    pub(crate) fn with_language(language: Option<String>, lock_language: bool) -> Self {
+        Self { language, lock_language, ..Self::default() }
+    }
+
+    /// Partial result timing for the session, clamped to sane bounds.
+    fn session_config(&self) -> SessionConfig {
+        let default = SessionConfig::default();
+        SessionConfig::new(
+            self.partial_interval_ms.unwrap_or(default.partial_interval_ms),
+            self.min_partial_ms.unwrap_or(default.min_partial_ms),
+        )
+    }
+
+    /// How often to send `stats`, if at all.
+    fn stats_interval(&self) -> Option<Duration> {
+        self.stats_interval_ms
+            .map(|ms| Duration::from_millis(ms.clamp(1000, 60_000)))
+    }
+}
+
 /// Outgoing WebSocket message types
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum ServerMessage {
     /// Partial transcription result (may change)
     Partial {
         text: String,
+        /// English translation of `text`, with `translate=true`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        translation: Option<String>,
         #[serde(rename = "ts")]
         timestamp: u64,
+        /// Position of the chunk in the stream, in milliseconds (for
+        /// adapters such as [`crate::deepgram`]; not sent).
+        #[serde(skip)]
+        start_ms: i64,
+        #[serde(skip)]
+        end_ms: i64,
     },
     /// Final transcription result (committed)
     Final {
         text: String,
+        /// English translation of the chunk, with `translate=true`. Unlike
+        /// `text`, it isn't deduplicated against the previous final.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        translation: Option<String>,
+        #[serde(rename = "ts")]
+        timestamp: u64,
+        /// Language of the chunk, for `language=auto` or multilingual models.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+        /// Probability of the detected language, with `language=auto`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        language_probability: Option<f32>,
+        /// Timing of each word in `text`, in milliseconds from the start of
+        /// the stream (since the last `end` or `reset`). Omitted when the
+        /// backend can't time words (the candle backend).
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        words: Vec<Word>,
+        /// Speaker of the chunk, from 0 in order of appearance, with
+        /// `speakers=true`. Omitted when the chunk has too little speech to
+        /// tell.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        speaker: Option<usize>,
+        /// Whether `speaker` differs from the previous final's, with
+        /// `speakers=true`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        speaker_changed: Option<bool>,
+        /// Position of the chunk in the stream, in milliseconds (not sent).
+        #[serde(skip)]
+        start_ms: i64,
+        #[serde(skip)]
+        end_ms: i64,
+    },
+    /// Whole transcript of the stream, sent after the last `final` in reply
+    /// to `end`
+    #[serde(rename = "session_complete")]
+    SessionComplete {
+        /// Every final since the stream started, joined with spaces.
+        text: String,
+        /// The finals, positioned in the stream.
+        segments: Vec<SessionSegment>,
+        /// Length of the streamed audio, in milliseconds.
+        duration_ms: i64,
+        #[serde(rename = "ts")]
+        timestamp: u64,
+    },
+    /// Transcription is falling behind the live audio
+    Lagging {
+        /// Audio received but not yet transcribed, in milliseconds.
+        behind_ms: u64,
+        /// Oldest audio dropped to catch up (`drop` policy), in milliseconds.
+        dropped_ms: u64,
+        #[serde(rename = "ts")]
+        timestamp: u64,
+    },
+    /// How the session is keeping up, every `stats_interval_ms`
+    Stats {
+        /// Audio received but not yet committed as a final, in milliseconds.
+        buffered_ms: u64,
+        /// Finals committed so far.
+        chunks_committed: u64,
+        /// Average time to transcribe a partial or final, in milliseconds.
+        avg_latency_ms: u64,
+        /// Real-time factor: transcription time per second of audio
+        /// transcribed (above 1 can't keep up).
+        rtf: f64,
+        #[serde(rename = "ts")]
+        timestamp: u64,
+    },
+    /// Language detected with `language=auto`, used for the rest of the
+    /// session
+    #[serde(rename = "language_detected")]
+    LanguageDetected {
+        language: String,
+        /// Probability of the detected language (0.0-1.0).
+        #[serde(skip_serializing_if = "Option::is_none")]
+        probability: Option<f32>,
         #[serde(rename = "ts")]
         timestamp: u64,
     },
-    /// Error message
-    Error { message: String },
+    /// Error message with a stable machine-readable `code`
+    Error { code: String, message: String },
     /// Acknowledgment of connection/reset
-    Ready { message: String },
+    Ready {
+        message: String,
+        /// ID of the session, on connection when captions are relayed to
+        /// Redis or delivered to a webhook.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+    },
 }
 
-/// State for a streaming transcription session
-struct StreamingSession {
-    /// Current audio chunk being accumulated (f32, 16kHz mono)
-    current_chunk: Vec<f32>,
-    /// Last time we ran transcription (for throttling)
-    last_transcribe_time: Option<Instant>,
-    /// Whether a transcription is currently in progress
-    transcription_pending: bool,
+impl From<ApiError> for ServerMessage {
+    fn from(err: ApiError) -> Self {
+        ServerMessage::Error {
+            code: err.code().to_string(),
+            message: err.to_string(),
+        }
+    }
 }
 
-impl StreamingSession {
-    fn new() -> Self {
-        Self {
-            current_chunk: Vec::with_capacity(CHUNK_SAMPLES),
-            last_transcribe_time: None,
-            transcription_pending: false,
+/// Convert base64-encoded 16-bit PCM to f32 samples
+fn decode_audio(base64_data: &str) -> Result<Vec<f32>, anyhow::Error> {
+    use base64::Engine;
+    let bytes = base64::engine::general_purpose::STANDARD.decode(base64_data)?;
+
+    // Convert 16-bit little-endian PCM to f32
+    if bytes.len() % 2 != 0 {
+        anyhow::bail!("Invalid audio data length: must be multiple of 2");
+    }
+    Ok(session::pcm16_to_f32(&bytes))
+}
+
+/// Audio a language must be detected from before it is locked in.
+const LANGUAGE_LOCK_SAMPLES: usize = 3 * SAMPLE_RATE as usize;
+
+/// A partial's detected language is locked in only with this probability;
+/// a final's is locked in regardless.
+const LANGUAGE_LOCK_PROBABILITY: f32 = 0.5;
+
+/// How a session's chunks are transcribed, from the query and `language`
+/// messages.
+#[derive(Debug, Clone, PartialEq)]
+struct ChunkOptions {
+    /// Language to transcribe in (`None` is English), or `auto`
+    language: Option<String>,
+    /// Lock in the first language detected from enough audio
+    detecting: bool,
+    translate: bool,
+    /// Voices heard so far, with `speakers=true`
+    speakers: Option<SpeakerTracker>,
+    audio_ctx: AudioContext,
+    constraint: Constraint,
+    /// From the client's last `vocabulary` message
+    vocabulary: SessionVocabulary,
+}
+
+impl ChunkOptions {
+    fn new(query: &StreamQuery) -> Self {
+        let mut options = Self {
+            language: None,
+            detecting: false,
+            translate: query.translate,
+            speakers: query.speakers.then(SpeakerTracker::default),
+            audio_ctx: query
+                .audio_ctx
+                .unwrap_or_else(|| AUDIO_CTX.get().copied().unwrap_or_default()),
+            constraint: query.constraint.unwrap_or_default(),
+            vocabulary: SessionVocabulary::default(),
+        };
+        if let Some(language) = &query.language {
+            options.set_language(language, query.lock_language);
         }
+        options
     }
 
-    fn reset(&mut self) {
-        self.current_chunk.clear();
-        self.last_transcribe_time = None;
-        self.transcription_pending = false;
+    /// Transcribe in `language` from now on; with `auto` and `lock`, until
+    /// one is detected.
+    fn set_language(&mut self, language: &str, lock: bool) {
+        let language = language.trim();
+        self.detecting = lock && language == "auto";
+        self.language = (!language.is_empty()).then(|| language.to_string());
     }
 
-    /// Add audio samples to the current chunk
-    /// Returns true if chunk is ready for auto-commit
-    fn add_samples(&mut self, samples: &[f32]) -> bool {
-        self.current_chunk.extend_from_slice(samples);
-        self.current_chunk.len() >= CHUNK_SAMPLES
+    /// Lock in the language detected in a chunk of `samples`, if it is the
+    /// first detected confidently enough, and return the
+    /// `language_detected` message.
+    fn detect(
+        &mut self,
+        result: &TranscribeResult,
+        samples: usize,
+        is_final: bool,
+    ) -> Option<ServerMessage> {
+        if !self.detecting || samples < LANGUAGE_LOCK_SAMPLES {
+            return None;
+        }
+        let language = result.language.clone()?;
+        let probability = result.language_probability;
+        if !is_final && probability.is_some_and(|p| p < LANGUAGE_LOCK_PROBABILITY) {
+            return None;
+        }
+        info!(language = %language, ?probability, "Stream language detected");
+        self.detecting = false;
+        self.language = Some(language.clone());
+        Some(ServerMessage::LanguageDetected { language, probability, timestamp: now_millis() })
     }
 
-    /// Check if enough time has passed to transcribe again
-    fn should_transcribe(&self) -> bool {
-        if self.transcription_pending {
-            return false;
+    /// Attribute a final chunk to a speaker, if speakers are tracked and it
+    /// has enough speech.
+    fn speaker(&mut self, chunk: &Transcribed) -> Option<SpeakerTurn> {
+        Some(self.speakers.as_mut()?.observe(chunk.voiceprint.as_ref()?))
+    }
+}
+
+/// Terms a session biases transcription toward, and phrases it suppresses.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct SessionVocabulary {
+    /// Initial prompt built from the terms
+    prompt: Option<String>,
+    /// Comma-separated phrases, like [`DecodingParams::suppress`]
+    suppress: Option<String>,
+}
+
+impl SessionVocabulary {
+    fn new(terms: &[String], suppress: &[String]) -> Result<Self, ApiError> {
+        if let Some(phrase) = suppress.iter().find(|phrase| phrase.contains(',')) {
+            return Err(ApiError::InvalidMessage(format!(
+                "Suppressed phrases can't contain commas: {:?}",
+                phrase
+            )));
         }
-        match self.last_transcribe_time {
-            None => true,
-            Some(last) => last.elapsed().as_millis() >= MIN_TRANSCRIBE_INTERVAL_MS,
+        let suppress: Vec<&str> =
+            suppress.iter().map(|phrase| phrase.trim()).filter(|p| !p.is_empty()).collect();
+        Ok(Self {
+            prompt: vocabulary::session_prompt(terms),
+            suppress: (!suppress.is_empty()).then(|| suppress.join(",")),
+        })
+    }
+
+    /// `options` with the session's prompt and suppressed phrases, the
+    /// latter in addition to the server's (`VOICEMARK_SUPPRESS`).
+    fn apply(&self, options: TranscribeOptions) -> TranscribeOptions {
+        let Some(suppress) = &self.suppress else {
+            return TranscribeOptions { initial_prompt: self.prompt.clone(), ..options };
+        };
+        let defaults = options.decoding.clone().or(&transcribe::decoding_defaults());
+        let suppress = match defaults.suppress.as_deref().filter(|s| !s.trim().is_empty()) {
+            Some(server) => format!("{},{}", server, suppress),
+            None => suppress.clone(),
+        };
+        TranscribeOptions {
+            initial_prompt: self.prompt.clone(),
+            decoding: DecodingParams { suppress: Some(suppress), ..options.decoding.clone() },
+            ..options
         }
     }
+}
+
+/// A transcribed chunk of streaming audio.
+struct Transcribed {
+    result: TranscribeResult,
+    /// English translation, with `translate=true`.
+    translation: Option<String>,
+    /// Voiceprint of a final chunk, with `speakers=true`.
+    voiceprint: Option<Voiceprint>,
+}
 
-    /// Get a clone of the current chunk for transcription
-    fn get_chunk_clone(&self) -> Vec<f32> {
-        self.current_chunk.clone()
+/// Transcribe a chunk of streaming audio on the blocking thread pool, ahead
+/// of batch work (see [`crate::scheduler`]), and translate it if the client
+/// asked for it. Final chunks get a voiceprint when speakers are tracked.
+///
+/// Returns `Ok(None)` when there is nothing to emit (silence or a likely
+/// hallucination).
+async fn transcribe_chunk(
+    audio_data: Vec<f32>,
+    options: &ChunkOptions,
+    is_final: bool,
+) -> anyhow::Result<Option<Transcribed>> {
+    let language = options.language.clone();
+    let translate = options.translate;
+    let speakers = is_final && options.speakers.is_some();
+    let audio_ctx = options.audio_ctx;
+    let constraint = options.constraint;
+    let vocabulary = options.vocabulary.clone();
+    let transcribed = tokio::task::spawn_blocking(move || -> anyhow::Result<Option<Transcribed>> {
+        let _permit = scheduler::acquire(Priority::Stream);
+        let result = session::transcribe_chunk_with(
+            &audio_data,
+            language.as_deref(),
+            |samples, options| {
+                let options =
+                    vocabulary.apply(TranscribeOptions { audio_ctx, constraint, ..options });
+                transcribe_stream(samples, options, is_final)
+            },
+        )?;
+        let Some(result) = result else {
+            return Ok(None);
+        };
+        let translation = if translate {
+            let language = language.as_deref();
+            Some(translate_chunk(&audio_data, &result, language, audio_ctx, is_final)?)
+        } else {
+            None
+        };
+        let voiceprint = if speakers { speaker::voiceprint(&audio_data) } else { None };
+        Ok(Some(Transcribed { result, translation, voiceprint }))
+    })
+        .await
+        .map_err(|e| anyhow::anyhow!("Spawn blocking failed: {}", e))?
+        .map_err(|e| anyhow::anyhow!("Transcription failed: {}", e));
+    #[cfg(feature = "sentry")]
+    if let (Err(e), Some(_)) = (&transcribed, remote::backend()) {
+        crate::crash_reports::capture_error("stream", e);
     }
+    transcribed
+}
 
-    /// Clear the current chunk after commit
-    fn clear_chunk(&mut self) {
-        self.current_chunk.clear();
+/// Transcribe streaming audio, with the partial model unless `is_final`
+/// (see [`configure`]). Falls back to the configured backend when there is
+/// no partial model, it fails to load, or transcription is remote.
+fn transcribe_stream(
+    samples: &[f32],
+    options: TranscribeOptions,
+    is_final: bool,
+) -> anyhow::Result<TranscribeResult> {
+    let partial_model = PARTIAL_MODEL.get().filter(|_| !is_final);
+    if let Some(path) = partial_model.filter(|_| remote::backend() == Some(Backend::Local)) {
+        // Reloads the model if it was unloaded while idle
+        match transcribe::load_model(path) {
+            Ok(_) => return transcribe::transcribe_with_model(path, samples, options),
+            Err(e) => {
+                if !PARTIAL_MODEL_FAILED.swap(true, Ordering::Relaxed) {
+                    warn!("{:#}; partials use the configured model", e);
+                }
+            }
+        }
     }
+    remote::transcribe(samples, options).map(|(result, _)| result)
+}
 
-    /// Check if chunk has enough audio for meaningful transcription (at least 0.5s)
-    fn has_meaningful_audio(&self) -> bool {
-        self.current_chunk.len() >= (SAMPLE_RATE / 2) as usize
+/// English translation of a chunk transcribed as `result`, by a second pass
+/// over its audio in the same language (with the partial model, unless
+/// `is_final`). English chunks are their own translation.
+fn translate_chunk(
+    audio_data: &[f32],
+    result: &TranscribeResult,
+    language: Option<&str>,
+    audio_ctx: AudioContext,
+    is_final: bool,
+) -> anyhow::Result<String> {
+    let language = result.language.as_deref().or(language).unwrap_or("en");
+    if language == "en" {
+        return Ok(result.text.clone());
     }
+    let options = TranscribeOptions {
+        language: Some(language.to_string()),
+        translate: true,
+        audio_ctx,
+        ..Default::default()
+    };
+    Ok(transcribe_stream(audio_data, options, is_final)?.text)
 }
 
-/// Convert base64-encoded 16-bit PCM to f32 samples
-fn decode_audio(base64_data: &str) -> Result<Vec<f32>, anyhow::Error> {
-    use base64::Engine;
-    let bytes = base64::engine::general_purpose::STANDARD.decode(base64_data)?;
+/// Running numbers of a stream, shared by its tasks for `stats` messages.
+#[derive(Debug, Default)]
+pub(crate) struct SessionStats {
+    /// Samples queued but not yet taken by the transcription task
+    pub(crate) queued: AtomicUsize,
+    /// Samples in the chunk being filled
+    buffered: AtomicUsize,
+    chunks_committed: AtomicU64,
+    transcriptions: AtomicU64,
+    /// Time spent transcribing, in microseconds
+    busy_us: AtomicU64,
+    /// Audio transcribed, in samples
+    transcribed: AtomicU64,
+}
 
-    // Convert 16-bit little-endian PCM to f32
-    if bytes.len() % 2 != 0 {
-        anyhow::bail!("Invalid audio data length: must be multiple of 2");
+impl SessionStats {
+    /// Run the transcription of `samples` of audio, timing it.
+    async fn time<T>(&self, samples: usize, transcription: impl Future<Output = T>) -> T {
+        let started = Instant::now();
+        let result = transcription.await;
+        self.busy_us.fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+        self.transcriptions.fetch_add(1, Ordering::Relaxed);
+        self.transcribed.fetch_add(samples as u64, Ordering::Relaxed);
+        result
     }
 
-    let samples: Vec<f32> = bytes
-        .chunks_exact(2)
-        .map(|chunk| {
-            let sample = i16::from_le_bytes([chunk[0], chunk[1]]);
-            sample as f32 / 32768.0
-        })
-        .collect();
+    fn message(&self) -> ServerMessage {
+        let buffered =
+            self.queued.load(Ordering::Relaxed) + self.buffered.load(Ordering::Relaxed);
+        let busy_us = self.busy_us.load(Ordering::Relaxed);
+        let transcriptions = self.transcriptions.load(Ordering::Relaxed);
+        let transcribed_us = self.transcribed.load(Ordering::Relaxed) * 1_000_000
+            / SAMPLE_RATE as u64;
+        ServerMessage::Stats {
+            buffered_ms: buffered as u64 * 1000 / SAMPLE_RATE as u64,
+            chunks_committed: self.chunks_committed.load(Ordering::Relaxed),
+            avg_latency_ms: busy_us.checked_div(transcriptions).unwrap_or(0) / 1000,
+            rtf: if transcribed_us > 0 { busy_us as f64 / transcribed_us as f64 } else { 0.0 },
+            timestamp: now_millis(),
+        }
+    }
+}
+
+/// Add streamed samples to the session and transcribe them if due.
+///
+/// Full chunks are charged to `tenant` (and counted in the access log
+/// `entry`) and returned as finals; otherwise a partial is returned if the
+/// throttle allows and `partials` is set. Transcriptions are timed in
+/// `stats`. A `language_detected` message comes first when the language is
+/// locked in.
+async fn process_audio(
+    session: &mut StreamingSession,
+    samples: &[f32],
+    tenant: Option<&Tenant>,
+    entry: Option<&Entry>,
+    options: &mut ChunkOptions,
+    stats: &SessionStats,
+    partials: bool,
+) -> anyhow::Result<Vec<ServerMessage>> {
+    match session.push(samples) {
+        Some(Work::Final(audio_data)) => {
+            info!("Auto-committing chunk ({} samples)", audio_data.len());
+            let len = audio_data.len();
+            tenants::charge(tenant, len);
+            access_log::add_audio(entry, len);
+            stats.chunks_committed.fetch_add(1, Ordering::Relaxed);
+            let transcribe_result =
+                stats.time(len, transcribe_chunk(audio_data, options, true)).await;
+            session.finish_transcription();
+
+            let Some(chunk) = transcribe_result? else {
+                return Ok(Vec::new());
+            };
+            let detected = options.detect(&chunk.result, len, true);
+            let speaker = options.speaker(&chunk);
+            Ok(detected.into_iter().chain([final_message(session, chunk, speaker)]).collect())
+        }
+        Some(Work::Partial(_)) if !partials => {
+            session.finish_transcription();
+            Ok(Vec::new())
+        }
+        Some(Work::Partial(audio_data)) => {
+            let len = audio_data.len();
+            let transcribe_result =
+                stats.time(len, transcribe_chunk(audio_data, options, false)).await;
+            session.finish_transcription();
 
-    Ok(samples)
+            let Some(chunk) = transcribe_result? else {
+                return Ok(Vec::new());
+            };
+            let detected = options.detect(&chunk.result, len, false);
+            let start_ms = session.duration_ms();
+            let partial = ServerMessage::Partial {
+                text: chunk.result.text,
+                translation: chunk.translation,
+                timestamp: now_millis(),
+                start_ms,
+                end_ms: start_ms + (len as u64 * 1000 / SAMPLE_RATE as u64) as i64,
+            };
+            Ok(detected.into_iter().chain([partial]).collect())
+        }
+        // Throttled, no response
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Commit a final result to the session and build its message, with its
+/// `speaker` if known.
+fn final_message(
+    session: &mut StreamingSession,
+    chunk: Transcribed,
+    speaker: Option<SpeakerTurn>,
+) -> ServerMessage {
+    let Transcribed { result, translation, .. } = chunk;
+    let language = result.language.clone();
+    let language_probability = result.language_probability;
+    let (text, words) = session.commit_final_result(result);
+    ServerMessage::Final {
+        text,
+        translation,
+        timestamp: now_millis(),
+        language,
+        language_probability,
+        words,
+        speaker: speaker.map(|turn| turn.speaker),
+        speaker_changed: speaker.map(|turn| turn.changed),
+        start_ms: session.last_chunk_offset_ms(),
+        end_ms: session.duration_ms(),
+    }
 }
 
 /// WebSocket upgrade handler
-pub async fn ws_handler(ws: WebSocketUpgrade) -> impl IntoResponse {
-    ws.on_upgrade(handle_socket)
+///
+/// With API keys enabled, the connection holds one of the key's stream
+/// slots until it closes, and committed audio is charged to the key. With
+/// `VOICEMARK_MAX_STREAMS_PER_CLIENT`, it also holds one of its client's
+/// slots (see [`ClientSlot`]). The access log line is written when the
+/// connection closes.
+#[utoipa::path(
+    get,
+    path = "/stream",
+    operation_id = "stream",
+    tag = "streaming",
+    params(StreamQuery),
+    description = "WebSocket upgrade. The client sends 16 kHz mono 16-bit little-endian PCM as \
+                   binary frames, or `ClientMessage` JSON text frames; the server replies with \
+                   `ServerMessage` JSON text frames.",
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 402, description = "Daily audio quota used up", body = Problem, content_type = "application/problem+json"),
+        (status = 429, description = "Too many concurrent streams for this key or client", body = Problem, content_type = "application/problem+json"),
+        (status = 400, description = "Invalid session ID or webhook URL", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "`record=true` or `webhook` but recording or webhooks are not enabled", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    tenant: Option<Extension<Tenant>>,
+    entry: Option<Extension<Entry>>,
+    peer: Option<Extension<ConnectInfo<SocketAddr>>>,
+    query: Result<Query<StreamQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Query(query) = query?;
+    if query.record && !recordings::enabled() {
+        return Err(ApiError::RecordingUnavailable);
+    }
+    if let Some(session_id) = &query.session_id {
+        validate_session_id(session_id)?;
+    }
+    let webhook = query.webhook.as_deref().map(webhooks::validate).transpose()?;
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    let entry = entry.map(|Extension(entry)| entry);
+    let peer = peer.map(|Extension(ConnectInfo(addr))| addr);
+    let slots = open_slots(tenant.as_ref(), peer)?;
+    let recorder = if query.record {
+        Some(Recorder::start().map_err(|e| ApiError::Internal(format!("{:#}", e)))?)
+    } else {
+        None
+    };
+    Ok(ws.on_upgrade(move |socket| {
+        handle_socket(socket, tenant, entry, slots, query, recorder, webhook)
+    }))
+}
+
+/// Stream slots a connection holds until it closes: one of its key's and
+/// one of its client's.
+pub(crate) type StreamSlots = (Option<tenants::StreamSlot>, Option<ClientSlot>);
+
+/// Claim the stream slots of a new connection from `tenant` at `peer`.
+pub(crate) fn open_slots(
+    tenant: Option<&Tenant>,
+    peer: Option<SocketAddr>,
+) -> Result<StreamSlots, ApiError> {
+    let max_per_client = MAX_STREAMS_PER_CLIENT.load(Ordering::Relaxed);
+    let client_slot = match client_identity(tenant, peer) {
+        Some(client) if max_per_client > 0 => Some(ClientSlot::open(client, max_per_client)?),
+        _ => None,
+    };
+    let slot = tenant.map(tenants::open_stream).transpose()?;
+    Ok((slot, client_slot))
 }
 
 /// Handle a WebSocket connection
-#[instrument(skip(socket))]
-async fn handle_socket(socket: WebSocket) {
-    info!("New streaming connection established");
+///
+/// The connection is served by two tasks joined by a queue: this one reads
+/// the socket, records the audio and sends replies, while [`run_session`]
+/// transcribes. Audio keeps being read while a chunk is transcribed, instead
+/// of stalling the socket and falling further behind.
+#[instrument(skip_all)]
+async fn handle_socket(
+    socket: WebSocket,
+    tenant: Option<Tenant>,
+    entry: Option<Entry>,
+    _slots: StreamSlots,
+    query: StreamQuery,
+    mut recorder: Option<Recorder>,
+    webhook: Option<reqwest::Url>,
+) {
+    let session_id =
+        query.session_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    info!(session_id = %session_id, "New streaming connection established");
+    let webhook = webhook.map(|url| Webhook::start(url, session_id.clone()));
+    ACTIVE_STREAMS.fetch_add(1, Ordering::Relaxed);
 
     let (mut sender, mut receiver) = socket.split();
-    let session = Arc::new(Mutex::new(StreamingSession::new()));
-
-    // Send ready message
+    let stats = Arc::new(SessionStats::default());
+    let (inputs, queue) = input_queue(stats.clone(), backpressure());
+    let (replies_tx, mut replies) = mpsc::unbounded_channel();
+    let mut stats_ticker = query.stats_interval().map(|period| {
+        tokio::time::interval_at(tokio::time::Instant::now() + period, period)
+    });
     let ready_msg = ServerMessage::Ready {
         message: "Streaming transcription ready".to_string(),
+        session_id: (relaying() || webhook.is_some()).then(|| session_id.clone()),
     };
-    if let Ok(json) = serde_json::to_string(&ready_msg) {
-        let _ = sender.send(Message::Text(json.into())).await;
-    }
+    let transcriber = tokio::spawn(run_session(
+        queue,
+        replies_tx,
+        tenant,
+        entry,
+        query,
+        stats.clone(),
+        webhook,
+    ));
 
-    // Process incoming messages
-    while let Some(msg) = receiver.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                match serde_json::from_str::<ClientMessage>(&text) {
-                    Ok(client_msg) => {
-                        let response = handle_client_message(client_msg, &session).await;
-                        if let Some(server_msg) = response {
-                            if let Ok(json) = serde_json::to_string(&server_msg) {
-                                if sender.send(Message::Text(json.into())).await.is_err() {
-                                    break;
-                                }
+    // Send ready message
+    let _ = send_message(&mut sender, &ready_msg).await;
+
+    loop {
+        tokio::select! {
+            msg = receiver.next() => {
+                let input = match msg {
+                    Some(Ok(Message::Text(text))) => parse_input(&text),
+                    // Raw binary audio (16-bit PCM)
+                    Some(Ok(Message::Binary(data))) if data.len() % 2 == 0 => {
+                        Ok(Input::Audio(session::pcm16_to_f32(&data)))
+                    }
+                    Some(Ok(Message::Close(_))) => {
+                        info!("Client closed connection");
+                        break;
+                    }
+                    Some(Err(e)) => {
+                        error!("WebSocket error: {}", e);
+                        break;
+                    }
+                    Some(Ok(_)) => continue,
+                    None => break,
+                };
+                match input {
+                    Ok(input) => {
+                        if let Input::Audio(samples) = &input {
+                            record(&mut recorder, samples);
+                        }
+                        match inputs.send(input).await {
+                            Ok(()) => {}
+                            Err(QueueError::Closed) => break,
+                            Err(QueueError::Full(e)) => {
+                                let _ = send_message(&mut sender, &e.into()).await;
+                                break;
                             }
                         }
                     }
                     Err(e) => {
-                        warn!("Failed to parse client message: {}", e);
-                        let error_msg = ServerMessage::Error {
-                            message: format!("Invalid message format: {}", e),
-                        };
-                        if let Ok(json) = serde_json::to_string(&error_msg) {
-                            let _ = sender.send(Message::Text(json.into())).await;
+                        if send_message(&mut sender, &e.into()).await.is_err() {
+                            break;
                         }
                     }
                 }
             }
-            Ok(Message::Binary(data)) => {
-                // Handle raw binary audio (16-bit PCM)
-                if data.len() % 2 == 0 {
-                    let samples: Vec<f32> = data
-                        .chunks_exact(2)
-                        .map(|chunk| {
-                            let sample = i16::from_le_bytes([chunk[0], chunk[1]]);
-                            sample as f32 / 32768.0
-                        })
-                        .collect();
-
-                    let mut session_guard = session.lock().await;
-                    let chunk_ready = session_guard.add_samples(&samples);
-                    debug!("Added {} samples, chunk_ready={}", samples.len(), chunk_ready);
-
-                    // If chunk is full, auto-commit it as final
-                    if chunk_ready {
-                        session_guard.transcription_pending = true;
-                        let audio_data = session_guard.get_chunk_clone();
-                        session_guard.clear_chunk(); // Clear for next chunk
-                        drop(session_guard);
-
-                        info!("Auto-committing chunk ({} samples)", audio_data.len());
-
-                        // Run transcription in a blocking thread
-                        let transcribe_result = tokio::task::spawn_blocking(move || {
-                            let options = TranscribeOptions {
-                                language: Some("en".to_string()),
-                                translate: false,
-                            };
-                            transcribe::transcribe(&audio_data, options)
-                        })
-                        .await;
-
-                        // Update session state
-                        let mut session_guard = session.lock().await;
-                        session_guard.transcription_pending = false;
-                        session_guard.last_transcribe_time = Some(Instant::now());
-                        drop(session_guard);
-
-                        // Send as FINAL (committed chunk)
-                        match transcribe_result {
-                            Ok(Ok(result)) => {
-                                let final_msg = ServerMessage::Final {
-                                    text: result.text,
-                                    timestamp: now_millis(),
-                                };
-                                if let Ok(json) = serde_json::to_string(&final_msg) {
-                                    if sender.send(Message::Text(json.into())).await.is_err() {
-                                        break;
-                                    }
-                                }
-                            }
-                            Ok(Err(e)) => {
-                                error!("Transcription error: {}", e);
-                            }
-                            Err(e) => {
-                                error!("Spawn blocking error: {}", e);
-                            }
-                        }
+            Some(reply) = replies.recv() => {
+                if let ServerMessage::Final { text, .. } = &reply {
+                    if let Some(rec) = recorder.as_mut() {
+                        rec.add_final(text);
                     }
-                    // Otherwise, send partial if throttle allows
-                    else if session_guard.should_transcribe() && session_guard.has_meaningful_audio() {
-                        session_guard.transcription_pending = true;
-                        let audio_data = session_guard.get_chunk_clone();
-                        drop(session_guard);
-
-                        // Run transcription in a blocking thread
-                        let transcribe_result = tokio::task::spawn_blocking(move || {
-                            let options = TranscribeOptions {
-                                language: Some("en".to_string()),
-                                translate: false,
-                            };
-                            transcribe::transcribe(&audio_data, options)
-                        })
-                        .await;
-
-                        // Update session state and send result
-                        let mut session_guard = session.lock().await;
-                        session_guard.transcription_pending = false;
-                        session_guard.last_transcribe_time = Some(Instant::now());
-                        drop(session_guard);
-
-                        match transcribe_result {
-                            Ok(Ok(result)) => {
-                                let partial_msg = ServerMessage::Partial {
-                                    text: result.text,
-                                    timestamp: now_millis(),
-                                };
-                                if let Ok(json) = serde_json::to_string(&partial_msg) {
-                                    if sender.send(Message::Text(json.into())).await.is_err() {
-                                        break;
-                                    }
-                                }
-                            }
-                            Ok(Err(e)) => {
-                                error!("Transcription error: {}", e);
-                            }
-                            Err(e) => {
-                                error!("Spawn blocking error: {}", e);
-                            }
-                        }
+                }
+                relay(&session_id, &reply);
+                if send_message(&mut sender, &reply).await.is_err() {
+                    break;
+                }
+            }
+            _ = next_tick(&mut stats_ticker) => {
+                if send_message(&mut sender, &stats.message()).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    // Stop transcribing: nobody is left to send results to
+    drop(inputs);
+    drop(replies);
+    if let Err(e) = transcriber.await {
+        error!("Streaming session task failed: {}", e);
+    }
+    if let Some(recorder) = recorder {
+        let _ = tokio::task::spawn_blocking(move || recorder.finish()).await;
+    }
+    ACTIVE_STREAMS.fetch_sub(1, Ordering::Relaxed);
+    info!("Streaming connection closed");
+}
+
+/// Wait for the next tick of `ticker`, or forever without one.
+async fn next_tick(ticker: &mut Option<Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Send a message to the client.
+async fn send_message(
+    sender: &mut SplitSink<WebSocket, Message>,
+    msg: &ServerMessage,
+) -> Result<(), axum::Error> {
+    match serde_json::to_string(msg) {
+        Ok(json) => sender.send(Message::Text(json)).await,
+        Err(_) => Ok(()),
+    }
+}
+
+/// Check a client-chosen session ID, which names a Redis channel.
+fn validate_session_id(session_id: &str) -> Result<(), ApiError> {
+    let valid = (1..=128).contains(&session_id.len())
+        && session_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+    if valid {
+        Ok(())
+    } else {
+        Err(ApiError::InvalidRequest(
+            "session_id must be 1-128 letters, digits, '-', '_', '.' or ':'".to_string(),
+        ))
+    }
+}
+
+/// Whether captions are relayed to Redis.
+fn relaying() -> bool {
+    #[cfg(feature = "redis")]
+    if crate::redis::enabled() {
+        return true;
+    }
+    false
+}
+
+/// Publish a transcript message to the session's Redis channel, if
+/// captions are relayed.
+fn relay(session_id: &str, msg: &ServerMessage) {
+    let transcript = matches!(
+        msg,
+        ServerMessage::Partial { .. }
+            | ServerMessage::Final { .. }
+            | ServerMessage::SessionComplete { .. }
+    );
+    if !transcript || !relaying() {
+        return;
+    }
+    #[cfg(feature = "redis")]
+    if let Ok(json) = serde_json::to_string(msg) {
+        crate::redis::publish(session_id, json);
+    }
+    #[cfg(not(feature = "redis"))]
+    let _ = session_id;
+}
+
+/// Append received samples to the session recording, if any.
+fn record(recorder: &mut Option<Recorder>, samples: &[f32]) {
+    if let Some(rec) = recorder.as_mut() {
+        if let Err(e) = rec.write(samples) {
+            error!("Failed to record session audio, recording stopped: {}", e);
+            if let Some(rec) = recorder.take() {
+                rec.finish();
+            }
+        }
+    }
+}
+
+/// Work queued for a session's transcription task, in the order received.
+#[derive(Debug)]
+pub(crate) enum Input {
+    /// 16kHz mono samples
+    Audio(Vec<f32>),
+    /// Samples of audio dropped from the queue under [`LagPolicy::Drop`],
+    /// in place of that audio
+    Dropped(usize),
+    End,
+    Reset,
+    Language(String),
+    Vocabulary(SessionVocabulary),
+}
+
+/// Why an input couldn't be queued for the transcription task.
+#[derive(Debug)]
+pub(crate) enum QueueError {
+    /// The session task has stopped.
+    Closed,
+    /// The queue is full and the lag policy keeps all audio; the connection
+    /// should be closed with this error.
+    Full(ApiError),
+}
+
+/// Create the queue between a connection and its transcription task, with
+/// the audio in it counted in `stats.queued` and bounded by `backpressure`
+/// (see [`Inputs::send`]).
+pub(crate) fn input_queue(
+    stats: Arc<SessionStats>,
+    backpressure: Backpressure,
+) -> (Inputs, Queue) {
+    let shared = Arc::new(InputQueue {
+        state: Mutex::default(),
+        queued: Notify::new(),
+        taken: Notify::new(),
+        stats,
+        backpressure,
+    });
+    (Inputs(shared.clone()), Queue(shared))
+}
+
+struct InputQueue {
+    state: Mutex<QueueState>,
+    /// Signalled when an input is queued or the connection is gone
+    queued: Notify,
+    /// Signalled when an input is taken or the transcription task is gone
+    taken: Notify,
+    stats: Arc<SessionStats>,
+    backpressure: Backpressure,
+}
+
+#[derive(Default)]
+struct QueueState {
+    /// Adjacent audio is kept as one input
+    inputs: VecDeque<Input>,
+    /// The other half of the queue is gone
+    closed: bool,
+}
+
+impl InputQueue {
+    fn state(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap()
+    }
+
+    fn close(&self) {
+        self.state().closed = true;
+        self.queued.notify_one();
+        self.taken.notify_one();
+    }
+}
+
+impl QueueState {
+    /// Drop the oldest `samples` of queued audio, leaving [`Input::Dropped`]
+    /// in its place so the stream's position is kept.
+    fn drop_oldest(&mut self, mut samples: usize) {
+        let mut kept = VecDeque::with_capacity(self.inputs.len() + 1);
+        for input in self.inputs.drain(..) {
+            match input {
+                Input::Audio(mut audio) if samples > 0 => {
+                    let dropped = samples.min(audio.len());
+                    audio.drain(..dropped);
+                    samples -= dropped;
+                    match kept.back_mut() {
+                        Some(Input::Dropped(gap)) => *gap += dropped,
+                        _ => kept.push_back(Input::Dropped(dropped)),
+                    }
+                    if !audio.is_empty() {
+                        kept.push_back(Input::Audio(audio));
                     }
                 }
+                other => kept.push_back(other),
             }
-            Ok(Message::Close(_)) => {
-                info!("Client closed connection");
-                break;
+        }
+        self.inputs = kept;
+    }
+}
+
+/// The connection's half of an [`input_queue`]; dropping it ends the
+/// session once the queue is drained.
+pub(crate) struct Inputs(Arc<InputQueue>);
+
+impl Inputs {
+    /// Queue `input` for the transcription task.
+    ///
+    /// Audio never waits: once more than the [`Backpressure`] limit is
+    /// queued, the oldest audio is dropped under [`LagPolicy::Drop`] (and
+    /// reported with the next `lagging` message), while
+    /// [`LagPolicy::Coalesce`] lets twice the limit build up, then refuses
+    /// it. Other inputs wait while [`INPUT_QUEUE_LEN`] are queued.
+    pub(crate) async fn send(&self, input: Input) -> Result<(), QueueError> {
+        let queue = &*self.0;
+        let input = match input {
+            Input::Audio(samples) => return self.send_audio(samples),
+            input => input,
+        };
+        loop {
+            {
+                let mut state = queue.state();
+                if state.closed {
+                    return Err(QueueError::Closed);
+                }
+                if state.inputs.len() < INPUT_QUEUE_LEN {
+                    state.inputs.push_back(input);
+                    queue.queued.notify_one();
+                    return Ok(());
+                }
             }
-            Err(e) => {
-                error!("WebSocket error: {}", e);
-                break;
+            queue.taken.notified().await;
+        }
+    }
+
+    fn send_audio(&self, samples: Vec<f32>) -> Result<(), QueueError> {
+        let queue = &*self.0;
+        let mut state = queue.state();
+        if state.closed {
+            return Err(QueueError::Closed);
+        }
+        let queued = queue.stats.queued.load(Ordering::Relaxed) + samples.len();
+        let limit = queue.backpressure.max_lag_samples();
+        if queue.backpressure.policy == LagPolicy::Coalesce && queued > 2 * limit {
+            return Err(QueueError::Full(ApiError::Overloaded(
+                "Transcription can't keep up with this stream".to_string(),
+            )));
+        }
+        queue.stats.queued.store(queued, Ordering::Relaxed);
+        match state.inputs.back_mut() {
+            Some(Input::Audio(audio)) => audio.extend(samples),
+            _ => state.inputs.push_back(Input::Audio(samples)),
+        }
+        if queue.backpressure.policy == LagPolicy::Drop && queued > limit {
+            state.drop_oldest(queued - limit);
+            queue.stats.queued.store(limit, Ordering::Relaxed);
+        }
+        queue.queued.notify_one();
+        Ok(())
+    }
+}
+
+impl Drop for Inputs {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// The transcription task's half of an [`input_queue`].
+pub(crate) struct Queue(Arc<InputQueue>);
+
+impl Queue {
+    /// Take the next input, waiting for one; `None` once the connection is
+    /// gone and everything it queued has been taken.
+    pub(crate) async fn recv(&mut self) -> Option<Input> {
+        let queue = &*self.0;
+        loop {
+            {
+                let mut state = queue.state();
+                if let Some(input) = state.inputs.pop_front() {
+                    if let Input::Audio(samples) = &input {
+                        queue.stats.queued.fetch_sub(samples.len(), Ordering::Relaxed);
+                    }
+                    queue.taken.notify_one();
+                    return Some(input);
+                }
+                if state.closed {
+                    return None;
+                }
             }
-            _ => {}
+            queue.queued.notified().await;
         }
     }
+}
 
-    info!("Streaming connection closed");
+impl Drop for Queue {
+    fn drop(&mut self) {
+        self.0.close();
+    }
 }
 
-/// Handle a parsed client message
-async fn handle_client_message(
-    msg: ClientMessage,
-    session: &Arc<Mutex<StreamingSession>>,
-) -> Option<ServerMessage> {
+/// Parse a JSON client message into work for the session.
+fn parse_input(text: &str) -> Result<Input, ApiError> {
+    let msg = serde_json::from_str::<ClientMessage>(text).map_err(|e| {
+        warn!("Failed to parse client message: {}", e);
+        ApiError::InvalidMessage(e.to_string())
+    })?;
     match msg {
         ClientMessage::Audio { data, sample_rate } => {
             if sample_rate != SAMPLE_RATE {
-                return Some(ServerMessage::Error {
-                    message: format!(
-                        "Expected sample rate {}, got {}",
-                        SAMPLE_RATE, sample_rate
-                    ),
-                });
+                return Err(ApiError::InvalidAudio(format!(
+                    "Expected sample rate {}, got {}",
+                    SAMPLE_RATE, sample_rate
+                )));
             }
+            decode_audio(&data)
+                .map(Input::Audio)
+                .map_err(|e| ApiError::InvalidAudio(format!("Failed to decode audio: {}", e)))
+        }
+        ClientMessage::End => Ok(Input::End),
+        ClientMessage::Reset => Ok(Input::Reset),
+        ClientMessage::Language { language } => Ok(Input::Language(language)),
+        ClientMessage::Vocabulary { terms, suppress } => {
+            SessionVocabulary::new(&terms, &suppress).map(Input::Vocabulary)
+        }
+    }
+}
 
-            match decode_audio(&data) {
-                Ok(samples) => {
-                    let mut session_guard = session.lock().await;
-                    let chunk_ready = session_guard.add_samples(&samples);
-                    debug!("Added {} samples from JSON message", samples.len());
-
-                    // If chunk is full, auto-commit
-                    if chunk_ready {
-                        session_guard.transcription_pending = true;
-                        let audio_data = session_guard.get_chunk_clone();
-                        session_guard.clear_chunk();
-                        drop(session_guard);
-
-                        let transcribe_result = tokio::task::spawn_blocking(move || {
-                            let options = TranscribeOptions {
-                                language: Some("en".to_string()),
-                                translate: false,
-                            };
-                            transcribe::transcribe(&audio_data, options)
-                        })
-                        .await;
-
-                        let mut session_guard = session.lock().await;
-                        session_guard.transcription_pending = false;
-                        session_guard.last_transcribe_time = Some(Instant::now());
-                        drop(session_guard);
-
-                        match transcribe_result {
-                            Ok(Ok(result)) => Some(ServerMessage::Final {
-                                text: result.text,
-                                timestamp: now_millis(),
-                            }),
-                            Ok(Err(e)) => Some(ServerMessage::Error {
-                                message: format!("Transcription failed: {}", e),
-                            }),
-                            Err(e) => Some(ServerMessage::Error {
-                                message: format!("Spawn blocking failed: {}", e),
-                            }),
-                        }
-                    }
-                    // Otherwise send partial if throttle allows
-                    else if session_guard.should_transcribe() && session_guard.has_meaningful_audio() {
-                        session_guard.transcription_pending = true;
-                        let audio_data = session_guard.get_chunk_clone();
-                        drop(session_guard);
-
-                        let transcribe_result = tokio::task::spawn_blocking(move || {
-                            let options = TranscribeOptions {
-                                language: Some("en".to_string()),
-                                translate: false,
-                            };
-                            transcribe::transcribe(&audio_data, options)
-                        })
-                        .await;
-
-                        let mut session_guard = session.lock().await;
-                        session_guard.transcription_pending = false;
-                        session_guard.last_transcribe_time = Some(Instant::now());
-                        drop(session_guard);
-
-                        match transcribe_result {
-                            Ok(Ok(result)) => Some(ServerMessage::Partial {
-                                text: result.text,
-                                timestamp: now_millis(),
-                            }),
-                            Ok(Err(e)) => Some(ServerMessage::Error {
-                                message: format!("Transcription failed: {}", e),
-                            }),
-                            Err(e) => Some(ServerMessage::Error {
-                                message: format!("Spawn blocking failed: {}", e),
-                            }),
+/// Transcribe a session's queued input, sending the replies in order.
+///
+/// Audio that queued up while a chunk was transcribed is taken together, so
+/// the next partial covers everything received so far. A backlog beyond
+/// the [`Backpressure`] limit is dropped or transcribed without partials,
+/// and reported with a `lagging` message. Stops when the queue closes or the
+/// replies can't be delivered.
+///
+/// Finals and session summaries also go to the `webhook`, if any. Its
+/// session outlives the connection: the audio still queued is transcribed,
+/// and the session ended as if by `end`, so the webhook gets the whole
+/// transcript.
+pub(crate) async fn run_session(
+    mut queue: Queue,
+    replies: mpsc::UnboundedSender<ServerMessage>,
+    tenant: Option<Tenant>,
+    entry: Option<Entry>,
+    query: StreamQuery,
+    stats: Arc<SessionStats>,
+    mut webhook: Option<Webhook>,
+) {
+    let mut session = StreamingSession::with_config(query.session_config());
+    let mut options = ChunkOptions::new(&query);
+    let backpressure = backpressure();
+    let mut checkpoint = start_checkpoint(&query, tenant.as_ref());
+    'session: loop {
+        let Some(input) = queue.recv().await else {
+            break;
+        };
+        if replies.is_closed() && webhook.is_none() {
+            break;
+        }
+
+        let responses = match input {
+            Input::Audio(samples) => {
+                // Everything received during the last transcription is taken
+                // at once; when coalescing a backlog, without partials
+                let behind = samples.len() + session.buffered_samples();
+                let partials = backpressure.policy == LagPolicy::Drop
+                    || behind <= backpressure.max_lag_samples();
+                let mut responses = Vec::new();
+                if !partials {
+                    responses.push(lagging(behind, 0));
+                }
+
+                // A chunk at a time, so a backlog is committed as several finals
+                for audio in samples.chunks(CHUNK_SAMPLES) {
+                    let result = process_audio(
+                        &mut session,
+                        audio,
+                        tenant.as_ref(),
+                        entry.as_ref(),
+                        &mut options,
+                        &stats,
+                        partials,
+                    )
+                    .await;
+                    match result {
+                        Ok(response) => responses.extend(response),
+                        Err(e) => {
+                            error!("Transcription error: {}", e);
+                            responses.push(ApiError::TranscriptionFailed(e.to_string()).into());
+                            break;
                         }
-                    } else {
-                        None // Throttled, no response
                     }
                 }
-                Err(e) => Some(ServerMessage::Error {
-                    message: format!("Failed to decode audio: {}", e),
-                }),
+                stats.buffered.store(session.buffered_samples(), Ordering::Relaxed);
+                responses
+            }
+            Input::Dropped(samples) => {
+                // The dropped audio came after the buffered audio, which is
+                // older still: drop both, keeping later finals in place
+                let behind = session.buffered_samples()
+                    + samples
+                    + stats.queued.load(Ordering::Relaxed);
+                let dropped = session.buffered_samples() + samples;
+                session.skip(samples);
+                stats.buffered.store(0, Ordering::Relaxed);
+                vec![lagging(behind, dropped)]
+            }
+            Input::End => {
+                let responses = end_session(
+                    &mut session,
+                    tenant.as_ref(),
+                    entry.as_ref(),
+                    &mut options,
+                    &stats,
+                )
+                .await;
+                stats.buffered.store(0, Ordering::Relaxed);
+                discard_checkpoint(&mut checkpoint, &query, tenant.as_ref());
+                responses
+            }
+            Input::Reset => {
+                session.reset();
+                discard_checkpoint(&mut checkpoint, &query, tenant.as_ref());
+                options = ChunkOptions::new(&query);
+                stats.buffered.store(0, Ordering::Relaxed);
+                vec![ServerMessage::Ready {
+                    message: "Session reset".to_string(),
+                    session_id: None,
+                }]
+            }
+            Input::Language(language) => {
+                options.set_language(&language, query.lock_language);
+                Vec::new()
+            }
+            Input::Vocabulary(vocabulary) => {
+                options.vocabulary = vocabulary;
+                Vec::new()
+            }
+        };
+
+        if let Some(checkpoint) = checkpoint.as_mut() {
+            if responses.iter().any(|r| matches!(r, ServerMessage::Final { .. })) {
+                checkpoint.autosave(|| segments(&session));
             }
         }
-        ClientMessage::End => {
-            let mut session_guard = session.lock().await;
-            let audio_data = session_guard.get_chunk_clone();
-            session_guard.reset();
-            drop(session_guard);
-
-            if audio_data.is_empty() {
-                return Some(ServerMessage::Final {
-                    text: String::new(),
-                    timestamp: now_millis(),
-                });
+        for response in responses {
+            if let Some(webhook) = webhook.as_mut() {
+                webhook.send(&response);
             }
+            if replies.send(response).is_err() && webhook.is_none() {
+                break 'session;
+            }
+        }
+    }
 
-            // Run final transcription in a blocking thread
-            let transcribe_result = tokio::task::spawn_blocking(move || {
-                let options = TranscribeOptions {
-                    language: Some("en".to_string()),
-                    translate: false,
-                };
-                transcribe::transcribe(&audio_data, options)
-            })
+    // Closed without `end`: deliver the rest of the session to the webhook
+    if let Some(webhook) = webhook.as_mut() {
+        if session.buffered_samples() > 0 || !session.transcript().is_empty() {
+            let responses = end_session(
+                &mut session,
+                tenant.as_ref(),
+                entry.as_ref(),
+                &mut options,
+                &stats,
+            )
             .await;
-
-            // Reset session
-            let mut session_guard = session.lock().await;
-            session_guard.reset();
-            drop(session_guard);
-
-            match transcribe_result {
-                Ok(Ok(result)) => Some(ServerMessage::Final {
-                    text: result.text,
-                    timestamp: now_millis(),
-                }),
-                Ok(Err(e)) => Some(ServerMessage::Error {
-                    message: format!("Finalization failed: {}", e),
-                }),
-                Err(e) => Some(ServerMessage::Error {
-                    message: format!("Spawn blocking failed: {}", e),
-                }),
+            for response in &responses {
+                webhook.send(response);
             }
         }
-        ClientMessage::Reset => {
-            let mut session_guard = session.lock().await;
-            session_guard.reset();
-            Some(ServerMessage::Ready {
-                message: "Session reset".to_string(),
-            })
+    }
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.discard();
+    }
+}
+
+/// Start checkpointing the transcript of a session of `tenant`, tagged
+/// `stream`.
+fn start_checkpoint(query: &StreamQuery, tenant: Option<&Tenant>) -> Option<Checkpoint> {
+    let options = TranscribeOptions {
+        language: query.language.clone(),
+        translate: query.translate,
+        ..Default::default()
+    };
+    let metadata = JobMetadata { tags: vec!["stream".to_string()], ..Default::default() };
+    Checkpoint::start(&options, &metadata, tenant.map(|tenant| tenant.tenant.as_str()))
+}
+
+/// Remove the checkpoint of a finished session and start one for the next.
+fn discard_checkpoint(
+    checkpoint: &mut Option<Checkpoint>,
+    query: &StreamQuery,
+    tenant: Option<&Tenant>,
+) {
+    if let Some(finished) = checkpoint.take() {
+        finished.discard();
+        *checkpoint = start_checkpoint(query, tenant);
+    }
+}
+
+/// The `lagging` message for a backlog of `behind` samples, of which the
+/// oldest `dropped` were dropped to catch up.
+fn lagging(behind: usize, dropped: usize) -> ServerMessage {
+    let behind_ms = behind as u64 * 1000 / SAMPLE_RATE as u64;
+    let dropped_ms = dropped as u64 * 1000 / SAMPLE_RATE as u64;
+    warn!(behind_ms, dropped_ms, "Stream transcription is lagging");
+    ServerMessage::Lagging { behind_ms, dropped_ms, timestamp: now_millis() }
+}
+
+/// Transcribe what's left, then return the last final and the whole
+/// transcript (also published as a transcript event) and start over. A locked-in language is kept.
+async fn end_session(
+    session: &mut StreamingSession,
+    tenant: Option<&Tenant>,
+    entry: Option<&Entry>,
+    options: &mut ChunkOptions,
+    stats: &SessionStats,
+) -> Vec<ServerMessage> {
+    let mut responses = Vec::new();
+    let audio_data = session.take_chunk();
+    let last = if audio_data.is_empty() {
+        empty_final(session, options)
+    } else {
+        let len = audio_data.len();
+        tenants::charge(tenant, len);
+        access_log::add_audio(entry, len);
+        stats.chunks_committed.fetch_add(1, Ordering::Relaxed);
+        match stats.time(len, transcribe_chunk(audio_data, options, true)).await {
+            Ok(Some(chunk)) => {
+                responses.extend(options.detect(&chunk.result, len, true));
+                let speaker = options.speaker(&chunk);
+                final_message(session, chunk, speaker)
+            }
+            Ok(None) => empty_final(session, options),
+            Err(e) => ApiError::TranscriptionFailed(e.to_string()).into(),
         }
+    };
+    let complete = ServerMessage::SessionComplete {
+        text: session.transcript_text(),
+        segments: session.transcript().to_vec(),
+        duration_ms: session.duration_ms(),
+        timestamp: now_millis(),
+    };
+    if events::enabled() && !session.transcript().is_empty() {
+        publish_transcript(session, options);
     }
+    session.reset();
+    responses.extend([last, complete]);
+    responses
+}
+
+/// Publish the session's transcript to the transcript event sinks.
+fn publish_transcript(session: &StreamingSession, options: &ChunkOptions) {
+    let segments = segments(session);
+    // The locked-in language, if any
+    let language = options.language.clone().filter(|language| language != "auto");
+    let event = TranscriptEvent::stream(
+        session.transcript_text(),
+        segments,
+        session.duration_ms(),
+        language,
+    );
+    events::publish(event);
 }
 
-/// Get current timestamp in milliseconds
-fn now_millis() -> u64 {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as u64
+/// The session's committed transcript as segments.
+fn segments(session: &StreamingSession) -> Vec<Segment> {
+    session
+        .transcript()
+        .iter()
+        .map(|s| Segment { start_ms: s.start_ms, end_ms: s.end_ms, text: s.text.clone() })
+        .collect()
 }
 
+/// A final with no text, for an `end` with nothing left to transcribe.
+fn empty_final(session: &StreamingSession, options: &ChunkOptions) -> ServerMessage {
+    ServerMessage::Final {
+        text: String::new(),
+        translation: options.translate.then(String::new),
+        timestamp: now_millis(),
+        language: None,
+        language_probability: None,
+        words: Vec::new(),
+        speaker: None,
+        speaker_changed: None,
+        start_ms: session.duration_ms(),
+        end_ms: session.duration_ms(),
+    }
+}
+
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_client_slots() {
+        let peer: SocketAddr = "203.0.113.7:50123".parse().unwrap();
+        let client = client_identity(None, Some(peer)).unwrap();
+        assert_eq!(client, "ip:203.0.113.7");
+        let tenant = Tenant {
+            key_id: "k-1".to_string(),
+            tenant: "acme".to_string(),
+            quota: Default::default(),
+        };
+        assert_eq!(client_identity(Some(&tenant), Some(peer)).as_deref(), Some("key:k-1"));
+        assert_eq!(client_identity(None, None), None);
+
+        let first = ClientSlot::open(client.clone(), 2).unwrap();
+        let _second = ClientSlot::open(client.clone(), 2).unwrap();
+        let error = ClientSlot::open(client.clone(), 2).unwrap_err();
+        assert_eq!(error.problem().code, "too_many_streams");
+        // A closed stream frees its slot
+        drop(first);
+        let _third = ClientSlot::open(client, 2).unwrap();
+    }
+
     #[test]
     fn test_decode_audio() {
         // Test with known values: two 16-bit samples
@@ -484,7 +1644,7 @@ mod tests {
         // Sample 2: 0x7FFF (32767) -> ~1.0
         let data = base64::Engine::encode(
             &base64::engine::general_purpose::STANDARD,
-            &[0x00, 0x00, 0xFF, 0x7F],
+            [0x00, 0x00, 0xFF, 0x7F],
         );
         let samples = decode_audio(&data).unwrap();
         assert_eq!(samples.len(), 2);
@@ -492,29 +1652,6 @@ mod tests {
         assert!((samples[1] - 0.99997).abs() < 0.001);
     }
 
-    #[test]
-    fn test_streaming_session_chunk_ready() {
-        let mut session = StreamingSession::new();
-
-        // Add samples less than chunk size - should return false
-        let small_samples = vec![0.5f32; CHUNK_SAMPLES / 2];
-        assert!(!session.add_samples(&small_samples));
-
-        // Add more to exceed chunk size - should return true
-        let more_samples = vec![0.5f32; CHUNK_SAMPLES];
-        assert!(session.add_samples(&more_samples));
-    }
-
-    #[test]
-    fn test_streaming_session_clear_chunk() {
-        let mut session = StreamingSession::new();
-        session.add_samples(&vec![0.5f32; 1000]);
-        assert!(!session.current_chunk.is_empty());
-        
-        session.clear_chunk();
-        assert!(session.current_chunk.is_empty());
-    }
-
     #[test]
     fn test_client_message_parsing() {
         let json = r#"{"type":"audio","data":"AAAA","sample_rate":16000}"#;
@@ -534,17 +1671,426 @@ mod tests {
         let json = r#"{"type":"reset"}"#;
         let msg: ClientMessage = serde_json::from_str(json).unwrap();
         assert!(matches!(msg, ClientMessage::Reset));
+
+        let json = r#"{"type":"language","language":"fr"}"#;
+        let msg: ClientMessage = serde_json::from_str(json).unwrap();
+        assert!(matches!(msg, ClientMessage::Language { language } if language == "fr"));
+
+        let json = r#"{"type":"vocabulary","terms":["Okonkwo"]}"#;
+        let msg: ClientMessage = serde_json::from_str(json).unwrap();
+        assert!(matches!(msg, ClientMessage::Vocabulary { terms, suppress }
+            if terms == ["Okonkwo"] && suppress.is_empty()));
+    }
+
+    #[test]
+    fn test_vocabulary_message_sets_prompt_and_suppression() {
+        let Ok(Input::Vocabulary(vocabulary)) = parse_input(
+            r#"{"type":"vocabulary","terms":["Okonkwo","Acme Corp"],"suppress":[" um "]}"#,
+        ) else {
+            panic!("Expected a vocabulary update");
+        };
+        let options = vocabulary.apply(TranscribeOptions::default());
+        assert_eq!(options.initial_prompt.as_deref(), Some("Okonkwo, Acme Corp"));
+        assert!(options.decoding.suppressed().contains(&"um"));
+
+        // Clearing it
+        let Ok(Input::Vocabulary(vocabulary)) =
+            parse_input(r#"{"type":"vocabulary","terms":[]}"#)
+        else {
+            panic!("Expected a vocabulary update");
+        };
+        assert_eq!(vocabulary, SessionVocabulary::default());
+
+        let invalid = parse_input(r#"{"type":"vocabulary","terms":[],"suppress":["a,b"]}"#);
+        assert!(matches!(invalid, Err(ApiError::InvalidMessage(_))));
     }
 
     #[test]
     fn test_server_message_serialization() {
         let msg = ServerMessage::Partial {
             text: "hello".to_string(),
+            translation: None,
             timestamp: 12345,
+            start_ms: 0,
+            end_ms: 0,
         };
         let json = serde_json::to_string(&msg).unwrap();
         assert!(json.contains("\"type\":\"partial\""));
         assert!(json.contains("\"text\":\"hello\""));
         assert!(json.contains("\"ts\":12345"));
+        assert!(!json.contains("translation"));
+
+        let msg = ServerMessage::Partial {
+            text: "hola".to_string(),
+            translation: Some("hello".to_string()),
+            timestamp: 12345,
+            start_ms: 0,
+            end_ms: 0,
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains("\"translation\":\"hello\""));
+    }
+
+    #[test]
+    fn test_final_message_reports_detected_language() {
+        let msg = ServerMessage::Final {
+            text: "hola".to_string(),
+            translation: None,
+            timestamp: 12345,
+            language: Some("es".to_string()),
+            language_probability: Some(0.9),
+            words: Vec::new(),
+            speaker: None,
+            speaker_changed: None,
+            start_ms: 0,
+            end_ms: 0,
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains("\"language\":\"es\""));
+        assert!(json.contains("\"language_probability\":0.9"));
+
+        let msg = ServerMessage::Final {
+            text: "hello".to_string(),
+            translation: None,
+            timestamp: 12345,
+            language: None,
+            language_probability: None,
+            words: Vec::new(),
+            speaker: None,
+            speaker_changed: None,
+            start_ms: 0,
+            end_ms: 0,
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(!json.contains("language"));
+        assert!(!json.contains("words"));
+        assert!(!json.contains("speaker"));
+    }
+
+    #[test]
+    fn test_session_ids_name_channels() {
+        assert!(validate_session_id("meeting-42").is_ok());
+        assert!(validate_session_id("team.a:standup_1").is_ok());
+        assert!(validate_session_id("").is_err());
+        assert!(validate_session_id("room 1").is_err());
+        assert!(validate_session_id("room*").is_err());
+        assert!(validate_session_id(&"a".repeat(129)).is_err());
+    }
+
+    #[test]
+    fn test_final_message_numbers_speakers() {
+        let mut session = StreamingSession::new();
+        let result = TranscribeResult {
+            text: "Over to you.".to_string(),
+            segments: 1,
+            avg_token_prob: 0.9,
+            timed_segments: Vec::new(),
+            words: Vec::new(),
+            tokens: Vec::new(),
+            language: None,
+            language_probability: None,
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let chunk = Transcribed { result, translation: None, voiceprint: None };
+        let turn = SpeakerTurn { speaker: 1, changed: true };
+        let json = serde_json::to_value(final_message(&mut session, chunk, Some(turn))).unwrap();
+        assert_eq!(json["speaker"], 1);
+        assert_eq!(json["speaker_changed"], true);
+
+        // Speakers are only tracked when asked for
+        assert!(ChunkOptions::new(&StreamQuery::default()).speakers.is_none());
+        let query = StreamQuery { speakers: true, ..Default::default() };
+        assert!(ChunkOptions::new(&query).speakers.is_some());
+    }
+
+    #[test]
+    fn test_parse_input() {
+        assert!(matches!(parse_input(r#"{"type":"end"}"#), Ok(Input::End)));
+        let audio = r#"{"type":"audio","data":"AAD/fw=="}"#;
+        assert!(matches!(parse_input(audio), Ok(Input::Audio(samples)) if samples.len() == 2));
+
+        let audio = r#"{"type":"audio","data":"AAD/fw==","sample_rate":44100}"#;
+        assert!(matches!(parse_input(audio), Err(ApiError::InvalidAudio(_))));
+        assert!(matches!(parse_input("{"), Err(ApiError::InvalidMessage(_))));
+    }
+
+    #[tokio::test]
+    async fn test_session_task_replies_in_order() {
+        let stats = Arc::new(SessionStats::default());
+        let (inputs, queue) = input_queue(stats.clone(), Backpressure::default());
+        let (replies_tx, mut replies) = mpsc::unbounded_channel();
+        // Queued before the task starts, so taken together
+        for _ in 0..3 {
+            inputs.send(Input::Audio(vec![0.0; 1600])).await.unwrap();
+        }
+        inputs.send(Input::End).await.unwrap();
+        inputs.send(Input::Reset).await.unwrap();
+        assert_eq!(stats.queued.load(Ordering::Relaxed), 4800);
+        drop(inputs);
+        let query = StreamQuery::default();
+        run_session(queue, replies_tx, None, None, query, stats.clone(), None).await;
+        assert_eq!(stats.queued.load(Ordering::Relaxed), 0);
+        assert_eq!(stats.chunks_committed.load(Ordering::Relaxed), 1);
+
+        // Silence: nothing to transcribe
+        let Some(ServerMessage::Final { text, .. }) = replies.recv().await else {
+            panic!("Expected the last final");
+        };
+        assert!(text.is_empty());
+        let Some(ServerMessage::SessionComplete { duration_ms, .. }) = replies.recv().await else {
+            panic!("Expected the session transcript");
+        };
+        assert_eq!(duration_ms, 300);
+        assert!(matches!(replies.recv().await, Some(ServerMessage::Ready { .. })));
+        assert!(replies.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stats_message() {
+        let stats = SessionStats::default();
+        stats.queued.store(8000, Ordering::Relaxed);
+        stats.buffered.store(16000, Ordering::Relaxed);
+        stats.chunks_committed.store(2, Ordering::Relaxed);
+        let message = serde_json::to_value(stats.message()).unwrap();
+        assert_eq!(message["type"], "stats");
+        assert_eq!(message["buffered_ms"], 1500);
+        assert_eq!(message["chunks_committed"], 2);
+        assert_eq!(message["rtf"], 0.0);
+
+        let audio = SAMPLE_RATE as usize * 10;
+        stats.time(audio, tokio::time::sleep(Duration::from_millis(20))).await;
+        let ServerMessage::Stats { avg_latency_ms, rtf, .. } = stats.message() else {
+            panic!("Expected a stats message");
+        };
+        assert!(avg_latency_ms >= 20);
+        assert!(rtf > 0.0 && rtf < 0.1);
+    }
+
+    #[test]
+    fn test_lag_policy_parsing() {
+        assert_eq!("drop".parse::<LagPolicy>().unwrap(), LagPolicy::Drop);
+        assert_eq!(" coalesce".parse::<LagPolicy>().unwrap(), LagPolicy::Coalesce);
+        assert!("block".parse::<LagPolicy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_queue_drops_oldest_audio() {
+        let stats = Arc::new(SessionStats::default());
+        let backpressure = Backpressure { max_lag_ms: 1000, policy: LagPolicy::Drop };
+        let (inputs, mut queue) = input_queue(stats.clone(), backpressure);
+        let audio = |from: usize| Input::Audio((from..from + 8000).map(|i| i as f32).collect());
+        inputs.send(audio(0)).await.unwrap();
+        inputs.send(Input::Language("de".to_string())).await.unwrap();
+        // Over a second queued: the oldest half second goes
+        for from in [8000, 16000] {
+            inputs.send(audio(from)).await.unwrap();
+        }
+        assert_eq!(stats.queued.load(Ordering::Relaxed), 16000);
+        assert!(matches!(queue.recv().await, Some(Input::Dropped(8000))));
+        assert!(matches!(queue.recv().await, Some(Input::Language(_))));
+        let Some(Input::Audio(samples)) = queue.recv().await else {
+            panic!("Expected the newest audio");
+        };
+        assert_eq!((samples.len(), samples[0]), (16000, 8000.0));
+        assert_eq!(stats.queued.load(Ordering::Relaxed), 0);
+
+        drop(queue);
+        let closed = inputs.send(Input::End).await;
+        assert!(matches!(closed, Err(QueueError::Closed)));
+    }
+
+    #[tokio::test]
+    async fn test_queue_refuses_audio_when_coalescing() {
+        let stats = Arc::new(SessionStats::default());
+        let backpressure = Backpressure { max_lag_ms: 1000, policy: LagPolicy::Coalesce };
+        let (inputs, mut queue) = input_queue(stats.clone(), backpressure);
+        // Up to twice the limit is kept, to transcribe without partials
+        inputs.send(Input::Audio(vec![0.0; 32000])).await.unwrap();
+        let refused = inputs.send(Input::Audio(vec![0.0; 1600])).await;
+        assert!(matches!(refused, Err(QueueError::Full(ApiError::Overloaded(_)))));
+        assert!(matches!(queue.recv().await, Some(Input::Audio(samples)) if samples.len() == 32000));
+
+        // Other inputs wait for room
+        for _ in 0..INPUT_QUEUE_LEN {
+            inputs.send(Input::Reset).await.unwrap();
+        }
+        let (queued, taken) = tokio::join!(inputs.send(Input::End), queue.recv());
+        assert!(queued.is_ok());
+        assert!(matches!(taken, Some(Input::Reset)));
+        drop(inputs);
+        let mut rest = 0;
+        while queue.recv().await.is_some() {
+            rest += 1;
+        }
+        assert_eq!(rest, INPUT_QUEUE_LEN);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_audio_keeps_its_place() {
+        let stats = Arc::new(SessionStats::default());
+        let backpressure = Backpressure { max_lag_ms: 1000, policy: LagPolicy::Drop };
+        let (inputs, queue) = input_queue(stats.clone(), backpressure);
+        let (replies_tx, mut replies) = mpsc::unbounded_channel();
+        inputs.send(Input::Audio(vec![0.0; 8000])).await.unwrap();
+        let task = tokio::spawn(run_session(
+            queue,
+            replies_tx,
+            None,
+            None,
+            StreamQuery::default(),
+            stats.clone(),
+            None,
+        ));
+        // Wait for the first half second to be buffered, then fall behind
+        while stats.buffered.load(Ordering::Relaxed) == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        inputs.send(Input::Audio(vec![0.0; 24000])).await.unwrap();
+        inputs.send(Input::End).await.unwrap();
+        drop(inputs);
+        task.await.unwrap();
+
+        // The buffered audio is older than the dropped, so goes with it
+        let Some(ServerMessage::Lagging { behind_ms, dropped_ms, .. }) = replies.recv().await
+        else {
+            panic!("Expected a lagging message");
+        };
+        assert_eq!((behind_ms, dropped_ms), (2000, 1000));
+        // Silent, so the last final is empty, at the end of the stream
+        let Some(ServerMessage::Final { end_ms, .. }) = replies.recv().await else {
+            panic!("Expected the last final");
+        };
+        assert_eq!(end_ms, 2000);
+        let Some(ServerMessage::SessionComplete { duration_ms, .. }) = replies.recv().await else {
+            panic!("Expected the session transcript");
+        };
+        assert_eq!(duration_ms, 2000);
+    }
+
+    #[test]
+    fn test_auto_language_locks_in() {
+        let query = StreamQuery { language: Some("auto".to_string()), ..Default::default() };
+        let mut options = ChunkOptions::new(&query);
+        assert!(options.detecting);
+        let detected = |language: &str, probability: f32| TranscribeResult {
+            text: String::new(),
+            segments: 1,
+            avg_token_prob: 0.9,
+            timed_segments: Vec::new(),
+            words: Vec::new(),
+            tokens: Vec::new(),
+            language: Some(language.to_string()),
+            language_probability: Some(probability),
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        // Too little audio, then too unsure for a partial
+        assert!(options.detect(&detected("de", 0.9), 16000, false).is_none());
+        assert!(options.detect(&detected("nl", 0.4), 48000, false).is_none());
+        let message = options.detect(&detected("de", 0.8), 48000, false);
+        let Some(ServerMessage::LanguageDetected { language, .. }) = message else {
+            panic!("Expected the language to be locked in");
+        };
+        assert_eq!(language, "de");
+        assert_eq!(options.language.as_deref(), Some("de"));
+        // Locked: later chunks are transcribed as German
+        assert!(options.detect(&detected("en", 0.9), 96000, true).is_none());
+
+        // The client can switch, or detect again
+        options.set_language("fr", true);
+        assert_eq!((options.language.as_deref(), options.detecting), (Some("fr"), false));
+        options.set_language("auto", true);
+        assert!(options.detecting);
+
+        // Detection per chunk, without lock-in
+        let query = StreamQuery { lock_language: false, ..query };
+        let mut options = ChunkOptions::new(&query);
+        assert!(options.detect(&detected("de", 0.9), 96000, true).is_none());
+        assert_eq!(options.language.as_deref(), Some("auto"));
+    }
+
+    #[test]
+    fn test_query_sets_partial_timing() {
+        assert_eq!(StreamQuery::default().session_config(), SessionConfig::default());
+
+        let query = StreamQuery {
+            partial_interval_ms: Some(150),
+            min_partial_ms: Some(0),
+            ..Default::default()
+        };
+        let config = query.session_config();
+        assert_eq!(config.partial_interval_ms, 150);
+        assert_eq!(config.min_partial_ms, 100);
+    }
+
+    #[test]
+    fn test_final_message_times_words_from_stream_start() {
+        let mut session = StreamingSession::new();
+        session.push(&[0.5f32; 16000]);
+        session.take_chunk();
+        session.push(&[0.5f32; 8000]);
+        session.take_chunk();
+
+        let result = TranscribeResult {
+            text: "Hello world".to_string(),
+            segments: 1,
+            avg_token_prob: 0.9,
+            timed_segments: Vec::new(),
+            words: vec![
+                Word { start_ms: 0, end_ms: 200, text: "Hello".to_string(), probability: 0.9 },
+                Word { start_ms: 200, end_ms: 480, text: "world".to_string(), probability: 0.8 },
+            ],
+            tokens: Vec::new(),
+            language: None,
+            language_probability: None,
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let chunk = Transcribed { result, translation: None, voiceprint: None };
+        let json = serde_json::to_value(final_message(&mut session, chunk, None)).unwrap();
+        assert_eq!(json["text"], "Hello world");
+        assert_eq!(json["words"][0]["start_ms"], 1000);
+        assert_eq!(json["words"][1]["end_ms"], 1480);
+        assert_eq!(json["words"][1]["text"], "world");
+    }
+
+    #[test]
+    fn test_english_chunks_translate_to_themselves() {
+        let result = TranscribeResult {
+            text: "Hello world".to_string(),
+            segments: 1,
+            avg_token_prob: 0.9,
+            timed_segments: Vec::new(),
+            words: Vec::new(),
+            tokens: Vec::new(),
+            language: Some("en".to_string()),
+            language_probability: Some(0.97),
+            trimmed: None,
+            non_speech: Vec::new(),
+            channels: Vec::new(),
+            warnings: Vec::new(),
+        };
+        // No model is loaded, so a second pass would fail
+        let translation =
+            translate_chunk(&[0.0; 16000], &result, Some("auto"), AudioContext::Fit, true)
+                .unwrap();
+        assert_eq!(translation, "Hello world");
+    }
+
+    #[test]
+    fn test_error_message_carries_code() {
+        let msg = ServerMessage::from(ApiError::InvalidMessage("bad json".to_string()));
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains("\"type\":\"error\""));
+        assert!(json.contains("\"code\":\"invalid_message\""));
+        assert!(json.contains("\"message\":\"Invalid message format: bad json\""));
     }
 }
@@ -0,0 +1,121 @@
+//! systemd integration for VoiceMark sidecar.
+//!
+//! - Socket activation: when started from a `.socket` unit, the sidecar
+//!   serves the sockets systemd passes in (`LISTEN_FDS`) instead of binding
+//!   `VOICEMARK_BIND`. systemd keeps those sockets open across restarts, so
+//!   clients queue rather than get refused while the sidecar restarts.
+//! - Readiness: with `Type=notify`, `READY=1` is sent to `NOTIFY_SOCKET` once
+//!   the model is loaded and the server is listening, so units ordered
+//!   after the sidecar only start when it can serve requests.
+//!
+//! Both are no-ops when not running under systemd, and on non-Unix targets.
+
+use anyhow::Result;
+use std::net::TcpListener;
+
+/// First file descriptor passed by systemd (`SD_LISTEN_FDS_START`).
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Take the listening sockets passed by systemd socket activation.
+///
+/// Returns an empty list unless `LISTEN_FDS` is set for this process. The
+/// activation variables are removed so they don't leak to child processes.
+#[cfg(unix)]
+pub fn listen_fds() -> Result<Vec<TcpListener>> {
+    use std::os::fd::FromRawFd;
+
+    let count = parse_listen_fds(
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(name);
+    }
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count as i32)
+        .map(|fd| {
+            // Safety: systemd hands these descriptors to this process, and
+            // nothing else in the process owns them
+            let listener = unsafe { TcpListener::from_raw_fd(fd) };
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+pub fn listen_fds() -> Result<Vec<TcpListener>> {
+    Ok(Vec::new())
+}
+
+/// Number of passed sockets, if they were passed to process `pid`.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn parse_listen_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    let for_us = listen_pid.and_then(|p| p.trim().parse::<u32>().ok()) == Some(pid);
+    if !for_us {
+        return 0;
+    }
+    listen_fds.and_then(|n| n.trim().parse().ok()).unwrap_or(0)
+}
+
+/// Send a state update (e.g. `READY=1`) to the service manager.
+///
+/// Does nothing unless `NOTIFY_SOCKET` is set; failures are logged.
+#[cfg(unix)]
+pub fn notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let Ok(path) = std::env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+    let sent = UnixDatagram::unbound().and_then(|socket| {
+        match path.strip_prefix('@') {
+            // Abstract socket namespace
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.send_to_addr(state.as_bytes(), &addr)
+            }
+            _ => socket.send_to(state.as_bytes(), &path),
+        }
+    });
+    if let Err(e) = sent {
+        tracing::warn!("Failed to notify systemd ({}): {}", state, e);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn notify(_state: &str) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listen_fds() {
+        assert_eq!(parse_listen_fds(Some("42"), Some("2"), 42), 2);
+        // Variables meant for another process (e.g. our parent) are ignored
+        assert_eq!(parse_listen_fds(Some("41"), Some("2"), 42), 0);
+        assert_eq!(parse_listen_fds(None, Some("2"), 42), 0);
+        assert_eq!(parse_listen_fds(Some("42"), None, 42), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_notify_sends_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let receiver = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+
+        std::env::set_var("NOTIFY_SOCKET", &path);
+        notify("READY=1");
+        std::env::remove_var("NOTIFY_SOCKET");
+
+        let mut buf = [0u8; 32];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+    }
+}
@@ -0,0 +1,718 @@
+//! API keys, quotas and usage accounting for VoiceMark sidecar.
+//!
+//! With `VOICEMARK_TENANTS_DB` set, every endpoint except `/health` requires
+//! an API key, sent as `Authorization: Bearer <key>`, `X-API-Key: <key>`, or
+//! an `api_key` query parameter (for WebSocket clients, which can't set
+//! headers). Each key belongs to a tenant and may carry quotas:
+//!
+//! - audio seconds per UTC day, checked before each transcription request
+//!   (`402 Payment Required` once used up), and
+//! - concurrent `/stream` connections (`429 Too Many Requests`).
+//!
+//! Transcribed audio is counted per key and day in SQLite and reported by
+//! `GET /usage`. Cached results are free. Only a SHA-256 hash of each key
+//! is stored; keys are created with the `create-key` subcommand or the admin
+//! API (see `admin.rs`):
+//!
+//! ```bash
+//! VOICEMARK_TENANTS_DB=tenants.db voicemark-sidecar create-key --tenant NAME \
+//!     [--audio-seconds-per-day N] [--max-streams N]
+//! ```
+//!
+//! With `VOICEMARK_JWT_JWKS_URL` set (see `jwt.rs`), bearer JWTs are accepted
+//! too. Their subject is the tenant, with key ID `jwt:<sub>` and no quotas;
+//! usage is recorded if a tenant database is configured as well.
+//!
+//! Jobs, uploads and transcripts belong to the tenant that created them,
+//! and other tenants get `404` for them (see [`can_access`]). The admin
+//! token (see `admin.rs`) is accepted here too, without quotas, and sees
+//! every tenant's.
+
+use anyhow::{Context, bail};
+use axum::{
+    Extension, Json,
+    extract::{ConnectInfo, Request},
+    http::{Method, header},
+    middleware::Next,
+    response::Response,
+};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::access_log;
+use crate::admin;
+use crate::clock::now_secs;
+use crate::cluster;
+use crate::error::{ApiError, Problem};
+use crate::jwt;
+
+/// Sample rate of decoded audio.
+const SAMPLE_RATE: u64 = 16000;
+
+/// Days of history reported by `GET /usage`.
+const USAGE_HISTORY_DAYS: u32 = 30;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS api_keys (
+    id TEXT PRIMARY KEY,
+    tenant TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL,
+    revoked_at INTEGER,
+    audio_seconds_per_day INTEGER,
+    max_concurrent_streams INTEGER
+);
+CREATE TABLE IF NOT EXISTS usage (
+    key_id TEXT NOT NULL REFERENCES api_keys(id),
+    day TEXT NOT NULL,
+    audio_ms INTEGER NOT NULL DEFAULT 0,
+    requests INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (key_id, day)
+);
+";
+
+/// Tenant database, unset when API keys are disabled.
+static DB: OnceLock<Mutex<Connection>> = OnceLock::new();
+
+/// Open `/stream` connections per key ID.
+static ACTIVE_STREAMS: OnceLock<Mutex<HashMap<String, u32>>> = OnceLock::new();
+
+/// Limits attached to an API key.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Quota {
+    /// Audio seconds per UTC day; `None` is unlimited.
+    pub audio_seconds_per_day: Option<u64>,
+    /// Concurrent `/stream` connections; `None` is unlimited.
+    pub max_concurrent_streams: Option<u32>,
+}
+
+/// The authenticated caller, added to request extensions by
+/// [`authenticate`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tenant {
+    pub key_id: String,
+    pub tenant: String,
+    pub quota: Quota,
+}
+
+/// Whether `caller` may see a job, upload or transcript created by `owner`:
+/// only its own tenant can, unless authentication is disabled or the caller
+/// is the admin (both without a tenant).
+pub fn can_access(caller: Option<&Tenant>, owner: Option<&str>) -> bool {
+    caller.is_none_or(|caller| owner == Some(caller.tenant.as_str()))
+}
+
+/// An API key as listed by the admin API (without the key itself).
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ApiKey {
+    pub id: String,
+    pub tenant: String,
+    /// Creation time (Unix seconds).
+    pub created_at: u64,
+    /// Revocation time (Unix seconds), if revoked.
+    pub revoked_at: Option<u64>,
+    pub quota: Quota,
+}
+
+/// Usage for one UTC day.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct DayUsage {
+    /// UTC date (`YYYY-MM-DD`).
+    pub day: String,
+    pub audio_seconds: f64,
+    pub requests: u64,
+}
+
+/// Usage report (`GET /usage`).
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UsageResponse {
+    pub tenant: String,
+    pub key_id: String,
+    pub quota: Quota,
+    pub today: DayUsage,
+    /// Audio seconds left today; `null` if unlimited.
+    pub remaining_audio_seconds: Option<f64>,
+    pub active_streams: u32,
+    /// Previous days with usage, most recent first.
+    pub history: Vec<DayUsage>,
+}
+
+/// Open (creating if needed) a tenant database.
+pub fn open(path: &Path) -> anyhow::Result<Connection> {
+    let conn = Connection::open(path)
+        .with_context(|| format!("Failed to open tenant database {:?}", path))?;
+    conn.execute_batch(SCHEMA)
+        .context("Failed to initialize tenant database")?;
+    Ok(conn)
+}
+
+/// Require API keys, stored in the database at `path`. Call once at startup.
+pub fn configure(path: &Path) -> anyhow::Result<()> {
+    let conn = open(path)?;
+    let keys: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM api_keys WHERE revoked_at IS NULL",
+        [],
+        |row| row.get(0),
+    )?;
+    info!(db = ?path, keys, "API keys required");
+    if DB.set(Mutex::new(conn)).is_err() {
+        bail!("Tenant database already configured");
+    }
+    Ok(())
+}
+
+/// The tenant database, if API keys are enabled.
+pub fn db() -> Option<MutexGuard<'static, Connection>> {
+    DB.get().map(|db| db.lock().unwrap())
+}
+
+fn active_streams() -> MutexGuard<'static, HashMap<String, u32>> {
+    ACTIVE_STREAMS.get_or_init(Default::default).lock().unwrap()
+}
+
+/// Create an API key for `tenant`, returning its ID and the key itself.
+/// The key is not stored and can't be recovered later.
+pub fn create_key(
+    conn: &Connection,
+    tenant: &str,
+    quota: &Quota,
+) -> rusqlite::Result<(String, String)> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let key = format!(
+        "vm_{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    conn.execute(
+        "INSERT INTO api_keys (id, tenant, key_hash, created_at, audio_seconds_per_day, max_concurrent_streams)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            id,
+            tenant,
+            hash_key(&key),
+            now_secs(),
+            quota.audio_seconds_per_day,
+            quota.max_concurrent_streams
+        ],
+    )?;
+    Ok((id, key))
+}
+
+const KEY_COLUMNS: &str =
+    "id, tenant, created_at, revoked_at, audio_seconds_per_day, max_concurrent_streams";
+
+fn key_from_row(row: &rusqlite::Row) -> rusqlite::Result<ApiKey> {
+    Ok(ApiKey {
+        id: row.get(0)?,
+        tenant: row.get(1)?,
+        created_at: row.get(2)?,
+        revoked_at: row.get(3)?,
+        quota: Quota {
+            audio_seconds_per_day: row.get(4)?,
+            max_concurrent_streams: row.get(5)?,
+        },
+    })
+}
+
+/// All API keys, including revoked ones, oldest first.
+pub fn list_keys(conn: &Connection) -> rusqlite::Result<Vec<ApiKey>> {
+    let mut statement = conn.prepare(&format!(
+        "SELECT {} FROM api_keys ORDER BY created_at, rowid",
+        KEY_COLUMNS
+    ))?;
+    let keys = statement.query_map([], key_from_row)?.collect();
+    keys
+}
+
+/// Look up an API key by ID.
+pub fn get_key(conn: &Connection, id: &str) -> rusqlite::Result<Option<ApiKey>> {
+    conn.query_row(
+        &format!("SELECT {} FROM api_keys WHERE id = ?1", KEY_COLUMNS),
+        params![id],
+        key_from_row,
+    )
+    .optional()
+}
+
+/// Revoke a key; it stops working on the next request. Returns `false` if
+/// there is no such key. Revoking twice keeps the first revocation time.
+pub fn revoke_key(conn: &Connection, id: &str) -> rusqlite::Result<bool> {
+    let exists = get_key(conn, id)?.is_some();
+    conn.execute(
+        "UPDATE api_keys SET revoked_at = ?2 WHERE id = ?1 AND revoked_at IS NULL",
+        params![id, now_secs()],
+    )?;
+    Ok(exists)
+}
+
+/// Replace a key's quota; it applies from the next request. Returns `false`
+/// if there is no such key.
+pub fn set_quota(conn: &Connection, id: &str, quota: &Quota) -> rusqlite::Result<bool> {
+    let updated = conn.execute(
+        "UPDATE api_keys SET audio_seconds_per_day = ?2, max_concurrent_streams = ?3 WHERE id = ?1",
+        params![id, quota.audio_seconds_per_day, quota.max_concurrent_streams],
+    )?;
+    Ok(updated > 0)
+}
+
+fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// Find the tenant for an unrevoked key.
+fn lookup(conn: &Connection, key: &str) -> rusqlite::Result<Option<Tenant>> {
+    conn.query_row(
+        "SELECT id, tenant, audio_seconds_per_day, max_concurrent_streams
+         FROM api_keys WHERE key_hash = ?1 AND revoked_at IS NULL",
+        params![hash_key(key)],
+        |row| {
+            Ok(Tenant {
+                key_id: row.get(0)?,
+                tenant: row.get(1)?,
+                quota: Quota {
+                    audio_seconds_per_day: row.get(2)?,
+                    max_concurrent_streams: row.get(3)?,
+                },
+            })
+        },
+    )
+    .optional()
+}
+
+/// Whether `key` is a valid, unrevoked API key.
+pub fn is_valid_key(conn: &Connection, key: &str) -> bool {
+    lookup(conn, key).ok().flatten().is_some()
+}
+
+/// Usage of `key_id` on `day`.
+fn day_usage(conn: &Connection, key_id: &str, day: &str) -> rusqlite::Result<DayUsage> {
+    let usage = conn
+        .query_row(
+            "SELECT audio_ms, requests FROM usage WHERE key_id = ?1 AND day = ?2",
+            params![key_id, day],
+            |row| Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?)),
+        )
+        .optional()?
+        .unwrap_or_default();
+    Ok(DayUsage {
+        day: day.to_string(),
+        audio_seconds: usage.0 as f64 / 1000.0,
+        requests: usage.1,
+    })
+}
+
+/// Add one request with `audio_ms` of audio to today's usage.
+fn add_usage(conn: &Connection, key_id: &str, day: &str, audio_ms: u64) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO usage (key_id, day, audio_ms, requests) VALUES (?1, ?2, ?3, 1)
+         ON CONFLICT (key_id, day)
+         DO UPDATE SET audio_ms = audio_ms + excluded.audio_ms, requests = requests + 1",
+        params![key_id, day, audio_ms],
+    )?;
+    Ok(())
+}
+
+/// Fail with `402` if `tenant` has used up today's audio quota.
+fn check_audio_quota(conn: &Connection, tenant: &Tenant) -> Result<(), ApiError> {
+    let Some(limit) = tenant.quota.audio_seconds_per_day else {
+        return Ok(());
+    };
+    let usage = day_usage(conn, &tenant.key_id, &today())
+        .map_err(|e| ApiError::Internal(format!("Failed to read usage: {}", e)))?;
+    if usage.audio_seconds >= limit as f64 {
+        return Err(ApiError::QuotaExceeded(format!(
+            "Daily audio quota of {} seconds used up",
+            limit
+        )));
+    }
+    Ok(())
+}
+
+/// Count `samples` of transcribed 16kHz audio against the caller's usage.
+/// On a cluster worker, the usage is reported to the coordinator, which
+/// authenticated the caller. Does nothing when API keys are disabled.
+pub fn charge(tenant: Option<&Tenant>, samples: usize) {
+    let Some(tenant) = tenant else {
+        return;
+    };
+    let audio_ms = samples as u64 * 1000 / SAMPLE_RATE;
+    if cluster::is_worker() {
+        cluster::report_usage(&tenant.key_id, audio_ms);
+    } else {
+        record_usage(&tenant.key_id, audio_ms);
+    }
+}
+
+/// Add `audio_ms` of transcribed audio to key `key_id`'s usage today. Does
+/// nothing when API keys are disabled.
+pub fn record_usage(key_id: &str, audio_ms: u64) {
+    let Some(conn) = db() else {
+        return;
+    };
+    if let Err(e) = add_usage(&conn, key_id, &today(), audio_ms) {
+        error!(key_id = %key_id, "Failed to record usage: {}", e);
+    }
+}
+
+/// A `/stream` connection counted against its key's concurrency quota,
+/// released on drop.
+#[derive(Debug)]
+pub struct StreamSlot {
+    key_id: String,
+}
+
+/// Claim a `/stream` slot for `tenant`, failing with `429` at its limit.
+pub fn open_stream(tenant: &Tenant) -> Result<StreamSlot, ApiError> {
+    let mut streams = active_streams();
+    let active = streams.entry(tenant.key_id.clone()).or_default();
+    if let Some(max) = tenant.quota.max_concurrent_streams {
+        if *active >= max {
+            return Err(ApiError::TooManyStreams(format!(
+                "Limit of {} concurrent streams reached",
+                max
+            )));
+        }
+    }
+    *active += 1;
+    Ok(StreamSlot {
+        key_id: tenant.key_id.clone(),
+    })
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        let mut streams = active_streams();
+        if let Some(active) = streams.get_mut(&self.key_id) {
+            *active = active.saturating_sub(1);
+            if *active == 0 {
+                streams.remove(&self.key_id);
+            }
+        }
+    }
+}
+
+/// Authentication middleware.
+///
+/// Rejects requests without a valid key or JWT (`401`) and transcription requests
+/// from tenants over their daily audio quota (`402`), then makes the
+/// [`Tenant`] available to handlers as an extension.
+pub async fn authenticate(mut request: Request, next: Next) -> Result<Response, ApiError> {
+    // Request bodies aren't `Sync`, so don't hold the request across awaits
+    let key = api_key(&request);
+    let transcribes = transcribes(request.method(), request.uri().path());
+    let tenant = if cluster::from_coordinator(request.headers()) {
+        // Authenticated and quota-checked by the coordinator, on behalf of
+        // the client it names
+        if let Some(client) = cluster::forwarded_client(request.headers()) {
+            if let Some(entry) = request.extensions().get::<access_log::Entry>() {
+                entry.set_client_ip(client.ip());
+            }
+            request.extensions_mut().insert(ConnectInfo(client));
+        }
+        cluster::forwarded_tenant(request.headers())
+    } else {
+        authorize(key, transcribes).await?
+    };
+    if let Some(tenant) = tenant {
+        if let Some(entry) = request.extensions().get::<access_log::Entry>() {
+            entry.set_tenant(&tenant);
+        }
+        request.extensions_mut().insert(tenant);
+    }
+    Ok(next.run(request).await)
+}
+
+/// Check a request's API key or JWT, and its quota if it `transcribes`;
+/// `None` if authentication is disabled or `key` is the admin token.
+async fn authorize(key: Option<String>, transcribes: bool) -> Result<Option<Tenant>, ApiError> {
+    if DB.get().is_none() && !jwt::enabled() {
+        return Ok(None);
+    }
+
+    let key = key.ok_or_else(|| ApiError::Unauthorized("Missing API key".to_string()))?;
+    if admin::is_admin_token(&key) {
+        return Ok(None);
+    }
+    let tenant = if jwt::enabled() && jwt::looks_like_jwt(&key) {
+        let claims = jwt::verify(&key).await?;
+        Tenant {
+            key_id: format!("jwt:{}", claims.sub),
+            tenant: claims.sub,
+            quota: Quota::default(),
+        }
+    } else {
+        let conn = db().ok_or_else(|| ApiError::Unauthorized("Invalid token".to_string()))?;
+        lookup(&conn, &key)
+            .map_err(|e| ApiError::Internal(format!("Failed to look up API key: {}", e)))?
+            .ok_or_else(|| ApiError::Unauthorized("Invalid or revoked API key".to_string()))?
+    };
+    if transcribes {
+        if let Some(conn) = db() {
+            check_audio_quota(&conn, &tenant)?;
+        }
+    }
+    Ok(Some(tenant))
+}
+
+/// The API key sent with a request, if any.
+fn api_key(request: &Request) -> Option<String> {
+    let headers = request.headers();
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        // `Token` is Deepgram's scheme, for clients of `/listen`
+        .and_then(|v| v.strip_prefix("Bearer ").or_else(|| v.strip_prefix("Token ")));
+    let header_key = headers.get("x-api-key").and_then(|v| v.to_str().ok());
+    let query_key = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("api_key="))
+    });
+    bearer
+        .or(header_key)
+        .or(query_key)
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+}
+
+/// Whether a request transcribes audio (and so counts against the quota).
+fn transcribes(method: &Method, path: &str) -> bool {
+    match *method {
+        Method::POST => {
+            path.starts_with("/transcribe")
+                || path == "/jobs"
+                || path == "/compare"
+                || (path.starts_with("/uploads/") && path.ends_with("/complete"))
+        }
+        Method::GET => path == "/stream" || path == "/listen",
+        _ => false,
+    }
+}
+
+/// Current UTC date.
+fn today() -> String {
+    utc_date(now_secs())
+}
+
+/// UTC date (`YYYY-MM-DD`) of a Unix timestamp.
+pub fn utc_date(unix_secs: u64) -> String {
+    // Civil-from-days (Howard Hinnant), for days since 1970-01-01
+    let z = (unix_secs / 86400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Usage endpoint (`GET /usage`) for the calling key.
+#[utoipa::path(
+    get,
+    path = "/usage",
+    tag = "usage",
+    responses(
+        (status = 200, description = "Usage and quota of the calling key", body = UsageResponse),
+        (status = 401, description = "Missing or invalid API key, or API keys are not enabled", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn get_usage(
+    tenant: Option<Extension<Tenant>>,
+) -> Result<Json<UsageResponse>, ApiError> {
+    let Some(Extension(tenant)) = tenant else {
+        return Err(ApiError::Unauthorized(
+            "API keys are not enabled on this server".to_string(),
+        ));
+    };
+    let conn = db().ok_or_else(|| ApiError::Internal("Tenant database unavailable".to_string()))?;
+    let internal = |e: rusqlite::Error| ApiError::Internal(format!("Failed to read usage: {}", e));
+
+    let today = day_usage(&conn, &tenant.key_id, &today()).map_err(internal)?;
+    let mut statement = conn
+        .prepare(
+            "SELECT day, audio_ms, requests FROM usage
+             WHERE key_id = ?1 AND day < ?2 ORDER BY day DESC LIMIT ?3",
+        )
+        .map_err(internal)?;
+    let history = statement
+        .query_map(params![tenant.key_id, today.day, USAGE_HISTORY_DAYS], |row| {
+            Ok(DayUsage {
+                day: row.get(0)?,
+                audio_seconds: row.get::<_, u64>(1)? as f64 / 1000.0,
+                requests: row.get(2)?,
+            })
+        })
+        .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+        .map_err(internal)?;
+
+    Ok(Json(UsageResponse {
+        remaining_audio_seconds: tenant
+            .quota
+            .audio_seconds_per_day
+            .map(|limit| (limit as f64 - today.audio_seconds).max(0.0)),
+        active_streams: active_streams().get(&tenant.key_id).copied().unwrap_or(0),
+        tenant: tenant.tenant,
+        key_id: tenant.key_id,
+        quota: tenant.quota,
+        today,
+        history,
+    }))
+}
+
+const USAGE: &str = "Usage: voicemark-sidecar create-key --tenant NAME \
+                     [--audio-seconds-per-day N] [--max-streams N]";
+
+/// Run the `create-key` subcommand, printing the new key.
+pub fn run_create_key(args: impl IntoIterator<Item = String>) -> anyhow::Result<()> {
+    let mut tenant = None;
+    let mut quota = Quota::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{} needs a value\n{}", arg, USAGE));
+        match arg.as_str() {
+            "--tenant" => tenant = Some(value()?),
+            "--audio-seconds-per-day" => {
+                quota.audio_seconds_per_day = Some(value()?.parse().context("Invalid --audio-seconds-per-day")?)
+            }
+            "--max-streams" => {
+                quota.max_concurrent_streams = Some(value()?.parse().context("Invalid --max-streams")?)
+            }
+            _ => bail!("Unknown argument '{}'\n{}", arg, USAGE),
+        }
+    }
+    let tenant = tenant.with_context(|| format!("--tenant is required\n{}", USAGE))?;
+    let path = std::env::var("VOICEMARK_TENANTS_DB").context("VOICEMARK_TENANTS_DB is not set")?;
+
+    let conn = open(Path::new(&path))?;
+    let (id, key) = create_key(&conn, &tenant, &quota)?;
+    println!("Created key {} for tenant '{}':\n{}", id, tenant, key);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_create_and_look_up_key() {
+        let conn = memory_db();
+        let quota = Quota {
+            audio_seconds_per_day: Some(60),
+            max_concurrent_streams: Some(2),
+        };
+        let (id, key) = create_key(&conn, "acme", &quota).unwrap();
+
+        let tenant = lookup(&conn, &key).unwrap().unwrap();
+        assert_eq!(tenant.key_id, id);
+        assert_eq!(tenant.tenant, "acme");
+        assert_eq!(tenant.quota, quota);
+        assert!(lookup(&conn, "vm_wrong").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_revoke_and_update_keys() {
+        let conn = memory_db();
+        let (id, key) = create_key(&conn, "acme", &Quota::default()).unwrap();
+
+        let quota = Quota {
+            audio_seconds_per_day: Some(120),
+            max_concurrent_streams: None,
+        };
+        assert!(set_quota(&conn, &id, &quota).unwrap());
+        assert_eq!(lookup(&conn, &key).unwrap().unwrap().quota, quota);
+
+        assert!(revoke_key(&conn, &id).unwrap());
+        assert!(lookup(&conn, &key).unwrap().is_none());
+        assert!(get_key(&conn, &id).unwrap().unwrap().revoked_at.is_some());
+        assert_eq!(list_keys(&conn).unwrap().len(), 1);
+
+        assert!(!revoke_key(&conn, "missing").unwrap());
+        assert!(!set_quota(&conn, "missing", &quota).unwrap());
+    }
+
+    #[test]
+    fn test_audio_quota() {
+        let conn = memory_db();
+        let quota = Quota {
+            audio_seconds_per_day: Some(10),
+            max_concurrent_streams: None,
+        };
+        let (_, key) = create_key(&conn, "acme", &quota).unwrap();
+        let tenant = lookup(&conn, &key).unwrap().unwrap();
+
+        add_usage(&conn, &tenant.key_id, &today(), 9_500).unwrap();
+        assert!(check_audio_quota(&conn, &tenant).is_ok());
+        add_usage(&conn, &tenant.key_id, &today(), 500).unwrap();
+        let err = check_audio_quota(&conn, &tenant).unwrap_err();
+        assert_eq!(err.code(), "quota_exceeded");
+
+        let usage = day_usage(&conn, &tenant.key_id, &today()).unwrap();
+        assert_eq!(usage.audio_seconds, 10.0);
+        assert_eq!(usage.requests, 2);
+    }
+
+    #[test]
+    fn test_stream_slots_are_released() {
+        let tenant = Tenant {
+            key_id: uuid::Uuid::new_v4().to_string(),
+            tenant: "acme".to_string(),
+            quota: Quota {
+                audio_seconds_per_day: None,
+                max_concurrent_streams: Some(1),
+            },
+        };
+        let slot = open_stream(&tenant).unwrap();
+        assert_eq!(open_stream(&tenant).unwrap_err().code(), "too_many_streams");
+        drop(slot);
+        assert!(open_stream(&tenant).is_ok());
+    }
+
+    #[test]
+    fn test_can_access() {
+        let tenant = |name: &str| Tenant {
+            key_id: format!("k-{}", name),
+            tenant: name.to_string(),
+            quota: Quota::default(),
+        };
+        assert!(can_access(Some(&tenant("acme")), Some("acme")));
+        assert!(!can_access(Some(&tenant("globex")), Some("acme")));
+        assert!(!can_access(Some(&tenant("acme")), None));
+        // Without authentication, and for the admin, there is no tenant
+        assert!(can_access(None, Some("acme")));
+        assert!(can_access(None, None));
+    }
+
+    #[test]
+    fn test_utc_date() {
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(951_782_400), "2000-02-29");
+        assert_eq!(utc_date(1_767_225_599), "2025-12-31");
+    }
+
+    #[test]
+    fn test_transcribes() {
+        assert!(transcribes(&Method::POST, "/transcribe/json"));
+        assert!(transcribes(&Method::GET, "/stream"));
+        assert!(transcribes(&Method::GET, "/listen"));
+        assert!(transcribes(&Method::POST, "/uploads/u-1/complete"));
+        assert!(transcribes(&Method::POST, "/compare"));
+        assert!(!transcribes(&Method::PATCH, "/uploads/u-1"));
+        assert!(!transcribes(&Method::GET, "/jobs"));
+        assert!(!transcribes(&Method::POST, "/inspect"));
+    }
+}
@@ -0,0 +1,174 @@
+//! HTTPS and mutual TLS for VoiceMark sidecar.
+//!
+//! With `VOICEMARK_TLS_CERT` and `VOICEMARK_TLS_KEY` set, every listener
+//! speaks HTTPS (HTTP/1.1, including WebSocket upgrades) instead of plain
+//! HTTP. `VOICEMARK_TLS_CLIENT_CA` additionally requires clients to present a
+//! certificate issued by a CA in that PEM bundle, so only provisioned devices
+//! can connect: anything else fails the handshake before a request is read.
+
+use anyhow::{Context, Result, bail};
+use axum::Router;
+use axum::extract::ConnectInfo;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioIo;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tower::ServiceExt;
+use tracing::{debug, info};
+
+/// Time a client gets to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Certificate files for the HTTPS listener.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first.
+    pub cert: PathBuf,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1).
+    pub key: PathBuf,
+    /// PEM bundle of CAs whose client certificates are accepted; `None`
+    /// doesn't ask for client certificates.
+    pub client_ca: Option<PathBuf>,
+}
+
+/// Build the TLS acceptor shared by all listeners.
+pub fn acceptor(config: &TlsConfig) -> Result<TlsAcceptor> {
+    let provider = Arc::new(ring::default_provider());
+    let certs = read_certs(&config.cert)?;
+    let key = PrivateKeyDer::from_pem_file(&config.key)
+        .with_context(|| format!("Failed to read private key {:?}", config.key))?;
+
+    let verifier = match &config.client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(path)? {
+                roots.add(cert).with_context(|| format!("Invalid CA certificate in {:?}", path))?;
+            }
+            info!(client_ca = ?path, cas = roots.len(), "Client certificates required");
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .context("Invalid client CA bundle")?
+        }
+        None => WebPkiClientVerifier::no_client_auth(),
+    };
+    let mut server = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .context("Failed to set up TLS")?
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(certs, key)
+        .context("The TLS certificate doesn't match its key")?;
+    server.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(server)))
+}
+
+/// All certificates in a PEM file, failing if there are none.
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read certificates from {:?}", path))?;
+    if certs.is_empty() {
+        bail!("No certificates in {:?}", path);
+    }
+    Ok(certs)
+}
+
+/// Serve `app` over TLS on `listener` until `stop` changes, then wait for
+/// open connections to finish.
+pub async fn serve(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    app: Router,
+    mut stop: watch::Receiver<()>,
+) -> std::io::Result<()> {
+    let mut connections = JoinSet::new();
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Usually out of file descriptors; don't spin
+                    debug!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            _ = stop.changed() => break,
+        };
+        while connections.try_join_next().is_some() {}
+
+        let (acceptor, app, stop) = (acceptor.clone(), app.clone(), stop.clone());
+        connections.spawn(async move {
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => serve_connection(stream, peer, app, stop).await,
+                Ok(Err(e)) => debug!(%peer, "TLS handshake failed: {}", e),
+                Err(_) => debug!(%peer, "TLS handshake timed out"),
+            }
+        });
+    }
+    while connections.join_next().await.is_some() {}
+    Ok(())
+}
+
+/// Serve HTTP/1.1 on one TLS connection, closing it gracefully on `stop`.
+async fn serve_connection(
+    stream: tokio_rustls::server::TlsStream<TcpStream>,
+    peer: SocketAddr,
+    app: Router,
+    mut stop: watch::Receiver<()>,
+) {
+    let service = hyper::service::service_fn(move |mut request: hyper::Request<Incoming>| {
+        // Same as `into_make_service_with_connect_info` on plain listeners
+        request.extensions_mut().insert(ConnectInfo(peer));
+        app.clone().oneshot(request)
+    });
+    let connection = http1::Builder::new()
+        .serve_connection(TokioIo::new(stream), service)
+        .with_upgrades();
+    tokio::pin!(connection);
+
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = stop.changed() => {
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    };
+    if let Err(e) = result {
+        debug!(%peer, "Connection error: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_missing_or_empty_certificates_are_rejected() {
+        let mut empty = tempfile::NamedTempFile::new().unwrap();
+        writeln!(empty, "not a certificate").unwrap();
+        let config = TlsConfig {
+            cert: empty.path().to_path_buf(),
+            key: empty.path().to_path_buf(),
+            client_ca: None,
+        };
+        let Err(e) = acceptor(&config) else { panic!("accepted a bad certificate") };
+        assert!(format!("{:#}", e).contains("No certificates"), "{:#}", e);
+
+        let config = TlsConfig { cert: PathBuf::from("/nonexistent/cert.pem"), ..config };
+        let Err(e) = acceptor(&config) else { panic!("accepted a bad certificate") };
+        assert!(format!("{:#}", e).contains("Failed to read certificates"), "{:#}", e);
+    }
+}
@@ -0,0 +1,2081 @@
+//! Persisted transcripts and retained audio for VoiceMark sidecar.
+//!
+//! When a data directory is configured, every batch transcription is saved
+//! as `<data_dir>/transcripts/<id>.json`. With audio retention enabled, the
+//! uploaded audio is kept next to it (`<audio_dir>/<id>.<ext>`) so recordings
+//! can be audited or re-transcribed with a future model. Transcripts and
+//! retained audio are pruned by a background task according to their own
+//! max age, count and disk budget, and `DELETE /transcripts/:id` removes a
+//! transcript with everything stored for it, including its cached result.
+//! With at-rest encryption (see [`crate::encryption`]), transcripts and
+//! retained audio are stored encrypted.
+//!
+//! Each transcript belongs to the tenant whose request produced it (see
+//! [`crate::tenants`]); other tenants don't see it listed or searched, and
+//! get `404` for it.
+//!
+//! Reviewers can correct individual segments (`PATCH /transcripts/:id`); the
+//! machine output is kept alongside each correction.
+//!
+//! `GET /transcripts` lists stored transcripts, newest first, filtered by
+//! job tags (`?tag=standup`) and metadata (`?metadata.meeting_id=m-42`).
+//!
+//! `POST /transcripts/:id/summarize` has the LLM (see [`crate::llm`])
+//! summarize a transcript; the summary is stored with it.
+//!
+//! `GET /transcripts/:id/export?format=docx|pdf` renders a transcript as a
+//! document (see [`crate::export`]), with the chapters of jobs run with
+//! `chapters=true` as headings and the channels of `channels=split`
+//! transcripts as speakers.
+//!
+//! With segment embeddings enabled (see [`crate::embeddings`]), each
+//! transcript's segments are embedded in the background once saved (and
+//! again after corrections) into `<data_dir>/embeddings/<id>.json`, and
+//! `GET /transcripts/semantic-search?q=...` ranks segments by similarity.
+//!
+//! Jobs also store the fingerprint of their audio (see
+//! [`voicemark_core::fingerprint`]) in `<data_dir>/fingerprints/<id>`, so
+//! a recording submitted again by the same tenant with the same options and
+//! model is answered with its existing transcript (see [`find_duplicate`]).
+//! The fingerprints are read once at startup and kept in memory.
+//!
+//! Long jobs and streaming sessions are checkpointed as they go (see
+//! [`Checkpoint`]): every autosave interval, the segments transcribed so
+//! far are saved as a transcript marked `partial`, which is removed once the
+//! transcription finishes. A crash mid-way leaves that partial transcript
+//! behind instead of nothing.
+
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::{Path, Query, rejection::{JsonRejection, QueryRejection}},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+use utoipa::{IntoParams, ToSchema};
+use voicemark_core::fingerprint::Fingerprint;
+
+use crate::audio;
+use crate::cache;
+use crate::clock::{now_millis, unix_millis};
+use crate::embeddings;
+use crate::encryption::{self, Kind};
+use crate::error::{ApiError, Problem};
+use crate::export::{self, Document, ExportFormat, Section, Turn};
+use crate::jobs::JobMetadata;
+use crate::llm::{self, Chapter};
+use crate::negotiate::{self, Accept, Cue};
+use crate::remote;
+use crate::tenants::{self, Tenant};
+use crate::upload::AudioFile;
+use crate::transcribe::{ChannelTranscript, Segment, TranscribeOptions, TranscribeResult};
+use crate::vocabulary;
+use voicemark_core::formatting::PARAGRAPH_PAUSE_MS;
+
+/// Default and maximum number of transcripts returned by `GET /transcripts`.
+const DEFAULT_LIST_LIMIT: usize = 50;
+const MAX_LIST_LIMIT: usize = 500;
+
+/// Default and maximum number of segments returned by a semantic search.
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+const MAX_SEARCH_LIMIT: usize = 100;
+
+/// How often stored data is checked against the retention policies.
+pub const RETENTION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Retention policy for uploaded audio.
+#[derive(Debug, Clone, Default)]
+pub struct AudioRetention {
+    /// Directory for retained audio.
+    pub dir: PathBuf,
+    /// Delete audio older than this; `None` keeps it forever.
+    pub max_age: Option<Duration>,
+    /// Delete the oldest audio once the directory exceeds this many bytes;
+    /// `None` means unlimited.
+    pub max_bytes: Option<u64>,
+    /// Delete the oldest audio once there are more recordings than this;
+    /// `None` means unlimited.
+    pub max_count: Option<usize>,
+}
+
+impl AudioRetention {
+    /// Whether anything is ever pruned.
+    pub fn is_bounded(&self) -> bool {
+        self.max_age.is_some() || self.max_bytes.is_some() || self.max_count.is_some()
+    }
+}
+
+/// Retention policy for stored transcripts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TranscriptRetention {
+    /// Delete transcripts created longer ago than this; `None` keeps them
+    /// forever.
+    pub max_age: Option<Duration>,
+    /// Delete the oldest transcripts once they, with their embeddings and
+    /// fingerprints, take more than this many bytes; `None` means unlimited.
+    pub max_bytes: Option<u64>,
+    /// Delete the oldest transcripts once there are more than this; `None`
+    /// means unlimited.
+    pub max_count: Option<usize>,
+}
+
+impl TranscriptRetention {
+    /// Whether anything is ever pruned.
+    pub fn is_bounded(&self) -> bool {
+        self.max_age.is_some() || self.max_bytes.is_some() || self.max_count.is_some()
+    }
+}
+
+/// Transcript store configuration.
+struct Store {
+    transcripts_dir: PathBuf,
+    /// Segment embeddings, one file per transcript.
+    embeddings_dir: PathBuf,
+    /// Audio fingerprints, one file per job transcript.
+    fingerprints_dir: PathBuf,
+    /// The stored fingerprints by transcript ID, so submissions aren't
+    /// compared against `fingerprints_dir` file by file.
+    fingerprints: Mutex<HashMap<String, Fingerprint>>,
+    audio: Option<AudioRetention>,
+    retention: TranscriptRetention,
+    /// How often long transcriptions are checkpointed; `None` disables it.
+    autosave: Option<Duration>,
+}
+
+/// Global store, unset when persistence is disabled.
+static STORE: OnceLock<Store> = OnceLock::new();
+
+/// Serializes read-modify-write updates of transcript files.
+static UPDATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// A persisted transcript (`GET /transcripts/:id`).
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Transcript {
+    pub id: String,
+    /// Creation time (Unix milliseconds).
+    pub created_at: u64,
+    /// Machine transcription.
+    pub text: String,
+    pub segments: usize,
+    /// `text` with reviewer corrections applied; absent until corrected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corrected_text: Option<String>,
+    /// Timed segments, with any corrections.
+    #[serde(default)]
+    pub segment_list: Vec<TranscriptSegment>,
+    /// Last correction time (Unix milliseconds).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<u64>,
+    /// Model file the transcript was produced with.
+    #[serde(default)]
+    pub model: Option<String>,
+    pub options: TranscribeOptions,
+    /// File name of the retained audio, if any. The file may since have
+    /// been removed by the retention policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<String>,
+    /// Metadata and tags of the job that produced the transcript.
+    #[serde(flatten)]
+    pub metadata: JobMetadata,
+    /// Latest summary, from `POST /transcripts/:id/summarize`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<TranscriptSummary>,
+    /// Titled chapters, for jobs submitted with `chapters=true`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chapters: Vec<Chapter>,
+    /// Checkpoint of an unfinished job or stream, left behind by a crash.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+    /// Key of the transcription's entry in the result cache (see
+    /// [`crate::cache`]), removed with the transcript.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_key: Option<String>,
+    /// Tenant the transcript belongs to; absent without authentication.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+/// An LLM-written summary of a transcript.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TranscriptSummary {
+    pub summary: String,
+    /// Follow-up tasks, if requested with `action_items=true`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub action_items: Vec<String>,
+    /// Creation time (Unix milliseconds).
+    pub created_at: u64,
+    /// Whether the summary was written from the corrected text.
+    #[serde(default)]
+    pub corrected: bool,
+}
+
+/// Query parameters of `GET /transcripts/:id/export`.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// `docx` or `pdf`.
+    pub format: ExportFormat,
+    /// Comma-separated speaker names, in order (`Alice,Bob` names speakers
+    /// 0 and 1); others are "Speaker 1", "Speaker 2", ...
+    #[serde(default)]
+    pub speakers: Option<String>,
+}
+
+/// Query parameters of `POST /transcripts/:id/summarize`.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SummarizeQuery {
+    /// Also list the action items agreed on.
+    #[serde(default)]
+    pub action_items: bool,
+}
+
+/// A transcript segment and its correction, if any.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TranscriptSegment {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    /// Machine output for this segment.
+    pub text: String,
+    /// Reviewer correction.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corrected: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edit: Option<EditInfo>,
+    /// Speaker, numbered from 0: the channel, for `channels=split`
+    /// transcripts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<u32>,
+}
+
+impl TranscriptSegment {
+    /// The corrected text, or the machine output if uncorrected.
+    pub fn effective_text(&self) -> &str {
+        self.corrected.as_deref().unwrap_or(&self.text)
+    }
+}
+
+/// Who corrected a segment, and when.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct EditInfo {
+    /// Correction time (Unix milliseconds).
+    pub edited_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub editor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Body of `PATCH /transcripts/:id`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CorrectionRequest {
+    /// Applied to every edit that doesn't name its own editor.
+    #[serde(default)]
+    pub editor: Option<String>,
+    pub edits: Vec<SegmentEdit>,
+}
+
+/// Response of `GET /transcripts`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TranscriptList {
+    /// Matching transcripts, newest first.
+    pub transcripts: Vec<Transcript>,
+}
+
+/// Filter of `GET /transcripts`; all conditions must match.
+#[derive(Debug, Default, PartialEq)]
+struct TranscriptFilter {
+    /// Tags the transcript must carry.
+    tags: Vec<String>,
+    /// `(key, value)` pairs the top-level metadata must contain.
+    metadata: Vec<(String, String)>,
+    /// Tenant the transcript must belong to; `None` matches any.
+    tenant: Option<String>,
+    limit: usize,
+}
+
+impl TranscriptFilter {
+    /// Parse `tag`, `metadata.<key>` and `limit` query parameters; other
+    /// parameters (such as `api_key`) are ignored.
+    fn from_query(params: Vec<(String, String)>) -> Result<Self, ApiError> {
+        let mut filter = Self { limit: DEFAULT_LIST_LIMIT, ..Self::default() };
+        for (key, value) in params {
+            if key == "tag" {
+                filter.tags.push(value);
+            } else if key == "limit" {
+                filter.limit = value
+                    .parse()
+                    .ok()
+                    .filter(|limit| (1..=MAX_LIST_LIMIT).contains(limit))
+                    .ok_or_else(|| {
+                        ApiError::InvalidRequest(format!(
+                            "limit must be between 1 and {}",
+                            MAX_LIST_LIMIT
+                        ))
+                    })?;
+            } else if let Some(field) = key.strip_prefix("metadata.") {
+                filter.metadata.push((field.to_string(), value));
+            }
+        }
+        Ok(filter)
+    }
+
+    fn matches(&self, transcript: &Transcript) -> bool {
+        let metadata = &transcript.metadata;
+        self.tags.iter().all(|tag| metadata.tags.contains(tag))
+            && self.metadata.iter().all(|(key, expected)| metadata.has(key, expected))
+            && self.tenant.as_ref().is_none_or(|tenant| transcript.tenant.as_ref() == Some(tenant))
+    }
+}
+
+/// Query parameters of `GET /transcripts/semantic-search`.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SemanticSearchQuery {
+    /// What to look for, in any wording.
+    pub q: String,
+    /// Maximum number of segments (default 10, max 100).
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Response of `GET /transcripts/semantic-search`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SemanticSearchResults {
+    /// Matching segments, most similar first.
+    pub results: Vec<SearchHit>,
+}
+
+/// A segment matching a semantic search.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SearchHit {
+    pub transcript_id: String,
+    /// Index into the transcript's `segment_list`.
+    pub segment: usize,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    /// Segment text, with any correction.
+    pub text: String,
+    /// Cosine similarity to the query (-1 to 1).
+    pub score: f32,
+}
+
+/// Stored segment embeddings of a transcript.
+#[derive(Debug, Serialize, Deserialize)]
+struct SegmentVectors {
+    /// Embedding model; vectors of other models are ignored by searches.
+    model: String,
+    /// One unit vector per segment of `segment_list`.
+    vectors: Vec<Vec<f32>>,
+}
+
+/// A correction of one segment.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SegmentEdit {
+    /// Index into `segment_list`.
+    pub index: usize,
+    /// Corrected text. Sending the machine output reverts the correction.
+    pub text: String,
+    #[serde(default)]
+    pub editor: Option<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// Enable persistence under `data_dir`, optionally retaining audio and
+/// checkpointing long transcriptions every `autosave`. Call once at startup.
+pub fn configure(
+    data_dir: PathBuf,
+    audio: Option<AudioRetention>,
+    retention: TranscriptRetention,
+    autosave: Option<Duration>,
+) -> anyhow::Result<()> {
+    let transcripts_dir = data_dir.join("transcripts");
+    std::fs::create_dir_all(&transcripts_dir)?;
+    let embeddings_dir = data_dir.join("embeddings");
+    std::fs::create_dir_all(&embeddings_dir)?;
+    let fingerprints_dir = data_dir.join("fingerprints");
+    std::fs::create_dir_all(&fingerprints_dir)?;
+    if let Some(retention) = &audio {
+        std::fs::create_dir_all(&retention.dir)?;
+        info!(dir = ?retention.dir, "Audio retention enabled");
+    }
+    info!(dir = ?transcripts_dir, autosave = ?autosave, "Transcript persistence enabled");
+    if retention.is_bounded() {
+        info!(?retention, "Transcript retention enabled");
+    }
+
+    let fingerprints = Mutex::new(load_fingerprints(&fingerprints_dir));
+    let store = Store {
+        transcripts_dir,
+        embeddings_dir,
+        fingerprints_dir,
+        fingerprints,
+        audio,
+        retention,
+        autosave,
+    };
+    if STORE.set(store).is_err() {
+        warn!("Transcript store already configured");
+    }
+    Ok(())
+}
+
+/// The fingerprints stored in `dir`, by transcript ID. Unreadable ones are
+/// skipped.
+fn load_fingerprints(dir: &std::path::Path) -> HashMap<String, Fingerprint> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to list audio fingerprints: {}", e);
+            return HashMap::new();
+        }
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let id = entry.file_name().into_string().ok()?;
+            let fingerprint = std::fs::read_to_string(entry.path()).ok()?.parse().ok()?;
+            Some((id, fingerprint))
+        })
+        .collect()
+}
+
+/// Persist a transcription for `tenant`, (if enabled) its audio, and the
+/// `fingerprint` of the audio if given and long enough to be matched.
+///
+/// Returns the transcript ID, or `None` if persistence is disabled or the
+/// write failed; failures are logged rather than failing the request.
+pub fn record(
+    audio_bytes: &[u8],
+    options: &TranscribeOptions,
+    result: &TranscribeResult,
+    metadata: &JobMetadata,
+    fingerprint: Option<&Fingerprint>,
+    tenant: Option<&str>,
+) -> Option<String> {
+    let store = STORE.get()?;
+    let id = uuid::Uuid::new_v4().to_string();
+
+    let audio = store.audio.as_ref().and_then(|retention| {
+        let name = format!("{}.{}", id, audio::sniff_extension(audio_bytes));
+        match encryption::write(retention.dir.join(&name), Kind::Audio, audio_bytes) {
+            Ok(()) => Some(name),
+            Err(e) => {
+                warn!("Failed to retain audio: {}", e);
+                None
+            }
+        }
+    });
+
+    let transcript = Transcript {
+        id: id.clone(),
+        created_at: now_millis(),
+        text: result.text.clone(),
+        segments: result.segments,
+        corrected_text: None,
+        segment_list: segment_list(&result.timed_segments, &result.channels),
+        updated_at: None,
+        model: remote::model_id(),
+        options: options.clone(),
+        audio,
+        metadata: metadata.clone(),
+        summary: None,
+        chapters: Vec::new(),
+        partial: false,
+        cache_key: Some(cache::cache_key(audio_bytes, options)),
+        tenant: tenant.map(str::to_string),
+    };
+
+    if let Err(e) = save(store, &transcript) {
+        warn!(id = %id, "Failed to persist transcript: {}", e);
+        return None;
+    }
+    if let Some(fingerprint) = fingerprint.filter(|f| f.is_distinctive()) {
+        let path = store.fingerprints_dir.join(&id);
+        if let Err(e) = std::fs::write(path, fingerprint.to_string()) {
+            warn!(id = %id, "Failed to store audio fingerprint: {}", e);
+        }
+        store.fingerprints.lock().unwrap().insert(id.clone(), fingerprint.clone());
+    }
+    index_segments(&transcript);
+    Some(id)
+}
+
+/// `segments` as stored, each with the channel it was heard on for
+/// `channels=split` results.
+fn segment_list(segments: &[Segment], channels: &[ChannelTranscript]) -> Vec<TranscriptSegment> {
+    segments
+        .iter()
+        .map(|segment| TranscriptSegment {
+            start_ms: segment.start_ms,
+            end_ms: segment.end_ms,
+            text: segment.text.clone(),
+            corrected: None,
+            edit: None,
+            speaker: speaker(channels, segment),
+        })
+        .collect()
+}
+
+/// The channel `segment` of a `channels=split` result was heard on.
+fn speaker(channels: &[ChannelTranscript], segment: &Segment) -> Option<u32> {
+    if channels.len() < 2 {
+        return None;
+    }
+    channels
+        .iter()
+        .find(|channel| channel.segments.contains(segment))
+        .map(|channel| channel.channel)
+}
+
+/// Store the chapters a job's transcript was split into. Failures are
+/// logged.
+pub fn set_chapters(id: &str, chapters: &[Chapter]) {
+    let Some(store) = STORE.get() else {
+        return;
+    };
+    let _guard = UPDATE_LOCK.lock().unwrap();
+    let Some(mut transcript) = get(id) else {
+        return;
+    };
+    transcript.chapters = chapters.to_vec();
+    if let Err(e) = save(store, &transcript) {
+        warn!(id = %id, "Failed to store chapters: {}", e);
+    }
+}
+
+/// The newest stored transcript of `tenant`'s recording with
+/// `fingerprint`, made with the same `options` and the current model, if
+/// any.
+pub fn find_duplicate(
+    fingerprint: &Fingerprint,
+    options: &TranscribeOptions,
+    tenant: Option<&str>,
+) -> Option<Transcript> {
+    duplicate(STORE.get()?, fingerprint, options, remote::model_id(), tenant)
+}
+
+fn duplicate(
+    store: &Store,
+    fingerprint: &Fingerprint,
+    options: &TranscribeOptions,
+    model: Option<String>,
+    tenant: Option<&str>,
+) -> Option<Transcript> {
+    if !fingerprint.is_distinctive() {
+        return None;
+    }
+    let ids: Vec<String> = store
+        .fingerprints
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, stored)| stored.matches(fingerprint))
+        .map(|(id, _)| id.clone())
+        .collect();
+    let options = serde_json::to_value(options).ok()?;
+    ids.iter()
+        // Transcripts may since have been deleted
+        .filter_map(|id| load(store, id))
+        .filter(|transcript| {
+            !transcript.partial
+                && transcript.tenant.as_deref() == tenant
+                && transcript.model == model
+                && serde_json::to_value(&transcript.options).ok().as_ref() == Some(&options)
+        })
+        .max_by_key(|transcript| transcript.created_at)
+}
+
+/// A partial transcript of a long job or streaming session, saved every
+/// autosave interval until the transcription finishes.
+pub struct Checkpoint {
+    transcript: Transcript,
+    saved_at: Instant,
+}
+
+impl Checkpoint {
+    /// Start checkpointing a transcription for `tenant`. `None` if
+    /// persistence or autosave is disabled.
+    pub fn start(
+        options: &TranscribeOptions,
+        metadata: &JobMetadata,
+        tenant: Option<&str>,
+    ) -> Option<Self> {
+        STORE.get()?.autosave?;
+        let transcript = Transcript {
+            id: uuid::Uuid::new_v4().to_string(),
+            created_at: now_millis(),
+            text: String::new(),
+            segments: 0,
+            corrected_text: None,
+            segment_list: Vec::new(),
+            updated_at: None,
+            model: remote::model_id(),
+            options: options.clone(),
+            audio: None,
+            metadata: metadata.clone(),
+            summary: None,
+            chapters: Vec::new(),
+            partial: true,
+            cache_key: None,
+            tenant: tenant.map(str::to_string),
+        };
+        Some(Self { transcript, saved_at: Instant::now() })
+    }
+
+    /// Save the segments transcribed so far if the autosave interval has
+    /// passed since the last save. `segments` is only called then.
+    pub fn autosave(&mut self, segments: impl FnOnce() -> Vec<Segment>) {
+        let Some(store) = STORE.get() else {
+            return;
+        };
+        if self.due(store, Instant::now()) {
+            self.save(store, segments());
+        }
+    }
+
+    fn due(&self, store: &Store, now: Instant) -> bool {
+        store.autosave.is_some_and(|interval| now.duration_since(self.saved_at) >= interval)
+    }
+
+    fn save(&mut self, store: &Store, segments: Vec<Segment>) {
+        self.saved_at = Instant::now();
+        let transcript = &mut self.transcript;
+        let texts: Vec<&str> = segments.iter().map(|segment| segment.text.trim()).collect();
+        transcript.text = texts.join(" ");
+        transcript.segments = segments.len();
+        transcript.segment_list = segments
+            .into_iter()
+            .map(|segment| TranscriptSegment {
+                start_ms: segment.start_ms,
+                end_ms: segment.end_ms,
+                text: segment.text,
+                corrected: None,
+                edit: None,
+                speaker: None,
+            })
+            .collect();
+        transcript.updated_at = Some(now_millis());
+        if let Err(e) = save(store, transcript) {
+            warn!(id = %transcript.id, "Failed to autosave transcript: {}", e);
+        }
+    }
+
+    /// Remove the checkpoint once the transcription has finished; its
+    /// result is persisted on its own.
+    pub fn discard(self) {
+        let id = &self.transcript.id;
+        let Some(path) = STORE.get().and_then(|store| transcript_path(store, id)) else {
+            return;
+        };
+        if let Err(e) = std::fs::remove_file(path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!(id = %id, "Failed to remove checkpoint: {}", e);
+            }
+        }
+    }
+}
+
+/// Load a persisted transcript.
+pub fn get(id: &str) -> Option<Transcript> {
+    load(STORE.get()?, id)
+}
+
+/// Load a persisted transcript `caller` may see; `404` if it doesn't
+/// exist or belongs to another tenant.
+fn get_for(caller: Option<&Tenant>, id: &str) -> Result<Transcript, ApiError> {
+    get(id)
+        .filter(|transcript| tenants::can_access(caller, transcript.tenant.as_deref()))
+        .ok_or_else(|| ApiError::TranscriptNotFound(id.to_string()))
+}
+
+fn load(store: &Store, id: &str) -> Option<Transcript> {
+    let bytes = encryption::read(transcript_path(store, id)?, Kind::Transcript).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// All persisted transcripts whose metadata has `key` equal to `value`,
+/// newest first.
+pub fn with_metadata(key: &str, value: &str) -> Vec<Transcript> {
+    list(&TranscriptFilter {
+        metadata: vec![(key.to_string(), value.to_string())],
+        limit: usize::MAX,
+        ..TranscriptFilter::default()
+    })
+}
+
+/// Path of `transcript`'s retained audio, if it was kept. The file may
+/// since have been pruned.
+pub fn audio_path(transcript: &Transcript) -> Option<PathBuf> {
+    let retention = STORE.get()?.audio.as_ref()?;
+    let name = std::path::Path::new(transcript.audio.as_deref()?).file_name()?;
+    Some(retention.dir.join(name))
+}
+
+/// List persisted transcripts matching `filter`, newest first.
+fn list(filter: &TranscriptFilter) -> Vec<Transcript> {
+    let Some(store) = STORE.get() else {
+        return Vec::new();
+    };
+    let entries = match std::fs::read_dir(&store.transcripts_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to list transcripts: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut transcripts: Vec<Transcript> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| serde_json::from_slice(&encryption::read(path, Kind::Transcript).ok()?).ok())
+        .filter(|transcript: &Transcript| filter.matches(transcript))
+        .collect();
+    transcripts.sort_by_key(|transcript| std::cmp::Reverse(transcript.created_at));
+    transcripts.truncate(filter.limit);
+    transcripts
+}
+
+/// Apply segment corrections to a persisted transcript.
+pub fn correct(id: &str, request: CorrectionRequest) -> Result<Transcript, ApiError> {
+    let store = STORE
+        .get()
+        .ok_or_else(|| ApiError::TranscriptNotFound(id.to_string()))?;
+    let _guard = UPDATE_LOCK.lock().unwrap();
+
+    let mut transcript = get(id).ok_or_else(|| ApiError::TranscriptNotFound(id.to_string()))?;
+    let corrected = apply_edits(&mut transcript, request, now_millis())?;
+    save(store, &transcript)
+        .map_err(|e| ApiError::Internal(format!("Failed to save transcript: {}", e)))?;
+    info!(id = %id, "Transcript corrected");
+    index_segments(&transcript);
+
+    if let Some(profile) = &transcript.options.profile {
+        let corrections: Vec<(&str, &str)> = corrected
+            .iter()
+            .map(|&i| &transcript.segment_list[i])
+            .map(|segment| (segment.text.as_str(), segment.effective_text()))
+            .collect();
+        vocabulary::learn(profile, &corrections);
+    }
+    Ok(transcript)
+}
+
+/// Summarize a persisted transcript with the LLM and store the summary.
+///
+/// The corrected text is summarized if there is one. Blocks while the LLM
+/// writes the summary.
+pub fn summarize(id: &str, action_items: bool) -> Result<TranscriptSummary, ApiError> {
+    let store = STORE
+        .get()
+        .ok_or_else(|| ApiError::TranscriptNotFound(id.to_string()))?;
+    let transcript = get(id).ok_or_else(|| ApiError::TranscriptNotFound(id.to_string()))?;
+    if !llm::enabled() {
+        return Err(ApiError::LlmUnavailable);
+    }
+    let text = transcript.corrected_text.as_deref().unwrap_or(&transcript.text);
+    if text.trim().is_empty() {
+        return Err(ApiError::InvalidRequest("Transcript is empty".to_string()));
+    }
+
+    // Not holding the update lock while the LLM works, so corrections
+    // aren't blocked on it
+    let llm::Summary { summary, action_items } =
+        llm::summarize(text, action_items).map_err(|e| ApiError::LlmFailed(format!("{:#}", e)))?;
+    let summary = TranscriptSummary {
+        summary,
+        action_items,
+        created_at: now_millis(),
+        corrected: transcript.corrected_text.is_some(),
+    };
+
+    let _guard = UPDATE_LOCK.lock().unwrap();
+    let mut transcript = get(id).ok_or_else(|| ApiError::TranscriptNotFound(id.to_string()))?;
+    transcript.summary = Some(summary.clone());
+    save(store, &transcript)
+        .map_err(|e| ApiError::Internal(format!("Failed to save transcript: {}", e)))?;
+    info!(id = %id, "Transcript summarized");
+    Ok(summary)
+}
+
+/// `transcript` laid out as a document: titled with its `title` metadata,
+/// with a section per chapter, and a turn per speaker change or pause of
+/// [`PARAGRAPH_PAUSE_MS`]. Speakers are called by `names`, in order.
+fn document(transcript: &Transcript, names: &[&str]) -> Document {
+    layout(
+        transcript.created_at,
+        &transcript.metadata,
+        &transcript.segment_list,
+        &transcript.chapters,
+        names,
+    )
+}
+
+/// A job's transcript laid out as a document, as [`document`] would once
+/// stored, whether or not it is.
+pub fn job_document(
+    metadata: &JobMetadata,
+    segments: &[Segment],
+    channels: &[ChannelTranscript],
+    chapters: &[Chapter],
+) -> Document {
+    layout(now_millis(), metadata, &segment_list(segments, channels), chapters, &[])
+}
+
+/// Lay out a transcript created at `created_at` (Unix milliseconds); see
+/// [`document`].
+fn layout(
+    created_at: u64,
+    metadata: &JobMetadata,
+    segment_list: &[TranscriptSegment],
+    chapters: &[Chapter],
+    names: &[&str],
+) -> Document {
+    let title = metadata.metadata.get("title").and_then(|title| title.as_str());
+    let secs = created_at / 1000;
+    let subtitle = format!(
+        "Transcribed {} {:02}:{:02} UTC",
+        crate::tenants::utc_date(secs),
+        secs / 3600 % 24,
+        secs / 60 % 60
+    );
+    let speaker = |speaker: u32| match names.get(speaker as usize) {
+        Some(name) => name.to_string(),
+        None => format!("Speaker {}", speaker + 1),
+    };
+
+    let mut sections: Vec<Section> = Vec::new();
+    let mut chapters = chapters.iter().peekable();
+    let mut previous: Option<&TranscriptSegment> = None;
+    for segment in segment_list {
+        let text = segment.effective_text().split_whitespace().collect::<Vec<_>>().join(" ");
+        if text.is_empty() {
+            continue;
+        }
+        let mut chapter = None;
+        while let Some(next) = chapters.next_if(|c| c.start_ms <= segment.start_ms) {
+            chapter = Some(next);
+        }
+        if sections.is_empty() || chapter.is_some() {
+            sections.push(Section { heading: chapter.map(|c| c.title.clone()), turns: Vec::new() });
+            previous = None;
+        }
+        let turns = &mut sections.last_mut().expect("a section was started").turns;
+        let continues = previous.is_some_and(|previous| {
+            previous.speaker == segment.speaker
+                && segment.start_ms - previous.end_ms < PARAGRAPH_PAUSE_MS
+        });
+        match turns.last_mut() {
+            Some(turn) if continues => {
+                turn.text.push(' ');
+                turn.text.push_str(&text);
+            }
+            _ => turns.push(Turn {
+                speaker: segment.speaker.map(speaker),
+                start_ms: segment.start_ms,
+                text,
+            }),
+        }
+        previous = Some(segment);
+    }
+    Document {
+        title: title.unwrap_or("Transcript").to_string(),
+        subtitle: Some(subtitle),
+        sections,
+    }
+}
+
+/// Validate and apply `request` to `transcript`, returning the indices of
+/// the segments that were corrected (not reverted). Nothing is changed if
+/// any edit is invalid.
+fn apply_edits(
+    transcript: &mut Transcript,
+    request: CorrectionRequest,
+    now: u64,
+) -> Result<Vec<usize>, ApiError> {
+    if request.edits.is_empty() {
+        return Err(ApiError::InvalidRequest("No edits given".to_string()));
+    }
+    if let Some(edit) = request
+        .edits
+        .iter()
+        .find(|edit| edit.index >= transcript.segment_list.len())
+    {
+        return Err(ApiError::InvalidRequest(format!(
+            "Segment {} out of range (transcript has {} segments)",
+            edit.index,
+            transcript.segment_list.len()
+        )));
+    }
+
+    let mut corrected = Vec::new();
+    for edit in request.edits {
+        let segment = &mut transcript.segment_list[edit.index];
+        let text = edit.text.trim();
+        if text == segment.text {
+            segment.corrected = None;
+            segment.edit = None;
+        } else {
+            corrected.push(edit.index);
+            segment.corrected = Some(text.to_string());
+            segment.edit = Some(EditInfo {
+                edited_at: now,
+                editor: edit.editor.or_else(|| request.editor.clone()),
+                note: edit.note,
+            });
+        }
+    }
+
+    let any_corrected = transcript.segment_list.iter().any(|s| s.corrected.is_some());
+    transcript.corrected_text =
+        any_corrected.then(|| apply_corrections(&transcript.text, &transcript.segment_list));
+    transcript.updated_at = Some(now);
+    Ok(corrected)
+}
+
+/// Rebuild `text` with each segment's correction substituted in place, so
+/// whisper's spacing and punctuation between segments is preserved.
+fn apply_corrections(text: &str, segments: &[TranscriptSegment]) -> String {
+    let mut corrected = String::with_capacity(text.len());
+    let mut rest = text;
+    for segment in segments {
+        let Some(pos) = rest.find(&segment.text) else {
+            continue;
+        };
+        corrected.push_str(&rest[..pos]);
+        corrected.push_str(segment.effective_text());
+        rest = &rest[pos + segment.text.len()..];
+    }
+    corrected.push_str(rest);
+    corrected
+}
+
+/// Path of a transcript file, or `None` for an invalid ID.
+fn transcript_path(store: &Store, id: &str) -> Option<PathBuf> {
+    // IDs are UUIDs; reject anything else so it can't escape the directory
+    uuid::Uuid::parse_str(id).ok()?;
+    Some(store.transcripts_dir.join(format!("{}.json", id)))
+}
+
+/// Write a transcript file atomically.
+fn save(store: &Store, transcript: &Transcript) -> std::io::Result<()> {
+    let path = store.transcripts_dir.join(format!("{}.json", transcript.id));
+    let json = serde_json::to_vec_pretty(transcript)?;
+    let tmp = path.with_extension("json.tmp");
+    encryption::write(&tmp, Kind::Transcript, json)?;
+    std::fs::rename(&tmp, &path)
+}
+
+/// Embed the segments of `transcript` in the background, replacing any
+/// stored vectors. Does nothing unless embeddings are enabled.
+fn index_segments(transcript: &Transcript) {
+    let (Some(store), Some(model)) = (STORE.get(), embeddings::model()) else {
+        return;
+    };
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    if transcript.segment_list.is_empty() {
+        return;
+    }
+    let id = transcript.id.clone();
+    let texts: Vec<String> = transcript
+        .segment_list
+        .iter()
+        .map(|segment| segment.effective_text().to_string())
+        .collect();
+    let path = store.embeddings_dir.join(format!("{}.json", id));
+    handle.spawn(async move {
+        let vectors = match embeddings::embed(&texts).await {
+            Ok(vectors) => vectors,
+            Err(e) => {
+                warn!(id = %id, "Failed to embed transcript segments: {:#}", e);
+                return;
+            }
+        };
+        let vectors = SegmentVectors { model: model.to_string(), vectors };
+        let result = match serde_json::to_vec(&vectors) {
+            Ok(json) => match encryption::seal(Kind::Embeddings, json) {
+                Ok(sealed) => tokio::fs::write(&path, sealed).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            warn!(id = %id, "Failed to store segment embeddings: {}", e);
+        }
+    });
+}
+
+/// Rank the stored segments of the transcripts `caller` may see by
+/// similarity to `query`, an embedding made with `model`.
+fn search(
+    store: &Store,
+    caller: Option<&Tenant>,
+    query: &[f32],
+    model: &str,
+    limit: usize,
+) -> Vec<SearchHit> {
+    let entries = match std::fs::read_dir(&store.embeddings_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to list segment embeddings: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut hits: Vec<SearchHit> = Vec::new();
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let Some(vectors) = encryption::read(&path, Kind::Embeddings)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<SegmentVectors>(&bytes).ok())
+        else {
+            continue;
+        };
+        // Transcripts are deleted or re-embedded independently of their vectors
+        let transcript = match load(store, id) {
+            Some(transcript) if transcript.segment_list.len() == vectors.vectors.len() => transcript,
+            _ => continue,
+        };
+        if !tenants::can_access(caller, transcript.tenant.as_deref()) {
+            continue;
+        }
+        if vectors.model != model {
+            continue;
+        }
+        for (i, (segment, vector)) in transcript.segment_list.iter().zip(&vectors.vectors).enumerate() {
+            hits.push(SearchHit {
+                transcript_id: transcript.id.clone(),
+                segment: i,
+                start_ms: segment.start_ms,
+                end_ms: segment.end_ms,
+                text: segment.effective_text().to_string(),
+                score: embeddings::similarity(query, vector),
+            });
+        }
+    }
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(limit);
+    hits
+}
+
+/// Delete retained audio that violates the retention policy.
+///
+/// Files older than `max_age` are removed first, then the oldest files until
+/// the total size is within `max_bytes` and there are at most `max_count`.
+/// Returns the number of recordings removed.
+pub fn enforce_audio_retention() -> usize {
+    match STORE.get().and_then(|store| store.audio.as_ref()) {
+        Some(retention) => prune_audio(retention, SystemTime::now()),
+        None => 0,
+    }
+}
+
+/// Delete the recordings in `retention.dir` that violate its policy. Files
+/// sharing a name but for the extension (a session recording and its
+/// transcript) count as one recording, and are removed together.
+pub fn prune_audio(retention: &AudioRetention, now: SystemTime) -> usize {
+    let Ok(entries) = std::fs::read_dir(&retention.dir) else {
+        return 0;
+    };
+    let mut recordings: HashMap<OsString, (Vec<PathBuf>, SystemTime, u64)> = HashMap::new();
+    for entry in entries.flatten() {
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        let path = entry.path();
+        let Some(stem) = path.file_stem().filter(|_| meta.is_file()) else {
+            continue;
+        };
+        let (paths, modified, len) =
+            recordings.entry(stem.to_os_string()).or_insert((Vec::new(), now, 0));
+        *modified = (*modified).min(meta.modified().unwrap_or(now));
+        *len += meta.len();
+        paths.push(path);
+    }
+    let mut recordings: Vec<_> = recordings.into_values().collect();
+    recordings.sort_by_key(|(_, modified, _)| *modified);
+
+    let mut total: u64 = recordings.iter().map(|(_, _, len)| len).sum();
+    let mut count = recordings.len();
+    let mut removed = 0;
+    for (paths, modified, len) in recordings {
+        let expired = retention
+            .max_age
+            .is_some_and(|max_age| now.duration_since(modified).unwrap_or_default() > max_age);
+        let over_budget = retention.max_bytes.is_some_and(|max| total > max);
+        let over_count = retention.max_count.is_some_and(|max| count > max);
+        if !expired && !over_budget && !over_count {
+            continue;
+        }
+        let mut deleted = true;
+        for path in paths {
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!(path = ?path, "Failed to remove retained audio: {}", e);
+                deleted = false;
+            }
+        }
+        if deleted {
+            total -= len;
+            count -= 1;
+            removed += 1;
+        }
+    }
+
+    if removed > 0 {
+        info!(removed, "Pruned retained audio");
+    }
+    removed
+}
+
+/// Encrypt the transcripts, segment embeddings and retained audio stored
+/// before encryption was turned on. Does nothing unless both are enabled.
+/// Returns the number of files encrypted.
+pub fn seal_existing() -> usize {
+    let Some(store) = STORE.get().filter(|_| encryption::enabled()) else {
+        return 0;
+    };
+    let json_files = |dir: &std::path::Path| {
+        list_files(dir)
+            .into_iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+    };
+
+    let mut sealed = 0;
+    for path in json_files(&store.transcripts_dir) {
+        // Corrections rewrite transcripts meanwhile
+        let _guard = UPDATE_LOCK.lock().unwrap();
+        sealed += seal_file(&path, Kind::Transcript);
+    }
+    for path in json_files(&store.embeddings_dir) {
+        sealed += seal_file(&path, Kind::Embeddings);
+    }
+    if let Some(retention) = &store.audio {
+        sealed += list_files(&retention.dir)
+            .iter()
+            .map(|path| seal_file(path, Kind::Audio))
+            .sum::<usize>();
+    }
+    if sealed > 0 {
+        info!(sealed, "Encrypted previously stored transcripts and audio");
+    }
+    sealed
+}
+
+/// Paths of the entries of `dir`.
+pub(crate) fn list_files(dir: &std::path::Path) -> Vec<PathBuf> {
+    std::fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default()
+}
+
+/// Encrypt the `kind` file at `path` in place, logging failures. Returns 1
+/// if it was encrypted now, 0 otherwise.
+pub(crate) fn seal_file(path: &std::path::Path, kind: Kind) -> usize {
+    match encryption::seal_file(path, kind) {
+        Ok(sealed) => usize::from(sealed),
+        Err(e) => {
+            warn!(path = ?path, "Failed to encrypt stored file: {}", e);
+            0
+        }
+    }
+}
+
+/// Delete stored transcripts that violate the retention policy.
+///
+/// Transcripts older than `max_age` are removed first, then the oldest
+/// until they take at most `max_bytes` and there are at most `max_count`.
+/// Returns the number of transcripts removed.
+pub fn enforce_retention() -> usize {
+    match STORE.get() {
+        Some(store) if store.retention.is_bounded() => prune(store, now_millis()),
+        _ => 0,
+    }
+}
+
+/// What pruning needs of a stored transcript.
+#[derive(Deserialize)]
+struct Stored {
+    id: String,
+    created_at: u64,
+    #[serde(default)]
+    audio: Option<String>,
+}
+
+/// What pruning needs of the transcript file at `path`. A file that can't
+/// be decrypted or parsed goes by its modification time, so it still
+/// expires; `None` only if that can't be read either.
+fn read_stored(path: &std::path::Path) -> Option<Stored> {
+    let parsed = encryption::read(path, Kind::Transcript)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|e| e.to_string()));
+    match parsed {
+        Ok(stored) => Some(stored),
+        Err(e) => {
+            warn!(path = ?path, "Unreadable transcript, aging it by modification time: {}", e);
+            let modified = std::fs::metadata(path).and_then(|meta| meta.modified()).ok()?;
+            Some(Stored {
+                id: path.file_stem()?.to_str()?.to_string(),
+                created_at: unix_millis(modified),
+                audio: None,
+            })
+        }
+    }
+}
+
+/// Delete the transcripts in `store` that violate its retention policy at
+/// `now` (Unix milliseconds).
+fn prune(store: &Store, now: u64) -> usize {
+    let entries = match std::fs::read_dir(&store.transcripts_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to list transcripts: {}", e);
+            return 0;
+        }
+    };
+    let file_len = |path: PathBuf| std::fs::metadata(path).map_or(0, |meta| meta.len());
+    let mut transcripts: Vec<(Stored, u64)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| {
+            let stored = read_stored(&path)?;
+            let len = file_len(path)
+                + file_len(store.embeddings_dir.join(format!("{}.json", stored.id)))
+                + file_len(store.fingerprints_dir.join(&stored.id));
+            Some((stored, len))
+        })
+        .collect();
+    transcripts.sort_by_key(|(stored, _)| stored.created_at);
+
+    let retention = &store.retention;
+    let mut total: u64 = transcripts.iter().map(|(_, len)| len).sum();
+    let mut count = transcripts.len();
+    let mut removed = 0;
+    let _guard = UPDATE_LOCK.lock().unwrap();
+    for (stored, len) in transcripts {
+        let age = Duration::from_millis(now.saturating_sub(stored.created_at));
+        let expired = retention.max_age.is_some_and(|max_age| age > max_age);
+        let over_budget = retention.max_bytes.is_some_and(|max| total > max);
+        let over_count = retention.max_count.is_some_and(|max| count > max);
+        if !expired && !over_budget && !over_count {
+            continue;
+        }
+        match remove(store, &stored.id, stored.audio.as_deref()) {
+            Ok(()) => {
+                total -= len;
+                count -= 1;
+                removed += 1;
+            }
+            Err(e) => warn!(id = %stored.id, "Failed to remove transcript: {}", e),
+        }
+    }
+
+    if removed > 0 {
+        info!(removed, "Pruned transcripts");
+    }
+    removed
+}
+
+/// Delete transcript `id` and everything stored for it.
+pub fn delete(id: &str) -> Result<(), ApiError> {
+    let not_found = || ApiError::TranscriptNotFound(id.to_string());
+    let store = STORE.get().ok_or_else(not_found)?;
+    let _guard = UPDATE_LOCK.lock().unwrap();
+    let transcript = load(store, id).ok_or_else(not_found)?;
+    remove(store, id, transcript.audio.as_deref())
+        .map_err(|e| ApiError::Internal(format!("Failed to delete transcript: {}", e)))?;
+    // Otherwise uploading the recording again would bring the text back
+    if let Some(key) = &transcript.cache_key {
+        cache::remove(key);
+    }
+    info!(id = %id, "Transcript deleted");
+    Ok(())
+}
+
+/// Remove transcript `id`, then its embeddings, fingerprint and retained
+/// `audio` file. Only failing to remove the transcript is an error; the
+/// others are logged.
+fn remove(store: &Store, id: &str, audio: Option<&str>) -> std::io::Result<()> {
+    let path = transcript_path(store, id).ok_or(std::io::ErrorKind::InvalidInput)?;
+    std::fs::remove_file(path)?;
+    store.fingerprints.lock().unwrap().remove(id);
+    let mut paths = vec![
+        store.embeddings_dir.join(format!("{}.json", id)),
+        store.fingerprints_dir.join(id),
+    ];
+    let audio = audio.and_then(|name| std::path::Path::new(name).file_name());
+    if let (Some(retention), Some(audio)) = (&store.audio, audio) {
+        paths.push(retention.dir.join(audio));
+    }
+    for path in paths {
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!(id = %id, path = ?path, "Failed to remove transcript data: {}", e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Transcript listing endpoint (`GET /transcripts`).
+#[utoipa::path(
+    get,
+    path = "/transcripts",
+    tag = "transcripts",
+    params(
+        ("tag" = Option<String>, Query, description = "Only transcripts with this tag; repeat to require several"),
+        ("metadata.<key>" = Option<String>, Query, description = "Only transcripts whose metadata has `<key>` equal to this value"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of transcripts (default 50, max 500)"),
+    ),
+    responses(
+        (status = 200, description = "Matching transcripts, newest first", body = TranscriptList),
+        (status = 400, description = "Invalid filter", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn list_transcripts(
+    tenant: Option<Extension<Tenant>>,
+    query: Result<Query<Vec<(String, String)>>, QueryRejection>,
+) -> Result<Json<TranscriptList>, ApiError> {
+    let Query(params) = query?;
+    let filter = TranscriptFilter {
+        tenant: tenant.map(|Extension(tenant)| tenant.tenant),
+        ..TranscriptFilter::from_query(params)?
+    };
+    let transcripts = tokio::task::spawn_blocking(move || list(&filter))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(TranscriptList { transcripts }))
+}
+
+/// Semantic search endpoint (`GET /transcripts/semantic-search`).
+///
+/// Embeds `q` and returns the stored segments most similar to it, across
+/// the caller's transcripts embedded with the current model.
+#[utoipa::path(
+    get,
+    path = "/transcripts/semantic-search",
+    tag = "transcripts",
+    params(SemanticSearchQuery),
+    responses(
+        (status = 200, description = "Matching segments, most similar first", body = SemanticSearchResults),
+        (status = 400, description = "Missing query or invalid limit", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 502, description = "The embeddings endpoint failed", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "Embeddings or transcript persistence not configured", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn semantic_search(
+    tenant: Option<Extension<Tenant>>,
+    query: Result<Query<SemanticSearchQuery>, QueryRejection>,
+) -> Result<Json<SemanticSearchResults>, ApiError> {
+    let Query(query) = query?;
+    let q = query.q.trim();
+    if q.is_empty() {
+        return Err(ApiError::InvalidRequest("q must not be empty".to_string()));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    if !(1..=MAX_SEARCH_LIMIT).contains(&limit) {
+        return Err(ApiError::InvalidRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_SEARCH_LIMIT
+        )));
+    }
+    let (Some(store), Some(model)) = (STORE.get(), embeddings::model()) else {
+        return Err(ApiError::EmbeddingsUnavailable);
+    };
+
+    let vector = embeddings::embed(&[q.to_string()])
+        .await
+        .map_err(|e| ApiError::EmbeddingsFailed(format!("{:#}", e)))?
+        .pop()
+        .unwrap_or_default();
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    let rank = move || search(store, tenant.as_ref(), &vector, model, limit);
+    let results = tokio::task::spawn_blocking(rank)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(Json(SemanticSearchResults { results }))
+}
+
+/// Transcript lookup endpoint.
+#[utoipa::path(
+    get,
+    path = "/transcripts/{id}",
+    tag = "transcripts",
+    params(("id" = String, Path, description = "Transcript ID")),
+    responses(
+        (status = 200, description = "Persisted transcript, as JSON, text or SRT subtitles per `Accept` (with corrections)", content(
+            (Transcript = "application/json"),
+            (String = "text/plain"),
+            (String = "application/x-subrip"),
+        )),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown transcript, or another tenant's", body = Problem, content_type = "application/problem+json"),
+        (status = 406, description = "`Accept` allows none of JSON, text or SRT", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn get_transcript(
+    Path(id): Path<String>,
+    tenant: Option<Extension<Tenant>>,
+    Accept(accept): Accept,
+) -> Result<Response, ApiError> {
+    let transcript = get_for(tenant.as_deref(), &id)?;
+    let text = transcript.corrected_text.as_deref().unwrap_or(&transcript.text);
+    let cues = transcript.segment_list.iter().map(|segment| Cue {
+        start_ms: segment.start_ms,
+        end_ms: segment.end_ms,
+        text: segment.effective_text(),
+    });
+    Ok(negotiate::respond(accept, &transcript, text, cues))
+}
+
+/// Transcript export endpoint (`GET /transcripts/:id/export`).
+///
+/// Renders the transcript, with corrections, as a Word document or PDF
+/// (see [`crate::export`]), as an attachment named after its ID.
+#[utoipa::path(
+    get,
+    path = "/transcripts/{id}/export",
+    tag = "transcripts",
+    params(("id" = String, Path, description = "Transcript ID"), ExportQuery),
+    responses(
+        (status = 200, description = "The transcript as a document", content(
+            (Vec<u8> = "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+            (Vec<u8> = "application/pdf"),
+        )),
+        (status = 400, description = "Missing or unknown format", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown transcript, or another tenant's", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn export_transcript(
+    Path(id): Path<String>,
+    tenant: Option<Extension<Tenant>>,
+    query: Result<Query<ExportQuery>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(query) = query?;
+    let transcript = get_for(tenant.as_deref(), &id)?;
+    let names = query.speakers.unwrap_or_default();
+    let names: Vec<&str> = names.split(',').map(str::trim).collect();
+    let document = document(&transcript, &names);
+    let format = query.format;
+    let bytes = tokio::task::spawn_blocking(move || export::render(&document, format))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map_err(|e| ApiError::Internal(format!("Failed to export transcript: {:#}", e)))?;
+    let disposition =
+        format!("attachment; filename=\"{}.{}\"", transcript.id, format.extension());
+    let headers = [
+        (header::CONTENT_TYPE, format.content_type().to_string()),
+        (header::CONTENT_DISPOSITION, disposition),
+    ];
+    Ok((headers, Body::from(bytes)).into_response())
+}
+
+/// Transcript deletion endpoint (`DELETE /transcripts/:id`).
+#[utoipa::path(
+    delete,
+    path = "/transcripts/{id}",
+    tag = "transcripts",
+    params(("id" = String, Path, description = "Transcript ID")),
+    responses(
+        (status = 204, description = "Transcript deleted, with its retained audio, embeddings and fingerprint"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown transcript, or another tenant's", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn delete_transcript(
+    Path(id): Path<String>,
+    tenant: Option<Extension<Tenant>>,
+) -> Result<StatusCode, ApiError> {
+    tokio::task::spawn_blocking(move || {
+        get_for(tenant.as_deref(), &id)?;
+        delete(&id)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))??;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Transcript correction endpoint (`PATCH /transcripts/:id`).
+#[utoipa::path(
+    patch,
+    path = "/transcripts/{id}",
+    tag = "transcripts",
+    params(("id" = String, Path, description = "Transcript ID")),
+    request_body = CorrectionRequest,
+    responses(
+        (status = 200, description = "Corrected transcript", body = Transcript),
+        (status = 400, description = "Invalid request or segment index", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown transcript, or another tenant's", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn correct_transcript(
+    Path(id): Path<String>,
+    tenant: Option<Extension<Tenant>>,
+    payload: Result<Json<CorrectionRequest>, JsonRejection>,
+) -> Result<Json<Transcript>, ApiError> {
+    let Json(request) = payload?;
+    tokio::task::spawn_blocking(move || {
+        get_for(tenant.as_deref(), &id)?;
+        correct(&id, request)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))?
+    .map(Json)
+}
+
+/// Transcript summary endpoint (`POST /transcripts/:id/summarize`).
+///
+/// Asks the LLM at `VOICEMARK_LLM_URL` for a summary (and, with
+/// `action_items=true`, the action items) of the transcript, stores it as
+/// the transcript's `summary` and returns it. Summarizing again replaces it.
+#[utoipa::path(
+    post,
+    path = "/transcripts/{id}/summarize",
+    tag = "transcripts",
+    params(("id" = String, Path, description = "Transcript ID"), SummarizeQuery),
+    responses(
+        (status = 200, description = "The stored summary", body = TranscriptSummary),
+        (status = 400, description = "Invalid query or empty transcript", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown transcript, or another tenant's", body = Problem, content_type = "application/problem+json"),
+        (status = 502, description = "The LLM failed or returned an unusable reply", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "No LLM endpoint configured", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn summarize_transcript(
+    Path(id): Path<String>,
+    tenant: Option<Extension<Tenant>>,
+    query: Result<Query<SummarizeQuery>, QueryRejection>,
+) -> Result<Json<TranscriptSummary>, ApiError> {
+    let Query(query) = query?;
+    tokio::task::spawn_blocking(move || {
+        get_for(tenant.as_deref(), &id)?;
+        summarize(&id, query.action_items)
+    })
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))?
+    .map(Json)
+}
+
+/// Retained audio endpoint (`GET /transcripts/:id/audio`).
+#[utoipa::path(
+    get,
+    path = "/transcripts/{id}/audio",
+    tag = "transcripts",
+    params(("id" = String, Path, description = "Transcript ID")),
+    responses(
+        (status = 200, description = "The uploaded audio, in its original format", body = AudioFile, content_type = "audio/*"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown transcript or another tenant's, or its audio was not retained", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn get_transcript_audio(
+    Path(id): Path<String>,
+    tenant: Option<Extension<Tenant>>,
+) -> Result<Response, ApiError> {
+    let transcript = get_for(tenant.as_deref(), &id)?;
+    let audio_dir = STORE
+        .get()
+        .and_then(|store| store.audio.as_ref())
+        .map(|retention| retention.dir.clone());
+
+    let (Some(name), Some(dir)) = (transcript.audio, audio_dir) else {
+        return Err(ApiError::AudioNotRetained(id));
+    };
+    let bytes = tokio::fs::read(dir.join(&name))
+        .await
+        .map_err(|_| ApiError::AudioNotRetained(id))?;
+    let bytes = encryption::open(Kind::Audio, bytes)
+        .map_err(|e| ApiError::Internal(format!("Failed to read retained audio: {}", e)))?;
+
+    let content_type = audio::content_type_for_extension(name.rsplit('.').next().unwrap_or(""));
+    Ok(([(header::CONTENT_TYPE, content_type)], Body::from(bytes)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_file(dir: &std::path::Path, name: &str, len: usize, age: Duration) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, vec![0u8; len]).unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_prune_audio_by_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = write_file(dir.path(), "old.webm", 10, Duration::from_secs(3 * 86400));
+        let new = write_file(dir.path(), "new.webm", 10, Duration::from_secs(60));
+
+        let retention = AudioRetention {
+            dir: dir.path().to_path_buf(),
+            max_age: Some(Duration::from_secs(86400)),
+            max_bytes: None,
+            max_count: None,
+        };
+        assert_eq!(prune_audio(&retention, SystemTime::now()), 1);
+        assert!(!old.exists());
+        assert!(new.exists());
+    }
+
+    #[test]
+    fn test_prune_audio_by_size_removes_oldest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = write_file(dir.path(), "a.wav", 100, Duration::from_secs(300));
+        let b = write_file(dir.path(), "b.wav", 100, Duration::from_secs(200));
+        let c = write_file(dir.path(), "c.wav", 100, Duration::from_secs(100));
+
+        let retention = AudioRetention {
+            dir: dir.path().to_path_buf(),
+            max_age: None,
+            max_bytes: Some(250),
+            max_count: None,
+        };
+        assert_eq!(prune_audio(&retention, SystemTime::now()), 1);
+        assert!(!a.exists());
+        assert!(b.exists());
+        assert!(c.exists());
+    }
+
+    #[test]
+    fn test_prune_audio_by_count_keeps_recordings_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = write_file(dir.path(), "old.opus", 10, Duration::from_secs(300));
+        let old_transcript = write_file(dir.path(), "old.json", 10, Duration::from_secs(200));
+        let new = write_file(dir.path(), "new.opus", 10, Duration::from_secs(100));
+        let new_transcript = write_file(dir.path(), "new.json", 10, Duration::from_secs(50));
+
+        let retention = AudioRetention {
+            dir: dir.path().to_path_buf(),
+            max_count: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(prune_audio(&retention, SystemTime::now()), 1);
+        assert!(!old.exists() && !old_transcript.exists());
+        assert!(new.exists() && new_transcript.exists());
+    }
+
+    fn transcript() -> Transcript {
+        let segment = |start_ms, text: &str| TranscriptSegment {
+            start_ms,
+            end_ms: start_ms + 500,
+            text: text.to_string(),
+            corrected: None,
+            edit: None,
+            speaker: None,
+        };
+        Transcript {
+            id: uuid::Uuid::new_v4().to_string(),
+            created_at: 0,
+            text: "Call Dr. Win, please.".to_string(),
+            segments: 4,
+            corrected_text: None,
+            segment_list: vec![
+                segment(0, "Call"),
+                segment(500, "Dr. Win"),
+                segment(1000, ","),
+                segment(1500, "please."),
+            ],
+            updated_at: None,
+            model: None,
+            options: TranscribeOptions::default(),
+            audio: None,
+            metadata: JobMetadata::default(),
+            summary: None,
+            chapters: Vec::new(),
+            partial: false,
+            cache_key: None,
+            tenant: None,
+        }
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_filter_from_query() {
+        let filter = TranscriptFilter::from_query(params(&[
+            ("tag", "standup"),
+            ("metadata.meeting_id", "m-42"),
+            ("limit", "10"),
+        ]))
+        .unwrap();
+        assert_eq!(filter.tags, vec!["standup"]);
+        assert_eq!(filter.metadata, vec![("meeting_id".to_string(), "m-42".to_string())]);
+        assert_eq!(filter.limit, 10);
+
+        assert_eq!(TranscriptFilter::from_query(Vec::new()).unwrap().limit, DEFAULT_LIST_LIMIT);
+        assert!(TranscriptFilter::from_query(params(&[("limit", "0")])).is_err());
+        assert_eq!(
+            TranscriptFilter::from_query(params(&[("api_key", "k")])).unwrap(),
+            TranscriptFilter { limit: DEFAULT_LIST_LIMIT, ..TranscriptFilter::default() }
+        );
+    }
+
+    #[test]
+    fn test_filter_matches_tags_and_metadata() {
+        let metadata: JobMetadata = serde_json::from_value(serde_json::json!({
+            "metadata": { "meeting_id": "m-42", "attendees": 3 },
+            "tags": ["standup", "team-a"],
+        }))
+        .unwrap();
+        let tagged = Transcript { metadata, ..transcript() };
+        let filter = |pairs: &[(&str, &str)]| TranscriptFilter::from_query(params(pairs)).unwrap();
+
+        assert!(filter(&[]).matches(&tagged));
+        assert!(filter(&[("tag", "standup"), ("tag", "team-a")]).matches(&tagged));
+        assert!(!filter(&[("tag", "standup"), ("tag", "team-b")]).matches(&tagged));
+        assert!(filter(&[("metadata.meeting_id", "m-42"), ("metadata.attendees", "3")]).matches(&tagged));
+        assert!(!filter(&[("metadata.meeting_id", "m-43")]).matches(&tagged));
+        assert!(!filter(&[("metadata.user_id", "u-7")]).matches(&tagged));
+        assert!(!filter(&[("tag", "standup")]).matches(&transcript()));
+    }
+
+    #[test]
+    fn test_filter_matches_tenant() {
+        let owned = Transcript { tenant: Some("acme".to_string()), ..transcript() };
+        let filter = |tenant: Option<&str>| TranscriptFilter {
+            tenant: tenant.map(str::to_string),
+            ..TranscriptFilter::default()
+        };
+
+        assert!(filter(None).matches(&owned));
+        assert!(filter(Some("acme")).matches(&owned));
+        assert!(!filter(Some("globex")).matches(&owned));
+        // Transcripts made without authentication belong to no tenant
+        assert!(!filter(Some("acme")).matches(&transcript()));
+    }
+
+    fn edit(index: usize, text: &str) -> SegmentEdit {
+        SegmentEdit {
+            index,
+            text: text.to_string(),
+            editor: None,
+            note: None,
+        }
+    }
+
+    #[test]
+    fn test_apply_edits_keeps_original() {
+        let mut transcript = transcript();
+        let request = CorrectionRequest {
+            editor: Some("reviewer".to_string()),
+            edits: vec![edit(1, " Dr. Nguyen ")],
+        };
+        assert_eq!(apply_edits(&mut transcript, request, 42).unwrap(), vec![1]);
+
+        let segment = &transcript.segment_list[1];
+        assert_eq!(segment.text, "Dr. Win");
+        assert_eq!(segment.corrected.as_deref(), Some("Dr. Nguyen"));
+        assert_eq!(segment.edit.as_ref().unwrap().editor.as_deref(), Some("reviewer"));
+        assert_eq!(transcript.text, "Call Dr. Win, please.");
+        assert_eq!(transcript.corrected_text.as_deref(), Some("Call Dr. Nguyen, please."));
+        assert_eq!(transcript.updated_at, Some(42));
+
+        // Sending the machine output again reverts the correction
+        let request = CorrectionRequest {
+            editor: None,
+            edits: vec![edit(1, "Dr. Win")],
+        };
+        assert!(apply_edits(&mut transcript, request, 43).unwrap().is_empty());
+        assert_eq!(transcript.segment_list[1].corrected, None);
+        assert_eq!(transcript.corrected_text, None);
+    }
+
+    #[test]
+    fn test_apply_edits_rejects_out_of_range_atomically() {
+        let mut transcript = transcript();
+        let request = CorrectionRequest {
+            editor: None,
+            edits: vec![edit(0, "Text"), edit(9, "oops")],
+        };
+        let err = apply_edits(&mut transcript, request, 1).unwrap_err();
+        assert_eq!(err.code(), "invalid_request");
+        assert_eq!(transcript.segment_list[0].corrected, None);
+        assert_eq!(transcript.updated_at, None);
+    }
+
+    #[test]
+    fn test_document_sections_follow_chapters_and_speakers() {
+        let mut transcript = transcript();
+        for (segment, speaker) in transcript.segment_list.iter_mut().zip([0, 0, 1, 1]) {
+            segment.speaker = Some(speaker);
+        }
+        transcript.segment_list[1].corrected = Some("Dr. Wynn".to_string());
+        transcript.chapters =
+            vec![Chapter { title: "Wrap-up".to_string(), start_ms: 1000, end_ms: 2000 }];
+        transcript.metadata.metadata.insert("title".to_string(), "Standup".into());
+
+        let document = document(&transcript, &["Alice"]);
+        assert_eq!(document.title, "Standup");
+        assert_eq!(document.subtitle.as_deref(), Some("Transcribed 1970-01-01 00:00 UTC"));
+        let turn = |speaker: &str, start_ms, text: &str| Turn {
+            speaker: Some(speaker.to_string()),
+            start_ms,
+            text: text.to_string(),
+        };
+        assert_eq!(
+            document.sections,
+            vec![
+                Section { heading: None, turns: vec![turn("Alice", 0, "Call Dr. Wynn")] },
+                Section {
+                    heading: Some("Wrap-up".to_string()),
+                    turns: vec![turn("Speaker 2", 1000, ", please.")],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_saves_partial_transcript() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store {
+            transcripts_dir: dir.path().to_path_buf(),
+            embeddings_dir: dir.path().to_path_buf(),
+            fingerprints_dir: dir.path().to_path_buf(),
+            fingerprints: Mutex::default(),
+            audio: None,
+            retention: TranscriptRetention::default(),
+            autosave: Some(Duration::from_secs(30)),
+        };
+        let start = Instant::now();
+        let mut checkpoint = Checkpoint {
+            transcript: Transcript { partial: true, ..transcript() },
+            saved_at: start,
+        };
+        assert!(!checkpoint.due(&store, start + Duration::from_secs(10)));
+        assert!(checkpoint.due(&store, start + Duration::from_secs(30)));
+
+        let segment = |start_ms, text: &str| Segment {
+            start_ms,
+            end_ms: start_ms + 1000,
+            text: text.to_string(),
+        };
+        checkpoint.save(&store, vec![segment(0, " Good morning."), segment(1000, " Let's start.")]);
+        assert!(!checkpoint.due(&store, Instant::now()));
+
+        let saved = load(&store, &checkpoint.transcript.id).unwrap();
+        assert!(saved.partial);
+        assert_eq!(saved.text, "Good morning. Let's start.");
+        assert_eq!(saved.segments, 2);
+        assert_eq!(saved.segment_list[1].start_ms, 1000);
+        assert!(saved.updated_at.is_some());
+
+        // Finished transcripts don't carry the flag
+        let json = serde_json::to_value(transcript()).unwrap();
+        assert!(json.get("partial").is_none());
+    }
+
+    #[test]
+    fn test_duplicate_needs_same_audio_options_and_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store {
+            transcripts_dir: dir.path().join("transcripts"),
+            embeddings_dir: dir.path().join("embeddings"),
+            fingerprints_dir: dir.path().join("fingerprints"),
+            fingerprints: Mutex::default(),
+            audio: None,
+            retention: TranscriptRetention::default(),
+            autosave: None,
+        };
+        std::fs::create_dir_all(&store.transcripts_dir).unwrap();
+        std::fs::create_dir_all(&store.fingerprints_dir).unwrap();
+
+        let audio = |pitch: f32| -> Vec<f32> {
+            (0..16000 * 40)
+                .map(|i| (i as f32 * pitch).sin() * ((i / 1600) % 7) as f32 / 10.0)
+                .collect()
+        };
+        let meeting = Fingerprint::of(&[audio(0.05)]);
+        let store_transcript = |transcript: &Transcript, fingerprint: &Fingerprint| {
+            save(&store, transcript).unwrap();
+            let path = store.fingerprints_dir.join(&transcript.id);
+            std::fs::write(path, fingerprint.to_string()).unwrap();
+        };
+        let reload = || {
+            *store.fingerprints.lock().unwrap() = load_fingerprints(&store.fingerprints_dir);
+        };
+        let older = transcript();
+        store_transcript(&older, &meeting);
+        let newer = Transcript { created_at: 1, ..transcript() };
+        store_transcript(&newer, &meeting);
+        let checkpoint = Transcript { created_at: 2, partial: true, ..transcript() };
+        store_transcript(&checkpoint, &meeting);
+        let acme = Transcript { created_at: 3, tenant: Some("acme".to_string()), ..transcript() };
+        store_transcript(&acme, &meeting);
+        reload();
+
+        let options = TranscribeOptions::default();
+        let found = duplicate(&store, &meeting, &options, None, None).unwrap();
+        assert_eq!(found.id, newer.id);
+        let found = duplicate(&store, &meeting, &options, None, Some("acme")).unwrap();
+        assert_eq!(found.id, acme.id);
+        assert!(duplicate(&store, &meeting, &options, None, Some("globex")).is_none());
+
+        let other = Fingerprint::of(&[audio(0.05).into_iter().rev().collect()]);
+        assert!(duplicate(&store, &other, &options, None, None).is_none());
+        let translated = TranscribeOptions { translate: true, ..Default::default() };
+        assert!(duplicate(&store, &meeting, &translated, None, None).is_none());
+        let large = Some("ggml-large.bin".to_string());
+        assert!(duplicate(&store, &meeting, &options, large, None).is_none());
+
+        // Removing a transcript takes its fingerprint out of the index
+        remove(&store, &acme.id, None).unwrap();
+        assert!(duplicate(&store, &meeting, &options, None, Some("acme")).is_none());
+    }
+
+    #[test]
+    fn test_search_ranks_segments_across_transcripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store {
+            transcripts_dir: dir.path().join("transcripts"),
+            embeddings_dir: dir.path().join("embeddings"),
+            fingerprints_dir: dir.path().join("fingerprints"),
+            fingerprints: Mutex::default(),
+            audio: None,
+            retention: TranscriptRetention::default(),
+            autosave: None,
+        };
+        std::fs::create_dir_all(&store.transcripts_dir).unwrap();
+        std::fs::create_dir_all(&store.embeddings_dir).unwrap();
+
+        let store_vectors = |transcript: &Transcript, model: &str, vectors: Vec<Vec<f32>>| {
+            save(&store, transcript).unwrap();
+            let vectors = SegmentVectors { model: model.to_string(), vectors };
+            let path = store.embeddings_dir.join(format!("{}.json", transcript.id));
+            std::fs::write(path, serde_json::to_vec(&vectors).unwrap()).unwrap();
+        };
+        let mut first = transcript();
+        first.segment_list[1].corrected = Some("Dr. Nguyen".to_string());
+        store_vectors(&first, "minilm", vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.6, 0.8], vec![-1.0, 0.0]]);
+        let second = Transcript { tenant: Some("acme".to_string()), ..transcript() };
+        store_vectors(&second, "minilm", vec![vec![0.8, 0.6]; 4]);
+        let other_model = transcript();
+        store_vectors(&other_model, "other", vec![vec![0.0, 1.0]; 4]);
+
+        let hits = search(&store, None, &[0.0, 1.0], "minilm", 3);
+        assert_eq!(hits.len(), 3);
+        assert_eq!((hits[0].transcript_id.as_str(), hits[0].segment), (first.id.as_str(), 1));
+        assert_eq!(hits[0].text, "Dr. Nguyen");
+        assert_eq!((hits[0].start_ms, hits[0].end_ms), (500, 1000));
+        assert_eq!((hits[1].transcript_id.as_str(), hits[1].segment), (first.id.as_str(), 2));
+        assert_eq!(hits[2].transcript_id, second.id);
+        assert!(hits.iter().all(|hit| hit.transcript_id != other_model.id));
+
+        // Tenants only find their own transcripts
+        let acme = Tenant {
+            key_id: "k-1".to_string(),
+            tenant: "acme".to_string(),
+            quota: Default::default(),
+        };
+        let hits = search(&store, Some(&acme), &[0.0, 1.0], "minilm", 10);
+        assert_eq!(hits.len(), 4);
+        assert!(hits.iter().all(|hit| hit.transcript_id == second.id));
+    }
+
+    #[test]
+    fn test_prune_removes_transcripts_with_their_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store {
+            transcripts_dir: dir.path().join("transcripts"),
+            embeddings_dir: dir.path().join("embeddings"),
+            fingerprints_dir: dir.path().join("fingerprints"),
+            fingerprints: Mutex::default(),
+            audio: Some(AudioRetention { dir: dir.path().join("audio"), ..Default::default() }),
+            retention: TranscriptRetention::default(),
+            autosave: None,
+        };
+        for dir in [&store.transcripts_dir, &store.embeddings_dir, &store.fingerprints_dir] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::create_dir_all(dir.path().join("audio")).unwrap();
+
+        let day: u64 = 24 * 60 * 60 * 1000;
+        let now = 10 * day;
+        let stored: Vec<Transcript> = (0..4)
+            .map(|age| Transcript {
+                created_at: now - age * day,
+                audio: Some(format!("{}.wav", age)),
+                ..transcript()
+            })
+            .collect();
+        for transcript in &stored {
+            save(&store, transcript).unwrap();
+            std::fs::write(store.fingerprints_dir.join(&transcript.id), "0:").unwrap();
+            let embeddings = store.embeddings_dir.join(format!("{}.json", transcript.id));
+            std::fs::write(embeddings, "{}").unwrap();
+            let audio = transcript.audio.as_ref().unwrap();
+            std::fs::write(dir.path().join("audio").join(audio), "RIFF").unwrap();
+        }
+
+        // Nothing to do without limits
+        assert_eq!(prune(&store, now), 0);
+
+        store.retention.max_age = Some(Duration::from_secs(2 * 24 * 60 * 60 + 1));
+        assert_eq!(prune(&store, now), 1);
+        let gone = &stored[3];
+        assert!(load(&store, &gone.id).is_none());
+        assert!(!store.fingerprints_dir.join(&gone.id).exists());
+        assert!(!store.embeddings_dir.join(format!("{}.json", gone.id)).exists());
+        assert!(!dir.path().join("audio/3.wav").exists());
+        assert!(dir.path().join("audio/2.wav").exists());
+
+        store.retention = TranscriptRetention { max_count: Some(1), ..Default::default() };
+        assert_eq!(prune(&store, now), 2);
+        assert!(load(&store, &stored[0].id).is_some());
+        assert!(load(&store, &stored[1].id).is_none());
+    }
+
+    #[test]
+    fn test_prune_ages_unreadable_transcripts_by_modification_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store {
+            transcripts_dir: dir.path().join("transcripts"),
+            embeddings_dir: dir.path().join("embeddings"),
+            fingerprints_dir: dir.path().join("fingerprints"),
+            fingerprints: Mutex::default(),
+            audio: None,
+            retention: TranscriptRetention {
+                max_age: Some(Duration::from_secs(60 * 60)),
+                ..Default::default()
+            },
+            autosave: None,
+        };
+        std::fs::create_dir_all(&store.transcripts_dir).unwrap();
+        let id = uuid::Uuid::new_v4().to_string();
+        let path = store.transcripts_dir.join(format!("{}.json", id));
+        std::fs::write(&path, b"VMSEAL01 sealed with a lost key").unwrap();
+        let modified = unix_millis(std::fs::metadata(&path).unwrap().modified().unwrap());
+
+        assert_eq!(prune(&store, modified + 60 * 1000), 0);
+        assert!(path.exists());
+        assert_eq!(prune(&store, modified + 2 * 60 * 60 * 1000), 1);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_get_rejects_non_uuid_ids() {
+        assert!(get("../secrets").is_none());
+    }
+}
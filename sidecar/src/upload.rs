@@ -0,0 +1,209 @@
+//! Audio upload extraction for VoiceMark sidecar.
+//!
+//! Transcription endpoints accept audio either as a multipart form or as a
+//! raw request body with an `audio/*` (or `video/*`, `application/octet-stream`)
+//! content type. Off-the-shelf clients don't always let callers choose the
+//! multipart field name, so any file-bearing field is accepted.
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::{StatusCode, header},
+};
+use axum_extra::extract::{Multipart, multipart::MultipartError};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+
+/// Multipart field names checked first, in order of preference.
+const PREFERRED_FIELDS: &[&str] = &["file", "audio"];
+
+/// Raw uploaded audio bytes, extracted from a multipart form or request body.
+pub(crate) struct AudioUpload(pub Vec<u8>);
+
+/// Multipart upload. The audio may also be sent in an `audio` field or any
+/// other file field.
+#[derive(ToSchema)]
+#[allow(dead_code)] // Only describes uploads in the OpenAPI document
+pub(crate) struct UploadForm {
+    /// The audio (or video) file.
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+/// Raw audio (or video) file.
+#[derive(ToSchema)]
+#[schema(value_type = String, format = Binary)]
+#[allow(dead_code)] // Only describes uploads in the OpenAPI document
+pub(crate) struct AudioFile(Vec<u8>);
+
+#[async_trait]
+impl<S> FromRequest<S> for AudioUpload
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        let audio_bytes = if content_type.starts_with("multipart/form-data") {
+            let mut multipart = Multipart::from_request(req, state).await?;
+            extract_audio_file(&mut multipart).await?
+        } else if is_raw_audio_type(&content_type) {
+            Bytes::from_request(req, state).await?.to_vec()
+        } else {
+            return Err(ApiError::UnsupportedMediaType);
+        };
+
+        if audio_bytes.is_empty() {
+            return Err(ApiError::EmptyAudio);
+        }
+
+        info!(bytes = audio_bytes.len(), "Received audio for transcription");
+        Ok(AudioUpload(audio_bytes))
+    }
+}
+
+/// Whether a raw request body with this content type is accepted as audio.
+fn is_raw_audio_type(content_type: &str) -> bool {
+    content_type.starts_with("audio/")
+        || content_type.starts_with("video/")
+        || content_type.starts_with("application/octet-stream")
+}
+
+/// Extract audio file bytes from multipart form.
+///
+/// Uses the `file` or `audio` field if present, otherwise the first field
+/// that carries a file (has a filename or a media content type).
+async fn extract_audio_file(multipart: &mut Multipart) -> Result<Vec<u8>, ApiError> {
+    let mut fallback = None;
+
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        let name = field.name().unwrap_or_default().to_string();
+        let is_file = field.file_name().is_some()
+            || field.content_type().is_some_and(is_raw_audio_type);
+
+        if PREFERRED_FIELDS.contains(&name.as_str()) {
+            let bytes = field.bytes().await.map_err(multipart_error)?;
+            return Ok(bytes.to_vec());
+        }
+
+        if is_file && fallback.is_none() {
+            let bytes = field.bytes().await.map_err(multipart_error)?;
+            fallback = Some(bytes.to_vec());
+        }
+    }
+
+    fallback.ok_or_else(|| {
+        ApiError::MissingAudio(
+            "No audio file found in multipart form (expected a 'file' or 'audio' field)".to_string(),
+        )
+    })
+}
+
+/// Map a multipart read error, treating oversized bodies as too large.
+fn multipart_error(e: MultipartError) -> ApiError {
+    error!("Failed to read multipart form: {}", e);
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        ApiError::AudioTooLarge
+    } else {
+        ApiError::InvalidRequest(e.body_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    fn multipart_request(parts: &[(&str, Option<&str>, &str)]) -> Request {
+        let mut body = String::new();
+        for (name, filename, value) in parts {
+            body.push_str("--BOUNDARY\r\n");
+            match filename {
+                Some(f) => body.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\r\n",
+                    name, f
+                )),
+                None => body.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                    name
+                )),
+            }
+            body.push_str(value);
+            body.push_str("\r\n");
+        }
+        body.push_str("--BOUNDARY--\r\n");
+
+        Request::builder()
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn extract(req: Request) -> Result<Vec<u8>, StatusCode> {
+        AudioUpload::from_request(req, &())
+            .await
+            .map(|AudioUpload(bytes)| bytes)
+            .map_err(|e| e.status())
+    }
+
+    #[tokio::test]
+    async fn test_accepts_audio_field() {
+        let req = multipart_request(&[("title", None, "x"), ("audio", None, "AUDIO")]);
+        assert_eq!(extract(req).await.unwrap(), b"AUDIO");
+    }
+
+    #[tokio::test]
+    async fn test_prefers_named_field_over_first_file() {
+        let req = multipart_request(&[
+            ("upload", Some("a.webm"), "FIRST"),
+            ("file", Some("b.webm"), "NAMED"),
+        ]);
+        assert_eq!(extract(req).await.unwrap(), b"NAMED");
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_first_file_field() {
+        let req = multipart_request(&[
+            ("title", None, "x"),
+            ("recording", Some("a.webm"), "RECORDING"),
+        ]);
+        assert_eq!(extract(req).await.unwrap(), b"RECORDING");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_form_without_file() {
+        let req = multipart_request(&[("title", None, "x")]);
+        assert_eq!(extract(req).await.unwrap_err(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_accepts_raw_audio_body() {
+        let req = Request::builder()
+            .header("content-type", "audio/webm")
+            .body(Body::from("RAW"))
+            .unwrap();
+        assert_eq!(extract(req).await.unwrap(), b"RAW");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unsupported_content_type() {
+        let req = Request::builder()
+            .header("content-type", "text/plain")
+            .body(Body::from("hello"))
+            .unwrap();
+        assert_eq!(
+            extract(req).await.unwrap_err(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+}
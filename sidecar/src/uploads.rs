@@ -0,0 +1,637 @@
+//! Resumable chunked uploads for VoiceMark sidecar.
+//!
+//! Large recordings are uploaded in pieces so a dropped connection only
+//! costs the chunk in flight:
+//!
+//! 1. `POST /uploads?length=<bytes>` starts an upload and returns its ID.
+//! 2. `PATCH /uploads/:id?offset=<n>` appends the raw request body at `n`,
+//!    which must equal the bytes received so far. After a drop, clients ask
+//!    `GET /uploads/:id` for the current `offset` and resume from there.
+//! 3. `POST /uploads/:id/complete` queues a job for the assembled file and
+//!    takes the same query parameters as `POST /jobs`. The upload is kept
+//!    until the job is queued, so a failed attempt can be retried.
+//!
+//! An upload belongs to the tenant that created it; other tenants get `404`
+//! for it.
+//!
+//! Chunks are appended to `<dir>/<id>.part` (`<data_dir>/uploads`, or the
+//! system temp directory). Upload state lives in memory; uploads idle for
+//! longer than [`UPLOAD_TTL`] are discarded.
+
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::{Path, Query, rejection::QueryRejection},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{info, instrument, warn};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::BatchQuery;
+use crate::access_log::Entry;
+use crate::email;
+use crate::error::{ApiError, Problem};
+use crate::jobs::{self, AnalysisQuery, Job, JobMetadata, MetadataQuery};
+use crate::tenants::{self, Tenant};
+use crate::transcribe::{DecodingParams, Segmentation};
+use crate::upload::AudioFile;
+
+/// Maximum total size of an upload. The assembled file is read into memory
+/// when the upload completes.
+pub const MAX_UPLOAD_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Uploads that receive no chunk for this long are discarded.
+pub const UPLOAD_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How often idle uploads are looked for.
+pub const CLEANUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// An upload as reported by the `/uploads` endpoints.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Upload {
+    pub id: String,
+    /// Bytes received so far; the next chunk must start here.
+    pub offset: u64,
+    /// Declared total size, if given when the upload was created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+}
+
+/// Query parameters of `POST /uploads`.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CreateQuery {
+    /// Total size of the file in bytes, if known.
+    length: Option<u64>,
+}
+
+/// Query parameters of `PATCH /uploads/:id`.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AppendQuery {
+    /// Position of this chunk in the file; must equal the upload's `offset`.
+    offset: u64,
+}
+
+/// In-memory state of an upload.
+#[derive(Debug)]
+struct UploadState {
+    offset: u64,
+    length: Option<u64>,
+    /// Whether a chunk is being written.
+    busy: bool,
+    updated: Instant,
+    /// Tenant that created the upload; `None` without authentication.
+    tenant: Option<String>,
+}
+
+impl UploadState {
+    fn snapshot(&self, id: &str) -> Upload {
+        Upload {
+            id: id.to_string(),
+            offset: self.offset,
+            length: self.length,
+        }
+    }
+}
+
+/// Upload table. File I/O happens outside the lock; `busy` keeps chunks of
+/// one upload from being written concurrently.
+#[derive(Debug, Default)]
+struct UploadRegistry {
+    uploads: HashMap<String, UploadState>,
+}
+
+impl UploadRegistry {
+    fn create(
+        &mut self,
+        length: Option<u64>,
+        tenant: Option<String>,
+        now: Instant,
+    ) -> Result<Upload, ApiError> {
+        if length.is_some_and(|length| length > MAX_UPLOAD_BYTES) {
+            return Err(ApiError::AudioTooLarge);
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        let state = UploadState {
+            offset: 0,
+            length,
+            busy: false,
+            updated: now,
+            tenant,
+        };
+        let upload = state.snapshot(&id);
+        self.uploads.insert(id, state);
+        Ok(upload)
+    }
+
+    /// `404` unless upload `id` exists and `caller` may see it.
+    fn check_access(&self, id: &str, caller: Option<&Tenant>) -> Result<(), ApiError> {
+        match self.uploads.get(id) {
+            Some(state) if tenants::can_access(caller, state.tenant.as_deref()) => Ok(()),
+            _ => Err(ApiError::UploadNotFound(id.to_string())),
+        }
+    }
+
+    fn get(&self, id: &str) -> Result<Upload, ApiError> {
+        self.uploads
+            .get(id)
+            .map(|state| state.snapshot(id))
+            .ok_or_else(|| ApiError::UploadNotFound(id.to_string()))
+    }
+
+    /// Reserve the upload for writing `len` bytes at `offset`.
+    fn begin_append(&mut self, id: &str, offset: u64, len: u64) -> Result<(), ApiError> {
+        let state = self
+            .uploads
+            .get_mut(id)
+            .ok_or_else(|| ApiError::UploadNotFound(id.to_string()))?;
+        if state.busy {
+            return Err(ApiError::UploadConflict(
+                "Another chunk of this upload is being written".to_string(),
+            ));
+        }
+        if offset != state.offset {
+            return Err(ApiError::UploadConflict(format!(
+                "Chunk offset {} does not match the upload offset {}",
+                offset, state.offset
+            )));
+        }
+        let end = state.offset + len;
+        if end > state.length.unwrap_or(MAX_UPLOAD_BYTES) {
+            return Err(ApiError::AudioTooLarge);
+        }
+        state.busy = true;
+        Ok(())
+    }
+
+    /// Release the upload after a write, advancing it by `written` bytes.
+    fn finish_append(&mut self, id: &str, written: u64, now: Instant) -> Option<Upload> {
+        let state = self.uploads.get_mut(id)?;
+        state.busy = false;
+        state.offset += written;
+        state.updated = now;
+        Some(state.snapshot(id))
+    }
+
+    /// Reserve a finished upload for completion, checking that it received
+    /// all its bytes. Release it with [`Self::finish_append`] if completion
+    /// fails, or remove it with [`Self::remove`] once its job is queued.
+    fn begin_complete(&mut self, id: &str) -> Result<Upload, ApiError> {
+        let upload = self.get(id)?;
+        let state = &self.uploads[id];
+        if state.busy {
+            return Err(ApiError::UploadConflict(
+                "A chunk of this upload is still being written".to_string(),
+            ));
+        }
+        if state.length.is_some_and(|length| length != state.offset) {
+            return Err(ApiError::UploadConflict(format!(
+                "Upload is incomplete: received {} of {} bytes",
+                state.offset,
+                state.length.unwrap_or_default()
+            )));
+        }
+        self.uploads.get_mut(id).unwrap().busy = true;
+        Ok(upload)
+    }
+
+    /// Remove a completed upload.
+    fn remove(&mut self, id: &str) {
+        self.uploads.remove(id);
+    }
+
+    /// Remove an upload that is not being written to.
+    fn cancel(&mut self, id: &str) -> Result<(), ApiError> {
+        match self.uploads.get(id) {
+            None => Err(ApiError::UploadNotFound(id.to_string())),
+            Some(state) if state.busy => Err(ApiError::UploadConflict(
+                "A chunk of this upload is being written".to_string(),
+            )),
+            Some(_) => {
+                self.uploads.remove(id);
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove idle uploads, returning their IDs.
+    fn remove_stale(&mut self, now: Instant) -> Vec<String> {
+        let stale: Vec<String> = self
+            .uploads
+            .iter()
+            .filter(|(_, state)| !state.busy && now.duration_since(state.updated) > UPLOAD_TTL)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &stale {
+            self.uploads.remove(id);
+        }
+        stale
+    }
+}
+
+/// Global upload registry.
+static UPLOADS: OnceLock<Mutex<UploadRegistry>> = OnceLock::new();
+
+/// Directory for partial uploads.
+static UPLOAD_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+fn registry() -> &'static Mutex<UploadRegistry> {
+    UPLOADS.get_or_init(|| Mutex::new(UploadRegistry::default()))
+}
+
+fn upload_dir() -> &'static PathBuf {
+    UPLOAD_DIR.get_or_init(|| std::env::temp_dir().join("voicemark-uploads"))
+}
+
+/// Store partial uploads under `dir`, discarding leftovers from a previous
+/// run (upload state does not survive restarts). Call once at startup.
+pub fn configure(dir: PathBuf) -> anyhow::Result<()> {
+    std::fs::create_dir_all(&dir)?;
+    for entry in std::fs::read_dir(&dir)?.flatten() {
+        if entry.path().extension().is_some_and(|ext| ext == "part") {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+    if UPLOAD_DIR.set(dir).is_err() {
+        warn!("Upload directory already configured");
+    }
+    Ok(())
+}
+
+/// Path of an upload's data file, or `None` for an invalid ID.
+fn part_path(id: &str) -> Option<PathBuf> {
+    // IDs are UUIDs; reject anything else so it can't escape the directory
+    uuid::Uuid::parse_str(id).ok()?;
+    Some(upload_dir().join(format!("{}.part", id)))
+}
+
+/// Discard uploads that have been idle for longer than [`UPLOAD_TTL`].
+/// Returns the number of uploads removed.
+pub fn remove_stale_uploads() -> usize {
+    let stale = registry().lock().unwrap().remove_stale(Instant::now());
+    for id in &stale {
+        if let Some(path) = part_path(id) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+    stale.len()
+}
+
+/// An upload being completed. Released for another attempt when dropped,
+/// unless its job was queued.
+struct Completing {
+    id: String,
+    path: PathBuf,
+    queued: bool,
+}
+
+impl Completing {
+    /// Forget the upload and delete its data, now that its job is queued.
+    async fn queued(mut self) {
+        self.queued = true;
+        registry().lock().unwrap().remove(&self.id);
+        let _ = tokio::fs::remove_file(&self.path).await;
+    }
+}
+
+impl Drop for Completing {
+    fn drop(&mut self) {
+        if !self.queued {
+            registry().lock().unwrap().finish_append(&self.id, 0, Instant::now());
+        }
+    }
+}
+
+/// Append `chunk` to the upload's data file at `offset`, truncating any
+/// partial write on failure.
+fn write_chunk(path: &std::path::Path, offset: u64, chunk: &[u8]) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+    file.set_len(offset)?;
+    let result = file.write_all(chunk).and_then(|()| file.sync_data());
+    if result.is_err() {
+        let _ = file.set_len(offset);
+    }
+    result
+}
+
+/// Upload creation endpoint (`POST /uploads`).
+#[utoipa::path(
+    post,
+    path = "/uploads",
+    tag = "uploads",
+    params(CreateQuery),
+    responses(
+        (status = 201, description = "Upload created", body = Upload),
+        (status = 400, description = "Invalid request", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 413, description = "Declared length exceeds the maximum upload size", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn create_upload(
+    tenant: Option<Extension<Tenant>>,
+    query: Result<Query<CreateQuery>, QueryRejection>,
+) -> Result<(StatusCode, Json<Upload>), ApiError> {
+    let Query(query) = query?;
+    tokio::fs::create_dir_all(upload_dir())
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to create upload directory: {}", e)))?;
+
+    let tenant = tenant.map(|Extension(tenant)| tenant.tenant);
+    let upload = registry().lock().unwrap().create(query.length, tenant, Instant::now())?;
+    info!(upload_id = %upload.id, length = ?upload.length, "Upload created");
+    Ok((StatusCode::CREATED, Json(upload)))
+}
+
+/// Upload status endpoint (`GET /uploads/:id`).
+#[utoipa::path(
+    get,
+    path = "/uploads/{id}",
+    tag = "uploads",
+    params(("id" = String, Path, description = "Upload ID")),
+    responses(
+        (status = 200, description = "Upload with the offset to resume from", body = Upload),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown, completed or expired upload, or another tenant's", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn upload_status(
+    Path(id): Path<String>,
+    tenant: Option<Extension<Tenant>>,
+) -> Result<Json<Upload>, ApiError> {
+    let registry = registry().lock().unwrap();
+    registry.check_access(&id, tenant.as_deref())?;
+    registry.get(&id).map(Json)
+}
+
+/// Chunk upload endpoint (`PATCH /uploads/:id`).
+#[utoipa::path(
+    patch,
+    path = "/uploads/{id}",
+    tag = "uploads",
+    params(("id" = String, Path, description = "Upload ID"), AppendQuery),
+    request_body(
+        description = "The next chunk of the file",
+        content((AudioFile = "application/octet-stream")),
+    ),
+    responses(
+        (status = 200, description = "Chunk stored", body = Upload),
+        (status = 400, description = "Invalid request", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown, completed or expired upload, or another tenant's", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "Offset mismatch, or another chunk is being written", body = Problem, content_type = "application/problem+json"),
+        (status = 413, description = "Chunk exceeds the declared length or maximum upload size", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+#[instrument(skip(tenant, query, chunk))]
+pub async fn append_chunk(
+    Path(id): Path<String>,
+    tenant: Option<Extension<Tenant>>,
+    query: Result<Query<AppendQuery>, QueryRejection>,
+    chunk: Bytes,
+) -> Result<Json<Upload>, ApiError> {
+    let Query(query) = query?;
+    let path = part_path(&id).ok_or_else(|| ApiError::UploadNotFound(id.clone()))?;
+    {
+        let mut registry = registry().lock().unwrap();
+        registry.check_access(&id, tenant.as_deref())?;
+        registry.begin_append(&id, query.offset, chunk.len() as u64)?;
+    }
+
+    let offset = query.offset;
+    let len = chunk.len() as u64;
+    let written = tokio::task::spawn_blocking(move || write_chunk(&path, offset, &chunk)).await;
+    let written = match written {
+        Ok(Ok(())) => len,
+        Ok(Err(e)) => {
+            registry().lock().unwrap().finish_append(&id, 0, Instant::now());
+            return Err(ApiError::Internal(format!("Failed to store chunk: {}", e)));
+        }
+        Err(e) => {
+            registry().lock().unwrap().finish_append(&id, 0, Instant::now());
+            return Err(ApiError::Internal(e.to_string()));
+        }
+    };
+
+    registry()
+        .lock()
+        .unwrap()
+        .finish_append(&id, written, Instant::now())
+        .map(Json)
+        .ok_or(ApiError::UploadNotFound(id))
+}
+
+/// Upload completion endpoint (`POST /uploads/:id/complete`).
+///
+/// Queues a job for the uploaded file, like `POST /jobs`. The upload is
+/// discarded once the job is queued; if queueing fails, it is kept and
+/// completion can be retried.
+#[utoipa::path(
+    post,
+    path = "/uploads/{id}/complete",
+    tag = "uploads",
+    params(
+        ("id" = String, Path, description = "Upload ID"),
+        BatchQuery, MetadataQuery, AnalysisQuery, DecodingParams, Segmentation,
+    ),
+    responses(
+        (status = 202, description = "Job queued (or already completed from the cache)", body = Job),
+        (status = 400, description = "Invalid request or empty upload", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 402, description = "Daily audio quota used up", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown, completed or expired upload, or another tenant's", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "Upload is incomplete or a chunk is being written", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Unsupported or corrupt audio", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "The assembled upload couldn't be read or doesn't match the bytes received", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "ffmpeg needed but unavailable, analysis needs an LLM and none is configured, or email was requested and SMTP isn't configured", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+#[instrument(skip(tenant, entry, query, metadata, analysis, decoding, segmentation))]
+#[allow(clippy::too_many_arguments)] // one per extractor
+pub async fn complete_upload(
+    Path(id): Path<String>,
+    tenant: Option<Extension<Tenant>>,
+    entry: Option<Extension<Entry>>,
+    query: Result<Query<BatchQuery>, QueryRejection>,
+    metadata: Result<Query<MetadataQuery>, QueryRejection>,
+    analysis: Result<Query<AnalysisQuery>, QueryRejection>,
+    decoding: Result<Query<DecodingParams>, QueryRejection>,
+    segmentation: Result<Query<Segmentation>, QueryRejection>,
+) -> Result<(StatusCode, Json<Job>), ApiError> {
+    let Query(query) = query?;
+    let Query(metadata) = metadata?;
+    let metadata = JobMetadata::from_query(metadata)?;
+    email::validate(&metadata)?;
+    let Query(analysis) = analysis?;
+    analysis.validate()?;
+    let Query(decoding) = decoding?;
+    let Query(segmentation) = segmentation?;
+    let options = crate::batch_options(query, decoding, segmentation)?;
+    let path = part_path(&id).ok_or_else(|| ApiError::UploadNotFound(id.clone()))?;
+
+    let upload = {
+        let mut registry = registry().lock().unwrap();
+        registry.check_access(&id, tenant.as_deref())?;
+        registry.begin_complete(&id)?
+    };
+    let completing = Completing { id: id.clone(), path, queued: false };
+    let audio_bytes = tokio::fs::read(&completing.path)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to read upload {}: {}", id, e)))?;
+    if audio_bytes.is_empty() {
+        return Err(ApiError::EmptyAudio);
+    }
+    if audio_bytes.len() as u64 != upload.offset {
+        return Err(ApiError::Internal(format!(
+            "Upload {} has {} bytes stored but {} were received",
+            id,
+            audio_bytes.len(),
+            upload.offset
+        )));
+    }
+
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    let entry = entry.map(|Extension(entry)| entry);
+    let bytes = audio_bytes.len();
+    let job = jobs::queue_job(tenant, entry, audio_bytes, options, metadata, analysis).await?;
+    completing.queued().await;
+    info!(upload_id = %id, bytes, job_id = %job.id, "Upload completed");
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Upload cancellation endpoint (`DELETE /uploads/:id`).
+#[utoipa::path(
+    delete,
+    path = "/uploads/{id}",
+    tag = "uploads",
+    params(("id" = String, Path, description = "Upload ID")),
+    responses(
+        (status = 204, description = "Upload discarded"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown, completed or expired upload, or another tenant's", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "A chunk is being written", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn cancel_upload(
+    Path(id): Path<String>,
+    tenant: Option<Extension<Tenant>>,
+) -> Result<StatusCode, ApiError> {
+    let path = part_path(&id).ok_or_else(|| ApiError::UploadNotFound(id.clone()))?;
+    {
+        let mut registry = registry().lock().unwrap();
+        registry.check_access(&id, tenant.as_deref())?;
+        registry.cancel(&id)?;
+    }
+    let _ = tokio::fs::remove_file(&path).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_requires_matching_offset() {
+        let mut registry = UploadRegistry::default();
+        let now = Instant::now();
+        let upload = registry.create(Some(10), None, now).unwrap();
+        assert_eq!(upload.offset, 0);
+
+        registry.begin_append(&upload.id, 0, 4).unwrap();
+        // A second chunk can't be written while the first is in flight
+        assert_eq!(
+            registry.begin_append(&upload.id, 0, 4).unwrap_err().code(),
+            "upload_conflict"
+        );
+        assert_eq!(registry.finish_append(&upload.id, 4, now).unwrap().offset, 4);
+
+        // Resending the first chunk after a drop is rejected with the offset
+        let err = registry.begin_append(&upload.id, 0, 4).unwrap_err();
+        assert!(err.to_string().contains("upload offset 4"));
+        registry.begin_append(&upload.id, 4, 6).unwrap();
+        registry.finish_append(&upload.id, 6, now);
+
+        assert_eq!(registry.begin_complete(&upload.id).unwrap().offset, 10);
+        // Completing holds the upload until its job is queued or it fails
+        assert_eq!(
+            registry.begin_append(&upload.id, 10, 0).unwrap_err().code(),
+            "upload_conflict"
+        );
+        assert_eq!(registry.cancel(&upload.id).unwrap_err().code(), "upload_conflict");
+        registry.finish_append(&upload.id, 0, now);
+        assert_eq!(registry.begin_complete(&upload.id).unwrap().offset, 10);
+        registry.remove(&upload.id);
+        assert_eq!(registry.get(&upload.id).unwrap_err().code(), "upload_not_found");
+    }
+
+    #[test]
+    fn test_upload_length_limits() {
+        let mut registry = UploadRegistry::default();
+        let now = Instant::now();
+        assert_eq!(
+            registry.create(Some(MAX_UPLOAD_BYTES + 1), None, now).unwrap_err().code(),
+            "audio_too_large"
+        );
+
+        let upload = registry.create(Some(4), None, now).unwrap();
+        assert_eq!(
+            registry.begin_append(&upload.id, 0, 5).unwrap_err().code(),
+            "audio_too_large"
+        );
+        registry.begin_append(&upload.id, 0, 2).unwrap();
+        registry.finish_append(&upload.id, 2, now);
+        // Completing before all declared bytes arrived is a conflict
+        assert_eq!(registry.begin_complete(&upload.id).unwrap_err().code(), "upload_conflict");
+    }
+
+    #[test]
+    fn test_uploads_belong_to_their_tenant() {
+        let mut registry = UploadRegistry::default();
+        let upload = registry.create(None, Some("acme".to_string()), Instant::now()).unwrap();
+        let tenant = |name: &str| Tenant {
+            key_id: format!("k-{}", name),
+            tenant: name.to_string(),
+            quota: Default::default(),
+        };
+
+        assert!(registry.check_access(&upload.id, Some(&tenant("acme"))).is_ok());
+        assert!(registry.check_access(&upload.id, None).is_ok());
+        let err = registry.check_access(&upload.id, Some(&tenant("globex"))).unwrap_err();
+        assert_eq!(err.code(), "upload_not_found");
+        assert_eq!(
+            registry.check_access("missing", None).unwrap_err().code(),
+            "upload_not_found"
+        );
+    }
+
+    #[test]
+    fn test_remove_stale_uploads() {
+        let mut registry = UploadRegistry::default();
+        let start = Instant::now();
+        let old = registry.create(None, None, start).unwrap();
+        let busy = registry.create(None, None, start).unwrap();
+        registry.begin_append(&busy.id, 0, 1).unwrap();
+        let later = start + UPLOAD_TTL + Duration::from_secs(1);
+        let fresh = registry.create(None, None, later).unwrap();
+
+        assert_eq!(registry.remove_stale(later), vec![old.id]);
+        assert!(registry.get(&busy.id).is_ok());
+        assert!(registry.get(&fresh.id).is_ok());
+    }
+
+    #[test]
+    fn test_write_chunk_truncates_to_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("upload.part");
+        write_chunk(&path, 0, b"hello").unwrap();
+        // Bytes past the offset (e.g. from an interrupted write) are dropped
+        write_chunk(&path, 3, b"p!").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"help!");
+    }
+}
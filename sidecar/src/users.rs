@@ -0,0 +1,265 @@
+//! Per-user data export and erasure for VoiceMark sidecar.
+//!
+//! VoiceMark has no accounts of its own: a user is whoever the `user_id`
+//! key of a job's metadata names (`POST /jobs?metadata={"user_id":"u-7"}`),
+//! which is kept with the job's transcript. Everything stored for those
+//! jobs can be handed over or erased on request:
+//!
+//! - `GET /users/:id/export` - A zip of the user's transcripts (with their
+//!   metadata, corrections and summaries), retained audio and in-memory jobs
+//! - `DELETE /users/:id/data` - Delete all of that, with the transcripts'
+//!   search embeddings and fingerprints
+//!
+//! Both reach every tenant's data, so they require the admin token (see
+//! [`crate::admin`]); tenant API keys get `403`.
+//!
+//! Erasure is refused with `409` (`jobs_running`) while one of the user's
+//! jobs is unfinished, as it would save its transcript afterwards. Deleting
+//! a transcript also removes its cached result. Session recordings and
+//! profile vocabularies aren't tied to a user and are left alone.
+
+use anyhow::Result;
+use axum::{
+    Json,
+    body::Body,
+    extract::Path,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::io::{Cursor, Write};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+use zip::CompressionMethod;
+use zip::write::SimpleFileOptions;
+
+use crate::clock;
+use crate::encryption::{self, Kind};
+use crate::error::{ApiError, Problem};
+use crate::jobs::{self, Job};
+use crate::transcripts::{self, Transcript};
+
+/// Metadata key naming the user a job belongs to.
+pub const USER_KEY: &str = "user_id";
+
+/// What `DELETE /users/:id/data` removed.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserErasure {
+    /// Transcripts deleted, with their retained audio, embeddings and
+    /// fingerprints.
+    pub transcripts: usize,
+    /// Jobs forgotten.
+    pub jobs: usize,
+}
+
+/// Table of contents of an export (`user.json`).
+#[derive(Debug, Serialize)]
+struct Manifest<'a> {
+    user_id: &'a str,
+    /// Export time (Unix milliseconds).
+    exported_at: u64,
+    transcripts: Vec<&'a str>,
+    audio: Vec<&'a str>,
+    jobs: Vec<&'a str>,
+}
+
+/// A transcript's retained audio file.
+struct Audio {
+    name: String,
+    bytes: Vec<u8>,
+}
+
+/// Everything stored for one user.
+struct UserData {
+    user_id: String,
+    /// Transcripts, newest first, with their retained audio if still there.
+    transcripts: Vec<(Transcript, Option<Audio>)>,
+    jobs: Vec<Job>,
+}
+
+fn belongs_to(user_id: &str) -> impl Fn(&jobs::JobMetadata) -> bool + '_ {
+    move |metadata| metadata.has(USER_KEY, user_id)
+}
+
+/// Gather everything stored for `user_id`.
+fn collect(user_id: &str) -> UserData {
+    let transcripts = transcripts::with_metadata(USER_KEY, user_id)
+        .into_iter()
+        .map(|transcript| {
+            let audio = transcripts::audio_path(&transcript).and_then(|path| {
+                let name = path.file_name()?.to_string_lossy().into_owned();
+                Some(Audio { name, bytes: encryption::read(path, Kind::Audio).ok()? })
+            });
+            (transcript, audio)
+        })
+        .collect();
+    UserData {
+        user_id: user_id.to_string(),
+        transcripts,
+        jobs: jobs::find_jobs(belongs_to(user_id)),
+    }
+}
+
+/// `data` as a zip: `user.json`, then `transcripts/<id>.json`,
+/// `audio/<file>` and `jobs/<id>.json`.
+fn archive(data: &UserData, exported_at: u64) -> Result<Vec<u8>> {
+    let manifest = Manifest {
+        user_id: &data.user_id,
+        exported_at,
+        transcripts: data.transcripts.iter().map(|(t, _)| t.id.as_str()).collect(),
+        audio: data
+            .transcripts
+            .iter()
+            .filter_map(|(_, audio)| Some(audio.as_ref()?.name.as_str()))
+            .collect(),
+        jobs: data.jobs.iter().map(|job| job.id.as_str()).collect(),
+    };
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+    zip.start_file("user.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    for (transcript, audio) in &data.transcripts {
+        zip.start_file(format!("transcripts/{}.json", transcript.id), options)?;
+        zip.write_all(&serde_json::to_vec_pretty(transcript)?)?;
+        if let Some(audio) = audio {
+            // Audio is compressed already
+            let stored = options.compression_method(CompressionMethod::Stored);
+            zip.start_file(format!("audio/{}", audio.name), stored)?;
+            zip.write_all(&audio.bytes)?;
+        }
+    }
+    for job in &data.jobs {
+        zip.start_file(format!("jobs/{}.json", job.id), options)?;
+        zip.write_all(&serde_json::to_vec_pretty(job)?)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Delete everything stored for `user_id`.
+fn erase(user_id: &str) -> Result<UserErasure, ApiError> {
+    let jobs = jobs::find_jobs(belongs_to(user_id));
+    let unfinished = jobs.iter().filter(|job| !job.status.is_finished()).count();
+    if unfinished > 0 {
+        return Err(ApiError::JobsRunning(format!(
+            "User '{}' has {} unfinished jobs; retry once they have finished",
+            user_id, unfinished
+        )));
+    }
+
+    let mut transcripts = 0;
+    for transcript in transcripts::with_metadata(USER_KEY, user_id) {
+        match transcripts::delete(&transcript.id) {
+            Ok(()) => transcripts += 1,
+            // Pruned in the meantime
+            Err(ApiError::TranscriptNotFound(_)) => {}
+            Err(e) => {
+                warn!(id = %transcript.id, "Failed to erase transcript: {}", e);
+                return Err(e);
+            }
+        }
+    }
+    let ids: Vec<String> = jobs.into_iter().map(|job| job.id).collect();
+    let jobs = jobs::forget_jobs(&ids);
+    info!(transcripts, jobs, "User data erased");
+    Ok(UserErasure { transcripts, jobs })
+}
+
+/// User data export endpoint (`GET /users/:id/export`).
+///
+/// A zip of everything stored for jobs whose metadata has this `user_id`:
+/// `user.json` (what's included), `transcripts/<id>.json`, `audio/<file>`
+/// for retained audio and `jobs/<id>.json` for jobs still in memory. A user
+/// with no data gets a zip with just `user.json`.
+#[utoipa::path(
+    get,
+    path = "/users/{id}/export",
+    tag = "users",
+    security(("admin_token" = [])),
+    params(("id" = String, Path, description = "User ID, the `user_id` of job metadata")),
+    responses(
+        (status = 200, description = "The user's data as a zip", body = Vec<u8>, content_type = "application/zip"),
+        (status = 401, description = "Missing or invalid admin token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Sent an API key instead of the admin token", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn export_user(Path(id): Path<String>) -> Result<Response, ApiError> {
+    let exported_at = clock::now_millis();
+    let bytes = tokio::task::spawn_blocking(move || archive(&collect(&id), exported_at))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map_err(|e| ApiError::Internal(format!("Failed to export user data: {:#}", e)))?;
+    let headers = [
+        (header::CONTENT_TYPE, "application/zip"),
+        (header::CONTENT_DISPOSITION, "attachment; filename=\"voicemark-export.zip\""),
+    ];
+    Ok((headers, Body::from(bytes)).into_response())
+}
+
+/// User data erasure endpoint (`DELETE /users/:id/data`).
+///
+/// Deletes the transcripts of jobs whose metadata has this `user_id`, with
+/// their retained audio, embeddings and fingerprints, and forgets the jobs.
+#[utoipa::path(
+    delete,
+    path = "/users/{id}/data",
+    tag = "users",
+    security(("admin_token" = [])),
+    params(("id" = String, Path, description = "User ID, the `user_id` of job metadata")),
+    responses(
+        (status = 200, description = "What was deleted", body = UserErasure),
+        (status = 401, description = "Missing or invalid admin token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Sent an API key instead of the admin token", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The user has unfinished jobs", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn delete_user_data(Path(id): Path<String>) -> Result<Json<UserErasure>, ApiError> {
+    tokio::task::spawn_blocking(move || erase(&id))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map(Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcribe::TranscribeOptions;
+
+    #[test]
+    fn test_archive_contents() {
+        let transcript: Transcript = serde_json::from_value(serde_json::json!({
+            "id": "t-1",
+            "created_at": 0,
+            "text": "Hello world",
+            "segments": 1,
+            "options": TranscribeOptions::default(),
+            "audio": "t-1.webm",
+            "metadata": { "user_id": "u-7" },
+        }))
+        .unwrap();
+        let data = UserData {
+            user_id: "u-7".to_string(),
+            transcripts: vec![(
+                transcript,
+                Some(Audio { name: "t-1.webm".to_string(), bytes: vec![1, 2, 3] }),
+            )],
+            jobs: Vec::new(),
+        };
+
+        let bytes = archive(&data, 42).unwrap();
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let names: Vec<&str> = zip.file_names().collect();
+        assert_eq!(names.len(), 3);
+        for name in ["user.json", "transcripts/t-1.json", "audio/t-1.webm"] {
+            assert!(names.contains(&name), "{:?}", names);
+        }
+        let manifest: serde_json::Value =
+            serde_json::from_reader(zip.by_name("user.json").unwrap()).unwrap();
+        assert_eq!(manifest["user_id"], "u-7");
+        assert_eq!(manifest["exported_at"], 42);
+        assert_eq!(manifest["transcripts"], serde_json::json!(["t-1"]));
+        let transcript: serde_json::Value =
+            serde_json::from_reader(zip.by_name("transcripts/t-1.json").unwrap()).unwrap();
+        assert_eq!(transcript["metadata"]["user_id"], "u-7");
+    }
+}
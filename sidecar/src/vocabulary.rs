@@ -0,0 +1,299 @@
+//! Learned vocabulary for VoiceMark sidecar.
+//!
+//! Corrections made with `PATCH /transcripts/:id` teach the sidecar the words
+//! whisper gets wrong for a profile (usually names). The most frequent ones
+//! are passed to whisper as the initial prompt for that profile's future
+//! requests, which biases decoding toward the corrected spellings.
+//!
+//! Each profile's vocabulary is stored as `<data_dir>/profiles/<profile>.json`.
+//!
+//! `/stream` clients can also bias a session toward terms of their own with
+//! `vocabulary` messages (see [`session_prompt`]), e.g. the names in the
+//! document being dictated into.
+
+use axum::{Json, extract::Path};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
+
+use crate::clock::now_millis;
+use crate::error::{ApiError, Problem};
+
+/// Longest prompt built from a vocabulary. Whisper only uses the last 224
+/// prompt tokens, so longer prompts would lose the most frequent terms.
+const MAX_PROMPT_CHARS: usize = 600;
+
+/// Most terms kept per profile; the least used are dropped beyond this.
+const MAX_TERMS: usize = 500;
+
+/// Longest accepted profile name.
+const MAX_PROFILE_LEN: usize = 64;
+
+/// Directory holding profile vocabularies, unset when persistence is disabled.
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Serializes read-modify-write updates of vocabulary files.
+static UPDATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// A profile's learned vocabulary.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Vocabulary {
+    /// Terms, most frequently corrected first.
+    pub terms: Vec<Term>,
+}
+
+/// A learned term.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Term {
+    pub term: String,
+    /// Number of corrections that introduced this term.
+    pub count: u32,
+    /// Last time it was learned (Unix milliseconds).
+    pub last_seen: u64,
+}
+
+/// Vocabulary endpoint response (`GET /profiles/:profile/vocabulary`).
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VocabularyResponse {
+    pub profile: String,
+    pub terms: Vec<Term>,
+    /// Initial prompt currently used for this profile.
+    pub prompt: Option<String>,
+}
+
+/// Store vocabularies under `data_dir`. Call once at startup.
+pub fn configure(data_dir: PathBuf) -> anyhow::Result<()> {
+    let dir = data_dir.join("profiles");
+    std::fs::create_dir_all(&dir)?;
+    info!(dir = ?dir, "Vocabulary learning enabled");
+    if DIR.set(dir).is_err() {
+        warn!("Vocabulary store already configured");
+    }
+    Ok(())
+}
+
+/// Check that `profile` is a usable profile name (letters, digits, `-`, `_`).
+pub fn validate_profile(profile: &str) -> Result<(), ApiError> {
+    let valid = !profile.is_empty()
+        && profile.len() <= MAX_PROFILE_LEN
+        && profile
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(ApiError::InvalidRequest(format!(
+            "Invalid profile '{}': use up to {} letters, digits, '-' or '_'",
+            profile, MAX_PROFILE_LEN
+        )))
+    }
+}
+
+/// Load a profile's vocabulary (empty if nothing was learned yet).
+pub fn load(profile: &str) -> Vocabulary {
+    let Some(path) = vocabulary_path(profile) else {
+        return Vocabulary::default();
+    };
+    std::fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Initial prompt for a profile, if it has learned any terms.
+pub fn prompt(profile: &str) -> Option<String> {
+    build_prompt(&load(profile))
+}
+
+/// Learn from corrections made for `profile`, given as
+/// `(machine output, corrected text)` pairs.
+pub fn learn(profile: &str, corrections: &[(&str, &str)]) {
+    let terms: Vec<String> = corrections
+        .iter()
+        .flat_map(|(original, corrected)| new_terms(original, corrected))
+        .collect();
+    if terms.is_empty() {
+        return;
+    }
+    let Some(path) = vocabulary_path(profile) else {
+        return;
+    };
+
+    let _guard = UPDATE_LOCK.lock().unwrap();
+    let mut vocabulary = load(profile);
+    add_terms(&mut vocabulary, &terms, now_millis());
+
+    let written = serde_json::to_vec_pretty(&vocabulary)
+        .map_err(std::io::Error::from)
+        .and_then(|json| {
+            let tmp = path.with_extension("json.tmp");
+            std::fs::write(&tmp, json)?;
+            std::fs::rename(&tmp, &path)
+        });
+    match written {
+        Ok(()) => debug!(profile, ?terms, "Learned vocabulary"),
+        Err(e) => warn!(profile, "Failed to save vocabulary: {}", e),
+    }
+}
+
+/// Words in `corrected` that whisper did not produce in `original`.
+fn new_terms(original: &str, corrected: &str) -> Vec<String> {
+    let original: Vec<&str> = words(original).collect();
+    let mut terms: Vec<String> = Vec::new();
+    for word in words(corrected) {
+        let learnable = word.chars().count() >= 2
+            && word.chars().any(char::is_alphabetic)
+            && !word.chars().any(char::is_control);
+        if learnable && !original.contains(&word) && !terms.iter().any(|t| t == word) {
+            terms.push(word.to_string());
+        }
+    }
+    terms
+}
+
+/// Whitespace-separated words with surrounding punctuation removed.
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+}
+
+/// Count `terms` into `vocabulary`, keeping it sorted and bounded.
+fn add_terms(vocabulary: &mut Vocabulary, terms: &[String], now: u64) {
+    for term in terms {
+        match vocabulary.terms.iter_mut().find(|t| &t.term == term) {
+            Some(existing) => {
+                existing.count += 1;
+                existing.last_seen = now;
+            }
+            None => vocabulary.terms.push(Term {
+                term: term.clone(),
+                count: 1,
+                last_seen: now,
+            }),
+        }
+    }
+    vocabulary
+        .terms
+        .sort_by(|a, b| b.count.cmp(&a.count).then(b.last_seen.cmp(&a.last_seen)));
+    vocabulary.terms.truncate(MAX_TERMS);
+}
+
+/// Join the most frequent terms into a prompt of at most [`MAX_PROMPT_CHARS`].
+fn build_prompt(vocabulary: &Vocabulary) -> Option<String> {
+    join_terms(vocabulary.terms.iter().map(|term| term.term.as_str()))
+}
+
+/// Initial prompt for terms a streaming session asked for, most important
+/// first. Blank and repeated terms are skipped.
+pub fn session_prompt(terms: &[String]) -> Option<String> {
+    let mut seen = Vec::new();
+    join_terms(terms.iter().map(|term| term.trim()).filter(|term| {
+        let new = !term.is_empty() && !seen.contains(term);
+        seen.push(*term);
+        new
+    }))
+}
+
+/// Join `terms` into a prompt of at most [`MAX_PROMPT_CHARS`], dropping the
+/// ones that don't fit.
+fn join_terms<'a>(terms: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let mut prompt = String::new();
+    for term in terms {
+        let separator = if prompt.is_empty() { "" } else { ", " };
+        if prompt.len() + separator.len() + term.len() > MAX_PROMPT_CHARS {
+            break;
+        }
+        prompt.push_str(separator);
+        prompt.push_str(term);
+    }
+    (!prompt.is_empty()).then_some(prompt)
+}
+
+fn vocabulary_path(profile: &str) -> Option<PathBuf> {
+    validate_profile(profile).ok()?;
+    Some(DIR.get()?.join(format!("{}.json", profile)))
+}
+
+/// Vocabulary endpoint (`GET /profiles/:profile/vocabulary`).
+#[utoipa::path(
+    get,
+    path = "/profiles/{profile}/vocabulary",
+    tag = "profiles",
+    params(("profile" = String, Path, description = "Profile name (letters, digits, `-`, `_`)")),
+    responses(
+        (status = 200, description = "Learned vocabulary and the prompt built from it", body = VocabularyResponse),
+        (status = 400, description = "Invalid profile name", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn get_vocabulary(
+    Path(profile): Path<String>,
+) -> Result<Json<VocabularyResponse>, ApiError> {
+    validate_profile(&profile)?;
+    let vocabulary = load(&profile);
+    let prompt = build_prompt(&vocabulary);
+    Ok(Json(VocabularyResponse {
+        profile,
+        terms: vocabulary.terms,
+        prompt,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_terms_only_keeps_corrected_words() {
+        assert_eq!(new_terms("Call Dr. Win, please.", "Call Dr. Nguyen, please."), vec!["Nguyen"]);
+        assert_eq!(new_terms("I'm Sean", "I'm Siobhan O'Neill"), vec!["Siobhan", "O'Neill"]);
+        // Punctuation-only and single-letter changes teach nothing
+        assert!(new_terms("hello world", "hello, world!").is_empty());
+        assert!(new_terms("a", "b").is_empty());
+    }
+
+    #[test]
+    fn test_add_terms_orders_by_count() {
+        let mut vocabulary = Vocabulary::default();
+        add_terms(&mut vocabulary, &["Nguyen".to_string()], 1);
+        add_terms(&mut vocabulary, &["Okonkwo".to_string()], 2);
+        add_terms(&mut vocabulary, &["Okonkwo".to_string()], 3);
+
+        let terms: Vec<_> = vocabulary.terms.iter().map(|t| (t.term.as_str(), t.count)).collect();
+        assert_eq!(terms, vec![("Okonkwo", 2), ("Nguyen", 1)]);
+        assert_eq!(build_prompt(&vocabulary).as_deref(), Some("Okonkwo, Nguyen"));
+    }
+
+    #[test]
+    fn test_build_prompt_is_bounded() {
+        let mut vocabulary = Vocabulary::default();
+        let terms: Vec<String> = (0..200).map(|i| format!("Name{}", i)).collect();
+        add_terms(&mut vocabulary, &terms, 1);
+
+        let prompt = build_prompt(&vocabulary).unwrap();
+        assert!(prompt.len() <= MAX_PROMPT_CHARS);
+        assert!(build_prompt(&Vocabulary::default()).is_none());
+    }
+
+    #[test]
+    fn test_session_prompt_skips_blank_and_repeated_terms() {
+        let terms = vec![
+            "Kubernetes".to_string(),
+            " ".to_string(),
+            "Acme Corp ".to_string(),
+            "Kubernetes".to_string(),
+        ];
+        assert_eq!(session_prompt(&terms).as_deref(), Some("Kubernetes, Acme Corp"));
+        assert!(session_prompt(&[]).is_none());
+    }
+
+    #[test]
+    fn test_validate_profile() {
+        assert!(validate_profile("team-a_1").is_ok());
+        assert!(validate_profile("").is_err());
+        assert!(validate_profile("../etc").is_err());
+    }
+}
@@ -0,0 +1,437 @@
+//! Watch-folder ingestion for VoiceMark sidecar.
+//!
+//! With `VOICEMARK_WATCH_DIRS`, the sidecar works as a drop-folder
+//! transcription appliance: audio files copied into a watched directory are
+//! queued as jobs, and their transcripts are written next to them (or to the
+//! directory's output directory) in each of `VOICEMARK_WATCH_FORMATS`, as
+//! `<name>.<format>`.
+//!
+//! Directories are scanned every `VOICEMARK_WATCH_INTERVAL_SECS`. A file is
+//! picked up once it hasn't been modified for [`SETTLE_TIME`], so it isn't
+//! read while still being copied. What was processed is recorded in a
+//! `.voicemark-watch.json` file in the output directory, by name, size and
+//! modification time, so files aren't transcribed again after a restart,
+//! while a replaced file is. Files that can't be transcribed are recorded
+//! with the error and skipped until they change.
+//!
+//! Files whose names differ only in extension (`call.wav`, `call.mp3`)
+//! would write the same transcripts, so only one of them is transcribed:
+//! the one transcribed before, or else the first by name. The others are
+//! recorded as failed.
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::clock;
+use crate::export::FileFormat;
+use crate::jobs::{self, AnalysisQuery, JobMetadata, JobStatus};
+use crate::BatchQuery;
+
+/// Name of the state file in each output directory.
+const STATE_FILE: &str = ".voicemark-watch.json";
+
+/// How long a file must go unmodified before it is picked up.
+pub const SETTLE_TIME: Duration = Duration::from_secs(5);
+
+/// Extensions of the files picked up, lowercase.
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "aac", "aiff", "flac", "m4a", "mkv", "mov", "mp3", "mp4", "oga", "ogg", "opus", "wav", "webm",
+    "wma",
+];
+
+/// A watched directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchFolder {
+    pub dir: PathBuf,
+    /// Where transcripts are written; next to the audio if unset.
+    pub output: Option<PathBuf>,
+}
+
+impl WatchFolder {
+    /// Parse a `VOICEMARK_WATCH_DIRS` entry: `<dir>` or `<dir>=<output dir>`.
+    pub fn parse(entry: &str) -> Option<Self> {
+        let (dir, output) = match entry.split_once('=') {
+            Some((dir, output)) => (dir.trim(), Some(output.trim()).filter(|o| !o.is_empty())),
+            None => (entry.trim(), None),
+        };
+        if dir.is_empty() {
+            return None;
+        }
+        Some(Self { dir: PathBuf::from(dir), output: output.map(PathBuf::from) })
+    }
+
+    fn output_dir(&self) -> &Path {
+        self.output.as_deref().unwrap_or(&self.dir)
+    }
+
+    /// Where the transcript of the file `name` is written in `format`.
+    fn output_path(&self, name: &str, format: FileFormat) -> PathBuf {
+        self.output_dir().join(format!("{}.{}", output_stem(name), format.extension()))
+    }
+}
+
+/// Watch-folder settings.
+#[derive(Debug, Clone)]
+pub struct WatchConfig {
+    pub folders: Vec<WatchFolder>,
+    /// Formats each transcript is written in.
+    pub formats: Vec<FileFormat>,
+    /// Time between scans.
+    pub interval: Duration,
+}
+
+/// What happened to a file, as recorded in the state file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Processed {
+    size: u64,
+    /// Modification time (Unix milliseconds).
+    modified_ms: u64,
+    /// Why it couldn't be transcribed, if it couldn't.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A watched directory's state file: processed files by name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    files: BTreeMap<String, Processed>,
+}
+
+impl State {
+    fn load(path: &Path) -> Self {
+        match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!(path = %path.display(), "Ignoring unreadable watch state: {}", e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+/// A file found in a watched directory, ready to be transcribed.
+#[derive(Debug, Clone, PartialEq)]
+struct Candidate {
+    name: String,
+    size: u64,
+    modified_ms: u64,
+}
+
+/// A file being transcribed.
+struct Pending {
+    folder: usize,
+    file: Candidate,
+    job_id: String,
+}
+
+/// Start watching `config.folders`, creating missing output directories.
+/// Call once at startup, from the runtime, once jobs can run.
+pub fn start(config: WatchConfig) -> Result<()> {
+    if config.formats.is_empty() {
+        bail!("VOICEMARK_WATCH_FORMATS lists no formats");
+    }
+    for (i, folder) in config.folders.iter().enumerate() {
+        if !folder.dir.is_dir() {
+            bail!("Watch directory {} does not exist", folder.dir.display());
+        }
+        if let Some(output) = &folder.output {
+            std::fs::create_dir_all(output)
+                .with_context(|| format!("Failed to create {}", output.display()))?;
+        }
+        if config.folders[..i].iter().any(|f| f.output_dir() == folder.output_dir()) {
+            bail!("Watch directories must not share an output directory");
+        }
+    }
+    info!(
+        dirs = ?config.folders.iter().map(|f| f.dir.display().to_string()).collect::<Vec<_>>(),
+        formats = ?config.formats,
+        "Watch folders enabled"
+    );
+    tokio::spawn(watch(config));
+    Ok(())
+}
+
+/// Scan the folders and write out finished transcripts, forever.
+async fn watch(config: WatchConfig) {
+    let mut states: Vec<State> = config
+        .folders
+        .iter()
+        .map(|folder| State::load(&folder.output_dir().join(STATE_FILE)))
+        .collect();
+    let mut pending: Vec<Pending> = Vec::new();
+    let mut interval = tokio::time::interval(config.interval);
+    loop {
+        interval.tick().await;
+        let mut changed = vec![false; config.folders.len()];
+
+        // Write out what finished since the last scan
+        let mut running = Vec::new();
+        for job in pending.drain(..) {
+            let processed = match jobs::get_job(&job.job_id) {
+                Some(j) if !j.status.is_finished() => {
+                    running.push(job);
+                    continue;
+                }
+                Some(j) if j.status == JobStatus::Completed => {
+                    let folder = config.folders[job.folder].clone();
+                    let formats = config.formats.clone();
+                    let name = job.file.name.clone();
+                    let write = move || write_outputs(&folder, &name, &j, &formats);
+                    match tokio::task::spawn_blocking(write).await {
+                        Ok(Ok(())) => None,
+                        Ok(Err(e)) => Some(format!("{:#}", e)),
+                        Err(e) => Some(e.to_string()),
+                    }
+                }
+                Some(j) => Some(j.error.map(|e| e.detail).unwrap_or_default()),
+                // Evicted before it was seen finishing; picked up again
+                None => continue,
+            };
+            let Candidate { name, size, modified_ms } = job.file;
+            match &processed {
+                None => info!(file = %name, job_id = %job.job_id, "Watched file transcribed"),
+                Some(e) => warn!(file = %name, job_id = %job.job_id, "Watched file failed: {}", e),
+            }
+            let processed = Processed { size, modified_ms, error: processed };
+            states[job.folder].files.insert(name, processed);
+            changed[job.folder] = true;
+        }
+        pending = running;
+
+        // Queue new and changed files
+        for (i, folder) in config.folders.iter().enumerate() {
+            let dir = folder.dir.clone();
+            let Ok(Ok(found)) = tokio::task::spawn_blocking(move || scan(&dir, SystemTime::now()))
+                .await
+            else {
+                warn!(dir = %folder.dir.display(), "Failed to scan watch directory");
+                continue;
+            };
+            for file in &found {
+                let seen = states[i].files.get(&file.name).is_some_and(|processed| {
+                    processed.size == file.size && processed.modified_ms == file.modified_ms
+                });
+                if seen || pending.iter().any(|p| p.folder == i && p.file.name == file.name) {
+                    continue;
+                }
+                let file = file.clone();
+                let queued = match output_clash(&found, &states[i], &file) {
+                    Some(other) => Err(format!(
+                        "Its transcripts would overwrite those of {}; rename one of them",
+                        other
+                    )),
+                    None => submit(folder, &file).await,
+                };
+                match queued {
+                    Ok(job_id) => {
+                        info!(file = %file.name, %job_id, "Watched file queued");
+                        pending.push(Pending { folder: i, file, job_id });
+                    }
+                    Err(e) => {
+                        warn!(file = %file.name, "Watched file failed: {}", e);
+                        let Candidate { name, size, modified_ms } = file;
+                        let processed = Processed { size, modified_ms, error: Some(e) };
+                        states[i].files.insert(name, processed);
+                        changed[i] = true;
+                    }
+                }
+            }
+        }
+
+        for (i, folder) in config.folders.iter().enumerate() {
+            if !changed[i] {
+                continue;
+            }
+            let path = folder.output_dir().join(STATE_FILE);
+            if let Err(e) = states[i].save(&path) {
+                warn!(path = %path.display(), "Failed to save watch state: {}", e);
+            }
+        }
+    }
+}
+
+/// Audio files in `dir` that haven't been modified for [`SETTLE_TIME`]
+/// before `now`, by name.
+fn scan(dir: &Path, now: SystemTime) -> std::io::Result<Vec<Candidate>> {
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if name.starts_with('.') || !metadata.is_file() || metadata.len() == 0 || !is_audio(&name)
+        {
+            continue;
+        }
+        let Ok(modified) = metadata.modified() else {
+            continue;
+        };
+        if now.duration_since(modified).unwrap_or_default() < SETTLE_TIME {
+            continue;
+        }
+        let modified_ms = clock::unix_millis(modified);
+        found.push(Candidate { name, size: metadata.len(), modified_ms });
+    }
+    found.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(found)
+}
+
+/// Whether `name` has an audio or video extension.
+fn is_audio(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// `name` without its extension, which its transcripts are named after.
+fn output_stem(name: &str) -> &str {
+    Path::new(name).file_stem().and_then(|s| s.to_str()).unwrap_or(name)
+}
+
+/// The file in `found` whose transcripts have the same names as `file`'s
+/// and take precedence: it was transcribed according to `state`, or it
+/// comes first by name and hasn't failed.
+fn output_clash<'a>(found: &'a [Candidate], state: &State, file: &Candidate) -> Option<&'a str> {
+    let stem = output_stem(&file.name);
+    found.iter().map(|other| other.name.as_str()).find(|&other| {
+        if other == file.name || output_stem(other) != stem {
+            return false;
+        }
+        match state.files.get(other) {
+            Some(processed) => processed.error.is_none(),
+            None => other < file.name.as_str(),
+        }
+    })
+}
+
+/// Queue `file` as a job titled with its name, returning the job ID.
+async fn submit(folder: &WatchFolder, file: &Candidate) -> Result<String, String> {
+    let path = folder.dir.join(&file.name);
+    let title = output_stem(&file.name);
+    let mut metadata = JobMetadata::default();
+    metadata.metadata.insert("title".to_string(), title.into());
+    metadata.metadata.insert("watch_file".to_string(), path.display().to_string().into());
+    let audio_bytes = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+    let options =
+        crate::batch_options(BatchQuery::default(), Default::default(), Default::default())
+            .map_err(|e| e.to_string())?;
+    let job = jobs::queue_job(None, None, audio_bytes, options, metadata, AnalysisQuery::default())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(job.id)
+}
+
+/// Write completed `job`'s transcript for the file `name` in each of
+/// `formats`.
+fn write_outputs(
+    folder: &WatchFolder,
+    name: &str,
+    job: &jobs::Job,
+    formats: &[FileFormat],
+) -> Result<()> {
+    let transcript = jobs::transcript(job).context("Completed job has no result")?;
+    for format in formats {
+        let path = folder.output_path(name, *format);
+        let bytes = transcript.render(*format)?;
+        std::fs::write(&path, bytes)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_folder() {
+        assert_eq!(
+            WatchFolder::parse(" /srv/inbox = /srv/transcripts "),
+            Some(WatchFolder {
+                dir: PathBuf::from("/srv/inbox"),
+                output: Some(PathBuf::from("/srv/transcripts")),
+            })
+        );
+        assert_eq!(WatchFolder::parse("/srv/inbox").unwrap().output, None);
+        assert_eq!(WatchFolder::parse("/srv/inbox=").unwrap().output, None);
+        assert_eq!(WatchFolder::parse(" "), None);
+    }
+
+    #[test]
+    fn test_scan_skips_unsettled_and_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.MP3", "a.wav", "a.txt", ".hidden.wav", "empty.wav"] {
+            let bytes: &[u8] = if name == "empty.wav" { b"" } else { b"audio" };
+            std::fs::write(dir.path().join(name), bytes).unwrap();
+        }
+        std::fs::create_dir(dir.path().join("sub.wav")).unwrap();
+
+        assert!(scan(dir.path(), SystemTime::now()).unwrap().is_empty());
+        let later = SystemTime::now() + SETTLE_TIME;
+        let names: Vec<String> =
+            scan(dir.path(), later).unwrap().into_iter().map(|c| c.name).collect();
+        assert_eq!(names, ["a.wav", "b.MP3"]);
+    }
+
+    #[test]
+    fn test_output_names() {
+        let folder = WatchFolder::parse("/srv/inbox=/srv/out").unwrap();
+        assert_eq!(
+            folder.output_path("call.part1.wav", FileFormat::Txt),
+            PathBuf::from("/srv/out/call.part1.txt")
+        );
+        assert_eq!(
+            folder.output_path("call.part2.wav", FileFormat::Srt),
+            PathBuf::from("/srv/out/call.part2.srt")
+        );
+
+        let found: Vec<Candidate> = ["a.mp3", "a.wav", "a.x.wav", "b.wav"]
+            .into_iter()
+            .map(|name| Candidate { name: name.to_string(), size: 1, modified_ms: 0 })
+            .collect();
+        let clashes = |state: &State| -> Vec<Option<&str>> {
+            found.iter().map(|file| output_clash(&found, state, file)).collect()
+        };
+        let mut state = State::default();
+        assert_eq!(clashes(&state), [None, Some("a.mp3"), None, None]);
+
+        // The one transcribed before keeps its outputs
+        let processed = |error: Option<&str>| Processed {
+            size: 1,
+            modified_ms: 0,
+            error: error.map(str::to_string),
+        };
+        state.files.insert("a.wav".to_string(), processed(None));
+        state.files.insert("a.mp3".to_string(), processed(Some("clash")));
+        assert_eq!(clashes(&state), [Some("a.wav"), None, None, None]);
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = WatchFolder { dir: dir.path().to_path_buf(), output: None };
+        let path = folder.output_dir().join(STATE_FILE);
+        assert!(State::load(&path).files.is_empty());
+
+        let mut state = State::default();
+        let processed = Processed { size: 5, modified_ms: 1000, error: Some("corrupt".into()) };
+        state.files.insert("a.wav".to_string(), processed.clone());
+        state.save(&path).unwrap();
+        assert_eq!(State::load(&path).files["a.wav"], processed);
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+}
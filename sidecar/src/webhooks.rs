@@ -0,0 +1,245 @@
+//! Transcript webhooks for streaming sessions.
+//!
+//! A `/stream` session opened with `?webhook=<url>` also has every committed
+//! `final`, and the `session_complete` summary, POSTed to that URL as JSON.
+//! A backend can then persist transcripts server-to-server, whatever
+//! happens to the client displaying the captions: if it disconnects without
+//! sending `end`, the rest of its audio is still transcribed and the summary
+//! still delivered.
+//!
+//! Webhooks are off unless `VOICEMARK_WEBHOOK_HOSTS` lists the hosts they
+//! may point at, so clients can't make the server call arbitrary addresses.
+//! With `VOICEMARK_WEBHOOK_SECRET`, each body is signed with HMAC-SHA256 in
+//! the `X-VoiceMark-Signature` header (`sha256=<hex>`).
+//!
+//! Each session delivers its messages in order from a background task, so a
+//! slow receiver never holds up the stream; a delivery that fails is retried
+//! a few times and then dropped with a warning.
+
+use anyhow::{Context, Result, bail};
+use reqwest::Url;
+use ring::hmac;
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tracing::{info, warn};
+
+use crate::error::ApiError;
+use crate::stream::ServerMessage;
+
+/// Header carrying the body's signature.
+const SIGNATURE_HEADER: &str = "X-VoiceMark-Signature";
+
+/// Timeout for one delivery.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delays before retrying a failed delivery.
+const RETRY_DELAYS: [Duration; 3] =
+    [Duration::from_secs(1), Duration::from_secs(4), Duration::from_secs(15)];
+
+/// Webhook settings.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Hosts webhooks may point at: exact names, `.example.com` for its
+    /// subdomains, or `*` for any host.
+    pub hosts: Vec<String>,
+    /// Secret the bodies are signed with, if any.
+    pub secret: Option<String>,
+}
+
+struct Webhooks {
+    hosts: Vec<String>,
+    key: Option<hmac::Key>,
+    client: reqwest::Client,
+}
+
+static WEBHOOKS: OnceLock<Webhooks> = OnceLock::new();
+
+/// Allow streaming sessions to register webhooks. Call once at startup.
+pub fn configure(config: WebhookConfig) -> Result<()> {
+    if config.hosts.is_empty() {
+        bail!("No webhook hosts allowed");
+    }
+    let client = reqwest::Client::builder()
+        .timeout(SEND_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .context("Failed to build the HTTP client")?;
+    let key = config
+        .secret
+        .as_deref()
+        .map(|secret| hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()));
+    info!(hosts = ?config.hosts, signed = key.is_some(), "Stream webhooks enabled");
+    let webhooks = Webhooks { hosts: config.hosts, key, client };
+    if WEBHOOKS.set(webhooks).is_err() {
+        bail!("Webhooks already configured");
+    }
+    Ok(())
+}
+
+/// Check a webhook URL a client asked for: `http` or `https`, to an
+/// allowed host.
+pub fn validate(url: &str) -> Result<Url, ApiError> {
+    let webhooks = WEBHOOKS.get().ok_or(ApiError::WebhookUnavailable)?;
+    let url = Url::parse(url.trim())
+        .map_err(|e| ApiError::InvalidRequest(format!("Invalid webhook URL: {}", e)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ApiError::InvalidRequest("Webhook URLs must be http or https".to_string()));
+    }
+    let host = url.host_str().unwrap_or_default();
+    if !host_allowed(&webhooks.hosts, host) {
+        return Err(ApiError::InvalidRequest(format!(
+            "Webhooks to '{}' are not allowed on this server",
+            host
+        )));
+    }
+    Ok(url)
+}
+
+/// Whether `host` matches one of the allowed `hosts`.
+fn host_allowed(hosts: &[String], host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    hosts.iter().any(|allowed| {
+        let allowed = allowed.to_ascii_lowercase();
+        if allowed == "*" {
+            return true;
+        }
+        match allowed.strip_prefix('.') {
+            Some(domain) => host == domain || host.ends_with(&allowed),
+            None => host == allowed,
+        }
+    })
+}
+
+/// A webhook delivery body: the message, with the session it belongs to.
+#[derive(Serialize)]
+struct Payload<'a> {
+    session_id: &'a str,
+    /// Position of the message in the session's deliveries, from 0.
+    sequence: u64,
+    #[serde(flatten)]
+    message: &'a ServerMessage,
+}
+
+/// One session's webhook. Dropping it stops the delivery task once the
+/// queued messages are delivered.
+pub struct Webhook {
+    session_id: String,
+    sequence: u64,
+    queue: UnboundedSender<Vec<u8>>,
+}
+
+impl Webhook {
+    /// Deliver the session's transcript messages to `url`. Call from the
+    /// runtime, after [`validate`].
+    pub fn start(url: Url, session_id: String) -> Self {
+        let (queue, deliveries) = mpsc::unbounded_channel();
+        tokio::spawn(deliver(url, session_id.clone(), deliveries));
+        Self { session_id, sequence: 0, queue }
+    }
+
+    /// Queue `msg` for delivery, if it is a `final` or `session_complete`.
+    pub fn send(&mut self, msg: &ServerMessage) {
+        if !matches!(msg, ServerMessage::Final { .. } | ServerMessage::SessionComplete { .. }) {
+            return;
+        }
+        let payload =
+            Payload { session_id: &self.session_id, sequence: self.sequence, message: msg };
+        if let Ok(body) = serde_json::to_vec(&payload) {
+            self.sequence += 1;
+            let _ = self.queue.send(body);
+        }
+    }
+}
+
+/// POST queued bodies to `url` in order, until the session's [`Webhook`]
+/// is dropped.
+async fn deliver(url: Url, session_id: String, mut deliveries: UnboundedReceiver<Vec<u8>>) {
+    let Some(webhooks) = WEBHOOKS.get() else {
+        return;
+    };
+    while let Some(body) = deliveries.recv().await {
+        let mut delays = RETRY_DELAYS.iter();
+        loop {
+            match post(webhooks, &url, body.clone()).await {
+                Ok(()) => break,
+                Err(e) => match delays.next() {
+                    Some(delay) => {
+                        warn!(%session_id, "Webhook delivery failed, retrying: {:#}", e);
+                        tokio::time::sleep(*delay).await;
+                    }
+                    None => {
+                        warn!(%session_id, "Webhook delivery failed: {:#}", e);
+                        break;
+                    }
+                },
+            }
+        }
+    }
+}
+
+async fn post(webhooks: &Webhooks, url: &Url, body: Vec<u8>) -> Result<()> {
+    let mut request = webhooks
+        .client
+        .post(url.clone())
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+    if let Some(key) = &webhooks.key {
+        request = request.header(SIGNATURE_HEADER, signature(key, &body));
+    }
+    let response = request.body(body).send().await?;
+    if !response.status().is_success() {
+        bail!("Webhook returned {}", response.status());
+    }
+    Ok(())
+}
+
+/// `sha256=<hex HMAC of body>`.
+fn signature(key: &hmac::Key, body: &[u8]) -> String {
+    let tag = hmac::sign(key, body);
+    let hex: String = tag.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed_hosts() {
+        let hosts = vec!["hooks.example.com".to_string(), ".internal".to_string()];
+        assert!(host_allowed(&hosts, "hooks.example.com"));
+        assert!(host_allowed(&hosts, "HOOKS.example.com."));
+        assert!(host_allowed(&hosts, "api.internal"));
+        assert!(host_allowed(&hosts, "internal"));
+        assert!(!host_allowed(&hosts, "example.com"));
+        assert!(!host_allowed(&hosts, "evilinternal"));
+        assert!(!host_allowed(&hosts, "169.254.169.254"));
+        assert!(host_allowed(&["*".to_string()], "169.254.169.254"));
+    }
+
+    #[test]
+    fn test_signature() {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"key");
+        assert_eq!(
+            signature(&key, b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn test_payload_carries_session_and_message() {
+        let message = ServerMessage::SessionComplete {
+            text: "Hello world.".to_string(),
+            segments: Vec::new(),
+            duration_ms: 1200,
+            timestamp: 1,
+        };
+        let payload = Payload { session_id: "abc", sequence: 3, message: &message };
+        let json = serde_json::to_value(&payload).unwrap();
+        assert_eq!(json["session_id"], "abc");
+        assert_eq!(json["sequence"], 3);
+        assert_eq!(json["type"], "session_complete");
+        assert_eq!(json["text"], "Hello world.");
+    }
+}
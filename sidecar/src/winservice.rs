@@ -0,0 +1,201 @@
+//! Windows service integration for VoiceMark sidecar.
+//!
+//! `install-service` registers the sidecar with the Service Control Manager
+//! (SCM) to start automatically with `--service`; `uninstall-service` stops
+//! and removes it. In `--service` mode the server runs until the SCM asks it
+//! to stop, instead of in a console window that can be closed by accident.
+//!
+//! Services start in `System32`, so the working directory is changed to the
+//! executable's directory and relative paths (such as the default model
+//! path) resolve next to the executable. Other settings come from system
+//! environment variables as usual.
+
+use anyhow::Result;
+
+/// Name the service is registered under.
+#[cfg_attr(not(windows), allow(dead_code))]
+const SERVICE_NAME: &str = "VoiceMarkSidecar";
+
+/// Name shown in the Services console.
+#[cfg_attr(not(windows), allow(dead_code))]
+const DISPLAY_NAME: &str = "VoiceMark Transcription Sidecar";
+
+#[cfg(windows)]
+mod imp {
+    use anyhow::{Context, Result};
+    use std::ffi::OsString;
+    use std::time::Duration;
+    use tracing::{error, info};
+    use windows_service::service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    };
+    use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+    use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    use super::{DISPLAY_NAME, SERVICE_NAME};
+
+    const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+    /// How long the SCM should wait for startup (model loading) to report.
+    const START_WAIT_HINT: Duration = Duration::from_secs(120);
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    pub fn run() -> Result<()> {
+        service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+            .context("Failed to start service dispatcher (is the SCM running this process?)")
+    }
+
+    fn service_main(_args: Vec<OsString>) {
+        if let Err(e) = run_service() {
+            error!("Service failed: {:#}", e);
+        }
+    }
+
+    fn status(state: ServiceState, exit_code: u32, wait_hint: Duration) -> ServiceStatus {
+        ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state: state,
+            controls_accepted: if state == ServiceState::Running {
+                ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+            } else {
+                ServiceControlAccept::empty()
+            },
+            exit_code: ServiceExitCode::Win32(exit_code),
+            checkpoint: 0,
+            wait_hint,
+            process_id: None,
+        }
+    }
+
+    fn run_service() -> Result<()> {
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let mut stop_tx = Some(stop_tx);
+        let handler = move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                if let Some(tx) = stop_tx.take() {
+                    let _ = tx.send(());
+                }
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        };
+        let status_handle = service_control_handler::register(SERVICE_NAME, handler)?;
+        status_handle.set_service_status(status(ServiceState::StartPending, 0, START_WAIT_HINT))?;
+
+        if let Some(dir) = std::env::current_exe()?.parent() {
+            std::env::set_current_dir(dir)?;
+        }
+
+        let ready_handle = status_handle;
+        let result = tokio::runtime::Runtime::new()?.block_on(crate::run_server(
+            async {
+                let _ = stop_rx.await;
+            },
+            move || {
+                if let Err(e) =
+                    ready_handle.set_service_status(status(ServiceState::Running, 0, Duration::ZERO))
+                {
+                    error!("Failed to report service as running: {}", e);
+                }
+            },
+        ));
+
+        let exit_code = match &result {
+            Ok(()) => 0,
+            Err(e) => {
+                error!("Server stopped: {:#}", e);
+                1
+            }
+        };
+        status_handle.set_service_status(status(ServiceState::Stopped, exit_code, Duration::ZERO))?;
+        result
+    }
+
+    pub fn install() -> Result<()> {
+        let manager = ServiceManager::local_computer(
+            None::<&str>,
+            ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+        )?;
+        let info = ServiceInfo {
+            name: SERVICE_NAME.into(),
+            display_name: DISPLAY_NAME.into(),
+            service_type: SERVICE_TYPE,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: std::env::current_exe()?,
+            launch_arguments: vec!["--service".into()],
+            dependencies: vec![],
+            account_name: None,
+            account_password: None,
+        };
+        let service = manager
+            .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+            .context("Failed to create service (run as Administrator)")?;
+        service.set_description("Speech-to-text transcription for VoiceMark")?;
+        info!("Installed service '{}'; start it with `sc start {}`", SERVICE_NAME, SERVICE_NAME);
+        Ok(())
+    }
+
+    pub fn uninstall() -> Result<()> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+        let service = manager
+            .open_service(
+                SERVICE_NAME,
+                ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+            )
+            .context("Failed to open service (is it installed? run as Administrator)")?;
+
+        if service.query_status()?.current_state != ServiceState::Stopped {
+            service.stop()?;
+            for _ in 0..30 {
+                std::thread::sleep(Duration::from_secs(1));
+                if service.query_status()?.current_state == ServiceState::Stopped {
+                    break;
+                }
+            }
+        }
+        service.delete()?;
+        info!("Uninstalled service '{}'", SERVICE_NAME);
+        Ok(())
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use anyhow::{Result, bail};
+
+    pub fn run() -> Result<()> {
+        unsupported()
+    }
+
+    pub fn install() -> Result<()> {
+        unsupported()
+    }
+
+    pub fn uninstall() -> Result<()> {
+        unsupported()
+    }
+
+    fn unsupported() -> Result<()> {
+        bail!("Windows service mode is only available on Windows (use systemd on Linux)")
+    }
+}
+
+/// Run as a Windows service (`--service`). Blocks until the service stops.
+pub fn run() -> Result<()> {
+    imp::run()
+}
+
+/// Register the service (`install-service`).
+pub fn install() -> Result<()> {
+    imp::install()
+}
+
+/// Stop and remove the service (`uninstall-service`).
+pub fn uninstall() -> Result<()> {
+    imp::uninstall()
+}
@@ -0,0 +1,263 @@
+//! Word-level alignment of transcripts for VoiceMark sidecar.
+//!
+//! Transcripts are compared word by word, ignoring case and punctuation, by
+//! a minimum edit distance alignment. [`diff`] returns the alignment for
+//! display (`POST /compare`); [`error_counts`] only counts substitutions,
+//! deletions and insertions, for word error rates, and works on transcripts
+//! of any length.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Largest alignment table [`diff`] builds, in cells (one byte each).
+const MAX_DIFF_CELLS: usize = 64 * 1024 * 1024;
+
+/// How a run of words differs between two transcripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffOp {
+    Equal,
+    /// Words only in the second transcript.
+    Insert,
+    /// Words only in the first transcript.
+    Delete,
+    /// Words of the first transcript replaced by those of the second.
+    Replace,
+}
+
+/// A run of words with the same [`DiffOp`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct DiffChunk {
+    pub op: DiffOp,
+    /// Words of the first transcript, as written; empty for inserts.
+    pub a: String,
+    /// Words of the second transcript, as written; empty for deletes.
+    pub b: String,
+}
+
+/// Word errors of a hypothesis against a reference.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct ErrorCounts {
+    /// Words in the reference.
+    pub reference_words: usize,
+    pub substitutions: usize,
+    pub deletions: usize,
+    pub insertions: usize,
+}
+
+impl ErrorCounts {
+    /// Total number of word errors.
+    pub fn errors(&self) -> usize {
+        self.substitutions + self.deletions + self.insertions
+    }
+
+    /// Word error rate: errors per reference word. An empty reference
+    /// scores 0 if the hypothesis is empty too, and 1 otherwise.
+    pub fn wer(&self) -> f64 {
+        match self.reference_words {
+            0 if self.insertions == 0 => 0.0,
+            0 => 1.0,
+            words => self.errors() as f64 / words as f64,
+        }
+    }
+}
+
+impl std::ops::AddAssign for ErrorCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.reference_words += other.reference_words;
+        self.substitutions += other.substitutions;
+        self.deletions += other.deletions;
+        self.insertions += other.insertions;
+    }
+}
+
+/// A word as written and as compared.
+struct Token<'a> {
+    text: &'a str,
+    key: String,
+}
+
+/// Words of `text`; pure punctuation (e.g. a dash) is not a word.
+fn tokens(text: &str) -> Vec<Token<'_>> {
+    text.split_whitespace()
+        .filter_map(|word| {
+            let key: String = word
+                .chars()
+                .filter(|c| c.is_alphanumeric() || *c == '\'')
+                .flat_map(char::to_lowercase)
+                .collect();
+            (!key.is_empty()).then_some(Token { text: word, key })
+        })
+        .collect()
+}
+
+/// Count the word errors of `hypothesis` against `reference`.
+pub fn error_counts(reference: &str, hypothesis: &str) -> ErrorCounts {
+    let reference = tokens(reference);
+    let hypothesis = tokens(hypothesis);
+
+    // One row of the edit distance table at a time, carrying the error
+    // counts of the cheapest path to each cell
+    let mut row: Vec<ErrorCounts> = (0..=hypothesis.len())
+        .map(|j| ErrorCounts { insertions: j, ..Default::default() })
+        .collect();
+    for (i, r) in reference.iter().enumerate() {
+        let mut next = Vec::with_capacity(row.len());
+        next.push(ErrorCounts { deletions: i + 1, ..Default::default() });
+        for (j, h) in hypothesis.iter().enumerate() {
+            let mut diagonal = row[j];
+            if r.key != h.key {
+                diagonal.substitutions += 1;
+            }
+            let mut up = row[j + 1];
+            up.deletions += 1;
+            let mut left = next[j];
+            left.insertions += 1;
+            let best = [diagonal, up, left]
+                .into_iter()
+                .min_by_key(ErrorCounts::errors)
+                .unwrap_or(diagonal);
+            next.push(best);
+        }
+        row = next;
+    }
+
+    ErrorCounts { reference_words: reference.len(), ..row[hypothesis.len()] }
+}
+
+/// Align `b` against `a` word by word.
+///
+/// Returns `None` if the transcripts are too long to align in memory
+/// (tens of thousands of words each).
+pub fn diff(a: &str, b: &str) -> Option<Vec<DiffChunk>> {
+    let a = tokens(a);
+    let b = tokens(b);
+    let width = b.len() + 1;
+    if (a.len() + 1).checked_mul(width)? > MAX_DIFF_CELLS {
+        return None;
+    }
+
+    // Edit distances of two rows, and the move into every cell
+    const DIAGONAL: u8 = 0;
+    const UP: u8 = 1;
+    const LEFT: u8 = 2;
+    let mut moves = vec![DIAGONAL; (a.len() + 1) * width];
+    moves[1..width].fill(LEFT);
+    let mut row: Vec<usize> = (0..width).collect();
+    for i in 1..=a.len() {
+        let mut next = vec![i; width];
+        moves[i * width] = UP;
+        for j in 1..width {
+            let diagonal = row[j - 1] + usize::from(a[i - 1].key != b[j - 1].key);
+            let up = row[j] + 1;
+            let left = next[j - 1] + 1;
+            let (cost, step) = if diagonal <= up && diagonal <= left {
+                (diagonal, DIAGONAL)
+            } else if up <= left {
+                (up, UP)
+            } else {
+                (left, LEFT)
+            };
+            next[j] = cost;
+            moves[i * width + j] = step;
+        }
+        row = next;
+    }
+
+    // Walk back from the end, then group runs of the same operation
+    let mut ops: Vec<(DiffOp, Option<&str>, Option<&str>)> = Vec::new();
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 || j > 0 {
+        match moves[i * width + j] {
+            DIAGONAL => {
+                let op = if a[i - 1].key == b[j - 1].key { DiffOp::Equal } else { DiffOp::Replace };
+                ops.push((op, Some(a[i - 1].text), Some(b[j - 1].text)));
+                i -= 1;
+                j -= 1;
+            }
+            UP => {
+                ops.push((DiffOp::Delete, Some(a[i - 1].text), None));
+                i -= 1;
+            }
+            _ => {
+                ops.push((DiffOp::Insert, None, Some(b[j - 1].text)));
+                j -= 1;
+            }
+        }
+    }
+
+    let mut chunks: Vec<DiffChunk> = Vec::new();
+    for (op, a_word, b_word) in ops.into_iter().rev() {
+        let chunk = match chunks.last_mut() {
+            Some(chunk) if chunk.op == op => chunk,
+            _ => {
+                chunks.push(DiffChunk { op, a: String::new(), b: String::new() });
+                chunks.last_mut().unwrap()
+            }
+        };
+        for (text, word) in [(&mut chunk.a, a_word), (&mut chunk.b, b_word)] {
+            if let Some(word) = word {
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                text.push_str(word);
+            }
+        }
+    }
+    Some(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(op: DiffOp, a: &str, b: &str) -> DiffChunk {
+        DiffChunk { op, a: a.to_string(), b: b.to_string() }
+    }
+
+    #[test]
+    fn test_diff_groups_runs() {
+        let chunks = diff("The cat sat on the mat.", "the cat sat at a mat today").unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                chunk(DiffOp::Equal, "The cat sat", "the cat sat"),
+                chunk(DiffOp::Replace, "on the", "at a"),
+                chunk(DiffOp::Equal, "mat.", "mat"),
+                chunk(DiffOp::Insert, "", "today"),
+            ]
+        );
+        assert_eq!(diff("one two", "two").unwrap()[0], chunk(DiffOp::Delete, "one", ""));
+        assert!(diff("", "").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_error_counts() {
+        let counts = error_counts("the cat sat on the mat", "the cat sat at mat");
+        assert_eq!(
+            counts,
+            ErrorCounts { reference_words: 6, substitutions: 1, deletions: 1, insertions: 0 }
+        );
+        assert!((counts.wer() - 2.0 / 6.0).abs() < 1e-9);
+        assert_eq!(error_counts("one two", "one two three").insertions, 1);
+
+        // Case and punctuation don't count
+        assert_eq!(error_counts("Hello, world!", "hello world - ").errors(), 0);
+        assert_eq!(error_counts("", "").wer(), 0.0);
+        assert_eq!(error_counts("", "noise").wer(), 1.0);
+    }
+
+    #[test]
+    fn test_error_counts_match_diff() {
+        let (a, b) = ("a b c d e f g", "a x c e f f g h");
+        let counts = error_counts(a, b);
+        let chunks = diff(a, b).unwrap();
+        let words = |text: &str| text.split_whitespace().count();
+        let changed: usize = chunks
+            .iter()
+            .filter(|chunk| chunk.op != DiffOp::Equal)
+            .map(|chunk| words(&chunk.a).max(words(&chunk.b)))
+            .sum();
+        assert_eq!(counts.errors(), changed);
+    }
+}
@@ -6,7 +6,7 @@
  * 
  * Key features:
  * - Low-latency audio capture via AudioWorklet
- * - WebSocket streaming to sidecar at /stream
+ * - WebSocket streaming to sidecar at /v1/stream
  * - Partial results displayed while speaking
  * - Final results on stop
  * 
@@ -19,7 +19,7 @@
 import type { AsrEventCallback, AsrEngine, AsrStartOptions } from './events';
 
 // Configuration
-const SIDECAR_WS_URL = 'ws://localhost:3001/stream';
+const SIDECAR_WS_URL = 'ws://localhost:3001/v1/stream';
 const TARGET_SAMPLE_RATE = 16000;
 
 // Engine state
@@ -47,6 +47,8 @@ interface ServerFinal {
 
 interface ServerError {
   type: 'error';
+  /** Stable machine-readable error code, e.g. `invalid_audio` */
+  code: string;
   message: string;
 }
 
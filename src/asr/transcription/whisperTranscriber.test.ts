@@ -32,7 +32,7 @@ describe('whisperTranscriber', () => {
       const result = await checkSidecarHealth();
 
       expect(result).toEqual({ ok: true, model_loaded: true });
-      expect(mockFetch).toHaveBeenCalledWith('http://localhost:3001/health');
+      expect(mockFetch).toHaveBeenCalledWith('http://localhost:3001/v1/health');
     });
 
     it('should throw when sidecar returns error status', async () => {
@@ -61,7 +61,7 @@ describe('whisperTranscriber', () => {
       
       // Check that it was a POST with FormData
       const [url, options] = mockFetch.mock.calls[0];
-      expect(url).toBe('http://localhost:3001/transcribe');
+      expect(url).toBe('http://localhost:3001/v1/transcribe');
       expect(options.method).toBe('POST');
       expect(options.body).toBeInstanceOf(FormData);
     });
@@ -92,6 +92,24 @@ describe('whisperTranscriber', () => {
       await expect(whisperTranscriber.transcribe(mockBlob)).rejects.toThrow('Model not loaded');
     });
 
+    it('should throw on server error with problem details', async () => {
+      const mockBlob = new Blob(['fake audio'], { type: 'audio/webm' });
+      
+      mockFetch.mockResolvedValueOnce({
+        ok: false,
+        text: async () =>
+          JSON.stringify({
+            type: 'urn:voicemark:error:model_not_loaded',
+            title: 'Model not loaded',
+            status: 503,
+            detail: 'Model not loaded',
+            code: 'model_not_loaded',
+          }),
+      });
+
+      await expect(whisperTranscriber.transcribe(mockBlob)).rejects.toThrow('Model not loaded');
+    });
+
     it('should throw on server error with plain text response', async () => {
       const mockBlob = new Blob(['fake audio'], { type: 'audio/webm' });
       
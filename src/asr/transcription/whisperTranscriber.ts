@@ -1,7 +1,7 @@
 /**
  * Whisper Transcriber - connects to the Rust sidecar for real transcription.
  *
- * Uses the POST /transcribe endpoint of voicemark-sidecar.
+ * Uses the POST /v1/transcribe endpoint of voicemark-sidecar.
  */
 
 import type { Transcriber, TranscribeOptions } from './types';
@@ -10,10 +10,10 @@ import type { Transcriber, TranscribeOptions } from './types';
 const SIDECAR_URL = import.meta.env.VITE_SIDECAR_URL ?? 'http://localhost:3001';
 
 /** Transcription endpoint */
-const TRANSCRIBE_ENDPOINT = `${SIDECAR_URL}/transcribe`;
+const TRANSCRIBE_ENDPOINT = `${SIDECAR_URL}/v1/transcribe`;
 
 /** Health check endpoint */
-const HEALTH_ENDPOINT = `${SIDECAR_URL}/health`;
+const HEALTH_ENDPOINT = `${SIDECAR_URL}/v1/health`;
 
 /**
  * Response from the sidecar /transcribe endpoint.
@@ -73,7 +73,7 @@ export const whisperTranscriber: Transcriber = {
       let errorMessage: string;
       try {
         const errorJson = JSON.parse(errorBody);
-        errorMessage = errorJson.error ?? errorBody;
+        errorMessage = errorJson.detail ?? errorJson.error ?? errorBody;
       } catch {
         errorMessage = errorBody;
       }